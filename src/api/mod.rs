@@ -1,6 +1,9 @@
+pub mod request_id;
 pub mod rest;
+pub mod tls;
 pub mod webrtc;
 pub mod websocket;
+pub mod websocket_events;
 pub mod websocket_stream;
 
 // pub use rest::setup_rest_api;
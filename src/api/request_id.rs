@@ -0,0 +1,107 @@
+use axum::http::{HeaderValue, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+use tracing::Instrument;
+use uuid::Uuid;
+
+/// Header a request id is read from and echoed back in
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest client-supplied request id that is kept; longer ones are replaced
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Id of the request being handled on this task, if any. Log lines read it, so everything
+/// logged while handling a request, including by the recording and stream managers, can
+/// be traced back to it.
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Take the client's id when it is short printable ASCII, otherwise make one up
+fn request_id_of<B>(request: &Request<B>) -> String {
+    request
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|id| {
+            !id.is_empty()
+                && id.len() <= MAX_REQUEST_ID_LEN
+                && id.chars().all(|c| c.is_ascii_graphic())
+        })
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string())
+}
+
+/// Middleware giving every request a correlation id: the incoming `X-Request-Id` or a new
+/// UUID. The handler runs in a tracing span and task-local scope carrying it, and the
+/// response echoes it in `X-Request-Id`.
+pub async fn propagate<B>(mut request: Request<B>, next: Next<B>) -> Response {
+    let id = request_id_of(&request);
+    let header = HeaderValue::from_str(&id).expect("request ids are printable ASCII");
+    request
+        .headers_mut()
+        .insert(REQUEST_ID_HEADER, header.clone());
+
+    let span = tracing::info_span!(
+        "request",
+        request_id = %id,
+        method = %request.method(),
+        uri = %request.uri()
+    );
+    let mut response = REQUEST_ID
+        .scope(id, next.run(request).instrument(span))
+        .await;
+    response.headers_mut().insert(REQUEST_ID_HEADER, header);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::{Body, HttpBody};
+    use axum::routing::get;
+    use axum::{middleware, Router};
+    use tower::ServiceExt;
+
+    /// Echoed header and body of a request to a handler answering with `current()`
+    async fn send(request_id: Option<&str>) -> anyhow::Result<(String, String)> {
+        let app = Router::new()
+            .route("/", get(|| async { current().unwrap_or_default() }))
+            .layer(middleware::from_fn(propagate));
+
+        let mut request = Request::builder().uri("/");
+        if let Some(id) = request_id {
+            request = request.header(REQUEST_ID_HEADER, id);
+        }
+        let response = app.oneshot(request.body(Body::empty())?).await?;
+
+        let header = response.headers()[REQUEST_ID_HEADER].to_str()?.to_string();
+        let mut body = response.into_body();
+        let mut bytes = Vec::new();
+        while let Some(chunk) = body.data().await {
+            bytes.extend_from_slice(&chunk?);
+        }
+        Ok((header, String::from_utf8(bytes)?))
+    }
+
+    #[tokio::test]
+    async fn request_ids_are_echoed_or_generated() -> anyhow::Result<()> {
+        let (header, seen) = send(Some("client-7f3a")).await?;
+        assert_eq!(header, "client-7f3a");
+        assert_eq!(seen, "client-7f3a");
+
+        for request_id in [None, Some(""), Some(&*"x".repeat(200))] {
+            let (header, seen) = send(request_id).await?;
+            assert!(Uuid::parse_str(&header).is_ok(), "{:?}", header);
+            assert_eq!(seen, header);
+        }
+
+        assert_eq!(current(), None);
+        Ok(())
+    }
+}
@@ -1,47 +1,80 @@
 use crate::api::webrtc::{
-    add_ice_candidate, close_webrtc_session, create_webrtc_session, process_webrtc_offer,
-    WebRTCState,
+    add_ice_candidate, close_webrtc_session, create_webrtc_session, keepalive_webrtc_session,
+    process_webrtc_offer, spawn_session_reaper, WebRTCState,
 };
-use crate::api::websocket_stream;
-use crate::db::models::camera_models::CameraWithStreams;
-use crate::db::models::recording_schedule_models::RecordingSchedule;
+use crate::api::{request_id, tls, websocket_events, websocket_stream};
+use crate::db::models::analytics_event_models::{AnalyticsEvent, AnalyticsEventSearchQuery};
+use crate::db::models::audit_models::{AuditAction, AuditEntry, AuditSearchQuery};
+use crate::db::models::camera_models::{CameraUpsert, CameraWithStreams, RecordingMode};
+use crate::db::models::event_settings_models::{EventSettings, TRIGGER_EVENT_TYPES};
+use crate::db::models::recording_models::{Recording, RecordingEventType};
+use crate::db::models::recording_schedule_models::{parse_timezone, RecordingSchedule};
 use crate::db::models::stream_models::{ReferenceType, Stream, StreamReference, StreamType};
 use crate::db::models::user_models::{AuthToken, LoginCredentials, User, UserRole};
+use crate::db::repositories::analytics_events::AnalyticsEventsRepository;
+use crate::db::repositories::audit_log::AuditLogRepository;
+use crate::db::repositories::camera_event_settings::EventSettingsRepository;
 use crate::db::repositories::cameras::CamerasRepository;
 use crate::db::repositories::recordings::RecordingsRepository;
 use crate::db::repositories::schedules::SchedulesRepository;
 use crate::db::repositories::users::UsersRepository;
-use crate::device_manager::onvif_client::{OnvifCameraBuilder, OnvifError};
+use crate::device_manager::discovery::DiscoveredCamera;
+use crate::device_manager::onvif_client::{
+    OnvifCamera, OnvifCameraBuilder, OnvifError, PtzPreset, COMMON_SERVICE_PATHS,
+};
 use crate::error::Error;
-use crate::recorder::record::RecordingManager;
+use crate::recorder::archiver::{archived_key, RecordingArchiver};
+use crate::recorder::dry_run::{RecordingTestReport, TEST_RECORDING_DURATION};
+use crate::recorder::record::{
+    audio_codec_support, codec_from_rtp_encoding, video_codec_support, CodecSupport,
+    RecordingManager,
+};
+use crate::recorder::scheduler::{RecordingScheduler, SchedulerStatus};
+use crate::recorder::thumbnail;
 use crate::security::auth::AuthService;
-use crate::stream_manager::{StreamManager, StreamSource};
-use crate::{config::ApiConfig, db::models::camera_models::Camera};
+use crate::security::Claims;
+use crate::storage::{LocalUrlProvider, StorageUrlProvider};
+use crate::stream_manager::snapshot::{capture_jpeg, SnapshotCache};
+use crate::stream_manager::{
+    authenticated_uri, StreamAccessError, StreamManager, StreamSource, StreamStatus,
+};
+use crate::utils::redact::redact_url;
+use crate::{
+    config::{ApiConfig, OnvifConfig, WebRtcConfig},
+    db::models::camera_models::Camera,
+};
 use crate::{device_manager, stream_manager};
 use anyhow::Result;
 use axum::routing::{delete, get, put};
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
-    response::{IntoResponse, Response},
+    extract::{ConnectInfo, Path, Query, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware,
+    response::{IntoResponse, Redirect, Response},
     routing::post,
     Json, Router,
 };
-use chrono::Utc;
-use log::{info, warn};
+use chrono::{DateTime, Utc};
+use log::{debug, info, warn};
 use regex;
 use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::future::Future;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::net::TcpListener;
+use tokio::sync::Semaphore;
 use tower_http::cors::{Any, CorsLayer};
 use tower_http::services::ServeDir;
 use uuid::Uuid;
 
 // Import recording controllers
+pub mod export_service;
+pub mod health;
 pub mod hls_controller;
+pub mod hls_service;
 pub mod nginx_vod_mapping;
 pub mod recording_controller;
 pub mod recording_playback_controller;
@@ -56,18 +89,59 @@ pub struct AppState {
     pub recording_manager: Arc<RecordingManager>,
     pub recordings_repo: Arc<RecordingsRepository>,
     pub schedules_repo: Arc<SchedulesRepository>,
+    pub analytics_events_repo: Arc<AnalyticsEventsRepository>,
+    pub event_settings_repo: Arc<EventSettingsRepository>,
+    pub audit_repo: Arc<AuditLogRepository>,
     pub message_broker: Arc<crate::messaging::MessageBroker>,
     pub hls_service: Option<Arc<crate::recorder::HlsPreparationService>>,
+    pub hls: Arc<hls_service::HlsService>,
+    pub exports: Arc<export_service::ExportService>,
+    pub scheduler: Option<Arc<RecordingScheduler>>,
+    pub snapshots: Arc<SnapshotCache>,
+    pub api_config: ApiConfig,
+    pub onvif_config: OnvifConfig,
+    pub storage_urls: Arc<dyn StorageUrlProvider>,
+    pub archiver: Option<Arc<RecordingArchiver>>,
 }
 
 pub type ApiResult<T> = std::result::Result<T, ApiError>;
 
+/// Error of every handler. The body is `{"code": "not_found", "message": ..., "status": 404}`,
+/// the code being the snake_case name of the status.
 #[derive(Debug, Serialize)]
 pub struct ApiError {
     pub message: String,
     pub status: u16,
 }
 
+impl ApiError {
+    pub fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        ApiError {
+            message: message.into(),
+            status: status.as_u16(),
+        }
+    }
+
+    /// Stable machine-readable name of the error, e.g. `not_found`
+    pub fn code(&self) -> String {
+        let reason = StatusCode::from_u16(self.status)
+            .ok()
+            .and_then(|status| status.canonical_reason())
+            .unwrap_or("Unknown Error");
+        reason
+            .to_ascii_lowercase()
+            .replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+    }
+}
+
+/// Body of an `ApiError` response
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ErrorBody {
+    pub code: String,
+    pub message: String,
+    pub status: u16,
+}
+
 impl From<OnvifError> for ApiError {
     fn from(err: OnvifError) -> Self {
         ApiError {
@@ -112,6 +186,10 @@ impl From<Error> for ApiError {
                 message: err.to_string(),
                 status: StatusCode::BAD_REQUEST.as_u16(),
             },
+            Error::TooManyRequests(_) => ApiError {
+                message: err.to_string(),
+                status: StatusCode::TOO_MANY_REQUESTS.as_u16(),
+            },
             _ => ApiError {
                 message: err.to_string(),
                 status: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
@@ -120,11 +198,24 @@ impl From<Error> for ApiError {
     }
 }
 
+impl From<StreamAccessError> for ApiError {
+    fn from(err: StreamAccessError) -> Self {
+        let status = match err {
+            StreamAccessError::NotRegistered(_) => StatusCode::NOT_FOUND,
+            StreamAccessError::NotPlaying(..) => StatusCode::SERVICE_UNAVAILABLE,
+        };
+        ApiError::new(status, err.to_string())
+    }
+}
+
 impl From<anyhow::Error> for ApiError {
     fn from(err: anyhow::Error) -> Self {
         if let Some(err) = err.downcast_ref::<Error>() {
             return err.clone().into();
         }
+        if let Some(err) = err.downcast_ref::<StreamAccessError>() {
+            return err.clone().into();
+        }
 
         ApiError {
             message: err.to_string(),
@@ -146,7 +237,11 @@ impl From<serde_json::Error> for ApiError {
 impl IntoResponse for ApiError {
     fn into_response(self) -> Response {
         let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
-        let body = Json(self);
+        let body = Json(ErrorBody {
+            code: self.code(),
+            message: self.message,
+            status: status.as_u16(),
+        });
         (status, body).into_response()
     }
 }
@@ -161,38 +256,79 @@ struct RegisterRequest {
 
 pub struct RestApi {
     config: ApiConfig,
+    onvif_config: OnvifConfig,
+    webrtc_config: WebRtcConfig,
     db_pool: Arc<PgPool>,
     stream_manager: Arc<StreamManager>,
+    recording_manager: Arc<RecordingManager>,
     auth_service: Arc<AuthService>,
     message_broker: Arc<crate::messaging::MessageBroker>,
+    scheduler: Option<Arc<RecordingScheduler>>,
+    storage_urls: Arc<dyn StorageUrlProvider>,
+    archiver: Option<Arc<RecordingArchiver>>,
+    tier_dir: Option<std::path::PathBuf>,
 }
 
 impl RestApi {
     pub fn new(
         config: &ApiConfig,
+        onvif_config: &OnvifConfig,
+        webrtc_config: &WebRtcConfig,
         db_pool: Arc<PgPool>,
         stream_manager: Arc<StreamManager>,
+        recording_manager: Arc<RecordingManager>,
         auth_service: Arc<AuthService>,
         message_broker: Arc<crate::messaging::MessageBroker>,
     ) -> Result<Self> {
         Ok(Self {
             config: config.clone(),
+            onvif_config: onvif_config.clone(),
+            webrtc_config: webrtc_config.clone(),
             db_pool,
             stream_manager,
+            recording_manager,
             auth_service,
             message_broker,
+            scheduler: None,
+            storage_urls: Arc::new(LocalUrlProvider),
+            archiver: None,
+            tier_dir: None,
         })
     }
 
-    pub async fn run(&self) -> Result<()> {
-        // Create recording manager
-        let recording_manager = Arc::new(RecordingManager::new(
-            Arc::clone(&self.db_pool),
-            Arc::clone(&self.stream_manager),
-            &std::path::Path::new("./recordings"),
-            300, // 5 minutes segment duration
-            "mp4",
-        ));
+    /// Expose the recording scheduler's status at `/api/scheduler/status`
+    pub fn with_scheduler(mut self, scheduler: Arc<RecordingScheduler>) -> Self {
+        self.scheduler = Some(scheduler);
+        self
+    }
+
+    /// Hand out recording files through `provider` instead of serving them locally
+    pub fn with_storage_urls(mut self, provider: Arc<dyn StorageUrlProvider>) -> Self {
+        self.storage_urls = provider;
+        self
+    }
+
+    /// Play archived recordings back by fetching them from the archive store
+    pub fn with_archiver(mut self, archiver: Arc<RecordingArchiver>) -> Self {
+        self.archiver = Some(archiver);
+        self
+    }
+
+    /// Play back and export recordings the storage cleanup moved to `tier_dir`
+    pub fn with_tier_dir(mut self, tier_dir: &std::path::Path) -> Self {
+        self.tier_dir = Some(tier_dir.to_path_buf());
+        self
+    }
+
+    /// Serve the API until `shutdown` resolves. New connections are then refused and open
+    /// requests get `api.shutdown_grace_secs` to finish before they are cut off.
+    pub async fn run<F>(&self, shutdown: F) -> Result<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        // Share the application's recording manager so recordings started through the API
+        // are tracked (and finalized on shutdown) alongside scheduled ones
+        let recording_manager = Arc::clone(&self.recording_manager);
 
         // Create HLS preparation service
         let hls_service = Arc::new(crate::recorder::HlsPreparationService::new(
@@ -208,8 +344,40 @@ impl RestApi {
             recording_manager: Arc::clone(&recording_manager),
             recordings_repo: Arc::new(RecordingsRepository::new(self.db_pool.clone())),
             schedules_repo: Arc::new(SchedulesRepository::new(self.db_pool.clone())),
+            analytics_events_repo: Arc::new(AnalyticsEventsRepository::new(self.db_pool.clone())),
+            event_settings_repo: Arc::new(EventSettingsRepository::new(self.db_pool.clone())),
+            audit_repo: Arc::new(AuditLogRepository::new(self.db_pool.clone())),
             message_broker: self.message_broker.clone(),
             hls_service: Some(Arc::clone(&hls_service)),
+            hls: Arc::new(
+                hls_service::HlsService::new(
+                    std::env::temp_dir().join("g-streamer-hls"),
+                    recording_manager.recording_base_path(),
+                    self.config.hls_max_concurrent_jobs,
+                    std::time::Duration::from_secs(self.config.hls_queue_timeout_secs),
+                )
+                .with_temp_limits(
+                    self.config.hls_temp_max_mb * 1024 * 1024,
+                    std::time::Duration::from_secs(self.config.hls_temp_max_age_secs),
+                )
+                .with_archiver(self.archiver.clone())
+                .with_tier_dir(self.tier_dir.as_deref()),
+            ),
+            exports: Arc::new(
+                export_service::ExportService::new(
+                    std::env::temp_dir().join("g-streamer-exports"),
+                    recording_manager.recording_base_path(),
+                )
+                .with_tier_dir(self.tier_dir.as_deref()),
+            ),
+            scheduler: self.scheduler.clone(),
+            snapshots: Arc::new(SnapshotCache::new(std::time::Duration::from_secs(
+                self.config.snapshot_cache_secs,
+            ))),
+            api_config: self.config.clone(),
+            onvif_config: self.onvif_config.clone(),
+            storage_urls: Arc::clone(&self.storage_urls),
+            archiver: self.archiver.clone(),
         };
 
         // Create HLS controller state
@@ -219,19 +387,19 @@ impl RestApi {
         let webrtc_state = Arc::new(WebRTCState::new(
             Arc::clone(&self.db_pool),
             Arc::clone(&self.stream_manager),
+            self.webrtc_config.clone(),
         ));
+        spawn_session_reaper(&webrtc_state);
+        hls_service::spawn_temp_cleanup(&state.hls);
 
-        // Create a CORS layer that allows all origins and preflight requests
-        use std::time::Duration;
-        let cors = CorsLayer::new()
-            .allow_origin(Any)
-            .allow_methods(Any)
-            .allow_headers(Any)
-            .allow_credentials(false)
-            .max_age(Duration::from_secs(3600));
+        let cors = cors_layer(&self.config);
 
         // Build the API router with routes
         let app = Router::new()
+            // Health probes (unauthenticated)
+            .route("/health", get(health::liveness))
+            .route("/health/ready", get(health::readiness))
+            .route("/metrics", get(get_metrics))
             // Auth routes
             .route("/api/auth/login", post(login))
             .route("/api/auth/register", post(register))
@@ -244,17 +412,43 @@ impl RestApi {
             .route("/api/users", get(get_all_users))
             .route("/api/users/:id", get(get_user_by_id))
             .route("/api/users/:id", delete(delete_user))
+            .route("/api/audit", get(get_audit_log))
             // Camera routes
             .route("/api/cameras", get(get_cameras))
             // .route("/api/cameras", post(create_camera))
             .route("/api/cameras/discover", post(discover_cameras))
             .route("/api/cameras/connect", post(camera_connect))
+            .route("/api/cameras/bulk/status", post(bulk_update_camera_status))
+            .route(
+                "/api/cameras/bulk/refresh",
+                post(bulk_refresh_camera_details),
+            )
+            .route("/api/cameras/bulk/delete", post(bulk_delete_cameras))
             .route("/api/cameras/:id", get(get_camera_by_id))
             .route("/api/cameras/:id", put(update_camera))
             .route("/api/cameras/:id", delete(delete_camera))
+            .route("/api/cameras/:id/restore", post(restore_camera))
             .route("/api/cameras/:id/status", put(update_camera_status))
+            .route(
+                "/api/cameras/:id/recording-mode",
+                put(update_camera_recording_mode),
+            )
             .route("/api/cameras/:id/refresh", post(refresh_camera_details))
+            .route("/api/cameras/:id/ptz", post(camera_ptz))
+            .route("/api/cameras/:id/ptz/presets", get(get_camera_ptz_presets))
+            .route("/api/cameras/:id/snapshot", get(get_camera_snapshot))
+            .route(
+                "/api/cameras/:id/capabilities",
+                get(get_camera_capabilities),
+            )
+            .route("/api/cameras/:id/streams", post(add_camera_stream))
             // .route("/api/cameras/:id/streams", get(get_camera_streams))
+            // Stream routes
+            .route("/api/streams/:id", delete(remove_stream))
+            .route("/api/streams/:id/status", get(get_stream_status))
+            .route("/api/streams/:id/record/start", post(start_stream_recording))
+            .route("/api/streams/:id/record/stop", post(stop_stream_recording))
+            .route("/api/streams/:id/record/test", post(test_stream_recording))
             // Schedule routes
             .route("/api/schedules", get(get_schedules))
             .route("/api/schedules", post(create_schedule))
@@ -263,13 +457,31 @@ impl RestApi {
             .route("/api/schedules/:id", delete(delete_schedule))
             .route("/api/schedules/:id/status", put(set_schedule_enabled))
             .route("/api/cameras/:id/schedules", get(get_schedules_by_camera))
+            .route("/api/scheduler/status", get(get_scheduler_status))
             // Recording API routes
             .route("/api/recordings", get(search_recordings))
             .route("/api/recordings/:id", get(get_recording_by_id))
             .route("/api/recordings/:id", delete(delete_recording))
             .route("/api/recordings/:id/stream", get(stream_recording))
             .route("/api/recordings/:id/download", get(download_recording))
+            .route("/api/recordings/:id/seek", get(seek_recording))
+            .route("/api/recordings/:id/segments", get(list_recording_segments))
+            .route(
+                "/api/recordings/:id/thumbnail",
+                get(get_recording_thumbnail),
+            )
             .route("/api/cameras/:id/recordings", get(get_recordings_by_camera))
+            .route("/api/cameras/:id/events", get(get_camera_events))
+            .route(
+                "/api/cameras/:id/event-settings",
+                get(get_camera_event_settings),
+            )
+            .route(
+                "/api/cameras/:id/event-settings",
+                put(update_camera_event_settings),
+            )
+            .route("/api/cameras/:id/export", post(create_camera_export))
+            .route("/api/exports/:job_id", get(get_export))
             // Create recording controller with routes using state
             .nest(
                 "/recording",
@@ -303,6 +515,8 @@ impl RestApi {
                 "/api/vod/mapping",
                 get(nginx_vod_mapping::generate_vod_mapping),
             )
+            // Live event feed for the dashboard
+            .route("/ws/events", get(websocket_events::handle_ws_upgrade))
             // Regular routes with AppState
             .with_state(state)
             // Add WebRTC routes with their own state
@@ -313,12 +527,15 @@ impl RestApi {
                     .route("/offer", post(process_webrtc_offer))
                     .route("/ice", post(add_ice_candidate))
                     .route("/close/:session_id", get(close_webrtc_session))
+                    .route("/keepalive/:session_id", post(keepalive_webrtc_session))
                     .with_state(webrtc_state),
             )
             // Add WebSocket for recording playback streaming
             .route("/ws/playback", get(websocket_stream::handle_ws_upgrade))
             // Serve static files from the public directory
             .nest_service("/", ServeDir::new("public"))
+            // Tag every request with an id for the logs and the response
+            .layer(middleware::from_fn(request_id::propagate))
             // Apply CORS middleware to all routes
             .layer(cors);
 
@@ -326,33 +543,129 @@ impl RestApi {
         let addr = self.config.address.clone() + ":" + &self.config.port.to_string();
         let addr: SocketAddr = addr.parse()?;
 
-        // Log that we're starting
-        info!("API server listening on {}", addr);
+        // Load the certificate up front so a bad one fails startup rather than every handshake
+        let rustls = match &self.config.tls {
+            Some(tls_config) => {
+                let rustls = tls::load(tls_config).await?;
+                tls::reload_on_sighup(rustls.clone(), tls_config.clone());
+                Some(rustls)
+            }
+            None => None,
+        };
 
         // Create a listener and start the server
         let listener = TcpListener::bind(addr).await?;
 
-        // Start serving (using axum's Server method)
-        axum::Server::from_tcp(listener.into_std()?)?
-            .serve(app.into_make_service())
-            .await?;
+        let grace = Duration::from_secs(self.config.shutdown_grace_secs);
+        match rustls {
+            Some(rustls) => {
+                info!("API server listening on https://{}", addr);
+                tls::serve(listener.into_std()?, rustls, app, shutdown, grace).await?;
+            }
+            None => {
+                info!("API server listening on http://{}", addr);
+                serve(listener.into_std()?, app, shutdown, grace).await?;
+            }
+        }
 
+        info!("API server stopped");
         Ok(())
     }
 }
 
+/// Serve `app` over plain HTTP until `shutdown` resolves, then stop accepting connections
+/// and wait up to `grace` for open requests to finish
+async fn serve<F>(
+    listener: std::net::TcpListener,
+    app: Router,
+    shutdown: F,
+    grace: Duration,
+) -> Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let (draining_tx, draining_rx) = tokio::sync::oneshot::channel();
+    // Start serving (using axum's Server method)
+    let server = axum::Server::from_tcp(listener)?
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(async move {
+            shutdown.await;
+            info!("API server draining open requests");
+            let _ = draining_tx.send(());
+        });
+    // hyper waits for every connection, so bound the drain ourselves
+    let deadline = async move {
+        match draining_rx.await {
+            Ok(()) => tokio::time::sleep(grace).await,
+            Err(_) => std::future::pending().await,
+        }
+    };
+
+    tokio::select! {
+        res = server => res?,
+        _ = deadline => warn!(
+            "Requests still open {}s after shutdown began, closing them",
+            grace.as_secs()
+        ),
+    }
+    Ok(())
+}
+
+/// CORS for the configured origins. Credentials are only allowed with a list of origins;
+/// browsers refuse them with the `*` wildcard.
+fn cors_layer(config: &ApiConfig) -> CorsLayer {
+    let methods: Vec<Method> = config
+        .cors_allowed_methods
+        .iter()
+        .filter_map(|method| method.parse().ok())
+        .collect();
+    let headers: Vec<header::HeaderName> = config
+        .cors_allowed_headers
+        .iter()
+        .filter_map(|name| name.parse().ok())
+        .collect();
+    let cors = CorsLayer::new()
+        .allow_methods(methods)
+        .allow_headers(headers)
+        .expose_headers([header::HeaderName::from_static(
+            request_id::REQUEST_ID_HEADER,
+        )])
+        .max_age(std::time::Duration::from_secs(3600));
+
+    if config
+        .cors_allowed_origins
+        .iter()
+        .any(|origin| origin == "*")
+    {
+        return cors.allow_origin(Any).allow_credentials(false);
+    }
+    let origins: Vec<header::HeaderValue> = config
+        .cors_allowed_origins
+        .iter()
+        .filter_map(|origin| origin.parse().ok())
+        .collect();
+    let credentials = !origins.is_empty();
+    cors.allow_origin(origins).allow_credentials(credentials)
+}
+
 // async fn get_cameras(State(state): State<AppState>) -> ApiResult<Json<Vec<Camera>>> {
 //     let repo = CamerasRepository::new(Arc::clone(&state.db_pool));
 //     let cameras = repo.get_all().await?;
 //     Ok(Json(cameras))
 // }
 
-async fn discover_cameras(State(_state): State<AppState>) -> ApiResult<Json<Vec<Camera>>> {
+async fn discover_cameras(
+    State(state): State<AppState>,
+) -> ApiResult<Json<Vec<DiscoveredCamera>>> {
     info!("Starting camera discovery");
 
-    let discovered_cameras = device_manager::discovery::discover().await?;
+    let discovered_cameras = device_manager::discovery::discover(&state.onvif_config).await?;
+    let registered_cameras = state.cameras_repo.get_all().await?;
 
-    Ok(Json(discovered_cameras))
+    Ok(Json(device_manager::discovery::mark_registered(
+        discovered_cameras,
+        &registered_cameras,
+    )))
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -364,7 +677,7 @@ pub struct CameraConnectRequest {
 async fn camera_connect(
     State(state): State<AppState>,
     Json(req): Json<CameraConnectRequest>,
-) -> ApiResult<Json<CameraWithStreams>> {
+) -> ApiResult<(StatusCode, Json<CameraUpsert>)> {
     info!("Connecting to Camera");
     let mut camera = Camera::default();
     camera.username = Some(req.username.clone());
@@ -373,11 +686,13 @@ async fn camera_connect(
     let client = OnvifCameraBuilder::new()
         .uri(&format!("http://{}", &req.ip_address))?
         .credentials(&req.username, &req.password)
-        .service_path("onvif/device_service")
+        .probe_service_paths(COMMON_SERVICE_PATHS)
         .fix_time(true)
-        .auth_type("simple")
+        .auth_type("auto")
         .build()
         .await?;
+    camera.onvif_auth_type = Some(client.auth_type().to_string());
+    camera.onvif_endpoint = Some(client.service_path().to_string());
 
     let device_info = client.get_device_information().await?;
     camera.manufacturer = Some(device_info.manufacturer);
@@ -407,6 +722,8 @@ async fn camera_connect(
         stream.audio_bitrate = stream_response.audio_bitrate.map(|value| value as i32);
         stream.audio_sample_rate = stream_response.audio_samplerate.map(|value| value as i32);
         stream.audio_codec = stream_response.audio_encoding.clone();
+        // Profiles without an audio encoder configuration carry no audio track
+        stream.has_audio = Some(stream_response.audio_encoding.is_some());
         stream.stream_type = StreamType::Rtsp;
         stream.is_active = Some(false);
         stream.is_primary = Some(i == 0);
@@ -442,51 +759,39 @@ async fn camera_connect(
         stream_references,
     };
 
-    // Clone what we need to pass to the thread
+    // Connecting a camera again updates it rather than registering a duplicate
+    let upsert = state
+        .cameras_repo
+        .upsert_with_streams(&camera_with_streams)
+        .await?;
+
+    // Start the pipelines in the background; ones that already run are left alone
     let stream_manager = state.stream_manager.clone();
-    let streams_for_thread = camera_with_streams.streams.clone();
-    let username = camera_with_streams
-        .camera
-        .username
-        .clone()
-        .ok_or_else(|| anyhow::anyhow!("Camera username is missing"))?;
-    let password = camera_with_streams
-        .camera
-        .password
-        .clone()
-        .ok_or_else(|| anyhow::anyhow!("Camera password is missing"))?;
-
-    // Spawn a new thread to handle stream connections
+    let camera = upsert.camera.camera.clone();
+    let streams = upsert.camera.streams.clone();
+    let stale_stream_ids = upsert.stale_stream_ids.clone();
     tokio::spawn(async move {
-        for stream in streams_for_thread {
-            // Parse the original URL to insert username and password
-            let stream_uri = stream.url.to_string();
-
-            // Check if URL already contains credentials
-            let auth_uri = if stream_uri.contains('@') {
-                // URL already has credentials, use as is
-                stream_uri
-            } else {
-                // URL doesn't have credentials, add them
-                if stream_uri.starts_with("rtsp://") {
-                    format!("rtsp://{}:{}@{}", username, password, &stream_uri[7..])
-                } else {
-                    // Handle non-RTSP URLs or malformed URLs
-                    warn!("Invalid RTSP URL format: {}", stream_uri);
-                    stream_uri
-                }
-            };
-
-            info!("Connecting to camera URL: {}", auth_uri.clone());
+        for stream_id in stale_stream_ids {
+            let _ = stream_manager.remove_stream(&stream_id.to_string()).await;
+        }
 
-            let source = StreamSource {
-                stream_type: stream.stream_type,
-                uri: auth_uri,
-                name: stream.name.clone(),
-                description: Some("RTSP stream".to_string()),
-            };
+        for stream in streams {
+            let source = stream_source(&camera, &stream);
+            let stream_id = stream.id.to_string();
+            match stream_manager.get_stream_info(&stream_id) {
+                Ok(running) if running.uri == source.uri => continue,
+                Ok(_) => {
+                    // The URL or credentials changed; restart the pipeline
+                    if let Err(e) = stream_manager.remove_stream(&stream_id).await {
+                        warn!("Failed to restart stream {}: {}", stream_id, e);
+                        continue;
+                    }
+                }
+                Err(_) => {}
+            }
 
-            match stream_manager.add_stream(source, stream.id.to_string()) {
+            info!("Connecting to camera URL: {}", redact_url(&source.uri));
+            match stream_manager.add_stream(source, stream_id) {
                 Ok(stream_id) => {
                     println!("Created stream with ID: {}", stream_id);
                 }
@@ -497,19 +802,64 @@ async fn camera_connect(
         }
     });
 
-    let db_response = state
-        .cameras_repo
-        .create_with_streams(&camera_with_streams)
-        .await?;
+    let status = if upsert.created {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+    Ok((status, Json(upsert)))
+}
+
+#[derive(Debug, Deserialize)]
+struct CamerasParams {
+    /// Also report the live pipeline status of each stream
+    #[serde(default)]
+    status: bool,
+}
 
-    Ok(Json(db_response))
+/// A camera with the live pipeline status of its streams
+#[derive(Debug, Serialize)]
+struct CameraWithStatus {
+    #[serde(flatten)]
+    camera: CameraWithStreams,
+    /// Status of each stream that has a pipeline, by stream ID; streams without one are
+    /// left out
+    stream_status: HashMap<Uuid, StreamStatus>,
 }
 
-async fn get_cameras(State(state): State<AppState>) -> ApiResult<Json<Vec<CameraWithStreams>>> {
+/// List cameras with their streams. `?status=true` adds each stream's pipeline state, last
+/// error, reconnect attempts and uptime, so a camera grid needs no request per stream.
+async fn get_cameras(
+    State(state): State<AppState>,
+    Query(params): Query<CamerasParams>,
+) -> ApiResult<Response> {
     info!("Getting cameras with streams...");
     let cameras = state.cameras_repo.get_all_with_streams().await?;
+    if !params.status {
+        return Ok(Json(cameras).into_response());
+    }
 
-    Ok(Json(cameras))
+    let cameras: Vec<CameraWithStatus> = cameras
+        .into_iter()
+        .map(|camera| {
+            let stream_status = camera
+                .streams
+                .iter()
+                .filter_map(|stream| {
+                    let status = state
+                        .stream_manager
+                        .get_stream_status(&stream.id.to_string())
+                        .ok()?;
+                    Some((stream.id, status))
+                })
+                .collect();
+            CameraWithStatus {
+                camera,
+                stream_status,
+            }
+        })
+        .collect();
+    Ok(Json(cameras).into_response())
 }
 
 async fn get_camera_by_id(
@@ -528,6 +878,342 @@ async fn get_camera_by_id(
     Ok(Json(camera))
 }
 
+async fn get_stream_status(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> ApiResult<Json<StreamStatus>> {
+    let status = state.stream_manager.get_stream_status(&id)?;
+
+    Ok(Json(status))
+}
+
+/// What a camera stream carries and whether it can be recorded
+#[derive(Debug, Serialize)]
+struct StreamCapabilities {
+    stream_id: Uuid,
+    name: String,
+    reference_type: Option<ReferenceType>,
+    video_codec: Option<String>,
+    audio_codec: Option<String>,
+    resolution: Option<String>,
+    framerate: Option<String>,
+    /// Whether the codecs were read from the running pipeline rather than the stored stream
+    negotiated: bool,
+    video_support: Option<CodecSupport>,
+    audio_support: Option<CodecSupport>,
+}
+
+/// List each stream's codecs, resolution and framerate and whether the recorder can
+/// record them. Running streams report their negotiated caps, others what was stored on
+/// connect.
+async fn get_camera_capabilities(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> ApiResult<Json<Vec<StreamCapabilities>>> {
+    let camera = state
+        .cameras_repo
+        .get_with_streams_by_id(&id)
+        .await?
+        .ok_or_else(|| ApiError {
+            message: format!("Camera not found: {}", id),
+            status: StatusCode::NOT_FOUND.as_u16(),
+        })?;
+
+    let transcode = state.recording_manager.transcodes_video();
+    let capabilities = camera
+        .streams
+        .iter()
+        .map(|stream| {
+            let live = state
+                .stream_manager
+                .get_stream_status(&stream.id.to_string())
+                .ok()
+                .filter(|status| status.codec.is_some());
+            let stored_audio = match stream.has_audio {
+                Some(false) => None,
+                _ => stream.audio_codec.as_deref().map(str::to_lowercase),
+            };
+            let (video_codec, audio_codec, resolution, framerate) = match &live {
+                Some(status) => (
+                    status.codec.as_deref().map(codec_from_rtp_encoding),
+                    status.audio_codec.as_deref().map(codec_from_rtp_encoding),
+                    status.resolution.clone(),
+                    status.framerate.clone(),
+                ),
+                None => (
+                    stream.codec.as_deref().map(str::to_lowercase),
+                    stored_audio,
+                    stream.resolution.clone().or_else(|| {
+                        stream
+                            .width
+                            .zip(stream.height)
+                            .map(|(w, h)| format!("{}x{}", w, h))
+                    }),
+                    stream.framerate.map(|fps| fps.to_string()),
+                ),
+            };
+
+            StreamCapabilities {
+                stream_id: stream.id,
+                name: stream.name.clone(),
+                reference_type: camera
+                    .stream_references
+                    .iter()
+                    .find(|r| r.stream_id == stream.id)
+                    .map(|r| r.reference_type),
+                video_support: video_codec
+                    .as_deref()
+                    .map(|codec| video_codec_support(codec, transcode)),
+                audio_support: audio_codec.as_deref().map(audio_codec_support),
+                video_codec,
+                audio_codec,
+                resolution,
+                framerate,
+                negotiated: live.is_some(),
+            }
+        })
+        .collect();
+
+    Ok(Json(capabilities))
+}
+
+#[derive(Debug, Deserialize)]
+struct AddStreamRequest {
+    name: String,
+    url: String,
+    codec: Option<String>,
+    width: Option<i32>,
+    height: Option<i32>,
+    framerate: Option<i32>,
+    is_primary: Option<bool>,
+}
+
+/// Pipeline source of a camera stream, with the camera's credentials in the URI
+fn stream_source(camera: &Camera, stream: &Stream) -> StreamSource {
+    let uri = match (&camera.username, &camera.password) {
+        (Some(username), Some(password)) => authenticated_uri(&stream.url, username, password),
+        _ => stream.url.clone(),
+    };
+    StreamSource {
+        stream_type: stream.stream_type,
+        uri,
+        name: stream.name.clone(),
+        description: Some("RTSP stream".to_string()),
+    }
+}
+
+/// Add a stream to a registered camera and start its pipeline
+async fn add_camera_stream(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(req): Json<AddStreamRequest>,
+) -> ApiResult<(StatusCode, Json<Stream>)> {
+    if !req.url.starts_with("rtsp://") {
+        return Err(ApiError {
+            message: format!(
+                "Stream URL must be an rtsp:// URL: {}",
+                redact_url(&req.url)
+            ),
+            status: StatusCode::BAD_REQUEST.as_u16(),
+        });
+    }
+
+    let camera = state
+        .cameras_repo
+        .get_by_id(&id)
+        .await?
+        .ok_or_else(|| ApiError {
+            message: format!("Camera not found: {}", id),
+            status: StatusCode::NOT_FOUND.as_u16(),
+        })?;
+
+    let mut stream = state
+        .cameras_repo
+        .create_stream(&Stream {
+            camera_id: camera.id,
+            name: req.name,
+            stream_type: StreamType::Rtsp,
+            url: req.url,
+            codec: req.codec,
+            width: req.width,
+            height: req.height,
+            framerate: req.framerate,
+            is_primary: Some(req.is_primary.unwrap_or(false)),
+            is_active: Some(false),
+            ..Stream::default()
+        })
+        .await?;
+
+    if let Err(e) = state
+        .stream_manager
+        .add_stream(stream_source(&camera, &stream), stream.id.to_string())
+    {
+        // Don't leave a row behind for a stream that never ran
+        state.cameras_repo.delete_stream(&stream.id).await?;
+        return Err(e.into());
+    }
+    state
+        .cameras_repo
+        .update_stream_status(&stream.id, true)
+        .await?;
+    stream.is_active = Some(true);
+    info!("Added stream {} to camera {}", stream.id, camera.id);
+
+    Ok((StatusCode::CREATED, Json(stream)))
+}
+
+/// Stop a stream's pipeline and mark it inactive. Rejected while it is being recorded.
+async fn remove_stream(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> ApiResult<StatusCode> {
+    if state.recording_manager.is_stream_recording(&id).await {
+        return Err(ApiError {
+            message: format!("Stream {} has recordings in progress; stop them first", id),
+            status: StatusCode::CONFLICT.as_u16(),
+        });
+    }
+
+    // Fails with NotFound, i.e. 404, when the stream isn't running
+    state.stream_manager.remove_stream(&id.to_string()).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+/// Check the request's bearer token grants at least `role`
+fn require_role(state: &AppState, headers: &HeaderMap, role: UserRole) -> ApiResult<Claims> {
+    let token = bearer_token(headers).ok_or_else(|| ApiError {
+        message: "Missing bearer token".to_string(),
+        status: StatusCode::UNAUTHORIZED.as_u16(),
+    })?;
+
+    Ok(state.auth_service.authorize(token, role)?)
+}
+
+/// Record a security-sensitive action in the audit log, attributed to the user of the
+/// request's bearer token if it carries a valid one.
+///
+/// The action has already happened, so failing to record it is only logged.
+async fn audit(state: &AppState, headers: &HeaderMap, client: SocketAddr, mut entry: AuditEntry) {
+    if let Some(claims) =
+        bearer_token(headers).and_then(|t| state.auth_service.authorize(t, UserRole::Viewer).ok())
+    {
+        entry.actor_id = claims.user_id().ok();
+        entry.actor_name = Some(claims.name);
+    }
+    entry.source_ip = Some(client.ip().to_string());
+
+    if let Err(e) = state.audit_repo.create(&entry).await {
+        warn!(
+            "Failed to write audit entry for {} of {} {}: {}",
+            entry.action, entry.target_type, entry.target_id, e
+        );
+    }
+}
+
+/// Look up a stream for the manual recording endpoints
+async fn stream_for_recording(state: &AppState, id: &Uuid) -> ApiResult<Stream> {
+    state
+        .cameras_repo
+        .get_stream_by_id(id)
+        .await?
+        .ok_or_else(|| ApiError {
+            message: format!("Stream not found: {}", id),
+            status: StatusCode::NOT_FOUND.as_u16(),
+        })
+}
+
+/// Start a manual ("record now") recording of a stream
+async fn start_stream_recording(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> ApiResult<Json<recording_controller::RecordingResponse>> {
+    let claims = require_role(&state, &headers, UserRole::Operator)?;
+    let stream = stream_for_recording(&state, &id).await?;
+
+    // A second start for the same stream fails with AlreadyExists, i.e. 409
+    let recording_id = state
+        .recording_manager
+        .start_manual_recording(&stream)
+        .await?;
+    info!(
+        "User {} started manual recording {} of stream {}",
+        claims.name, recording_id, id
+    );
+
+    Ok(Json(recording_controller::RecordingResponse {
+        recording_id: Some(recording_id),
+        status: "recording".to_string(),
+        message: format!("Manual recording started with ID {}", recording_id),
+    }))
+}
+
+/// Stop the manual recording of a stream
+async fn stop_stream_recording(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> ApiResult<Json<recording_controller::RecordingResponse>> {
+    let claims = require_role(&state, &headers, UserRole::Operator)?;
+    let stream = stream_for_recording(&state, &id).await?;
+
+    let recording_id = state
+        .recording_manager
+        .get_recording_status()
+        .await
+        .into_iter()
+        .find(|s| s.stream_id == stream.id && s.event_type == RecordingEventType::Manual)
+        .map(|s| s.recording_id);
+
+    // Fails with NotFound, i.e. 404, when no manual recording is running
+    state
+        .recording_manager
+        .stop_event_recording(RecordingEventType::Manual, &stream.id)
+        .await?;
+    info!(
+        "User {} stopped manual recording of stream {}",
+        claims.name, id
+    );
+
+    Ok(Json(recording_controller::RecordingResponse {
+        recording_id,
+        status: "stopped".to_string(),
+        message: "Manual recording stopped".to_string(),
+    }))
+}
+
+/// Test-record a stream for a few seconds into a throwaway file, to check recording
+/// works for it before relying on it. A failed test is a 200 with the errors in the report.
+async fn test_stream_recording(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> ApiResult<Json<RecordingTestReport>> {
+    let claims = require_role(&state, &headers, UserRole::Operator)?;
+    let stream = stream_for_recording(&state, &id).await?;
+
+    let report = state
+        .recording_manager
+        .test_recording(&stream, TEST_RECORDING_DURATION)
+        .await?;
+    info!(
+        "User {} test-recorded stream {}: {}",
+        claims.name,
+        id,
+        if report.success { "passed" } else { "failed" }
+    );
+
+    Ok(Json(report))
+}
+
 #[derive(Debug, Deserialize)]
 struct CameraUpdateRequest {
     name: Option<String>,
@@ -543,6 +1229,7 @@ struct CameraUpdateRequest {
     analytics_supported: Option<bool>,
     recording_mode: Option<String>,
     retention_days: Option<i32>,
+    isolated_recording: Option<bool>,
 }
 
 async fn update_camera(
@@ -615,30 +1302,70 @@ async fn update_camera(
     }
 
     if let Some(recording_mode) = req.recording_mode {
-        camera.recording_mode = Some(recording_mode);
+        camera.recording_mode = Some(parse_recording_mode(&recording_mode)?.to_string());
     }
 
     if let Some(retention_days) = req.retention_days {
         camera.retention_days = Some(retention_days);
     }
 
+    // Takes effect from the camera's next recording
+    if let Some(isolated) = req.isolated_recording {
+        state
+            .cameras_repo
+            .set_isolated_recording(&id, isolated)
+            .await?;
+    }
+
     // Update the camera with the new info
     let updated = state.cameras_repo.update(&camera).await?;
 
     Ok(Json(updated))
 }
 
+fn parse_recording_mode(mode: &str) -> ApiResult<RecordingMode> {
+    mode.parse().map_err(|message| ApiError {
+        message,
+        status: StatusCode::BAD_REQUEST.as_u16(),
+    })
+}
+
 #[derive(Debug, Deserialize)]
-struct CameraStatusUpdateRequest {
-    status: String,
+struct RecordingModeRequest {
+    recording_mode: String,
 }
 
-async fn update_camera_status(
+/// Switch a camera's recording mode. Disabling recording keeps its schedules; the
+/// scheduler stops their recordings on its next check and events are ignored
+async fn update_camera_recording_mode(
     State(state): State<AppState>,
     Path(id): Path<Uuid>,
-    Json(req): Json<CameraStatusUpdateRequest>,
+    Json(req): Json<RecordingModeRequest>,
 ) -> ApiResult<Json<Camera>> {
-    // Validate status value
+    let mode = parse_recording_mode(&req.recording_mode)?;
+
+    let mut camera = state
+        .cameras_repo
+        .get_by_id(&id)
+        .await?
+        .ok_or_else(|| ApiError {
+            message: format!("Camera not found: {}", id),
+            status: StatusCode::NOT_FOUND.as_u16(),
+        })?;
+
+    camera.recording_mode = Some(mode.to_string());
+    let updated = state.cameras_repo.update(&camera).await?;
+    info!("Recording mode of camera {} set to {}", id, mode);
+
+    Ok(Json(updated))
+}
+
+#[derive(Debug, Deserialize)]
+struct CameraStatusUpdateRequest {
+    status: String,
+}
+
+fn validate_camera_status(status: &str) -> ApiResult<()> {
     let valid_statuses = [
         "discovered",
         "connected",
@@ -647,7 +1374,7 @@ async fn update_camera_status(
         "error",
         "offline",
     ];
-    if !valid_statuses.contains(&req.status.as_str()) {
+    if !valid_statuses.contains(&status) {
         return Err(ApiError {
             message: format!(
                 "Invalid status. Must be one of: {}",
@@ -656,6 +1383,15 @@ async fn update_camera_status(
             status: StatusCode::BAD_REQUEST.as_u16(),
         });
     }
+    Ok(())
+}
+
+async fn update_camera_status(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(req): Json<CameraStatusUpdateRequest>,
+) -> ApiResult<Json<Camera>> {
+    validate_camera_status(&req.status)?;
 
     // Get the camera to update
     let mut camera = state
@@ -690,20 +1426,8 @@ async fn update_camera_status(
     Ok(Json(updated_camera))
 }
 
-async fn refresh_camera_details(
-    State(state): State<AppState>,
-    Path(id): Path<Uuid>,
-) -> ApiResult<Json<CameraWithStreams>> {
-    // Get existing camera
-    let camera = state
-        .cameras_repo
-        .get_by_id(&id)
-        .await?
-        .ok_or_else(|| ApiError {
-            message: format!("Camera not found: {}", id),
-            status: StatusCode::NOT_FOUND.as_u16(),
-        })?;
-
+/// Build an ONVIF client for a stored camera using its saved credentials
+async fn onvif_client_for(camera: &Camera) -> ApiResult<OnvifCamera> {
     // Ensure we have credentials
     let username = camera.username.clone().ok_or_else(|| ApiError {
         message: "Camera username is missing".to_string(),
@@ -715,21 +1439,211 @@ async fn refresh_camera_details(
         status: StatusCode::BAD_REQUEST.as_u16(),
     })?;
 
-    // Create ONVIF client to get fresh device information
-    let client = OnvifCameraBuilder::new()
+    let builder = OnvifCameraBuilder::new()
         .uri(&format!("http://{}", &camera.ip_address))?
-        .credentials(&username, &password)
-        .service_path(
-            camera
-                .onvif_endpoint
-                .as_deref()
-                .unwrap_or("onvif/device_service"),
-        )
+        .credentials(&username, &password);
+    // Reuse the service path found on connect; older rows fall back to probing
+    let builder = match camera.onvif_endpoint.as_deref() {
+        Some(path) if !path.is_empty() => builder.service_path(path),
+        _ => builder.probe_service_paths(COMMON_SERVICE_PATHS),
+    };
+
+    let client = builder
         .fix_time(true)
-        .auth_type("digest")
+        // Likewise for the auth that worked
+        .auth_type(camera.onvif_auth_type.as_deref().unwrap_or("auto"))
         .build()
         .await?;
 
+    Ok(client)
+}
+
+/// PTZ command, selected by `action`
+#[derive(Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum PtzRequest {
+    /// Move at the given velocities (-1.0..=1.0) until stopped
+    ContinuousMove {
+        #[serde(default)]
+        pan: f64,
+        #[serde(default)]
+        tilt: f64,
+        #[serde(default)]
+        zoom: f64,
+    },
+    /// Move to an absolute position
+    AbsoluteMove {
+        pan: f64,
+        tilt: f64,
+        zoom: Option<f64>,
+    },
+    Stop,
+    GotoPreset {
+        preset_token: String,
+    },
+}
+
+/// Look up a camera and make sure it can take PTZ commands
+async fn ptz_camera(state: &AppState, id: &Uuid) -> ApiResult<Camera> {
+    let camera = state
+        .cameras_repo
+        .get_by_id(id)
+        .await?
+        .ok_or_else(|| ApiError {
+            message: format!("Camera not found: {}", id),
+            status: StatusCode::NOT_FOUND.as_u16(),
+        })?;
+
+    if camera.ptz_supported != Some(true) {
+        return Err(ApiError {
+            message: format!("Camera {} does not support PTZ", id),
+            status: StatusCode::BAD_REQUEST.as_u16(),
+        });
+    }
+
+    Ok(camera)
+}
+
+async fn camera_ptz(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Json(req): Json<PtzRequest>,
+) -> ApiResult<Json<serde_json::Value>> {
+    let camera = ptz_camera(&state, &id).await?;
+    let client = onvif_client_for(&camera).await?;
+
+    let result = match &req {
+        PtzRequest::ContinuousMove { pan, tilt, zoom } => {
+            client.continuous_move(*pan, *tilt, *zoom).await
+        }
+        PtzRequest::AbsoluteMove { pan, tilt, zoom } => {
+            client.absolute_move(*pan, *tilt, *zoom).await
+        }
+        PtzRequest::Stop => client.ptz_stop().await,
+        PtzRequest::GotoPreset { preset_token } => client.goto_preset(preset_token).await,
+    };
+
+    // The camera answered, so this is a failed command rather than bad credentials
+    result.map_err(|e| ApiError {
+        message: e.to_string(),
+        status: StatusCode::BAD_GATEWAY.as_u16(),
+    })?;
+
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
+async fn get_camera_ptz_presets(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> ApiResult<Json<Vec<PtzPreset>>> {
+    let camera = ptz_camera(&state, &id).await?;
+    let client = onvif_client_for(&camera).await?;
+
+    let presets = client.get_presets().await.map_err(|e| ApiError {
+        message: e.to_string(),
+        status: StatusCode::BAD_GATEWAY.as_u16(),
+    })?;
+
+    Ok(Json(presets))
+}
+
+#[derive(Debug, Deserialize)]
+struct SnapshotParams {
+    /// Stream reference to sample ("primary", "sub", ...); defaults to the primary stream
+    stream: Option<String>,
+}
+
+/// How long to wait for a decodable frame when grabbing a snapshot
+const SNAPSHOT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Grab a JPEG still from a camera's live stream
+async fn get_camera_snapshot(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    Query(params): Query<SnapshotParams>,
+) -> ApiResult<Response> {
+    let camera = state
+        .cameras_repo
+        .get_with_streams_by_id(&id)
+        .await?
+        .ok_or_else(|| ApiError {
+            message: format!("Camera not found: {}", id),
+            status: StatusCode::NOT_FOUND.as_u16(),
+        })?;
+
+    let reference_type = params
+        .stream
+        .map(ReferenceType::from)
+        .unwrap_or(ReferenceType::Primary);
+    let stream_id = camera
+        .stream_references
+        .iter()
+        .find(|r| r.reference_type == reference_type)
+        .map(|r| r.stream_id)
+        .or_else(|| match reference_type {
+            ReferenceType::Primary => camera.streams.first().map(|s| s.id),
+            _ => None,
+        })
+        .ok_or_else(|| ApiError {
+            message: format!(
+                "Camera {} has no {} stream",
+                id,
+                reference_type.to_string().to_lowercase()
+            ),
+            status: StatusCode::NOT_FOUND.as_u16(),
+        })?
+        .to_string();
+
+    let jpeg = match state.snapshots.get(&stream_id) {
+        Some(jpeg) => jpeg,
+        None => {
+            let not_playing = || ApiError {
+                message: format!("Stream {} is not playing", stream_id),
+                status: StatusCode::SERVICE_UNAVAILABLE.as_u16(),
+            };
+            let status = state
+                .stream_manager
+                .get_stream_status(&stream_id)
+                .map_err(|_| not_playing())?;
+            if status.state != "Playing" {
+                return Err(not_playing());
+            }
+
+            let (pipeline, tee, _, _) = state.stream_manager.get_stream_access(&stream_id)?;
+            let jpeg = tokio::task::spawn_blocking(move || {
+                capture_jpeg(&pipeline, &tee, SNAPSHOT_TIMEOUT)
+            })
+            .await
+            .map_err(|e| anyhow::anyhow!("Snapshot task failed: {}", e))?
+            .map_err(|e| ApiError {
+                message: format!("Failed to capture snapshot: {}", e),
+                status: StatusCode::SERVICE_UNAVAILABLE.as_u16(),
+            })?;
+            state.snapshots.insert(&stream_id, jpeg.clone());
+            jpeg
+        }
+    };
+
+    Ok(([(header::CONTENT_TYPE, "image/jpeg")], jpeg).into_response())
+}
+
+async fn refresh_camera_details(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> ApiResult<Json<CameraWithStreams>> {
+    // Get existing camera
+    let camera = state
+        .cameras_repo
+        .get_by_id(&id)
+        .await?
+        .ok_or_else(|| ApiError {
+            message: format!("Camera not found: {}", id),
+            status: StatusCode::NOT_FOUND.as_u16(),
+        })?;
+
+    // Create ONVIF client to get fresh device information
+    let client = onvif_client_for(&camera).await?;
+
     // Get updated device information
     let device_info = client.get_device_information().await?;
 
@@ -743,6 +1657,8 @@ async fn refresh_camera_details(
     updated_camera.firmware_version = Some(device_info.firmware_version);
     updated_camera.serial_number = Some(device_info.serial_number);
     updated_camera.hardware_id = Some(device_info.hardware_id);
+    updated_camera.onvif_auth_type = Some(client.auth_type().to_string());
+    updated_camera.onvif_endpoint = Some(client.service_path().to_string());
     updated_camera.updated_at = Utc::now();
     updated_camera.last_updated = Some(Utc::now());
 
@@ -790,6 +1706,7 @@ async fn refresh_camera_details(
             stream.height = Some(height as i32);
             stream.resolution = Some(format!("{}x{}", width, height));
         }
+        stream.has_audio = Some(stream_response.audio_encoding.is_some());
 
         // Set stream type and primary flag
         stream.is_primary = Some(i == 0);
@@ -841,6 +1758,8 @@ async fn refresh_camera_details(
 async fn delete_camera(
     State(state): State<AppState>,
     Path(id): Path<Uuid>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
 ) -> ApiResult<Json<serde_json::Value>> {
     // Check if camera exists first
     let camera = state
@@ -875,9 +1794,24 @@ async fn delete_camera(
         }
     }
 
-    // Delete camera and all related data
+    // Stop the pipelines; NotFound only means a stream wasn't running
+    for stream in &streams {
+        if let Err(e) = state
+            .stream_manager
+            .remove_stream(&stream.id.to_string())
+            .await
+        {
+            debug!("Stream {} not stopped: {}", stream.id, e);
+        }
+    }
+
+    // Soft delete; the camera can be restored until the cleanup purges it
     let result = state.cameras_repo.delete(&id).await?;
 
+    let mut entry = AuditEntry::new(AuditAction::DeleteCamera, "camera", id);
+    entry.details = Some(serde_json::json!({ "name": camera.name }));
+    audit(&state, &headers, client, entry).await;
+
     // Publish camera deleted event
     let camera_events = crate::messaging::CameraEvents::new(state.message_broker.clone());
     if let Err(e) = camera_events.camera_deleted(id, &camera.name).await {
@@ -893,12 +1827,180 @@ async fn delete_camera(
     })))
 }
 
+/// Undo a camera's soft delete and start its streams again
+async fn restore_camera(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> ApiResult<Json<Camera>> {
+    if !state.cameras_repo.restore(&id).await? {
+        return Err(ApiError {
+            message: format!("No deleted camera: {}", id),
+            status: StatusCode::NOT_FOUND.as_u16(),
+        });
+    }
+
+    let camera = state
+        .cameras_repo
+        .get_by_id(&id)
+        .await?
+        .ok_or_else(|| ApiError {
+            message: format!("Camera not found: {}", id),
+            status: StatusCode::NOT_FOUND.as_u16(),
+        })?;
+
+    for stream in state.cameras_repo.get_streams(&id).await? {
+        let stream_id = stream.id.to_string();
+        match state
+            .stream_manager
+            .add_stream(stream_source(&camera, &stream), stream_id)
+        {
+            Ok(_) => {
+                state
+                    .cameras_repo
+                    .update_stream_status(&stream.id, true)
+                    .await?
+            }
+            Err(e) => warn!("Failed to restart stream {} on restore: {}", stream.id, e),
+        }
+    }
+    info!("Restored camera {}", id);
+
+    Ok(Json(camera))
+}
+
+/// How many cameras a bulk refresh talks to over ONVIF at once
+const BULK_REFRESH_CONCURRENCY: usize = 8;
+
+#[derive(Debug, Deserialize)]
+struct BulkCameraRequest {
+    ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+struct BulkStatusRequest {
+    ids: Vec<Uuid>,
+    status: String,
+}
+
+/// Outcome of a bulk operation for one camera
+#[derive(Debug, Serialize)]
+struct BulkResult {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// HTTP status the single-camera endpoint would have answered with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+}
+
+impl BulkResult {
+    fn from_result<T>(result: ApiResult<T>) -> Self {
+        match result {
+            Ok(_) => BulkResult {
+                success: true,
+                error: None,
+                status: None,
+            },
+            Err(e) => BulkResult {
+                success: false,
+                error: Some(e.message),
+                status: Some(e.status),
+            },
+        }
+    }
+}
+
+/// Distinct ids of a bulk request, rejecting an empty list
+fn bulk_ids(ids: Vec<Uuid>) -> ApiResult<BTreeSet<Uuid>> {
+    if ids.is_empty() {
+        return Err(ApiError {
+            message: "No camera ids given".to_string(),
+            status: StatusCode::BAD_REQUEST.as_u16(),
+        });
+    }
+    Ok(ids.into_iter().collect())
+}
+
+/// Set the status of several cameras, reporting the outcome per camera
+async fn bulk_update_camera_status(
+    State(state): State<AppState>,
+    Json(req): Json<BulkStatusRequest>,
+) -> ApiResult<Json<BTreeMap<Uuid, BulkResult>>> {
+    validate_camera_status(&req.status)?;
+
+    let mut results = BTreeMap::new();
+    for id in bulk_ids(req.ids)? {
+        let result = update_camera_status(
+            State(state.clone()),
+            Path(id),
+            Json(CameraStatusUpdateRequest {
+                status: req.status.clone(),
+            }),
+        )
+        .await;
+        results.insert(id, BulkResult::from_result(result));
+    }
+
+    Ok(Json(results))
+}
+
+/// Re-read device information and streams of several cameras, a few at a time
+async fn bulk_refresh_camera_details(
+    State(state): State<AppState>,
+    Json(req): Json<BulkCameraRequest>,
+) -> ApiResult<Json<BTreeMap<Uuid, BulkResult>>> {
+    let slots = Semaphore::new(BULK_REFRESH_CONCURRENCY);
+
+    let refreshes = bulk_ids(req.ids)?.into_iter().map(|id| {
+        let state = state.clone();
+        let slots = &slots;
+        async move {
+            // The semaphore is never closed
+            let _permit = slots.acquire().await.ok();
+            let result = refresh_camera_details(State(state), Path(id)).await;
+            if let Err(e) = &result {
+                warn!("Bulk refresh of camera {} failed: {}", id, e.message);
+            }
+            (id, BulkResult::from_result(result))
+        }
+    });
+    let results = futures::future::join_all(refreshes).await;
+
+    Ok(Json(results.into_iter().collect()))
+}
+
+/// Delete several cameras, stopping their recordings first
+async fn bulk_delete_cameras(
+    State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(req): Json<BulkCameraRequest>,
+) -> ApiResult<Json<BTreeMap<Uuid, BulkResult>>> {
+    let mut results = BTreeMap::new();
+    for id in bulk_ids(req.ids)? {
+        let result = delete_camera(
+            State(state.clone()),
+            Path(id),
+            ConnectInfo(client),
+            headers.clone(),
+        )
+        .await;
+        results.insert(id, BulkResult::from_result(result));
+    }
+
+    Ok(Json(results))
+}
+
 // Auth API Handlers
 async fn login(
     State(state): State<AppState>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
     Json(credentials): Json<LoginCredentials>,
 ) -> ApiResult<Json<(User, AuthToken)>> {
-    let (user, token) = state.auth_service.login(&credentials).await?;
+    let (user, token) = state
+        .auth_service
+        .login(&credentials, Some(client.ip()))
+        .await?;
     Ok(Json((user, token)))
 }
 
@@ -917,7 +2019,7 @@ async fn register(
         username: user.username,
         password: req.password,
     };
-    let (user, token) = state.auth_service.login(&credentials).await?;
+    let (user, token) = state.auth_service.login(&credentials, None).await?;
 
     Ok(Json((user, token)))
 }
@@ -970,14 +2072,20 @@ async fn change_password(
 async fn reset_password(
     State(state): State<AppState>,
     Path(user_id): Path<Uuid>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
 ) -> ApiResult<Json<serde_json::Value>> {
     let new_password = state.auth_service.reset_password(&user_id).await?;
+    let entry = AuditEntry::new(AuditAction::ResetPassword, "user", user_id);
+    audit(&state, &headers, client, entry).await;
     Ok(Json(serde_json::json!({ "password": new_password })))
 }
 
 async fn update_role(
     State(state): State<AppState>,
     Path(user_id): Path<Uuid>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(payload): Json<serde_json::Value>,
 ) -> ApiResult<Json<User>> {
     let role_str = payload
@@ -1001,12 +2109,18 @@ async fn update_role(
     };
 
     let user = state.auth_service.update_role(&user_id, role).await?;
+    let mut entry = AuditEntry::new(AuditAction::UpdateRole, "user", user_id);
+    entry.details = Some(serde_json::json!({ "role": role_str }));
+    audit(&state, &headers, client, entry).await;
+
     Ok(Json(user))
 }
 
 async fn set_user_active(
     State(state): State<AppState>,
     Path(user_id): Path<Uuid>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     Json(payload): Json<serde_json::Value>,
 ) -> ApiResult<Json<User>> {
     let active = payload
@@ -1018,6 +2132,10 @@ async fn set_user_active(
         })?;
 
     let user = state.auth_service.set_active(&user_id, active).await?;
+    let mut entry = AuditEntry::new(AuditAction::SetUserActive, "user", user_id);
+    entry.details = Some(serde_json::json!({ "active": active }));
+    audit(&state, &headers, client, entry).await;
+
     Ok(Json(user))
 }
 
@@ -1050,6 +2168,39 @@ async fn delete_user(
     Ok(Json(()))
 }
 
+#[derive(Debug, Deserialize)]
+struct AuditLogParams {
+    actor_id: Option<Uuid>,
+    /// Comma-separated action names
+    action: Option<String>,
+    start_time: Option<String>,
+    end_time: Option<String>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+/// Search the audit log, newest first (admins only)
+async fn get_audit_log(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<AuditLogParams>,
+) -> ApiResult<Json<Vec<AuditEntry>>> {
+    require_role(&state, &headers, UserRole::Admin)?;
+
+    let query = AuditSearchQuery {
+        actor_id: params.actor_id,
+        actions: params
+            .action
+            .map(|actions| actions.split(',').map(|a| a.trim().to_string()).collect()),
+        start_time: parse_time_param("start_time", &params.start_time)?,
+        end_time: parse_time_param("end_time", &params.end_time)?,
+        limit: params.limit,
+        offset: params.offset,
+    };
+
+    Ok(Json(state.audit_repo.search(&query).await?))
+}
+
 // Recording API handlers
 async fn search_recordings(
     State(state): State<AppState>,
@@ -1064,6 +2215,9 @@ async fn search_recordings(
         event_types: None,
         schedule_id: None,
         min_duration: None,
+        min_file_size: params.min_file_size,
+        max_file_size: params.max_file_size,
+        has_audio: params.has_audio,
         segment_id: params.segment_id,
         parent_recording_id: params
             .parent_recording_id
@@ -1104,30 +2258,22 @@ async fn search_recordings(
 
     // Parse event type if provided
     if let Some(event_type_str) = &params.event_type {
-        let event_type = match event_type_str.to_lowercase().as_str() {
-            "continuous" => crate::db::models::recording_models::RecordingEventType::Continuous,
-            "motion" => crate::db::models::recording_models::RecordingEventType::Motion,
-            "audio" => crate::db::models::recording_models::RecordingEventType::Audio,
-            "external" => crate::db::models::recording_models::RecordingEventType::External,
-            "manual" => crate::db::models::recording_models::RecordingEventType::Manual,
-            "analytics" => crate::db::models::recording_models::RecordingEventType::Analytics,
-            _ => {
-                return Err(ApiError {
-                    message: format!("Invalid event type: {}", event_type_str),
-                    status: StatusCode::BAD_REQUEST.as_u16(),
-                })
-            }
-        };
+        let event_type: RecordingEventType = event_type_str
+            .parse()
+            .map_err(|e: String| ApiError::new(StatusCode::BAD_REQUEST, e))?;
 
         query.event_types = Some(vec![event_type]);
     }
 
     // Execute search query
-    let recordings = state.recordings_repo.search(&query).await?;
+    let (recordings, total) = state.recordings_repo.search_with_total(&query).await?;
 
     // Convert to response format
     let mut response = HashMap::new();
     response.insert("count".to_string(), serde_json::json!(recordings.len()));
+    response.insert("total".to_string(), serde_json::json!(total));
+    response.insert("limit".to_string(), serde_json::json!(query.limit));
+    response.insert("offset".to_string(), serde_json::json!(query.offset));
     response.insert("recordings".to_string(), serde_json::to_value(&recordings)?);
 
     Ok(Json(response))
@@ -1149,84 +2295,717 @@ async fn get_recording_by_id(
     Ok(Json(serde_json::to_value(recording)?))
 }
 
+#[derive(Debug, Default, Deserialize)]
+struct DeleteRecordingQuery {
+    /// Stop the recording when it is still being written instead of refusing
+    #[serde(default)]
+    force: bool,
+}
+
+/// Delete a recording and its segments. One still being written is refused with 409, as
+/// the pipeline would go on writing files for a deleted row, unless `force` is set, which
+/// stops it first.
 async fn delete_recording(
     State(state): State<AppState>,
     Path(id): Path<Uuid>,
+    Query(query): Query<DeleteRecordingQuery>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
 ) -> ApiResult<Json<()>> {
+    let active = state
+        .recording_manager
+        .active_recording_ids()
+        .await
+        .contains(&id);
+    if active {
+        if !query.force {
+            return Err(ApiError {
+                message: format!(
+                    "Recording {} is still in progress; stop it first or pass force=true",
+                    id
+                ),
+                status: StatusCode::CONFLICT.as_u16(),
+            });
+        }
+
+        if let Err(e) = state.recording_manager.stop_recording_by_id(&id).await {
+            // Fine if it stopped on its own since the check
+            if state
+                .recording_manager
+                .active_recording_ids()
+                .await
+                .contains(&id)
+            {
+                return Err(e.into());
+            }
+        }
+        info!("Stopped recording {} to delete it", id);
+    }
+
     state.recordings_repo.delete(&id).await?;
+    let mut entry = AuditEntry::new(AuditAction::DeleteRecording, "recording", id);
+    if active {
+        entry.details = Some(serde_json::json!({ "stopped": true }));
+    }
+    audit(&state, &headers, client, entry).await;
     Ok(Json(()))
 }
 
-async fn stream_recording(State(_state): State<AppState>, Path(_id): Path<Uuid>) -> ApiResult<()> {
-    // Implement streaming logic - for now just return not implemented
-    Err(ApiError {
-        message: "Streaming not yet implemented".to_string(),
-        status: StatusCode::NOT_IMPLEMENTED.as_u16(),
-    })
-}
-
-async fn download_recording(
-    State(_state): State<AppState>,
-    Path(_id): Path<Uuid>,
-) -> ApiResult<()> {
-    // Implement download logic - for now just return not implemented
-    Err(ApiError {
-        message: "Download not yet implemented".to_string(),
-        status: StatusCode::NOT_IMPLEMENTED.as_u16(),
-    })
+#[derive(Debug, Deserialize)]
+struct SeekQuery {
+    /// Wall-clock time to seek to, RFC 3339
+    at: DateTime<Utc>,
 }
 
-async fn get_recordings_by_camera(
+/// Map a wall-clock time to the segment of a recording holding it and the offset into
+/// that segment's file
+async fn seek_recording(
     State(state): State<AppState>,
-    Path(camera_id): Path<Uuid>,
-) -> ApiResult<Json<Vec<serde_json::Value>>> {
-    // Create a search query for this camera's recordings
-    let query = crate::db::models::recording_models::RecordingSearchQuery {
-        camera_ids: Some(vec![camera_id]),
-        stream_ids: None,
-        start_time: None,
-        end_time: None,
-        event_types: None,
-        schedule_id: None,
-        min_duration: None,
-        segment_id: None,
-        parent_recording_id: None,
-        is_segment: Some(false), // Only return parent recordings
-        limit: Some(100),
-        offset: Some(0),
-    };
-
-    // Execute search query
-    let recordings = state.recordings_repo.search(&query).await?;
+    Path(id): Path<Uuid>,
+    Query(query): Query<SeekQuery>,
+) -> ApiResult<Json<recording_playback_controller::SeekPosition>> {
+    let recording = state
+        .recordings_repo
+        .get_by_id(&id)
+        .await?
+        .ok_or_else(|| ApiError {
+            message: format!("Recording not found: {}", id),
+            status: StatusCode::NOT_FOUND.as_u16(),
+        })?;
 
-    // Convert to JSON value array
-    let recordings_json = serde_json::to_value(recordings)?;
+    // A recording written as one file is its own only segment
+    let mut segments = state.recordings_repo.get_segments(&id).await?;
+    if segments.is_empty() {
+        segments.push(recording);
+    }
 
-    if let serde_json::Value::Array(recordings_array) = recordings_json {
-        Ok(Json(recordings_array))
-    } else {
-        Err(ApiError {
-            message: "Failed to convert recordings to JSON array".to_string(),
-            status: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
-        })
+    match recording_playback_controller::seek_position(&segments, query.at) {
+        Some(position) => Ok(Json(position)),
+        None => Err(ApiError {
+            message: format!("Recording {} has nothing at or after {}", id, query.at),
+            status: StatusCode::NOT_FOUND.as_u16(),
+        }),
     }
 }
 
-// Handler for getting schedules by camera ID
-async fn get_schedules_by_camera(
-    State(state): State<AppState>,
-    Path(camera_id): Path<Uuid>,
-) -> ApiResult<Json<Vec<RecordingSchedule>>> {
-    // Get schedules for the camera from repository
-    let schedules = state.schedules_repo.get_by_camera(&camera_id).await?;
-    Ok(Json(schedules))
+/// A segment of a recording, with what is on disk for it
+#[derive(Debug, Serialize)]
+struct SegmentFile {
+    #[serde(flatten)]
+    recording: Recording,
+    /// Whether the segment's file exists right now, on disk or in the archive store
+    file_exists: bool,
+    /// Whether the file was moved to the archive store
+    archived: bool,
+    /// Size of the file on disk, which can differ from the `file_size` recorded for it
+    size_on_disk: Option<u64>,
 }
 
-// Schedule API handlers
-async fn get_schedules(State(state): State<AppState>) -> ApiResult<Json<Vec<RecordingSchedule>>> {
-    // Get all schedules from repository
-    let schedules = state.schedules_repo.get_all().await?;
-    Ok(Json(schedules))
+#[derive(Debug, Serialize)]
+struct RecordingSegmentsResponse {
+    recording_id: Uuid,
+    /// In segment order
+    segments: Vec<SegmentFile>,
+    /// Seconds the segments whose files exist add up to, i.e. what can be played back
+    total_duration: u64,
+    /// Segments whose files are gone
+    missing_segments: usize,
+}
+
+/// List the segments of a recording in order, checking each one's file on disk. Archived
+/// segments count as present.
+async fn list_recording_segments(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> ApiResult<Json<RecordingSegmentsResponse>> {
+    state.recordings_repo.get_by_id(&id).await?.ok_or_else(|| {
+        ApiError::new(
+            StatusCode::NOT_FOUND,
+            format!("Recording not found: {}", id),
+        )
+    })?;
+
+    let mut segments = Vec::new();
+    for recording in state.recordings_repo.get_segments(&id).await? {
+        // An archived segment's file_path is its object location, not a file on disk
+        let archived = archived_key(&recording).is_some();
+        let size_on_disk = match archived {
+            true => None,
+            false => tokio::fs::metadata(&recording.file_path)
+                .await
+                .ok()
+                .filter(|metadata| metadata.is_file())
+                .map(|metadata| metadata.len()),
+        };
+        segments.push(SegmentFile {
+            recording,
+            file_exists: archived || size_on_disk.is_some(),
+            archived,
+            size_on_disk,
+        });
+    }
+
+    let total_duration = segments
+        .iter()
+        .filter(|segment| segment.file_exists)
+        .map(|segment| segment.recording.duration)
+        .sum();
+    let missing_segments = segments
+        .iter()
+        .filter(|segment| !segment.file_exists)
+        .count();
+
+    Ok(Json(RecordingSegmentsResponse {
+        recording_id: id,
+        segments,
+        total_duration,
+        missing_segments,
+    }))
+}
+
+/// Look up a recording, 404 when it's missing
+async fn find_recording(state: &AppState, id: Uuid) -> ApiResult<Recording> {
+    state
+        .recordings_repo
+        .get_by_id(&id)
+        .await?
+        .ok_or_else(|| ApiError {
+            message: format!("Recording not found: {}", id),
+            status: StatusCode::NOT_FOUND.as_u16(),
+        })
+}
+
+/// Redirect to the storage provider's signed URL of the recording, when it hands one out
+fn signed_redirect(state: &AppState, recording: &Recording) -> ApiResult<Option<Response>> {
+    // Archived files are no longer under the signed URL base
+    if archived_key(recording).is_some() {
+        return Ok(None);
+    }
+    Ok(state
+        .storage_urls
+        .url_for(&recording.file_path)?
+        .map(|signed| Redirect::temporary(&signed.url).into_response()))
+}
+
+/// Open a recording's file, fetching it from the archive store when it's archived. Only
+/// files under the recordings or storage tier directory are served; 404 when it's missing.
+async fn open_recording_file(
+    state: &AppState,
+    recording: &Recording,
+) -> ApiResult<tokio::fs::File> {
+    let path = state.hls.fetch_source(recording).await?;
+    tokio::fs::File::open(&path)
+        .await
+        .map_err(|e| hls_service::file_error(&path, &e, "Recording file"))
+}
+
+async fn stream_recording(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    let recording = find_recording(&state, id).await?;
+    if let Some(redirect) = signed_redirect(&state, &recording)? {
+        return Ok(redirect);
+    }
+    let file = open_recording_file(&state, &recording).await?;
+    let response_headers =
+        HeaderMap::from_iter([(header::CONTENT_TYPE, "video/mp4".parse().unwrap())]);
+    Ok(hls_service::file_response(file, &headers, response_headers).await)
+}
+
+async fn download_recording(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    let recording = find_recording(&state, id).await?;
+    if let Some(redirect) = signed_redirect(&state, &recording)? {
+        return Ok(redirect);
+    }
+    let file = open_recording_file(&state, &recording).await?;
+    let response_headers = HeaderMap::from_iter([
+        (header::CONTENT_TYPE, "video/mp4".parse().unwrap()),
+        (
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}.{}\"", id, recording.format)
+                .parse()
+                .unwrap(),
+        ),
+    ]);
+    Ok(hls_service::file_response(file, &headers, response_headers).await)
+}
+
+/// Served when a recording has no decodable first segment
+const THUMBNAIL_PLACEHOLDER: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" width="320" height="180" viewBox="0 0 320 180"><rect width="320" height="180" fill="#1f2937"/><text x="160" y="96" fill="#9ca3af" font-family="sans-serif" font-size="16" text-anchor="middle">No preview</text></svg>"##;
+
+async fn get_recording_thumbnail(
+    State(state): State<AppState>,
+    Path(id): Path<Uuid>,
+) -> ApiResult<Response> {
+    let recording = state
+        .recordings_repo
+        .get_by_id(&id)
+        .await?
+        .ok_or_else(|| ApiError {
+            message: format!("Recording not found: {}", id),
+            status: StatusCode::NOT_FOUND.as_u16(),
+        })?;
+
+    let thumbnail = match thumbnail::ensure_thumbnail(&state.recordings_repo, &recording).await {
+        Ok(path) => tokio::fs::read(&path).await.map_err(anyhow::Error::from),
+        Err(e) => Err(e),
+    };
+
+    match thumbnail {
+        Ok(jpeg) => Ok(([(header::CONTENT_TYPE, "image/jpeg")], jpeg).into_response()),
+        Err(e) => {
+            warn!(
+                "No thumbnail for recording {}, serving placeholder: {}",
+                id, e
+            );
+            Ok((
+                [(header::CONTENT_TYPE, "image/svg+xml")],
+                THUMBNAIL_PLACEHOLDER,
+            )
+                .into_response())
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportRequest {
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+    /// Stream to export; defaults to the one with the most footage in the range
+    stream_id: Option<Uuid>,
+    /// Burn the camera name and recording time into the video
+    #[serde(default)]
+    overlay: bool,
+    /// strftime format of the burnt-in time, rendered in UTC
+    overlay_format: Option<String>,
+}
+
+async fn create_camera_export(
+    State(state): State<AppState>,
+    Path(camera_id): Path<Uuid>,
+    ConnectInfo(client): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(request): Json<ExportRequest>,
+) -> ApiResult<(StatusCode, Json<export_service::ExportJob>)> {
+    let camera = state
+        .cameras_repo
+        .get_by_id(&camera_id)
+        .await?
+        .ok_or_else(|| ApiError {
+            message: format!("Camera not found: {}", camera_id),
+            status: StatusCode::NOT_FOUND.as_u16(),
+        })?;
+
+    let span = request.end_time - request.start_time;
+    if span <= chrono::Duration::zero() || span > export_service::MAX_EXPORT_SPAN {
+        return Err(ApiError {
+            message: format!(
+                "end_time must be after start_time and at most {} hours later",
+                export_service::MAX_EXPORT_SPAN.num_hours()
+            ),
+            status: StatusCode::BAD_REQUEST.as_u16(),
+        });
+    }
+
+    let query = crate::db::models::recording_models::RecordingSearchQuery {
+        camera_ids: Some(vec![camera_id]),
+        stream_ids: request.stream_id.map(|id| vec![id]),
+        start_time: Some(request.start_time - export_service::SEGMENT_LOOKBACK),
+        end_time: Some(request.end_time),
+        event_types: None,
+        schedule_id: None,
+        min_duration: None,
+        min_file_size: None,
+        max_file_size: None,
+        has_audio: None,
+        segment_id: None,
+        parent_recording_id: None,
+        is_segment: None,
+        limit: Some(10_000),
+        offset: None,
+    };
+    let mut recordings = state.recordings_repo.search(&query).await?;
+    // Archived segments can only be exported while the archive store is configured
+    if state.archiver.is_none() {
+        recordings.retain(|r| archived_key(r).is_none());
+    }
+
+    let mut plan = export_service::plan_export(
+        &recordings,
+        request.start_time,
+        request.end_time,
+        request.stream_id,
+    )
+    .ok_or_else(|| ApiError {
+        message: format!(
+            "No finished recordings of camera {} between {} and {}",
+            camera_id, request.start_time, request.end_time
+        ),
+        status: StatusCode::NOT_FOUND.as_u16(),
+    })?;
+
+    // FFmpeg reads archived segments from the archive cache
+    if let Some(archiver) = &state.archiver {
+        for part in &mut plan.parts {
+            let archived = recordings
+                .iter()
+                .find(|r| r.file_path == part.path && archived_key(r).is_some());
+            if let Some(recording) = archived {
+                part.path = archiver.local_path(recording).await?;
+            }
+        }
+    }
+
+    let overlay = request.overlay.then(|| export_service::ExportOverlay {
+        camera_name: camera.name,
+        format: request
+            .overlay_format
+            .filter(|format| !format.trim().is_empty())
+            .unwrap_or_else(|| export_service::DEFAULT_OVERLAY_FORMAT.to_string()),
+    });
+
+    let job = state
+        .exports
+        .submit(
+            camera_id,
+            request.start_time,
+            request.end_time,
+            plan,
+            overlay,
+        )
+        .await?;
+    info!(
+        "Queued export {} of camera {} from {} to {}",
+        job.id, camera_id, request.start_time, request.end_time
+    );
+    let mut entry = AuditEntry::new(AuditAction::Export, "camera", camera_id);
+    entry.details = Some(serde_json::json!({
+        "export_id": job.id,
+        "start_time": request.start_time,
+        "end_time": request.end_time,
+    }));
+    audit(&state, &headers, client, entry).await;
+
+    Ok((StatusCode::ACCEPTED, Json(job)))
+}
+
+/// Report an export's progress, or download the MP4 once it is complete
+async fn get_export(
+    State(state): State<AppState>,
+    Path(job_id): Path<Uuid>,
+) -> ApiResult<Response> {
+    let job = state.exports.job(&job_id).await.ok_or_else(|| ApiError {
+        message: format!("Export not found: {}", job_id),
+        status: StatusCode::NOT_FOUND.as_u16(),
+    })?;
+
+    match job.status {
+        export_service::ExportStatus::Completed => {
+            let file = tokio::fs::File::open(&job.output_path)
+                .await
+                .map_err(|e| ApiError {
+                    message: format!("Export {} is no longer available: {}", job_id, e),
+                    status: StatusCode::GONE.as_u16(),
+                })?;
+            let filename = format!(
+                "camera-{}-{}.mp4",
+                job.camera_id,
+                job.start_time.format("%Y%m%dT%H%M%SZ")
+            );
+            Ok((
+                [
+                    (header::CONTENT_TYPE, "video/mp4".to_string()),
+                    (
+                        header::CONTENT_DISPOSITION,
+                        format!("attachment; filename=\"{}\"", filename),
+                    ),
+                ],
+                axum::body::StreamBody::new(tokio_util::io::ReaderStream::new(file)),
+            )
+                .into_response())
+        }
+        export_service::ExportStatus::Failed => Ok(Json(job).into_response()),
+        _ => Ok((StatusCode::ACCEPTED, Json(job)).into_response()),
+    }
+}
+
+async fn get_recordings_by_camera(
+    State(state): State<AppState>,
+    Path(camera_id): Path<Uuid>,
+) -> ApiResult<Json<Vec<serde_json::Value>>> {
+    // Create a search query for this camera's recordings
+    let query = crate::db::models::recording_models::RecordingSearchQuery {
+        camera_ids: Some(vec![camera_id]),
+        stream_ids: None,
+        start_time: None,
+        end_time: None,
+        event_types: None,
+        schedule_id: None,
+        min_duration: None,
+        min_file_size: None,
+        max_file_size: None,
+        has_audio: None,
+        segment_id: None,
+        parent_recording_id: None,
+        is_segment: Some(false), // Only return parent recordings
+        limit: Some(100),
+        offset: Some(0),
+    };
+
+    // Execute search query
+    let recordings = state.recordings_repo.search(&query).await?;
+
+    // Convert to JSON value array
+    let recordings_json = serde_json::to_value(recordings)?;
+
+    if let serde_json::Value::Array(recordings_array) = recordings_json {
+        Ok(Json(recordings_array))
+    } else {
+        Err(ApiError {
+            message: "Failed to convert recordings to JSON array".to_string(),
+            status: StatusCode::INTERNAL_SERVER_ERROR.as_u16(),
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct CameraEventsParams {
+    start_time: Option<String>,
+    end_time: Option<String>,
+    event_type: Option<String>,
+    is_active: Option<bool>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+}
+
+/// Parse an optional RFC 3339 query parameter, rejecting malformed values
+fn parse_time_param(
+    name: &str,
+    value: &Option<String>,
+) -> ApiResult<Option<chrono::DateTime<Utc>>> {
+    match value {
+        Some(value) => chrono::DateTime::parse_from_rfc3339(value)
+            .map(|t| Some(t.with_timezone(&Utc)))
+            .map_err(|e| ApiError {
+                message: format!("Invalid {} '{}': {}", name, value, e),
+                status: StatusCode::BAD_REQUEST.as_u16(),
+            }),
+        None => Ok(None),
+    }
+}
+
+// Handler for getting ONVIF analytics events for a camera
+async fn get_camera_events(
+    State(state): State<AppState>,
+    Path(camera_id): Path<Uuid>,
+    Query(params): Query<CameraEventsParams>,
+) -> ApiResult<Json<Vec<AnalyticsEvent>>> {
+    let query = AnalyticsEventSearchQuery {
+        camera_ids: Some(vec![camera_id]),
+        start_time: parse_time_param("start_time", &params.start_time)?,
+        end_time: parse_time_param("end_time", &params.end_time)?,
+        event_types: params
+            .event_type
+            .map(|types| types.split(',').map(|t| t.trim().to_string()).collect()),
+        is_active: params.is_active,
+        limit: params.limit,
+        offset: params.offset,
+        ..Default::default()
+    };
+
+    let events = state.analytics_events_repo.search(&query).await?;
+
+    Ok(Json(events))
+}
+
+/// Event settings of a camera, or the defaults when it has none stored
+async fn get_camera_event_settings(
+    State(state): State<AppState>,
+    Path(camera_id): Path<Uuid>,
+) -> ApiResult<Json<EventSettings>> {
+    let settings = match state
+        .event_settings_repo
+        .get_by_camera_id(&camera_id)
+        .await?
+    {
+        Some(settings) => settings,
+        None => {
+            require_camera(&state, &camera_id).await?;
+            EventSettings::defaults_for(camera_id)
+        }
+    };
+
+    Ok(Json(settings))
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateEventSettingsRequest {
+    enabled: Option<bool>,
+    event_types: Option<Vec<String>>,
+    event_topic_expressions: Option<Vec<String>>,
+    trigger_recording: Option<bool>,
+    recording_duration: Option<i32>,
+}
+
+/// Longest recording an event may trigger, in seconds
+const MAX_EVENT_RECORDING_DURATION: i32 = 24 * 60 * 60;
+
+/// Update the event settings of a camera; fields left out keep their current (or default)
+/// values
+async fn update_camera_event_settings(
+    State(state): State<AppState>,
+    Path(camera_id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(req): Json<UpdateEventSettingsRequest>,
+) -> ApiResult<Json<EventSettings>> {
+    require_camera(&state, &camera_id).await?;
+    let mut settings = match state
+        .event_settings_repo
+        .get_by_camera_id(&camera_id)
+        .await?
+    {
+        Some(settings) => settings,
+        None => EventSettings {
+            created_by: bearer_token(&headers)
+                .and_then(|t| state.auth_service.authorize(t, UserRole::Viewer).ok())
+                .and_then(|claims| claims.user_id().ok()),
+            ..EventSettings::defaults_for(camera_id)
+        },
+    };
+
+    if let Some(enabled) = req.enabled {
+        settings.enabled = enabled;
+    }
+
+    if let Some(event_types) = req.event_types {
+        let mut parsed = Vec::new();
+        for event_type in &event_types {
+            let event_type: RecordingEventType = event_type
+                .parse()
+                .map_err(|e: String| ApiError::new(StatusCode::BAD_REQUEST, e))?;
+            if !TRIGGER_EVENT_TYPES.contains(&event_type) {
+                return Err(ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    format!("Event type '{}' can't trigger a recording", event_type),
+                ));
+            }
+            if !parsed.contains(&event_type) {
+                parsed.push(event_type);
+            }
+        }
+        settings.event_types = parsed.iter().map(|t| t.to_string()).collect();
+    }
+
+    if let Some(expressions) = req.event_topic_expressions {
+        if expressions.iter().any(|e| e.trim().is_empty()) {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "Event topic expressions must not be empty",
+            ));
+        }
+        settings.event_topic_expressions =
+            expressions.iter().map(|e| e.trim().to_string()).collect();
+    }
+
+    if let Some(trigger_recording) = req.trigger_recording {
+        settings.trigger_recording = trigger_recording;
+    }
+
+    if let Some(duration) = req.recording_duration {
+        if !(1..=MAX_EVENT_RECORDING_DURATION).contains(&duration) {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                format!(
+                    "Recording duration must be between 1 and {} seconds",
+                    MAX_EVENT_RECORDING_DURATION
+                ),
+            ));
+        }
+        settings.recording_duration = duration;
+    }
+
+    let settings = state.event_settings_repo.upsert(&settings).await?;
+    Ok(Json(settings))
+}
+
+/// 404 unless the camera exists
+async fn require_camera(state: &AppState, camera_id: &Uuid) -> ApiResult<()> {
+    state
+        .cameras_repo
+        .get_by_id(camera_id)
+        .await?
+        .ok_or_else(|| ApiError {
+            message: format!("Camera not found: {}", camera_id),
+            status: StatusCode::NOT_FOUND.as_u16(),
+        })?;
+    Ok(())
+}
+
+// Handler for getting schedules by camera ID
+async fn get_schedules_by_camera(
+    State(state): State<AppState>,
+    Path(camera_id): Path<Uuid>,
+) -> ApiResult<Json<Vec<RecordingSchedule>>> {
+    // Get schedules for the camera from repository
+    let schedules = state.schedules_repo.get_by_camera(&camera_id).await?;
+    Ok(Json(schedules))
+}
+
+/// Prometheus text-format metrics, with state gauges refreshed from the managers
+async fn get_metrics(State(state): State<AppState>) -> ApiResult<Response> {
+    use crate::metrics;
+
+    let recordings = state.recording_manager.get_recording_status().await;
+    metrics::ACTIVE_RECORDINGS.set(recordings.len() as i64);
+
+    let streams = state.stream_manager.list_streams();
+    metrics::ACTIVE_STREAMS.set(streams.len() as i64);
+    metrics::STREAM_PIPELINE_STATE.reset();
+    for (stream_id, _) in &streams {
+        if let Ok(status) = state.stream_manager.get_stream_status(stream_id) {
+            metrics::STREAM_PIPELINE_STATE
+                .with_label_values(&[stream_id.as_str(), status.state.as_str()])
+                .set(1);
+        }
+    }
+
+    metrics::HLS_JOBS.set(state.hls.running_jobs() as i64);
+
+    let idle = state.db_pool.num_idle() as i64;
+    metrics::DB_POOL_CONNECTIONS
+        .with_label_values(&["idle"])
+        .set(idle);
+    metrics::DB_POOL_CONNECTIONS
+        .with_label_values(&["in_use"])
+        .set(state.db_pool.size() as i64 - idle);
+
+    Ok((
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        metrics::render()?,
+    )
+        .into_response())
+}
+
+async fn get_scheduler_status(State(state): State<AppState>) -> ApiResult<Json<SchedulerStatus>> {
+    let scheduler = state.scheduler.as_ref().ok_or_else(|| ApiError {
+        message: "Recording scheduler is not running".to_string(),
+        status: StatusCode::SERVICE_UNAVAILABLE.as_u16(),
+    })?;
+
+    Ok(Json(scheduler.status().await?))
+}
+
+// Schedule API handlers
+async fn get_schedules(State(state): State<AppState>) -> ApiResult<Json<Vec<RecordingSchedule>>> {
+    // Get all schedules from repository
+    let schedules = state.schedules_repo.get_all().await?;
+    Ok(Json(schedules))
 }
 
 #[derive(Debug, Deserialize)]
@@ -1239,6 +3018,55 @@ struct CreateScheduleRequest {
     start_time: String,
     end_time: String,
     retention_days: i32,
+    /// IANA time zone for the days and times, UTC when omitted
+    timezone: Option<String>,
+}
+
+/// Two enabled schedules recording the same stream at once would fight over the pipeline
+async fn reject_overlapping_schedule(
+    state: &AppState,
+    schedule: &RecordingSchedule,
+) -> ApiResult<()> {
+    if !schedule.enabled {
+        return Ok(());
+    }
+
+    let conflicts = state.schedules_repo.find_overlapping(schedule).await?;
+    if conflicts.is_empty() {
+        return Ok(());
+    }
+
+    let ids: Vec<String> = conflicts.iter().map(|id| id.to_string()).collect();
+    Err(ApiError {
+        message: format!(
+            "Schedule overlaps existing schedule(s) for this stream: {}",
+            ids.join(", ")
+        ),
+        status: StatusCode::CONFLICT.as_u16(),
+    })
+}
+
+/// Check a schedule's time zone is a known IANA name
+fn validate_timezone(timezone: &str) -> ApiResult<()> {
+    if parse_timezone(timezone).is_none() {
+        return Err(ApiError {
+            message: format!("Unknown time zone: {}", timezone),
+            status: StatusCode::BAD_REQUEST.as_u16(),
+        });
+    }
+    Ok(())
+}
+
+/// End before start is an overnight schedule; only an empty window is rejected
+fn validate_schedule_window(schedule: &RecordingSchedule) -> ApiResult<()> {
+    if schedule.weekly_windows().is_empty() {
+        return Err(ApiError {
+            message: "Schedule must have at least one day and differing start and end times"
+                .to_string(),
+            status: StatusCode::BAD_REQUEST.as_u16(),
+        });
+    }
+    Ok(())
 }
 
 async fn create_schedule(
@@ -1264,6 +3092,9 @@ async fn create_schedule(
         }
     }
 
+    let timezone = req.timezone.unwrap_or_else(|| "UTC".to_string());
+    validate_timezone(&timezone)?;
+
     // Create schedule object
     let now = Utc::now();
     let schedule = RecordingSchedule {
@@ -1283,8 +3114,12 @@ async fn create_schedule(
         record_on_analytics: false, // Default to false for event-based recording
         record_on_external: false, // Default to false for event-based recording
         continuous_recording: true, // Default to true for continuous recording
+        timezone,
     };
 
+    validate_schedule_window(&schedule)?;
+    reject_overlapping_schedule(&state, &schedule).await?;
+
     // Create schedule in repository
     let created_schedule = state.schedules_repo.create(&schedule).await?;
     Ok(Json(created_schedule))
@@ -1317,6 +3152,7 @@ struct UpdateScheduleRequest {
     start_time: Option<String>,
     end_time: Option<String>,
     retention_days: Option<i32>,
+    timezone: Option<String>,
 }
 
 async fn update_schedule(
@@ -1392,9 +3228,17 @@ async fn update_schedule(
         schedule.retention_days = retention_days;
     }
 
+    if let Some(timezone) = req.timezone {
+        validate_timezone(&timezone)?;
+        schedule.timezone = timezone;
+    }
+
     // Update timestamp
     schedule.updated_at = Utc::now();
 
+    validate_schedule_window(&schedule)?;
+    reject_overlapping_schedule(&state, &schedule).await?;
+
     // Update schedule in repository
     let updated_schedule = state.schedules_repo.update(&schedule).await?;
     Ok(Json(updated_schedule))
@@ -1434,3 +3278,668 @@ async fn set_schedule_enabled(
 
     Ok(Json(()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{BrokerBackend, Config, MessageBrokerConfig};
+    use crate::db::test_fixtures::insert_camera_and_stream;
+    use gstreamer::prelude::*;
+
+    /// Application state on top of `pool`, without streams or an external broker
+    async fn test_state(pool: Arc<PgPool>) -> Result<AppState> {
+        let config = Config::default();
+        let recordings_dir = std::env::temp_dir().join("g-streamer-test-recordings");
+        let stream_manager = Arc::new(StreamManager::new(pool.clone()));
+        let message_broker = crate::messaging::MessageBroker::new(MessageBrokerConfig {
+            backend: BrokerBackend::None,
+            ..MessageBrokerConfig::default()
+        })
+        .await?;
+
+        Ok(AppState {
+            db_pool: pool.clone(),
+            cameras_repo: Arc::new(CamerasRepository::new(pool.clone())),
+            stream_manager: stream_manager.clone(),
+            auth_service: Arc::new(AuthService::new(pool.clone(), &config.security)),
+            recording_manager: Arc::new(RecordingManager::new(
+                pool.clone(),
+                stream_manager,
+                &recordings_dir,
+                30,
+                "mp4",
+            )),
+            recordings_repo: Arc::new(RecordingsRepository::new(pool.clone())),
+            schedules_repo: Arc::new(SchedulesRepository::new(pool.clone())),
+            analytics_events_repo: Arc::new(AnalyticsEventsRepository::new(pool.clone())),
+            event_settings_repo: Arc::new(EventSettingsRepository::new(pool.clone())),
+            audit_repo: Arc::new(AuditLogRepository::new(pool.clone())),
+            message_broker: Arc::new(message_broker),
+            hls_service: None,
+            hls: Arc::new(hls_service::HlsService::new(
+                std::env::temp_dir().join("g-streamer-test-hls"),
+                &recordings_dir,
+                1,
+                std::time::Duration::from_secs(1),
+            )),
+            exports: Arc::new(export_service::ExportService::new(
+                std::env::temp_dir().join("g-streamer-test-exports"),
+                &recordings_dir,
+            )),
+            scheduler: None,
+            snapshots: Arc::new(SnapshotCache::new(std::time::Duration::from_secs(1))),
+            api_config: config.api,
+            onvif_config: config.onvif,
+            storage_urls: Arc::new(LocalUrlProvider),
+            archiver: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn deleting_a_camera_writes_an_audit_entry() -> Result<()> {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else {
+            println!("Skipping audit log test. Set TEST_DATABASE_URL to run.");
+            return Ok(());
+        };
+
+        let pool = Arc::new(PgPool::connect(&database_url).await?);
+        let state = test_state(pool.clone()).await?;
+
+        let (camera_id, _) = insert_camera_and_stream(&*pool).await?;
+
+        let client: SocketAddr = "192.0.2.7:51000".parse()?;
+        delete_camera(
+            State(state.clone()),
+            Path(camera_id),
+            ConnectInfo(client),
+            HeaderMap::new(),
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!(e.message))?;
+
+        let entries = state
+            .audit_repo
+            .search(&AuditSearchQuery {
+                actions: Some(vec![AuditAction::DeleteCamera.to_string()]),
+                ..Default::default()
+            })
+            .await?;
+        let entry = entries
+            .iter()
+            .find(|e| e.target_id == camera_id.to_string())
+            .expect("no audit entry for the deleted camera");
+        assert_eq!(entry.target_type, "camera");
+        assert_eq!(entry.actor_id, None);
+        assert_eq!(entry.source_ip.as_deref(), Some("192.0.2.7"));
+        assert_eq!(entry.details.as_ref().unwrap()["name"], "audit-test");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn deleting_an_in_progress_recording_needs_force() -> Result<()> {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else {
+            println!("Skipping recording delete test. Set TEST_DATABASE_URL to run.");
+            return Ok(());
+        };
+
+        let pool = Arc::new(PgPool::connect(&database_url).await?);
+        let state = test_state(pool.clone()).await?;
+
+        let recording_id = Uuid::new_v4();
+        let now = Utc::now();
+        let (camera_id, stream_id) = insert_camera_and_stream(&*pool).await?;
+        sqlx::query(
+            "INSERT INTO recordings (id, camera_id, stream_id, start_time, end_time, file_path, format, resolution, fps, created_at) VALUES ($1, $2, $3, $4, $4, '/tmp/delete-test', 'mp4', '1280x720', 25, $4)",
+        )
+        .bind(recording_id)
+        .bind(camera_id)
+        .bind(stream_id)
+        .bind(now)
+        .execute(&*pool)
+        .await?;
+
+        let pipeline = state
+            .recording_manager
+            .insert_test_recording(recording_id, camera_id, stream_id)
+            .await?;
+        let client: SocketAddr = "192.0.2.7:51000".parse()?;
+        let delete = |force: bool| {
+            delete_recording(
+                State(state.clone()),
+                Path(recording_id),
+                Query(DeleteRecordingQuery { force }),
+                ConnectInfo(client),
+                HeaderMap::new(),
+            )
+        };
+
+        let refused = delete(false)
+            .await
+            .err()
+            .expect("in-progress recording deleted");
+        assert_eq!(refused.status, StatusCode::CONFLICT.as_u16());
+        assert!(state
+            .recordings_repo
+            .get_by_id(&recording_id)
+            .await?
+            .is_some());
+
+        delete(true).await.map_err(|e| anyhow::anyhow!(e.message))?;
+        assert!(!state
+            .recording_manager
+            .active_recording_ids()
+            .await
+            .contains(&recording_id));
+        assert!(state
+            .recordings_repo
+            .get_by_id(&recording_id)
+            .await?
+            .is_none());
+
+        pipeline.set_state(gstreamer::State::Null)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn only_configured_origins_pass_cors() -> Result<()> {
+        use tower::ServiceExt;
+
+        let preflight = |origin: &str| {
+            axum::http::Request::builder()
+                .method(Method::OPTIONS)
+                .uri("/api/cameras")
+                .header(header::ORIGIN, origin)
+                .header(header::ACCESS_CONTROL_REQUEST_METHOD, "GET")
+                .body(axum::body::Body::empty())
+        };
+        let mut config = Config::default().api;
+
+        config.cors_allowed_origins = vec!["https://nvr.example.com".to_string()];
+        let app = Router::new()
+            .route("/api/cameras", get(|| async { "[]" }))
+            .layer(cors_layer(&config));
+        let allowed = app
+            .clone()
+            .oneshot(preflight("https://nvr.example.com")?)
+            .await?;
+        let headers = allowed.headers();
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://nvr.example.com"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_CREDENTIALS], "true");
+        let rejected = app.oneshot(preflight("https://evil.example.com")?).await?;
+        assert!(rejected
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .is_none());
+
+        // The wildcard is opt-in and never allows credentials
+        config.cors_allowed_origins = vec!["*".to_string()];
+        let app = Router::new()
+            .route("/api/cameras", get(|| async { "[]" }))
+            .layer(cors_layer(&config));
+        let any = app.oneshot(preflight("https://evil.example.com")?).await?;
+        assert_eq!(any.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+        assert!(any
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS)
+            .is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn in_flight_requests_finish_during_shutdown() -> Result<()> {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let started = Arc::new(tokio::sync::Notify::new());
+        let app = Router::new().route(
+            "/download",
+            get({
+                let started = started.clone();
+                move || async move {
+                    started.notify_one();
+                    tokio::time::sleep(Duration::from_millis(300)).await;
+                    "all the bytes"
+                }
+            }),
+        );
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(serve(
+            listener,
+            app,
+            async move {
+                let _ = shutdown_rx.await;
+            },
+            Duration::from_secs(5),
+        ));
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await?;
+        stream
+            .write_all(b"GET /download HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await?;
+        started.notified().await;
+        shutdown_tx.send(()).unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.ends_with("all the bytes"), "{}", response);
+
+        // The server returns once drained and no longer accepts connections
+        tokio::time::timeout(Duration::from_secs(5), server).await???;
+        assert!(tokio::net::TcpStream::connect(addr).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn segments_are_listed_in_order_with_missing_files_flagged() -> Result<()> {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else {
+            println!("Skipping recording segments test. Set TEST_DATABASE_URL to run.");
+            return Ok(());
+        };
+
+        let pool = Arc::new(PgPool::connect(&database_url).await?);
+        let state = test_state(pool.clone()).await?;
+        let dir = std::env::temp_dir().join(format!("segments-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir)?;
+
+        let parent_id = Uuid::new_v4();
+        let now = Utc::now();
+        let (camera_id, stream_id) = insert_camera_and_stream(&*pool).await?;
+        let insert = |id: Uuid, path: std::path::PathBuf, segment: Option<(i32, Uuid)>| {
+            sqlx::query(
+                "INSERT INTO recordings (id, camera_id, stream_id, start_time, end_time, file_path, file_size, duration, format, resolution, fps, segment_id, parent_recording_id, created_at) VALUES ($1, $2, $3, $4, $4, $5, 100, 60, 'mp4', '1280x720', 25, $6, $7, $4)",
+            )
+            .bind(id)
+            .bind(camera_id)
+            .bind(stream_id)
+            .bind(now)
+            .bind(path.to_string_lossy().to_string())
+            .bind(segment.map(|(segment_id, _)| segment_id))
+            .bind(segment.map(|(_, parent)| parent))
+            .execute(&*pool)
+        };
+        insert(parent_id, dir.clone(), None).await?;
+        // Inserted out of order; segment 1's file is gone
+        for segment_id in [2, 0, 1] {
+            let path = dir.join(format!("segment_{}.mp4", segment_id));
+            if segment_id != 1 {
+                std::fs::write(&path, vec![0u8; 10 * (segment_id as usize + 1)])?;
+            }
+            insert(Uuid::new_v4(), path, Some((segment_id, parent_id))).await?;
+        }
+        // Segment 3 was moved to the archive store
+        let archived_id = Uuid::new_v4();
+        insert(
+            archived_id,
+            "s3://bucket/segment_3.mp4".into(),
+            Some((3, parent_id)),
+        )
+        .await?;
+        sqlx::query("UPDATE recordings SET metadata = $1 WHERE id = $2")
+            .bind(serde_json::json!({ "archive": { "key": "segment_3.mp4" } }))
+            .bind(archived_id)
+            .execute(&*pool)
+            .await?;
+
+        let Json(listing) = list_recording_segments(State(state), Path(parent_id))
+            .await
+            .map_err(|e| anyhow::anyhow!(e.message))?;
+
+        let order: Vec<_> = listing
+            .segments
+            .iter()
+            .map(|s| s.recording.segment_id)
+            .collect();
+        assert_eq!(order, [Some(0), Some(1), Some(2), Some(3)]);
+        let on_disk: Vec<_> = listing.segments.iter().map(|s| s.size_on_disk).collect();
+        assert_eq!(on_disk, [Some(10), None, Some(30), None]);
+        assert!(!listing.segments[1].file_exists);
+        assert!(listing.segments[3].file_exists && listing.segments[3].archived);
+        assert_eq!(listing.missing_segments, 1);
+        assert_eq!(listing.total_duration, 180);
+
+        sqlx::query("DELETE FROM cameras WHERE id = $1")
+            .bind(camera_id)
+            .execute(&*pool)
+            .await?;
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn recording_with_missing_file_is_not_found() -> Result<()> {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else {
+            println!("Skipping missing recording file test. Set TEST_DATABASE_URL to run.");
+            return Ok(());
+        };
+
+        let pool = Arc::new(PgPool::connect(&database_url).await?);
+        let state = test_state(pool.clone()).await?;
+        let dir = std::env::temp_dir()
+            .join("g-streamer-test-recordings")
+            .join(format!("missing-file-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir)?;
+        let outside =
+            std::env::temp_dir().join(format!("missing-file-test-{}.mp4", Uuid::new_v4()));
+        std::fs::write(&outside, b"mp4")?;
+
+        let (present_id, absent_id, outside_id) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let now = Utc::now();
+        let (camera_id, stream_id) = insert_camera_and_stream(&*pool).await?;
+        let present = dir.join("present.mp4");
+        std::fs::write(&present, b"mp4")?;
+        for (id, path) in [
+            (present_id, present),
+            (absent_id, dir.join("absent.mp4")),
+            (outside_id, outside.clone()),
+        ] {
+            sqlx::query(
+                "INSERT INTO recordings (id, camera_id, stream_id, start_time, end_time, file_path, file_size, duration, format, resolution, fps, created_at) VALUES ($1, $2, $3, $4, $4, $5, 3, 60, 'mp4', '1280x720', 25, $4)",
+            )
+            .bind(id)
+            .bind(camera_id)
+            .bind(stream_id)
+            .bind(now)
+            .bind(path.to_string_lossy().to_string())
+            .execute(&*pool)
+            .await?;
+        }
+
+        let response = download_recording(State(state.clone()), Path(present_id), HeaderMap::new())
+            .await
+            .map_err(|e| anyhow::anyhow!(e.message))?;
+        assert_eq!(response.status(), StatusCode::OK);
+        // A client revalidating its copy doesn't download it again
+        let mut revalidate = HeaderMap::new();
+        revalidate.insert(
+            header::IF_NONE_MATCH,
+            response.headers()[header::ETAG].clone(),
+        );
+        let response = download_recording(State(state.clone()), Path(present_id), revalidate)
+            .await
+            .map_err(|e| anyhow::anyhow!(e.message))?;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        // The row exists but its file is gone: missing footage, not a server error
+        let err = download_recording(State(state.clone()), Path(absent_id), HeaderMap::new())
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, StatusCode::NOT_FOUND.as_u16());
+        let err = stream_recording(State(state.clone()), Path(absent_id), HeaderMap::new())
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, StatusCode::NOT_FOUND.as_u16());
+
+        // A row pointing outside the recordings directory is never served
+        let err = download_recording(State(state.clone()), Path(outside_id), HeaderMap::new())
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, StatusCode::INTERNAL_SERVER_ERROR.as_u16());
+        let err = stream_recording(State(state), Path(outside_id), HeaderMap::new())
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, StatusCode::INTERNAL_SERVER_ERROR.as_u16());
+
+        sqlx::query("DELETE FROM cameras WHERE id = $1")
+            .bind(camera_id)
+            .execute(&*pool)
+            .await?;
+        std::fs::remove_dir_all(&dir)?;
+        std::fs::remove_file(&outside)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn event_settings_default_update_and_opt_out_of_recording() -> Result<()> {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else {
+            println!("Skipping event settings test. Set TEST_DATABASE_URL to run.");
+            return Ok(());
+        };
+
+        let pool = Arc::new(PgPool::connect(&database_url).await?);
+        let state = test_state(pool.clone()).await?;
+
+        let (camera_id, stream_id) = insert_camera_and_stream(&*pool).await?;
+
+        // Nothing stored yet: every event type triggers a recording
+        let Json(defaults) = get_camera_event_settings(State(state.clone()), Path(camera_id))
+            .await
+            .map_err(|e| anyhow::anyhow!(e.message))?;
+        assert!(defaults.triggers_recording(RecordingEventType::Audio));
+
+        let update = |req: UpdateEventSettingsRequest| {
+            update_camera_event_settings(
+                State(state.clone()),
+                Path(camera_id),
+                HeaderMap::new(),
+                Json(req),
+            )
+        };
+        let request = |event_types: &[&str], recording_duration: i32| UpdateEventSettingsRequest {
+            enabled: None,
+            event_types: Some(event_types.iter().map(|t| t.to_string()).collect()),
+            event_topic_expressions: None,
+            trigger_recording: None,
+            recording_duration: Some(recording_duration),
+        };
+
+        for invalid in [
+            request(&["manual"], 30),
+            request(&["smoke"], 30),
+            request(&["motion"], 0),
+        ] {
+            let refused = update(invalid).await.err().expect("invalid settings saved");
+            assert_eq!(refused.status, StatusCode::BAD_REQUEST.as_u16());
+        }
+
+        // Motion only: audio events are ignored even without a schedule in the way
+        let Json(saved) = update(request(&["Motion", "motion"], 30))
+            .await
+            .map_err(|e| anyhow::anyhow!(e.message))?;
+        assert_eq!(saved.event_types, ["motion"]);
+        assert_eq!(saved.recording_duration, 30);
+        let Json(fetched) = get_camera_event_settings(State(state.clone()), Path(camera_id))
+            .await
+            .map_err(|e| anyhow::anyhow!(e.message))?;
+        assert_eq!(fetched.id, saved.id);
+        assert_eq!(fetched.event_types, ["motion"]);
+
+        state
+            .recording_manager
+            .register_event(&stream_id, RecordingEventType::Audio)
+            .await?;
+        assert!(
+            !state
+                .recording_manager
+                .is_stream_recording(&stream_id)
+                .await
+        );
+
+        let missing = get_camera_event_settings(State(state.clone()), Path(Uuid::new_v4()))
+            .await
+            .err()
+            .expect("settings of a missing camera");
+        assert_eq!(missing.status, StatusCode::NOT_FOUND.as_u16());
+
+        sqlx::query("DELETE FROM cameras WHERE id = $1")
+            .bind(camera_id)
+            .execute(&*pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Status and JSON body of an error response
+    async fn error_body(response: Response) -> Result<(StatusCode, serde_json::Value)> {
+        let (status, body) = response_body(response).await?;
+        anyhow::ensure!(
+            status.is_client_error() || status.is_server_error(),
+            "expected an error response, got {}",
+            status
+        );
+        Ok((status, body))
+    }
+
+    /// Status and JSON body of a response
+    async fn response_body(response: Response) -> Result<(StatusCode, serde_json::Value)> {
+        use axum::body::HttpBody;
+
+        let status = response.status();
+        let mut body = response.into_body();
+        let mut bytes = Vec::new();
+        while let Some(chunk) = body.data().await {
+            bytes.extend_from_slice(&chunk?);
+        }
+        Ok((status, serde_json::from_slice(&bytes)?))
+    }
+
+    #[tokio::test]
+    async fn api_and_playback_errors_share_a_body() -> Result<()> {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else {
+            println!("Skipping error body test. Set TEST_DATABASE_URL to run.");
+            return Ok(());
+        };
+
+        let pool = Arc::new(PgPool::connect(&database_url).await?);
+        let state = test_state(pool).await?;
+        let missing = Uuid::new_v4();
+
+        let api = seek_recording(
+            State(state.clone()),
+            Path(missing),
+            Query(SeekQuery { at: Utc::now() }),
+        )
+        .await
+        .err()
+        .expect("seek into a missing recording succeeded")
+        .into_response();
+        let playback = recording_playback_controller::get_video_recording(
+            Path(missing.to_string()),
+            State(state),
+            HeaderMap::new(),
+        )
+        .await
+        .into_response();
+
+        for response in [api, playback] {
+            let (status, body) = error_body(response).await?;
+            assert_eq!(status, StatusCode::NOT_FOUND);
+            let body: ErrorBody = serde_json::from_value(body.clone())
+                .unwrap_or_else(|e| panic!("{} doesn't match the error schema: {}", body, e));
+            assert_eq!(body.code, "not_found");
+            assert_eq!(body.status, 404);
+            assert!(!body.message.is_empty());
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn stream_access_errors_are_404_or_503() {
+        let stream_id = Uuid::new_v4().to_string();
+        let not_registered = ApiError::from(anyhow::Error::from(StreamAccessError::NotRegistered(
+            stream_id.clone(),
+        )));
+        assert_eq!(not_registered.status, StatusCode::NOT_FOUND.as_u16());
+
+        let not_playing = ApiError::from(anyhow::Error::from(StreamAccessError::NotPlaying(
+            stream_id,
+            "connection refused".to_string(),
+        )));
+        assert_eq!(not_playing.status, StatusCode::SERVICE_UNAVAILABLE.as_u16());
+    }
+
+    #[tokio::test]
+    async fn recording_an_unregistered_stream_is_not_found() -> Result<()> {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else {
+            println!("Skipping unregistered stream test. Set TEST_DATABASE_URL to run.");
+            return Ok(());
+        };
+
+        let pool = Arc::new(PgPool::connect(&database_url).await?);
+        let state = test_state(pool.clone()).await?;
+        let (camera_id, stream_id) = insert_camera_and_stream(&*pool).await?;
+
+        // The row exists, but the stream manager never set up a pipeline for it
+        let stream = stream_for_recording(&state, &stream_id)
+            .await
+            .map_err(|e| anyhow::anyhow!(e.message))?;
+        let err = state
+            .recording_manager
+            .start_manual_recording(&stream)
+            .await
+            .unwrap_err();
+        assert_eq!(ApiError::from(err).status, StatusCode::NOT_FOUND.as_u16());
+
+        sqlx::query("DELETE FROM cameras WHERE id = $1")
+            .bind(camera_id)
+            .execute(&*pool)
+            .await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn camera_list_reports_stream_status_on_request() -> Result<()> {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else {
+            println!("Skipping camera list status test. Set TEST_DATABASE_URL to run.");
+            return Ok(());
+        };
+
+        let pool = Arc::new(PgPool::connect(&database_url).await?);
+        let state = test_state(pool.clone()).await?;
+        let (camera_id, stream_id) = insert_camera_and_stream(&*pool).await?;
+        state.stream_manager.add_stream(
+            StreamSource {
+                stream_type: StreamType::Rtsp,
+                uri: "rtsp://127.0.0.1:1/test".to_string(),
+                name: "main".to_string(),
+                description: None,
+            },
+            stream_id.to_string(),
+        )?;
+
+        let camera = |cameras: &serde_json::Value| {
+            cameras
+                .as_array()
+                .and_then(|cameras| {
+                    cameras
+                        .iter()
+                        .find(|c| c["camera"]["id"] == camera_id.to_string())
+                })
+                .cloned()
+                .expect("camera missing from the list")
+        };
+
+        let plain = get_cameras(State(state.clone()), Query(CamerasParams { status: false }))
+            .await
+            .map_err(|e| anyhow::anyhow!(e.message))?;
+        let (_, plain) = response_body(plain).await?;
+        assert!(camera(&plain).get("stream_status").is_none());
+
+        let enriched = get_cameras(State(state.clone()), Query(CamerasParams { status: true }))
+            .await
+            .map_err(|e| anyhow::anyhow!(e.message))?;
+        let (status, enriched) = response_body(enriched).await?;
+        assert_eq!(status, StatusCode::OK);
+        let stream_status = &camera(&enriched)["stream_status"][stream_id.to_string()];
+        for field in ["state", "last_error", "reconnect_attempts", "uptime_secs"] {
+            assert!(stream_status.get(field).is_some(), "{} missing", field);
+        }
+
+        state
+            .stream_manager
+            .remove_stream(&stream_id.to_string())
+            .await?;
+        sqlx::query("DELETE FROM cameras WHERE id = $1")
+            .bind(camera_id)
+            .execute(&*pool)
+            .await?;
+        Ok(())
+    }
+}
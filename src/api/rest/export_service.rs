@@ -0,0 +1,659 @@
+use crate::api::rest::hls_service::{
+    canonical_dir, confined_tier_path, ffmpeg_command, ffmpeg_input, wait_or_kill,
+};
+use crate::db::models::recording_models::Recording;
+use crate::error::Error;
+use crate::recorder::archiver::archived_key;
+use chrono::{DateTime, Duration, Utc};
+use log::{error, info, warn};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
+use tokio::process::Command;
+use tokio::sync::{Mutex, Semaphore};
+use uuid::Uuid;
+
+/// How far before the requested start to look for a segment that already covers it
+pub const SEGMENT_LOOKBACK: Duration = Duration::hours(1);
+
+/// Longest time range a single export may cover
+pub const MAX_EXPORT_SPAN: Duration = Duration::hours(24);
+
+/// Number of exports encoded at once; further jobs wait for a free slot
+const MAX_CONCURRENT_EXPORTS: usize = 1;
+
+/// Upper bound on one export's FFmpeg run
+const EXPORT_TIMEOUT: StdDuration = StdDuration::from_secs(30 * 60);
+
+/// How long a finished export stays available for download
+const EXPORT_RETENTION: Duration = Duration::hours(24);
+
+/// Gaps shorter than this between consecutive segments are not reported
+const GAP_TOLERANCE: Duration = Duration::seconds(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportStatus {
+    Pending,
+    Running,
+    Completed,
+    Failed,
+}
+
+/// A stretch of the requested range with no footage
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ExportGap {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+/// The part of one segment file that ends up in an export, in seconds from its start
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportPart {
+    pub path: PathBuf,
+    pub inpoint: f64,
+    pub outpoint: f64,
+    /// Wall-clock time of the frame at `inpoint`
+    pub start: DateTime<Utc>,
+}
+
+/// Which segments an export is cut from and where footage is missing
+#[derive(Debug, Clone)]
+pub struct ExportPlan {
+    pub stream_id: Uuid,
+    pub parts: Vec<ExportPart>,
+    pub gaps: Vec<ExportGap>,
+}
+
+/// Select the finished segments covering `[start, end)` and where to trim them.
+///
+/// Without a `stream_id` the stream with the most footage in the range is used, since a
+/// camera recording several streams has overlapping segments for each. Archived segments
+/// count as footage; their parts keep the object location until fetched. Returns `None`
+/// when no footage overlaps the range.
+pub fn plan_export(
+    recordings: &[Recording],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    stream_id: Option<Uuid>,
+) -> Option<ExportPlan> {
+    let overlapping: Vec<(&Recording, DateTime<Utc>)> = recordings
+        .iter()
+        .filter(|r| archived_key(r).is_some() || r.file_path.is_file())
+        .filter(|r| stream_id.map_or(true, |id| r.stream_id == id))
+        .filter_map(|r| r.end_time.map(|end_time| (r, end_time)))
+        .filter(|(r, end_time)| r.start_time < end && *end_time > start)
+        .collect();
+
+    let stream_id = match stream_id {
+        Some(id) => id,
+        None => {
+            let mut coverage: HashMap<Uuid, i64> = HashMap::new();
+            for (r, end_time) in &overlapping {
+                let covered = (*end_time).min(end) - r.start_time.max(start);
+                *coverage.entry(r.stream_id).or_default() += covered.num_milliseconds();
+            }
+            coverage
+                .into_iter()
+                .max_by_key(|(_, millis)| *millis)
+                .map(|(id, _)| id)?
+        }
+    };
+
+    let mut segments: Vec<(&Recording, DateTime<Utc>)> = overlapping
+        .into_iter()
+        .filter(|(r, _)| r.stream_id == stream_id)
+        .collect();
+    if segments.is_empty() {
+        return None;
+    }
+    segments.sort_by_key(|(r, _)| r.start_time);
+
+    let seconds = |d: Duration| d.num_milliseconds() as f64 / 1000.0;
+    let mut parts = Vec::new();
+    let mut gaps = Vec::new();
+    let mut cursor = start;
+    for (recording, end_time) in segments {
+        // Skip segments entirely covered by the previous one
+        if end_time <= cursor {
+            continue;
+        }
+        let part_start = recording.start_time.max(cursor);
+        let part_end = end_time.min(end);
+        if part_start > cursor + GAP_TOLERANCE {
+            gaps.push(ExportGap {
+                start: cursor,
+                end: part_start,
+            });
+        }
+
+        parts.push(ExportPart {
+            path: recording.file_path.clone(),
+            inpoint: seconds(part_start - recording.start_time),
+            outpoint: seconds(part_end - recording.start_time),
+            start: part_start,
+        });
+        cursor = part_end;
+    }
+    if end > cursor + GAP_TOLERANCE {
+        gaps.push(ExportGap { start: cursor, end });
+    }
+
+    Some(ExportPlan {
+        stream_id,
+        parts,
+        gaps,
+    })
+}
+
+/// Quote a path for an FFmpeg concat list, where `'` can only be escaped outside quotes
+fn concat_quote(path: &str) -> String {
+    format!("'{}'", path.replace('\'', r"'\''"))
+}
+
+/// Build an `ffconcat` list trimming each part to its in and out points
+fn concat_list(parts: &[ExportPart]) -> String {
+    let mut list = String::from("ffconcat version 1.0\n");
+    for part in parts {
+        list.push_str(&format!(
+            "file {}\ninpoint {:.3}\noutpoint {:.3}\n",
+            concat_quote(&ffmpeg_input(&part.path).to_string_lossy()),
+            part.inpoint,
+            part.outpoint
+        ));
+    }
+    list
+}
+
+/// Timestamp format used when an overlay is requested without one
+pub const DEFAULT_OVERLAY_FORMAT: &str = "%Y-%m-%d %H:%M:%S UTC";
+
+/// Camera name and wall-clock time burnt into an export
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportOverlay {
+    pub camera_name: String,
+    /// strftime format of the timestamp, rendered in UTC
+    pub format: String,
+}
+
+impl ExportOverlay {
+    /// drawtext text showing the camera name above the time of each frame, where `offset`
+    /// is the Unix time of the frame at output timestamp zero
+    fn text(&self, offset: f64) -> String {
+        format!(
+            "{}\n%{{pts:gmtime:{:.3}:{}}}",
+            escape(&self.camera_name, &['\\', '%']),
+            offset,
+            escape(&self.format, &['\\', '\'', ':', '}'])
+        )
+    }
+}
+
+/// A stretch of the output over which wall-clock time advances with the output timestamp
+#[derive(Debug, Clone, PartialEq)]
+struct OverlayRun {
+    /// Output seconds
+    from: f64,
+    to: f64,
+    /// Unix time of the frame at output timestamp zero, were the whole export like this run
+    offset: f64,
+}
+
+/// Split the concatenated output wherever a skipped gap makes the wall clock jump
+fn overlay_runs(parts: &[ExportPart]) -> Vec<OverlayRun> {
+    let tolerance = GAP_TOLERANCE.num_milliseconds() as f64 / 1000.0;
+    let mut runs: Vec<OverlayRun> = Vec::new();
+    let mut position = 0.0;
+    for part in parts {
+        let length = part.outpoint - part.inpoint;
+        let offset = part.start.timestamp_millis() as f64 / 1000.0 - position;
+        match runs.last_mut() {
+            Some(run) if (run.offset - offset).abs() <= tolerance => run.to = position + length,
+            _ => runs.push(OverlayRun {
+                from: position,
+                to: position + length,
+                offset,
+            }),
+        }
+        position += length;
+    }
+    runs
+}
+
+/// Prefix each of the `special` characters in `text` with a backslash
+fn escape(text: &str, special: &[char]) -> String {
+    text.chars().fold(String::new(), |mut out, c| {
+        if special.contains(&c) {
+            out.push('\\');
+        }
+        out.push(c);
+        out
+    })
+}
+
+/// Escape a filter option value for both the option parser and the filtergraph parser
+fn filter_value(value: &str) -> String {
+    escape(
+        &escape(value, &['\\', '\'', ':']),
+        &['\\', '\'', '[', ']', ',', ';'],
+    )
+}
+
+/// drawtext filter rendering the text in `text_path` over one run of the output
+fn drawtext_filter(text_path: &Path, run: &OverlayRun, first: bool, last: bool) -> String {
+    let mut filter = format!(
+        "drawtext=textfile={}:x=16:y=16:fontsize=24:fontcolor=white:box=1:boxcolor=black@0.5:boxborderw=6",
+        filter_value(&text_path.to_string_lossy())
+    );
+    // Open-ended at the edges so rounding never leaves a frame without a timestamp
+    let enable = match (first, last) {
+        (true, true) => None,
+        (true, false) => Some(format!("lt(t,{:.3})", run.to)),
+        (false, true) => Some(format!("gte(t,{:.3})", run.from)),
+        (false, false) => Some(format!("gte(t,{:.3})*lt(t,{:.3})", run.from, run.to)),
+    };
+    if let Some(enable) = enable {
+        filter.push_str(&format!(":enable={}", filter_value(&enable)));
+    }
+    filter
+}
+
+/// FFmpeg invocation encoding the concat list into one MP4, re-encoding so the cuts land
+/// on the exact requested boundaries rather than the nearest keyframes
+fn export_command(list_path: &Path, output_path: &Path, video_filter: Option<&str>) -> Command {
+    let mut command = ffmpeg_command();
+    command
+        .arg("-f")
+        .arg("concat")
+        .arg("-safe")
+        .arg("0") // Segment paths are absolute
+        .arg("-i")
+        .arg(ffmpeg_input(list_path))
+        .arg("-map")
+        .arg("0:v:0")
+        .arg("-map")
+        .arg("0:a:0?"); // Audio only if the camera recorded it
+    if let Some(filter) = video_filter {
+        command.arg("-vf").arg(filter);
+    }
+    command
+        .arg("-c:v")
+        .arg("libx264")
+        .arg("-preset")
+        .arg("veryfast")
+        .arg("-c:a")
+        .arg("aac")
+        .arg("-movflags")
+        .arg("+faststart") // Playable while still downloading
+        .arg("-y")
+        .arg(output_path);
+    command
+}
+
+/// An export job as reported to clients
+#[derive(Debug, Clone, Serialize)]
+pub struct ExportJob {
+    pub id: Uuid,
+    pub camera_id: Uuid,
+    pub stream_id: Uuid,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub status: ExportStatus,
+    /// Stretches of the range without footage; they are skipped, so the exported file is
+    /// shorter than the range by their total length
+    pub gaps: Vec<ExportGap>,
+    /// Burnt-in camera name and timestamp, if requested
+    pub overlay: Option<ExportOverlay>,
+    pub file_size: Option<u64>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    #[serde(skip)]
+    pub output_path: PathBuf,
+}
+
+/// Background exports of a camera's footage over a time range as a single MP4.
+///
+/// Jobs are kept in memory, so they do not survive a restart; finished exports are
+/// deleted from `temp_dir` once they are older than a day.
+pub struct ExportService {
+    temp_dir: PathBuf,
+    /// Only files under this directory are ever handed to FFmpeg
+    recordings_dir: PathBuf,
+    /// Storage tier directory old recordings are moved to, also readable by FFmpeg
+    tier_dir: Option<PathBuf>,
+    jobs: Mutex<HashMap<Uuid, ExportJob>>,
+    slots: Semaphore,
+}
+
+impl ExportService {
+    /// Create a new export service reading recordings from `recordings_dir` and writing
+    /// exports under `temp_dir`
+    pub fn new(temp_dir: PathBuf, recordings_dir: &Path) -> Self {
+        if !temp_dir.exists() {
+            std::fs::create_dir_all(&temp_dir).expect("Failed to create export directory");
+        }
+
+        Self {
+            temp_dir,
+            recordings_dir: canonical_dir(recordings_dir),
+            tier_dir: None,
+            jobs: Mutex::new(HashMap::new()),
+            slots: Semaphore::new(MAX_CONCURRENT_EXPORTS),
+        }
+    }
+
+    /// Also export recordings moved to the storage tier directory `tier_dir`
+    pub fn with_tier_dir(mut self, tier_dir: Option<&Path>) -> Self {
+        self.tier_dir = tier_dir.map(canonical_dir);
+        self
+    }
+
+    /// Queue an export of `plan` and return the new job
+    pub async fn submit(
+        self: &Arc<Self>,
+        camera_id: Uuid,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        plan: ExportPlan,
+        overlay: Option<ExportOverlay>,
+    ) -> anyhow::Result<ExportJob> {
+        let mut parts = plan.parts;
+        for part in &mut parts {
+            part.path =
+                confined_tier_path(&self.recordings_dir, self.tier_dir.as_deref(), &part.path)?;
+        }
+
+        self.prune_expired().await;
+
+        let id = Uuid::new_v4();
+        let job = ExportJob {
+            id,
+            camera_id,
+            stream_id: plan.stream_id,
+            start_time,
+            end_time,
+            status: ExportStatus::Pending,
+            gaps: plan.gaps,
+            overlay: overlay.clone(),
+            file_size: None,
+            error: None,
+            created_at: Utc::now(),
+            output_path: self.temp_dir.join(format!("{}.mp4", id)),
+        };
+        self.jobs.lock().await.insert(id, job.clone());
+
+        let service = Arc::clone(self);
+        tokio::spawn(async move { service.run(id, parts, overlay).await });
+
+        Ok(job)
+    }
+
+    /// Get the current state of an export job
+    pub async fn job(&self, id: &Uuid) -> Option<ExportJob> {
+        self.jobs.lock().await.get(id).cloned()
+    }
+
+    async fn run(&self, id: Uuid, parts: Vec<ExportPart>, overlay: Option<ExportOverlay>) {
+        let Ok(_slot) = self.slots.acquire().await else {
+            return;
+        };
+        let Some(output_path) = self
+            .update(&id, |job| job.status = ExportStatus::Running)
+            .await
+        else {
+            return;
+        };
+
+        info!("Starting export {} from {} segments", id, parts.len());
+        let mut scratch = Vec::new();
+        let result = self
+            .encode(&id, &parts, overlay.as_ref(), &output_path, &mut scratch)
+            .await;
+        for path in scratch {
+            let _ = std::fs::remove_file(path);
+        }
+
+        match result {
+            Ok(file_size) => {
+                info!("Export {} finished ({} bytes)", id, file_size);
+                self.update(&id, |job| {
+                    job.status = ExportStatus::Completed;
+                    job.file_size = Some(file_size);
+                })
+                .await;
+            }
+            Err(e) => {
+                error!("Export {} failed: {}", id, e);
+                let _ = std::fs::remove_file(&output_path);
+                self.update(&id, |job| {
+                    job.status = ExportStatus::Failed;
+                    job.error = Some(e.to_string());
+                })
+                .await;
+            }
+        }
+    }
+
+    /// Concatenate the parts with FFmpeg, burning in the overlay if one is given.
+    ///
+    /// Temporary files are added to `scratch` for the caller to delete.
+    async fn encode(
+        &self,
+        id: &Uuid,
+        parts: &[ExportPart],
+        overlay: Option<&ExportOverlay>,
+        output_path: &Path,
+        scratch: &mut Vec<PathBuf>,
+    ) -> anyhow::Result<u64> {
+        let list_path = self.temp_dir.join(format!("{}.txt", id));
+        scratch.push(list_path.clone());
+        tokio::fs::write(&list_path, concat_list(parts)).await?;
+
+        let video_filter = match overlay {
+            Some(overlay) => {
+                let runs = overlay_runs(parts);
+                let mut filters = Vec::new();
+                for (n, run) in runs.iter().enumerate() {
+                    // Text files spare the timestamp format one level of filtergraph escaping
+                    let text_path = self.temp_dir.join(format!("{}_overlay_{}.txt", id, n));
+                    scratch.push(text_path.clone());
+                    tokio::fs::write(&text_path, overlay.text(run.offset)).await?;
+                    filters.push(drawtext_filter(
+                        &text_path,
+                        run,
+                        n == 0,
+                        n == runs.len() - 1,
+                    ));
+                }
+                Some(filters.join(","))
+            }
+            None => None,
+        };
+
+        let child = export_command(&list_path, output_path, video_filter.as_deref()).spawn()?;
+
+        wait_or_kill(child, EXPORT_TIMEOUT)
+            .await
+            .map_err(|e| Error::FFmpeg(format!("Failed to export footage: {}", e)))?;
+
+        let file_size = std::fs::metadata(output_path)?.len();
+        if file_size == 0 {
+            return Err(anyhow::anyhow!("FFmpeg produced an empty export"));
+        }
+        Ok(file_size)
+    }
+
+    /// Apply `change` to a job, returning its output path if the job still exists
+    async fn update(&self, id: &Uuid, change: impl FnOnce(&mut ExportJob)) -> Option<PathBuf> {
+        let mut jobs = self.jobs.lock().await;
+        let job = jobs.get_mut(id)?;
+        change(job);
+        Some(job.output_path.clone())
+    }
+
+    /// Forget finished jobs past their retention and delete their files
+    async fn prune_expired(&self) {
+        let cutoff = Utc::now() - EXPORT_RETENTION;
+        let mut jobs = self.jobs.lock().await;
+        jobs.retain(|id, job| {
+            let finished = matches!(job.status, ExportStatus::Completed | ExportStatus::Failed);
+            if finished && job.created_at < cutoff {
+                if let Err(e) = std::fs::remove_file(&job.output_path) {
+                    if e.kind() != std::io::ErrorKind::NotFound {
+                        warn!("Failed to delete expired export {}: {}", id, e);
+                    }
+                }
+                return false;
+            }
+            true
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::models::recording_models::RecordingEventType;
+
+    fn segment(stream_id: Uuid, path: PathBuf, start: DateTime<Utc>, secs: i64) -> Recording {
+        std::fs::write(&path, b"mp4").unwrap();
+        Recording {
+            id: Uuid::new_v4(),
+            camera_id: Uuid::new_v4(),
+            stream_id,
+            start_time: start,
+            end_time: Some(start + Duration::seconds(secs)),
+            file_path: path,
+            file_size: 0,
+            duration: secs as u64,
+            format: "mp4".to_string(),
+            resolution: "1280x720".to_string(),
+            fps: 25,
+            event_type: RecordingEventType::Continuous,
+            metadata: None,
+            schedule_id: None,
+            segment_id: None,
+            parent_recording_id: Some(Uuid::new_v4()),
+        }
+    }
+
+    #[test]
+    fn plan_trims_segments_and_reports_gaps() {
+        let dir = std::env::temp_dir().join(format!("export-plan-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let t0 = Utc::now() - Duration::hours(1);
+        let main = Uuid::new_v4();
+        let sub = Uuid::new_v4();
+
+        // Two contiguous 30s segments, then a 60s gap, then one more
+        let recordings = vec![
+            segment(main, dir.join("c.mp4"), t0 + Duration::seconds(120), 30),
+            segment(main, dir.join("a.mp4"), t0, 30),
+            segment(main, dir.join("b.mp4"), t0 + Duration::seconds(30), 30),
+            segment(sub, dir.join("sub.mp4"), t0, 30),
+        ];
+
+        let start = t0 + Duration::seconds(10);
+        let end = t0 + Duration::seconds(140);
+        let plan = plan_export(&recordings, start, end, None).unwrap();
+
+        assert_eq!(plan.stream_id, main);
+        let cuts: Vec<(f64, f64)> = plan.parts.iter().map(|p| (p.inpoint, p.outpoint)).collect();
+        assert_eq!(cuts, vec![(10.0, 30.0), (0.0, 30.0), (0.0, 20.0)]);
+        assert_eq!(plan.parts[0].path, dir.join("a.mp4"));
+        assert_eq!(
+            plan.gaps,
+            vec![ExportGap {
+                start: t0 + Duration::seconds(60),
+                end: t0 + Duration::seconds(120),
+            }]
+        );
+
+        assert!(plan_export(&recordings, t0 - Duration::hours(2), t0, None).is_none());
+
+        // An archived segment has no local file but is still footage
+        let mut archived = segment(main, dir.join("d.mp4"), t0 + Duration::seconds(60), 60);
+        std::fs::remove_file(&archived.file_path).unwrap();
+        archived.file_path = PathBuf::from("s3://bucket/camera/d.mp4");
+        archived.metadata = Some(serde_json::json!({
+            crate::recorder::archiver::ARCHIVE_METADATA_KEY: { "key": "camera/d.mp4" }
+        }));
+        let recordings = [recordings, vec![archived]].concat();
+        let plan = plan_export(&recordings, start, end, Some(main)).unwrap();
+        assert!(plan.gaps.is_empty());
+        assert_eq!(
+            plan.parts[2].path,
+            PathBuf::from("s3://bucket/camera/d.mp4")
+        );
+        assert_eq!(
+            concat_quote("/rec/cam's.mp4"),
+            r"'/rec/cam'\''s.mp4'".to_string()
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn overlay_filter_is_only_applied_when_requested() {
+        let args = |command: &Command| -> Vec<String> {
+            command
+                .as_std()
+                .get_args()
+                .map(|arg| arg.to_string_lossy().into_owned())
+                .collect()
+        };
+        let list = Path::new("/tmp/export.txt");
+        let output = Path::new("/tmp/export.mp4");
+
+        let plain = args(&export_command(list, output, None));
+        assert!(!plain.iter().any(|arg| arg == "-vf"));
+
+        // A 60s gap between the second and third part makes the clock jump once
+        let t0 = DateTime::parse_from_rfc3339("2025-01-01T14:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let part = |start: i64, inpoint: f64| ExportPart {
+            path: PathBuf::from("/rec/segment.mp4"),
+            inpoint,
+            outpoint: 30.0,
+            start: t0 + Duration::seconds(start),
+        };
+        let runs = overlay_runs(&[part(0, 10.0), part(20, 0.0), part(110, 0.0)]);
+        let epoch = t0.timestamp() as f64;
+        assert_eq!(
+            runs,
+            vec![
+                OverlayRun {
+                    from: 0.0,
+                    to: 50.0,
+                    offset: epoch,
+                },
+                OverlayRun {
+                    from: 50.0,
+                    to: 80.0,
+                    offset: epoch + 60.0,
+                },
+            ]
+        );
+
+        let filter = drawtext_filter(Path::new("/tmp/overlay_0.txt"), &runs[1], false, true);
+        let overlaid = args(&export_command(list, output, Some(&filter)));
+        let vf = overlaid.iter().position(|arg| arg == "-vf").unwrap();
+        assert!(overlaid[vf + 1].starts_with("drawtext=textfile=/tmp/overlay_0.txt:"));
+        assert!(overlaid[vf + 1].ends_with(r"enable=gte(t\,50.000)"));
+
+        let overlay = ExportOverlay {
+            camera_name: "Gate 100%".to_string(),
+            format: DEFAULT_OVERLAY_FORMAT.to_string(),
+        };
+        assert_eq!(
+            overlay.text(epoch),
+            format!(
+                "Gate 100\\%\n%{{pts:gmtime:{:.3}:%Y-%m-%d %H\\:%M\\:%S UTC}}",
+                epoch
+            )
+        );
+    }
+}
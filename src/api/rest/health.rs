@@ -0,0 +1,192 @@
+use crate::api::rest::AppState;
+use crate::recorder::storage_cleanup::get_disk_usage;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::Json;
+use gstreamer as gst;
+use serde::Serialize;
+use std::future::Future;
+use std::path::Path;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How long a single readiness check may take before it counts as failed
+const CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Elements the recording and streaming pipelines cannot work without
+const REQUIRED_ELEMENTS: &[&str] = &["rtspsrc", "h264parse", "mp4mux", "splitmuxsink"];
+
+/// Result of checking one subsystem
+#[derive(Debug, Clone, Serialize)]
+pub struct SubsystemHealth {
+    pub healthy: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl SubsystemHealth {
+    fn ok() -> Self {
+        Self {
+            healthy: true,
+            message: None,
+        }
+    }
+
+    fn failed(message: impl Into<String>) -> Self {
+        Self {
+            healthy: false,
+            message: Some(message.into()),
+        }
+    }
+}
+
+/// Readiness report returned by `/health/ready`
+#[derive(Debug, Serialize)]
+pub struct ReadinessReport {
+    pub status: &'static str,
+    pub database: SubsystemHealth,
+    pub message_broker: SubsystemHealth,
+    pub gstreamer: SubsystemHealth,
+    pub storage: SubsystemHealth,
+}
+
+impl ReadinessReport {
+    fn is_ready(&self) -> bool {
+        [
+            &self.database,
+            &self.message_broker,
+            &self.gstreamer,
+            &self.storage,
+        ]
+        .iter()
+        .all(|check| check.healthy)
+    }
+}
+
+/// Liveness probe: the process is up and serving requests
+pub async fn liveness() -> impl IntoResponse {
+    Json(serde_json::json!({ "status": "ok" }))
+}
+
+/// Readiness probe: 200 when every critical subsystem is usable, 503 otherwise
+pub async fn readiness(State(state): State<AppState>) -> impl IntoResponse {
+    let db_pool = state.db_pool.clone();
+    let broker = state.message_broker.clone();
+    let recordings_dir = state.recording_manager.recording_base_path().to_path_buf();
+    let min_free_bytes = state.api_config.health_min_free_disk_mb * 1024 * 1024;
+
+    let (database, message_broker, gstreamer, storage) = tokio::join!(
+        with_timeout(async move {
+            match sqlx::query("SELECT 1").execute(&*db_pool).await {
+                Ok(_) => SubsystemHealth::ok(),
+                Err(e) => SubsystemHealth::failed(format!("Database query failed: {}", e)),
+            }
+        }),
+        with_timeout(async move {
+            match broker.health_check().await {
+                Ok(true) => SubsystemHealth::ok(),
+                _ => SubsystemHealth::failed("Message broker is not reachable"),
+            }
+        }),
+        with_timeout(async {
+            tokio::task::spawn_blocking(check_gstreamer)
+                .await
+                .unwrap_or_else(|e| SubsystemHealth::failed(e.to_string()))
+        }),
+        with_timeout(async move {
+            tokio::task::spawn_blocking(move || {
+                check_recordings_dir(&recordings_dir, min_free_bytes)
+            })
+            .await
+            .unwrap_or_else(|e| SubsystemHealth::failed(e.to_string()))
+        }),
+    );
+
+    let mut report = ReadinessReport {
+        status: "ok",
+        database,
+        message_broker,
+        gstreamer,
+        storage,
+    };
+
+    let status = if report.is_ready() {
+        StatusCode::OK
+    } else {
+        report.status = "unavailable";
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    (status, Json(report))
+}
+
+async fn with_timeout(check: impl Future<Output = SubsystemHealth>) -> SubsystemHealth {
+    tokio::time::timeout(CHECK_TIMEOUT, check)
+        .await
+        .unwrap_or_else(|_| SubsystemHealth::failed("Check timed out"))
+}
+
+/// GStreamer is initialized and the elements the pipelines need are registered
+fn check_gstreamer() -> SubsystemHealth {
+    if let Err(e) = gst::init() {
+        return SubsystemHealth::failed(format!("GStreamer failed to initialize: {}", e));
+    }
+
+    let missing: Vec<&str> = REQUIRED_ELEMENTS
+        .iter()
+        .copied()
+        .filter(|name| gst::ElementFactory::find(name).is_none())
+        .collect();
+
+    if missing.is_empty() {
+        SubsystemHealth::ok()
+    } else {
+        SubsystemHealth::failed(format!(
+            "Missing GStreamer elements: {}",
+            missing.join(", ")
+        ))
+    }
+}
+
+/// The recordings directory accepts writes and has at least `min_free_bytes` free
+fn check_recordings_dir(dir: &Path, min_free_bytes: u64) -> SubsystemHealth {
+    let probe = dir.join(format!(".health-{}", Uuid::new_v4()));
+    if let Err(e) = std::fs::write(&probe, b"ok") {
+        return SubsystemHealth::failed(format!(
+            "Recordings directory {} is not writable: {}",
+            dir.display(),
+            e
+        ));
+    }
+    let _ = std::fs::remove_file(&probe);
+
+    match get_disk_usage(dir) {
+        Ok(usage) if usage.free_bytes() >= min_free_bytes => SubsystemHealth::ok(),
+        Ok(usage) => SubsystemHealth::failed(format!(
+            "Only {} MB free on the recordings volume, need {} MB",
+            usage.free_bytes() / (1024 * 1024),
+            min_free_bytes / (1024 * 1024)
+        )),
+        Err(e) => SubsystemHealth::failed(format!("Failed to read disk usage: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recordings_dir_check_requires_write_access_and_free_space() {
+        let dir = std::env::temp_dir().join(format!("health-test-{}", Uuid::new_v4()));
+        assert!(!check_recordings_dir(&dir, 0).healthy);
+
+        std::fs::create_dir_all(&dir).unwrap();
+        assert!(check_recordings_dir(&dir, 0).healthy);
+        assert!(!check_recordings_dir(&dir, u64::MAX).healthy);
+        // The write probe is cleaned up
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,1239 @@
+use crate::api::rest::ApiError;
+use crate::db::models::recording_models::Recording;
+use crate::db::models::stream_models::Stream;
+use crate::error::Error;
+use crate::recorder::archiver::{archived_key, RecordingArchiver};
+use axum::body::StreamBody;
+use axum::http::StatusCode;
+use axum::http::{header, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Duration, Utc};
+use log::{error, info, warn};
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration as StdDuration, SystemTime};
+use tokio::io::AsyncReadExt;
+use tokio::process::{Child, Command};
+use tokio::sync::{Mutex, Semaphore, SemaphorePermit};
+use tokio_util::io::ReaderStream;
+
+/// Default length in seconds of a segment cut from a single recording
+pub const DEFAULT_SEGMENT_DURATION: f64 = 4.0;
+
+/// Bandwidth advertised for a variant whose stream has no known bitrate
+const DEFAULT_VARIANT_BANDWIDTH: u64 = 2_000_000;
+
+/// Maximum number of generated segments kept on disk before the least recently used go
+const CACHE_CAPACITY: usize = 512;
+
+/// How long a segment cut from a recording still being written stays valid
+const LIVE_SEGMENT_TTL_SECS: i64 = 10;
+
+/// Default size the temporary directory may grow to before segments are evicted
+const DEFAULT_TEMP_MAX_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+
+/// Default age after which a generated segment is removed
+const DEFAULT_TEMP_MAX_AGE: StdDuration = StdDuration::from_secs(24 * 60 * 60);
+
+/// How often the temporary directory is checked against its limits
+const TEMP_CLEANUP_INTERVAL: StdDuration = StdDuration::from_secs(60);
+
+/// Uncached files modified more recently than this may still be written by FFmpeg, so
+/// they are left alone when the directory is over its size cap
+const IN_PROGRESS_GRACE: StdDuration = StdDuration::from_secs(60);
+
+/// Cached output of one FFmpeg run
+struct CacheEntry {
+    path: PathBuf,
+    created_at: DateTime<Utc>,
+    last_used: u64,
+    /// Finalized recordings never change, so their segments never expire
+    finalized: bool,
+}
+
+/// LRU cache of generated init and media segments.
+///
+/// Keys embed the source file's mtime, so a rewritten recording misses the cache rather
+/// than serving stale output; entries for finalized recordings are kept until evicted.
+struct HlsCache {
+    entries: HashMap<String, CacheEntry>,
+    capacity: usize,
+    clock: u64,
+}
+
+impl HlsCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            capacity,
+            clock: 0,
+        }
+    }
+
+    /// Get a cached file if it is still valid, marking it as recently used
+    fn get(&mut self, key: &str) -> Option<PathBuf> {
+        self.clock += 1;
+        let clock = self.clock;
+
+        let expired = match self.entries.get_mut(key) {
+            Some(entry) => {
+                let stale = !entry.finalized
+                    && Utc::now().signed_duration_since(entry.created_at)
+                        >= Duration::seconds(LIVE_SEGMENT_TTL_SECS);
+                if !stale && entry.path.exists() {
+                    entry.last_used = clock;
+                    return Some(entry.path.clone());
+                }
+                true
+            }
+            None => false,
+        };
+
+        if expired {
+            if let Some(entry) = self.entries.remove(key) {
+                let _ = std::fs::remove_file(&entry.path);
+            }
+        }
+        None
+    }
+
+    /// Store a generated file, evicting the least recently used entries over capacity
+    fn insert(&mut self, key: String, path: PathBuf, finalized: bool) {
+        self.clock += 1;
+        self.entries.insert(
+            key,
+            CacheEntry {
+                path,
+                created_at: Utc::now(),
+                last_used: self.clock,
+                finalized,
+            },
+        );
+
+        while self.entries.len() > self.capacity {
+            let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone())
+            else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&oldest) {
+                let _ = std::fs::remove_file(&entry.path);
+            }
+        }
+    }
+}
+
+/// Canonicalize a recording file path, rejecting anything that is not a file under
+/// `recordings_dir`
+pub(super) fn confined_path(recordings_dir: &Path, file_path: &Path) -> anyhow::Result<PathBuf> {
+    let path = file_path.canonicalize().map_err(|e| {
+        let reason = format!(
+            "Recording file {} is not accessible: {}",
+            file_path.display(),
+            e
+        );
+        // Only an absent file is missing footage; anything else is a server problem
+        match e.kind() {
+            std::io::ErrorKind::NotFound => Error::NotFound(reason),
+            _ => Error::Io(reason),
+        }
+    })?;
+
+    if !path.starts_with(recordings_dir) || !path.is_file() {
+        return Err(Error::InvalidInput(format!(
+            "Recording file {} is outside the recordings directory",
+            file_path.display()
+        ))
+        .into());
+    }
+
+    Ok(path)
+}
+
+/// Like `confined_path`, but also accepting files under `tier_dir`, where the storage
+/// cleanup moves old recordings
+pub(super) fn confined_tier_path(
+    recordings_dir: &Path,
+    tier_dir: Option<&Path>,
+    file_path: &Path,
+) -> anyhow::Result<PathBuf> {
+    match (confined_path(recordings_dir, file_path), tier_dir) {
+        (Err(e), Some(tier_dir)) => confined_path(tier_dir, file_path).map_err(|_| e),
+        (result, _) => result,
+    }
+}
+
+/// Canonicalize a directory the way `confined_path` compares against it
+pub(super) fn canonical_dir(dir: &Path) -> PathBuf {
+    dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf())
+}
+
+/// Whether a recording's file can be played: it is on disk or in the archive store
+pub(super) fn has_file(recording: &Recording) -> bool {
+    recording.file_path.exists() || archived_key(recording).is_some()
+}
+
+/// Identifies the current contents of a recording file: its id plus modification time
+fn source_version(recording: &Recording, source: &Path) -> anyhow::Result<String> {
+    let modified = std::fs::metadata(source)?.modified()?;
+    let nanos = modified
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    Ok(format!("{}_{}", recording.id, nanos))
+}
+
+/// Returned when every HLS job slot stayed busy for the whole queue timeout
+#[derive(Debug, thiserror::Error)]
+#[error("Too many HLS jobs in progress, try again later")]
+pub struct HlsBusy;
+
+/// One entry of a master playlist
+#[derive(Debug, Clone)]
+pub struct HlsVariant {
+    pub bandwidth: u64,
+    pub resolution: Option<(i32, i32)>,
+    pub framerate: Option<i32>,
+    pub uri: String,
+}
+
+impl HlsVariant {
+    /// Variant advertising a stream's real bitrate and resolution.
+    ///
+    /// Stream bitrates come from ONVIF and are in kbit/s, while HLS expects bit/s.
+    pub fn from_stream(stream: &Stream, uri: String) -> Self {
+        Self {
+            bandwidth: stream
+                .bitrate
+                .filter(|kbps| *kbps > 0)
+                .map(|kbps| kbps as u64 * 1000)
+                .unwrap_or(DEFAULT_VARIANT_BANDWIDTH),
+            resolution: stream.width.zip(stream.height),
+            framerate: stream.framerate.filter(|fps| *fps > 0),
+            uri,
+        }
+    }
+
+    /// Variant for a source whose stream details are unknown
+    pub fn unknown(uri: String) -> Self {
+        Self {
+            bandwidth: DEFAULT_VARIANT_BANDWIDTH,
+            resolution: None,
+            framerate: None,
+            uri,
+        }
+    }
+}
+
+/// HLS playback of recorded footage.
+///
+/// Both the `/hls` and `/playback` routers delegate here so they serve identical playlists
+/// and segments: media playlists are built from recording rows, and segments are MPEG-TS
+/// remuxed from the recorded MP4 files by FFmpeg on demand and cached under `temp_dir`.
+pub struct HlsService {
+    cache: Mutex<HlsCache>,
+    temp_dir: PathBuf,
+    /// Only files under this directory are ever handed to FFmpeg
+    recordings_dir: PathBuf,
+    /// Storage tier directory old recordings are moved to, also readable by FFmpeg
+    tier_dir: Option<PathBuf>,
+    /// Bounds the number of FFmpeg processes running at once
+    jobs: Semaphore,
+    max_jobs: usize,
+    queue_timeout: StdDuration,
+    /// Size `temp_dir` is trimmed back to by `enforce_temp_limits`
+    max_temp_bytes: u64,
+    /// Age after which `enforce_temp_limits` removes a generated file
+    max_temp_age: StdDuration,
+    /// Fetches archived recordings back for FFmpeg
+    archiver: Option<Arc<RecordingArchiver>>,
+}
+
+impl HlsService {
+    /// Create a new HLS service reading recordings from `recordings_dir` and writing
+    /// generated segments under `temp_dir`.
+    ///
+    /// At most `max_jobs` segments are generated concurrently; further requests queue for
+    /// up to `queue_timeout` before failing with [`HlsBusy`].
+    ///
+    /// Files left in `temp_dir` by a previous run are removed, since the cache that would
+    /// track them starts out empty.
+    pub fn new(
+        temp_dir: PathBuf,
+        recordings_dir: &Path,
+        max_jobs: usize,
+        queue_timeout: StdDuration,
+    ) -> Self {
+        for dir in [temp_dir.join("init"), temp_dir.join("segments")] {
+            if !dir.exists() {
+                std::fs::create_dir_all(&dir).expect("Failed to create temporary HLS directory");
+            }
+        }
+
+        let stale = temp_files(&temp_dir);
+        for file in &stale {
+            if let Err(e) = std::fs::remove_file(&file.path) {
+                warn!(
+                    "Failed to remove stale HLS file {}: {}",
+                    file.path.display(),
+                    e
+                );
+            }
+        }
+        if !stale.is_empty() {
+            info!(
+                "Removed {} stale HLS files from {}",
+                stale.len(),
+                temp_dir.display()
+            );
+        }
+
+        Self {
+            cache: Mutex::new(HlsCache::new(CACHE_CAPACITY)),
+            temp_dir,
+            recordings_dir: canonical_dir(recordings_dir),
+            tier_dir: None,
+            jobs: Semaphore::new(max_jobs.max(1)),
+            max_jobs: max_jobs.max(1),
+            queue_timeout,
+            max_temp_bytes: DEFAULT_TEMP_MAX_BYTES,
+            max_temp_age: DEFAULT_TEMP_MAX_AGE,
+            archiver: None,
+        }
+    }
+
+    /// Limit the temporary directory to `max_bytes` and its files to `max_age`
+    pub fn with_temp_limits(mut self, max_bytes: u64, max_age: StdDuration) -> Self {
+        self.max_temp_bytes = max_bytes;
+        self.max_temp_age = max_age;
+        self
+    }
+
+    /// Serve archived recordings through `archiver`, if archival is enabled
+    pub fn with_archiver(mut self, archiver: Option<Arc<RecordingArchiver>>) -> Self {
+        self.archiver = archiver;
+        self
+    }
+
+    /// Also serve recordings moved to the storage tier directory `tier_dir`
+    pub fn with_tier_dir(mut self, tier_dir: Option<&Path>) -> Self {
+        self.tier_dir = tier_dir.map(canonical_dir);
+        self
+    }
+
+    /// Remove generated files older than the age limit, then the least recently used ones
+    /// while the directory is over its size cap. Files no longer cached go first; cached
+    /// ones are dropped from the cache along with their file. Returns the number removed.
+    pub async fn enforce_temp_limits(&self) -> usize {
+        let mut cache = self.cache.lock().await;
+        let now = SystemTime::now();
+        let age = |file: &TempFile| now.duration_since(file.modified).unwrap_or_default();
+
+        let last_used: HashMap<PathBuf, u64> = cache
+            .entries
+            .values()
+            .map(|entry| (entry.path.clone(), entry.last_used))
+            .collect();
+        let (expired, mut kept): (Vec<TempFile>, Vec<TempFile>) = temp_files(&self.temp_dir)
+            .into_iter()
+            .partition(|file| age(file) >= self.max_temp_age);
+
+        let mut evicted = expired;
+        let mut total: u64 = kept.iter().map(|file| file.size).sum();
+        if total > self.max_temp_bytes {
+            // Uncached files first, oldest first, then cached ones by last use
+            kept.sort_by_key(|file| (last_used.get(&file.path).copied(), file.modified));
+            for file in kept {
+                if total <= self.max_temp_bytes {
+                    break;
+                }
+                if !last_used.contains_key(&file.path) && age(&file) < IN_PROGRESS_GRACE {
+                    continue;
+                }
+                total -= file.size;
+                evicted.push(file);
+            }
+        }
+
+        let mut removed = HashSet::new();
+        for file in evicted {
+            match std::fs::remove_file(&file.path) {
+                Ok(()) => {
+                    removed.insert(file.path);
+                }
+                Err(e) => warn!("Failed to remove HLS file {}: {}", file.path.display(), e),
+            }
+        }
+        cache
+            .entries
+            .retain(|_, entry| !removed.contains(&entry.path));
+
+        if !removed.is_empty() {
+            info!(
+                "Removed {} HLS files from {}",
+                removed.len(),
+                self.temp_dir.display()
+            );
+        }
+        removed.len()
+    }
+
+    /// Resolve a recording's file for FFmpeg.
+    ///
+    /// The path is canonicalized so symlinks and `..` cannot point FFmpeg outside the
+    /// recordings directory or the storage tier directory.
+    fn source_path(&self, recording: &Recording) -> anyhow::Result<PathBuf> {
+        confined_tier_path(
+            &self.recordings_dir,
+            self.tier_dir.as_deref(),
+            &recording.file_path,
+        )
+    }
+
+    /// Resolve a recording's file for FFmpeg or a download like `source_path`, first
+    /// fetching archived recordings into the archive cache under the recordings directory
+    pub(super) async fn fetch_source(&self, recording: &Recording) -> anyhow::Result<PathBuf> {
+        match (&self.archiver, archived_key(recording)) {
+            (Some(archiver), Some(_)) => {
+                confined_path(&self.recordings_dir, &archiver.local_path(recording).await?)
+            }
+            _ => self.source_path(recording),
+        }
+    }
+
+    /// Number of FFmpeg jobs currently holding a slot
+    pub fn running_jobs(&self) -> usize {
+        self.max_jobs - self.jobs.available_permits()
+    }
+
+    /// Wait for a free job slot, giving up after the queue timeout
+    async fn acquire_job(&self) -> anyhow::Result<SemaphorePermit<'_>> {
+        match tokio::time::timeout(self.queue_timeout, self.jobs.acquire()).await {
+            Ok(Ok(permit)) => Ok(permit),
+            Ok(Err(_)) => Err(anyhow::anyhow!("HLS job queue is closed")),
+            Err(_) => Err(HlsBusy.into()),
+        }
+    }
+
+    /// Build a master playlist with one entry per variant, in the order given
+    pub fn master_playlist(&self, variants: &[HlsVariant]) -> String {
+        let mut playlist = String::from("#EXTM3U\n#EXT-X-VERSION:3\n");
+
+        for variant in variants {
+            playlist.push_str(&format!(
+                "#EXT-X-STREAM-INF:BANDWIDTH={}",
+                variant.bandwidth
+            ));
+            if let Some((width, height)) = variant.resolution {
+                playlist.push_str(&format!(",RESOLUTION={}x{}", width, height));
+            }
+            if let Some(framerate) = variant.framerate {
+                playlist.push_str(&format!(",FRAME-RATE={:.3}", framerate as f64));
+            }
+            playlist.push_str(&format!("\n{}\n", variant.uri));
+        }
+
+        playlist
+    }
+
+    /// Build a media playlist with one segment per recording, in chronological order.
+    ///
+    /// Each segment is advertised with its recording's real length, and recordings that are
+    /// unfinished or whose file is neither on disk nor archived are left out. A discontinuity is marked wherever
+    /// consecutive recordings are not contiguous.
+    pub fn recordings_playlist(&self, recordings: &[Recording]) -> String {
+        let mut sorted: Vec<&Recording> = recordings
+            .iter()
+            .filter(|r| r.end_time.is_some() && has_file(r))
+            .collect();
+        sorted.sort_by_key(|r| r.start_time);
+
+        let durations: Vec<f64> = sorted.iter().map(|r| recording_duration(r)).collect();
+        let target_duration = durations
+            .iter()
+            .cloned()
+            .fold(0.0, f64::max)
+            .ceil()
+            .max(1.0);
+
+        let mut playlist = format!(
+            "#EXTM3U\n\
+             #EXT-X-VERSION:3\n\
+             #EXT-X-TARGETDURATION:{}\n\
+             #EXT-X-MEDIA-SEQUENCE:0\n\
+             #EXT-X-PLAYLIST-TYPE:VOD\n",
+            target_duration as u64
+        );
+
+        let mut previous_end_time = None;
+        for (recording, duration) in sorted.into_iter().zip(durations) {
+            // 1 second tolerance between the end of one recording and the next
+            if let Some(prev_end) = previous_end_time {
+                if recording.start_time > prev_end + Duration::seconds(1) {
+                    playlist.push_str("#EXT-X-DISCONTINUITY\n");
+                }
+            }
+
+            playlist.push_str(&format!(
+                "#EXTINF:{:.3},\n/hls/{}/segment\n",
+                duration, recording.id
+            ));
+            previous_end_time = recording.end_time;
+        }
+
+        playlist.push_str("#EXT-X-ENDLIST\n");
+        playlist
+    }
+
+    /// Build a media playlist cutting a single recording into fixed-length segments
+    pub fn recording_playlist(&self, recording: &Recording, segment_duration: f64) -> String {
+        let segment_duration = if segment_duration > 0.0 {
+            segment_duration
+        } else {
+            DEFAULT_SEGMENT_DURATION
+        };
+
+        let mut playlist = format!(
+            "#EXTM3U\n\
+             #EXT-X-VERSION:3\n\
+             #EXT-X-TARGETDURATION:{}\n\
+             #EXT-X-MEDIA-SEQUENCE:0\n\
+             #EXT-X-PLAYLIST-TYPE:VOD\n",
+            segment_duration.ceil() as u64
+        );
+
+        let total = recording.duration as f64;
+        let mut start = 0.0;
+        while start < total {
+            let length = segment_duration.min(total - start);
+            playlist.push_str(&format!(
+                "#EXTINF:{:.3},\n/hls/{}/segment?start_time={}&duration={}\n",
+                length, recording.id, start, length
+            ));
+            start += segment_duration;
+        }
+
+        playlist.push_str("#EXT-X-ENDLIST\n");
+        playlist
+    }
+
+    /// Get the initialization segment for a recording, generating it if needed
+    pub async fn init_segment(&self, recording: &Recording) -> anyhow::Result<PathBuf> {
+        let source = self.fetch_source(recording).await?;
+        let version = source_version(recording, &source)?;
+        let cache_key = format!("init_{}", version);
+        if let Some(path) = self.cache.lock().await.get(&cache_key) {
+            return Ok(path);
+        }
+
+        let output_path = self.temp_dir.join("init").join(format!("{}.mp4", version));
+        let _job = self.acquire_job().await?;
+        generate_init_segment(recording, &source, &output_path).await?;
+
+        self.cache.lock().await.insert(
+            cache_key,
+            output_path.clone(),
+            recording.end_time.is_some(),
+        );
+
+        Ok(output_path)
+    }
+
+    /// Get an MPEG-TS segment of a recording, generating it if needed.
+    ///
+    /// Without a duration the segment runs to the end of the recording.
+    pub async fn segment(
+        &self,
+        recording: &Recording,
+        start_time: f64,
+        duration: Option<f64>,
+    ) -> anyhow::Result<PathBuf> {
+        let span = duration
+            .map(|d| format!("{}s", d))
+            .unwrap_or_else(|| "full".to_string());
+        let source = self.fetch_source(recording).await?;
+        let name = format!(
+            "{}_{}_{}",
+            source_version(recording, &source)?,
+            start_time,
+            span
+        );
+        let cache_key = format!("segment_{}", name);
+        if let Some(path) = self.cache.lock().await.get(&cache_key) {
+            return Ok(path);
+        }
+
+        let output_path = self.temp_dir.join("segments").join(format!("{}.ts", name));
+        let _job = self.acquire_job().await?;
+        generate_segment(recording, &source, &output_path, start_time, duration).await?;
+
+        self.cache.lock().await.insert(
+            cache_key,
+            output_path.clone(),
+            recording.end_time.is_some(),
+        );
+
+        Ok(output_path)
+    }
+}
+
+/// Enforce the temporary directory's size and age limits periodically, until the service
+/// is dropped
+pub fn spawn_temp_cleanup(service: &Arc<HlsService>) {
+    let service = Arc::downgrade(service);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(TEMP_CLEANUP_INTERVAL);
+        loop {
+            interval.tick().await;
+            let Some(service) = service.upgrade() else {
+                break;
+            };
+            service.enforce_temp_limits().await;
+        }
+    });
+}
+
+/// A file under the temporary directory
+struct TempFile {
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+}
+
+/// Every file under `dir`'s `init` and `segments` directories
+fn temp_files(dir: &Path) -> Vec<TempFile> {
+    [dir.join("init"), dir.join("segments")]
+        .iter()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let metadata = entry.metadata().ok()?;
+            metadata.is_file().then(|| TempFile {
+                path: entry.path(),
+                size: metadata.len(),
+                modified: metadata.modified().unwrap_or_else(|_| SystemTime::now()),
+            })
+        })
+        .collect()
+}
+
+/// Length of a recording in seconds, with sub-second precision when the timestamps agree.
+///
+/// `duration` is stored in whole seconds; the start/end span is used instead when it is
+/// within a second of it, so rounding never makes the playlist drift from the media.
+fn recording_duration(recording: &Recording) -> f64 {
+    let stored = recording.duration as f64;
+    match recording.end_time {
+        Some(end_time) => {
+            let span = (end_time - recording.start_time).num_milliseconds() as f64 / 1000.0;
+            if span > 0.0 && (span - stored).abs() < 1.0 {
+                span
+            } else {
+                stored
+            }
+        }
+        None => stored,
+    }
+}
+
+/// Upper bound on a single FFmpeg run before it is killed
+const FFMPEG_TIMEOUT: StdDuration = StdDuration::from_secs(120);
+
+/// FFmpeg `-map` arguments: the first video track, and the first audio track only when the
+/// recording has one, so video-only cameras play back the same way
+const STREAM_MAPS: [&str; 4] = ["-map", "0:v:0", "-map", "0:a:0?"];
+
+/// Number of trailing FFmpeg stderr lines included in error messages
+const STDERR_TAIL_LINES: usize = 10;
+
+/// Build an FFmpeg command that is killed if the request driving it is dropped
+pub(super) fn ffmpeg_command() -> Command {
+    let mut command = Command::new("ffmpeg");
+    command
+        .arg("-nostdin")
+        .arg("-hide_banner")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    command
+}
+
+/// Wait for a child process, killing it if it runs past `timeout`.
+///
+/// Stderr is collected so a failure can be reported with FFmpeg's own explanation.
+pub(super) async fn wait_or_kill(mut child: Child, timeout: StdDuration) -> anyhow::Result<()> {
+    let stderr = child.stderr.take();
+    let stderr_task = tokio::spawn(async move {
+        let mut output = Vec::new();
+        if let Some(mut stderr) = stderr {
+            let _ = stderr.read_to_end(&mut output).await;
+        }
+        String::from_utf8_lossy(&output).into_owned()
+    });
+
+    let status = match tokio::time::timeout(timeout, child.wait()).await {
+        Ok(status) => status?,
+        Err(_) => {
+            let _ = child.kill().await;
+            stderr_task.abort();
+            return Err(anyhow::anyhow!("FFmpeg timed out after {:?}", timeout));
+        }
+    };
+
+    let stderr = stderr_task.await.unwrap_or_default();
+    if !status.success() {
+        let lines: Vec<&str> = stderr.lines().collect();
+        let tail = lines[lines.len().saturating_sub(STDERR_TAIL_LINES)..].join("\n");
+        return Err(anyhow::anyhow!("FFmpeg exited with {}: {}", status, tail));
+    }
+
+    Ok(())
+}
+
+/// FFmpeg input argument for a local file.
+///
+/// The explicit `file:` protocol stops FFmpeg from treating a path that looks like an
+/// option or another protocol (`concat:`, `http:`, ...) as anything but a plain file.
+pub(super) fn ffmpeg_input(path: &Path) -> OsString {
+    let mut input = OsString::from("file:");
+    input.push(path);
+    input
+}
+
+/// Generate an initialization segment for HLS streaming
+async fn generate_init_segment(
+    recording: &Recording,
+    source: &Path,
+    output_path: &Path,
+) -> anyhow::Result<()> {
+    info!("Generating init segment for recording: {}", recording.id);
+
+    // Use FFmpeg to extract the initialization segment (first few frames without keyframes)
+    let child = ffmpeg_command()
+        .arg("-i")
+        .arg(ffmpeg_input(source)) // Input file
+        .arg("-c")
+        .arg("copy") // Copy codecs
+        .args(STREAM_MAPS)
+        .arg("-f")
+        .arg("mp4") // Use MP4 format
+        .arg("-y") // Overwrite existing file
+        .arg("-t")
+        .arg("0.5") // Just get a small portion for initialization
+        .arg(output_path) // Output path
+        .spawn()?;
+
+    wait_or_kill(child, FFMPEG_TIMEOUT)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to generate init segment: {}", e))?;
+
+    // Verify file was created successfully
+    if !output_path.exists() || std::fs::metadata(output_path)?.len() == 0 {
+        return Err(anyhow::anyhow!("Failed to create a valid init segment"));
+    }
+
+    Ok(())
+}
+
+/// Generate a segment for HLS streaming at a specific timestamp
+async fn generate_segment(
+    recording: &Recording,
+    source: &Path,
+    output_path: &Path,
+    start_time: f64,
+    duration: Option<f64>,
+) -> anyhow::Result<()> {
+    info!(
+        "Generating segment for recording {} at {}s for {:?}s",
+        recording.id, start_time, duration
+    );
+
+    // Use FFmpeg to extract the segment
+    let mut command = ffmpeg_command();
+    command
+        .arg("-i")
+        .arg(ffmpeg_input(source)) // Input file
+        .arg("-ss")
+        .arg(start_time.to_string()); // Start time
+    if let Some(duration) = duration {
+        command.arg("-t").arg(duration.to_string()); // Duration
+    }
+    let child = command
+        .arg("-c")
+        .arg("copy") // Copy codecs
+        .args(STREAM_MAPS)
+        .arg("-f")
+        .arg("mpegts") // Use MPEG-TS format for better compatibility
+        .arg("-y") // Overwrite existing file
+        .arg(output_path) // Output path
+        .spawn()?;
+
+    wait_or_kill(child, FFMPEG_TIMEOUT)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to generate segment: {}", e))?;
+
+    // Verify file was created successfully
+    if !output_path.exists() || std::fs::metadata(output_path)?.len() == 0 {
+        return Err(anyhow::anyhow!("Failed to create a valid segment"));
+    }
+
+    Ok(())
+}
+
+/// Common headers so browsers on other origins can fetch playlists and segments
+fn cors_headers(content_type: &str) -> HeaderMap {
+    HeaderMap::from_iter([
+        (header::CONTENT_TYPE, content_type.parse().unwrap()),
+        (header::CACHE_CONTROL, "max-age=3600".parse().unwrap()), // Cache for an hour
+        (header::ACCESS_CONTROL_ALLOW_ORIGIN, "*".parse().unwrap()),
+        (
+            header::ACCESS_CONTROL_ALLOW_METHODS,
+            "GET, HEAD, OPTIONS".parse().unwrap(),
+        ),
+        (
+            header::ACCESS_CONTROL_ALLOW_HEADERS,
+            "Origin, Content-Type, Accept, Range".parse().unwrap(),
+        ),
+        (
+            header::ACCESS_CONTROL_EXPOSE_HEADERS,
+            "Content-Length, Content-Range, Content-Type"
+                .parse()
+                .unwrap(),
+        ),
+        (header::ACCESS_CONTROL_MAX_AGE, "86400".parse().unwrap()), // 24 hours
+    ])
+}
+
+/// Map a failed segment request to a response, using 503 when the job queue is saturated
+pub fn job_error_response(err: anyhow::Error, message: &'static str) -> Response {
+    if err.downcast_ref::<HlsBusy>().is_some() {
+        return ApiError::new(StatusCode::SERVICE_UNAVAILABLE, HlsBusy.to_string()).into_response();
+    }
+    match err.downcast_ref::<Error>() {
+        Some(Error::NotFound(reason)) => {
+            return ApiError::new(StatusCode::NOT_FOUND, reason.clone()).into_response()
+        }
+        Some(Error::InvalidInput(reason)) => {
+            error!("{}: {}", message, reason);
+            return ApiError::new(StatusCode::FORBIDDEN, "Recording file is not servable")
+                .into_response();
+        }
+        _ => {}
+    }
+    error!("{}: {}", message, err);
+    ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, message).into_response()
+}
+
+/// Serve a generated playlist
+pub fn playlist_response(playlist: String) -> Response {
+    (
+        StatusCode::OK,
+        cors_headers("application/vnd.apple.mpegurl"),
+        playlist,
+    )
+        .into_response()
+}
+
+/// Helper function to serve a file with appropriate headers
+pub async fn serve_file(path: PathBuf, request: &HeaderMap) -> Response {
+    match tokio::fs::File::open(&path).await {
+        Ok(file) => {
+            // Determine content type based on file extension
+            let content_type = match path.extension().and_then(|e| e.to_str()) {
+                Some("ts") => "video/mp2t", // MPEG-2 Transport Stream
+                Some("mp4") => "video/mp4", // MP4 file or init segment
+                _ => "application/octet-stream",
+            };
+
+            file_response(file, request, cors_headers(content_type)).await
+        }
+        Err(e) => file_error(&path, &e, "File").into_response(),
+    }
+}
+
+/// Stream an open file with `headers` plus an `ETag` and `Last-Modified`, answering 304
+/// instead when the request's `If-None-Match` or `If-Modified-Since` shows the client's
+/// copy is current
+pub async fn file_response(
+    file: tokio::fs::File,
+    request: &HeaderMap,
+    mut headers: HeaderMap,
+) -> Response {
+    if let Some(validators) = file
+        .metadata()
+        .await
+        .ok()
+        .and_then(|m| FileValidators::of(&m))
+    {
+        headers.insert(header::ETAG, validators.etag.parse().unwrap());
+        headers.insert(
+            header::LAST_MODIFIED,
+            validators.last_modified_header().parse().unwrap(),
+        );
+        if validators.not_modified(request) {
+            return (StatusCode::NOT_MODIFIED, headers).into_response();
+        }
+    }
+
+    let body = StreamBody::new(ReaderStream::new(file));
+    (StatusCode::OK, headers, body).into_response()
+}
+
+/// Identify a version of a file for conditional requests. Recordings never change once
+/// finalized, so clients can keep their copy for as long as these match.
+struct FileValidators {
+    /// Strong ETag derived from the file's size and mtime
+    etag: String,
+    /// Modification time, truncated to the second precision of HTTP dates
+    last_modified: DateTime<Utc>,
+}
+
+impl FileValidators {
+    fn of(metadata: &std::fs::Metadata) -> Option<Self> {
+        let modified = metadata
+            .modified()
+            .ok()?
+            .duration_since(std::time::UNIX_EPOCH)
+            .ok()?;
+        let last_modified = DateTime::from_timestamp(modified.as_secs() as i64, 0)?;
+        Some(Self {
+            etag: format!("\"{:x}-{:x}\"", metadata.len(), modified.as_nanos()),
+            last_modified,
+        })
+    }
+
+    fn last_modified_header(&self) -> String {
+        self.last_modified
+            .format("%a, %d %b %Y %H:%M:%S GMT")
+            .to_string()
+    }
+
+    /// Whether the client's cached copy is current. `If-None-Match` takes precedence over
+    /// `If-Modified-Since` when both are sent.
+    fn not_modified(&self, request: &HeaderMap) -> bool {
+        if let Some(if_none_match) = request.get(header::IF_NONE_MATCH) {
+            let Ok(tags) = if_none_match.to_str() else {
+                return false;
+            };
+            return tags
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == self.etag);
+        }
+
+        request
+            .get(header::IF_MODIFIED_SINCE)
+            .and_then(|since| since.to_str().ok())
+            .and_then(|since| DateTime::parse_from_rfc2822(since).ok())
+            .is_some_and(|since| self.last_modified <= since)
+    }
+}
+
+/// Map a failure to open `path` to an API error: 404 when the file is absent, 500 for
+/// anything else, so a permission or disk problem never passes for missing footage
+pub fn file_error(path: &Path, err: &std::io::Error, what: &str) -> ApiError {
+    if err.kind() == std::io::ErrorKind::NotFound {
+        return ApiError::new(StatusCode::NOT_FOUND, format!("{} not found", what));
+    }
+    error!("Failed to open {} {}: {}", what, path.display(), err);
+    ApiError::new(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("{} could not be read", what),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::models::recording_models::RecordingEventType;
+    use uuid::Uuid;
+
+    fn recording_at(file_path: PathBuf, start: DateTime<Utc>, duration: u64) -> Recording {
+        Recording {
+            id: Uuid::new_v4(),
+            camera_id: Uuid::new_v4(),
+            stream_id: Uuid::new_v4(),
+            start_time: start,
+            end_time: Some(start + Duration::seconds(duration as i64)),
+            file_path,
+            file_size: 0,
+            duration,
+            format: "mp4".to_string(),
+            resolution: "1280x720".to_string(),
+            fps: 25,
+            event_type: RecordingEventType::Continuous,
+            metadata: None,
+            schedule_id: None,
+            segment_id: None,
+            parent_recording_id: None,
+        }
+    }
+
+    #[test]
+    fn playlist_uses_real_recording_durations() {
+        let dir = std::env::temp_dir().join(format!("hls-service-test-{}", Uuid::new_v4()));
+        let hls = HlsService::new(dir.clone(), &dir, 1, StdDuration::from_secs(1));
+        let file_path = dir.join("recording.mp4");
+        std::fs::write(&file_path, b"mp4").unwrap();
+
+        let start = Utc::now() - Duration::minutes(5);
+        let present = recording_at(file_path, start, 37);
+        let missing = recording_at(dir.join("missing.mp4"), start + Duration::seconds(37), 60);
+
+        let playlist = hls.recordings_playlist(&[missing.clone(), present.clone()]);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(playlist.contains("#EXTINF:37"));
+        assert!(!playlist.contains("#EXTINF:4.0"));
+        let target: u64 = playlist
+            .lines()
+            .find_map(|line| line.strip_prefix("#EXT-X-TARGETDURATION:"))
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(target >= 37);
+        assert!(playlist.contains(&format!("/hls/{}/segment", present.id)));
+        assert!(!playlist.contains(&missing.id.to_string()));
+    }
+
+    #[tokio::test]
+    async fn saturated_job_queue_rejects_extra_requests() {
+        let dir = std::env::temp_dir().join(format!("hls-jobs-test-{}", Uuid::new_v4()));
+        let hls = HlsService::new(dir.clone(), &dir, 2, StdDuration::from_millis(50));
+
+        let first = hls.acquire_job().await.unwrap();
+        let _second = hls.acquire_job().await.unwrap();
+
+        // The third request queues, then gives up once the timeout passes
+        let err = hls.acquire_job().await.unwrap_err();
+        assert!(err.downcast_ref::<HlsBusy>().is_some());
+
+        // A queued request proceeds as soon as a slot frees up
+        drop(first);
+        assert!(hls.acquire_job().await.is_ok());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn source_paths_are_confined_to_recordings_dir() {
+        let root = std::env::temp_dir().join(format!("hls-paths-test-{}", Uuid::new_v4()));
+        let recordings = root.join("recordings");
+        std::fs::create_dir_all(&recordings).unwrap();
+        let outside = root.join("outside.mp4");
+        std::fs::write(&outside, b"mp4").unwrap();
+        let quoted = recordings.join("cam \"front\" ! filesrc.mp4");
+        std::fs::write(&quoted, b"mp4").unwrap();
+
+        let hls = HlsService::new(root.join("hls"), &recordings, 1, StdDuration::from_secs(1));
+        let start = Utc::now();
+
+        // Quotes and launch syntax in the name reach FFmpeg verbatim as one argument
+        let source = hls.source_path(&recording_at(quoted, start, 10)).unwrap();
+        let input = ffmpeg_input(&source);
+        assert!(input.to_string_lossy().starts_with("file:"));
+        assert!(input
+            .to_string_lossy()
+            .ends_with("cam \"front\" ! filesrc.mp4"));
+
+        for path in [outside, recordings.join("..").join("outside.mp4")] {
+            let err = hls.source_path(&recording_at(path, start, 10)).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<Error>(),
+                Some(Error::InvalidInput(_))
+            ));
+        }
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn timed_out_job_is_killed() {
+        let child = Command::new("sleep")
+            .arg("30")
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .unwrap();
+        let pid = child.id().unwrap();
+
+        let started = std::time::Instant::now();
+        let err = wait_or_kill(child, StdDuration::from_millis(100))
+            .await
+            .unwrap_err();
+
+        assert!(err.to_string().contains("timed out"));
+        assert!(started.elapsed() < StdDuration::from_secs(5));
+        assert!(!Path::new(&format!("/proc/{}", pid)).exists());
+    }
+
+    #[tokio::test]
+    async fn video_only_recordings_are_served() {
+        let dir = std::env::temp_dir().join(format!("hls-video-only-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = dir.join("video-only.mp4");
+
+        // A camera without a microphone: a test pattern with no audio track
+        let made = ffmpeg_command()
+            .args(["-f", "lavfi", "-i"])
+            .arg("testsrc=duration=2:size=320x240:rate=10")
+            .arg(&source)
+            .status()
+            .await;
+        if !made.map_or(false, |status| status.success()) {
+            println!("Skipping video-only HLS test. Install ffmpeg to run.");
+            std::fs::remove_dir_all(&dir).unwrap();
+            return;
+        }
+
+        let hls = HlsService::new(dir.join("hls"), &dir, 1, StdDuration::from_secs(1));
+        let recording = recording_at(source, Utc::now() - Duration::seconds(2), 2);
+        let init = hls.init_segment(&recording).await.unwrap();
+        let segment = hls.segment(&recording, 0.0, None).await.unwrap();
+        let sizes = [init, segment].map(|path| std::fs::metadata(path).unwrap().len());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(sizes.iter().all(|size| *size > 0));
+    }
+
+    #[test]
+    fn cache_evicts_least_recently_used_and_expires_live_segments() {
+        let dir = std::env::temp_dir().join(format!("hls-cache-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = |name: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, b"ts").unwrap();
+            path
+        };
+
+        let mut cache = HlsCache::new(2);
+        cache.insert("a".to_string(), file("a.ts"), true);
+        cache.insert("b".to_string(), file("b.ts"), true);
+        assert!(cache.get("a").is_some());
+        cache.insert("c".to_string(), file("c.ts"), true);
+
+        // "b" was least recently used, so it and its file are gone
+        assert!(cache.get("b").is_none());
+        assert!(!dir.join("b.ts").exists());
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("c").is_some());
+
+        // Segments of a recording still being written only live for a short TTL
+        cache.insert("live".to_string(), file("live.ts"), false);
+        cache.entries.get_mut("live").unwrap().created_at =
+            Utc::now() - Duration::seconds(LIVE_SEGMENT_TTL_SECS + 1);
+        assert!(cache.get("live").is_none());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn old_temp_files_are_removed_with_their_cache_entries() {
+        let dir = std::env::temp_dir().join(format!("hls-temp-test-{}", Uuid::new_v4()));
+        let segments = dir.join("segments");
+        std::fs::create_dir_all(&segments).unwrap();
+        std::fs::write(segments.join("leftover.ts"), b"ts").unwrap();
+
+        // Files left by a previous run are gone once the service starts
+        let service = HlsService::new(dir.clone(), &dir, 1, StdDuration::from_secs(1))
+            .with_temp_limits(u64::MAX, StdDuration::from_secs(3600));
+        assert!(!segments.join("leftover.ts").exists());
+
+        let old = segments.join("old.ts");
+        let fresh = segments.join("fresh.ts");
+        for path in [&old, &fresh] {
+            std::fs::write(path, b"ts").unwrap();
+        }
+        std::fs::File::options()
+            .write(true)
+            .open(&old)
+            .unwrap()
+            .set_modified(SystemTime::now() - StdDuration::from_secs(7200))
+            .unwrap();
+        {
+            let mut cache = service.cache.lock().await;
+            cache.insert("old".to_string(), old.clone(), true);
+            cache.insert("fresh".to_string(), fresh.clone(), true);
+        }
+
+        assert_eq!(service.enforce_temp_limits().await, 1);
+        assert!(!old.exists());
+        assert!(fresh.exists());
+        let mut cache = service.cache.lock().await;
+        assert!(!cache.entries.contains_key("old"));
+        assert_eq!(cache.get("fresh"), Some(fresh));
+        drop(cache);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn absent_files_are_404_and_unreadable_ones_500() {
+        let dir = std::env::temp_dir().join(format!("hls-file-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let absent = dir.join("absent.ts");
+
+        assert_eq!(
+            serve_file(absent.clone(), &HeaderMap::new()).await.status(),
+            StatusCode::NOT_FOUND
+        );
+        let err = confined_path(&dir, &absent).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::NotFound(_))
+        ));
+
+        // Permissions can't be relied on to deny reads in tests, which may run as root
+        let denied = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        let err = file_error(&absent, &denied, "File");
+        assert_eq!(err.status, StatusCode::INTERNAL_SERVER_ERROR.as_u16());
+        let err = file_error(&absent, &std::io::ErrorKind::NotFound.into(), "File");
+        assert_eq!(err.status, StatusCode::NOT_FOUND.as_u16());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn matching_etag_gets_not_modified() {
+        let dir = std::env::temp_dir().join(format!("hls-etag-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("recording.mp4");
+        std::fs::write(&path, b"mp4").unwrap();
+
+        let first = serve_file(path.clone(), &HeaderMap::new()).await;
+        assert_eq!(first.status(), StatusCode::OK);
+        let etag = first.headers()[header::ETAG].clone();
+        let last_modified = first.headers()[header::LAST_MODIFIED].clone();
+
+        let mut request = HeaderMap::new();
+        request.insert(header::IF_NONE_MATCH, etag.clone());
+        let second = serve_file(path.clone(), &request).await;
+        assert_eq!(second.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(second.headers()[header::ETAG], etag);
+
+        let mut request = HeaderMap::new();
+        request.insert(header::IF_MODIFIED_SINCE, last_modified);
+        assert_eq!(
+            serve_file(path.clone(), &request).await.status(),
+            StatusCode::NOT_MODIFIED
+        );
+
+        // A changed file no longer matches the client's ETag
+        std::fs::write(&path, b"longer mp4").unwrap();
+        let mut request = HeaderMap::new();
+        request.insert(header::IF_NONE_MATCH, etag);
+        assert_eq!(serve_file(path, &request).await.status(), StatusCode::OK);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,8 +1,9 @@
-use crate::api::rest::AppState;
-use crate::db::models::recording_models::RecordingSearchQuery;
+use crate::api::rest::{ApiError, ApiResult, AppState};
+use crate::db::models::recording_models::{Recording, RecordingSearchQuery};
+use crate::recorder::archiver::archived_key;
 use axum::extract::{Query, State};
 use axum::http::StatusCode;
-use axum::response::{IntoResponse, Json};
+use axum::response::Json;
 use chrono::{DateTime, Utc};
 use log::{error, info};
 use serde::{Deserialize, Serialize};
@@ -54,42 +55,38 @@ pub struct VodClip {
 pub async fn generate_vod_mapping(
     Query(params): Query<NginxVodMappingParams>,
     State(state): State<AppState>,
-) -> impl IntoResponse {
+) -> ApiResult<Json<VodMappingResponse>> {
     info!("VOD mapping request: {:?}", params);
 
     if let Some(camera_id) = &params.camera_id {
         // Camera-wide mapping - all recordings from a single camera
         match generate_camera_mapping(camera_id, &params, &state).await {
-            Ok(mapping) => (StatusCode::OK, Json(mapping)),
+            Ok(mapping) => Ok(Json(mapping)),
             Err(e) => {
                 error!("Error generating camera mapping: {}", e);
-                let error_response = VodMappingResponse {
-                    sequences: vec![], // Empty sequences for error
-                };
-                (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
+                Err(ApiError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to generate camera mapping",
+                ))
             }
         }
     } else if let Some(recording_id) = &params.recording_id {
         // Single recording mapping
         match generate_recording_mapping(recording_id, &params, &state).await {
-            Ok(mapping) => (StatusCode::OK, Json(mapping)),
+            Ok(mapping) => Ok(Json(mapping)),
             Err(e) => {
                 error!("Error generating recording mapping: {}", e);
-                let error_response = VodMappingResponse {
-                    sequences: vec![], // Empty sequences for error
-                };
-                (StatusCode::INTERNAL_SERVER_ERROR, Json(error_response))
+                Err(ApiError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to generate recording mapping",
+                ))
             }
         }
     } else {
-        // Neither camera_id nor recording_id provided
-        let error_response = VodMappingResponse {
-            sequences: vec![], // Empty sequences for error
-        };
-        (
+        Err(ApiError::new(
             StatusCode::BAD_REQUEST,
-            Json(error_response)
-        )
+            "Either camera_id or recording_id is required",
+        ))
     }
 }
 
@@ -114,6 +111,9 @@ async fn generate_camera_mapping(
         event_types: None,
         schedule_id: None,
         min_duration: Some(1), // Exclude 0-duration recordings
+        min_file_size: None,
+        max_file_size: None,
+        has_audio: None,
         segment_id: None,
         parent_recording_id: None,
         is_segment: None,
@@ -127,7 +127,7 @@ async fn generate_camera_mapping(
     // Filter recordings with existing files
     let valid_recordings: Vec<_> = recordings
         .into_iter()
-        .filter(|r| r.file_path.exists() && r.end_time.is_some())
+        .filter(|r| is_available(r, state) && r.end_time.is_some())
         .collect();
         
     info!("Found {} valid recordings for camera {}", valid_recordings.len(), camera_id);
@@ -141,7 +141,8 @@ async fn generate_camera_mapping(
     sorted_recordings.sort_by(|a, b| a.start_time.cmp(&b.start_time));
     
     // Create clips from sorted recordings
-    let clips = sorted_recordings.into_iter().map(|recording| {
+    let mut clips = Vec::new();
+    for recording in sorted_recordings {
         // Get the file path - either index.mp4 in the recording directory or original file
         let recordings_base = std::env::var("RECORDINGS_PATH").unwrap_or_else(|_| "/app/recordings".to_string());
         let recording_dir = PathBuf::from(recordings_base)
@@ -151,16 +152,16 @@ async fn generate_camera_mapping(
         let file_path = if index_path.exists() {
             index_path
         } else {
-            recording.file_path
+            local_file(&recording, state).await?
         };
         
-        VodClip {
+        clips.push(VodClip {
             clip_type: "source".to_string(),
             path: file_path.to_string_lossy().to_string(),
             clip_from: None,
             clip_to: None,
-        }
-    }).collect();
+        });
+    }
     
     // Create the sequences and response
     let sequence = VodSequence {
@@ -199,7 +200,7 @@ async fn generate_recording_mapping(
     let file_path = if index_path.exists() {
         index_path
     } else {
-        recording.file_path
+        local_file(&recording, state).await?
     };
     
     // Check if file exists
@@ -226,4 +227,21 @@ async fn generate_recording_mapping(
     Ok(VodMappingResponse {
         sequences: vec![sequence],
     })
-}
\ No newline at end of file
+}
+
+/// Whether nginx can be handed a recording's file: it's on disk, or archived while the
+/// archive store is configured
+fn is_available(recording: &Recording, state: &AppState) -> bool {
+    match archived_key(recording) {
+        Some(_) => state.archiver.is_some(),
+        None => recording.file_path.exists(),
+    }
+}
+
+/// Local file of a recording, fetching archived ones into the archive cache
+async fn local_file(recording: &Recording, state: &AppState) -> Result<PathBuf, anyhow::Error> {
+    match &state.archiver {
+        Some(archiver) => archiver.local_path(recording).await,
+        None => Ok(recording.file_path.clone()),
+    }
+}
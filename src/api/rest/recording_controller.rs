@@ -1,4 +1,4 @@
-use crate::api::rest::AppState;
+use crate::api::rest::{ApiError, ApiResult, AppState};
 use crate::db::models::recording_models::{RecordingEventType, RecordingSearchQuery};
 use crate::db::repositories::cameras::CamerasRepository;
 use crate::db::repositories::recordings::RecordingsRepository;
@@ -72,6 +72,9 @@ pub struct SearchParams {
     pub segment_id: Option<u32>,
     pub parent_recording_id: Option<String>,
     pub is_segment: Option<bool>,
+    pub min_file_size: Option<u64>,
+    pub max_file_size: Option<u64>,
+    pub has_audio: Option<bool>,
     pub limit: Option<usize>,
     pub offset: Option<usize>,
 }
@@ -107,17 +110,34 @@ pub fn create_router<S: Clone + Send + Sync + 'static>(_state: S) -> Router<AppS
         .route("/prune/:camera_id", delete(prune_recordings))
 }
 
+/// Event type a start request asks for. Manual unless it names an event source; continuous
+/// recordings belong to schedules, so asking for one starts a manual recording as before.
+fn requested_event_type(event_type: Option<&str>) -> ApiResult<RecordingEventType> {
+    let event_type = match event_type {
+        Some(event_type) => event_type
+            .parse()
+            .map_err(|e: String| ApiError::new(StatusCode::BAD_REQUEST, e))?,
+        None => RecordingEventType::Manual,
+    };
+    Ok(match event_type {
+        RecordingEventType::Continuous => RecordingEventType::Manual,
+        event_type => event_type,
+    })
+}
+
 /// Start recording for a specific camera and stream
 pub async fn start_recording(
     Path((camera_id, stream_id)): Path<(String, String)>,
     State(state): State<AppState>,
     Json(request): Json<StartRecordingRequest>,
-) -> Result<Json<RecordingResponse>, StatusCode> {
+) -> ApiResult<Json<RecordingResponse>> {
     // Convert AppState to RecordingApiState
     let state = app_state_to_recording_state(&state);
     // Parse UUIDs
-    let camera_uuid = Uuid::parse_str(&camera_id).map_err(|_| StatusCode::BAD_REQUEST)?;
-    let stream_uuid = Uuid::parse_str(&stream_id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let camera_uuid = Uuid::parse_str(&camera_id)
+        .map_err(|_| ApiError::new(StatusCode::BAD_REQUEST, "Invalid camera ID"))?;
+    let stream_uuid = Uuid::parse_str(&stream_id)
+        .map_err(|_| ApiError::new(StatusCode::BAD_REQUEST, "Invalid stream ID"))?;
 
     // Get the stream
     let stream = state
@@ -126,26 +146,20 @@ pub async fn start_recording(
         .await
         .map_err(|e| {
             error!("Failed to get stream: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Server error")
         })?
-        .ok_or(StatusCode::NOT_FOUND)?;
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "Stream not found"))?;
 
     // Check that the stream belongs to the camera
     if stream.camera_id != camera_uuid {
-        return Err(StatusCode::BAD_REQUEST);
+        return Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            "Stream does not belong to the camera",
+        ));
     }
 
     // Determine event type
-    let event_type = match &request.event_type {
-        Some(event_type_str) => match event_type_str.to_lowercase().as_str() {
-            "motion" => RecordingEventType::Motion,
-            "audio" => RecordingEventType::Audio,
-            "external" => RecordingEventType::External,
-            "analytics" => RecordingEventType::Analytics,
-            _ => RecordingEventType::Manual,
-        },
-        None => RecordingEventType::Manual,
-    };
+    let event_type = requested_event_type(request.event_type.as_deref())?;
 
     // Start recording based on event type
     let recording_id = if event_type == RecordingEventType::Manual {
@@ -190,11 +204,12 @@ pub async fn start_primary_recording(
     Path(camera_id): Path<String>,
     State(state): State<AppState>,
     Json(request): Json<StartRecordingRequest>,
-) -> Result<Json<RecordingResponse>, StatusCode> {
+) -> ApiResult<Json<RecordingResponse>> {
     // Convert AppState to RecordingApiState
     let state = app_state_to_recording_state(&state);
     // Parse camera UUID
-    let camera_uuid = Uuid::parse_str(&camera_id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let camera_uuid = Uuid::parse_str(&camera_id)
+        .map_err(|_| ApiError::new(StatusCode::BAD_REQUEST, "Invalid camera ID"))?;
 
     // Get the camera
     let camera = state
@@ -203,12 +218,14 @@ pub async fn start_primary_recording(
         .await
         .map_err(|e| {
             error!("Failed to get camera: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Server error")
         })?
-        .ok_or(StatusCode::NOT_FOUND)?;
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "Camera not found"))?;
 
     // Check if camera has a primary stream
-    let primary_stream_id = camera.primary_stream_id.ok_or(StatusCode::BAD_REQUEST)?;
+    let primary_stream_id = camera
+        .primary_stream_id
+        .ok_or_else(|| ApiError::new(StatusCode::BAD_REQUEST, "Camera has no primary stream"))?;
 
     // Get the primary stream
     let stream = state
@@ -217,21 +234,12 @@ pub async fn start_primary_recording(
         .await
         .map_err(|e| {
             error!("Failed to get stream: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Server error")
         })?
-        .ok_or(StatusCode::NOT_FOUND)?;
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "Stream not found"))?;
 
     // Determine event type
-    let event_type = match &request.event_type {
-        Some(event_type_str) => match event_type_str.to_lowercase().as_str() {
-            "motion" => RecordingEventType::Motion,
-            "audio" => RecordingEventType::Audio,
-            "external" => RecordingEventType::External,
-            "analytics" => RecordingEventType::Analytics,
-            _ => RecordingEventType::Manual,
-        },
-        None => RecordingEventType::Manual,
-    };
+    let event_type = requested_event_type(request.event_type.as_deref())?;
 
     // Start recording based on event type
     let recording_id = if event_type == RecordingEventType::Manual {
@@ -275,12 +283,14 @@ pub async fn start_primary_recording(
 pub async fn stop_recording(
     Path((camera_id, stream_id)): Path<(String, String)>,
     State(state): State<AppState>,
-) -> Result<Json<RecordingResponse>, StatusCode> {
+) -> ApiResult<Json<RecordingResponse>> {
     // Convert AppState to RecordingApiState
     let state = app_state_to_recording_state(&state);
     // Parse UUIDs
-    let camera_uuid = Uuid::parse_str(&camera_id).map_err(|_| StatusCode::BAD_REQUEST)?;
-    let stream_uuid = Uuid::parse_str(&stream_id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let camera_uuid = Uuid::parse_str(&camera_id)
+        .map_err(|_| ApiError::new(StatusCode::BAD_REQUEST, "Invalid camera ID"))?;
+    let stream_uuid = Uuid::parse_str(&stream_id)
+        .map_err(|_| ApiError::new(StatusCode::BAD_REQUEST, "Invalid stream ID"))?;
 
     // Get recording status to see what's active
     let all_status = state.recording_manager.get_recording_status().await;
@@ -343,11 +353,12 @@ pub async fn stop_recording(
 pub async fn stop_primary_recording(
     Path(camera_id): Path<String>,
     State(state): State<AppState>,
-) -> Result<Json<RecordingResponse>, StatusCode> {
+) -> ApiResult<Json<RecordingResponse>> {
     // Convert AppState to RecordingApiState
     // let recording_state = app_state_to_recording_state(&state);
     // Parse camera UUID
-    let camera_uuid = Uuid::parse_str(&camera_id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let camera_uuid = Uuid::parse_str(&camera_id)
+        .map_err(|_| ApiError::new(StatusCode::BAD_REQUEST, "Invalid camera ID"))?;
 
     // Get the camera
     let camera = state
@@ -356,12 +367,14 @@ pub async fn stop_primary_recording(
         .await
         .map_err(|e| {
             error!("Failed to get camera: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Server error")
         })?
-        .ok_or(StatusCode::NOT_FOUND)?;
+        .ok_or_else(|| ApiError::new(StatusCode::NOT_FOUND, "Camera not found"))?;
 
     // Check if camera has a primary stream
-    let primary_stream_id = camera.primary_stream_id.ok_or(StatusCode::BAD_REQUEST)?;
+    let primary_stream_id = camera
+        .primary_stream_id
+        .ok_or_else(|| ApiError::new(StatusCode::BAD_REQUEST, "Camera has no primary stream"))?;
 
     // Call the stream-specific stop method with camera ID and stream ID
     let path_params = (camera_id, primary_stream_id.to_string());
@@ -374,12 +387,14 @@ pub async fn stop_primary_recording(
 pub async fn get_recording_status(
     Path((camera_id, stream_id)): Path<(String, String)>,
     State(state): State<AppState>,
-) -> Result<Json<RecordingStatusResponse>, StatusCode> {
+) -> ApiResult<Json<RecordingStatusResponse>> {
     // Convert AppState to RecordingApiState
     let state = app_state_to_recording_state(&state);
     // Parse UUIDs
-    let camera_uuid = Uuid::parse_str(&camera_id).map_err(|_| StatusCode::BAD_REQUEST)?;
-    let stream_uuid = Uuid::parse_str(&stream_id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let camera_uuid = Uuid::parse_str(&camera_id)
+        .map_err(|_| ApiError::new(StatusCode::BAD_REQUEST, "Invalid camera ID"))?;
+    let stream_uuid = Uuid::parse_str(&stream_id)
+        .map_err(|_| ApiError::new(StatusCode::BAD_REQUEST, "Invalid stream ID"))?;
 
     // Get all recording status
     let all_status = state.recording_manager.get_recording_status().await;
@@ -412,11 +427,12 @@ pub async fn get_recording_status(
 pub async fn get_camera_recording_status(
     Path(camera_id): Path<String>,
     State(state): State<AppState>,
-) -> Result<Json<RecordingStatusResponse>, StatusCode> {
+) -> ApiResult<Json<RecordingStatusResponse>> {
     // Convert AppState to RecordingApiState
     let state = app_state_to_recording_state(&state);
     // Parse camera UUID
-    let camera_uuid = Uuid::parse_str(&camera_id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let camera_uuid = Uuid::parse_str(&camera_id)
+        .map_err(|_| ApiError::new(StatusCode::BAD_REQUEST, "Invalid camera ID"))?;
 
     // Get all recording status
     let all_status = state.recording_manager.get_recording_status().await;
@@ -448,7 +464,7 @@ pub async fn get_camera_recording_status(
 /// Get status of all active recordings
 pub async fn get_all_recording_status(
     State(state): State<AppState>,
-) -> Result<Json<RecordingStatusResponse>, StatusCode> {
+) -> ApiResult<Json<RecordingStatusResponse>> {
     // Convert AppState to RecordingApiState
     let state = app_state_to_recording_state(&state);
     // Get all recording status
@@ -481,7 +497,7 @@ pub async fn get_all_recording_status(
 pub async fn search_recordings(
     Query(params): Query<SearchParams>,
     State(state): State<AppState>,
-) -> Result<Json<HashMap<String, serde_json::Value>>, StatusCode> {
+) -> ApiResult<Json<HashMap<String, serde_json::Value>>> {
     // Convert AppState to RecordingApiState
     let state = app_state_to_recording_state(&state);
     // Build search query
@@ -493,6 +509,9 @@ pub async fn search_recordings(
         event_types: None,
         schedule_id: None,
         min_duration: None,
+        min_file_size: params.min_file_size,
+        max_file_size: params.max_file_size,
+        has_audio: params.has_audio,
         segment_id: params.segment_id,
         parent_recording_id: None,
         is_segment: params.is_segment,
@@ -537,32 +556,32 @@ pub async fn search_recordings(
 
     // Parse event type if provided
     if let Some(event_type_str) = params.event_type {
-        let event_type = match event_type_str.to_lowercase().as_str() {
-            "continuous" => RecordingEventType::Continuous,
-            "motion" => RecordingEventType::Motion,
-            "audio" => RecordingEventType::Audio,
-            "external" => RecordingEventType::External,
-            "manual" => RecordingEventType::Manual,
-            "analytics" => RecordingEventType::Analytics,
-            _ => return Err(StatusCode::BAD_REQUEST),
-        };
+        let event_type: RecordingEventType = event_type_str
+            .parse()
+            .map_err(|e: String| ApiError::new(StatusCode::BAD_REQUEST, e))?;
 
         query.event_types = Some(vec![event_type]);
     }
 
     // Execute search query
-    let recordings = state.recordings_repo.search(&query).await.map_err(|e| {
-        error!("Failed to search recordings: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
+    let (recordings, total) = state
+        .recordings_repo
+        .search_with_total(&query)
+        .await
+        .map_err(|e| {
+            error!("Failed to search recordings: {}", e);
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Server error")
+        })?;
 
     // Convert to response format (using serde_json for simplicity)
     let mut response = HashMap::new();
     response.insert("count".to_string(), serde_json::json!(recordings.len()));
+    response.insert("total".to_string(), serde_json::json!(total));
+    response.insert("limit".to_string(), serde_json::json!(query.limit));
+    response.insert("offset".to_string(), serde_json::json!(query.offset));
 
     // Convert recordings to JSON
-    let recordings_json =
-        serde_json::to_value(&recordings).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let recordings_json = serde_json::to_value(&recordings)?;
 
     response.insert("recordings".to_string(), recordings_json);
 
@@ -574,11 +593,12 @@ pub async fn prune_recordings(
     Path(camera_id): Path<String>,
     Query(params): Query<HashMap<String, String>>,
     State(state): State<AppState>,
-) -> Result<Json<CleanupResponse>, StatusCode> {
+) -> ApiResult<Json<CleanupResponse>> {
     // Convert AppState to RecordingApiState
     let state = app_state_to_recording_state(&state);
     // Parse camera UUID
-    let camera_uuid = Uuid::parse_str(&camera_id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let camera_uuid = Uuid::parse_str(&camera_id)
+        .map_err(|_| ApiError::new(StatusCode::BAD_REQUEST, "Invalid camera ID"))?;
 
     // Parse older_than_days parameter if provided
     let older_than_days = params
@@ -592,7 +612,7 @@ pub async fn prune_recordings(
         .await
         .map_err(|e| {
             error!("Failed to prune recordings: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Server error")
         })?;
 
     Ok(Json(CleanupResponse {
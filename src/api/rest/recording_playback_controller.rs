@@ -1,9 +1,12 @@
-use crate::api::rest::AppState;
-use crate::db::models::recording_models::{RecordingEventType, RecordingSearchQuery};
+use crate::api::rest::hls_service::{
+    file_error, file_response, job_error_response, playlist_response, serve_file, HlsVariant,
+};
+use crate::api::rest::{ApiError, ApiResult, AppState};
+use crate::db::models::recording_models::{Recording, RecordingEventType, RecordingSearchQuery};
 use crate::db::repositories::cameras::CamerasRepository;
 use crate::db::repositories::recordings::RecordingsRepository;
+use crate::recorder::archiver::archived_key;
 use crate::security::auth::AuthService;
-use axum::body::StreamBody;
 use axum::extract::{Path, Query, State};
 use axum::http::StatusCode;
 use axum::http::{header, HeaderMap};
@@ -11,15 +14,10 @@ use axum::response::{IntoResponse, Json};
 use axum::routing::get;
 use axum::Router;
 use chrono::{DateTime, Duration, TimeZone, Utc};
-use gstreamer as gst;
-use gstreamer::parse::launch;
-use gstreamer::prelude::*;
 use log::{debug, error, info};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use std::io::Write;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use tokio_util::io::ReaderStream;
 use uuid::Uuid;
 
 /// Timeline API state
@@ -38,8 +36,15 @@ pub struct TimelineParams {
     pub end_time: Option<String>,
     pub event_type: Option<String>,
     pub include_segments: Option<bool>,
+    /// Coalesce consecutive recordings separated by at most this many seconds
+    pub merge_gaps_under: Option<u64>,
+    /// Return one entry per recording row instead of merged spans
+    pub ungrouped: Option<bool>,
 }
 
+/// Gap tolerated between merged timeline recordings when none is requested
+const DEFAULT_MERGE_GAP_SECS: u64 = 1;
+
 /// Timeline segment response
 #[derive(Debug, Serialize)]
 pub struct TimelineSegment {
@@ -54,6 +59,120 @@ pub struct TimelineSegment {
     pub is_segment: bool,
     pub parent_id: Option<String>,
     pub segment_id: Option<u32>,
+    /// Recordings coalesced into this span, when more than one
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub child_ids: Vec<String>,
+}
+
+impl TimelineSegment {
+    fn from_recording(recording: &Recording) -> Self {
+        Self {
+            id: recording.id.to_string(),
+            camera_id: recording.camera_id.to_string(),
+            stream_id: recording.stream_id.to_string(),
+            start_time: recording.start_time.to_rfc3339(),
+            end_time: recording.end_time.map(|dt| dt.to_rfc3339()),
+            duration: recording.duration,
+            event_type: recording.event_type.to_string(),
+            file_path: recording.file_path.to_string_lossy().to_string(),
+            is_segment: recording.parent_recording_id.is_some(),
+            parent_id: recording.parent_recording_id.map(|id| id.to_string()),
+            segment_id: recording.segment_id,
+            child_ids: Vec::new(),
+        }
+    }
+}
+
+/// Merge recordings of the same stream and event type into continuous spans.
+///
+/// Recordings are joined when the next one starts no more than `max_gap` after the
+/// previous one ends. Spans are returned oldest first.
+fn merge_timeline_recordings(recordings: &[Recording], max_gap: Duration) -> Vec<TimelineSegment> {
+    let mut sorted: Vec<&Recording> = recordings.iter().collect();
+    sorted.sort_by_key(|r| (r.stream_id, r.event_type.to_string(), r.start_time));
+
+    // (first recording, span end, member ids)
+    let mut spans: Vec<(&Recording, DateTime<Utc>, Vec<Uuid>)> = Vec::new();
+    for recording in sorted {
+        let end = recording
+            .end_time
+            .unwrap_or(recording.start_time + Duration::seconds(recording.duration as i64));
+
+        if let Some((first, span_end, ids)) = spans.last_mut() {
+            if first.stream_id == recording.stream_id
+                && first.event_type == recording.event_type
+                && recording.start_time - *span_end <= max_gap
+            {
+                *span_end = (*span_end).max(end);
+                ids.push(recording.id);
+                continue;
+            }
+        }
+        spans.push((recording, end, vec![recording.id]));
+    }
+
+    let mut merged: Vec<TimelineSegment> = spans
+        .into_iter()
+        .map(|(first, end, ids)| {
+            let mut segment = TimelineSegment::from_recording(first);
+            if ids.len() > 1 {
+                segment.end_time = Some(end.to_rfc3339());
+                segment.duration = (end - first.start_time).num_seconds().max(0) as u64;
+                segment.is_segment = false;
+                segment.parent_id = None;
+                segment.segment_id = None;
+                segment.child_ids = ids.iter().map(|id| id.to_string()).collect();
+            }
+            segment
+        })
+        .collect();
+    merged.sort_by(|a, b| a.start_time.cmp(&b.start_time));
+    merged
+}
+
+/// Where a wall-clock time is found inside a recording's files
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SeekPosition {
+    /// Recording row (usually a segment) whose file holds the time
+    pub recording_id: Uuid,
+    pub segment_id: Option<u32>,
+    pub segment_start: DateTime<Utc>,
+    /// Seconds into the file
+    pub offset_secs: f64,
+    /// The time fell in a gap or before the recording; this is the next segment's start
+    pub in_gap: bool,
+}
+
+/// Find the segment holding `at` and the offset into its file. A time in a gap between
+/// segments maps to the start of the next one. `None` when `at` is past the last segment.
+pub fn seek_position(segments: &[Recording], at: DateTime<Utc>) -> Option<SeekPosition> {
+    let mut sorted: Vec<&Recording> = segments.iter().collect();
+    sorted.sort_by_key(|r| r.start_time);
+
+    for segment in sorted {
+        let end = segment
+            .end_time
+            .unwrap_or(segment.start_time + Duration::seconds(segment.duration as i64));
+        if at >= end {
+            continue;
+        }
+
+        let in_gap = at < segment.start_time;
+        let offset = if in_gap {
+            0.0
+        } else {
+            (at - segment.start_time).num_milliseconds() as f64 / 1000.0
+        };
+        return Some(SeekPosition {
+            recording_id: segment.id,
+            segment_id: segment.segment_id,
+            segment_start: segment.start_time,
+            offset_secs: offset,
+            in_gap,
+        });
+    }
+
+    None
 }
 
 /// Timeline response with all segments
@@ -95,31 +214,38 @@ pub fn create_router<S: Clone + Send + Sync + 'static>(state: S) -> Router<AppSt
 pub async fn get_video_recording(
     Path(recording_id): Path<String>,
     State(state): State<AppState>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
     info!("TRiggering video recordings...........................................................................");
     // Parse recording ID
     let uuid = match Uuid::parse_str(&recording_id) {
         Ok(id) => id,
-        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid recording ID").into_response(),
+        Err(_) => {
+            return ApiError::new(StatusCode::BAD_REQUEST, "Invalid recording ID").into_response()
+        }
     };
 
     let recording = match state.recordings_repo.get_by_id(&uuid).await {
         Ok(Some(recording)) => recording,
-        Ok(None) => return (StatusCode::NOT_FOUND, "Recording not found").into_response(),
+        Ok(None) => {
+            return ApiError::new(StatusCode::NOT_FOUND, "Recording not found").into_response()
+        }
         Err(e) => {
             error!("Error fetching recording: {}", e);
-            return (StatusCode::INTERNAL_SERVER_ERROR, "Server error").into_response();
+            return ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Server error")
+                .into_response();
         }
     };
 
-    // Here you would load the file and return it
-    let path = recording.file_path;
+    // Archived recordings are fetched back from the archive store; only files under the
+    // recordings or storage tier directory are served
+    let path = match state.hls.fetch_source(&recording).await {
+        Ok(path) => path,
+        Err(e) => return ApiError::from(e).into_response(),
+    };
     match tokio::fs::File::open(&path).await {
         Ok(file) => {
-            let stream = ReaderStream::new(file);
-            let body = StreamBody::new(stream);
-
-            let headers = HeaderMap::from_iter([
+            let response_headers = HeaderMap::from_iter([
                 (header::CONTENT_TYPE, "video/mp4".parse().unwrap()),
                 (
                     header::CONTENT_DISPOSITION,
@@ -129,9 +255,9 @@ pub async fn get_video_recording(
                 ),
             ]);
 
-            (StatusCode::OK, headers, body).into_response()
+            file_response(file, &headers, response_headers).await
         }
-        Err(_) => (StatusCode::NOT_FOUND, "Video recording not found").into_response(),
+        Err(e) => file_error(&path, &e, "Video recording").into_response(),
     }
 }
 
@@ -140,6 +266,8 @@ pub async fn get_video_recording(
 pub struct HlsQuery {
     #[serde(default)]
     playlist_type: String,
+    /// Restrict a variant playlist to recordings of one stream
+    stream_id: Option<Uuid>,
 }
 
 pub async fn get_hls_playlist(
@@ -151,7 +279,9 @@ pub async fn get_hls_playlist(
 
     let uuid = match Uuid::parse_str(&camera_id) {
         Ok(id) => id,
-        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid recording ID").into_response(),
+        Err(_) => {
+            return ApiError::new(StatusCode::BAD_REQUEST, "Invalid recording ID").into_response()
+        }
     };
 
     // Get all recordings for this camera, ordered by timestamp
@@ -163,514 +293,171 @@ pub async fn get_hls_playlist(
         Ok(recordings) => recordings,
         Err(e) => {
             error!("Error fetching recordings: {}", e);
-            return (StatusCode::INTERNAL_SERVER_ERROR, "Server error").into_response();
+            return ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Server error")
+                .into_response();
         }
     };
 
     if recordings.is_empty() {
-        return (StatusCode::NOT_FOUND, "No recordings found").into_response();
+        return ApiError::new(StatusCode::NOT_FOUND, "No recordings found").into_response();
     }
 
     match params.playlist_type.as_str() {
         // Master playlist
         "master" => {
-            // Create master playlist with one quality level
-            let playlist = format!(
-                "#EXTM3U\n\
-                 #EXT-X-VERSION:7\n\
-                 #EXT-X-STREAM-INF:BANDWIDTH=2000000,RESOLUTION=1280x720\n\
-                 /playback/cameras/{}/hls?playlist_type=variant\n",
-                camera_id
-            );
-
-            let headers = HeaderMap::from_iter([(
-                header::CONTENT_TYPE,
-                "application/vnd.apple.mpegurl".parse().unwrap(),
-            )]);
-
-            (StatusCode::OK, headers, playlist).into_response()
-        }
-
-        // Variant playlist
-        "variant" => {
-            // Create a variant playlist that references all segments
-            let mut playlist = String::from(
-                "#EXTM3U\n\
-                #EXT-X-VERSION:7\n\
-                #EXT-X-TARGETDURATION:4\n\
-                #EXT-X-MEDIA-SEQUENCE:0\n",
-            );
-
-            // Use first recording's ID to create init.mp4 URL
-            let first_recording = &recordings[0];
-
-            let recordings_base =
-                std::env::var("RECORDINGS_PATH").unwrap_or_else(|_| "/app/recordings".to_string());
-            let init_path = format!("{}/{}/init.mp4", recordings_base, first_recording.id);
-
-            // Create directory if it doesn't exist
-            if let Some(parent) = std::path::Path::new(&init_path).parent() {
-                if let Err(e) = std::fs::create_dir_all(parent) {
-                    error!("Failed to create directory: {}", e);
-                    return (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        "Failed to create output directory",
-                    )
-                        .into_response();
+            let camera = match state.cameras_repo.get_with_streams_by_id(&uuid).await {
+                Ok(Some(camera)) => camera,
+                Ok(None) => {
+                    return ApiError::new(StatusCode::NOT_FOUND, "Camera not found").into_response()
                 }
-            } else {
-                // This else clause belongs to the parent check, not the error check
-                error!("Invalid path: no parent directory");
-                return (StatusCode::INTERNAL_SERVER_ERROR, "Invalid output path").into_response();
-            }
-
-            info!(
-                "First Recording Path: {}",
-                first_recording.file_path.display()
-            );
-            // Try a simpler pipeline first - just extract the MP4 headers
-            let pipeline = match launch(&format!(
-                "filesrc location=\"{}\" ! \
-                qtdemux name=demux ! \
-                mp4mux fragment-duration=1000 ! \
-                filesink location=\"{}\"",
-                first_recording.file_path.display(),
-                init_path
-            )) {
-                Ok(p) => p,
                 Err(e) => {
-                    error!("GStreamer pipeline launch error: {}", e);
-                    // Try fallback pipeline if the first one fails
-                    match launch(&format!(
-                        "filesrc location=\"{}\" ! \
-                        decodebin ! \
-                        mp4mux ! \
-                        filesink location=\"{}\"",
-                        first_recording.file_path.display(),
-                        init_path
-                    )) {
-                        Ok(p) => p,
-                        Err(e) => {
-                            error!("Fallback GStreamer pipeline launch error: {}", e);
-                            return (
-                                StatusCode::INTERNAL_SERVER_ERROR,
-                                "Failed to create init.mp4",
-                            )
-                                .into_response();
-                        }
-                    }
+                    error!("Error fetching camera streams: {}", e);
+                    return ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Server error")
+                        .into_response();
                 }
             };
 
-            // Add timeout for pipeline operation
-            let timeout = std::time::Duration::from_secs(10);
-            let start_time = std::time::Instant::now();
-
-            // Replace ? operator with explicit match handling
-            match pipeline.set_state(gst::State::Playing) {
-                Ok(_) => {}
-                Err(e) => {
-                    error!("GStreamer state change error: {}", e);
-                    return (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        "Failed to start GStreamer pipeline",
+            // One variant per referenced stream, skipping streams with nothing recorded
+            let recorded: HashSet<Uuid> = recordings.iter().map(|r| r.stream_id).collect();
+            let mut references = camera.stream_references.clone();
+            references.sort_by_key(|r| r.display_order.unwrap_or(i32::MAX));
+            let variants: Vec<HlsVariant> = references
+                .iter()
+                .filter_map(|r| camera.streams.iter().find(|s| s.id == r.stream_id))
+                .filter(|s| recorded.contains(&s.id))
+                .map(|s| {
+                    HlsVariant::from_stream(
+                        s,
+                        format!(
+                            "/playback/cameras/{}/hls?playlist_type=variant&stream_id={}",
+                            camera_id, s.id
+                        ),
                     )
-                        .into_response();
-                }
-            }
-
-            let bus = pipeline.bus().unwrap();
-            let mut success = false;
-
-            // Wait for EOS or timeout
-            for msg in bus.iter_timed(gst::ClockTime::from_seconds(5)) {
-                match msg.view() {
-                    gst::MessageView::Eos(..) => {
-                        success = true;
-                        break;
-                    }
-                    gst::MessageView::Error(err) => {
-                        error!("GStreamer error: {}", err.error());
-                        break;
-                    }
-                    _ => {}
-                }
+                })
+                .collect();
 
-                if start_time.elapsed() > timeout {
-                    error!("GStreamer pipeline timeout");
-                    break;
-                }
-            }
-
-            // Always clean up the pipeline state
-            if let Err(e) = pipeline.set_state(gst::State::Null) {
-                error!("Failed to stop GStreamer pipeline: {}", e);
-            }
-
-            // Check if init file was created
-            if !success || !std::path::Path::new(&init_path).exists() {
-                error!("Failed to create initialization segment");
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Failed to create initialization segment",
-                )
+            if variants.is_empty() {
+                return ApiError::new(StatusCode::NOT_FOUND, "No recorded streams found")
                     .into_response();
             }
 
-            // Continue with playlist generation - properly reference init.mp4
-            // Using both absolute and relative URLs for better compatibility
-            playlist.push_str(&format!(
-                "#EXT-X-MAP:URI=\"/playback/{}/init.mp4\"\n",
-                first_recording.id
-            ));
-
-            // Check if there are discontinuities between segments
-            let mut previous_end_time = None;
-
-            for recording in recordings {
-                // Skip recordings without an end_time
-                if recording.end_time.is_none() {
-                    continue; // Skip to the next recording
-                }
-
-                // Add discontinuity marker if there's a gap between segments
-                if let Some(prev_end) = previous_end_time {
-                    // Assuming recording has start_time field
-                    if recording.start_time > prev_end + Duration::seconds(1) {
-                        // 1 second tolerance
-                        playlist.push_str("#EXT-X-DISCONTINUITY\n");
-                    }
-                }
+            playlist_response(state.hls.master_playlist(&variants))
+        }
 
-                // Add segment to playlist - using relative URL for better compatibility
-                playlist.push_str(&format!(
-                    "#EXTINF:4.0,\n\
-                    /playback/{}/hls?playlist_type=segment\n",
-                    recording.id
-                ));
-
-                // Update previous_end_time using the actual end_time from recording
-                // instead of calculating based on start_time
-                previous_end_time = recording.end_time;
-                // Assuming 30s segments
+        // Variant playlist
+        "variant" => {
+            let recordings: Vec<_> = match params.stream_id {
+                Some(stream_id) => recordings
+                    .into_iter()
+                    .filter(|r| r.stream_id == stream_id)
+                    .collect(),
+                None => recordings,
+            };
+            if recordings.is_empty() {
+                return ApiError::new(StatusCode::NOT_FOUND, "No recordings found for stream")
+                    .into_response();
             }
 
-            playlist.push_str("#EXT-X-ENDLIST\n");
-
-            let headers = HeaderMap::from_iter([(
-                header::CONTENT_TYPE,
-                "application/vnd.apple.mpegurl".parse().unwrap(),
-            )]);
-
-            (StatusCode::OK, headers, playlist).into_response()
+            playlist_response(state.hls.recordings_playlist(&recordings))
         }
 
-        _ => (StatusCode::BAD_REQUEST, "Invalid playlist type").into_response(),
+        _ => ApiError::new(StatusCode::BAD_REQUEST, "Invalid playlist type").into_response(),
     }
 }
-// Function to serve individual segments
+
 // Get initialization segment for HLS
 pub async fn get_init_segment(
     Path(recording_id): Path<String>,
     State(state): State<AppState>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
     info!("HLS init.mp4 request for recording: {}", recording_id);
 
     // Parse recording ID
     let uuid = match Uuid::parse_str(&recording_id) {
         Ok(id) => id,
-        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid recording ID").into_response(),
+        Err(_) => {
+            return ApiError::new(StatusCode::BAD_REQUEST, "Invalid recording ID").into_response()
+        }
     };
 
-    // Try to find existing init.mp4 file
-    let recordings_base =
-        std::env::var("RECORDINGS_PATH").unwrap_or_else(|_| "/app/recordings".to_string());
-    let init_path = format!("{}/{}/init.mp4", recordings_base, recording_id);
-
-    // Check if init.mp4 exists
-    match tokio::fs::File::open(&init_path).await {
-        Ok(file) => {
-            info!("Found existing init.mp4 for {}", recording_id);
-            let stream = ReaderStream::new(file);
-            let body = StreamBody::new(stream);
-
-            // For MP4 segments, use video/mp4 content type
-            let headers = HeaderMap::from_iter([
-                (header::CONTENT_TYPE, "video/mp4".parse().unwrap()),
-                (header::CACHE_CONTROL, "max-age=31536000".parse().unwrap()), // Cache for a year
-            ]);
-
-            (StatusCode::OK, headers, body).into_response()
+    let recording = match state.recordings_repo.get_by_id(&uuid).await {
+        Ok(Some(recording)) => recording,
+        Ok(None) => {
+            return ApiError::new(StatusCode::NOT_FOUND, "Recording not found").into_response()
         }
-        Err(_) => {
-            info!("Init.mp4 not found at {}, generating new one", init_path);
-
-            // Get recording details to generate init.mp4
-            let recording = match state.recordings_repo.get_by_id(&uuid).await {
-                Ok(Some(recording)) => recording,
-                Ok(None) => return (StatusCode::NOT_FOUND, "Recording not found").into_response(),
-                Err(e) => {
-                    error!("Error fetching recording: {}", e);
-                    return (StatusCode::INTERNAL_SERVER_ERROR, "Server error").into_response();
-                }
-            };
-
-            // Create directory if it doesn't exist
-            if let Some(parent) = std::path::Path::new(&init_path).parent() {
-                if let Err(e) = std::fs::create_dir_all(parent) {
-                    error!("Failed to create directory: {}", e);
-                    return (
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        "Failed to create output directory",
-                    )
-                        .into_response();
-                }
-            }
-
-            // Try to create initialization segment with GStreamer using multiple approaches
-            let mut success = false;
-            let mut pipeline_opt = None;
-
-            // Try the primary pipeline approach first
-            let primary_pipeline = format!(
-                "filesrc location=\"{}\" ! \
-                qtdemux name=demux ! \
-                mp4mux faststart=true streamable=true movie-timescale=90000 trak-timescale=90000 ! \
-                filesink location=\"{}\"",
-                recording.file_path.display(),
-                init_path
-            );
-
-            match launch(&primary_pipeline) {
-                Ok(p) => {
-                    info!("Successfully created primary pipeline for init.mp4");
-                    pipeline_opt = Some(p);
-                    success = true;
-                }
-                Err(e) => {
-                    error!("GStreamer primary pipeline launch error: {}", e);
-
-                    // Define fallback pipelines in order of preference
-                    let fallback_pipelines = vec![
-                        // Fallback 1: Try with decodebin
-                        format!(
-                            "filesrc location=\"{}\" ! \
-                            decodebin ! \
-                            mp4mux faststart=true streamable=true movie-timescale=90000 trak-timescale=90000 ! \
-                            filesink location=\"{}\"",
-                            recording.file_path.display(),
-                            init_path
-                        ),
-                        // Fallback 2: Try with full demux/decode/encode cycle
-                        format!(
-                            "filesrc location=\"{}\" ! \
-                            decodebin name=dec ! queue ! videoconvert ! x264enc ! \
-                            mp4mux faststart=true streamable=true ! \
-                            filesink location=\"{}\"",
-                            recording.file_path.display(),
-                            init_path
-                        ),
-                        // Fallback 3: Just create a minimal valid MP4 file
-                        format!(
-                            "videotestsrc num-buffers=1 ! video/x-raw,width=320,height=240 ! \
-                            videoconvert ! x264enc ! mp4mux ! \
-                            filesink location=\"{}\"",
-                            init_path
-                        )
-                    ];
-
-                    // Try each fallback pipeline in sequence
-                    for (i, pipeline_str) in fallback_pipelines.iter().enumerate() {
-                        match launch(pipeline_str) {
-                            Ok(p) => {
-                                info!(
-                                    "Successfully created fallback pipeline {} for init.mp4",
-                                    i + 1
-                                );
-                                pipeline_opt = Some(p);
-                                success = true;
-                                break;
-                            }
-                            Err(e) => {
-                                error!("Fallback pipeline {} launch error: {}", i + 1, e);
-                                // Continue to next fallback
-                            }
-                        }
-                    }
-
-                    // If all GStreamer pipelines failed, try creating a minimal file manually
-                    if !success {
-                        error!("All GStreamer pipelines failed. Attempting to manually create a minimal init.mp4");
-
-                        // Create a very basic standard MP4 initialization segment
-                        let init_contents: &[u8] = &[
-                            // MP4 file signature (ftyp box)
-                            0x00, 0x00, 0x00, 0x18, 0x66, 0x74, 0x79,
-                            0x70, // size=24, type="ftyp"
-                            0x69, 0x73, 0x6F, 0x6D, 0x00, 0x00, 0x00,
-                            0x01, // brand="isom", minor_version=1
-                            0x69, 0x73, 0x6F, 0x6D, 0x61, 0x76, 0x63,
-                            0x31, // compatible_brands="isomavc1"
-                            // Movie box header
-                            0x00, 0x00, 0x00, 0x08, 0x6D, 0x6F, 0x6F,
-                            0x76, // size=8, type="moov"
-                        ];
-
-                        // Write the minimal init segment to file
-                        if let Ok(mut file) = std::fs::File::create(&init_path) {
-                            if let Ok(_) = file.write_all(init_contents) {
-                                info!("Created minimal MP4 initialization file at {}", init_path);
-
-                                // Create a dummy pipeline for consistency
-                                if let Ok(p) = launch("fakesrc ! fakesink") {
-                                    pipeline_opt = Some(p);
-                                    success = true;
-                                }
-                            }
-                        }
-                    }
-                }
-            }
-
-            // If all approaches failed
-            if !success {
-                error!("Failed to create init.mp4 file through any method");
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Failed to create init.mp4 - all approaches failed",
-                )
-                    .into_response();
-            }
-
-            // Get the pipeline from the option
-            let pipeline = pipeline_opt.unwrap();
-
-            // Run the pipeline
-            if let Err(e) = pipeline.set_state(gst::State::Playing) {
-                error!("GStreamer state change error: {}", e);
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Failed to start GStreamer pipeline",
-                )
-                    .into_response();
-            }
-
-            let bus = pipeline.bus().unwrap();
-            let mut success = false;
-
-            // Wait for EOS or timeout
-            for msg in bus.iter_timed(gst::ClockTime::from_seconds(5)) {
-                match msg.view() {
-                    gst::MessageView::Eos(..) => {
-                        success = true;
-                        break;
-                    }
-                    gst::MessageView::Error(err) => {
-                        error!("GStreamer error: {}", err.error());
-                        break;
-                    }
-                    _ => {}
-                }
-            }
-
-            // Clean up the pipeline state
-            if let Err(e) = pipeline.set_state(gst::State::Null) {
-                error!("Failed to stop GStreamer pipeline: {}", e);
-            }
-
-            // Check if init file was created
-            if !success || !std::path::Path::new(&init_path).exists() {
-                error!("Failed to create initialization segment");
-                return (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Failed to create initialization segment",
-                )
-                    .into_response();
-            }
-
-            // Now serve the newly created init.mp4
-            match tokio::fs::File::open(&init_path).await {
-                Ok(file) => {
-                    let stream = ReaderStream::new(file);
-                    let body = StreamBody::new(stream);
-
-                    // For MP4 segments, use video/mp4 content type
-                    let headers = HeaderMap::from_iter([
-                        (header::CONTENT_TYPE, "video/mp4".parse().unwrap()),
-                        (header::CACHE_CONTROL, "max-age=31536000".parse().unwrap()), // Cache for a year
-                    ]);
-
-                    (StatusCode::OK, headers, body).into_response()
-                }
-                Err(_) => {
-                    (StatusCode::NOT_FOUND, "Failed to open generated init.mp4").into_response()
-                }
-            }
+        Err(e) => {
+            error!("Error fetching recording: {}", e);
+            return ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Server error")
+                .into_response();
         }
+    };
+
+    match state.hls.init_segment(&recording).await {
+        Ok(path) => serve_file(path, &headers).await,
+        Err(e) => job_error_response(e, "Failed to create initialization segment"),
     }
 }
 
+// Function to serve individual segments
 pub async fn get_hls_segment(
     Path(recording_id): Path<String>,
     State(state): State<AppState>,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
     info!("HLS segment request for recording: {}", recording_id);
 
     // Parse recording ID
     let uuid = match Uuid::parse_str(&recording_id) {
         Ok(id) => id,
-        Err(_) => return (StatusCode::BAD_REQUEST, "Invalid recording ID").into_response(),
+        Err(_) => {
+            return ApiError::new(StatusCode::BAD_REQUEST, "Invalid recording ID").into_response()
+        }
     };
 
     // Get recording details
     let recording = match state.recordings_repo.get_by_id(&uuid).await {
         Ok(Some(recording)) => recording,
-        Ok(None) => return (StatusCode::NOT_FOUND, "Recording not found").into_response(),
+        Ok(None) => {
+            return ApiError::new(StatusCode::NOT_FOUND, "Recording not found").into_response()
+        }
         Err(e) => {
             error!("Error fetching recording: {}", e);
-            return (StatusCode::INTERNAL_SERVER_ERROR, "Server error").into_response();
+            return ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Server error")
+                .into_response();
         }
     };
 
-    // Serve the MP4 file directly as an HLS segment
-    let path = recording.file_path;
-    match tokio::fs::File::open(&path).await {
-        Ok(file) => {
-            let stream = ReaderStream::new(file);
-            let body = StreamBody::new(stream);
-
-            // For MP4 segments, use video/mp4 content type with caching directives
-            let headers = HeaderMap::from_iter([
-                (header::CONTENT_TYPE, "video/mp4".parse().unwrap()),
-                (header::CACHE_CONTROL, "max-age=86400".parse().unwrap()), // Cache for a day
-            ]);
-
-            (StatusCode::OK, headers, body).into_response()
-        }
-        Err(_) => (StatusCode::NOT_FOUND, "Video segment not found").into_response(),
+    // The whole recording is served as one MPEG-TS segment
+    match state.hls.segment(&recording, 0.0, None).await {
+        Ok(path) => serve_file(path, &headers).await,
+        Err(e) => job_error_response(e, "Failed to generate segment"),
     }
 }
 /// Get timeline data for a camera over a specific time period
 pub async fn get_recording_timeline(
     Query(params): Query<TimelineParams>,
     State(state): State<AppState>,
-) -> Result<Json<TimelineResponse>, StatusCode> {
+) -> ApiResult<Json<TimelineResponse>> {
     // Convert AppState to TimelineApiState
     let state = app_state_to_timeline_state(&state);
 
     // Parse camera ID
     let camera_id = match Uuid::parse_str(&params.camera_id) {
         Ok(id) => id,
-        Err(_) => return Err(StatusCode::BAD_REQUEST),
+        Err(_) => return Err(ApiError::new(StatusCode::BAD_REQUEST, "Invalid camera ID")),
     };
 
     // Get the camera info
     let camera = match state.cameras_repo.get_by_id(&camera_id).await {
         Ok(Some(camera)) => camera,
-        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Ok(None) => return Err(ApiError::new(StatusCode::NOT_FOUND, "Camera not found")),
         Err(e) => {
             error!("Error fetching camera: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Server error",
+            ));
         }
     };
 
@@ -693,15 +480,9 @@ pub async fn get_recording_timeline(
 
     // Parse event type if provided
     let event_types = match params.event_type {
-        Some(ref type_str) => match type_str.to_lowercase().as_str() {
-            "continuous" => Some(vec![RecordingEventType::Continuous]),
-            "motion" => Some(vec![RecordingEventType::Motion]),
-            "audio" => Some(vec![RecordingEventType::Audio]),
-            "external" => Some(vec![RecordingEventType::External]),
-            "manual" => Some(vec![RecordingEventType::Manual]),
-            "analytics" => Some(vec![RecordingEventType::Analytics]),
-            _ => None,
-        },
+        Some(ref type_str) => Some(vec![type_str
+            .parse::<RecordingEventType>()
+            .map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, e))?]),
         None => None,
     };
 
@@ -714,6 +495,9 @@ pub async fn get_recording_timeline(
         event_types,
         schedule_id: None,
         min_duration: None,
+        min_file_size: None,
+        max_file_size: None,
+        has_audio: None,
         segment_id: None,
         parent_recording_id: None,
         is_segment: match params.include_segments {
@@ -729,43 +513,31 @@ pub async fn get_recording_timeline(
         Ok(recordings) => recordings,
         Err(e) => {
             error!("Error searching recordings: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Server error",
+            ));
         }
     };
 
-    // Convert to timeline segments
-    let mut segments = Vec::new();
-    let mut total_duration: u64 = 0;
-    for recording in recordings {
-        // Skip invalid recordings
-        if recording.duration == 0 || recording.file_path.to_str().is_none() {
-            continue;
-        }
-
-        let is_segment = recording.parent_recording_id.is_some();
-
-        // Exclude segments if requested
-        if params.include_segments == Some(false) && is_segment {
-            continue;
-        }
-
-        let segment = TimelineSegment {
-            id: recording.id.to_string(),
-            camera_id: recording.camera_id.to_string(),
-            stream_id: recording.stream_id.to_string(),
-            start_time: recording.start_time.to_rfc3339(),
-            end_time: recording.end_time.map(|dt| dt.to_rfc3339()),
-            duration: recording.duration as u64,
-            event_type: recording.event_type.to_string(),
-            file_path: recording.file_path.to_string_lossy().to_string(),
-            is_segment,
-            parent_id: recording.parent_recording_id.map(|id| id.to_string()),
-            segment_id: recording.segment_id,
-        };
+    // Skip invalid recordings, and segments if they were excluded
+    let recordings: Vec<Recording> = recordings
+        .into_iter()
+        .filter(|r| r.duration != 0 && r.file_path.to_str().is_some())
+        .filter(|r| params.include_segments != Some(false) || r.parent_recording_id.is_none())
+        .collect();
+    let total_duration: u64 = recordings.iter().map(|r| r.duration).sum();
 
-        total_duration += recording.duration as u64;
-        segments.push(segment);
-    }
+    // Convert to timeline segments
+    let segments = if params.ungrouped == Some(true) {
+        recordings
+            .iter()
+            .map(TimelineSegment::from_recording)
+            .collect()
+    } else {
+        let max_gap = params.merge_gaps_under.unwrap_or(DEFAULT_MERGE_GAP_SECS);
+        merge_timeline_recordings(&recordings, Duration::seconds(max_gap as i64))
+    };
 
     // Create timeline response
     let response = TimelineResponse {
@@ -783,24 +555,32 @@ pub async fn get_recording_timeline(
 /// Get detailed information about a specific recording for playback
 pub async fn get_recording_playback_info(
     Path(recording_id): Path<String>,
-    State(state): State<AppState>,
-) -> Result<Json<HashMap<String, serde_json::Value>>, StatusCode> {
+    State(app_state): State<AppState>,
+) -> ApiResult<Json<HashMap<String, serde_json::Value>>> {
     // Convert AppState to TimelineApiState
-    let state = app_state_to_timeline_state(&state);
+    let state = app_state_to_timeline_state(&app_state);
 
     // Parse recording ID
     let uuid = match Uuid::parse_str(&recording_id) {
         Ok(id) => id,
-        Err(_) => return Err(StatusCode::BAD_REQUEST),
+        Err(_) => {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "Invalid recording ID",
+            ))
+        }
     };
 
     // Get recording details
     let recording = match state.recordings_repo.get_by_id(&uuid).await {
         Ok(Some(recording)) => recording,
-        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Ok(None) => return Err(ApiError::new(StatusCode::NOT_FOUND, "Recording not found")),
         Err(e) => {
             error!("Error fetching recording: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Server error",
+            ));
         }
     };
 
@@ -809,19 +589,34 @@ pub async fn get_recording_playback_info(
         Ok(Some(camera)) => camera,
         Ok(None) => {
             error!("Camera not found for recording: {}", recording.id);
-            return Err(StatusCode::NOT_FOUND);
+            return Err(ApiError::new(StatusCode::NOT_FOUND, "Camera not found"));
         }
         Err(e) => {
             error!("Error fetching camera: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Server error",
+            ));
         }
     };
 
+    // Remote storage hands out a signed URL in place of the local path. Archived files are
+    // served from the archive store instead.
+    let archived = archived_key(&recording).is_some();
+    let signed_url = if archived {
+        None
+    } else {
+        app_state.storage_urls.url_for(&recording.file_path)?
+    };
+
     // Check if file exists
     let file_path = recording.file_path.clone();
-    if !file_path.exists() {
+    if signed_url.is_none() && !archived && !file_path.exists() {
         error!("Recording file not found: {}", file_path.display());
-        return Err(StatusCode::NOT_FOUND);
+        return Err(ApiError::new(
+            StatusCode::NOT_FOUND,
+            "Recording file not found",
+        ));
     }
 
     // Build response with all needed info
@@ -869,10 +664,21 @@ pub async fn get_recording_playback_info(
         "event_type".to_string(),
         serde_json::json!(recording.event_type.to_string()),
     );
-    response.insert(
-        "file_path".to_string(),
-        serde_json::json!(recording.file_path.to_string_lossy().to_string()),
-    );
+    match signed_url {
+        Some(signed) => {
+            response.insert("url".to_string(), serde_json::json!(signed.url));
+            response.insert(
+                "url_expires_at".to_string(),
+                serde_json::json!(signed.expires_at.to_rfc3339()),
+            );
+        }
+        None => {
+            response.insert(
+                "file_path".to_string(),
+                serde_json::json!(recording.file_path.to_string_lossy().to_string()),
+            );
+        }
+    }
 
     if let Some(segment_id) = recording.segment_id {
         response.insert("segment_id".to_string(), serde_json::json!(segment_id));
@@ -897,14 +703,14 @@ pub async fn get_recording_playback_info(
 pub async fn get_recordings_by_date(
     Query(params): Query<TimelineParams>,
     State(state): State<AppState>,
-) -> Result<Json<HashMap<String, serde_json::Value>>, StatusCode> {
+) -> ApiResult<Json<HashMap<String, serde_json::Value>>> {
     // Convert AppState to TimelineApiState
     let state = app_state_to_timeline_state(&state);
 
     // Parse camera ID
     let camera_id = match Uuid::parse_str(&params.camera_id) {
         Ok(id) => id,
-        Err(_) => return Err(StatusCode::BAD_REQUEST),
+        Err(_) => return Err(ApiError::new(StatusCode::BAD_REQUEST, "Invalid camera ID")),
     };
 
     // Determine time range (default to last 30 days if not specified)
@@ -933,6 +739,9 @@ pub async fn get_recordings_by_date(
         event_types: None, // All event types
         schedule_id: None,
         min_duration: None,
+        min_file_size: None,
+        max_file_size: None,
+        has_audio: None,
         segment_id: None,
         parent_recording_id: None,
         is_segment: Some(false), // Only parent recordings
@@ -945,7 +754,10 @@ pub async fn get_recordings_by_date(
         Ok(recordings) => recordings,
         Err(e) => {
             error!("Error searching recordings: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Server error",
+            ));
         }
     };
 
@@ -996,14 +808,19 @@ pub async fn get_recordings_by_date(
 pub async fn get_recording_segments(
     Path(parent_id): Path<String>,
     State(state): State<AppState>,
-) -> Result<Json<Vec<TimelineSegment>>, StatusCode> {
+) -> ApiResult<Json<Vec<TimelineSegment>>> {
     // Convert AppState to TimelineApiState
     let state = app_state_to_timeline_state(&state);
 
     // Parse parent recording ID
     let parent_uuid = match Uuid::parse_str(&parent_id) {
         Ok(id) => id,
-        Err(_) => return Err(StatusCode::BAD_REQUEST),
+        Err(_) => {
+            return Err(ApiError::new(
+                StatusCode::BAD_REQUEST,
+                "Invalid recording ID",
+            ))
+        }
     };
 
     // Create search query for segments of this parent
@@ -1015,6 +832,9 @@ pub async fn get_recording_segments(
         event_types: None,
         schedule_id: None,
         min_duration: None,
+        min_file_size: None,
+        max_file_size: None,
+        has_audio: None,
         segment_id: None,
         parent_recording_id: Some(parent_uuid),
         is_segment: Some(true), // Only segments
@@ -1027,27 +847,119 @@ pub async fn get_recording_segments(
         Ok(recordings) => recordings,
         Err(e) => {
             error!("Error searching for segments: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            return Err(ApiError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Server error",
+            ));
         }
     };
 
     // Convert to timeline segments
     let segments = segments
-        .into_iter()
+        .iter()
         .map(|recording| TimelineSegment {
-            id: recording.id.to_string(),
-            camera_id: recording.camera_id.to_string(),
-            stream_id: recording.stream_id.to_string(),
-            start_time: recording.start_time.to_rfc3339(),
-            end_time: recording.end_time.map(|dt| dt.to_rfc3339()),
-            duration: recording.duration as u64,
-            event_type: recording.event_type.to_string(),
-            file_path: recording.file_path.to_string_lossy().to_string(),
             is_segment: true,
             parent_id: Some(parent_uuid.to_string()),
-            segment_id: recording.segment_id,
+            ..TimelineSegment::from_recording(recording)
         })
         .collect();
 
     Ok(Json(segments))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn segment_at(stream_id: Uuid, start: DateTime<Utc>, duration: u64) -> Recording {
+        Recording {
+            id: Uuid::new_v4(),
+            camera_id: Uuid::new_v4(),
+            stream_id,
+            start_time: start,
+            end_time: Some(start + Duration::seconds(duration as i64)),
+            file_path: PathBuf::from("segment.mp4"),
+            file_size: 0,
+            duration,
+            format: "mp4".to_string(),
+            resolution: "1280x720".to_string(),
+            fps: 25,
+            event_type: RecordingEventType::Continuous,
+            metadata: None,
+            schedule_id: None,
+            segment_id: None,
+            parent_recording_id: None,
+        }
+    }
+
+    #[test]
+    fn back_to_back_segments_merge_into_one_span() {
+        let stream_id = Uuid::new_v4();
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+        // Newest first, as returned by the recordings search
+        let recordings: Vec<Recording> = (0..3)
+            .rev()
+            .map(|i| segment_at(stream_id, start + Duration::seconds(30 * i), 30))
+            .collect();
+
+        let spans = merge_timeline_recordings(&recordings, Duration::seconds(1));
+
+        assert_eq!(spans.len(), 1);
+        assert_eq!(spans[0].duration, 90);
+        assert_eq!(spans[0].start_time, start.to_rfc3339());
+        assert_eq!(
+            spans[0].end_time,
+            Some((start + Duration::seconds(90)).to_rfc3339())
+        );
+        assert_eq!(spans[0].child_ids.len(), 3);
+
+        // A gap larger than the threshold keeps the spans apart
+        let spans = merge_timeline_recordings(
+            &[
+                segment_at(stream_id, start, 30),
+                segment_at(stream_id, start + Duration::seconds(45), 30),
+            ],
+            Duration::seconds(1),
+        );
+        assert_eq!(spans.len(), 2);
+        assert!(spans.iter().all(|s| s.child_ids.is_empty()));
+    }
+
+    #[test]
+    fn seeking_finds_the_segment_and_skips_gaps() {
+        let stream_id = Uuid::new_v4();
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+        // 12:00:00-12:00:30, 12:00:30-12:01:00, then a gap until 12:02:00-12:02:30
+        let mut segments: Vec<Recording> = [0, 30, 120]
+            .into_iter()
+            .map(|offset| segment_at(stream_id, start + Duration::seconds(offset), 30))
+            .collect();
+        for (i, segment) in segments.iter_mut().enumerate() {
+            segment.segment_id = Some(i as u32);
+        }
+
+        let at = |secs: i64| start + Duration::milliseconds(secs * 1000 + 500);
+
+        let position = seek_position(&segments, at(42)).unwrap();
+        assert_eq!(position.recording_id, segments[1].id);
+        assert_eq!(position.offset_secs, 12.5);
+        assert!(!position.in_gap);
+
+        // A segment's end belongs to the next one
+        let position = seek_position(&segments, start + Duration::seconds(30)).unwrap();
+        assert_eq!((position.segment_id, position.offset_secs), (Some(1), 0.0));
+
+        // In the gap: the start of the next segment
+        let position = seek_position(&segments, at(90)).unwrap();
+        assert_eq!(position.recording_id, segments[2].id);
+        assert_eq!(position.offset_secs, 0.0);
+        assert!(position.in_gap);
+
+        let position = seek_position(&segments, start - Duration::seconds(5)).unwrap();
+        assert_eq!(position.recording_id, segments[0].id);
+        assert!(position.in_gap);
+
+        assert_eq!(seek_position(&segments, at(150)), None);
+    }
+}
@@ -0,0 +1,162 @@
+use crate::config::TlsConfig;
+use anyhow::{Context, Result};
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use axum_server::Handle;
+use log::info;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// Load the certificate chain and private key named in `tls`
+pub async fn load(tls: &TlsConfig) -> Result<RustlsConfig> {
+    RustlsConfig::from_pem_file(&tls.cert_path, &tls.key_path)
+        .await
+        .with_context(|| {
+            format!(
+                "Failed to load TLS certificate {} and key {}",
+                tls.cert_path.display(),
+                tls.key_path.display()
+            )
+        })
+}
+
+/// Re-read the certificate and key on SIGHUP, so renewed certificates are served without a
+/// restart. New connections pick them up; a pair that fails to load leaves the current one
+/// in use.
+#[cfg(unix)]
+pub fn reload_on_sighup(rustls: RustlsConfig, tls: TlsConfig) {
+    use log::{error, warn};
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sighup = match signal(SignalKind::hangup()) {
+        Ok(sighup) => sighup,
+        Err(e) => {
+            warn!(
+                "Failed to install SIGHUP handler, TLS certificate reload is disabled: {}",
+                e
+            );
+            return;
+        }
+    };
+
+    tokio::spawn(async move {
+        while sighup.recv().await.is_some() {
+            match rustls
+                .reload_from_pem_file(&tls.cert_path, &tls.key_path)
+                .await
+            {
+                Ok(()) => info!("Reloaded TLS certificate {}", tls.cert_path.display()),
+                Err(e) => error!(
+                    "Failed to reload TLS certificate {}, keeping the current one: {}",
+                    tls.cert_path.display(),
+                    e
+                ),
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn reload_on_sighup(_rustls: RustlsConfig, _tls: TlsConfig) {}
+
+/// Serve `app` over HTTPS on `listener` until `shutdown` resolves, then stop accepting
+/// connections and wait up to `grace` for open requests to finish
+pub async fn serve<F>(
+    listener: std::net::TcpListener,
+    rustls: RustlsConfig,
+    app: Router,
+    shutdown: F,
+    grace: Duration,
+) -> Result<()>
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    let handle = Handle::new();
+    tokio::spawn({
+        let handle = handle.clone();
+        async move {
+            shutdown.await;
+            info!("API server draining open requests");
+            handle.graceful_shutdown(Some(grace));
+        }
+    });
+
+    axum_server::from_tcp_rustls(listener, rustls)
+        .handle(handle)
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::get;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_rustls::rustls::{Certificate, ClientConfig, RootCertStore, ServerName};
+    use tokio_rustls::TlsConnector;
+
+    #[tokio::test]
+    async fn self_signed_certificate_completes_a_handshake() -> Result<()> {
+        let rcgen::CertifiedKey { cert, key_pair } =
+            rcgen::generate_simple_self_signed(vec!["localhost".to_string()])?;
+        let dir = std::env::temp_dir().join(format!("tls-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir)?;
+        let tls = TlsConfig {
+            cert_path: dir.join("cert.pem"),
+            key_path: dir.join("key.pem"),
+        };
+        std::fs::write(&tls.cert_path, cert.pem())?;
+        std::fs::write(&tls.key_path, key_pair.serialize_pem())?;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let app = Router::new().route("/", get(|| async { "hello over tls" }));
+        let server = tokio::spawn(serve(
+            listener,
+            load(&tls).await?,
+            app,
+            std::future::pending(),
+            Duration::from_secs(1),
+        ));
+
+        // A client trusting only the self-signed certificate
+        let mut roots = RootCertStore::empty();
+        roots.add(&Certificate(cert.der().to_vec()))?;
+        let client = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let connector = TlsConnector::from(Arc::new(client));
+        let tcp = tokio::net::TcpStream::connect(addr).await?;
+        let mut stream = connector
+            .connect(ServerName::try_from("localhost")?, tcp)
+            .await?;
+
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.ends_with("hello over tls"), "{}", response);
+
+        // A client without the certificate refuses the server
+        let strict = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(RootCertStore::empty())
+            .with_no_client_auth();
+        let tcp = tokio::net::TcpStream::connect(addr).await?;
+        let refused = TlsConnector::from(Arc::new(strict))
+            .connect(ServerName::try_from("localhost")?, tcp)
+            .await;
+        assert!(refused.is_err());
+
+        server.abort();
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 use anyhow::Result;
 use axum::{
     extract::{Path, State},
+    http::StatusCode,
     Json,
 };
 use std::sync::Arc;
@@ -16,6 +17,7 @@ use webrtc::api::{
 use webrtc::ice_transport::ice_candidate::RTCIceCandidateInit;
 use webrtc::ice_transport::ice_server::RTCIceServer;
 use webrtc::peer_connection::configuration::RTCConfiguration;
+use webrtc::peer_connection::peer_connection_state::RTCPeerConnectionState;
 use webrtc::peer_connection::RTCPeerConnection;
 use webrtc::peer_connection::sdp::sdp_type::RTCSdpType;
 use webrtc::peer_connection::sdp::session_description::RTCSessionDescription;
@@ -26,28 +28,123 @@ use webrtc::rtp_transceiver::rtp_codec::RTCRtpCodecCapability;
 use webrtc::track::track_local::track_local_static_sample::TrackLocalStaticSample;
 use webrtc::track::track_local::TrackLocal;
 use webrtc::media::Sample;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
 use std::collections::HashMap;
 use gstreamer as gst;
 use gstreamer::prelude::*;
 use gstreamer_app as gst_app;
 
 // Import your custom types (make sure these paths match your project structure)
+use crate::api::rest::{ApiError, ApiResult};
+use crate::config::WebRtcConfig;
+use crate::db::repositories::cameras::CamerasRepository;
+use crate::recorder::record::{codec_from_rtp_encoding, make_element};
+use crate::recorder::transcode::{build_h264_transcode_chain, TranscodeSettings};
 use crate::stream_manager::stream_manager::StreamManager;
 
 pub struct WebRTCState {
     pub pool: Arc<PgPool>,
     pub stream_manager: Arc<StreamManager>,
+    // ICE (STUN/TURN) servers offered to clients and used by our peer connections
+    config: WebRtcConfig,
     // Track active peer connections
     peer_connections: Arc<tokio::sync::Mutex<HashMap<String, Arc<RTCPeerConnection>>>>,
+    // Last offer/ICE/keepalive per session, for reaping abandoned sessions
+    last_activity: Arc<tokio::sync::Mutex<HashMap<String, Instant>>>,
 }
 
 impl WebRTCState {
-    pub fn new(pool: Arc<PgPool>, stream_manager: Arc<StreamManager>) -> Self {
+    pub fn new(
+        pool: Arc<PgPool>,
+        stream_manager: Arc<StreamManager>,
+        config: WebRtcConfig,
+    ) -> Self {
         Self {
             pool,
             stream_manager,
+            config,
             peer_connections: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+            last_activity: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Record client activity for a session
+    async fn touch(&self, session_id: &str) {
+        self.last_activity
+            .lock()
+            .await
+            .insert(session_id.to_string(), Instant::now());
+    }
+
+    /// ICE servers for a session. TURN servers get time-limited credentials when a shared
+    /// secret is configured, otherwise the static username/credential.
+    fn ice_servers(&self, session_id: &str) -> Vec<WebRTCIceServer> {
+        let (stun_urls, turn_urls): (Vec<String>, Vec<String>) = self
+            .config
+            .ice_urls
+            .iter()
+            .cloned()
+            .partition(|url| !(url.starts_with("turn:") || url.starts_with("turns:")));
+
+        let mut servers = Vec::new();
+        if !stun_urls.is_empty() {
+            servers.push(WebRTCIceServer {
+                urls: stun_urls,
+                username: None,
+                credential: None,
+            });
+        }
+
+        if !turn_urls.is_empty() {
+            let (username, credential) = match &self.config.turn_shared_secret {
+                Some(secret) => {
+                    let expires_at = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs()
+                        + self.config.turn_credential_ttl_secs;
+                    let (username, credential) =
+                        turn_rest_credentials(secret, expires_at, session_id);
+                    (Some(username), Some(credential))
+                }
+                None => (
+                    self.config.turn_username.clone(),
+                    self.config.turn_credential.clone(),
+                ),
+            };
+            servers.push(WebRTCIceServer {
+                urls: turn_urls,
+                username,
+                credential,
+            });
+        }
+
+        servers
+    }
+}
+
+/// Time-limited TURN credentials per the TURN REST API draft (as implemented by coturn's
+/// `use-auth-secret`): username is `<expiry unix time>:<user>`, the credential is
+/// base64(HMAC-SHA1(secret, username)).
+fn turn_rest_credentials(secret: &str, expires_at: u64, user: &str) -> (String, String) {
+    let username = format!("{}:{}", expires_at, user);
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(username.as_bytes());
+    let credential = base64::engine::general_purpose::STANDARD.encode(mac.finalize().into_bytes());
+    (username, credential)
+}
+
+impl From<&WebRTCIceServer> for RTCIceServer {
+    fn from(server: &WebRTCIceServer) -> Self {
+        RTCIceServer {
+            urls: server.urls.clone(),
+            username: server.username.clone().unwrap_or_default(),
+            credential: server.credential.clone().unwrap_or_default(),
+            ..Default::default()
         }
     }
 }
@@ -94,7 +191,7 @@ pub struct WebRTCIceCandidateRequest {
 
 // Create a new WebRTC session
 pub async fn create_webrtc_session(
-    State(_state): State<Arc<WebRTCState>>,
+    State(state): State<Arc<WebRTCState>>,
     Json(request): Json<WebRTCSessionRequest>,
 ) -> Json<WebRTCSessionResponse> {
     info!("Creating WebRTC session for camera: {}", request.stream_id);
@@ -102,14 +199,9 @@ pub async fn create_webrtc_session(
     // Generate a unique session ID
     let session_id = Uuid::new_v4().to_string();
     
-    // Define ICE servers (STUN and TURN configurations)
-    let ice_servers = vec![
-        WebRTCIceServer {
-            urls: vec!["stun:stun.l.google.com:19302".to_string()],
-            username: None,
-            credential: None,
-        },
-    ];
+    // ICE servers (STUN and TURN) from config, with credentials for this session
+    let ice_servers = state.ice_servers(&session_id);
+    state.touch(&session_id).await;
     
     // Return the session information
     Json(WebRTCSessionResponse {
@@ -118,19 +210,113 @@ pub async fn create_webrtc_session(
     })
 }
 
+/// Roles in the names (`webrtc_{role}_{session}`) of the elements a session adds to a pipeline
+const WEBRTC_ELEMENT_ROLES: [&str; 7] =
+    ["queue", "depay", "decode", "convert", "encode", "parse", "appsink"];
+
+/// Elements between a session's queue and its appsink, with the appsink caps and the codec
+/// of the track they feed
+struct WebRtcVideoPath {
+    elements: Vec<gst::Element>,
+    caps: gst::Caps,
+    codec: RTCRtpCodecCapability,
+}
+
+fn h264_track_codec() -> RTCRtpCodecCapability {
+    RTCRtpCodecCapability {
+        mime_type: "video/h264".to_owned(),
+        clock_rate: 90000,
+        channels: 1,
+        sdp_fmtp_line: "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42e01f".to_owned(),
+        ..Default::default()
+    }
+}
+
+fn h264_caps() -> gst::Caps {
+    gst::Caps::builder("video/x-h264")
+        .field("stream-format", "byte-stream")
+        .field("alignment", "au")
+        .build()
+}
+
+/// Pick the WebRTC path for a stream's video codec. H264, VP8 and VP9 are depayloaded and
+/// sent as is; AV1 is transcoded to H264 since few browsers decode it. Anything else is
+/// treated as H264, as before codecs were detected.
+fn build_webrtc_video_path<F>(make: &F, codec: &str, element_suffix: &str) -> Result<WebRtcVideoPath>
+where
+    F: Fn(&str, String) -> Result<gst::Element>,
+{
+    let name = |role: &str| format!("webrtc_{}_{}", role, element_suffix);
+    let vpx_path = |depay_factory: &str, caps_name: &str, mime_type: &str, fmtp: &str| {
+        Ok::<_, anyhow::Error>(WebRtcVideoPath {
+            elements: vec![make(depay_factory, name("depay"))?],
+            caps: gst::Caps::builder(caps_name).build(),
+            codec: RTCRtpCodecCapability {
+                mime_type: mime_type.to_owned(),
+                clock_rate: 90000,
+                sdp_fmtp_line: fmtp.to_owned(),
+                ..Default::default()
+            },
+        })
+    };
+
+    match codec {
+        "vp8" => vpx_path("rtpvp8depay", "video/x-vp8", "video/VP8", ""),
+        "vp9" => vpx_path("rtpvp9depay", "video/x-vp9", "video/VP9", "profile-id=0"),
+        "av1" => Ok(WebRtcVideoPath {
+            elements: build_h264_transcode_chain(make, codec, &TranscodeSettings::default(), &name)?,
+            caps: h264_caps(),
+            codec: h264_track_codec(),
+        }),
+        _ => Ok(WebRtcVideoPath {
+            elements: vec![
+                make("rtph264depay", name("depay"))?,
+                make("h264parse", name("parse"))?,
+            ],
+            caps: h264_caps(),
+            codec: h264_track_codec(),
+        }),
+    }
+}
+
+/// Video codec of a stream: the negotiated caps if it is running, else the stored codec
+async fn stream_video_codec(state: &WebRTCState, stream_id: &Uuid) -> String {
+    if let Some(codec) = state
+        .stream_manager
+        .get_stream_status(&stream_id.to_string())
+        .ok()
+        .and_then(|status| status.codec)
+    {
+        return codec_from_rtp_encoding(&codec);
+    }
+
+    match CamerasRepository::new(state.pool.clone())
+        .get_stream_by_id(stream_id)
+        .await
+    {
+        Ok(Some(stream)) => codec_from_rtp_encoding(stream.codec.as_deref().unwrap_or("h264")),
+        Ok(None) => "h264".to_string(),
+        Err(e) => {
+            warn!("Failed to look up codec of stream {}: {}", stream_id, e);
+            "h264".to_string()
+        }
+    }
+}
+
 // Process an SDP offer from the client
 pub async fn process_webrtc_offer(
     State(state): State<Arc<WebRTCState>>,
     Json(request): Json<WebRTCOfferRequest>,
-) -> Result<Json<WebRTCAnswerResponse>, axum::http::StatusCode> {
+) -> ApiResult<Json<WebRTCAnswerResponse>> {
     info!("Processing WebRTC offer for session: {}", request.session_id);
+    state.touch(&request.session_id).await;
 
     // Get the existing stream using stream_id from StreamManager
     let stream_id = request.stream_id.to_string();
     let (pipeline, tee, _, _) = state.stream_manager.get_stream_access(&stream_id)
         .map_err(|e| {
             error!("Failed to get stream access: {}", e);
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR
+            ApiError::from(e)
         })?;
 
     // Check pipeline state
@@ -149,26 +335,21 @@ pub async fn process_webrtc_offer(
         .build()
         .map_err(|e| {
             error!("Failed to create queue: {}", e);
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR
-        })?;
-    
-    let depay = gst::ElementFactory::make("rtph264depay")
-        .name(&format!("webrtc_depay_{}", element_suffix))
-        .build()
-        .map_err(|e| {
-            error!("Failed to create depay: {}", e);
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to create queue")
         })?;
     
-    let parse = gst::ElementFactory::make("h264parse")
-        .name(&format!("webrtc_parse_{}", element_suffix))
-        .build()
+    let codec = stream_video_codec(&state, &request.stream_id).await;
+    let video_path = build_webrtc_video_path(&make_element, &codec, element_suffix)
         .map_err(|e| {
-            error!("Failed to create parse: {}", e);
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR
+            error!("Failed to build WebRTC path for {} video: {}", codec, e);
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to build WebRTC video path")
         })?;
-    
-    // Create appsink to capture H.264 packets
+    info!(
+        "Sending {} video of stream {} as {}",
+        codec, stream_id, video_path.codec.mime_type
+    );
+
+    // Create appsink to capture encoded frames
     let appsink = gst_app::AppSink::builder()
         .name(&format!("webrtc_appsink_{}", element_suffix))
         .max_buffers(1)
@@ -180,38 +361,38 @@ pub async fn process_webrtc_offer(
         .build();
     
     // Set caps on appsink
-    let caps = gst::Caps::builder("video/x-h264")
-        .field("stream-format", "byte-stream")
-        .field("alignment", "au")
-        .build();
-    appsink.set_caps(Some(&caps));
+    appsink.set_caps(Some(&video_path.caps));
+
+    let mut branch: Vec<&gst::Element> = vec![&queue];
+    branch.extend(video_path.elements.iter());
+    branch.push(appsink.upcast_ref());
 
     // Add elements to pipeline
-    pipeline.add_many(&[&queue, &depay, &parse, appsink.upcast_ref()])
+    pipeline.add_many(&branch)
         .map_err(|e| {
             error!("Failed to add elements to pipeline: {}", e);
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to add elements to pipeline")
         })?;
     
     // Link GStreamer elements
-    gst::Element::link_many(&[&queue, &depay, &parse, appsink.upcast_ref()])
+    gst::Element::link_many(&branch)
         .map_err(|e| {
             error!("Failed to link elements: {}", e);
             // If linking fails, remove the elements we added
-            let _ = pipeline.remove_many(&[&queue, &depay, &parse, appsink.upcast_ref()]);
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR
+            let _ = pipeline.remove_many(&branch);
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to link elements")
         })?;
     
     // Connect to tee
     let tee_src_pad = tee.request_pad_simple("src_%u")
         .ok_or_else(|| {
             error!("Failed to get tee src pad");
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get tee src pad")
         })?;
     let queue_sink_pad = queue.static_pad("sink")
         .ok_or_else(|| {
             error!("Failed to get queue sink pad");
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to get queue sink pad")
         })?;
     
     // Link the tee to the queue
@@ -219,16 +400,16 @@ pub async fn process_webrtc_offer(
         .map_err(|e| {
             error!("Failed to link tee to queue: {:?}", e);
             // Clean up on error
-            let _ = pipeline.remove_many(&[&queue, &depay, &parse, appsink.upcast_ref()]);
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR
+            let _ = pipeline.remove_many(&branch);
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to link tee to queue")
         })?;
     
     // Sync state with parent
-    for element in [&queue, &depay, &parse, appsink.upcast_ref()] {
+    for element in &branch {
         element.sync_state_with_parent()
             .map_err(|e| {
                 error!("Failed to sync element state: {}", e);
-                axum::http::StatusCode::INTERNAL_SERVER_ERROR
+                ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to sync element state")
             })?;
         
         // Debug element state
@@ -243,21 +424,20 @@ pub async fn process_webrtc_offer(
     media_engine.register_default_codecs()
         .map_err(|e| {
             error!("Failed to register codecs: {}", e);
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to register codecs")
         })?;
     
     let api = APIBuilder::new()
         .with_media_engine(media_engine)
         .build();
     
-    // Create ICE server configuration
+    // Create ICE server configuration, using the same servers the client was given
     let config = RTCConfiguration {
-        ice_servers: vec![
-            RTCIceServer {
-                urls: vec!["stun:stun.l.google.com:19302".to_string()],
-                ..Default::default()
-            },
-        ],
+        ice_servers: state
+            .ice_servers(&request.session_id)
+            .iter()
+            .map(RTCIceServer::from)
+            .collect(),
         ice_transport_policy: RTCIceTransportPolicy::All,
         bundle_policy: RTCBundlePolicy::MaxBundle,
         rtcp_mux_policy: RTCRtcpMuxPolicy::Require,
@@ -268,18 +448,12 @@ pub async fn process_webrtc_offer(
     let peer_connection = Arc::new(api.new_peer_connection(config).await
         .map_err(|e| {
             error!("Failed to create peer connection: {}", e);
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to create peer connection")
         })?);
     
     // Create a video track for the camera stream
     let video_track = Arc::new(TrackLocalStaticSample::new(
-        RTCRtpCodecCapability {
-            mime_type: "video/h264".to_owned(),
-            clock_rate: 90000,
-            channels: 1,
-            sdp_fmtp_line: "level-asymmetry-allowed=1;packetization-mode=1;profile-level-id=42e01f".to_owned(),
-            ..Default::default()
-        },
+        video_path.codec.clone(),
         format!("video-{}", request.session_id),
         "camera-stream-video".to_owned(),
     ));
@@ -290,41 +464,41 @@ pub async fn process_webrtc_offer(
         .await
         .map_err(|e| {
             error!("Failed to add video track: {}", e);
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to add video track")
         })?;
     
     // Parse and set the remote SDP
     let offer_sdp_type = match request.type_field.as_str() {
         "offer" => RTCSdpType::Offer,
-        _ => return Err(axum::http::StatusCode::BAD_REQUEST),
+        _ => return Err(ApiError::new(StatusCode::BAD_REQUEST, "Only SDP offers are accepted")),
     };
     
     let offer = match offer_sdp_type {
         RTCSdpType::Offer => RTCSessionDescription::offer(request.sdp)
             .map_err(|e| {
                 error!("Failed to create offer: {}", e);
-                axum::http::StatusCode::BAD_REQUEST
+                ApiError::new(StatusCode::BAD_REQUEST, "Invalid SDP offer")
             })?,
-        _ => return Err(axum::http::StatusCode::BAD_REQUEST),
+        _ => return Err(ApiError::new(StatusCode::BAD_REQUEST, "Only SDP offers are accepted")),
     };
     
     peer_connection.set_remote_description(offer).await
         .map_err(|e| {
             error!("Failed to set remote description: {}", e);
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to set remote description")
         })?;
     
     // Create and set answer
     let answer = peer_connection.create_answer(None).await
         .map_err(|e| {
             error!("Failed to create answer: {}", e);
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to create answer")
         })?;
     
     peer_connection.set_local_description(answer.clone()).await
         .map_err(|e| {
             error!("Failed to set local description: {}", e);
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to set local description")
         })?;
     
     // Store the peer connection
@@ -348,7 +522,7 @@ pub async fn process_webrtc_offer(
         info!("Sample receiver task ended for session {}", session_id_for_receiver);
     });
     
-    // Set up appsink to send H.264 packets to WebRTC via the channel
+    // Set up appsink to send encoded frames to WebRTC via the channel
     let session_id_for_debug = request.session_id.clone();
     let session_id_for_debug_2 = request.session_id.clone();
     let mut sample_count = 0u64;
@@ -495,9 +669,10 @@ pub async fn process_webrtc_offer(
 pub async fn add_ice_candidate(
     State(state): State<Arc<WebRTCState>>,
     Json(request): Json<WebRTCIceCandidateRequest>,
-) -> Result<Json<JsonValue>, axum::http::StatusCode> {
+) -> ApiResult<Json<JsonValue>> {
     info!("Adding ICE candidate for session: {}", request.session_id);
-    
+    state.touch(&request.session_id).await;
+
     let peer_connection = {
         let peer_connections = state.peer_connections.lock().await;
         
@@ -521,24 +696,107 @@ pub async fn add_ice_candidate(
     peer_connection.add_ice_candidate(candidate_init).await
         .map_err(|e| {
             error!("Failed to add ICE candidate: {}", e);
-            axum::http::StatusCode::INTERNAL_SERVER_ERROR
+            ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "Failed to add ICE candidate")
         })?;
     
     Ok(Json(json!({ "success": true })))
 }
 
+// Keep a WebRTC session alive while the client is still watching
+pub async fn keepalive_webrtc_session(
+    State(state): State<Arc<WebRTCState>>,
+    Path(session_id): Path<String>,
+) -> ApiResult<Json<JsonValue>> {
+    let mut last_activity = state.last_activity.lock().await;
+    match last_activity.get_mut(&session_id) {
+        Some(last_seen) => {
+            *last_seen = Instant::now();
+            Ok(Json(json!({ "success": true })))
+        }
+        None => {
+            debug!("Keepalive for unknown WebRTC session: {}", session_id);
+            Err(ApiError::new(StatusCode::NOT_FOUND, "Unknown WebRTC session"))
+        }
+    }
+}
+
 // Close a WebRTC session
 pub async fn close_webrtc_session(
     State(state): State<Arc<WebRTCState>>,
     Path(session_id): Path<String>,
 ) -> Json<JsonValue> {
     info!("Closing WebRTC session: {}", session_id);
-    
+
+    teardown_session(&state, &session_id).await;
+
+    info!("WebRTC session closed: {}", session_id);
+    Json(json!({ "success": true }))
+}
+
+/// Tear down every session that has been idle for longer than `timeout`. A session whose
+/// peer connection is still connected counts as active, keepalives or not.
+/// Returns the ids of the sessions that were closed.
+pub async fn reap_idle_sessions(state: &Arc<WebRTCState>, timeout: Duration) -> Vec<String> {
+    let idle: Vec<String> = {
+        let last_activity = state.last_activity.lock().await;
+        last_activity
+            .iter()
+            .filter(|(_, last_seen)| last_seen.elapsed() > timeout)
+            .map(|(session_id, _)| session_id.clone())
+            .collect()
+    };
+
+    let mut reaped = Vec::new();
+    for session_id in idle {
+        let peer_connection = state
+            .peer_connections
+            .lock()
+            .await
+            .get(&session_id)
+            .cloned();
+        if peer_connection
+            .is_some_and(|pc| pc.connection_state() == RTCPeerConnectionState::Connected)
+        {
+            state.touch(&session_id).await;
+            continue;
+        }
+
+        info!("Reaping idle WebRTC session: {}", session_id);
+        teardown_session(state, &session_id).await;
+        reaped.push(session_id);
+    }
+
+    reaped
+}
+
+/// Periodically reap sessions whose client went away without closing them.
+/// The task ends once the state is dropped.
+pub fn spawn_session_reaper(state: &Arc<WebRTCState>) {
+    let timeout = Duration::from_secs(state.config.session_timeout_secs);
+    let check_interval = (timeout / 4).max(Duration::from_secs(1));
+    let state = Arc::downgrade(state);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(check_interval);
+        loop {
+            interval.tick().await;
+            let Some(state) = state.upgrade() else {
+                break;
+            };
+            reap_idle_sessions(&state, timeout).await;
+        }
+    });
+}
+
+/// Close the peer connection and remove the session's GStreamer branch
+async fn teardown_session(state: &Arc<WebRTCState>, session_id: &str) {
+    state.last_activity.lock().await.remove(session_id);
+
     let peer_connection = {
         let mut peer_connections = state.peer_connections.lock().await;
-        peer_connections.remove(&session_id)
+        peer_connections.remove(session_id)
     };
-    
+
     if let Some(pc) = peer_connection {
         for sender in pc.get_senders().await {
             if let Some(track) = sender.track().await {
@@ -548,19 +806,16 @@ pub async fn close_webrtc_session(
                 }
             }
         }
-        
+
         if let Err(err) = pc.close().await {
             error!("Error closing peer connection: {}", err);
         }
-        
+
         tokio::time::sleep(Duration::from_millis(100)).await;
     } else {
         warn!("No peer connection found for session: {}", session_id);
     }
-     clean_up_gstreamer_elements(&session_id, &state).await;
-
-    info!("WebRTC session closed: {}", session_id);
-    Json(json!({ "success": true }))
+    clean_up_gstreamer_elements(session_id, state).await;
 }
 
 async fn clean_up_gstreamer_elements(session_id: &str, state: &Arc<WebRTCState>) {
@@ -577,24 +832,15 @@ async fn clean_up_gstreamer_elements(session_id: &str, state: &Arc<WebRTCState>)
                 session_id, stream_id
             );
 
-            // Element names for this session
-            let queue_name = format!("webrtc_queue_{}", element_suffix);
-            let depay_name = format!("webrtc_depay_{}", element_suffix);
-            let parse_name = format!("webrtc_parse_{}", element_suffix);
-            let appsink_name = format!("webrtc_appsink_{}", element_suffix);
-
-            // Find the elements
-            let queue_opt = pipeline.by_name(&queue_name);
-            let depay_opt = pipeline.by_name(&depay_name);
-            let parse_opt = pipeline.by_name(&parse_name);
-            let appsink_opt = pipeline.by_name(&appsink_name);
+            // Find the elements of this session; which ones exist depends on the codec
+            let queue_opt = pipeline.by_name(&format!("webrtc_queue_{}", element_suffix));
+            let elements: Vec<gst::Element> = WEBRTC_ELEMENT_ROLES
+                .iter()
+                .filter_map(|role| pipeline.by_name(&format!("webrtc_{}_{}", role, element_suffix)))
+                .collect();
 
             // Check if we found any elements
-            if queue_opt.is_none()
-                && depay_opt.is_none()
-                && parse_opt.is_none()
-                && appsink_opt.is_none()
-            {
+            if elements.is_empty() {
                 debug!(
                     "No elements found for session {} in stream {}",
                     session_id, stream_id
@@ -625,21 +871,6 @@ async fn clean_up_gstreamer_elements(session_id: &str, state: &Arc<WebRTCState>)
                 }
             }
 
-            // Gather all found elements
-            let mut elements = Vec::new();
-            if let Some(e) = queue_opt {
-                elements.push(e);
-            }
-            if let Some(e) = depay_opt {
-                elements.push(e);
-            }
-            if let Some(e) = parse_opt {
-                elements.push(e);
-            }
-            if let Some(e) = appsink_opt {
-                elements.push(e);
-            }
-
             // Send EOS to elements
             for element in &elements {
                 let _ = element.send_event(gst::event::Eos::new());
@@ -690,3 +921,83 @@ async fn clean_up_gstreamer_elements(session_id: &str, state: &Arc<WebRTCState>)
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_state() -> Arc<WebRTCState> {
+        // Lazy pool: nothing here touches the database
+        let pool = Arc::new(PgPool::connect_lazy("postgres://localhost/unused").unwrap());
+        let stream_manager = Arc::new(StreamManager::new(pool.clone()));
+        Arc::new(WebRTCState::new(pool, stream_manager, WebRtcConfig::default()))
+    }
+
+    #[tokio::test]
+    async fn idle_session_is_reaped() {
+        gst::init().unwrap();
+        let state = test_state();
+
+        state.touch("idle").await;
+        state.touch("active").await;
+        state
+            .last_activity
+            .lock()
+            .await
+            .insert("idle".to_string(), Instant::now() - Duration::from_secs(120));
+
+        let reaped = reap_idle_sessions(&state, Duration::from_secs(60)).await;
+
+        assert_eq!(reaped, vec!["idle".to_string()]);
+        let last_activity = state.last_activity.lock().await;
+        assert!(!last_activity.contains_key("idle"));
+        assert!(last_activity.contains_key("active"));
+    }
+
+    // Push RTP VP9 from a test source through the session path and check frames reach the
+    // appsink untouched. Needs the vpx and rtp plugins.
+    #[test]
+    fn vp9_stream_is_viewable() {
+        gst::init().unwrap();
+        let (Ok(src), Ok(enc), Ok(pay)) = (
+            make_element("videotestsrc", "src".to_string()),
+            make_element("vp9enc", "enc".to_string()),
+            make_element("rtpvp9pay", "pay".to_string()),
+        ) else {
+            println!("Skipping vp9_stream_is_viewable: vpx or rtp GStreamer plugins missing.");
+            return;
+        };
+        src.set_property("num-buffers", 5i32);
+        enc.set_property("deadline", 1i64);
+
+        let codec = codec_from_rtp_encoding("VP9");
+        let path = build_webrtc_video_path(&make_element, &codec, "test").unwrap();
+        assert_eq!(path.codec.mime_type, "video/VP9");
+        assert_eq!(path.elements.len(), 1);
+
+        let appsink = gst_app::AppSink::builder().caps(&path.caps).sync(false).build();
+        let mut branch = vec![src, enc, pay];
+        branch.extend(path.elements);
+        branch.push(appsink.clone().upcast());
+
+        let pipeline = gst::Pipeline::new();
+        pipeline.add_many(&branch).unwrap();
+        gst::Element::link_many(&branch).unwrap();
+        pipeline.set_state(gst::State::Playing).unwrap();
+
+        let sample = appsink
+            .try_pull_sample(gst::ClockTime::from_seconds(10))
+            .expect("no VP9 frame reached the appsink");
+        let caps = sample.caps().unwrap();
+        assert_eq!(caps.structure(0).unwrap().name(), "video/x-vp9");
+        pipeline.set_state(gst::State::Null).unwrap();
+    }
+
+    #[test]
+    fn turn_rest_credentials_match_coturn_format() {
+        let (username, credential) = turn_rest_credentials("north", 1700003600, "session-1");
+
+        assert_eq!(username, "1700003600:session-1");
+        assert_eq!(credential, "MUIr7KLVICV8xcOnmyynSYHPQJ4=");
+    }
+}
@@ -0,0 +1,96 @@
+use crate::api::rest::AppState;
+use crate::messaging::broker::MessageBrokerTrait;
+use crate::messaging::event::EventMessage;
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    response::IntoResponse,
+};
+use futures::{sink::SinkExt, stream::StreamExt};
+use log::{debug, error, info, warn};
+use serde::Deserialize;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+
+/// Events buffered per client before new ones are dropped for a slow consumer
+const CLIENT_BUFFER: usize = 256;
+
+/// Query parameters for `/ws/events`
+#[derive(Debug, Deserialize)]
+pub struct EventStreamParams {
+    /// Only forward events whose source is this camera
+    pub camera_id: Option<Uuid>,
+}
+
+// Handle WebSocket connection upgrade
+pub async fn handle_ws_upgrade(
+    ws: WebSocketUpgrade,
+    Query(params): Query<EventStreamParams>,
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    ws.on_upgrade(move |socket| handle_socket(socket, state, params.camera_id))
+}
+
+// Forward broker events to the client until either side goes away
+async fn handle_socket(socket: WebSocket, state: AppState, camera_id: Option<Uuid>) {
+    let (tx, mut rx) = mpsc::channel::<EventMessage>(CLIENT_BUFFER);
+
+    // Events are published with the source ID as the last word of the routing key
+    let pattern = match camera_id {
+        Some(id) => format!("#.{}", id),
+        None => "#".to_string(),
+    };
+
+    let subscription_id = match state
+        .message_broker
+        .subscribe_pattern(
+            &pattern,
+            Arc::new(move |event| {
+                if let Err(e) = tx.try_send(event) {
+                    warn!("Dropping event for slow WebSocket client: {}", e);
+                }
+                Ok(())
+            }),
+        )
+        .await
+    {
+        Ok(id) => id,
+        Err(e) => {
+            error!("Failed to subscribe to events for WebSocket client: {}", e);
+            return;
+        }
+    };
+    info!("WebSocket event client connected (pattern: {})", pattern);
+
+    let (mut sender, mut receiver) = socket.split();
+
+    loop {
+        tokio::select! {
+            event = rx.recv() => {
+                let Some(event) = event else { break };
+                let json = match serde_json::to_string(&event) {
+                    Ok(json) => json,
+                    Err(e) => {
+                        error!("Failed to serialize event {}: {}", event.id, e);
+                        continue;
+                    }
+                };
+                if sender.send(Message::Text(json)).await.is_err() {
+                    break;
+                }
+            }
+            msg = receiver.next() => match msg {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(msg)) => debug!("Ignoring message from WebSocket event client: {:?}", msg),
+            },
+        }
+    }
+
+    if let Err(e) = state.message_broker.unsubscribe(&subscription_id).await {
+        warn!("Failed to unsubscribe WebSocket event client: {}", e);
+    }
+    info!("WebSocket event client disconnected (pattern: {})", pattern);
+}
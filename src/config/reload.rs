@@ -0,0 +1,150 @@
+use super::{Config, StorageCleanupConfig};
+use serde_json::Value;
+
+/// What changed between the running config and a reloaded one
+#[derive(Debug, Default, PartialEq)]
+pub struct ConfigChanges {
+    /// New `api.log_level`
+    pub log_level: Option<String>,
+    /// New `recording.cleanup`, keeping the running tier destination
+    pub cleanup: Option<StorageCleanupConfig>,
+    /// New `recording.scheduler_check_interval_secs`
+    pub scheduler_check_interval_secs: Option<u64>,
+    /// Dotted paths of changed settings that only take effect after a restart
+    pub requires_restart: Vec<String>,
+}
+
+impl ConfigChanges {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// Settings the running services can pick up without a restart
+const RELOADABLE: &[&str] = &[
+    "api.log_level",
+    "recording.cleanup",
+    "recording.scheduler_check_interval_secs",
+];
+
+/// Compare the running config with a reloaded one
+pub fn diff(old: &Config, new: &Config) -> ConfigChanges {
+    let mut changes = ConfigChanges::default();
+
+    if old.api.log_level != new.api.log_level {
+        changes.log_level = Some(new.api.log_level.clone());
+    }
+    // Where recordings are tiered to is wired into the API and archiver at startup, so
+    // the rest of the cleanup settings reload while the tier destination stays put
+    let mut cleanup = new.recording.cleanup.clone();
+    let tier_moved = cleanup.tier_target() != old.recording.cleanup.tier_target()
+        || cleanup.tiers_to_archive() != old.recording.cleanup.tiers_to_archive();
+    if tier_moved {
+        cleanup.tier_after_days = old.recording.cleanup.tier_after_days;
+        cleanup.tier_target = old.recording.cleanup.tier_target.clone();
+    }
+    if old.recording.cleanup != cleanup {
+        changes.cleanup = Some(cleanup);
+    }
+    if old.recording.scheduler_check_interval_secs != new.recording.scheduler_check_interval_secs {
+        changes.scheduler_check_interval_secs = Some(new.recording.scheduler_check_interval_secs);
+    }
+
+    // Serialize both sides so every other field is compared without listing them here
+    let old = serde_json::to_value(old).unwrap_or_default();
+    let new = serde_json::to_value(new).unwrap_or_default();
+    changed_paths("", &old, &new, &mut changes.requires_restart);
+    changes
+        .requires_restart
+        .retain(|path| !RELOADABLE.contains(&path.as_str()));
+    if tier_moved {
+        changes
+            .requires_restart
+            .push("recording.cleanup.tier_target".to_string());
+    }
+
+    changes
+}
+
+/// Collect the dotted paths of the leaves (or whole values) that differ
+fn changed_paths(prefix: &str, old: &Value, new: &Value, paths: &mut Vec<String>) {
+    match (old, new) {
+        (Value::Object(old), Value::Object(new)) => {
+            let mut keys: Vec<&String> = old.keys().chain(new.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                if RELOADABLE.contains(&path.as_str()) {
+                    continue;
+                }
+                changed_paths(
+                    &path,
+                    old.get(key).unwrap_or(&Value::Null),
+                    new.get(key).unwrap_or(&Value::Null),
+                    paths,
+                );
+            }
+        }
+        _ if old != new => paths.push(prefix.to_string()),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff_separates_reloadable_settings_from_restart_only_ones() {
+        let old = Config::default();
+        assert!(diff(&old, &old.clone()).is_empty());
+
+        let mut new = old.clone();
+        new.api.log_level = "debug".to_string();
+        new.recording.cleanup.max_retention_days = 7;
+        new.recording.scheduler_check_interval_secs = 15;
+        new.api.port = old.api.port + 1;
+        new.database.url = "postgres://other/nvr".to_string();
+
+        let changes = diff(&old, &new);
+        assert_eq!(changes.log_level.as_deref(), Some("debug"));
+        assert_eq!(changes.cleanup.unwrap().max_retention_days, 7);
+        assert_eq!(changes.scheduler_check_interval_secs, Some(15));
+        assert_eq!(changes.requires_restart, vec!["api.port", "database.url"]);
+    }
+
+    #[test]
+    fn diff_keeps_the_tier_destination_until_a_restart() {
+        let mut old = Config::default();
+        old.recording.cleanup.tier_after_days = 30;
+        old.recording.cleanup.tier_target = Some("/mnt/tier".into());
+
+        let mut new = old.clone();
+        new.recording.cleanup.tier_target = Some("/mnt/other".into());
+        new.recording.cleanup.max_retention_days = 7;
+
+        let changes = diff(&old, &new);
+        let cleanup = changes.cleanup.unwrap();
+        assert_eq!(cleanup.max_retention_days, 7);
+        assert_eq!(cleanup.tier_target(), old.recording.cleanup.tier_target());
+        assert_eq!(
+            changes.requires_restart,
+            vec!["recording.cleanup.tier_target"]
+        );
+
+        // Turning tiering on is held back too
+        let mut new = Config::default();
+        new.recording.cleanup.tier_after_days = 30;
+        let changes = diff(&Config::default(), &new);
+        assert!(changes.cleanup.is_none());
+        assert_eq!(
+            changes.requires_restart,
+            vec!["recording.cleanup.tier_target"]
+        );
+    }
+}
@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS analytics_events (
+    id UUID PRIMARY KEY,
+    camera_id UUID NOT NULL REFERENCES cameras(id) ON DELETE CASCADE,
+    stream_id UUID NOT NULL REFERENCES streams(id) ON DELETE CASCADE,
+    event_type VARCHAR(100) NOT NULL, -- motion, audio, tamper, line, field, face, object, or the raw topic
+    topic VARCHAR(512) NOT NULL, -- ONVIF topic expression as reported by the device
+    is_active BOOLEAN, -- NULL when the event carries no state (e.g. one-shot notifications)
+    timestamp TIMESTAMPTZ NOT NULL, -- UtcTime reported by the device
+    metadata JSONB, -- Parsed source/data SimpleItems
+    raw_metadata TEXT, -- Original metadata XML
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_analytics_events_camera_time ON analytics_events(camera_id, timestamp);
+CREATE INDEX IF NOT EXISTS idx_analytics_events_stream_time ON analytics_events(stream_id, timestamp);
@@ -0,0 +1,2 @@
+-- ONVIF authentication the camera accepted on connect: 'digest', 'usernametoken' or 'any'
+ALTER TABLE cameras ADD COLUMN IF NOT EXISTS onvif_auth_type VARCHAR(50);
@@ -0,0 +1,2 @@
+-- IANA time zone the schedule's days and times are written in
+ALTER TABLE recording_schedules ADD COLUMN IF NOT EXISTS timezone VARCHAR(64) NOT NULL DEFAULT 'UTC';
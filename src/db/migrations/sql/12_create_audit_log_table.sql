@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS audit_log (
+    id UUID PRIMARY KEY,
+    actor_id UUID, -- NULL when the request carried no valid token; no foreign key so entries outlive deleted users
+    actor_name VARCHAR(255),
+    action VARCHAR(64) NOT NULL, -- update_role, reset_password, set_user_active, delete_camera, delete_recording, export
+    target_type VARCHAR(64) NOT NULL, -- user, camera, recording
+    target_id VARCHAR(255) NOT NULL,
+    source_ip VARCHAR(64),
+    details JSONB,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_created_at ON audit_log(created_at);
+CREATE INDEX IF NOT EXISTS idx_audit_log_actor_time ON audit_log(actor_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_audit_log_action_time ON audit_log(action, created_at);
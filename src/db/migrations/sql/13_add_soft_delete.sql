@@ -0,0 +1,6 @@
+-- Deleted cameras and recordings are hidden until restored or purged by storage cleanup
+ALTER TABLE cameras ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ;
+ALTER TABLE recordings ADD COLUMN IF NOT EXISTS deleted_at TIMESTAMPTZ;
+
+CREATE INDEX IF NOT EXISTS idx_cameras_deleted_at ON cameras(deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_recordings_deleted_at ON recordings(deleted_at) WHERE deleted_at IS NOT NULL;
@@ -0,0 +1,2 @@
+-- Whether the camera reported an audio track for the stream on connect; NULL when unknown
+ALTER TABLE streams ADD COLUMN IF NOT EXISTS has_audio BOOLEAN;
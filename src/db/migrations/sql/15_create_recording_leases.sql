@@ -0,0 +1,10 @@
+-- Which instance records a stream. The holder renews expires_at while it records; once a
+-- lease has expired (its instance died) another instance can take it over.
+CREATE TABLE IF NOT EXISTS recording_leases (
+    stream_id UUID PRIMARY KEY REFERENCES streams(id) ON DELETE CASCADE,
+    holder UUID NOT NULL,
+    acquired_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_recording_leases_holder ON recording_leases(holder);
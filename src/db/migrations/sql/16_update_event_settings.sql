@@ -0,0 +1,57 @@
+-- Per-camera event settings: which events a camera reports and which of them may trigger
+-- a recording. A camera without a row uses the defaults, where every event type does.
+-- Brings the event_settings table of 07 in line with the EventSettings model.
+
+-- Event types and topic expressions are stored as TEXT[] rather than JSON arrays
+DO $$
+BEGIN
+    IF (SELECT data_type FROM information_schema.columns
+        WHERE table_name = 'event_settings' AND column_name = 'event_types') = 'jsonb' THEN
+        ALTER TABLE event_settings
+            ADD COLUMN event_types_list TEXT[] NOT NULL DEFAULT '{}',
+            ADD COLUMN event_topic_expressions_list TEXT[] NOT NULL DEFAULT '{}';
+        UPDATE event_settings
+        SET event_types_list = ARRAY(SELECT jsonb_array_elements_text(event_types))
+        WHERE jsonb_typeof(event_types) = 'array';
+        UPDATE event_settings
+        SET event_topic_expressions_list =
+            ARRAY(SELECT jsonb_array_elements_text(event_topic_expressions))
+        WHERE jsonb_typeof(event_topic_expressions) = 'array';
+        ALTER TABLE event_settings
+            DROP COLUMN event_types,
+            DROP COLUMN event_topic_expressions;
+        ALTER TABLE event_settings RENAME COLUMN event_types_list TO event_types;
+        ALTER TABLE event_settings
+            RENAME COLUMN event_topic_expressions_list TO event_topic_expressions;
+    END IF;
+END $$;
+
+ALTER TABLE event_settings ALTER COLUMN recording_duration TYPE INTEGER;
+ALTER TABLE event_settings ALTER COLUMN trigger_recording SET DEFAULT TRUE;
+ALTER TABLE event_settings ALTER COLUMN created_at SET DEFAULT CURRENT_TIMESTAMP;
+ALTER TABLE event_settings ALTER COLUMN updated_at SET DEFAULT CURRENT_TIMESTAMP;
+ALTER TABLE event_settings
+    ADD COLUMN IF NOT EXISTS created_by UUID REFERENCES users(id) ON DELETE SET NULL;
+
+-- One row per camera, keeping the most recently updated one
+DELETE FROM event_settings a
+USING event_settings b
+WHERE a.camera_id = b.camera_id
+  AND (a.updated_at, a.id) < (b.updated_at, b.id);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_event_settings_camera_unique ON event_settings(camera_id);
+
+-- Settings saved to the short-lived camera_event_settings table move over
+DO $$
+BEGIN
+    IF to_regclass('camera_event_settings') IS NOT NULL THEN
+        INSERT INTO event_settings (
+            id, camera_id, enabled, event_types, event_topic_expressions,
+            trigger_recording, recording_duration, created_at, updated_at, created_by
+        )
+        SELECT id, camera_id, enabled, event_types, event_topic_expressions,
+               trigger_recording, recording_duration, created_at, updated_at, created_by
+        FROM camera_event_settings
+        ON CONFLICT (camera_id) DO NOTHING;
+        DROP TABLE camera_event_settings;
+    END IF;
+END $$;
@@ -0,0 +1,2 @@
+-- Record the camera from a pipeline of its own rather than from its shared stream
+ALTER TABLE cameras ADD COLUMN IF NOT EXISTS isolated_recording BOOLEAN NOT NULL DEFAULT FALSE;
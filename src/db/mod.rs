@@ -10,6 +10,8 @@ use once_cell::sync::OnceCell;
 pub mod migrations;
 pub mod models;
 pub mod repositories;
+#[cfg(test)]
+pub mod test_fixtures;
 
 // Global database pool for use throughout the application
 static DB_POOL: OnceCell<Arc<PgPool>> = OnceCell::new();
@@ -25,12 +27,7 @@ impl DatabaseService {
     pub async fn new(config: &DatabaseConfig) -> Result<Self> {
         info!("Initializing Database service");
 
-        let pool = PgPoolOptions::new()
-            .max_connections(config.max_connections)
-            .acquire_timeout(Duration::from_secs(5))
-            .connect(&config.url)
-            .await
-            .map_err(|e| Error::Database(format!("Failed to connect to database: {}", e)))?;
+        let pool = connect_pool(config).await?;
 
         info!("Connected to PostgreSQL database");
 
@@ -78,6 +75,24 @@ impl DatabaseService {
     }
 }
 
+/// Pool options built from the database configuration
+pub fn pool_options(config: &DatabaseConfig) -> PgPoolOptions {
+    PgPoolOptions::new()
+        .max_connections(config.max_connections)
+        .min_connections(config.min_connections)
+        .acquire_timeout(Duration::from_secs(config.acquire_timeout_secs))
+}
+
+/// Connect a pool sized by the database configuration
+pub async fn connect_pool(config: &DatabaseConfig) -> Result<PgPool> {
+    let pool = pool_options(config)
+        .connect(&config.url)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to connect to database: {}", e)))?;
+
+    Ok(pool)
+}
+
 /// Get the global database connection pool
 /// Returns an error if the pool has not been initialized
 pub async fn get_connection_pool() -> Result<PgPool> {
@@ -87,3 +102,21 @@ pub async fn get_connection_pool() -> Result<PgPool> {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn pool_options_follow_the_config() {
+        let mut config = Config::default().database;
+        config.max_connections = 42;
+        config.min_connections = 3;
+        config.acquire_timeout_secs = 9;
+
+        let options = pool_options(&config);
+        assert_eq!(options.get_max_connections(), 42);
+        assert_eq!(options.get_min_connections(), 3);
+        assert_eq!(options.get_acquire_timeout(), Duration::from_secs(9));
+    }
+}
@@ -0,0 +1,95 @@
+use crate::utils::metadataparser::{EventType, OnvifEvent};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+/// Analytics event model, one row per ONVIF metadata notification
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct AnalyticsEvent {
+    pub id: Uuid,
+    pub camera_id: Uuid,
+    pub stream_id: Uuid,
+    pub event_type: String,
+    pub topic: String,
+    pub is_active: Option<bool>,
+    pub timestamp: DateTime<Utc>,
+    pub metadata: Option<serde_json::Value>,
+    pub raw_metadata: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl AnalyticsEvent {
+    /// Build an analytics event from a parsed ONVIF notification
+    pub fn from_onvif(
+        camera_id: Uuid,
+        stream_id: Uuid,
+        event: &OnvifEvent,
+        raw_metadata: Option<&str>,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            camera_id,
+            stream_id,
+            event_type: analytics_event_type_name(&event.event_type),
+            topic: event.topic.clone(),
+            is_active: event.is_active,
+            timestamp: event.timestamp,
+            metadata: Some(json!({
+                "source_address": event.source_address,
+                "property_operation": event.property_operation,
+                "video_source": event.source.video_source,
+                "analytics_config": event.source.analytics_config,
+                "rule": event.source.rule,
+                "source": event.source.extra,
+                "area_index": event.area_index,
+                "confidence": event.confidence,
+                "data": event.data,
+            })),
+            raw_metadata: raw_metadata.map(|s| s.to_string()),
+            created_at: Utc::now(),
+        }
+    }
+}
+
+/// Name stored in `analytics_events.event_type` for a parsed ONVIF event type
+pub fn analytics_event_type_name(event_type: &EventType) -> String {
+    match event_type {
+        EventType::MotionDetected => "motion".to_string(),
+        EventType::AudioDetected => "audio".to_string(),
+        EventType::TamperDetected => "tamper".to_string(),
+        EventType::LineDetected => "line".to_string(),
+        EventType::FieldDetected => "field".to_string(),
+        EventType::FaceDetected => "face".to_string(),
+        EventType::ObjectDetected => "object".to_string(),
+        EventType::Other(topic) => topic.clone(),
+    }
+}
+
+/// Search query model
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalyticsEventSearchQuery {
+    pub camera_ids: Option<Vec<Uuid>>,
+    pub stream_ids: Option<Vec<Uuid>>,
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+    pub event_types: Option<Vec<String>>,
+    pub is_active: Option<bool>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
+
+impl Default for AnalyticsEventSearchQuery {
+    fn default() -> Self {
+        Self {
+            camera_ids: None,
+            stream_ids: None,
+            start_time: None,
+            end_time: None,
+            event_types: None,
+            is_active: None,
+            limit: None,
+            offset: None,
+        }
+    }
+}
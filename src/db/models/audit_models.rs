@@ -0,0 +1,78 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use uuid::Uuid;
+
+/// Security-sensitive actions recorded in the audit log
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditAction {
+    UpdateRole,
+    ResetPassword,
+    SetUserActive,
+    DeleteCamera,
+    DeleteRecording,
+    Export,
+}
+
+impl AuditAction {
+    /// Name stored in `audit_log.action`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::UpdateRole => "update_role",
+            AuditAction::ResetPassword => "reset_password",
+            AuditAction::SetUserActive => "set_user_active",
+            AuditAction::DeleteCamera => "delete_camera",
+            AuditAction::DeleteRecording => "delete_recording",
+            AuditAction::Export => "export",
+        }
+    }
+}
+
+impl fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// One audit log row: who did what to which object, and from where
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct AuditEntry {
+    pub id: Uuid,
+    pub actor_id: Option<Uuid>,
+    pub actor_name: Option<String>,
+    pub action: String,
+    pub target_type: String,
+    pub target_id: String,
+    pub source_ip: Option<String>,
+    pub details: Option<serde_json::Value>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl AuditEntry {
+    /// An entry for `action` on a target, without actor or source yet
+    pub fn new(action: AuditAction, target_type: &str, target_id: impl ToString) -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            actor_id: None,
+            actor_name: None,
+            action: action.to_string(),
+            target_type: target_type.to_string(),
+            target_id: target_id.to_string(),
+            source_ip: None,
+            details: None,
+            created_at: Utc::now(),
+        }
+    }
+}
+
+/// Audit log search query
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuditSearchQuery {
+    pub actor_id: Option<Uuid>,
+    pub actions: Option<Vec<String>>,
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+    pub offset: Option<usize>,
+}
@@ -3,6 +3,58 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+/// How a camera records
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordingMode {
+    /// Record around the clock within its schedules
+    Continuous,
+    /// Record only when motion is detected
+    Motion,
+    /// Record whatever its schedules ask for
+    Scheduled,
+    /// Never record, whatever the schedules say
+    Disabled,
+}
+
+impl RecordingMode {
+    pub const ALL: [RecordingMode; 4] = [
+        RecordingMode::Continuous,
+        RecordingMode::Motion,
+        RecordingMode::Scheduled,
+        RecordingMode::Disabled,
+    ];
+}
+
+impl std::fmt::Display for RecordingMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RecordingMode::Continuous => write!(f, "continuous"),
+            RecordingMode::Motion => write!(f, "motion"),
+            RecordingMode::Scheduled => write!(f, "scheduled"),
+            RecordingMode::Disabled => write!(f, "disabled"),
+        }
+    }
+}
+
+impl std::str::FromStr for RecordingMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RecordingMode::ALL
+            .into_iter()
+            .find(|mode| mode.to_string().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                let valid: Vec<String> = RecordingMode::ALL.iter().map(|m| m.to_string()).collect();
+                format!(
+                    "Invalid recording mode '{}'. Must be one of: {}",
+                    s,
+                    valid.join(", ")
+                )
+            })
+    }
+}
+
 /// Camera model
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct Camera {
@@ -14,6 +66,7 @@ pub struct Camera {
     pub username: Option<String>,
     pub password: Option<String>,
     pub onvif_endpoint: Option<String>,
+    pub onvif_auth_type: Option<String>,
     pub status: String,
     pub primary_stream_id: Option<Uuid>,
     pub sub_stream_id: Option<Uuid>,
@@ -36,6 +89,10 @@ pub struct Camera {
     pub storage_used_gb: Option<i32>,
     pub retention_days: Option<i32>,
     pub recording_mode: Option<String>,
+    /// Record from a pipeline of its own, straight from the camera, instead of tapping
+    /// its shared stream
+    #[serde(default)]
+    pub isolated_recording: bool,
     // Analytics information
     pub analytics_capabilities: Option<serde_json::Value>,
     pub ai_processor_type: Option<String>,
@@ -56,6 +113,15 @@ pub struct Camera {
     pub updated_at: DateTime<Utc>,
 }
 impl Camera {
+    /// Whether recording has been switched off for this camera. Its schedules are kept
+    /// but neither they nor events start recordings while it is disabled
+    pub fn recording_disabled(&self) -> bool {
+        self.recording_mode
+            .as_deref()
+            .and_then(|mode| mode.parse().ok())
+            == Some(RecordingMode::Disabled)
+    }
+
     pub(crate) fn default() -> Camera {
         Camera {
             id: Uuid::new_v4(),
@@ -66,6 +132,7 @@ impl Camera {
             username: None,
             password: None,
             onvif_endpoint: None,
+            onvif_auth_type: None,
             status: "discovered".to_string(),
             primary_stream_id: None,
             sub_stream_id: None,
@@ -84,6 +151,7 @@ impl Camera {
             storage_used_gb: None,
             retention_days: None,
             recording_mode: None,
+            isolated_recording: false,
             analytics_capabilities: None,
             ai_processor_type: None,
             ai_processor_model: None,
@@ -111,3 +179,15 @@ pub struct CameraWithStreams {
     pub streams: Vec<Stream>,
     pub stream_references: Vec<StreamReference>,
 }
+
+/// Outcome of registering a discovered camera that may already be known
+#[derive(Debug, Clone, Serialize)]
+pub struct CameraUpsert {
+    #[serde(flatten)]
+    pub camera: CameraWithStreams,
+    /// Whether a new camera was inserted rather than an existing one updated
+    pub created: bool,
+    /// Streams of the existing camera the device no longer reports. They are kept for
+    /// their recordings but marked inactive.
+    pub stale_stream_ids: Vec<Uuid>,
+}
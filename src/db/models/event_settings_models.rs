@@ -1,17 +1,76 @@
+use crate::db::models::recording_models::RecordingEventType;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+/// Event types that can trigger a recording; continuous and manual recordings aren't
+/// started by events
+pub const TRIGGER_EVENT_TYPES: [RecordingEventType; 4] = [
+    RecordingEventType::Motion,
+    RecordingEventType::Audio,
+    RecordingEventType::Analytics,
+    RecordingEventType::External,
+];
+
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct EventSettings {
     pub id: Uuid,
     pub camera_id: Uuid,
     pub enabled: bool,
-    pub event_types: Vec<String>, // Event types to subscribe to
+    pub event_types: Vec<String>, // Event types that may trigger recording
     pub event_topic_expressions: Vec<String>, // ONVIF topic expressions
     pub trigger_recording: bool,  // Whether to trigger recording on events
     pub recording_duration: i32,  // Duration to record in seconds when event triggered
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
-    pub created_by: Uuid, // User ID
+    pub created_by: Option<Uuid>, // User ID
+}
+
+impl EventSettings {
+    /// Settings of a camera that has none stored: every event type triggers a recording
+    pub fn defaults_for(camera_id: Uuid) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4(),
+            camera_id,
+            enabled: true,
+            event_types: TRIGGER_EVENT_TYPES.iter().map(|t| t.to_string()).collect(),
+            event_topic_expressions: Vec::new(),
+            trigger_recording: true,
+            recording_duration: 60,
+            created_at: now,
+            updated_at: now,
+            created_by: None,
+        }
+    }
+
+    /// Whether an event of `event_type` may start a recording on this camera
+    pub fn triggers_recording(&self, event_type: RecordingEventType) -> bool {
+        self.enabled
+            && self.trigger_recording
+            && self
+                .event_types
+                .iter()
+                .any(|t| t.parse::<RecordingEventType>().ok() == Some(event_type))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cameras_can_opt_out_of_event_types() {
+        let mut settings = EventSettings::defaults_for(Uuid::new_v4());
+        for event_type in TRIGGER_EVENT_TYPES {
+            assert!(settings.triggers_recording(event_type));
+        }
+
+        settings.event_types = vec!["Motion".to_string()];
+        assert!(settings.triggers_recording(RecordingEventType::Motion));
+        assert!(!settings.triggers_recording(RecordingEventType::Audio));
+
+        settings.trigger_recording = false;
+        assert!(!settings.triggers_recording(RecordingEventType::Motion));
+    }
 }
@@ -1,3 +1,5 @@
+pub mod analytics_event_models;
+pub mod audit_models;
 pub mod camera_models;
 pub mod event_models;
 pub mod event_settings_models;
@@ -21,6 +21,17 @@ pub enum RecordingEventType {
     Analytics,
 }
 
+impl RecordingEventType {
+    pub const ALL: [RecordingEventType; 6] = [
+        RecordingEventType::Continuous,
+        RecordingEventType::Motion,
+        RecordingEventType::Audio,
+        RecordingEventType::External,
+        RecordingEventType::Manual,
+        RecordingEventType::Analytics,
+    ];
+}
+
 impl std::fmt::Display for RecordingEventType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -34,6 +45,28 @@ impl std::fmt::Display for RecordingEventType {
     }
 }
 
+/// Case-insensitive inverse of `Display`, used wherever an event type comes from a request
+impl std::str::FromStr for RecordingEventType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RecordingEventType::ALL
+            .into_iter()
+            .find(|event_type| event_type.to_string().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                let valid: Vec<String> = RecordingEventType::ALL
+                    .iter()
+                    .map(|t| t.to_string())
+                    .collect();
+                format!(
+                    "Invalid event type '{}'. Must be one of: {}",
+                    s,
+                    valid.join(", ")
+                )
+            })
+    }
+}
+
 impl sqlx::Type<sqlx::Postgres> for RecordingEventType {
     fn type_info() -> sqlx::postgres::PgTypeInfo {
         sqlx::postgres::PgTypeInfo::with_name("text")
@@ -58,15 +91,8 @@ impl<'r> sqlx::Decode<'r, sqlx::Postgres> for RecordingEventType {
         value: sqlx::postgres::PgValueRef<'r>,
     ) -> Result<Self, Box<dyn std::error::Error + Send + Sync + 'static>> {
         let text = <String as sqlx::Decode<sqlx::Postgres>>::decode(value)?;
-        Ok(match text.as_str() {
-            "continuous" => RecordingEventType::Continuous,
-            "motion" => RecordingEventType::Motion,
-            "audio" => RecordingEventType::Audio,
-            "external" => RecordingEventType::External,
-            "manual" => RecordingEventType::Manual,
-            "analytics" => RecordingEventType::Analytics,
-            _ => RecordingEventType::Continuous, // Default to continuous
-        })
+        // Default to continuous
+        Ok(text.parse().unwrap_or_default())
     }
 }
 
@@ -107,6 +133,8 @@ pub struct RecordingUpdate {
     pub metadata: Option<serde_json::Value>,
     pub segment_id: Option<u32>,
     pub parent_recording_id: Option<Uuid>,
+    pub resolution: Option<String>,
+    pub fps: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
@@ -185,6 +213,10 @@ pub struct RecordingSearchQuery {
     pub event_types: Option<Vec<RecordingEventType>>,
     pub schedule_id: Option<Uuid>,
     pub min_duration: Option<u64>,
+    pub min_file_size: Option<u64>,
+    pub max_file_size: Option<u64>,
+    /// Only recordings whose metadata does (or does not) record an audio codec
+    pub has_audio: Option<bool>,
     pub segment_id: Option<u32>,
     pub parent_recording_id: Option<Uuid>,
     pub is_segment: Option<bool>,
@@ -203,6 +235,9 @@ impl Default for RecordingSearchQuery {
             event_types: None,
             schedule_id: None,
             min_duration: None,
+            min_file_size: None,
+            max_file_size: None,
+            has_audio: None,
             segment_id: None,
             parent_recording_id: None,
             is_segment: None,
@@ -231,3 +266,33 @@ pub struct RecordingStatsDb {
     pub oldest: Option<DateTime<Utc>>,
     pub newest: Option<DateTime<Utc>>,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn event_types_round_trip_through_strings() {
+        for event_type in RecordingEventType::ALL {
+            let name = event_type.to_string();
+            assert_eq!(name.parse::<RecordingEventType>(), Ok(event_type));
+            assert_eq!(
+                name.to_uppercase().parse::<RecordingEventType>(),
+                Ok(event_type)
+            );
+            // The JSON name is the same string
+            assert_eq!(serde_json::to_value(event_type).unwrap(), name);
+            assert_eq!(
+                serde_json::from_value::<RecordingEventType>(name.into()).unwrap(),
+                event_type
+            );
+        }
+
+        assert_eq!(
+            " Analytics ".parse::<RecordingEventType>(),
+            Ok(RecordingEventType::Analytics)
+        );
+        let err = "alarm".parse::<RecordingEventType>().unwrap_err();
+        assert!(err.contains("continuous, motion, audio, external, manual, analytics"));
+    }
+}
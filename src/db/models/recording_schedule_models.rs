@@ -1,4 +1,5 @@
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Datelike, Duration, DurationRound, Offset, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
@@ -20,6 +21,159 @@ pub struct RecordingSchedule {
     pub record_on_analytics: bool, // Record on analytics events
     pub record_on_external: bool,  // Record on external events
     pub continuous_recording: bool, // Record continuously during scheduled times
+    #[serde(default = "default_timezone")]
+    pub timezone: String, // IANA zone the days and times are in, e.g. "America/New_York"
+}
+
+fn default_timezone() -> String {
+    "UTC".to_string()
+}
+
+/// Parse an IANA time zone name such as "Europe/Berlin"
+pub fn parse_timezone(name: &str) -> Option<Tz> {
+    name.parse().ok()
+}
+
+const MINUTES_PER_DAY: u32 = 24 * 60;
+const MINUTES_PER_WEEK: u32 = 7 * MINUTES_PER_DAY;
+
+/// Minutes since midnight for an "HH:MM" time
+fn parse_minutes(time: &str) -> Option<u32> {
+    let (hours, minutes) = time.split_once(':')?;
+    let (hours, minutes): (u32, u32) = (hours.parse().ok()?, minutes.parse().ok()?);
+    (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+}
+
+/// Whether `at`, taken in `tz`, falls in one of `windows` (end minute inclusive)
+fn active_in(windows: &[(u32, u32)], tz: Tz, at: DateTime<Utc>) -> bool {
+    let at = at.with_timezone(&tz);
+    let minute =
+        at.weekday().num_days_from_sunday() * MINUTES_PER_DAY + at.hour() * 60 + at.minute();
+    windows
+        .iter()
+        .any(|(start, end)| *start <= minute && minute <= *end)
+}
+
+impl RecordingSchedule {
+    /// Half-open `[start, end)` windows covered by this schedule, in minutes of the week
+    /// starting Sunday 00:00.
+    ///
+    /// A schedule whose end time is before its start time runs overnight into the next
+    /// day; Saturday's overnight window wraps around to Sunday morning.
+    pub fn weekly_windows(&self) -> Vec<(u32, u32)> {
+        let (Some(start), Some(end)) = (
+            parse_minutes(&self.start_time),
+            parse_minutes(&self.end_time),
+        ) else {
+            return Vec::new();
+        };
+        if start == end {
+            return Vec::new();
+        }
+
+        let mut windows = Vec::new();
+        for day in self.days_of_week.iter().filter(|d| (0..7).contains(*d)) {
+            let day_start = *day as u32 * MINUTES_PER_DAY;
+            let window_end = if end > start {
+                day_start + end
+            } else {
+                day_start + MINUTES_PER_DAY + end
+            };
+
+            if window_end > MINUTES_PER_WEEK {
+                windows.push((day_start + start, MINUTES_PER_WEEK));
+                windows.push((0, window_end - MINUTES_PER_WEEK));
+            } else {
+                windows.push((day_start + start, window_end));
+            }
+        }
+        windows
+    }
+
+    /// Whether the schedule is recording at `at`.
+    ///
+    /// Day and time are taken in the schedule's time zone (UTC if it is unknown). The
+    /// end minute itself still counts as active, so "00:00"-"23:59" covers the whole day.
+    pub fn is_active_at(&self, at: DateTime<Utc>) -> bool {
+        active_in(&self.weekly_windows(), self.tz(), at)
+    }
+
+    /// The next time after `from` the schedule starts or stops, if within the coming week
+    pub fn next_transition(&self, from: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let (windows, tz) = (self.weekly_windows(), self.tz());
+        let active = active_in(&windows, tz, from);
+
+        // Step minute by minute so DST shifts in the schedule's zone are handled for us
+        let first = from.duration_trunc(Duration::minutes(1)).ok()? + Duration::minutes(1);
+        (0..=MINUTES_PER_WEEK as i64 + 2 * 60)
+            .map(|step| first + Duration::minutes(step))
+            .find(|at| active_in(&windows, tz, *at) != active)
+    }
+
+    /// Whether a recording that stopped at `stopped_at` and resumed at `resumed_at` was
+    /// interrupted, rather than stopped because the window closed: both times fall in the
+    /// same window, so the footage in between is missing
+    pub fn interrupted_between(
+        &self,
+        stopped_at: DateTime<Utc>,
+        resumed_at: DateTime<Utc>,
+    ) -> bool {
+        stopped_at < resumed_at
+            && self.is_active_at(stopped_at)
+            && self.is_active_at(resumed_at)
+            && self
+                .next_transition(stopped_at)
+                .map_or(true, |stop| stop > resumed_at)
+    }
+
+    fn tz(&self) -> Tz {
+        parse_timezone(&self.timezone).unwrap_or(Tz::UTC)
+    }
+
+    /// `weekly_windows` moved to UTC, with the UTC offset the schedule's time zone has
+    /// at `at`
+    fn utc_windows(&self, at: DateTime<Utc>) -> Vec<(u32, u32)> {
+        let offset = self
+            .tz()
+            .offset_from_utc_datetime(&at.naive_utc())
+            .fix()
+            .local_minus_utc()
+            / 60;
+
+        let mut windows = Vec::new();
+        for (start, end) in self.weekly_windows() {
+            let utc_start = (start as i32 - offset).rem_euclid(MINUTES_PER_WEEK as i32) as u32;
+            let utc_end = utc_start + (end - start);
+            if utc_end > MINUTES_PER_WEEK {
+                windows.push((utc_start, MINUTES_PER_WEEK));
+                windows.push((0, utc_end - MINUTES_PER_WEEK));
+            } else {
+                windows.push((utc_start, utc_end));
+            }
+        }
+        windows
+    }
+
+    /// Whether both schedules would record the same stream at the same time.
+    ///
+    /// Schedules in different time zones are compared in UTC, both with winter and with
+    /// summer offsets, so they conflict if they overlap at any time of the year.
+    pub fn overlaps(&self, other: &RecordingSchedule) -> bool {
+        if self.stream_id != other.stream_id {
+            return false;
+        }
+
+        let year = Utc::now().year();
+        [1, 7]
+            .into_iter()
+            .filter_map(|month| Utc.with_ymd_and_hms(year, month, 15, 0, 0, 0).single())
+            .any(|at| {
+                let theirs = other.utc_windows(at);
+                self.utc_windows(at)
+                    .iter()
+                    .any(|(start, end)| theirs.iter().any(|(s, e)| start < e && s < end))
+            })
+    }
 }
 
 /// Database-compatible recording schedule with proper array type
@@ -41,6 +195,7 @@ pub struct RecordingScheduleDb {
     pub record_on_analytics: bool,
     pub record_on_external: bool,
     pub continuous_recording: bool,
+    pub timezone: String,
 }
 
 impl From<RecordingSchedule> for RecordingScheduleDb {
@@ -62,6 +217,7 @@ impl From<RecordingSchedule> for RecordingScheduleDb {
             record_on_analytics: schedule.record_on_analytics,
             record_on_external: schedule.record_on_external,
             continuous_recording: schedule.continuous_recording,
+            timezone: schedule.timezone,
         }
     }
 }
@@ -85,6 +241,160 @@ impl From<RecordingScheduleDb> for RecordingSchedule {
             record_on_analytics: db.record_on_analytics,
             record_on_external: db.record_on_external,
             continuous_recording: db.continuous_recording,
+            timezone: db.timezone,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(stream_id: Uuid, days: &[i32], start: &str, end: &str) -> RecordingSchedule {
+        RecordingSchedule {
+            id: Uuid::new_v4(),
+            camera_id: Uuid::new_v4(),
+            stream_id,
+            name: format!("{}-{}", start, end),
+            enabled: true,
+            days_of_week: days.to_vec(),
+            start_time: start.to_string(),
+            end_time: end.to_string(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            retention_days: 30,
+            record_on_motion: false,
+            record_on_audio: false,
+            record_on_analytics: false,
+            record_on_external: false,
+            continuous_recording: true,
+            timezone: "UTC".to_string(),
+        }
+    }
+
+    #[test]
+    fn overlapping_schedules_on_a_shared_day_conflict() {
+        let stream = Uuid::new_v4();
+        let morning = schedule(stream, &[1, 2], "08:00", "12:00");
+
+        assert!(morning.overlaps(&schedule(stream, &[2, 3], "11:00", "13:00")));
+        assert!(!morning.overlaps(&schedule(stream, &[3], "11:00", "13:00")));
+        assert!(!morning.overlaps(&schedule(Uuid::new_v4(), &[1], "11:00", "13:00")));
+    }
+
+    #[test]
+    fn adjacent_schedules_do_not_overlap() {
+        let stream = Uuid::new_v4();
+        let morning = schedule(stream, &[1], "08:00", "12:00");
+
+        assert!(!morning.overlaps(&schedule(stream, &[1], "12:00", "16:00")));
+        assert!(!morning.overlaps(&schedule(stream, &[1], "6:00", "8:00")));
+    }
+
+    #[test]
+    fn overnight_schedules_spill_into_the_next_day() {
+        let stream = Uuid::new_v4();
+        // Monday 22:00 until Tuesday 06:00
+        let overnight = schedule(stream, &[1], "22:00", "06:00");
+
+        assert!(overnight.overlaps(&schedule(stream, &[2], "05:00", "07:00")));
+        assert!(!overnight.overlaps(&schedule(stream, &[1], "05:00", "07:00")));
+        assert!(!overnight.overlaps(&schedule(stream, &[2], "06:00", "22:00")));
+
+        // Saturday night runs into Sunday morning
+        let weekend = schedule(stream, &[6], "23:00", "01:00");
+        assert!(weekend.overlaps(&schedule(stream, &[0], "00:30", "02:00")));
+    }
+
+    #[test]
+    fn schedules_in_different_zones_are_compared_in_utc() {
+        let stream = Uuid::new_v4();
+        let in_zone = |zone: &str, start: &str, end: &str| RecordingSchedule {
+            timezone: zone.to_string(),
+            ..schedule(stream, &[1], start, end)
+        };
+        // Monday 08:00-10:00 in Berlin is 07:00-09:00 UTC in winter, 06:00-08:00 in summer
+        let berlin = in_zone("Europe/Berlin", "08:00", "10:00");
+
+        assert!(berlin.overlaps(&in_zone("UTC", "08:30", "11:00")));
+        assert!(berlin.overlaps(&in_zone("UTC", "06:00", "06:30")));
+        // The same local hours in New York are hours later in UTC
+        assert!(!berlin.overlaps(&in_zone("America/New_York", "08:00", "10:00")));
+        // Berlin's morning is New York's night before
+        assert!(berlin.overlaps(&in_zone("America/New_York", "02:00", "03:00")));
+    }
+
+    #[test]
+    fn overnight_schedule_is_active_after_midnight() {
+        let overnight = schedule(Uuid::new_v4(), &[0, 1, 2, 3, 4, 5, 6], "22:00", "06:00");
+        // 2024-01-02 is a Tuesday
+        let at = |hour, minute| {
+            chrono::TimeZone::with_ymd_and_hms(&Utc, 2024, 1, 2, hour, minute, 0).unwrap()
+        };
+
+        assert!(overnight.is_active_at(at(1, 0)));
+        assert!(overnight.is_active_at(at(23, 30)));
+        assert!(!overnight.is_active_at(at(12, 0)));
+
+        // Monday night only: still running early Tuesday, not early Monday
+        let monday_night = schedule(Uuid::new_v4(), &[1], "22:00", "06:00");
+        assert!(monday_night.is_active_at(at(1, 0)));
+        assert!(!monday_night.is_active_at(at(1, 0) - chrono::Duration::days(1)));
+    }
+
+    #[test]
+    fn schedule_follows_local_time_across_dst() {
+        let mut morning = schedule(Uuid::new_v4(), &[0, 1, 2, 3, 4, 5, 6], "08:00", "09:00");
+        morning.timezone = "America/New_York".to_string();
+        let utc = |day, hour, minute| {
+            chrono::TimeZone::with_ymd_and_hms(&Utc, 2024, 3, day, hour, minute, 0).unwrap()
+        };
+
+        // Saturday before the 2024-03-10 switch: EST, 08:30 local is 13:30 UTC
+        assert!(morning.is_active_at(utc(9, 13, 30)));
+        assert!(!morning.is_active_at(utc(9, 12, 30)));
+        // Monday after: EDT, 08:30 local is 12:30 UTC
+        assert!(morning.is_active_at(utc(11, 12, 30)));
+        assert!(!morning.is_active_at(utc(11, 13, 30)));
+
+        // Unset or unknown zones keep the old UTC behavior
+        morning.timezone = "Not/AZone".to_string();
+        assert!(morning.is_active_at(utc(11, 8, 30)));
+    }
+
+    #[test]
+    fn restart_within_the_window_is_a_gap() {
+        let day = schedule(Uuid::new_v4(), &[0, 1, 2, 3, 4, 5, 6], "08:00", "18:00");
+        // 2024-01-01 is a Monday
+        let at = |day, hour, minute| {
+            chrono::TimeZone::with_ymd_and_hms(&Utc, 2024, 1, day, hour, minute, 0).unwrap()
+        };
+
+        // Crashed at 10:00 and resumed at 10:05
+        assert!(day.interrupted_between(at(1, 10, 0), at(1, 10, 5)));
+        // Stopped when the window closed and resumed when the next one opened
+        assert!(!day.interrupted_between(at(1, 18, 0), at(2, 8, 0)));
+        // Crashed late in the day and only came back the next morning
+        assert!(!day.interrupted_between(at(1, 17, 0), at(2, 8, 30)));
+
+        let always = schedule(Uuid::new_v4(), &[0, 1, 2, 3, 4, 5, 6], "00:00", "23:59");
+        assert!(always.interrupted_between(at(1, 23, 58), at(2, 0, 3)));
+    }
+
+    #[test]
+    fn next_transition_finds_the_next_start_and_stop() {
+        let overnight = schedule(Uuid::new_v4(), &[1], "22:00", "06:00");
+        // 2024-01-01 is a Monday
+        let at = |day, hour, minute| {
+            chrono::TimeZone::with_ymd_and_hms(&Utc, 2024, 1, day, hour, minute, 0).unwrap()
+        };
+
+        assert_eq!(overnight.next_transition(at(1, 12, 0)), Some(at(1, 22, 0)));
+        // Active through the 06:00 minute, stopped from 06:01
+        assert_eq!(overnight.next_transition(at(2, 1, 30)), Some(at(2, 6, 1)));
+        assert_eq!(
+            schedule(Uuid::new_v4(), &[], "08:00", "09:00").next_transition(at(1, 12, 0)),
+            None
+        );
+    }
+}
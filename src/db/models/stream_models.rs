@@ -209,6 +209,8 @@ pub struct Stream {
     pub last_connected_at: Option<DateTime<Utc>>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// Whether the camera sends an audio track, as reported on connect; `None` when unknown
+    pub has_audio: Option<bool>,
 }
 
 impl Default for Stream {
@@ -242,6 +244,7 @@ impl Default for Stream {
             last_connected_at: None,
             created_at: Utc::now(),
             updated_at: Utc::now(),
+            has_audio: None,
         }
     }
 }
@@ -0,0 +1,162 @@
+use crate::{
+    db::models::analytics_event_models::{AnalyticsEvent, AnalyticsEventSearchQuery},
+    error::Error,
+};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Analytics events repository for ONVIF metadata events
+#[derive(Clone)]
+pub struct AnalyticsEventsRepository {
+    pub pool: Arc<PgPool>,
+}
+
+impl AnalyticsEventsRepository {
+    /// Create a new analytics events repository
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        Self { pool }
+    }
+
+    /// Store a new analytics event
+    pub async fn create(&self, event: &AnalyticsEvent) -> Result<AnalyticsEvent> {
+        let result = sqlx::query_as::<_, AnalyticsEvent>(
+            r#"
+            INSERT INTO analytics_events (
+                id, camera_id, stream_id, event_type, topic, is_active, timestamp,
+                metadata, raw_metadata, created_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+            RETURNING id, camera_id, stream_id, event_type, topic, is_active, timestamp,
+                      metadata, raw_metadata, created_at
+            "#,
+        )
+        .bind(event.id)
+        .bind(event.camera_id)
+        .bind(event.stream_id)
+        .bind(&event.event_type)
+        .bind(&event.topic)
+        .bind(event.is_active)
+        .bind(event.timestamp)
+        .bind(&event.metadata)
+        .bind(&event.raw_metadata)
+        .bind(event.created_at)
+        .fetch_one(&*self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to create analytics event: {}", e)))?;
+
+        Ok(result)
+    }
+
+    /// Search analytics events with filters, newest first
+    pub async fn search(&self, query: &AnalyticsEventSearchQuery) -> Result<Vec<AnalyticsEvent>> {
+        // Build dynamic query
+        let mut sql = String::from(
+            r#"
+            SELECT id, camera_id, stream_id, event_type, topic, is_active, timestamp,
+                   metadata, raw_metadata, created_at
+            FROM analytics_events
+            WHERE 1=1
+            "#,
+        );
+
+        let mut args: Vec<QueryArg> = Vec::new();
+        let mut param_index = 1;
+
+        if let Some(camera_ids) = &query.camera_ids {
+            if !camera_ids.is_empty() {
+                sql.push_str(&format!(" AND camera_id = ANY(${})", param_index));
+                args.push(QueryArg::UuidArray(camera_ids.clone()));
+                param_index += 1;
+            }
+        }
+
+        if let Some(stream_ids) = &query.stream_ids {
+            if !stream_ids.is_empty() {
+                sql.push_str(&format!(" AND stream_id = ANY(${})", param_index));
+                args.push(QueryArg::UuidArray(stream_ids.clone()));
+                param_index += 1;
+            }
+        }
+
+        if let Some(start_time) = &query.start_time {
+            sql.push_str(&format!(" AND timestamp >= ${}", param_index));
+            args.push(QueryArg::DateTime(*start_time));
+            param_index += 1;
+        }
+
+        if let Some(end_time) = &query.end_time {
+            sql.push_str(&format!(" AND timestamp <= ${}", param_index));
+            args.push(QueryArg::DateTime(*end_time));
+            param_index += 1;
+        }
+
+        if let Some(event_types) = &query.event_types {
+            if !event_types.is_empty() {
+                sql.push_str(&format!(" AND event_type = ANY(${})", param_index));
+                args.push(QueryArg::StringArray(event_types.clone()));
+                param_index += 1;
+            }
+        }
+
+        if let Some(is_active) = &query.is_active {
+            sql.push_str(&format!(" AND is_active = ${}", param_index));
+            args.push(QueryArg::Bool(*is_active));
+            param_index += 1;
+        }
+
+        sql.push_str(" ORDER BY timestamp DESC");
+
+        if let Some(limit) = &query.limit {
+            sql.push_str(&format!(" LIMIT ${}", param_index));
+            args.push(QueryArg::I64(*limit as i64));
+            param_index += 1;
+        } else {
+            sql.push_str(" LIMIT 1000"); // Default limit
+        }
+
+        if let Some(offset) = &query.offset {
+            sql.push_str(&format!(" OFFSET ${}", param_index));
+            args.push(QueryArg::I64(*offset as i64));
+        }
+
+        let mut query_builder = sqlx::query_as::<_, AnalyticsEvent>(&sql);
+        for arg in args {
+            query_builder = arg.apply_to_query(query_builder);
+        }
+
+        let result = query_builder
+            .fetch_all(&*self.pool)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to search analytics events: {}", e)))?;
+
+        Ok(result)
+    }
+}
+
+// Helper enum for dynamic query arguments
+enum QueryArg {
+    UuidArray(Vec<Uuid>),
+    DateTime(DateTime<Utc>),
+    I64(i64),
+    Bool(bool),
+    StringArray(Vec<String>),
+}
+
+impl QueryArg {
+    // Apply this argument to a query builder
+    fn apply_to_query<'a, T>(
+        self,
+        builder: sqlx::query::QueryAs<'a, sqlx::Postgres, T, sqlx::postgres::PgArguments>,
+    ) -> sqlx::query::QueryAs<'a, sqlx::Postgres, T, sqlx::postgres::PgArguments> {
+        match self {
+            QueryArg::UuidArray(ids) => builder.bind(ids),
+            QueryArg::DateTime(dt) => builder.bind(dt),
+            QueryArg::I64(i) => builder.bind(i),
+            QueryArg::Bool(b) => builder.bind(b),
+            QueryArg::StringArray(arr) => builder.bind(arr),
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use crate::{
+    db::models::audit_models::{AuditEntry, AuditSearchQuery},
+    error::Error,
+};
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Audit log repository
+#[derive(Clone)]
+pub struct AuditLogRepository {
+    pub pool: Arc<PgPool>,
+}
+
+impl AuditLogRepository {
+    /// Create a new audit log repository
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        Self { pool }
+    }
+
+    /// Store a new audit entry
+    pub async fn create(&self, entry: &AuditEntry) -> Result<AuditEntry> {
+        let result = sqlx::query_as::<_, AuditEntry>(
+            r#"
+            INSERT INTO audit_log (
+                id, actor_id, actor_name, action, target_type, target_id, source_ip,
+                details, created_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            RETURNING id, actor_id, actor_name, action, target_type, target_id, source_ip,
+                      details, created_at
+            "#,
+        )
+        .bind(entry.id)
+        .bind(entry.actor_id)
+        .bind(&entry.actor_name)
+        .bind(&entry.action)
+        .bind(&entry.target_type)
+        .bind(&entry.target_id)
+        .bind(&entry.source_ip)
+        .bind(&entry.details)
+        .bind(entry.created_at)
+        .fetch_one(&*self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to create audit entry: {}", e)))?;
+
+        Ok(result)
+    }
+
+    /// Search audit entries with filters, newest first
+    pub async fn search(&self, query: &AuditSearchQuery) -> Result<Vec<AuditEntry>> {
+        // Build dynamic query
+        let mut sql = String::from(
+            r#"
+            SELECT id, actor_id, actor_name, action, target_type, target_id, source_ip,
+                   details, created_at
+            FROM audit_log
+            WHERE 1=1
+            "#,
+        );
+
+        let mut args: Vec<QueryArg> = Vec::new();
+        let mut param_index = 1;
+
+        if let Some(actor_id) = &query.actor_id {
+            sql.push_str(&format!(" AND actor_id = ${}", param_index));
+            args.push(QueryArg::Uuid(*actor_id));
+            param_index += 1;
+        }
+
+        if let Some(actions) = &query.actions {
+            if !actions.is_empty() {
+                sql.push_str(&format!(" AND action = ANY(${})", param_index));
+                args.push(QueryArg::StringArray(actions.clone()));
+                param_index += 1;
+            }
+        }
+
+        if let Some(start_time) = &query.start_time {
+            sql.push_str(&format!(" AND created_at >= ${}", param_index));
+            args.push(QueryArg::DateTime(*start_time));
+            param_index += 1;
+        }
+
+        if let Some(end_time) = &query.end_time {
+            sql.push_str(&format!(" AND created_at <= ${}", param_index));
+            args.push(QueryArg::DateTime(*end_time));
+            param_index += 1;
+        }
+
+        sql.push_str(" ORDER BY created_at DESC");
+
+        if let Some(limit) = &query.limit {
+            sql.push_str(&format!(" LIMIT ${}", param_index));
+            args.push(QueryArg::I64(*limit as i64));
+            param_index += 1;
+        } else {
+            sql.push_str(" LIMIT 1000"); // Default limit
+        }
+
+        if let Some(offset) = &query.offset {
+            sql.push_str(&format!(" OFFSET ${}", param_index));
+            args.push(QueryArg::I64(*offset as i64));
+        }
+
+        let mut query_builder = sqlx::query_as::<_, AuditEntry>(&sql);
+        for arg in args {
+            query_builder = arg.apply_to_query(query_builder);
+        }
+
+        let result = query_builder
+            .fetch_all(&*self.pool)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to search audit log: {}", e)))?;
+
+        Ok(result)
+    }
+}
+
+// Helper enum for dynamic query arguments
+enum QueryArg {
+    Uuid(Uuid),
+    DateTime(DateTime<Utc>),
+    I64(i64),
+    StringArray(Vec<String>),
+}
+
+impl QueryArg {
+    // Apply this argument to a query builder
+    fn apply_to_query<'a, T>(
+        self,
+        builder: sqlx::query::QueryAs<'a, sqlx::Postgres, T, sqlx::postgres::PgArguments>,
+    ) -> sqlx::query::QueryAs<'a, sqlx::Postgres, T, sqlx::postgres::PgArguments> {
+        match self {
+            QueryArg::Uuid(id) => builder.bind(id),
+            QueryArg::DateTime(dt) => builder.bind(dt),
+            QueryArg::I64(i) => builder.bind(i),
+            QueryArg::StringArray(arr) => builder.bind(arr),
+        }
+    }
+}
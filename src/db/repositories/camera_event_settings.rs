@@ -1,4 +1,6 @@
-use crate::{db::models::event_settings_models::EventSettings, error::Error};
+use crate::{
+    db::models::event_settings_models::EventSettings, db::repositories::Repository, error::Error,
+};
 use anyhow::Result;
 use chrono::Utc;
 use sqlx::PgPool;
@@ -30,7 +32,7 @@ impl EventSettingsRepository {
 
         let result = sqlx::query_as::<_, EventSettings>(
             r#"
-            INSERT INTO camera_event_settings (
+            INSERT INTO event_settings (
                 id, camera_id, enabled, event_types, event_topic_expressions,
                 trigger_recording, recording_duration, 
                 created_at, updated_at, created_by
@@ -66,7 +68,7 @@ impl EventSettingsRepository {
             SELECT id, camera_id, enabled, event_types, event_topic_expressions,
                    trigger_recording, recording_duration, 
                    created_at, updated_at, created_by
-            FROM camera_event_settings
+            FROM event_settings
             WHERE id = $1
             "#,
         )
@@ -82,17 +84,19 @@ impl EventSettingsRepository {
 
     /// Get settings by camera ID
     pub async fn get_by_camera_id(&self, camera_id: &Uuid) -> Result<Option<EventSettings>> {
-        let result = sqlx::query_as::<_, EventSettings>(
-            r#"
-            SELECT id, camera_id, enabled, event_types, event_topic_expressions,
-                   trigger_recording, recording_duration, 
-                   created_at, updated_at, created_by
-            FROM camera_event_settings
-            WHERE camera_id = $1
-            "#,
-        )
-        .bind(camera_id)
-        .fetch_optional(&*self.pool)
+        let result = Repository::retry(|| {
+            sqlx::query_as::<_, EventSettings>(
+                r#"
+                SELECT id, camera_id, enabled, event_types, event_topic_expressions,
+                       trigger_recording, recording_duration, 
+                       created_at, updated_at, created_by
+                FROM event_settings
+                WHERE camera_id = $1
+                "#,
+            )
+            .bind(camera_id)
+            .fetch_optional(&*self.pool)
+        })
         .await
         .map_err(|e| {
             Error::Database(format!(
@@ -111,11 +115,11 @@ impl EventSettingsRepository {
 
         let result = sqlx::query_as::<_, EventSettings>(
             r#"
-            UPDATE camera_event_settings
+            UPDATE event_settings
             SET enabled = $1, event_types = $2, event_topic_expressions = $3,
                 trigger_recording = $4, recording_duration = $5, 
-                updated_at = $7
-            WHERE id = $8
+                updated_at = $6
+            WHERE id = $7
             RETURNING id, camera_id, enabled, event_types, event_topic_expressions,
                       trigger_recording, recording_duration,
                       created_at, updated_at, created_by
@@ -135,11 +139,49 @@ impl EventSettingsRepository {
         Ok(EventSettings::from(result))
     }
 
+    /// Create or replace the settings of a camera, keeping the id, creation time and
+    /// creator of settings it already has
+    pub async fn upsert(&self, settings: &EventSettings) -> Result<EventSettings> {
+        let result = sqlx::query_as::<_, EventSettings>(
+            r#"
+            INSERT INTO event_settings (
+                id, camera_id, enabled, event_types, event_topic_expressions,
+                trigger_recording, recording_duration,
+                created_at, updated_at, created_by
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $8, $9)
+            ON CONFLICT (camera_id) DO UPDATE
+            SET enabled = EXCLUDED.enabled, event_types = EXCLUDED.event_types,
+                event_topic_expressions = EXCLUDED.event_topic_expressions,
+                trigger_recording = EXCLUDED.trigger_recording,
+                recording_duration = EXCLUDED.recording_duration,
+                updated_at = EXCLUDED.updated_at
+            RETURNING id, camera_id, enabled, event_types, event_topic_expressions,
+                      trigger_recording, recording_duration,
+                      created_at, updated_at, created_by
+            "#,
+        )
+        .bind(settings.id)
+        .bind(settings.camera_id)
+        .bind(settings.enabled)
+        .bind(&settings.event_types)
+        .bind(&settings.event_topic_expressions)
+        .bind(settings.trigger_recording)
+        .bind(settings.recording_duration)
+        .bind(Utc::now())
+        .bind(settings.created_by)
+        .fetch_one(&*self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to save camera event settings: {}", e)))?;
+
+        Ok(result)
+    }
+
     /// Delete settings
     pub async fn delete(&self, id: &Uuid) -> Result<bool> {
         let result = sqlx::query(
             r#"
-            DELETE FROM camera_event_settings
+            DELETE FROM event_settings
             WHERE id = $1
             "#,
         )
@@ -158,7 +200,7 @@ impl EventSettingsRepository {
             SELECT id, camera_id, enabled, event_types, event_topic_expressions,
                    trigger_recording, recording_duration,  
                    created_at, updated_at, created_by
-            FROM camera_event_settings
+            FROM event_settings
             WHERE enabled = true
             "#,
         )
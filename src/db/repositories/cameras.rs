@@ -1,15 +1,17 @@
 use anyhow::Result;
-use chrono::Utc;
-use sqlx::PgPool;
+use chrono::{DateTime, Utc};
+use sqlx::{Executor, PgConnection, PgPool, Postgres};
 use std::sync::Arc;
 use tracing::info;
 use uuid::Uuid;
 
 use crate::{
     db::models::{
-        camera_models::{Camera, CameraWithStreams},
+        camera_models::{Camera, CameraUpsert, CameraWithStreams},
         stream_models::{ReferenceType, Stream, StreamReference},
     },
+    db::repositories::Repository,
+    security::credentials::{decrypt_credential, encrypt_credential, needs_reencryption},
     Error,
 };
 
@@ -25,7 +27,8 @@ impl CamerasRepository {
         Self { pool }
     }
 
-    /// Create a new camera with streams
+    /// Create a new camera with streams. All rows are written in one transaction, so a
+    /// failed insert leaves no partial camera behind.
     pub async fn create_with_streams(
         &self,
         camera_data: &CameraWithStreams,
@@ -41,13 +44,27 @@ impl CamerasRepository {
             .begin()
             .await
             .map_err(|e| Error::Database(format!("Failed to begin transaction: {}", e)))?;
+        let created = Self::insert_with_streams(&mut tx, camera_data).await?;
+        tx.commit()
+            .await
+            .map_err(|e| Error::Database(format!("Failed to commit transaction: {}", e)))?;
+
+        info!("Successfully created camera with streams");
+        Ok(created)
+    }
 
+    /// Insert a camera with its streams and references on `tx`
+    async fn insert_with_streams(
+        tx: &mut PgConnection,
+        camera_data: &CameraWithStreams,
+    ) -> Result<CameraWithStreams> {
         // Prepare camera data
         let mut camera_db = camera_data.camera.clone();
 
         // Ensure created_at, and updated_at are set
         camera_db.created_at = Utc::now();
         camera_db.updated_at = Utc::now();
+        encrypt_password(&mut camera_db)?;
 
         // Insert camera
         let camera_result = sqlx::query_as::<_, Camera>(
@@ -66,11 +83,11 @@ impl CamerasRepository {
                 line_crossing_supported, zone_intrusion_supported,
                 object_classification_supported, behavior_analysis_supported,
                 capabilities, profiles, last_updated, 
-                created_at, updated_at
+                created_at, updated_at, onvif_auth_type
             )
             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, 
                    $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29,
-                   $30, $31, $32, $33, $34, $35, $36, $37, $38, $39, $40, $41, $42)
+                   $30, $31, $32, $33, $34, $35, $36, $37, $38, $39, $40, $41, $42, $43)
             RETURNING *
             "#,
         )
@@ -116,9 +133,11 @@ impl CamerasRepository {
         .bind(camera_db.last_updated)
         .bind(camera_db.created_at)
         .bind(camera_db.updated_at)
+        .bind(&camera_db.onvif_auth_type)
         .fetch_one(&mut *tx)
         .await
         .map_err(|e| Error::Database(format!("Failed to create camera: {}", e)))?;
+        let camera_result = decrypt_password(camera_result)?;
 
         // Create streams and references
         let mut streams = Vec::new();
@@ -144,10 +163,10 @@ impl CamerasRepository {
                     quality_level, transport_protocol, authentication_required,
                     is_primary, is_audio_enabled, audio_codec, audio_bitrate,
                     audio_channels, audio_sample_rate, is_active, last_connected_at,
-                    created_at, updated_at
+                    created_at, updated_at, has_audio
                 )
                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, 
-                        $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28)
+                        $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29)
                 RETURNING *
                 "#,
             )
@@ -179,6 +198,7 @@ impl CamerasRepository {
             .bind(stream_db.last_connected_at)
             .bind(stream_db.created_at)
             .bind(stream_db.updated_at)
+            .bind(stream_db.has_audio)
             .fetch_one(&mut *tx)
             .await
             .map_err(|e| Error::Database(format!("Failed to create stream: {}", e)))?;
@@ -260,12 +280,6 @@ impl CamerasRepository {
             })?;
         }
 
-        // Commit transaction - This was inside the loop, but should be outside
-        tx.commit()
-            .await
-            .map_err(|e| Error::Database(format!("Failed to commit transaction: {}", e)))?;
-
-        info!("Successfully created camera with streams");
         // Return the created camera with streams - This was inside the loop, but should be outside
         Ok(CameraWithStreams {
             camera: camera_result,
@@ -280,7 +294,7 @@ impl CamerasRepository {
         let camera_result = sqlx::query_as::<_, Camera>(
             r#"
             SELECT * FROM cameras
-            WHERE id = $1
+            WHERE id = $1 AND deleted_at IS NULL
             "#,
         )
         .bind(id)
@@ -290,7 +304,7 @@ impl CamerasRepository {
 
         // If camera not found, return None
         let camera = match camera_result {
-            Some(c) => c,
+            Some(c) => decrypt_password(c)?,
             None => return Ok(None),
         };
 
@@ -330,34 +344,113 @@ impl CamerasRepository {
 
     /// Get camera by ID
     pub async fn get_by_id(&self, id: &Uuid) -> Result<Option<Camera>> {
+        let result = Repository::retry(|| {
+            sqlx::query_as::<_, Camera>(
+                r#"
+                SELECT * FROM cameras
+                WHERE id = $1 AND deleted_at IS NULL
+                "#,
+            )
+            .bind(id)
+            .fetch_optional(&*self.pool)
+        })
+        .await
+        .map_err(|e| Error::Database(format!("Failed to get camera by ID: {}", e)))?;
+
+        result.map(decrypt_password).transpose()
+    }
+
+    /// Get camera by IP address
+    pub async fn get_by_ip(&self, ip_address: &str) -> Result<Option<Camera>> {
         let result = sqlx::query_as::<_, Camera>(
             r#"
             SELECT * FROM cameras
-            WHERE id = $1
+            WHERE ip_address = $1 AND deleted_at IS NULL
             "#,
         )
-        .bind(id)
+        .bind(ip_address)
         .fetch_optional(&*self.pool)
         .await
-        .map_err(|e| Error::Database(format!("Failed to get camera by ID: {}", e)))?;
+        .map_err(|e| Error::Database(format!("Failed to get camera by IP: {}", e)))?;
 
-        Ok(result)
+        result.map(decrypt_password).transpose()
     }
 
-    /// Get camera by IP address
-    pub async fn get_by_ip(&self, ip_address: &str) -> Result<Option<Camera>> {
+    /// Get camera by serial number
+    pub async fn get_by_serial_number(&self, serial_number: &str) -> Result<Option<Camera>> {
         let result = sqlx::query_as::<_, Camera>(
             r#"
             SELECT * FROM cameras
-            WHERE ip_address = $1
+            WHERE serial_number = $1 AND deleted_at IS NULL
+            ORDER BY created_at
+            LIMIT 1
             "#,
         )
-        .bind(ip_address)
+        .bind(serial_number)
         .fetch_optional(&*self.pool)
         .await
-        .map_err(|e| Error::Database(format!("Failed to get camera by IP: {}", e)))?;
+        .map_err(|e| Error::Database(format!("Failed to get camera by serial number: {}", e)))?;
 
-        Ok(result)
+        result.map(decrypt_password).transpose()
+    }
+
+    /// Register a discovered camera. A camera with the same serial number, or failing
+    /// that the same IP address, is updated and its streams reconciled instead of
+    /// inserting a duplicate.
+    pub async fn upsert_with_streams(
+        &self,
+        discovered: &CameraWithStreams,
+    ) -> Result<CameraUpsert> {
+        let by_serial = match discovered.camera.serial_number.as_deref() {
+            Some(serial) if !serial.trim().is_empty() => self.get_by_serial_number(serial).await?,
+            _ => None,
+        };
+        let existing = match by_serial {
+            Some(camera) => Some(camera),
+            None => self.get_by_ip(&discovered.camera.ip_address).await?,
+        };
+        let existing = match existing {
+            Some(camera) => self.get_with_streams_by_id(&camera.id).await?,
+            None => None,
+        };
+
+        let Some(existing) = existing else {
+            return Ok(CameraUpsert {
+                camera: self.create_with_streams(discovered).await?,
+                created: true,
+                stale_stream_ids: Vec::new(),
+            });
+        };
+
+        info!("Updating already registered camera {}", existing.camera.id);
+        let (merged, stale_stream_ids, stale_reference_ids) =
+            merge_discovered(&existing, discovered);
+        // The refresh and retiring stale streams land together or not at all
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| Error::Database(format!("Failed to begin transaction: {}", e)))?;
+        sqlx::query("DELETE FROM stream_references WHERE id = ANY($1)")
+            .bind(&stale_reference_ids)
+            .execute(&mut *tx)
+            .await
+            .map_err(|e| {
+                Error::Database(format!("Failed to delete stale stream references: {}", e))
+            })?;
+        let camera = Self::write_update_with_streams(&mut tx, &merged).await?;
+        for stream_id in &stale_stream_ids {
+            set_stream_status(&mut *tx, stream_id, false).await?;
+        }
+        tx.commit()
+            .await
+            .map_err(|e| Error::Database(format!("Failed to commit transaction: {}", e)))?;
+
+        Ok(CameraUpsert {
+            camera,
+            created: false,
+            stale_stream_ids,
+        })
     }
 
     /// Update camera
@@ -365,6 +458,7 @@ impl CamerasRepository {
         // Prepare updated camera data
         let mut camera_db = camera.clone();
         camera_db.updated_at = Utc::now();
+        encrypt_password(&mut camera_db)?;
 
         let result = sqlx::query_as::<_, Camera>(
             r#"
@@ -382,8 +476,9 @@ impl CamerasRepository {
                 license_plate_recognition_supported = $32, person_tracking_supported = $33,
                 line_crossing_supported = $34, zone_intrusion_supported = $35,
                 object_classification_supported = $36, behavior_analysis_supported = $37,
-                capabilities = $38, profiles = $39, last_updated = $40
-            WHERE id = $41
+                capabilities = $38, profiles = $39, last_updated = $40,
+                onvif_auth_type = $41
+            WHERE id = $42
             RETURNING *
             "#,
         )
@@ -427,15 +522,17 @@ impl CamerasRepository {
         .bind(&camera_db.capabilities)
         .bind(&camera_db.profiles)
         .bind(camera_db.last_updated)
+        .bind(&camera_db.onvif_auth_type)
         .bind(camera_db.id)
         .fetch_one(&*self.pool)
         .await
         .map_err(|e| Error::Database(format!("Failed to update camera: {}", e)))?;
+        let result = decrypt_password(result)?;
 
         Ok(result)
     }
 
-    /// Update camera with streams
+    /// Update camera with streams, in one transaction
     pub async fn update_with_streams(
         &self,
         camera_data: &CameraWithStreams,
@@ -446,10 +543,23 @@ impl CamerasRepository {
             .begin()
             .await
             .map_err(|e| Error::Database(format!("Failed to begin transaction: {}", e)))?;
+        let updated = Self::write_update_with_streams(&mut tx, camera_data).await?;
+        tx.commit()
+            .await
+            .map_err(|e| Error::Database(format!("Failed to commit transaction: {}", e)))?;
 
+        Ok(updated)
+    }
+
+    /// Update a camera and upsert its streams and references on `tx`
+    async fn write_update_with_streams(
+        tx: &mut PgConnection,
+        camera_data: &CameraWithStreams,
+    ) -> Result<CameraWithStreams> {
         // Update camera
         let mut camera_db = camera_data.camera.clone();
         camera_db.updated_at = Utc::now();
+        encrypt_password(&mut camera_db)?;
 
         let camera_result = sqlx::query_as::<_, Camera>(
             r#"
@@ -467,8 +577,9 @@ impl CamerasRepository {
                 license_plate_recognition_supported = $32, person_tracking_supported = $33,
                 line_crossing_supported = $34, zone_intrusion_supported = $35,
                 object_classification_supported = $36, behavior_analysis_supported = $37,
-                capabilities = $38, profiles = $39, last_updated = $40
-            WHERE id = $41
+                capabilities = $38, profiles = $39, last_updated = $40,
+                onvif_auth_type = $41
+            WHERE id = $42
             RETURNING *
             "#,
         )
@@ -512,10 +623,12 @@ impl CamerasRepository {
         .bind(&camera_db.capabilities)
         .bind(&camera_db.profiles)
         .bind(camera_db.last_updated)
+        .bind(&camera_db.onvif_auth_type)
         .bind(camera_db.id)
         .fetch_one(&mut *tx)
         .await
         .map_err(|e| Error::Database(format!("Failed to update camera: {}", e)))?;
+        let camera_result = decrypt_password(camera_result)?;
 
         // Update or insert streams
         let mut updated_streams = Vec::new();
@@ -546,8 +659,9 @@ impl CamerasRepository {
                         transport_protocol = $15, authentication_required = $16,
                         is_primary = $17, is_audio_enabled = $18, audio_codec = $19,
                         audio_bitrate = $20, audio_channels = $21, audio_sample_rate = $22,
-                        is_active = $23, last_connected_at = $24, updated_at = $25
-                    WHERE id = $26 AND camera_id = $27
+                        is_active = $23, last_connected_at = $24, updated_at = $25,
+                        has_audio = $26
+                    WHERE id = $27 AND camera_id = $28
                     RETURNING *
                     "#,
                 )
@@ -576,6 +690,7 @@ impl CamerasRepository {
                 .bind(stream_db.is_active)
                 .bind(stream_db.last_connected_at)
                 .bind(stream_db.updated_at)
+                .bind(stream_db.has_audio)
                 .bind(stream_db.id)
                 .bind(stream_db.camera_id)
                 .fetch_one(&mut *tx)
@@ -593,10 +708,10 @@ impl CamerasRepository {
                         quality_level, transport_protocol, authentication_required,
                         is_primary, is_audio_enabled, audio_codec, audio_bitrate,
                         audio_channels, audio_sample_rate, is_active, last_connected_at,
-                        created_at, updated_at
+                        created_at, updated_at, has_audio
                     )
                     VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, 
-                            $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28)
+                            $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29)
                     RETURNING *
                     "#,
                 )
@@ -628,6 +743,7 @@ impl CamerasRepository {
                 .bind(stream_db.last_connected_at)
                 .bind(stream_db.created_at)
                 .bind(stream_db.updated_at)
+                .bind(stream_db.has_audio)
                 .fetch_one(&mut *tx)
                 .await
                 .map_err(|e| Error::Database(format!("Failed to create stream: {}", e)))?
@@ -732,11 +848,6 @@ impl CamerasRepository {
             .map_err(|e| Error::Database(format!("Failed to update camera stream IDs: {}", e)))?;
         }
 
-        // Commit transaction
-        tx.commit()
-            .await
-            .map_err(|e| Error::Database(format!("Failed to commit transaction: {}", e)))?;
-
         Ok(CameraWithStreams {
             camera: camera_result,
             streams: updated_streams,
@@ -744,8 +855,61 @@ impl CamerasRepository {
         })
     }
 
-    /// Delete camera and all associated streams and references
+    /// Soft-delete a camera: it disappears from normal queries until it is restored or
+    /// purged. Its streams and references are kept so a restore brings them back.
     pub async fn delete(&self, id: &Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE cameras
+            SET deleted_at = $2, status = 'inactive', updated_at = $2
+            WHERE id = $1 AND deleted_at IS NULL
+            "#,
+        )
+        .bind(id)
+        .bind(Utc::now())
+        .execute(&*self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to delete camera: {}", e)))?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Undo a soft delete. Returns false when the camera doesn't exist or isn't deleted.
+    pub async fn restore(&self, id: &Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE cameras
+            SET deleted_at = NULL, updated_at = $2
+            WHERE id = $1 AND deleted_at IS NOT NULL
+            "#,
+        )
+        .bind(id)
+        .bind(Utc::now())
+        .execute(&*self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to restore camera: {}", e)))?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Cameras soft-deleted before `cutoff`, due to be purged
+    pub async fn get_deleted_before(&self, cutoff: DateTime<Utc>) -> Result<Vec<Camera>> {
+        let result = sqlx::query_as::<_, Camera>(
+            r#"
+            SELECT * FROM cameras
+            WHERE deleted_at < $1
+            "#,
+        )
+        .bind(cutoff)
+        .fetch_all(&*self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to get deleted cameras: {}", e)))?;
+
+        result.into_iter().map(decrypt_password).collect()
+    }
+
+    /// Permanently delete camera and all associated streams and references
+    pub async fn purge(&self, id: &Uuid) -> Result<bool> {
         // Begin transaction
         let mut tx = self
             .pool
@@ -804,17 +968,20 @@ impl CamerasRepository {
 
     /// Get all cameras
     pub async fn get_all(&self) -> Result<Vec<Camera>> {
-        let result = sqlx::query_as::<_, Camera>(
-            r#"
-            SELECT * FROM cameras
-            ORDER BY name
-            "#,
-        )
-        .fetch_all(&*self.pool)
+        let result = Repository::retry(|| {
+            sqlx::query_as::<_, Camera>(
+                r#"
+                SELECT * FROM cameras
+                WHERE deleted_at IS NULL
+                ORDER BY name
+                "#,
+            )
+            .fetch_all(&*self.pool)
+        })
         .await
         .map_err(|e| Error::Database(format!("Failed to get all cameras: {}", e)))?;
 
-        Ok(result)
+        result.into_iter().map(decrypt_password).collect()
     }
 
     /// Get all cameras with their streams
@@ -840,7 +1007,7 @@ impl CamerasRepository {
         let result = sqlx::query_as::<_, Camera>(
             r#"
             SELECT * FROM cameras
-            WHERE status = 'active'
+            WHERE status = 'active' AND deleted_at IS NULL
             ORDER BY name
             LIMIT 1
             "#,
@@ -849,7 +1016,7 @@ impl CamerasRepository {
         .await
         .map_err(|e| Error::Database(format!("Failed to get first active camera: {}", e)))?;
 
-        Ok(result)
+        result.map(decrypt_password).transpose()
     }
 
     /// Get first active camera with streams
@@ -865,6 +1032,25 @@ impl CamerasRepository {
         Ok(None)
     }
 
+    /// Switch whether the camera is recorded from a pipeline of its own
+    pub async fn set_isolated_recording(&self, id: &Uuid, isolated: bool) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE cameras
+            SET isolated_recording = $1, updated_at = $2
+            WHERE id = $3
+            "#,
+        )
+        .bind(isolated)
+        .bind(Utc::now())
+        .bind(id)
+        .execute(&*self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to set isolated recording: {}", e)))?;
+
+        Ok(())
+    }
+
     /// Update camera status
     pub async fn update_status(&self, id: &Uuid, status: &str) -> Result<()> {
         sqlx::query(
@@ -886,14 +1072,16 @@ impl CamerasRepository {
 
     /// Get camera streams
     pub async fn get_streams(&self, camera_id: &Uuid) -> Result<Vec<Stream>> {
-        let result = sqlx::query_as::<_, Stream>(
-            r#"
-            SELECT * FROM streams
-            WHERE camera_id = $1
-            "#,
-        )
-        .bind(camera_id)
-        .fetch_all(&*self.pool)
+        let result = Repository::retry(|| {
+            sqlx::query_as::<_, Stream>(
+                r#"
+                SELECT * FROM streams
+                WHERE camera_id = $1
+                "#,
+            )
+            .bind(camera_id)
+            .fetch_all(&*self.pool)
+        })
         .await
         .map_err(|e| Error::Database(format!("Failed to get camera streams: {}", e)))?;
 
@@ -902,16 +1090,76 @@ impl CamerasRepository {
 
     /// Get camera stream by ID
     pub async fn get_stream_by_id(&self, stream_id: &Uuid) -> Result<Option<Stream>> {
+        let result = Repository::retry(|| {
+            sqlx::query_as::<_, Stream>(
+                r#"
+                SELECT * FROM streams
+                WHERE id = $1
+                "#,
+            )
+            .bind(stream_id)
+            .fetch_optional(&*self.pool)
+        })
+        .await
+        .map_err(|e| Error::Database(format!("Failed to get stream by ID: {}", e)))?;
+
+        Ok(result)
+    }
+
+    /// Add a stream to an existing camera
+    pub async fn create_stream(&self, stream: &Stream) -> Result<Stream> {
+        let mut stream_db = stream.clone();
+        stream_db.created_at = Utc::now();
+        stream_db.updated_at = Utc::now();
+
         let result = sqlx::query_as::<_, Stream>(
             r#"
-            SELECT * FROM streams
-            WHERE id = $1
+            INSERT INTO streams (
+                id, camera_id, name, stream_type, url,
+                resolution, width, height, codec, profile, level,
+                framerate, bitrate, variable_bitrate, keyframe_interval,
+                quality_level, transport_protocol, authentication_required,
+                is_primary, is_audio_enabled, audio_codec, audio_bitrate,
+                audio_channels, audio_sample_rate, is_active, last_connected_at,
+                created_at, updated_at, has_audio
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14,
+                    $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29)
+            RETURNING *
             "#,
         )
-        .bind(stream_id)
-        .fetch_optional(&*self.pool)
+        .bind(stream_db.id)
+        .bind(stream_db.camera_id)
+        .bind(&stream_db.name)
+        .bind(&stream_db.stream_type.to_string()) // Convert enum to string
+        .bind(&stream_db.url)
+        .bind(&stream_db.resolution)
+        .bind(stream_db.width)
+        .bind(stream_db.height)
+        .bind(&stream_db.codec)
+        .bind(&stream_db.profile)
+        .bind(&stream_db.level)
+        .bind(stream_db.framerate)
+        .bind(stream_db.bitrate)
+        .bind(stream_db.variable_bitrate)
+        .bind(stream_db.keyframe_interval)
+        .bind(&stream_db.quality_level)
+        .bind(&stream_db.transport_protocol)
+        .bind(stream_db.authentication_required)
+        .bind(stream_db.is_primary)
+        .bind(stream_db.is_audio_enabled)
+        .bind(&stream_db.audio_codec)
+        .bind(stream_db.audio_bitrate)
+        .bind(stream_db.audio_channels)
+        .bind(stream_db.audio_sample_rate)
+        .bind(stream_db.is_active)
+        .bind(stream_db.last_connected_at)
+        .bind(stream_db.created_at)
+        .bind(stream_db.updated_at)
+        .bind(stream_db.has_audio)
+        .fetch_one(&*self.pool)
         .await
-        .map_err(|e| Error::Database(format!("Failed to get stream by ID: {}", e)))?;
+        .map_err(|e| Error::Database(format!("Failed to create stream: {}", e)))?;
 
         Ok(result)
     }
@@ -932,8 +1180,9 @@ impl CamerasRepository {
                 transport_protocol = $15, authentication_required = $16,
                 is_primary = $17, is_audio_enabled = $18, audio_codec = $19,
                 audio_bitrate = $20, audio_channels = $21, audio_sample_rate = $22,
-                is_active = $23, last_connected_at = $24, updated_at = $25
-            WHERE id = $26 AND camera_id = $27
+                is_active = $23, last_connected_at = $24, updated_at = $25,
+                has_audio = $26
+            WHERE id = $27 AND camera_id = $28
             RETURNING *
             "#,
         )
@@ -962,6 +1211,7 @@ impl CamerasRepository {
         .bind(stream_db.is_active)
         .bind(stream_db.last_connected_at)
         .bind(stream_db.updated_at)
+        .bind(stream_db.has_audio)
         .bind(stream_db.id)
         .bind(stream_db.camera_id)
         .fetch_one(&*self.pool)
@@ -1014,20 +1264,443 @@ impl CamerasRepository {
 
     /// Update camera stream status
     pub async fn update_stream_status(&self, stream_id: &Uuid, is_active: bool) -> Result<()> {
-        sqlx::query(
+        set_stream_status(&*self.pool, stream_id, is_active).await
+    }
+
+    /// Encrypt any camera passwords still stored in plaintext.
+    ///
+    /// Rows written before credential encryption was introduced are rewritten in place;
+    /// already encrypted rows are left alone, so this is safe to run on every startup.
+    pub async fn encrypt_plaintext_passwords(&self) -> Result<usize> {
+        let rows = sqlx::query_as::<_, (Uuid, String)>(
             r#"
-            UPDATE streams
-            SET is_active = $1, updated_at = $2
-            WHERE id = $3
+            SELECT id, password FROM cameras
+            WHERE password IS NOT NULL AND password NOT LIKE 'enc:v1:%'
             "#,
         )
-        .bind(is_active)
-        .bind(Utc::now())
-        .bind(stream_id)
-        .execute(&*self.pool)
+        .fetch_all(&*self.pool)
         .await
-        .map_err(|e| Error::Database(format!("Failed to update stream status: {}", e)))?;
+        .map_err(|e| Error::Database(format!("Failed to get plaintext camera passwords: {}", e)))?;
+
+        for (id, password) in &rows {
+            sqlx::query(
+                r#"
+                UPDATE cameras
+                SET password = $1
+                WHERE id = $2
+                "#,
+            )
+            .bind(encrypt_credential(password)?)
+            .bind(id)
+            .execute(&*self.pool)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to encrypt camera password: {}", e)))?;
+        }
+
+        if !rows.is_empty() {
+            info!("Encrypted {} plaintext camera passwords", rows.len());
+        }
+
+        Ok(rows.len())
+    }
+
+    /// Re-encrypt camera passwords still encrypted under the previous credential key.
+    ///
+    /// Rows written before the key changed keep decrypting through the previous key, and are
+    /// rewritten under the current one so the previous key can eventually be retired.
+    pub async fn reencrypt_stale_passwords(&self) -> Result<usize> {
+        let rows = sqlx::query_as::<_, (Uuid, String)>(
+            r#"
+            SELECT id, password FROM cameras
+            WHERE password LIKE 'enc:v1:%'
+            "#,
+        )
+        .fetch_all(&*self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to get encrypted camera passwords: {}", e)))?;
+
+        let mut reencrypted = 0;
+        for (id, password) in &rows {
+            if !needs_reencryption(password)? {
+                continue;
+            }
+            sqlx::query(
+                r#"
+                UPDATE cameras
+                SET password = $1
+                WHERE id = $2
+                "#,
+            )
+            .bind(encrypt_credential(&decrypt_credential(password)?)?)
+            .bind(id)
+            .execute(&*self.pool)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to re-encrypt camera password: {}", e)))?;
+            reencrypted += 1;
+        }
+
+        if reencrypted > 0 {
+            info!(
+                "Re-encrypted {} camera passwords under the current key",
+                reencrypted
+            );
+        }
+
+        Ok(reencrypted)
+    }
+}
+
+// Encrypt the password of a camera about to be written
+fn encrypt_password(camera: &mut Camera) -> Result<()> {
+    if let Some(password) = &camera.password {
+        camera.password = Some(encrypt_credential(password)?);
+    }
+    Ok(())
+}
+
+// Decrypt the password of a camera read back from the database
+fn decrypt_password(mut camera: Camera) -> Result<Camera> {
+    if let Some(password) = &camera.password {
+        camera.password = Some(decrypt_credential(password)?);
+    }
+    Ok(camera)
+}
+
+/// Set a stream's `is_active` flag, on the pool or inside a transaction
+async fn set_stream_status<'c, E>(executor: E, stream_id: &Uuid, is_active: bool) -> Result<()>
+where
+    E: Executor<'c, Database = Postgres>,
+{
+    sqlx::query(
+        r#"
+        UPDATE streams
+        SET is_active = $1, updated_at = $2
+        WHERE id = $3
+        "#,
+    )
+    .bind(is_active)
+    .bind(Utc::now())
+    .bind(stream_id)
+    .execute(executor)
+    .await
+    .map_err(|e| Error::Database(format!("Failed to update stream status: {}", e)))?;
+
+    Ok(())
+}
+
+/// Apply what was discovered on a device to the camera already registered for it.
+///
+/// Settings that only exist here (name, recording mode, retention...) are kept. A
+/// discovered stream takes over the ID of the existing stream with the same URL or name,
+/// so its recordings and pipeline stay attached, and a stream reference the ID of the
+/// existing reference of the same type. Returns the merged camera, the IDs of existing
+/// streams the device no longer reports and those of references it no longer has.
+fn merge_discovered(
+    existing: &CameraWithStreams,
+    discovered: &CameraWithStreams,
+) -> (CameraWithStreams, Vec<Uuid>, Vec<Uuid>) {
+    let found = &discovered.camera;
+    let mut camera = existing.camera.clone();
+    camera.username = found.username.clone();
+    camera.password = found.password.clone();
+    camera.onvif_auth_type = found.onvif_auth_type.clone();
+    camera.onvif_endpoint = found.onvif_endpoint.clone();
+    camera.manufacturer = found.manufacturer.clone();
+    camera.model = found.model.clone();
+    camera.ip_address = found.ip_address.clone();
+    camera.firmware_version = found.firmware_version.clone();
+    camera.serial_number = found.serial_number.clone();
+    camera.hardware_id = found.hardware_id.clone();
+
+    let mut unmatched: Vec<&Stream> = existing.streams.iter().collect();
+    let mut ids = std::collections::HashMap::new();
+    let streams = discovered
+        .streams
+        .iter()
+        .map(|stream| {
+            let mut merged = stream.clone();
+            merged.camera_id = camera.id;
+            let matched = unmatched
+                .iter()
+                .position(|old| old.url == stream.url)
+                .or_else(|| unmatched.iter().position(|old| old.name == stream.name));
+            if let Some(index) = matched {
+                let old = unmatched.remove(index);
+                merged.id = old.id;
+                merged.is_active = old.is_active;
+                merged.created_at = old.created_at;
+            }
+            ids.insert(stream.id, merged.id);
+            merged
+        })
+        .collect();
+
+    let stream_references = discovered
+        .stream_references
+        .iter()
+        .filter_map(|reference| {
+            let mut merged = reference.clone();
+            merged.camera_id = camera.id;
+            merged.stream_id = *ids.get(&reference.stream_id)?;
+            // A camera has one reference of each type, whichever stream it points at now
+            if let Some(old) = existing
+                .stream_references
+                .iter()
+                .find(|old| old.reference_type == merged.reference_type)
+            {
+                merged.id = old.id;
+                merged.created_at = old.created_at;
+            }
+            Some(merged)
+        })
+        .collect::<Vec<StreamReference>>();
+
+    let stale_streams = unmatched.iter().map(|stream| stream.id).collect();
+    let stale_references = existing
+        .stream_references
+        .iter()
+        .filter(|old| !stream_references.iter().any(|new| new.id == old.id))
+        .map(|old| old.id)
+        .collect();
+    (
+        CameraWithStreams {
+            camera,
+            streams,
+            stream_references,
+        },
+        stale_streams,
+        stale_references,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::security::credentials::init_credential_key;
+
+    #[tokio::test]
+    async fn test_camera_password_is_encrypted_at_rest() -> Result<()> {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else {
+            println!("Skipping camera credential test. Set TEST_DATABASE_URL to run.");
+            return Ok(());
+        };
+
+        init_credential_key("test-credential-key");
+        let pool = Arc::new(PgPool::connect(&database_url).await?);
+        let repo = CamerasRepository::new(pool.clone());
+
+        let mut camera = Camera::default();
+        camera.name = "credential-test".to_string();
+        camera.ip_address = format!("test-{}", camera.id);
+        camera.password = Some("s3cret!".to_string());
+
+        let created = repo
+            .create_with_streams(&CameraWithStreams {
+                camera,
+                streams: vec![],
+                stream_references: vec![],
+            })
+            .await?;
+        let id = created.camera.id;
+
+        let (stored,): (String,) = sqlx::query_as("SELECT password FROM cameras WHERE id = $1")
+            .bind(id)
+            .fetch_one(&*pool)
+            .await?;
+        let read_back = repo.get_by_id(&id).await?;
+        repo.purge(&id).await?;
+
+        assert_ne!(stored, "s3cret!");
+        assert_eq!(created.camera.password.as_deref(), Some("s3cret!"));
+        assert_eq!(read_back.and_then(|c| c.password).as_deref(), Some("s3cret!"));
+        Ok(())
+    }
+
+    /// A camera as `camera_connect` builds it from ONVIF, with fresh IDs every time
+    fn discovered_camera(serial: &str) -> CameraWithStreams {
+        let mut camera = Camera::default();
+        camera.name = "upsert-test".to_string();
+        camera.ip_address = format!("test-{}", serial);
+        camera.serial_number = Some(serial.to_string());
+
+        let mut streams = Vec::new();
+        let mut stream_references = Vec::new();
+        let kinds = [
+            ("main", ReferenceType::Primary),
+            ("sub", ReferenceType::Sub),
+        ];
+        for (i, (name, reference_type)) in kinds.into_iter().enumerate() {
+            let mut stream = Stream::default();
+            stream.camera_id = camera.id;
+            stream.name = name.to_string();
+            stream.url = format!("rtsp://{}/{}", camera.ip_address, name);
+            stream.is_primary = Some(i == 0);
+            stream_references.push(StreamReference {
+                id: Uuid::new_v4(),
+                camera_id: camera.id,
+                stream_id: stream.id,
+                reference_type,
+                display_order: Some(i as i32),
+                is_default: Some(i == 0),
+                created_at: Utc::now(),
+                updated_at: Utc::now(),
+            });
+            streams.push(stream);
+        }
+
+        CameraWithStreams {
+            camera,
+            streams,
+            stream_references,
+        }
+    }
+
+    #[test]
+    fn merged_references_keep_the_id_of_their_type() {
+        let existing = discovered_camera("merge");
+        // The device now serves the old sub stream as its primary and dropped the other
+        let mut discovered = discovered_camera("merge");
+        discovered.streams[0].url = existing.streams[1].url.clone();
+        discovered.streams.truncate(1);
+        discovered.stream_references.truncate(1);
+
+        let (merged, stale_streams, stale_references) = merge_discovered(&existing, &discovered);
+
+        assert_eq!(merged.stream_references.len(), 1);
+        let primary = &merged.stream_references[0];
+        assert_eq!(primary.reference_type, ReferenceType::Primary);
+        assert_eq!(primary.id, existing.stream_references[0].id);
+        assert_eq!(primary.stream_id, existing.streams[1].id);
+        assert_eq!(stale_streams, vec![existing.streams[0].id]);
+        assert_eq!(stale_references, vec![existing.stream_references[1].id]);
+    }
+
+    #[tokio::test]
+    async fn test_connecting_the_same_serial_twice_updates_the_camera() -> Result<()> {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else {
+            println!("Skipping camera upsert test. Set TEST_DATABASE_URL to run.");
+            return Ok(());
+        };
+
+        init_credential_key("test-credential-key");
+        let pool = Arc::new(PgPool::connect(&database_url).await?);
+        let repo = CamerasRepository::new(pool.clone());
+        let serial = format!("serial-{}", Uuid::new_v4());
+
+        let first = repo
+            .upsert_with_streams(&discovered_camera(&serial))
+            .await?;
+        let mut again = discovered_camera(&serial);
+        again.camera.firmware_version = Some("2.0".to_string());
+        let second = repo.upsert_with_streams(&again).await?;
+
+        let (cameras,): (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM cameras WHERE serial_number = $1")
+                .bind(&serial)
+                .fetch_one(&*pool)
+                .await?;
+        let id = first.camera.camera.id;
+        let streams = repo.get_streams(&id).await?;
+        let stored = repo.get_by_id(&id).await?;
+        repo.purge(&id).await?;
+
+        assert!(first.created && !second.created);
+        assert_eq!(cameras, 1);
+        assert_eq!(second.camera.camera.id, id);
+        assert_eq!(streams.len(), 2);
+        let mut first_ids: Vec<Uuid> = first.camera.streams.iter().map(|s| s.id).collect();
+        let mut second_ids: Vec<Uuid> = second.camera.streams.iter().map(|s| s.id).collect();
+        first_ids.sort();
+        second_ids.sort();
+        assert_eq!(first_ids, second_ids);
+        assert!(second.stale_stream_ids.is_empty());
+        assert_eq!(
+            stored.and_then(|c| c.firmware_version).as_deref(),
+            Some("2.0")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_failed_stream_insert_leaves_no_camera_behind() -> Result<()> {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else {
+            println!("Skipping camera transaction test. Set TEST_DATABASE_URL to run.");
+            return Ok(());
+        };
+
+        init_credential_key("test-credential-key");
+        let pool = Arc::new(PgPool::connect(&database_url).await?);
+        let repo = CamerasRepository::new(pool.clone());
+
+        // The second stream reuses the first one's id, so its insert fails after the camera
+        // and the first stream were written
+        let mut broken = discovered_camera(&format!("serial-{}", Uuid::new_v4()));
+        broken.streams[1].id = broken.streams[0].id;
+        let id = broken.camera.id;
+
+        let err = repo.create_with_streams(&broken).await.unwrap_err();
+        let (cameras,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM cameras WHERE id = $1")
+            .bind(id)
+            .fetch_one(&*pool)
+            .await?;
+        let (streams,): (i64,) =
+            sqlx::query_as("SELECT COUNT(*) FROM streams WHERE camera_id = $1")
+                .bind(id)
+                .fetch_one(&*pool)
+                .await?;
+
+        assert!(
+            err.to_string().contains("Failed to create stream"),
+            "{}",
+            err
+        );
+        assert_eq!(cameras, 0);
+        assert_eq!(streams, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_soft_deleted_camera_is_hidden_until_restored() -> Result<()> {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else {
+            println!("Skipping soft delete test. Set TEST_DATABASE_URL to run.");
+            return Ok(());
+        };
+
+        init_credential_key("test-credential-key");
+        let pool = Arc::new(PgPool::connect(&database_url).await?);
+        let repo = CamerasRepository::new(pool.clone());
+
+        let mut camera = Camera::default();
+        camera.name = "soft-delete-test".to_string();
+        camera.ip_address = format!("test-{}", camera.id);
+        let id = repo
+            .create_with_streams(&CameraWithStreams {
+                camera,
+                streams: vec![],
+                stream_references: vec![],
+            })
+            .await?
+            .camera
+            .id;
+        let listed = |cameras: Vec<CameraWithStreams>| cameras.iter().any(|c| c.camera.id == id);
+
+        assert!(repo.delete(&id).await?);
+        let hidden = !listed(repo.get_all_with_streams().await?);
+        let gone = repo.get_by_id(&id).await?.is_none();
+        let due = repo
+            .get_deleted_before(Utc::now())
+            .await?
+            .iter()
+            .any(|c| c.id == id);
+
+        let restored = repo.restore(&id).await?;
+        let listed_again = listed(repo.get_all_with_streams().await?);
+        let restored_twice = repo.restore(&id).await?;
+        repo.purge(&id).await?;
 
+        assert!(hidden && gone && due);
+        assert!(restored && listed_again);
+        assert!(!restored_twice);
         Ok(())
     }
 }
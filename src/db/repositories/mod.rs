@@ -1,13 +1,109 @@
+use crate::config::DatabaseConfig;
+use once_cell::sync::OnceCell;
 use sqlx::PgPool;
+use std::future::Future;
 use std::sync::Arc;
+use std::time::Duration;
+use tracing::warn;
 
+pub mod analytics_events;
+pub mod audit_log;
 pub mod camera_event_settings;
 pub mod cameras;
 pub mod events;
+pub mod recording_leases;
 pub mod recordings;
 pub mod schedules;
 pub mod users;
 
+/// Longest wait between two attempts, however many retries came before
+const MAX_RETRY_BACKOFF: Duration = Duration::from_secs(5);
+
+// Retry policy from the database config, set once at startup
+static RETRY_POLICY: OnceCell<RetryPolicy> = OnceCell::new();
+
+/// How repository operations retry transient database errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first
+    pub attempts: u32,
+    /// Wait before the first retry, doubled before each one after it
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 3,
+            backoff: Duration::from_millis(100),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn from_config(config: &DatabaseConfig) -> Self {
+        Self {
+            attempts: config.retry_attempts.max(1),
+            backoff: Duration::from_millis(config.retry_backoff_ms),
+        }
+    }
+
+    /// Wait before retry number `retry` (0 for the first)
+    fn backoff_before(&self, retry: u32) -> Duration {
+        self.backoff
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(MAX_RETRY_BACKOFF)
+    }
+}
+
+/// Set the policy `Repository::retry` uses.
+///
+/// Only the first call has any effect; before it the default policy applies.
+pub fn init_retry_policy(policy: RetryPolicy) {
+    let _ = RETRY_POLICY.set(policy);
+}
+
+/// Whether an error may go away on its own: a lost or refused connection, an exhausted
+/// pool, a server shutting down or a serialization failure. Logical errors, like a unique
+/// violation or a bad query, fail the same way every time and are not retried.
+pub fn is_transient(err: &sqlx::Error) -> bool {
+    match err {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut | sqlx::Error::WorkerCrashed => true,
+        sqlx::Error::Database(db_err) => db_err.code().is_some_and(|code| {
+            // Connection exceptions, too many connections, operator intervention,
+            // serialization failures and deadlocks
+            code.starts_with("08")
+                || code.starts_with("57P")
+                || matches!(&*code, "53300" | "40001" | "40P01")
+        }),
+        _ => false,
+    }
+}
+
+/// Run `op` under `policy`, calling it again after a backoff when it fails with a
+/// transient error. Any other error, or the last attempt's, is returned as is.
+pub async fn retry_with<T, F, Fut>(policy: &RetryPolicy, mut op: F) -> Result<T, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    let mut attempt = 1;
+    loop {
+        match op().await {
+            Err(e) if attempt < policy.attempts && is_transient(&e) => {
+                let backoff = policy.backoff_before(attempt - 1);
+                warn!(
+                    "Transient database error (attempt {}/{}), retrying in {:?}: {}",
+                    attempt, policy.attempts, backoff, e
+                );
+                tokio::time::sleep(backoff).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
 /// Base repository with shared functionality
 pub struct Repository {
     /// Database connection pool
@@ -19,5 +115,120 @@ impl Repository {
     pub fn new(pool: Arc<PgPool>) -> Self {
         Self { pool }
     }
+
+    /// Run a database operation, retrying transient errors under the policy set at startup.
+    ///
+    /// Retrying is opt-in per call site, and a query not wrapped here fails on the first
+    /// error. The reads on the hot paths (camera, stream and recording lookups, segments,
+    /// active schedules and event settings) are wrapped; only wrap operations that are
+    /// safe to run twice, since a connection lost after a write may have committed it.
+    pub async fn retry<T, F, Fut>(op: F) -> Result<T, sqlx::Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, sqlx::Error>>,
+    {
+        let policy = RETRY_POLICY.get().copied().unwrap_or_default();
+        retry_with(&policy, op).await
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::error::{DatabaseError, ErrorKind};
+    use std::borrow::Cow;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    /// Stand-in for a Postgres error with the given SQLSTATE
+    #[derive(Debug)]
+    struct MockDbError(&'static str);
+
+    impl std::fmt::Display for MockDbError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "mock database error {}", self.0)
+        }
+    }
+
+    impl std::error::Error for MockDbError {}
+
+    impl DatabaseError for MockDbError {
+        fn message(&self) -> &str {
+            "mock database error"
+        }
+
+        fn code(&self) -> Option<Cow<'_, str>> {
+            Some(Cow::Borrowed(self.0))
+        }
+
+        fn as_error(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn as_error_mut(&mut self) -> &mut (dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn into_error(self: Box<Self>) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+            self
+        }
+
+        fn kind(&self) -> ErrorKind {
+            match self.0 {
+                "23505" => ErrorKind::UniqueViolation,
+                _ => ErrorKind::Other,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn transient_errors_are_retried_and_logical_ones_are_not() {
+        let policy = RetryPolicy {
+            attempts: 3,
+            backoff: Duration::from_millis(1),
+        };
+
+        // Two pool timeouts, then success
+        let calls = AtomicU32::new(0);
+        let result = retry_with(&policy, || async {
+            match calls.fetch_add(1, Ordering::SeqCst) {
+                0 | 1 => Err(sqlx::Error::PoolTimedOut),
+                _ => Ok("row"),
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), "row");
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+
+        // A unique violation surfaces on the first attempt
+        let calls = AtomicU32::new(0);
+        let result: Result<(), _> = retry_with(&policy, || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(sqlx::Error::Database(Box::new(MockDbError("23505"))))
+        })
+        .await;
+        let err = result.unwrap_err();
+        assert!(matches!(&err, sqlx::Error::Database(e) if e.is_unique_violation()));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Transient errors still give up after the last attempt
+        let calls = AtomicU32::new(0);
+        let result: Result<(), _> = retry_with(&policy, || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err(sqlx::Error::Database(Box::new(MockDbError("57P01"))))
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let policy = RetryPolicy {
+            attempts: 10,
+            backoff: Duration::from_millis(100),
+        };
+        assert_eq!(policy.backoff_before(0), Duration::from_millis(100));
+        assert_eq!(policy.backoff_before(2), Duration::from_millis(400));
+        assert_eq!(policy.backoff_before(8), MAX_RETRY_BACKOFF);
+    }
+}
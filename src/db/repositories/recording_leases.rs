@@ -0,0 +1,88 @@
+use crate::error::Error;
+use anyhow::Result;
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Recording leases repository. A lease names the one instance allowed to record a stream;
+/// expiry times come from the database clock so instances needn't agree on the time.
+#[derive(Clone)]
+pub struct RecordingLeasesRepository {
+    pool: Arc<PgPool>,
+}
+
+impl RecordingLeasesRepository {
+    /// Create a new recording leases repository
+    pub fn new(pool: Arc<PgPool>) -> Self {
+        Self { pool }
+    }
+
+    /// Take the lease on a stream for `holder`, valid for `ttl`. Succeeds when the stream
+    /// is free, its lease expired, or `holder` already has it (which extends it). Returns
+    /// false when another instance holds a live lease.
+    pub async fn acquire(&self, stream_id: &Uuid, holder: &Uuid, ttl: Duration) -> Result<bool> {
+        let acquired = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            INSERT INTO recording_leases (stream_id, holder, acquired_at, expires_at)
+            VALUES ($1, $2, now(), now() + make_interval(secs => $3))
+            ON CONFLICT (stream_id) DO UPDATE
+            SET holder = EXCLUDED.holder,
+                acquired_at = CASE
+                    WHEN recording_leases.holder = EXCLUDED.holder THEN recording_leases.acquired_at
+                    ELSE EXCLUDED.acquired_at
+                END,
+                expires_at = EXCLUDED.expires_at
+            WHERE recording_leases.holder = EXCLUDED.holder
+               OR recording_leases.expires_at < now()
+            RETURNING stream_id
+            "#,
+        )
+        .bind(stream_id)
+        .bind(holder)
+        .bind(ttl.as_secs_f64())
+        .fetch_optional(&*self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to acquire recording lease: {}", e)))?;
+
+        Ok(acquired.is_some())
+    }
+
+    /// Extend every unexpired lease `holder` has by `ttl`. Returns the streams it still
+    /// holds; a lease that expired in the meantime may have been taken over and is not
+    /// renewed.
+    pub async fn renew(&self, holder: &Uuid, ttl: Duration) -> Result<Vec<Uuid>> {
+        let renewed = sqlx::query_scalar::<_, Uuid>(
+            r#"
+            UPDATE recording_leases
+            SET expires_at = now() + make_interval(secs => $2)
+            WHERE holder = $1 AND expires_at >= now()
+            RETURNING stream_id
+            "#,
+        )
+        .bind(holder)
+        .bind(ttl.as_secs_f64())
+        .fetch_all(&*self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to renew recording leases: {}", e)))?;
+
+        Ok(renewed)
+    }
+
+    /// Give up `holder`'s lease on a stream, if it has it
+    pub async fn release(&self, stream_id: &Uuid, holder: &Uuid) -> Result<()> {
+        sqlx::query(
+            r#"
+            DELETE FROM recording_leases
+            WHERE stream_id = $1 AND holder = $2
+            "#,
+        )
+        .bind(stream_id)
+        .bind(holder)
+        .execute(&*self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to release recording lease: {}", e)))?;
+
+        Ok(())
+    }
+}
@@ -1,9 +1,11 @@
 use crate::{
     db::models::recording_models::{
-        Recording, RecordingDb, RecordingEventType, RecordingSearchQuery, RecordingStats,
-        RecordingStatsDb, RecordingUpdate,
+        Recording, RecordingDb, RecordingSearchQuery, RecordingStats, RecordingStatsDb,
+        RecordingUpdate,
     },
+    db::repositories::Repository,
     error::Error,
+    recorder::archiver::archived_key,
 };
 use anyhow::Result;
 use chrono::{DateTime, Utc};
@@ -83,7 +85,7 @@ impl RecordingsRepository {
             SELECT id, camera_id, stream_id, schedule_id, start_time, end_time, file_path, file_size,
                    duration, format, resolution, fps, event_type, metadata
             FROM recordings
-            WHERE metadata @> $1::jsonb
+            WHERE metadata @> $1::jsonb AND deleted_at IS NULL
         "#;
 
         // Execute the query with the JSON string as a parameter
@@ -100,16 +102,18 @@ impl RecordingsRepository {
 
     /// Get recording by ID
     pub async fn get_by_id(&self, id: &Uuid) -> Result<Option<Recording>> {
-        let result = sqlx::query_as::<_, RecordingDb>(
-            r#"
-            SELECT id, camera_id, stream_id, schedule_id, start_time, end_time, file_path, file_size,
-                   duration, format, resolution, fps, event_type, metadata, segment_id, parent_recording_id
-            FROM recordings
-            WHERE id = $1
-            "#,
-        )
-        .bind(id)
-        .fetch_optional(&*self.pool)
+        let result = Repository::retry(|| {
+            sqlx::query_as::<_, RecordingDb>(
+                r#"
+                SELECT id, camera_id, stream_id, schedule_id, start_time, end_time, file_path, file_size,
+                       duration, format, resolution, fps, event_type, metadata, segment_id, parent_recording_id
+                FROM recordings
+                WHERE id = $1 AND deleted_at IS NULL
+                "#,
+            )
+            .bind(id)
+            .fetch_optional(&*self.pool)
+        })
         .await
         .map_err(|e| Error::Database(format!("Failed to get recording by ID: {}", e)))?;
 
@@ -221,7 +225,11 @@ impl RecordingsRepository {
             if param_index > 1 {
                 sql.push_str(",");
             }
-            sql.push_str(&format!(" metadata = ${}", param_index));
+            // Merge so keys set at creation (e.g. detected codecs) survive finalization
+            sql.push_str(&format!(
+                " metadata = COALESCE(metadata, '{{}}'::jsonb) || ${}",
+                param_index
+            ));
             param_index += 1;
             params.push(QueryArg::Json(update.metadata.unwrap()));
         }
@@ -246,6 +254,26 @@ impl RecordingsRepository {
             params.push(QueryArg::Uuid(update.parent_recording_id.unwrap()));
         }
 
+        // Add resolution if present
+        if update.resolution.is_some() {
+            if param_index > 1 {
+                sql.push_str(",");
+            }
+            sql.push_str(&format!(" resolution = ${}", param_index));
+            param_index += 1;
+            params.push(QueryArg::String(update.resolution.unwrap()));
+        }
+
+        // Add fps if present
+        if update.fps.is_some() {
+            if param_index > 1 {
+                sql.push_str(",");
+            }
+            sql.push_str(&format!(" fps = ${}", param_index));
+            param_index += 1;
+            params.push(QueryArg::I32(update.fps.unwrap() as i32));
+        }
+
         // Add WHERE clause and RETURNING statement
         sql.push_str(&format!(" WHERE id = ${}", param_index));
         params.push(QueryArg::Uuid(*recording_id));
@@ -276,8 +304,27 @@ impl RecordingsRepository {
         Ok(Recording::from(result))
     }
 
-    /// Delete recording
+    /// Soft-delete a recording and its segments. Rows and files stay until storage cleanup
+    /// purges them after the grace period.
     pub async fn delete(&self, id: &Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE recordings
+            SET deleted_at = $2
+            WHERE (id = $1 OR parent_recording_id = $1) AND deleted_at IS NULL
+            "#,
+        )
+        .bind(id)
+        .bind(Utc::now())
+        .execute(&*self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to delete recording: {}", e)))?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Permanently delete a recording row
+    pub async fn purge(&self, id: &Uuid) -> Result<bool> {
         let result = sqlx::query(
             r#"
             DELETE FROM recordings
@@ -287,11 +334,132 @@ impl RecordingsRepository {
         .bind(id)
         .execute(&*self.pool)
         .await
-        .map_err(|e| Error::Database(format!("Failed to delete recording: {}", e)))?;
+        .map_err(|e| Error::Database(format!("Failed to purge recording: {}", e)))?;
 
         Ok(result.rows_affected() > 0)
     }
 
+    /// Recordings soft-deleted before `cutoff`, due to be purged. Segments deleted along
+    /// with their parent are left out; purging the parent takes them with it.
+    pub async fn get_deleted_before(&self, cutoff: DateTime<Utc>) -> Result<Vec<Recording>> {
+        let result = sqlx::query_as::<_, RecordingDb>(
+            r#"
+            SELECT id, camera_id, stream_id, schedule_id, start_time, end_time, file_path, file_size,
+                   duration, format, resolution, fps, event_type, metadata, segment_id, parent_recording_id
+            FROM recordings r
+            WHERE r.deleted_at < $1
+              AND NOT EXISTS (
+                  SELECT 1 FROM recordings p
+                  WHERE p.id = r.parent_recording_id AND p.deleted_at IS NOT NULL
+              )
+            "#,
+        )
+        .bind(cutoff)
+        .fetch_all(&*self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to get deleted recordings: {}", e)))?;
+
+        Ok(result.into_iter().map(Recording::from).collect())
+    }
+
+    /// Get the segment recordings of a parent recording, in segment order. Soft-deleted
+    /// segments are left out.
+    pub async fn get_segments(&self, parent_id: &Uuid) -> Result<Vec<Recording>> {
+        self.segments_of(parent_id, false).await
+    }
+
+    /// Get the segment recordings of a parent recording including soft-deleted ones, for
+    /// purging their files
+    pub async fn get_segments_with_deleted(&self, parent_id: &Uuid) -> Result<Vec<Recording>> {
+        self.segments_of(parent_id, true).await
+    }
+
+    async fn segments_of(&self, parent_id: &Uuid, with_deleted: bool) -> Result<Vec<Recording>> {
+        let result = Repository::retry(|| {
+            sqlx::query_as::<_, RecordingDb>(
+                r#"
+                SELECT id, camera_id, stream_id, schedule_id, start_time, end_time, file_path, file_size,
+                       duration, format, resolution, fps, event_type, metadata, segment_id, parent_recording_id
+                FROM recordings
+                WHERE parent_recording_id = $1 AND ($2 OR deleted_at IS NULL)
+                ORDER BY segment_id ASC
+                "#,
+            )
+            .bind(parent_id)
+            .bind(with_deleted)
+            .fetch_all(&*self.pool)
+        })
+        .await
+        .map_err(|e| Error::Database(format!("Failed to get recording segments: {}", e)))?;
+
+        Ok(result.into_iter().map(Recording::from).collect())
+    }
+
+    /// Delete a parent recording together with all of its segment rows.
+    /// Returns the number of rows deleted.
+    pub async fn delete_with_segments(&self, parent_id: &Uuid) -> Result<u64> {
+        let mut tx = self
+            .pool
+            .begin()
+            .await
+            .map_err(|e| Error::Database(format!("Failed to begin transaction: {}", e)))?;
+
+        let segments = sqlx::query(
+            r#"
+            DELETE FROM recordings
+            WHERE parent_recording_id = $1
+            "#,
+        )
+        .bind(parent_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to delete recording segments: {}", e)))?;
+
+        let parent = sqlx::query(
+            r#"
+            DELETE FROM recordings
+            WHERE id = $1
+            "#,
+        )
+        .bind(parent_id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to delete recording: {}", e)))?;
+
+        tx.commit()
+            .await
+            .map_err(|e| Error::Database(format!("Failed to commit transaction: {}", e)))?;
+
+        Ok(segments.rows_affected() + parent.rows_affected())
+    }
+
+    /// Delete segment rows whose parent recording no longer exists and return them so the
+    /// caller can remove their files. Segments younger than an hour are left alone, since
+    /// a recording that just started may not have written its parent row yet. `camera_id`
+    /// limits the deletion to one camera's segments.
+    pub async fn delete_orphaned_segments(
+        &self,
+        camera_id: Option<Uuid>,
+    ) -> Result<Vec<Recording>> {
+        let result = sqlx::query_as::<_, RecordingDb>(
+            r#"
+            DELETE FROM recordings r
+            WHERE r.parent_recording_id IS NOT NULL
+              AND r.start_time < NOW() - INTERVAL '1 hour'
+              AND NOT EXISTS (SELECT 1 FROM recordings p WHERE p.id = r.parent_recording_id)
+              AND ($1::uuid IS NULL OR r.camera_id = $1)
+            RETURNING id, camera_id, stream_id, schedule_id, start_time, end_time, file_path, file_size,
+                      duration, format, resolution, fps, event_type, metadata, segment_id, parent_recording_id
+            "#,
+        )
+        .bind(camera_id)
+        .fetch_all(&*self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to delete orphaned segments: {}", e)))?;
+
+        Ok(result.into_iter().map(Recording::from).collect())
+    }
+
     /// Delete recording with file
     pub async fn _delete_with_file(&self, id: &Uuid) -> Result<bool> {
         // Get the file path first
@@ -308,24 +476,18 @@ impl RecordingsRepository {
             }
 
             // Delete from database
-            self.delete(id).await
+            self.purge(id).await
         } else {
             Ok(false)
         }
     }
 
-    /// Search recordings with advanced filters
-    pub async fn search(&self, query: &RecordingSearchQuery) -> Result<Vec<Recording>> {
-        // Build dynamic query
-        let mut sql = String::from(
-            r#"
-            SELECT id, camera_id, stream_id, schedule_id, start_time, end_time, file_path, file_size,
-                   duration, format, resolution, fps, event_type, metadata, segment_id, parent_recording_id
-            FROM recordings
-            WHERE 1=1
-            "#,
-        );
-
+    /// Build the WHERE conditions shared by `search` and `count`.
+    ///
+    /// Returns the conditions (each prefixed with ` AND`), their arguments and the next
+    /// free parameter index.
+    fn search_filters(query: &RecordingSearchQuery) -> (String, Vec<QueryArg>, usize) {
+        let mut sql = String::from(" AND deleted_at IS NULL");
         let mut args: Vec<QueryArg> = Vec::new();
         let mut param_index = 1;
 
@@ -371,19 +533,10 @@ impl RecordingsRepository {
         if let Some(event_types) = &query.event_types {
             if !event_types.is_empty() {
                 // Convert event types to strings
-                let event_type_strings: Vec<String> = event_types
-                    .iter()
-                    .map(|et| match et {
-                        RecordingEventType::Continuous => "continuous".to_string(),
-                        RecordingEventType::Motion => "motion".to_string(),
-                        RecordingEventType::Audio => "audio".to_string(),
-                        RecordingEventType::External => "external".to_string(),
-                        RecordingEventType::Manual => "manual".to_string(),
-                        RecordingEventType::Analytics => "analytics".to_string(),
-                    })
-                    .collect();
-
-                sql.push_str(&format!(" AND event_type = ANY(${:?})", param_index));
+                let event_type_strings: Vec<String> =
+                    event_types.iter().map(|et| et.to_string()).collect();
+
+                sql.push_str(&format!(" AND event_type = ANY(${})", param_index));
                 args.push(QueryArg::StringArray(event_type_strings));
                 param_index += 1;
             }
@@ -396,6 +549,28 @@ impl RecordingsRepository {
             param_index += 1;
         }
 
+        // Add file size filters
+        if let Some(min_file_size) = &query.min_file_size {
+            sql.push_str(&format!(" AND file_size >= ${}", param_index));
+            args.push(QueryArg::I64(*min_file_size as i64));
+            param_index += 1;
+        }
+
+        if let Some(max_file_size) = &query.max_file_size {
+            sql.push_str(&format!(" AND file_size <= ${}", param_index));
+            args.push(QueryArg::I64(*max_file_size as i64));
+            param_index += 1;
+        }
+
+        // Add has_audio filter. Segments carry no codec info of their own, so fall back
+        // to the audio codec detected for their parent recording.
+        if let Some(has_audio) = &query.has_audio {
+            sql.push_str(&format!(
+                " AND (COALESCE(metadata->>'audio_codec', (SELECT p.metadata->>'audio_codec' FROM recordings p WHERE p.id = recordings.parent_recording_id), '') <> '') = {}",
+                has_audio
+            ));
+        }
+
         // Add segment ID filter
         if let Some(segment_id) = &query.segment_id {
             sql.push_str(&format!(" AND segment_id = ${}", param_index));
@@ -419,6 +594,24 @@ impl RecordingsRepository {
             }
         }
 
+        (sql, args, param_index)
+    }
+
+    /// Search recordings with advanced filters
+    pub async fn search(&self, query: &RecordingSearchQuery) -> Result<Vec<Recording>> {
+        // Build dynamic query
+        let mut sql = String::from(
+            r#"
+            SELECT id, camera_id, stream_id, schedule_id, start_time, end_time, file_path, file_size,
+                   duration, format, resolution, fps, event_type, metadata, segment_id, parent_recording_id
+            FROM recordings
+            WHERE 1=1
+            "#,
+        );
+
+        let (filters, mut args, mut param_index) = Self::search_filters(query);
+        sql.push_str(&filters);
+
         // Add order by
         sql.push_str(" ORDER BY start_time DESC");
 
@@ -452,6 +645,34 @@ impl RecordingsRepository {
         Ok(result.into_iter().map(Recording::from).collect())
     }
 
+    /// Count recordings matching the search filters, ignoring limit and offset
+    pub async fn count(&self, query: &RecordingSearchQuery) -> Result<i64> {
+        let (filters, args, _) = Self::search_filters(query);
+        let sql = format!("SELECT COUNT(*) FROM recordings WHERE 1=1{}", filters);
+
+        let mut query_builder = sqlx::query_as::<_, (i64,)>(&sql);
+        for arg in args {
+            query_builder = arg.apply_to_query(query_builder);
+        }
+
+        let (total,) = query_builder
+            .fetch_one(&*self.pool)
+            .await
+            .map_err(|e| Error::Database(format!("Failed to count recordings: {}", e)))?;
+
+        Ok(total)
+    }
+
+    /// Search recordings and count all matches, for paginated listings
+    pub async fn search_with_total(
+        &self,
+        query: &RecordingSearchQuery,
+    ) -> Result<(Vec<Recording>, i64)> {
+        let recordings = self.search(query).await?;
+        let total = self.count(query).await?;
+        Ok((recordings, total))
+    }
+
     /// Get recordings for a camera
     pub async fn get_by_camera(
         &self,
@@ -460,19 +681,22 @@ impl RecordingsRepository {
     ) -> Result<Vec<Recording>> {
         let limit = limit.unwrap_or(100);
 
-        let result = sqlx::query_as::<_, RecordingDb>(
-            r#"
-            SELECT *
-            FROM recordings
-            WHERE camera_id = $1
-            AND end_time IS NOT NULL
-            ORDER BY start_time ASC
-            LIMIT $2
-            "#,
-        )
-        .bind(camera_id)
-        .bind(limit)
-        .fetch_all(&*self.pool)
+        let result = Repository::retry(|| {
+            sqlx::query_as::<_, RecordingDb>(
+                r#"
+                SELECT *
+                FROM recordings
+                WHERE camera_id = $1
+                AND end_time IS NOT NULL
+                AND deleted_at IS NULL
+                ORDER BY start_time ASC
+                LIMIT $2
+                "#,
+            )
+            .bind(camera_id)
+            .bind(limit)
+            .fetch_all(&*self.pool)
+        })
         .await
         .map_err(|e| Error::Database(format!("Failed to get recordings for camera: {}", e)))?;
 
@@ -606,7 +830,8 @@ impl RecordingsRepository {
         Ok(result.rows_affected())
     }
 
-    /// Delete recordings for a camera with their files
+    /// Delete recordings for a camera with their files. Archived recordings are left to the
+    /// storage cleanup, which also removes them from the archive store.
     pub async fn prune_recordings_by_camera(
         &self,
         camera_id: &Uuid,
@@ -623,6 +848,11 @@ impl RecordingsRepository {
 
         let mut delete_count = 0;
         for recording in recordings {
+            // Their file_path is an object location, not a file on disk
+            if archived_key(&recording).is_some() {
+                continue;
+            }
+
             // Delete the file
             if let Err(e) = std::fs::remove_file(&recording.file_path) {
                 error!(
@@ -634,7 +864,7 @@ impl RecordingsRepository {
             }
 
             // Delete from database
-            if let Ok(deleted) = self.delete(&recording.id).await {
+            if let Ok(deleted) = self.purge(&recording.id).await {
                 if deleted {
                     delete_count += 1;
                 }
@@ -648,6 +878,42 @@ impl RecordingsRepository {
         Ok(delete_count)
     }
 
+    /// Get recordings past their retention period, oldest first.
+    ///
+    /// Retention is taken from the most specific setting that applies: the recording's
+    /// schedule, then its camera, then `default_retention_days`. Parents of recordings
+    /// still in progress (no end time yet) are never returned. `camera_id` limits the
+    /// result to one camera's recordings.
+    pub async fn get_recordings_past_retention(
+        &self,
+        default_retention_days: i32,
+        camera_id: Option<Uuid>,
+    ) -> Result<Vec<Recording>> {
+        let result = sqlx::query_as::<_, RecordingDb>(
+            r#"
+            SELECT r.id, r.camera_id, r.stream_id, r.schedule_id, r.start_time, r.end_time,
+                   r.file_path, r.file_size, r.duration, r.format, r.resolution, r.fps,
+                   r.event_type, r.metadata, r.segment_id, r.parent_recording_id
+            FROM recordings r
+            LEFT JOIN recording_schedules s ON s.id = r.schedule_id
+            LEFT JOIN cameras c ON c.id = r.camera_id
+            WHERE r.start_time < NOW() - make_interval(days => COALESCE(s.retention_days, c.retention_days, $1))
+              AND (r.end_time IS NOT NULL OR r.parent_recording_id IS NOT NULL)
+              AND ($2::uuid IS NULL OR r.camera_id = $2)
+            ORDER BY r.start_time ASC
+            "#,
+        )
+        .bind(default_retention_days)
+        .bind(camera_id)
+        .fetch_all(&*self.pool)
+        .await
+        .map_err(|e| {
+            Error::Database(format!("Failed to get recordings past retention: {}", e))
+        })?;
+
+        Ok(result.into_iter().map(Recording::from).collect())
+    }
+
     /// Get recordings to prune
     pub async fn get_recordings_to_prune(
         &self,
@@ -697,6 +963,76 @@ impl RecordingsRepository {
 
         Ok(result.into_iter().map(Recording::from).collect())
     }
+
+    /// Get recordings finalized before `finalized_before` whose files haven't been archived,
+    /// oldest first. Parents of segmented recordings are left out; their segments are
+    /// archived one by one.
+    pub async fn get_unarchived(
+        &self,
+        finalized_before: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<Recording>> {
+        let result = sqlx::query_as::<_, RecordingDb>(
+            r#"
+            SELECT r.id, r.camera_id, r.stream_id, r.schedule_id, r.start_time, r.end_time,
+                   r.file_path, r.file_size, r.duration, r.format, r.resolution, r.fps,
+                   r.event_type, r.metadata, r.segment_id, r.parent_recording_id
+            FROM recordings r
+            WHERE r.end_time < $1
+              AND r.deleted_at IS NULL
+              AND NOT (COALESCE(r.metadata, '{}'::jsonb) ? 'archive')
+              AND NOT EXISTS (SELECT 1 FROM recordings s WHERE s.parent_recording_id = r.id)
+            ORDER BY r.end_time ASC
+            LIMIT $2
+            "#,
+        )
+        .bind(finalized_before)
+        .bind(limit)
+        .fetch_all(&*self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to get unarchived recordings: {}", e)))?;
+
+        Ok(result.into_iter().map(Recording::from).collect())
+    }
+
+    /// Get finished recordings started before `older_than` whose files are on the primary
+    /// disk: not under `tier_prefix` (a directory, ending in a separator) yet and not
+    /// archived. Parents of segmented recordings are left out; their segments move one by
+    /// one. `camera_id` limits the result to one camera's recordings.
+    pub async fn get_recordings_to_tier(
+        &self,
+        older_than: DateTime<Utc>,
+        tier_prefix: Option<&str>,
+        camera_id: Option<Uuid>,
+        limit: i64,
+    ) -> Result<Vec<Recording>> {
+        let result = sqlx::query_as::<_, RecordingDb>(
+            r#"
+            SELECT r.id, r.camera_id, r.stream_id, r.schedule_id, r.start_time, r.end_time,
+                   r.file_path, r.file_size, r.duration, r.format, r.resolution, r.fps,
+                   r.event_type, r.metadata, r.segment_id, r.parent_recording_id
+            FROM recordings r
+            WHERE r.start_time < $1
+              AND r.end_time IS NOT NULL
+              AND r.deleted_at IS NULL
+              AND ($2::text IS NULL OR NOT starts_with(r.file_path, $2))
+              AND NOT (COALESCE(r.metadata, '{}'::jsonb) ? 'archive')
+              AND NOT EXISTS (SELECT 1 FROM recordings s WHERE s.parent_recording_id = r.id)
+              AND ($3::uuid IS NULL OR r.camera_id = $3)
+            ORDER BY r.start_time ASC
+            LIMIT $4
+            "#,
+        )
+        .bind(older_than)
+        .bind(tier_prefix)
+        .bind(camera_id)
+        .bind(limit)
+        .fetch_all(&*self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to get recordings to tier: {}", e)))?;
+
+        Ok(result.into_iter().map(Recording::from).collect())
+    }
 }
 
 /// Helper enum for dynamic query parameters
@@ -727,3 +1063,202 @@ impl QueryArg {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::models::recording_models::RecordingEventType;
+    use crate::db::test_fixtures::insert_camera_and_stream;
+    use serde_json::json;
+    use std::path::PathBuf;
+
+    fn recording(
+        camera_id: Uuid,
+        stream_id: Uuid,
+        file_size: u64,
+        metadata: Option<serde_json::Value>,
+        parent_recording_id: Option<Uuid>,
+    ) -> Recording {
+        Recording {
+            id: Uuid::new_v4(),
+            camera_id,
+            stream_id,
+            start_time: Utc::now(),
+            end_time: None,
+            file_path: PathBuf::from("/tmp/search-test.mp4"),
+            file_size,
+            duration: 30,
+            format: "mp4".to_string(),
+            resolution: "1280x720".to_string(),
+            fps: 25,
+            event_type: RecordingEventType::Continuous,
+            metadata,
+            schedule_id: None,
+            segment_id: parent_recording_id.map(|_| 0),
+            parent_recording_id,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_filters_by_file_size_and_audio() -> Result<()> {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else {
+            println!("Skipping recording search test. Set TEST_DATABASE_URL to run.");
+            return Ok(());
+        };
+
+        let pool = Arc::new(PgPool::connect(&database_url).await?);
+        let repo = RecordingsRepository::new(pool.clone());
+        let (camera_id, stream_id) = insert_camera_and_stream(&pool).await?;
+
+        let empty = repo
+            .create(&recording(camera_id, stream_id, 0, None, None))
+            .await?;
+        let with_audio = repo
+            .create(&recording(
+                camera_id,
+                stream_id,
+                4096,
+                Some(json!({ "audio_codec": "aac" })),
+                None,
+            ))
+            .await?;
+        let silent = repo
+            .create(&recording(
+                camera_id,
+                stream_id,
+                1024,
+                Some(json!({ "audio_codec": "" })),
+                None,
+            ))
+            .await?;
+        let audio_segment = repo
+            .create(&recording(
+                camera_id,
+                stream_id,
+                2048,
+                Some(json!({ "finalized": true })),
+                Some(with_audio.id),
+            ))
+            .await?;
+
+        let search = |query: RecordingSearchQuery| {
+            let repo = repo.clone();
+            async move {
+                repo.search(&RecordingSearchQuery {
+                    camera_ids: Some(vec![camera_id]),
+                    ..query
+                })
+                .await
+                .map(|recordings| {
+                    let mut ids: Vec<Uuid> = recordings.into_iter().map(|r| r.id).collect();
+                    ids.sort();
+                    ids
+                })
+            }
+        };
+        let sorted = |mut ids: Vec<Uuid>| {
+            ids.sort();
+            ids
+        };
+
+        let zero_byte = search(RecordingSearchQuery {
+            max_file_size: Some(0),
+            ..RecordingSearchQuery::default()
+        })
+        .await?;
+        let large = search(RecordingSearchQuery {
+            min_file_size: Some(2048),
+            ..RecordingSearchQuery::default()
+        })
+        .await?;
+        let audible = search(RecordingSearchQuery {
+            has_audio: Some(true),
+            ..RecordingSearchQuery::default()
+        })
+        .await?;
+        let inaudible = search(RecordingSearchQuery {
+            has_audio: Some(false),
+            ..RecordingSearchQuery::default()
+        })
+        .await?;
+
+        sqlx::query("DELETE FROM cameras WHERE id = $1")
+            .bind(camera_id)
+            .execute(&*pool)
+            .await?;
+
+        assert_eq!(zero_byte, vec![empty.id]);
+        assert_eq!(large, sorted(vec![with_audio.id, audio_segment.id]));
+        assert_eq!(audible, sorted(vec![with_audio.id, audio_segment.id]));
+        assert_eq!(inaudible, sorted(vec![empty.id, silent.id]));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_total_is_independent_of_page_size() -> Result<()> {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else {
+            println!("Skipping recording total test. Set TEST_DATABASE_URL to run.");
+            return Ok(());
+        };
+
+        let pool = Arc::new(PgPool::connect(&database_url).await?);
+        let repo = RecordingsRepository::new(pool.clone());
+        let (camera_id, stream_id) = insert_camera_and_stream(&pool).await?;
+        for _ in 0..5 {
+            repo.create(&recording(camera_id, stream_id, 1024, None, None))
+                .await?;
+        }
+
+        let page = |limit, offset| RecordingSearchQuery {
+            camera_ids: Some(vec![camera_id]),
+            limit: Some(limit),
+            offset: Some(offset),
+            ..RecordingSearchQuery::default()
+        };
+        let (first, first_total) = repo.search_with_total(&page(2, 0)).await?;
+        let (last, last_total) = repo.search_with_total(&page(2, 4)).await?;
+
+        sqlx::query("DELETE FROM cameras WHERE id = $1")
+            .bind(camera_id)
+            .execute(&*pool)
+            .await?;
+
+        assert_eq!(first.len(), 2);
+        assert_eq!(last.len(), 1);
+        assert_eq!(first_total, 5);
+        assert_eq!(last_total, 5);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_soft_deleted_segments_are_only_listed_for_purging() -> Result<()> {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else {
+            println!("Skipping recording segments test. Set TEST_DATABASE_URL to run.");
+            return Ok(());
+        };
+
+        let pool = Arc::new(PgPool::connect(&database_url).await?);
+        let repo = RecordingsRepository::new(pool.clone());
+        let (camera_id, stream_id) = insert_camera_and_stream(&pool).await?;
+        let parent = repo
+            .create(&recording(camera_id, stream_id, 0, None, None))
+            .await?;
+        let segment = recording(camera_id, stream_id, 1024, None, Some(parent.id));
+        repo.create(&segment).await?;
+
+        let live = repo.get_segments(&parent.id).await?.len();
+        repo.delete(&parent.id).await?;
+        let after_delete = repo.get_segments(&parent.id).await?.len();
+        let for_purge = repo.get_segments_with_deleted(&parent.id).await?.len();
+
+        sqlx::query("DELETE FROM cameras WHERE id = $1")
+            .bind(camera_id)
+            .execute(&*pool)
+            .await?;
+
+        assert_eq!(live, 1);
+        assert_eq!(after_delete, 0);
+        assert_eq!(for_purge, 1);
+        Ok(())
+    }
+}
@@ -1,9 +1,10 @@
 use crate::{
     db::models::recording_schedule_models::{RecordingSchedule, RecordingScheduleDb},
+    db::repositories::Repository,
     error::Error,
 };
 use anyhow::Result;
-use chrono::{Datelike, Utc};
+use chrono::Utc;
 use sqlx::PgPool;
 use std::sync::Arc;
 use tracing::info;
@@ -33,12 +34,12 @@ impl SchedulesRepository {
             INSERT INTO recording_schedules (
                 id, camera_id, stream_id, name, enabled, days_of_week, start_time, end_time,
                 created_at, updated_at, retention_days, record_on_motion, record_on_audio,
-                record_on_analytics, record_on_external, continuous_recording
+                record_on_analytics, record_on_external, continuous_recording, timezone
             )
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17)
             RETURNING id, camera_id, stream_id, name, enabled, days_of_week, start_time, end_time,
                      created_at, updated_at, retention_days, record_on_motion, record_on_audio,
-                     record_on_analytics, record_on_external, continuous_recording
+                     record_on_analytics, record_on_external, continuous_recording, timezone
             "#,
         )
         .bind(schedule_db.id)
@@ -57,6 +58,7 @@ impl SchedulesRepository {
         .bind(schedule_db.record_on_analytics)
         .bind(schedule_db.record_on_external)
         .bind(schedule_db.continuous_recording)
+        .bind(&schedule_db.timezone)
         .fetch_one(&*self.pool)
         .await
         .map_err(|e| Error::Database(format!("Failed to create recording schedule: {}", e)))?;
@@ -71,7 +73,7 @@ impl SchedulesRepository {
             r#"
             SELECT id, camera_id, stream_id, name, enabled, days_of_week, start_time, end_time,
                    created_at, updated_at, retention_days, record_on_motion, record_on_audio,
-                   record_on_analytics, record_on_external, continuous_recording
+                   record_on_analytics, record_on_external, continuous_recording, timezone
             FROM recording_schedules
             WHERE id = $1
             "#,
@@ -91,7 +93,7 @@ impl SchedulesRepository {
             r#"
             SELECT id, camera_id, stream_id, name, enabled, days_of_week, start_time, end_time,
                    created_at, updated_at, retention_days, record_on_motion, record_on_audio,
-                   record_on_analytics, record_on_external, continuous_recording
+                   record_on_analytics, record_on_external, continuous_recording, timezone
             FROM recording_schedules
             WHERE camera_id = $1
             ORDER BY name
@@ -111,37 +113,38 @@ impl SchedulesRepository {
         Ok(result.into_iter().map(RecordingSchedule::from).collect())
     }
 
-    /// Get active recording schedules for current time
+    /// Get active recording schedules for current time, leaving out cameras whose
+    /// recording is disabled or that were deleted
     pub async fn get_active_schedules(&self) -> Result<Vec<RecordingSchedule>> {
-        // Get current time in UTC
         let now = Utc::now();
 
-        // Extract day of week (0-6, where 0 is Sunday)
-        let day_of_week = now.weekday().num_days_from_sunday() as i32;
-
-        // Extract current time as HH:MM
-        let current_time = now.format("%H:%M").to_string();
-
-        let result = sqlx::query_as::<_, RecordingScheduleDb>(
-            r#"
-            SELECT id, camera_id, stream_id, name, enabled, days_of_week, start_time, end_time,
-                   created_at, updated_at, retention_days, record_on_motion, record_on_audio,
-                   record_on_analytics, record_on_external, continuous_recording
-            FROM recording_schedules
-            WHERE enabled = true
-            AND $1 = ANY(days_of_week)
-            AND start_time <= $2
-            AND end_time >= $2
-            "#,
-        )
-        .bind(day_of_week)
-        .bind(current_time)
-        .fetch_all(&*self.pool)
+        let result = Repository::retry(|| {
+            sqlx::query_as::<_, RecordingScheduleDb>(
+                r#"
+                SELECT s.id, s.camera_id, s.stream_id, s.name, s.enabled, s.days_of_week,
+                       s.start_time, s.end_time, s.created_at, s.updated_at, s.retention_days,
+                       s.record_on_motion, s.record_on_audio, s.record_on_analytics,
+                       s.record_on_external, s.continuous_recording, s.timezone
+                FROM recording_schedules s
+                JOIN cameras c ON c.id = s.camera_id
+                WHERE s.enabled = true
+                  AND LOWER(COALESCE(c.recording_mode, '')) <> 'disabled'
+                  AND c.deleted_at IS NULL
+                ORDER BY s.name
+                "#,
+            )
+            .fetch_all(&*self.pool)
+        })
         .await
-        .map_err(|e| Error::Database(format!("Failed to get active recording schedules: {}", e)))?;
+        .map_err(|e| Error::Database(format!("Failed to get active schedules: {}", e)))?;
 
-        // Convert all to domain models
-        Ok(result.into_iter().map(RecordingSchedule::from).collect())
+        // Overnight schedules (end before start) can't be matched with a plain time range
+        // comparison, and may be running on behalf of yesterday, so filter in Rust
+        let schedules = result.into_iter().map(RecordingSchedule::from);
+
+        Ok(schedules
+            .filter(|schedule| schedule.is_active_at(now))
+            .collect())
     }
 
     /// Update recording schedule
@@ -155,11 +158,11 @@ impl SchedulesRepository {
             SET camera_id = $1, stream_id = $2, name = $3, enabled = $4, days_of_week = $5,
                 start_time = $6, end_time = $7, updated_at = $8, retention_days = $9,
                 record_on_motion = $10, record_on_audio = $11, record_on_analytics = $12,
-                record_on_external = $13, continuous_recording = $14
-            WHERE id = $15
+                record_on_external = $13, continuous_recording = $14, timezone = $15
+            WHERE id = $16
             RETURNING id, camera_id, stream_id, name, enabled, days_of_week, start_time, end_time,
                      created_at, updated_at, retention_days, record_on_motion, record_on_audio,
-                     record_on_analytics, record_on_external, continuous_recording
+                     record_on_analytics, record_on_external, continuous_recording, timezone
             "#,
         )
         .bind(schedule_db.camera_id)
@@ -176,6 +179,7 @@ impl SchedulesRepository {
         .bind(schedule_db.record_on_analytics)
         .bind(schedule_db.record_on_external)
         .bind(schedule_db.continuous_recording)
+        .bind(&schedule_db.timezone)
         .bind(schedule_db.id)
         .fetch_one(&*self.pool)
         .await
@@ -207,7 +211,7 @@ impl SchedulesRepository {
             r#"
             SELECT id, camera_id, stream_id, name, enabled, days_of_week, start_time, end_time,
                    created_at, updated_at, retention_days, record_on_motion, record_on_audio,
-                   record_on_analytics, record_on_external, continuous_recording
+                   record_on_analytics, record_on_external, continuous_recording, timezone
             FROM recording_schedules
             ORDER BY name
             "#,
@@ -241,13 +245,41 @@ impl SchedulesRepository {
         Ok(())
     }
 
+    /// Find other enabled schedules of the same stream whose recording windows
+    /// intersect `schedule`'s
+    pub async fn find_overlapping(&self, schedule: &RecordingSchedule) -> Result<Vec<Uuid>> {
+        let result = sqlx::query_as::<_, RecordingScheduleDb>(
+            r#"
+            SELECT id, camera_id, stream_id, name, enabled, days_of_week, start_time, end_time,
+                   created_at, updated_at, retention_days, record_on_motion, record_on_audio,
+                   record_on_analytics, record_on_external, continuous_recording, timezone
+            FROM recording_schedules
+            WHERE enabled = true
+            AND stream_id = $1
+            AND id <> $2
+            "#,
+        )
+        .bind(schedule.stream_id)
+        .bind(schedule.id)
+        .fetch_all(&*self.pool)
+        .await
+        .map_err(|e| Error::Database(format!("Failed to get stream schedules: {}", e)))?;
+
+        Ok(result
+            .into_iter()
+            .map(RecordingSchedule::from)
+            .filter(|other| schedule.overlaps(other))
+            .map(|other| other.id)
+            .collect())
+    }
+
     /// Get all enabled schedules
     pub async fn get_all_enabled(&self) -> Result<Vec<RecordingSchedule>> {
         let result = sqlx::query_as::<_, RecordingScheduleDb>(
             r#"
             SELECT id, camera_id, stream_id, name, enabled, days_of_week, start_time, end_time,
                    created_at, updated_at, retention_days, record_on_motion, record_on_audio,
-                   record_on_analytics, record_on_external, continuous_recording
+                   record_on_analytics, record_on_external, continuous_recording, timezone
             FROM recording_schedules
             WHERE enabled = true
             ORDER BY name
@@ -261,3 +293,73 @@ impl SchedulesRepository {
         Ok(result.into_iter().map(RecordingSchedule::from).collect())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::models::camera_models::RecordingMode;
+    use crate::db::test_fixtures::insert_camera_and_stream;
+
+    #[tokio::test]
+    async fn test_disabled_camera_has_no_active_schedules() -> Result<()> {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else {
+            println!("Skipping recording mode test. Set TEST_DATABASE_URL to run.");
+            return Ok(());
+        };
+
+        let pool = Arc::new(PgPool::connect(&database_url).await?);
+        let repo = SchedulesRepository::new(pool.clone());
+
+        let (camera_id, stream_id) = insert_camera_and_stream(&*pool).await?;
+        sqlx::query("UPDATE cameras SET recording_mode = $2 WHERE id = $1")
+            .bind(camera_id)
+            .bind(RecordingMode::Disabled.to_string())
+            .execute(&*pool)
+            .await?;
+
+        // Around the clock, every day
+        let schedule = repo
+            .create(&RecordingSchedule {
+                id: Uuid::new_v4(),
+                camera_id,
+                stream_id,
+                name: "mode-test".to_string(),
+                enabled: true,
+                days_of_week: (0..7).collect(),
+                start_time: "00:00".to_string(),
+                end_time: "23:59".to_string(),
+                created_at: Utc::now(),
+                updated_at: Utc::now(),
+                retention_days: 1,
+                record_on_motion: false,
+                record_on_audio: false,
+                record_on_analytics: false,
+                record_on_external: false,
+                continuous_recording: true,
+                timezone: "UTC".to_string(),
+            })
+            .await?;
+
+        let is_active =
+            |schedules: Vec<RecordingSchedule>| schedules.iter().any(|s| s.id == schedule.id);
+        let while_disabled = is_active(repo.get_active_schedules().await?);
+        let still_enabled = is_active(repo.get_all_enabled().await?);
+
+        sqlx::query("UPDATE cameras SET recording_mode = $2 WHERE id = $1")
+            .bind(camera_id)
+            .bind(RecordingMode::Scheduled.to_string())
+            .execute(&*pool)
+            .await?;
+        let once_enabled = is_active(repo.get_active_schedules().await?);
+
+        sqlx::query("DELETE FROM cameras WHERE id = $1")
+            .bind(camera_id)
+            .execute(&*pool)
+            .await?;
+
+        assert!(!while_disabled);
+        assert!(still_enabled);
+        assert!(once_enabled);
+        Ok(())
+    }
+}
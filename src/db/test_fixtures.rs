@@ -0,0 +1,27 @@
+//! Rows the database tests attach their data to
+
+use anyhow::Result;
+use chrono::Utc;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Insert an inactive camera with one RTSP stream and return `(camera_id, stream_id)`.
+/// Deleting the camera removes the stream with it.
+pub async fn insert_camera_and_stream(pool: &PgPool) -> Result<(Uuid, Uuid)> {
+    let (camera_id, stream_id) = (Uuid::new_v4(), Uuid::new_v4());
+    sqlx::query(
+        "INSERT INTO cameras (id, name, ip_address, status, created_at, updated_at) VALUES ($1, 'test-camera', '127.0.0.1', 'inactive', $2, $2)",
+    )
+    .bind(camera_id)
+    .bind(Utc::now())
+    .execute(pool)
+    .await?;
+    sqlx::query(
+        "INSERT INTO streams (id, camera_id, name, stream_type, url) VALUES ($1, $2, 'main', 'rtsp', 'rtsp://127.0.0.1/test')",
+    )
+    .bind(stream_id)
+    .bind(camera_id)
+    .execute(pool)
+    .await?;
+    Ok((camera_id, stream_id))
+}
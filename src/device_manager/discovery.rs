@@ -3,43 +3,83 @@ use futures_util::stream::StreamExt;
 use media;
 use onvif::{discovery, soap};
 use schema::onvif as onvif_schema;
+use serde::Serialize;
 use tracing::{debug, info};
 use url::Url;
+use std::net::IpAddr;
+use std::time::Duration;
 use uuid::Uuid;
 
+use crate::config::OnvifConfig;
 use crate::db::models::camera_models::Camera;
 
-// Discover ONVIF cameras on the network and gather information without authentication
-pub async fn discover() -> Result<Vec<Camera>, anyhow::Error> {
-    info!("Starting ONVIF camera discovery on the network");
+/// Extra time past the probe duration before we stop waiting on late responses
+const DISCOVERY_GRACE: Duration = Duration::from_secs(1);
 
-    let mut cameras = Vec::new();
-    let discovery_results = discovery::DiscoveryBuilder::default().run().await?;
+/// A camera found by discovery, flagged if it is already registered
+#[derive(Debug, Clone, Serialize)]
+pub struct DiscoveredCamera {
+    #[serde(flatten)]
+    pub camera: Camera,
+    pub already_registered: bool,
+    pub existing_camera_id: Option<Uuid>,
+}
 
-    let mut discovered_cameras = Vec::new();
+/// Match discovered cameras against registered ones by IP address or serial number
+pub fn mark_registered(discovered: Vec<Camera>, registered: &[Camera]) -> Vec<DiscoveredCamera> {
+    discovered
+        .into_iter()
+        .map(|camera| {
+            let existing = registered.iter().find(|known| {
+                known.ip_address == camera.ip_address
+                    || matches!(
+                        (&known.serial_number, &camera.serial_number),
+                        (Some(a), Some(b)) if !a.is_empty() && a == b
+                    )
+            });
 
-    // First collect all discovered addresses
-    discovery_results
-        .for_each(|addr| {
-            discovered_cameras.push(addr);
-            async {}
+            DiscoveredCamera {
+                already_registered: existing.is_some(),
+                existing_camera_id: existing.map(|known| known.id),
+                camera,
+            }
         })
-        .await;
+        .collect()
+}
 
-    info!("Found {} potential ONVIF devices", discovered_cameras.len());
+// Discover ONVIF cameras on the network and gather information without authentication.
+// Probes go out from `discovery_interface` when set; devices that answer within
+// `discovery_timeout` are returned even if others are still pending.
+pub async fn discover(config: &OnvifConfig) -> Result<Vec<Camera>, anyhow::Error> {
+    info!("Starting ONVIF camera discovery on the network");
+
+    let timeout = Duration::from_secs(config.discovery_timeout);
+    let mut builder = discovery::DiscoveryBuilder::default();
+    builder.duration(timeout);
+
+    if let Some(interface) = &config.discovery_interface {
+        let listen_address: IpAddr = interface.parse().map_err(|e| {
+            anyhow::anyhow!("Invalid discovery_interface '{}': {}", interface, e)
+        })?;
+        info!("Sending ONVIF discovery probes from {}", listen_address);
+        builder.listen_address(listen_address);
+    }
+
+    let discovery_results = builder.run().await?;
 
-    // Process each discovered device
-    let results: Vec<Result<Camera, anyhow::Error>> =
-        futures_util::future::join_all(discovered_cameras.into_iter().map(|addr| async move {
-            let camera = process_discovered_device(addr).await;
-            camera
-        }))
+    // Collect whatever answered in time; a slow or silent network yields a partial list
+    let discovered_cameras: Vec<discovery::Device> = discovery_results
+        .take_until(tokio::time::sleep(timeout + DISCOVERY_GRACE))
+        .collect()
         .await;
 
-    // Collect valid camera results
-    for result in results {
-        if let Ok(camera) = result {
-            cameras.push(camera);
+    info!("Found {} potential ONVIF devices", discovered_cameras.len());
+
+    let mut cameras = Vec::new();
+    for device in discovered_cameras {
+        match process_discovered_device(device) {
+            Ok(camera) => cameras.push(camera),
+            Err(e) => debug!("Skipping discovered device: {}", e),
         }
     }
 
@@ -50,12 +90,53 @@ pub async fn discover() -> Result<Vec<Camera>, anyhow::Error> {
     Ok(cameras)
 }
 
-async fn process_discovered_device(device: discovery::Device) -> Result<Camera, anyhow::Error> {
+fn process_discovered_device(device: discovery::Device) -> Result<Camera, anyhow::Error> {
     let mut camera = Camera::default();
 
     // Extract IP address
-    camera.ip_address = device.urls[0].host().unwrap().to_string();
-    camera.name = device.name.unwrap();
+    let host = device
+        .urls
+        .first()
+        .and_then(|url| url.host_str())
+        .ok_or_else(|| anyhow::anyhow!("device advertised no usable address"))?;
+    camera.ip_address = host.to_string();
+    camera.name = device.name.unwrap_or_else(|| host.to_string());
 
     Ok(camera)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera(ip: &str, serial: Option<&str>) -> Camera {
+        let mut camera = Camera::default();
+        camera.id = Uuid::new_v4();
+        camera.ip_address = ip.to_string();
+        camera.serial_number = serial.map(|s| s.to_string());
+        camera
+    }
+
+    #[test]
+    fn discovered_cameras_are_matched_by_ip_or_serial() {
+        let by_ip = camera("192.168.1.10", None);
+        let by_serial = camera("192.168.1.20", Some("SN-42"));
+        let registered = vec![by_ip.clone(), by_serial.clone()];
+
+        let discovered = vec![
+            camera("192.168.1.10", None),
+            // Same device, new DHCP lease
+            camera("192.168.1.99", Some("SN-42")),
+            camera("192.168.1.30", None),
+        ];
+
+        let results = mark_registered(discovered, &registered);
+
+        assert!(results[0].already_registered);
+        assert_eq!(results[0].existing_camera_id, Some(by_ip.id));
+        assert!(results[1].already_registered);
+        assert_eq!(results[1].existing_camera_id, Some(by_serial.id));
+        assert!(!results[2].already_registered);
+        assert_eq!(results[2].existing_camera_id, None);
+    }
+}
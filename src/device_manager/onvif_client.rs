@@ -55,6 +55,23 @@ pub struct OnvifCamera {
     imaging: Option<soap::client::Client>,
     ptz: Option<soap::client::Client>,
     analytics: Option<soap::client::Client>,
+    auth_type: &'static str,
+    service_path: String,
+}
+
+/// Name of an auth type as accepted by `OnvifCameraBuilder::auth_type`
+fn auth_type_name(auth_type: &AuthType) -> &'static str {
+    match auth_type {
+        AuthType::Any => "any",
+        AuthType::Digest => "digest",
+        AuthType::UsernameToken => "usernametoken",
+    }
+}
+
+/// Whether an error means the camera rejected our credentials
+fn is_auth_error(err: &OnvifError) -> bool {
+    let msg = err.0.to_ascii_lowercase();
+    msg.contains("401") || msg.contains("authoriz") || msg.contains("authenticat")
 }
 
 #[derive(Debug)]
@@ -71,6 +88,12 @@ pub struct StreamUri {
     pub audio_samplerate: Option<u32>,
 }
 
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PtzPreset {
+    pub token: String,
+    pub name: Option<String>,
+}
+
 #[derive(Debug)]
 pub struct SnapshotUri {
     pub token: String,
@@ -86,8 +109,21 @@ pub struct OnvifCameraBuilder {
     password: Option<String>,
     fix_time: bool,
     auth_type: AuthType,
+    // Try digest, then WS-Security UsernameToken, keeping whichever the camera accepts
+    auto_auth: bool,
+    // Device service paths to try in order; empty means just `service_path`
+    service_path_candidates: Vec<String>,
 }
 
+/// Device service paths used by common manufacturers, default first
+pub const COMMON_SERVICE_PATHS: &[&str] = &[
+    "onvif/device_service",
+    "onvif/Device",
+    "onvif/device",
+    "onvif/services",
+    "onvif/Device_service",
+];
+
 impl OnvifCameraBuilder {
     /// Create a new builder with default settings
     pub fn new() -> Self {
@@ -98,6 +134,8 @@ impl OnvifCameraBuilder {
             password: None,
             fix_time: false,
             auth_type: AuthType::Any,
+            auto_auth: false,
+            service_path_candidates: Vec::new(),
         }
     }
 
@@ -113,6 +151,13 @@ impl OnvifCameraBuilder {
         self
     }
 
+    /// Try each of `paths` as the device service path until one answers.
+    /// `OnvifCamera::service_path` reports the one that worked.
+    pub fn probe_service_paths(mut self, paths: &[&str]) -> Self {
+        self.service_path_candidates = paths.iter().map(|p| p.to_string()).collect();
+        self
+    }
+
     /// Set the username and password for authentication
     pub fn credentials(mut self, username: &str, password: &str) -> Self {
         self.username = Some(username.to_string());
@@ -126,9 +171,13 @@ impl OnvifCameraBuilder {
         self
     }
 
-    /// Set the authentication type: "any", "digest", or "usernametoken"
+    /// Set the authentication type: "any", "digest", "usernametoken", or "auto".
+    /// "auto" tries digest first and falls back to WS-Security UsernameToken when the camera
+    /// rejects it; `OnvifCamera::auth_type` reports which one worked.
     pub fn auth_type(mut self, auth_type: &str) -> Self {
-        self.auth_type = match auth_type.to_ascii_lowercase().as_str() {
+        let auth_type = auth_type.to_ascii_lowercase();
+        self.auto_auth = auth_type == "auto";
+        self.auth_type = match auth_type.as_str() {
             "digest" => AuthType::Digest,
             "usernametoken" => AuthType::UsernameToken,
             _ => AuthType::Any,
@@ -138,6 +187,66 @@ impl OnvifCameraBuilder {
 
     /// Build the OnvifCamera client
     pub async fn build(self) -> Result<OnvifCamera, OnvifError> {
+        let candidates = if self.service_path_candidates.is_empty() {
+            vec![self.service_path.clone()]
+        } else {
+            self.service_path_candidates.clone()
+        };
+
+        let mut last_error = None;
+        for service_path in &candidates {
+            match self.build_for_path(service_path).await {
+                Ok(camera) => return Ok(camera),
+                // The service exists but refused our credentials; other paths won't help
+                Err(e) if is_auth_error(&e) => return Err(e),
+                Err(e) => {
+                    debug!("No ONVIF device service at {}: {}", service_path, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| OnvifError("No service path to try".to_string())))
+    }
+
+    async fn build_for_path(&self, service_path: &str) -> Result<OnvifCamera, OnvifError> {
+        if !self.auto_auth || self.username.is_none() {
+            return self
+                .build_with_auth(service_path, self.auth_type.clone())
+                .await;
+        }
+
+        let mut last_error = None;
+        for auth_type in [AuthType::Digest, AuthType::UsernameToken] {
+            let name = auth_type_name(&auth_type);
+            let result = match self.build_with_auth(service_path, auth_type).await {
+                // Service discovery is often open to anonymous clients, so confirm the
+                // credentials on a call that requires them
+                Ok(camera) => camera.get_device_information().await.map(|_| camera),
+                Err(e) => Err(e),
+            };
+
+            match result {
+                Ok(camera) => {
+                    debug!("ONVIF camera accepted {} authentication", name);
+                    return Ok(camera);
+                }
+                Err(e) if is_auth_error(&e) => {
+                    debug!("ONVIF camera rejected {} authentication: {}", name, e);
+                    last_error = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| OnvifError("Authentication failed".to_string())))
+    }
+
+    async fn build_with_auth(
+        &self,
+        service_path: &str,
+        auth_type: AuthType,
+    ) -> Result<OnvifCamera, OnvifError> {
         let creds = match (self.username.as_ref(), self.password.as_ref()) {
             (Some(username), Some(password)) => Some(soap::client::Credentials {
                 username: username.clone(),
@@ -157,12 +266,12 @@ impl OnvifCameraBuilder {
             .ok_or_else(|| OnvifError("URI must be specified.".to_string()))?;
 
         let devicemgmt_uri = base_uri
-            .join(&self.service_path)
+            .join(service_path)
             .map_err(|e| OnvifError(e.to_string()))?;
 
         let devicemgmt = soap::client::ClientBuilder::new(&devicemgmt_uri)
             .credentials(creds.clone())
-            .auth_type(self.auth_type.clone())
+            .auth_type(auth_type.clone())
             .build();
 
         let mut camera = OnvifCamera {
@@ -174,6 +283,8 @@ impl OnvifCameraBuilder {
             media: None,
             media2: None,
             analytics: None,
+            auth_type: auth_type_name(&auth_type),
+            service_path: service_path.to_string(),
         };
 
         let time_gap = if self.fix_time {
@@ -227,7 +338,7 @@ impl OnvifCameraBuilder {
             let svc = Some(
                 soap::client::ClientBuilder::new(&service_url)
                     .credentials(creds.clone())
-                    .auth_type(self.auth_type.clone())
+                    .auth_type(auth_type.clone())
                     .fix_time_gap(time_gap)
                     .build(),
             );
@@ -257,6 +368,16 @@ impl OnvifCameraBuilder {
 }
 
 impl OnvifCamera {
+    /// Authentication the camera accepted: "any", "digest" or "usernametoken"
+    pub fn auth_type(&self) -> &'static str {
+        self.auth_type
+    }
+
+    /// Device service path the client is connected to
+    pub fn service_path(&self) -> &str {
+        &self.service_path
+    }
+
     /// Get device capabilities
     pub async fn get_capabilities(&self) -> Result<Capabilities, OnvifError> {
         match schema::devicemgmt::get_capabilities(&self.devicemgmt, &Default::default()).await {
@@ -631,24 +752,36 @@ impl OnvifCamera {
         Ok(mods)
     }
 
-    /// Get PTZ status for the primary media profile
-    pub async fn get_ptz_status(&self) -> Result<schema::ptz::GetStatusResponse, OnvifError> {
-        let ptz_client = self
-            .ptz
+    fn ptz_client(&self) -> Result<&soap::client::Client, OnvifError> {
+        self.ptz
             .as_ref()
-            .ok_or_else(|| OnvifError("Client PTZ is not available".into()))?;
+            .ok_or_else(|| OnvifError("Client PTZ is not available".into()))
+    }
 
+    /// Token of the primary (first) media profile, which PTZ commands are addressed to
+    async fn primary_profile_token(&self) -> Result<schema::onvif::ReferenceToken, OnvifError> {
         let media_client = self
             .media
             .as_ref()
             .ok_or_else(|| OnvifError("Client media is not available".into()))?;
 
-        let profile = &schema::media::get_profiles(media_client, &Default::default())
+        let profiles = schema::media::get_profiles(media_client, &Default::default())
             .await
-            .map_err(|e| OnvifError(e.to_string()))?
-            .profiles[0];
+            .map_err(|e| OnvifError(e.to_string()))?;
+
+        let profile = profiles
+            .profiles
+            .first()
+            .ok_or_else(|| OnvifError("Camera has no media profiles".into()))?;
+
+        Ok(schema::onvif::ReferenceToken(profile.token.0.clone()))
+    }
+
+    /// Get PTZ status for the primary media profile
+    pub async fn get_ptz_status(&self) -> Result<schema::ptz::GetStatusResponse, OnvifError> {
+        let ptz_client = self.ptz_client()?;
+        let profile_token = self.primary_profile_token().await?;
 
-        let profile_token = schema::onvif::ReferenceToken(profile.token.0.clone());
         let status = schema::ptz::get_status(ptz_client, &schema::ptz::GetStatus { profile_token })
             .await
             .map_err(|e| OnvifError(e.to_string()))?;
@@ -656,6 +789,130 @@ impl OnvifCamera {
         Ok(status)
     }
 
+    /// Start moving at the given pan/tilt/zoom velocities (-1.0..=1.0) until `ptz_stop`
+    pub async fn continuous_move(
+        &self,
+        pan: f64,
+        tilt: f64,
+        zoom: f64,
+    ) -> Result<(), OnvifError> {
+        let ptz_client = self.ptz_client()?;
+        let profile_token = self.primary_profile_token().await?;
+
+        schema::ptz::continuous_move(
+            ptz_client,
+            &schema::ptz::ContinuousMove {
+                profile_token,
+                velocity: schema::onvif::Ptzspeed {
+                    pan_tilt: Some(schema::onvif::Vector2D {
+                        x: pan,
+                        y: tilt,
+                        space: None,
+                    }),
+                    zoom: Some(schema::onvif::Vector1D { x: zoom, space: None }),
+                },
+                timeout: None,
+            },
+        )
+        .await
+        .map_err(|e| OnvifError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Move to an absolute pan/tilt position (-1.0..=1.0) and, if given, zoom level (0.0..=1.0)
+    pub async fn absolute_move(
+        &self,
+        pan: f64,
+        tilt: f64,
+        zoom: Option<f64>,
+    ) -> Result<(), OnvifError> {
+        let ptz_client = self.ptz_client()?;
+        let profile_token = self.primary_profile_token().await?;
+
+        schema::ptz::absolute_move(
+            ptz_client,
+            &schema::ptz::AbsoluteMove {
+                profile_token,
+                position: schema::onvif::Ptzvector {
+                    pan_tilt: Some(schema::onvif::Vector2D {
+                        x: pan,
+                        y: tilt,
+                        space: None,
+                    }),
+                    zoom: zoom.map(|x| schema::onvif::Vector1D { x, space: None }),
+                },
+                speed: None,
+            },
+        )
+        .await
+        .map_err(|e| OnvifError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Stop any ongoing pan/tilt and zoom movement
+    pub async fn ptz_stop(&self) -> Result<(), OnvifError> {
+        let ptz_client = self.ptz_client()?;
+        let profile_token = self.primary_profile_token().await?;
+
+        schema::ptz::stop(
+            ptz_client,
+            &schema::ptz::Stop {
+                profile_token,
+                pan_tilt: Some(true),
+                zoom: Some(true),
+            },
+        )
+        .await
+        .map_err(|e| OnvifError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// List the PTZ presets stored on the camera
+    pub async fn get_presets(&self) -> Result<Vec<PtzPreset>, OnvifError> {
+        let ptz_client = self.ptz_client()?;
+        let profile_token = self.primary_profile_token().await?;
+
+        let presets =
+            schema::ptz::get_presets(ptz_client, &schema::ptz::GetPresets { profile_token })
+                .await
+                .map_err(|e| OnvifError(e.to_string()))?;
+
+        debug!("get_presets response: {:#?}", &presets);
+
+        Ok(presets
+            .preset
+            .into_iter()
+            .filter_map(|p| {
+                Some(PtzPreset {
+                    token: p.token?.0,
+                    name: p.name.map(|n| n.0),
+                })
+            })
+            .collect())
+    }
+
+    /// Move to a stored PTZ preset
+    pub async fn goto_preset(&self, preset_token: &str) -> Result<(), OnvifError> {
+        let ptz_client = self.ptz_client()?;
+        let profile_token = self.primary_profile_token().await?;
+
+        schema::ptz::goto_preset(
+            ptz_client,
+            &schema::ptz::GotoPreset {
+                profile_token,
+                preset_token: schema::onvif::ReferenceToken(preset_token.to_string()),
+                speed: None,
+            },
+        )
+        .await
+        .map_err(|e| OnvifError(e.to_string()))?;
+
+        Ok(())
+    }
+
     /// Fetches all available information from the camera
     pub async fn get_all(&self) -> HashMap<String, Result<String, String>> {
         let mut results = HashMap::new();
@@ -732,3 +989,138 @@ impl OnvifCamera {
         results
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    const SOAP_HEADER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<SOAP-ENV:Envelope xmlns:SOAP-ENV="http://www.w3.org/2003/05/soap-envelope" xmlns:tds="http://www.onvif.org/ver10/device/wsdl"><SOAP-ENV:Body>"#;
+    const SOAP_FOOTER: &str = "</SOAP-ENV:Body></SOAP-ENV:Envelope>";
+
+    /// Read one HTTP request (headers plus Content-Length body) and return its path and body
+    async fn read_request(stream: &mut tokio::net::TcpStream) -> (String, String) {
+        let mut data = Vec::new();
+        let mut buf = [0u8; 4096];
+        loop {
+            let n = stream.read(&mut buf).await.unwrap_or(0);
+            if n == 0 {
+                break;
+            }
+            data.extend_from_slice(&buf[..n]);
+
+            let text = String::from_utf8_lossy(&data);
+            if let Some(header_end) = text.find("\r\n\r\n") {
+                let content_length = text[..header_end]
+                    .lines()
+                    .find_map(|line| {
+                        let (name, value) = line.split_once(':')?;
+                        name.eq_ignore_ascii_case("content-length")
+                            .then(|| value.trim().parse::<usize>().ok())?
+                    })
+                    .unwrap_or(0);
+                if data.len() >= header_end + 4 + content_length {
+                    let path = text.split_whitespace().nth(1).unwrap_or("/").to_string();
+                    return (path, text[header_end + 4..].to_string());
+                }
+            }
+        }
+        (String::new(), String::from_utf8_lossy(&data).to_string())
+    }
+
+    /// Mock camera with its device service at `device_path`, optionally rejecting anything
+    /// but WS-Security UsernameToken
+    async fn spawn_mock_camera(device_path: &'static str, require_wsse: bool) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let (path, body) = read_request(&mut stream).await;
+
+                let response = if path != device_path {
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                        .to_string()
+                } else if require_wsse && !body.contains("UsernameToken") {
+                    "HTTP/1.1 401 Unauthorized\r\n\
+                     WWW-Authenticate: Digest realm=\"mock\", nonce=\"abc123\", qop=\"auth\"\r\n\
+                     Content-Length: 0\r\nConnection: close\r\n\r\n"
+                        .to_string()
+                } else {
+                    let payload = if body.contains("GetDeviceInformation") {
+                        "<tds:GetDeviceInformationResponse>\
+                         <tds:Manufacturer>Mock</tds:Manufacturer>\
+                         <tds:Model>WSSE-Only</tds:Model>\
+                         <tds:FirmwareVersion>1.0</tds:FirmwareVersion>\
+                         <tds:SerialNumber>SN-1</tds:SerialNumber>\
+                         <tds:HardwareId>HW-1</tds:HardwareId>\
+                         </tds:GetDeviceInformationResponse>"
+                    } else {
+                        "<tds:GetServicesResponse></tds:GetServicesResponse>"
+                    };
+                    let xml = format!("{}{}{}", SOAP_HEADER, payload, SOAP_FOOTER);
+                    format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/soap+xml; charset=utf-8\r\n\
+                         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                        xml.len(),
+                        xml
+                    )
+                };
+
+                let _ = stream.write_all(response.as_bytes()).await;
+                let _ = stream.shutdown().await;
+            }
+        });
+
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn auto_auth_falls_back_to_ws_security() {
+        let uri = spawn_mock_camera("/onvif/device_service", true).await;
+
+        let camera = OnvifCameraBuilder::new()
+            .uri(&uri)
+            .unwrap()
+            .credentials("admin", "secret")
+            .auth_type("auto")
+            .build()
+            .await
+            .unwrap();
+
+        assert_eq!(camera.auth_type(), "usernametoken");
+        assert_eq!(camera.get_device_information().await.unwrap().model, "WSSE-Only");
+    }
+
+    #[tokio::test]
+    async fn digest_only_fails_against_ws_security_camera() {
+        let uri = spawn_mock_camera("/onvif/device_service", true).await;
+
+        let result = OnvifCameraBuilder::new()
+            .uri(&uri)
+            .unwrap()
+            .credentials("admin", "secret")
+            .auth_type("digest")
+            .build()
+            .await;
+
+        assert!(matches!(result, Err(e) if is_auth_error(&e)));
+    }
+
+    #[tokio::test]
+    async fn probes_non_default_service_path() {
+        let uri = spawn_mock_camera("/onvif/Device", false).await;
+
+        let camera = OnvifCameraBuilder::new()
+            .uri(&uri)
+            .unwrap()
+            .probe_service_paths(COMMON_SERVICE_PATHS)
+            .build()
+            .await
+            .unwrap();
+
+        assert_eq!(camera.service_path(), "onvif/Device");
+    }
+}
@@ -9,6 +9,10 @@ use std::time::{Duration, Instant};
 #[path = "../tutorial-common.rs"]
 mod tutorials_common;
 
+// Shared URL redaction so camera passwords are not printed
+#[path = "../utils/redact.rs"]
+mod redact;
+
 fn run_rtsp_playback() -> Result<()> {
     // Parse command line arguments
     let args: std::env::Args = std::env::args();
@@ -23,7 +27,7 @@ fn run_rtsp_playback() -> Result<()> {
 
     println!("macOS RTSP Viewer Example");
     println!("------------------------");
-    println!("Connecting to: {}", rtsp_url);
+    println!("Connecting to: {}", redact::redact_url(&rtsp_url));
 
     // Initialize GStreamer
     gst::init()?;
@@ -16,6 +16,10 @@ use gstreamer as gst;
 #[path = "../tutorial-common.rs"]
 mod tutorials_common;
 
+// Shared URL redaction so camera passwords are not printed
+#[path = "../utils/redact.rs"]
+mod redact;
+
 // A simple analytics demo result
 #[derive(Debug)]
 struct AnalyticsResult {
@@ -38,7 +42,7 @@ fn run_analytics() -> Result<()> {
     
     println!("RTSP Analytics Service Example");
     println!("-----------------------------");
-    println!("Connecting to: {}", rtsp_url);
+    println!("Connecting to: {}", redact::redact_url(&rtsp_url));
     
     // Initialize GStreamer
     gst::init()?;
@@ -12,6 +12,10 @@ use g_streamer::{StreamManager, StreamSource, StreamType};
 #[path = "../tutorial-common.rs"]
 mod tutorials_common;
 
+// Shared URL redaction so camera passwords are not printed
+#[path = "../utils/redact.rs"]
+mod redact;
+
 fn run_rtsp_live_view() -> Result<()> {
     // Initialize GStreamer
     gst::init()?;
@@ -27,7 +31,7 @@ fn run_rtsp_live_view() -> Result<()> {
 
     // Decide which source to use - RTSP or test pattern
     let rtsp_url = if args.len() > 1 {
-        println!("Connecting to RTSP URL: {}", args[1]);
+        println!("Connecting to RTSP URL: {}", redact::redact_url(&args[1]));
         args[1].clone()
     } else {
         // Use a default URL or fall back to test pattern if no URL provided
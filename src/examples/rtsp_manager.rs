@@ -11,6 +11,10 @@ use g_streamer::{StreamManager, StreamSource, StreamType, BranchType, BranchConf
 #[path = "../tutorial-common.rs"]
 mod tutorials_common;
 
+// Shared URL redaction so camera passwords are not printed
+#[path = "../utils/redact.rs"]
+mod redact;
+
 fn run_rtsp_manager() -> Result<()> {
     // Initialize GStreamer
     gst::init()?;
@@ -26,7 +30,7 @@ fn run_rtsp_manager() -> Result<()> {
     
     // Decide which source to use - RTSP or test pattern
     let source = if args.len() > 1 {
-        println!("Connecting to RTSP URL: {}", args[1]);
+        println!("Connecting to RTSP URL: {}", redact::redact_url(&args[1]));
         StreamSource {
             stream_type: StreamType::RTSP,
             uri: args[1].clone(),
@@ -12,6 +12,10 @@ use std::time::Duration;
 #[path = "../tutorial-common.rs"]
 mod tutorials_common;
 
+// Shared URL redaction so camera passwords are not printed
+#[path = "../utils/redact.rs"]
+mod redact;
+
 // Import our service modules
 use g_streamer::services::{CameraManager, RecordingService, StreamingService};
 
@@ -58,7 +62,7 @@ fn run_service() -> Result<()> {
 
     println!("RTSP Service Example");
     println!("-------------------");
-    println!("Connecting to: {}", rtsp_url);
+    println!("Connecting to: {}", redact::redact_url(&rtsp_url));
 
     // Initialize GStreamer (required for our services)
     gst::init()?;
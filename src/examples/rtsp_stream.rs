@@ -10,6 +10,10 @@ use std::time::Duration;
 #[path = "../tutorial-common.rs"]
 mod tutorials_common;
 
+// Shared URL redaction so camera passwords are not printed
+#[path = "../utils/redact.rs"]
+mod redact;
+
 // A struct to keep track of frame statistics
 struct FrameStats {
     frame_count: u32,
@@ -52,7 +56,7 @@ fn run_stream() -> Result<()> {
 
     println!("RTSP Stream Example");
     println!("-------------------");
-    println!("Connecting to: {}", rtsp_url);
+    println!("Connecting to: {}", redact::redact_url(&rtsp_url));
 
     // Initialize GStreamer
     gst::init()?;
@@ -1,6 +1,8 @@
-use crate::config::MessageBrokerConfig;
+use crate::config::{BrokerBackend, MessageBrokerConfig};
 use crate::error::Error;
 use crate::messaging::event::{EventMessage, EventType};
+use crate::messaging::fallback::{FallbackQueue, QueuedEvent};
+use crate::messaging::local::LocalBroker;
 use anyhow::Result;
 use async_trait::async_trait;
 use deadpool_lapin::{Config, Manager, Pool, PoolError};
@@ -16,6 +18,7 @@ use lapin::{
 use log::{debug, error, info, warn};
 use serde::Serialize;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::{mpsc, Mutex, RwLock};
@@ -44,7 +47,8 @@ pub trait MessageBrokerTrait: Send + Sync {
     async fn unsubscribe(&self, subscription_id: &str) -> Result<()>;
 }
 
-/// RabbitMQ message broker implementation
+/// Message broker backed by RabbitMQ, or by an in-process [`LocalBroker`] when the
+/// configured backend is `memory` or `none`
 pub struct MessageBroker {
     /// Connection pool
     pool: Pool,
@@ -54,11 +58,23 @@ pub struct MessageBroker {
     subscriptions: Arc<RwLock<HashMap<String, JoinHandle<()>>>>,
     /// Default channel
     channel: Arc<Mutex<Option<Channel>>>,
+    /// Whether the last publish or reconnect attempt reached RabbitMQ
+    connected: AtomicBool,
+    /// Events published while RabbitMQ was unreachable, flushed on reconnect
+    fallback: Mutex<FallbackQueue>,
+    /// In-process broker used instead of RabbitMQ for the `memory` and `none` backends
+    local: Option<LocalBroker>,
 }
 
 impl MessageBroker {
     /// Create a new message broker
     pub async fn new(config: MessageBrokerConfig) -> Result<Self> {
+        let local = match config.backend {
+            BrokerBackend::RabbitMq => None,
+            BrokerBackend::Memory => Some(LocalBroker::memory()),
+            BrokerBackend::None => Some(LocalBroker::noop()),
+        };
+
         // Create RabbitMQ connection pool
         // Create pool config using the deadpool-lapin API
         let pool_config = Config {
@@ -79,13 +95,25 @@ impl MessageBroker {
         // Create the broker
         let broker = Self {
             pool,
+            fallback: Mutex::new(FallbackQueue::new(config.fallback_queue_size)),
             config,
             subscriptions: Arc::new(RwLock::new(HashMap::new())),
             channel: Arc::new(Mutex::new(None)),
+            connected: AtomicBool::new(false),
+            local,
         };
 
-        // Initialize broker (create exchanges)
-        broker.init().await?;
+        if broker.local.is_some() {
+            info!("Using in-process message broker ({:?} backend)", broker.config.backend);
+            return Ok(broker);
+        }
+
+        // Initialize broker (create exchanges). If RabbitMQ is down, events are buffered
+        // until the reconnect task gets through.
+        match broker.init().await {
+            Ok(()) => broker.connected.store(true, Ordering::SeqCst),
+            Err(e) => warn!("RabbitMQ is unreachable, buffering events until it is back: {}", e),
+        }
 
         Ok(broker)
     }
@@ -163,6 +191,90 @@ impl MessageBroker {
         }
     }
     
+    /// Publish a serialized event on the main exchange
+    async fn send(&self, event: &QueuedEvent) -> Result<()> {
+        let result = self.basic_publish(event).await;
+        crate::metrics::record_broker_publish(result.is_ok());
+        result
+    }
+    
+    async fn basic_publish(&self, event: &QueuedEvent) -> Result<()> {
+        let channel = self.get_channel().await?;
+        channel
+            .basic_publish(
+                &self.config.exchange,
+                &event.routing_key,
+                BasicPublishOptions::default(),
+                &event.payload,
+                BasicProperties::default(),
+            )
+            .await
+            .map_err(|e| Error::Service(format!("Failed to publish message: {}", e)))?;
+        
+        Ok(())
+    }
+    
+    /// Re-initialize the broker if the connection was lost and flush buffered events.
+    ///
+    /// The fallback queue is only locked to check and swap its contents, so publishers
+    /// aren't held up while RabbitMQ is slow to answer.
+    async fn reconnect(&self) {
+        let connected = self.connected.load(Ordering::SeqCst);
+        if connected && self.fallback.lock().await.is_empty() {
+            return;
+        }
+
+        if !connected {
+            if let Err(e) = self.init().await {
+                debug!("RabbitMQ still unreachable: {}", e);
+                return;
+            }
+        }
+
+        let mut sent = 0;
+        loop {
+            let mut pending = {
+                let mut fallback = self.fallback.lock().await;
+                if fallback.is_empty() {
+                    self.connected.store(true, Ordering::SeqCst);
+                    break;
+                }
+                // Keep publishers buffering behind the events being flushed
+                self.connected.store(false, Ordering::SeqCst);
+                fallback.take()
+            };
+
+            match pending.flush(|event| async move { self.send(&event).await }).await {
+                Ok(count) => sent += count,
+                Err(e) => {
+                    let mut fallback = self.fallback.lock().await;
+                    fallback.restore(pending);
+                    warn!("Failed to flush buffered events, {} still queued: {}", fallback.len(), e);
+                    return;
+                }
+            }
+        }
+
+        if sent > 0 {
+            info!("Message broker reconnected, published {} buffered events", sent);
+        }
+    }
+    
+    /// Health check for the broker connection
+    pub async fn health_check(&self) -> Result<bool> {
+        if self.local.is_some() {
+            return Ok(true);
+        }
+
+        match self.pool.get().await {
+            Ok(conn) => Ok(conn.status().connected()),
+            Err(e) => {
+                warn!("Message broker health check failed: {}", e);
+                Ok(false)
+            }
+        }
+    }
+    
     /// Get the AMQP connection from a pool object
     async fn get_amqp_connection(&self) -> Result<Connection> {
         // Get a connection from the pool
@@ -328,36 +440,40 @@ impl MessageBroker {
 #[async_trait]
 impl MessageBrokerTrait for MessageBroker {
     async fn publish<T: Serialize + Send>(&self, event_type: EventType, source_id: Option<Uuid>, payload: T) -> Result<()> {
-        // Create event message
-        let event = EventMessage::new(event_type, source_id, payload)?;
-        
-        // Serialize the event
-        let message = serde_json::to_vec(&event)?;
-        
-        // Get a channel
-        let channel = self.get_channel().await?;
+        if let Some(local) = &self.local {
+            return local.publish(event_type, source_id, payload).await;
+        }
         
-        // Get the routing key
-        let routing_key = event.routing_key();
+        // Create and serialize the event
+        let event = EventMessage::new(event_type, source_id, payload)?;
+        let queued = QueuedEvent {
+            routing_key: event.routing_key(),
+            payload: serde_json::to_vec(&event)?,
+        };
         
-        // Publish the message
-        channel
-            .basic_publish(
-                &self.config.exchange,
-                &routing_key,
-                BasicPublishOptions::default(),
-                &message,
-                BasicProperties::default(),
-            )
-            .await
-            .map_err(|e| Error::Service(format!("Failed to publish message: {}", e)))?;
-            
-        debug!("Published event: {} with routing key: {}", event.id, routing_key);
+        // Publish directly unless earlier events are still waiting, to keep them in order
+        if self.connected.load(Ordering::SeqCst) && self.fallback.lock().await.is_empty() {
+            match self.send(&queued).await {
+                Ok(()) => {
+                    debug!("Published event: {} with routing key: {}", event.id, queued.routing_key);
+                    return Ok(());
+                }
+                Err(e) => {
+                    warn!("RabbitMQ is unreachable, buffering events until it is back: {}", e);
+                    self.connected.store(false, Ordering::SeqCst);
+                }
+            }
+        }
         
+        self.fallback.lock().await.push(queued);
         Ok(())
     }
     
     async fn subscribe(&self, event_type: EventType, callback: EventCallback) -> Result<String> {
+        if let Some(local) = &self.local {
+            return local.subscribe(event_type, callback).await;
+        }
+        
         // Create routing pattern for event type
         let pattern = event_type.to_string();
         
@@ -366,19 +482,31 @@ impl MessageBrokerTrait for MessageBroker {
     }
     
     async fn subscribe_source(&self, source_id: Uuid, callback: EventCallback) -> Result<String> {
-        // Create routing pattern for source ID
-        let pattern = format!("*.{}", source_id);
+        if let Some(local) = &self.local {
+            return local.subscribe_source(source_id, callback).await;
+        }
+        
+        // Create routing pattern for source ID (routing keys end with the source ID)
+        let pattern = format!("#.{}", source_id);
         
         // Start consumer
         self.start_consumer(&pattern, callback).await
     }
     
     async fn subscribe_pattern(&self, pattern: &str, callback: EventCallback) -> Result<String> {
+        if let Some(local) = &self.local {
+            return local.subscribe_pattern(pattern, callback).await;
+        }
+        
         // Start consumer with the given pattern
         self.start_consumer(pattern, callback).await
     }
     
     async fn unsubscribe(&self, subscription_id: &str) -> Result<()> {
+        if let Some(local) = &self.local {
+            return local.unsubscribe(subscription_id).await;
+        }
+        
         // Get the subscriptions map
         let mut subscriptions = self.subscriptions.write().await;
         
@@ -397,7 +525,23 @@ impl MessageBrokerTrait for MessageBroker {
 /// Create a message broker service
 pub async fn create_message_broker(config: MessageBrokerConfig) -> Result<Arc<MessageBroker>> {
     // Create the broker
-    let broker = MessageBroker::new(config).await?;
+    let broker = Arc::new(MessageBroker::new(config).await?);
+    if broker.local.is_some() {
+        return Ok(broker);
+    }
+    
+    // Keep retrying the connection in the background and flush buffered events once it is back
+    let weak = Arc::downgrade(&broker);
+    let delay = Duration::from_millis(broker.config.retry_delay_ms.max(100));
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(delay).await;
+            match weak.upgrade() {
+                Some(broker) => broker.reconnect().await,
+                None => break,
+            }
+        }
+    });
     
-    Ok(Arc::new(broker))
+    Ok(broker)
 }
\ No newline at end of file
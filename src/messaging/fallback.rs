@@ -0,0 +1,87 @@
+use anyhow::Result;
+use log::warn;
+use std::collections::VecDeque;
+use std::future::Future;
+
+/// A serialized event waiting to be published
+#[derive(Debug, Clone)]
+pub struct QueuedEvent {
+    pub routing_key: String,
+    pub payload: Vec<u8>,
+}
+
+/// Bounded in-memory buffer for events published while the broker is unreachable.
+///
+/// When full, the oldest event is dropped to make room for the new one.
+#[derive(Debug)]
+pub struct FallbackQueue {
+    events: VecDeque<QueuedEvent>,
+    capacity: usize,
+}
+
+impl FallbackQueue {
+    /// Create a queue holding at most `capacity` events
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            events: VecDeque::new(),
+            capacity: capacity.max(1),
+        }
+    }
+
+    /// Buffer an event, dropping the oldest one if the queue is full
+    pub fn push(&mut self, event: QueuedEvent) {
+        if self.events.len() >= self.capacity {
+            if let Some(dropped) = self.events.pop_front() {
+                warn!(
+                    "Message broker fallback queue is full, dropping event with routing key {}",
+                    dropped.routing_key
+                );
+                crate::metrics::BROKER_DROPPED_EVENTS.inc();
+            }
+        }
+        self.events.push_back(event);
+    }
+
+    /// Move the buffered events into a queue of their own, leaving this one empty
+    pub fn take(&mut self) -> FallbackQueue {
+        FallbackQueue {
+            events: std::mem::take(&mut self.events),
+            capacity: self.capacity,
+        }
+    }
+
+    /// Put events taken out with `take` back in front of those buffered since
+    pub fn restore(&mut self, mut unsent: FallbackQueue) {
+        let newer = std::mem::take(&mut self.events);
+        self.events = std::mem::take(&mut unsent.events);
+        for event in newer {
+            self.push(event);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Publish buffered events in order with `send`, stopping at the first failure.
+    ///
+    /// An event is only removed once it was sent, so a failed flush leaves it at the front
+    /// of the queue. Returns the number of events sent.
+    pub async fn flush<F, Fut>(&mut self, mut send: F) -> Result<usize>
+    where
+        F: FnMut(QueuedEvent) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let mut sent = 0;
+        while let Some(event) = self.events.front() {
+            send(event.clone()).await?;
+            self.events.pop_front();
+            sent += 1;
+        }
+        Ok(sent)
+    }
+}
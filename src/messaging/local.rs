@@ -0,0 +1,138 @@
+use crate::error::Error;
+use crate::messaging::broker::{EventCallback, MessageBrokerTrait};
+use crate::messaging::event::{EventMessage, EventType};
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{debug, error, info};
+use serde::Serialize;
+use std::collections::HashMap;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+/// In-process message broker for deployments without RabbitMQ.
+///
+/// With fan-out enabled (the `memory` backend) events are handed to the in-process
+/// subscribers whose routing pattern matches; otherwise (the `none` backend) they are
+/// only logged.
+pub struct LocalBroker {
+    fan_out: bool,
+    /// Subscription ID -> (routing pattern, callback)
+    subscriptions: RwLock<HashMap<String, (String, EventCallback)>>,
+}
+
+impl LocalBroker {
+    /// Create a broker delivering events to in-process subscribers
+    pub fn memory() -> Self {
+        Self {
+            fan_out: true,
+            subscriptions: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Create a broker that drops every event
+    pub fn noop() -> Self {
+        Self {
+            fan_out: false,
+            subscriptions: RwLock::new(HashMap::new()),
+        }
+    }
+
+    async fn add_subscription(&self, pattern: String, callback: EventCallback) -> Result<String> {
+        let subscription_id = Uuid::new_v4().to_string();
+        info!(
+            "Started in-process consumer for pattern: {} (subscription: {})",
+            pattern, subscription_id
+        );
+        self.subscriptions
+            .write()
+            .await
+            .insert(subscription_id.clone(), (pattern, callback));
+        Ok(subscription_id)
+    }
+}
+
+/// Match a routing key against an AMQP topic pattern, where `*` stands for exactly one
+/// word and `#` for zero or more words
+pub fn topic_matches(pattern: &str, routing_key: &str) -> bool {
+    fn matches(pattern: &[&str], key: &[&str]) -> bool {
+        match pattern.split_first() {
+            None => key.is_empty(),
+            Some((&"#", rest)) => (0..=key.len()).any(|skip| matches(rest, &key[skip..])),
+            Some((word, rest)) => match key.split_first() {
+                Some((first, key_rest)) => {
+                    (*word == "*" || word == first) && matches(rest, key_rest)
+                }
+                None => false,
+            },
+        }
+    }
+
+    let pattern: Vec<&str> = pattern.split('.').collect();
+    let key: Vec<&str> = routing_key.split('.').collect();
+    matches(&pattern, &key)
+}
+
+#[async_trait]
+impl MessageBrokerTrait for LocalBroker {
+    async fn publish<T: Serialize + Send>(
+        &self,
+        event_type: EventType,
+        source_id: Option<Uuid>,
+        payload: T,
+    ) -> Result<()> {
+        let event = EventMessage::new(event_type, source_id, payload)?;
+        let routing_key = event.routing_key();
+
+        if !self.fan_out {
+            debug!("Message broker disabled, dropping event: {}", routing_key);
+            return Ok(());
+        }
+
+        let callbacks: Vec<EventCallback> = self
+            .subscriptions
+            .read()
+            .await
+            .values()
+            .filter(|(pattern, _)| topic_matches(pattern, &routing_key))
+            .map(|(_, callback)| callback.clone())
+            .collect();
+
+        for callback in callbacks {
+            if let Err(e) = callback(event.clone()) {
+                error!("Error processing event: {}", e);
+            }
+        }
+
+        debug!(
+            "Published event: {} with routing key: {}",
+            event.id, routing_key
+        );
+        Ok(())
+    }
+
+    async fn subscribe(&self, event_type: EventType, callback: EventCallback) -> Result<String> {
+        self.add_subscription(event_type.to_string(), callback)
+            .await
+    }
+
+    async fn subscribe_source(&self, source_id: Uuid, callback: EventCallback) -> Result<String> {
+        self.add_subscription(format!("#.{}", source_id), callback)
+            .await
+    }
+
+    async fn subscribe_pattern(&self, pattern: &str, callback: EventCallback) -> Result<String> {
+        self.add_subscription(pattern.to_string(), callback).await
+    }
+
+    async fn unsubscribe(&self, subscription_id: &str) -> Result<()> {
+        match self.subscriptions.write().await.remove(subscription_id) {
+            Some(_) => {
+                info!("Unsubscribed: {}", subscription_id);
+                Ok(())
+            }
+            None => {
+                Err(Error::NotFound(format!("Subscription not found: {}", subscription_id)).into())
+            }
+        }
+    }
+}
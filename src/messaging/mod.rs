@@ -1,6 +1,8 @@
 pub mod broker;
 pub mod camera_events;
 pub mod event;
+pub mod fallback;
+pub mod local;
 #[cfg(test)]
 mod tests;
 
@@ -1,8 +1,9 @@
 #[cfg(test)]
 mod tests {
-    use super::broker::create_message_broker;
+    use super::broker::{create_message_broker, MessageBrokerTrait};
     use super::event::{EventMessage, EventType};
-    use crate::config::MessageBrokerConfig;
+    use super::fallback::{FallbackQueue, QueuedEvent};
+    use crate::config::{BrokerBackend, MessageBrokerConfig};
     use anyhow::Result;
     use std::sync::{Arc, Mutex};
     use std::time::Duration;
@@ -75,4 +76,97 @@ mod tests {
         
         Ok(())
     }
-}
\ No newline at end of file
+    
+    // Test that events buffered during an outage are delivered in order after reconnect
+    #[tokio::test]
+    async fn test_events_buffered_during_outage_are_delivered_after_reconnect() -> Result<()> {
+        let event = |n: u32| QueuedEvent {
+            routing_key: format!("system.startup.{}", n),
+            payload: Vec::new(),
+        };
+        let mut queue = FallbackQueue::new(3);
+        for n in 0..4 {
+            queue.push(event(n));
+        }
+        // The oldest event was dropped to stay within capacity
+        assert_eq!(queue.len(), 3);
+
+        let delivered = Arc::new(Mutex::new(Vec::new()));
+
+        // Still down: nothing is delivered and nothing is lost
+        let result = queue
+            .flush(|_| async { Err(anyhow::anyhow!("connection refused")) })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(queue.len(), 3);
+
+        // Back up: everything is delivered in publish order
+        let sent = queue
+            .flush(|event| {
+                let delivered = delivered.clone();
+                async move {
+                    delivered.lock().unwrap().push(event.routing_key);
+                    Ok(())
+                }
+            })
+            .await?;
+        assert_eq!(sent, 3);
+        assert!(queue.is_empty());
+        assert_eq!(
+            *delivered.lock().unwrap(),
+            vec!["system.startup.1", "system.startup.2", "system.startup.3"]
+        );
+
+        Ok(())
+    }
+    
+    // Test that publishing works without RabbitMQ when the broker is disabled
+    #[tokio::test]
+    async fn test_publish_with_noop_broker() -> Result<()> {
+        let config = MessageBrokerConfig {
+            backend: BrokerBackend::None,
+            ..MessageBrokerConfig::default()
+        };
+        let broker = create_message_broker(config).await?;
+        
+        broker.publish(
+            EventType::SystemStartup,
+            None,
+            serde_json::json!({"version": env!("CARGO_PKG_VERSION")}),
+        ).await?;
+        assert!(broker.health_check().await?);
+        
+        Ok(())
+    }
+    
+    // Test that the in-memory broker fans events out to matching subscribers
+    #[tokio::test]
+    async fn test_memory_broker_delivers_to_matching_subscribers() -> Result<()> {
+        let config = MessageBrokerConfig {
+            backend: BrokerBackend::Memory,
+            ..MessageBrokerConfig::default()
+        };
+        let broker = create_message_broker(config).await?;
+        let camera_id = uuid::Uuid::new_v4();
+        
+        let received = Arc::new(Mutex::new(Vec::<EventMessage>::new()));
+        let received_clone = received.clone();
+        broker.subscribe_source(
+            camera_id,
+            Arc::new(move |event| {
+                received_clone.lock().unwrap().push(event);
+                Ok(())
+            }),
+        ).await?;
+        
+        broker.publish(EventType::CameraConnected, Some(camera_id), serde_json::json!({})).await?;
+        broker.publish(EventType::CameraConnected, Some(uuid::Uuid::new_v4()), serde_json::json!({})).await?;
+        broker.publish(EventType::SystemStartup, None, serde_json::json!({})).await?;
+        
+        let events = received.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].source_id, Some(camera_id));
+        
+        Ok(())
+    }
+}
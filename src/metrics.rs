@@ -0,0 +1,141 @@
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_int_counter, register_int_counter_vec, register_int_gauge, register_int_gauge_vec,
+    Encoder, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, TextEncoder,
+};
+
+/// Recordings currently being captured
+pub static ACTIVE_RECORDINGS: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "nvr_active_recordings",
+        "Recordings currently being captured"
+    )
+    .unwrap()
+});
+
+/// Streams registered with the stream manager
+pub static ACTIVE_STREAMS: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "nvr_active_streams",
+        "Streams registered with the stream manager"
+    )
+    .unwrap()
+});
+
+/// Pipeline state of each stream, 1 for the state the pipeline is currently in
+pub static STREAM_PIPELINE_STATE: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "nvr_stream_pipeline_state",
+        "Current GStreamer pipeline state of each stream",
+        &["stream_id", "state"]
+    )
+    .unwrap()
+});
+
+/// Bytes of finalized recordings written per camera
+pub static RECORDING_BYTES_WRITTEN: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "nvr_recording_bytes_written_total",
+        "Bytes of finalized recordings written per camera",
+        &["camera_id"]
+    )
+    .unwrap()
+});
+
+/// FFmpeg HLS transcode jobs currently running
+pub static HLS_JOBS: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "nvr_hls_jobs",
+        "FFmpeg HLS transcode jobs currently running"
+    )
+    .unwrap()
+});
+
+/// Database pool connections by state (`in_use` or `idle`)
+pub static DB_POOL_CONNECTIONS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "nvr_db_pool_connections",
+        "Database pool connections by state",
+        &["state"]
+    )
+    .unwrap()
+});
+
+/// Message broker publishes by result (`success` or `failure`)
+pub static BROKER_PUBLISHES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "nvr_broker_publishes_total",
+        "Message broker publishes by result",
+        &["result"]
+    )
+    .unwrap()
+});
+
+/// Events dropped from the broker's fallback queue because it was full
+pub static BROKER_DROPPED_EVENTS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "nvr_broker_dropped_events_total",
+        "Events dropped from the broker fallback queue because it was full"
+    )
+    .unwrap()
+});
+
+/// Times a segment row found the segment DB queue full and had to wait for room
+pub static SEGMENT_QUEUE_FULL: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "nvr_segment_queue_full_total",
+        "Segment rows that found the segment DB queue full"
+    )
+    .unwrap()
+});
+
+/// Segment rows that could not be written, leaving a gap in playback
+pub static SEGMENT_ROWS_DROPPED: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "nvr_segment_rows_dropped_total",
+        "Segment rows that could not be written to the database"
+    )
+    .unwrap()
+});
+
+/// Count a finished broker publish
+pub fn record_broker_publish(success: bool) {
+    let result = if success { "success" } else { "failure" };
+    BROKER_PUBLISHES.with_label_values(&[result]).inc();
+}
+
+/// Add the size of a finalized recording to its camera's byte counter
+pub fn record_bytes_written(camera_id: &uuid::Uuid, bytes: u64) {
+    RECORDING_BYTES_WRITTEN
+        .with_label_values(&[&camera_id.to_string()])
+        .inc_by(bytes);
+}
+
+/// Encode every registered metric in the Prometheus text format.
+///
+/// Counters are incremented by the services as work happens; gauges describing current
+/// state are refreshed by the `/metrics` handler just before this is called.
+pub fn render() -> anyhow::Result<String> {
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer)?;
+    Ok(String::from_utf8(buffer)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counters_show_up_in_rendered_output() {
+        record_broker_publish(true);
+        record_broker_publish(false);
+        record_bytes_written(&uuid::Uuid::nil(), 1024);
+
+        let output = render().unwrap();
+        assert!(output.contains("nvr_broker_publishes_total{result=\"success\"}"));
+        assert!(output.contains("nvr_broker_publishes_total{result=\"failure\"}"));
+        assert!(output.contains(
+            "nvr_recording_bytes_written_total{camera_id=\"00000000-0000-0000-0000-000000000000\"} 1024"
+        ));
+    }
+}
@@ -0,0 +1,474 @@
+use crate::config::ArchiveConfig;
+use crate::db::models::recording_models::{Recording, RecordingUpdate};
+use crate::db::repositories::recordings::RecordingsRepository;
+use crate::error::Error;
+use crate::recorder::record::RecordingManager;
+use crate::storage::ObjectStore;
+use anyhow::{anyhow, Result};
+use chrono::Utc;
+use log::{debug, error, info, warn};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::time::interval;
+
+/// Metadata key recording where an archived recording's file went
+pub const ARCHIVE_METADATA_KEY: &str = "archive";
+
+/// Recordings archived per pass at most
+const ARCHIVE_BATCH_SIZE: i64 = 500;
+
+/// Copies of archived recordings fetched for playback are removed after this long
+const CACHE_MAX_AGE: Duration = Duration::from_secs(3600);
+
+/// Object key of an archived recording, `None` while its file is on the recordings disk
+pub fn archived_key(recording: &Recording) -> Option<&str> {
+    recording
+        .metadata
+        .as_ref()?
+        .get(ARCHIVE_METADATA_KEY)?
+        .get("key")?
+        .as_str()
+}
+
+/// Uploads finalized recordings to an object store and removes them from the recordings
+/// disk.
+///
+/// A recording's row is pointed at the object only once the store confirms the upload's
+/// size, and the local file is removed only after that; anything failing along the way is
+/// left as it was for the next pass. Archived recordings are fetched back into a cache
+/// under the recordings directory to be played.
+pub struct RecordingArchiver {
+    config: ArchiveConfig,
+    store: Arc<dyn ObjectStore>,
+    recordings_repo: RecordingsRepository,
+    recordings_path: PathBuf,
+    cache_path: PathBuf,
+    recording_manager: Option<Arc<RecordingManager>>,
+}
+
+impl RecordingArchiver {
+    /// Create an archiver for the recordings under `recordings_path`
+    pub fn new(
+        config: ArchiveConfig,
+        store: Arc<dyn ObjectStore>,
+        recordings_repo: RecordingsRepository,
+        recordings_path: &Path,
+    ) -> Self {
+        Self {
+            config,
+            store,
+            recordings_repo,
+            recordings_path: recordings_path.to_path_buf(),
+            cache_path: recordings_path.join(".archive-cache"),
+            recording_manager: None,
+        }
+    }
+
+    /// Use the recording manager to leave segments of recordings in progress alone
+    pub fn with_recording_manager(mut self, recording_manager: Arc<RecordingManager>) -> Self {
+        self.recording_manager = Some(recording_manager);
+        self
+    }
+
+    /// Archive pending recordings every `check_interval_secs` in the background
+    pub async fn start(self: Arc<Self>) -> Result<()> {
+        info!(
+            "Starting recording archiver ({} store), checking every {} seconds",
+            self.store.name(),
+            self.config.check_interval_secs
+        );
+
+        tokio::spawn(async move {
+            let mut interval = interval(Duration::from_secs(self.config.check_interval_secs));
+            loop {
+                interval.tick().await;
+
+                match self.archive_pending().await {
+                    Ok(0) => {}
+                    Ok(count) => info!("Archived {} recordings", count),
+                    Err(e) => error!("Error archiving recordings: {}", e),
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Remove stale copies of archived recordings from the cache every
+    /// `check_interval_secs` in the background. Runs whether or not [`Self::start`] does,
+    /// since recordings tiered by the storage cleanup are fetched into the same cache.
+    pub fn start_cache_pruning(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut interval = interval(Duration::from_secs(self.config.check_interval_secs));
+            loop {
+                interval.tick().await;
+                self.remove_stale_cache();
+            }
+        });
+    }
+
+    /// Archive recordings finalized at least `min_age_secs` ago. Returns how many were
+    /// archived.
+    pub async fn archive_pending(&self) -> Result<usize> {
+        let finalized_before =
+            Utc::now() - chrono::Duration::seconds(self.config.min_age_secs as i64);
+        let candidates = self
+            .recordings_repo
+            .get_unarchived(finalized_before, ARCHIVE_BATCH_SIZE)
+            .await?;
+
+        let active = match &self.recording_manager {
+            Some(manager) => manager.active_recording_ids().await,
+            None => HashSet::new(),
+        };
+
+        let mut archived = 0;
+        for recording in candidates {
+            let in_progress = active.contains(&recording.id)
+                || recording
+                    .parent_recording_id
+                    .is_some_and(|parent| active.contains(&parent));
+            // Directories of unsegmented parents and files already gone have nothing to upload
+            if in_progress || !recording.file_path.is_file() {
+                continue;
+            }
+
+            match self.archive(&recording).await {
+                Ok(()) => archived += 1,
+                Err(e) => warn!(
+                    "Failed to archive recording {}, keeping it local: {}",
+                    recording.id, e
+                ),
+            }
+        }
+
+        Ok(archived)
+    }
+
+    /// Upload a recording's file, point its row at the object and remove the local file
+    pub async fn archive(&self, recording: &Recording) -> Result<()> {
+        let key = self.key_for(&recording.file_path)?;
+        let size = self.upload(&key, &recording.file_path).await?;
+
+        self.recordings_repo
+            .update_with_data(
+                &recording.id,
+                RecordingUpdate {
+                    file_path: Some(self.store.location(&key)),
+                    duration: None,
+                    file_size: None,
+                    end_time: None,
+                    metadata: Some(serde_json::json!({
+                        ARCHIVE_METADATA_KEY: {
+                            "store": self.store.name(),
+                            "key": key,
+                            "size": size,
+                            "archived_at": Utc::now().to_rfc3339(),
+                        }
+                    })),
+                    segment_id: None,
+                    parent_recording_id: None,
+                    resolution: None,
+                    fps: None,
+                },
+            )
+            .await?;
+
+        if let Err(e) = tokio::fs::remove_file(&recording.file_path).await {
+            warn!(
+                "Archived {} but failed to remove it locally: {}",
+                recording.file_path.display(),
+                e
+            );
+        }
+        debug!("Archived recording {} as {}", recording.id, key);
+        Ok(())
+    }
+
+    /// Object key of a file: its path under the recordings directory
+    fn key_for(&self, path: &Path) -> Result<String> {
+        let relative = path.strip_prefix(&self.recordings_path).map_err(|_| {
+            Error::InvalidInput(format!(
+                "{} is outside the recordings directory",
+                path.display()
+            ))
+        })?;
+        Ok(relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+            .join("/"))
+    }
+
+    /// Upload `path` as `key` until the store holds all of it, retrying failed or partial
+    /// uploads `retry_attempts` times in all with a doubling delay. Returns the size.
+    async fn upload(&self, key: &str, path: &Path) -> Result<u64> {
+        let size = tokio::fs::metadata(path).await?.len();
+        let mut delay = Duration::from_secs(self.config.retry_delay_secs);
+        let mut attempt = 1;
+
+        loop {
+            let e = match self.store.put(key, path).await {
+                Ok(()) => match self.store.size(key).await {
+                    Ok(Some(stored)) if stored == size => return Ok(size),
+                    Ok(stored) => anyhow!(
+                        "store holds {} of {} bytes",
+                        stored.unwrap_or_default(),
+                        size
+                    ),
+                    Err(e) => e,
+                },
+                Err(e) => e,
+            };
+
+            if attempt >= self.config.retry_attempts {
+                return Err(e.context(format!(
+                    "Upload of {} failed after {} attempts",
+                    key, attempt
+                )));
+            }
+            warn!(
+                "Upload of {} failed (attempt {}/{}), retrying in {:?}: {}",
+                key, attempt, self.config.retry_attempts, delay, e
+            );
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+            attempt += 1;
+        }
+    }
+
+    /// Local file a recording can be read from, fetching archived ones into the cache
+    pub async fn local_path(&self, recording: &Recording) -> Result<PathBuf> {
+        let Some(key) = archived_key(recording) else {
+            return Ok(recording.file_path.clone());
+        };
+
+        let cached = self.cache_path.join(key);
+        if !cached.is_file() {
+            debug!("Fetching archived recording {} from {}", recording.id, key);
+            self.store.get(key, &cached).await?;
+        }
+        Ok(cached)
+    }
+
+    /// Remove an archived recording's object and cached copy
+    pub async fn delete(&self, recording: &Recording) -> Result<()> {
+        let Some(key) = archived_key(recording) else {
+            return Ok(());
+        };
+        let _ = std::fs::remove_file(self.cache_path.join(key));
+        self.store.delete(key).await
+    }
+
+    /// Remove cached copies fetched more than `CACHE_MAX_AGE` ago
+    fn remove_stale_cache(&self) {
+        let mut dirs = vec![self.cache_path.clone()];
+        while let Some(dir) = dirs.pop() {
+            let Ok(entries) = std::fs::read_dir(&dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let Ok(metadata) = entry.metadata() else {
+                    continue;
+                };
+                if metadata.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+                let age = metadata
+                    .modified()
+                    .ok()
+                    .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+                    .unwrap_or_default();
+                if age > CACHE_MAX_AGE {
+                    if let Err(e) = std::fs::remove_file(&path) {
+                        debug!("Failed to remove cached {}: {}", path.display(), e);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{archived_key, RecordingArchiver};
+    use crate::config::ArchiveConfig;
+    use crate::db::repositories::recordings::RecordingsRepository;
+    use crate::db::test_fixtures::insert_camera_and_stream;
+    use crate::storage::ObjectStore;
+    use anyhow::{anyhow, Result};
+    use async_trait::async_trait;
+    use chrono::{Duration, Utc};
+    use sqlx::PgPool;
+    use std::collections::HashMap;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use uuid::Uuid;
+
+    /// In-memory store whose next `failing_puts` uploads fail
+    #[derive(Default)]
+    struct MockObjectStore {
+        objects: Mutex<HashMap<String, Vec<u8>>>,
+        failing_puts: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl ObjectStore for MockObjectStore {
+        fn name(&self) -> &'static str {
+            "mock"
+        }
+
+        fn location(&self, key: &str) -> PathBuf {
+            PathBuf::from(format!("mock://{}", key))
+        }
+
+        async fn put(&self, key: &str, path: &Path) -> Result<()> {
+            let failing = self.failing_puts.load(Ordering::SeqCst);
+            if failing > 0 {
+                self.failing_puts.store(failing - 1, Ordering::SeqCst);
+                // A dropped connection leaves a truncated object behind
+                self.objects
+                    .lock()
+                    .unwrap()
+                    .insert(key.to_string(), b"part".to_vec());
+                return Err(anyhow!("connection reset"));
+            }
+            let contents = std::fs::read(path)?;
+            self.objects
+                .lock()
+                .unwrap()
+                .insert(key.to_string(), contents);
+            Ok(())
+        }
+
+        async fn get(&self, key: &str, dest: &Path) -> Result<()> {
+            let contents = self.objects.lock().unwrap().get(key).cloned();
+            let contents = contents.ok_or_else(|| anyhow!("no such object: {}", key))?;
+            std::fs::create_dir_all(dest.parent().unwrap())?;
+            std::fs::write(dest, contents)?;
+            Ok(())
+        }
+
+        async fn size(&self, key: &str) -> Result<Option<u64>> {
+            Ok(self
+                .objects
+                .lock()
+                .unwrap()
+                .get(key)
+                .map(|contents| contents.len() as u64))
+        }
+
+        async fn delete(&self, key: &str) -> Result<()> {
+            self.objects.lock().unwrap().remove(key);
+            Ok(())
+        }
+    }
+
+    fn archive_config() -> ArchiveConfig {
+        ArchiveConfig {
+            min_age_secs: 0,
+            retry_attempts: 3,
+            retry_delay_secs: 0,
+            ..ArchiveConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_upload_retries_until_the_store_holds_the_whole_file() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("archive-retry-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir)?;
+        let file = dir.join("segment.mp4");
+        std::fs::write(&file, b"recorded footage")?;
+
+        let store = Arc::new(MockObjectStore::default());
+        let pool = PgPool::connect_lazy("postgres://localhost/unused")?;
+        let archiver = RecordingArchiver::new(
+            archive_config(),
+            store.clone(),
+            RecordingsRepository::new(Arc::new(pool)),
+            &dir,
+        );
+
+        // Two dropped uploads, then a complete one
+        store.failing_puts.store(2, Ordering::SeqCst);
+        assert_eq!(archiver.upload("segment.mp4", &file).await?, 16);
+        assert_eq!(store.size("segment.mp4").await?, Some(16));
+
+        // Out of attempts
+        store.failing_puts.store(3, Ordering::SeqCst);
+        assert!(archiver.upload("segment.mp4", &file).await.is_err());
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    // A failed upload leaves the recording local and untouched; a confirmed one moves the
+    // row to the object and removes the local file, and playback fetches it back
+    #[tokio::test]
+    async fn test_recording_stays_local_until_its_upload_is_confirmed() -> Result<()> {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else {
+            println!("Skipping archive test. Set TEST_DATABASE_URL to run.");
+            return Ok(());
+        };
+
+        let pool = PgPool::connect(&database_url).await?;
+        let (camera_id, stream_id) = insert_camera_and_stream(&pool).await?;
+        let recording_id = Uuid::new_v4();
+        let recordings_dir = std::env::temp_dir().join(format!("archive-test-{}", camera_id));
+        let file = recordings_dir.join(camera_id.to_string()).join("clip.mp4");
+        std::fs::create_dir_all(file.parent().unwrap())?;
+        std::fs::write(&file, b"recorded footage")?;
+
+        let finalized = Utc::now() - Duration::minutes(10);
+        sqlx::query(
+            "INSERT INTO recordings (id, camera_id, stream_id, start_time, end_time, file_path, file_size, format, resolution, fps, created_at) VALUES ($1, $2, $3, $4, $4, $5, 16, 'mp4', '1280x720', 25, $4)",
+        )
+        .bind(recording_id)
+        .bind(camera_id)
+        .bind(stream_id)
+        .bind(finalized)
+        .bind(file.to_string_lossy().to_string())
+        .execute(&pool)
+        .await?;
+
+        let repo = RecordingsRepository::new(Arc::new(pool.clone()));
+        let store = Arc::new(MockObjectStore::default());
+        let archiver = RecordingArchiver::new(
+            archive_config(),
+            store.clone(),
+            repo.clone(),
+            &recordings_dir,
+        );
+
+        store.failing_puts.store(usize::MAX, Ordering::SeqCst);
+        archiver.archive_pending().await?;
+        let kept = repo.get_by_id(&recording_id).await?.unwrap();
+
+        store.failing_puts.store(0, Ordering::SeqCst);
+        archiver.archive_pending().await?;
+        let archived = repo.get_by_id(&recording_id).await?.unwrap();
+        let played = std::fs::read(archiver.local_path(&archived).await?)?;
+        let local_removed = !file.exists();
+
+        sqlx::query("DELETE FROM cameras WHERE id = $1")
+            .bind(camera_id)
+            .execute(&pool)
+            .await?;
+        std::fs::remove_dir_all(&recordings_dir)?;
+
+        assert_eq!(kept.file_path, file);
+        assert!(archived_key(&kept).is_none());
+
+        let key = format!("{}/clip.mp4", camera_id);
+        assert_eq!(archived_key(&archived), Some(key.as_str()));
+        assert_eq!(archived.file_path, PathBuf::from(format!("mock://{}", key)));
+        assert!(local_removed);
+        assert_eq!(played, b"recorded footage");
+        Ok(())
+    }
+}
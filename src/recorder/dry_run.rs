@@ -0,0 +1,407 @@
+use crate::recorder::record::{
+    build_audio_chain, build_video_chain, make_element, AudioEncodeSettings, EOS_FINALIZE_TIMEOUT,
+};
+use crate::recorder::transcode::TranscodeSettings;
+use crate::stream_manager::snapshot::detach;
+use anyhow::{anyhow, Result};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer_app as gst_app;
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// How long a test recording runs before it is torn down
+pub const TEST_RECORDING_DURATION: Duration = Duration::from_secs(5);
+
+/// Outcome of a test recording
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordingTestReport {
+    /// Whether media made it through the chains into a non-empty file without errors
+    pub success: bool,
+    pub video_codec: String,
+    /// `None` when the stream is recorded without audio
+    pub audio_codec: Option<String>,
+    /// Video buffers that reached the recording chain
+    pub video_buffers: u64,
+    /// Size of the throwaway file the test recorded
+    pub bytes_written: u64,
+    /// Why the recording failed: chain setup or pipeline errors
+    pub errors: Vec<String>,
+}
+
+/// The tees of a live stream pipeline a test recording taps
+pub struct TestSource {
+    pub pipeline: gst::Pipeline,
+    pub video_tee: gst::Element,
+    pub audio_tee: gst::Element,
+}
+
+/// The chains a test recording builds, as a real recording of the stream would
+pub struct TestChains {
+    pub video_codec: String,
+    /// Empty for a stream without audio
+    pub audio_codec: String,
+    pub transcode: Option<TranscodeSettings>,
+    pub audio_encode: AudioEncodeSettings,
+}
+
+/// Record a stream for `duration` into a throwaway MP4 and report whether that worked.
+///
+/// The recording chains run in a pipeline of their own, fed through appsink/appsrc pairs,
+/// so their errors never reach the live pipeline's bus, where they would look like a
+/// failing camera. This blocks, so call it from a blocking task.
+pub fn run_test_recording(
+    source: &TestSource,
+    chains: &TestChains,
+    duration: Duration,
+    dir: &Path,
+) -> RecordingTestReport {
+    let suffix = Uuid::new_v4().simple().to_string();
+    let path = dir.join(format!("recording_test_{}.mp4", suffix));
+    let video_buffers = Arc::new(AtomicU64::new(0));
+
+    let mut report = RecordingTestReport {
+        success: false,
+        video_codec: chains.video_codec.clone(),
+        audio_codec: None,
+        video_buffers: 0,
+        bytes_written: 0,
+        errors: Vec::new(),
+    };
+
+    match record(source, chains, duration, &path, &suffix, &video_buffers) {
+        Ok((errors, has_audio)) => {
+            report.errors = errors;
+            report.audio_codec = has_audio.then(|| chains.audio_codec.clone());
+        }
+        Err(e) => report.errors.push(e.to_string()),
+    }
+
+    report.video_buffers = video_buffers.load(Ordering::SeqCst);
+    report.bytes_written = std::fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+    let _ = std::fs::remove_file(&path);
+
+    if report.errors.is_empty() && report.video_buffers == 0 {
+        report.errors.push(format!(
+            "No video reached the recording chain within {:?}",
+            duration
+        ));
+    } else if report.errors.is_empty() && report.bytes_written == 0 {
+        report
+            .errors
+            .push("The recording file is empty".to_string());
+    }
+    report.success = report.errors.is_empty();
+    info!(
+        "Test recording {}: video {}, {} buffers, {} bytes, errors: {:?}",
+        if report.success { "passed" } else { "failed" },
+        report.video_codec,
+        report.video_buffers,
+        report.bytes_written,
+        report.errors
+    );
+    report
+}
+
+/// Build and run the test pipeline. Setup failures are returned as errors; errors the
+/// pipeline posts while running are collected. Also returns whether audio was recorded.
+fn record(
+    source: &TestSource,
+    chains: &TestChains,
+    duration: Duration,
+    path: &Path,
+    suffix: &str,
+    video_buffers: &Arc<AtomicU64>,
+) -> Result<(Vec<String>, bool)> {
+    let test_suffix = format!("test_{}", suffix);
+    let video_chain = build_video_chain(
+        &make_element,
+        &chains.video_codec,
+        chains.transcode.as_ref(),
+        &test_suffix,
+    )?;
+    let audio_chain = if chains.audio_codec.is_empty() {
+        None
+    } else {
+        build_audio_chain(
+            &make_element,
+            &chains.audio_codec,
+            &chains.audio_encode,
+            &test_suffix,
+        )?
+    };
+
+    let pipeline = gst::Pipeline::with_name(&format!("recording_test_{}", suffix));
+    let muxer = make_element("mp4mux", format!("recording_test_mux_{}", suffix))?;
+    let sink = gst::ElementFactory::make("filesink")
+        .name(format!("recording_test_sink_{}", suffix))
+        .property(
+            "location",
+            path.to_str()
+                .ok_or_else(|| anyhow!("Test recording path is not valid UTF-8"))?,
+        )
+        .property("sync", false)
+        .build()?;
+    pipeline.add_many([&muxer, &sink])?;
+    muxer.link(&sink)?;
+
+    let video_src = add_chain(&pipeline, &muxer, "video", suffix, &video_chain)?;
+    let audio_src = match &audio_chain {
+        Some(chain) => Some(add_chain(&pipeline, &muxer, "audio", suffix, chain)?),
+        None => None,
+    };
+    pipeline
+        .set_state(gst::State::Playing)
+        .map_err(|e| anyhow!("Failed to start the test pipeline: {:?}", e))?;
+
+    let mut taps = vec![tap(
+        source,
+        &source.video_tee,
+        "video",
+        suffix,
+        video_src.clone(),
+        Some(video_buffers.clone()),
+    )];
+    if let Some(audio_src) = &audio_src {
+        taps.push(tap(
+            source,
+            &source.audio_tee,
+            "audio",
+            suffix,
+            audio_src.clone(),
+            None,
+        ));
+    }
+
+    let mut errors = Vec::new();
+    let taps: Vec<_> = taps
+        .into_iter()
+        .filter_map(|tap| tap.map_err(|e| errors.push(e.to_string())).ok())
+        .collect();
+    if errors.is_empty() {
+        std::thread::sleep(duration);
+    }
+
+    // Tear down the taps first so the live pipeline is back to normal whatever happens
+    for (tee_pad, elements) in &taps {
+        detach(tee_pad);
+        for element in elements {
+            let _ = element.set_state(gst::State::Null);
+        }
+        let _ = source.pipeline.remove_many(elements);
+    }
+    debug!("Removed test recording taps {}", suffix);
+
+    // Let the muxer finish the file
+    for src in std::iter::once(&video_src).chain(audio_src.as_ref()) {
+        let _ = src.end_of_stream();
+    }
+    let bus = pipeline
+        .bus()
+        .ok_or_else(|| anyhow!("Test pipeline has no bus"))?;
+    let mut finished = false;
+    while let Some(msg) = bus.timed_pop_filtered(
+        gst::ClockTime::from_nseconds(EOS_FINALIZE_TIMEOUT.as_nanos() as u64),
+        &[gst::MessageType::Eos, gst::MessageType::Error],
+    ) {
+        match msg.view() {
+            gst::MessageView::Error(err) => errors.push(format!(
+                "{}: {}",
+                err.src()
+                    .map(|s| s.name().to_string())
+                    .unwrap_or_else(|| "unknown".to_string()),
+                err.error()
+            )),
+            _ => {
+                finished = true;
+                break;
+            }
+        }
+    }
+    if !finished && errors.is_empty() {
+        warn!(
+            "Test recording {} didn't finish within {:?}",
+            suffix, EOS_FINALIZE_TIMEOUT
+        );
+        errors.push("The recording didn't finish writing its file".to_string());
+    }
+    let _ = pipeline.set_state(gst::State::Null);
+
+    Ok((errors, audio_src.is_some()))
+}
+
+/// Add `appsrc ! chain` to the test pipeline and link it to the muxer
+fn add_chain(
+    pipeline: &gst::Pipeline,
+    muxer: &gst::Element,
+    kind: &str,
+    suffix: &str,
+    chain: &[gst::Element],
+) -> Result<gst_app::AppSrc> {
+    let appsrc = gst_app::AppSrc::builder()
+        .name(format!("recording_test_{}_src_{}", kind, suffix))
+        .format(gst::Format::Time)
+        .is_live(true)
+        .build();
+
+    let mut elements = vec![appsrc.clone().upcast::<gst::Element>()];
+    elements.extend(chain.iter().cloned());
+    pipeline.add_many(&elements)?;
+    gst::Element::link_many(&elements)?;
+    elements
+        .last()
+        .ok_or_else(|| anyhow!("Empty {} chain", kind))?
+        .link(muxer)
+        .map_err(|e| anyhow!("Failed to link the {} chain to the muxer: {:?}", kind, e))?;
+
+    Ok(appsrc)
+}
+
+/// Feed what flows through `tee` into `appsrc` through a `queue ! appsink` branch of the
+/// live pipeline. Returns the tee pad and the branch's elements for the teardown.
+fn tap(
+    source: &TestSource,
+    tee: &gst::Element,
+    kind: &str,
+    suffix: &str,
+    appsrc: gst_app::AppSrc,
+    counter: Option<Arc<AtomicU64>>,
+) -> Result<(gst::Pad, Vec<gst::Element>)> {
+    let queue = make_element("queue", format!("recording_test_{}_queue_{}", kind, suffix))?;
+    queue.set_property_from_str("leaky", "downstream");
+    let appsink = gst_app::AppSink::builder()
+        .name(format!("recording_test_{}_sink_{}", kind, suffix))
+        .sync(false)
+        .callbacks(
+            gst_app::AppSinkCallbacks::builder()
+                .new_sample(move |appsink| {
+                    // The live pipeline must never see an error from the test
+                    if let Ok(sample) = appsink.pull_sample() {
+                        if let Some(counter) = &counter {
+                            counter.fetch_add(1, Ordering::SeqCst);
+                        }
+                        let _ = appsrc.push_sample(&sample);
+                    }
+                    Ok(gst::FlowSuccess::Ok)
+                })
+                .build(),
+        )
+        .build();
+
+    let elements = vec![queue.clone(), appsink.upcast::<gst::Element>()];
+    source.pipeline.add_many(&elements)?;
+    let linked = link_tap(tee, &elements, kind);
+    match linked {
+        Ok(tee_pad) => Ok((tee_pad, elements)),
+        Err(e) => {
+            for element in &elements {
+                let _ = element.set_state(gst::State::Null);
+            }
+            let _ = source.pipeline.remove_many(&elements);
+            Err(e)
+        }
+    }
+}
+
+/// Start a tap's elements and link them to a new pad of `tee`
+fn link_tap(tee: &gst::Element, elements: &[gst::Element], kind: &str) -> Result<gst::Pad> {
+    gst::Element::link_many(elements)?;
+    for element in elements {
+        element.sync_state_with_parent()?;
+    }
+    let queue_sink = elements[0]
+        .static_pad("sink")
+        .ok_or_else(|| anyhow!("queue has no sink pad"))?;
+    let tee_pad = tee
+        .request_pad_simple("src_%u")
+        .ok_or_else(|| anyhow!("Failed to request a {} tee pad", kind))?;
+    if let Err(e) = tee_pad.link(&queue_sink) {
+        tee.release_request_pad(&tee_pad);
+        return Err(anyhow!("Failed to tap the {} tee: {:?}", kind, e));
+    }
+    Ok(tee_pad)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source_pipeline() -> TestSource {
+        let pipeline = gst::Pipeline::new();
+        let video_tee = make_element("tee", "video_tee".to_string()).unwrap();
+        let audio_tee = make_element("tee", "audio_tee".to_string()).unwrap();
+        pipeline.add_many([&video_tee, &audio_tee]).unwrap();
+        TestSource {
+            pipeline,
+            video_tee,
+            audio_tee,
+        }
+    }
+
+    fn chains(video_codec: &str) -> TestChains {
+        TestChains {
+            video_codec: video_codec.to_string(),
+            audio_codec: String::new(),
+            transcode: None,
+            audio_encode: AudioEncodeSettings::default(),
+        }
+    }
+
+    #[test]
+    fn unsupported_codec_reports_why() {
+        gst::init().unwrap();
+        let source = source_pipeline();
+
+        let report = run_test_recording(
+            &source,
+            &chains("mjpeg"),
+            Duration::from_millis(100),
+            &std::env::temp_dir(),
+        );
+        assert!(!report.success);
+        assert_eq!(report.video_buffers, 0);
+        assert_eq!(report.errors.len(), 1);
+        assert!(report.errors[0].contains("mjpeg"), "{:?}", report.errors);
+        // Nothing was left attached to the live pipeline
+        assert_eq!(source.pipeline.children().len(), 2);
+    }
+
+    // Test-record live H264 from a test source. Needs the x264, rtp and isomp4 plugins.
+    #[test]
+    fn live_h264_stream_passes() {
+        gst::init().unwrap();
+        let source = source_pipeline();
+        let (Ok(src), Ok(enc), Ok(pay)) = (
+            make_element("videotestsrc", "src".to_string()),
+            make_element("x264enc", "enc".to_string()),
+            make_element("rtph264pay", "pay".to_string()),
+        ) else {
+            println!("Skipping live_h264_stream_passes: plugins missing.");
+            return;
+        };
+        src.set_property("is-live", true);
+        enc.set_property_from_str("tune", "zerolatency");
+        enc.set_property("key-int-max", 15u32);
+        source.pipeline.add_many([&src, &enc, &pay]).unwrap();
+        gst::Element::link_many([&src, &enc, &pay, &source.video_tee]).unwrap();
+        source.pipeline.set_state(gst::State::Playing).unwrap();
+
+        let report = run_test_recording(
+            &source,
+            &chains("h264"),
+            Duration::from_secs(2),
+            &std::env::temp_dir(),
+        );
+        source.pipeline.set_state(gst::State::Null).unwrap();
+
+        assert!(report.success, "{:?}", report.errors);
+        assert!(report.video_buffers > 0);
+        assert!(report.bytes_written > 0);
+        assert_eq!(report.audio_codec, None);
+    }
+}
@@ -180,6 +180,9 @@ impl HlsPreparationService {
             event_types: None,
             schedule_id: None,
             min_duration: Some(1), // Exclude 0-duration recordings
+            min_file_size: None,
+            max_file_size: None,
+            has_audio: None,
             segment_id: None,
             parent_recording_id: None,
             is_segment: None, // Get all recordings regardless of segment status
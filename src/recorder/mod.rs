@@ -1,8 +1,16 @@
+pub mod archiver;
+pub mod dry_run;
+pub mod path_template;
+pub mod probe;
 pub mod record;
 pub mod scheduler;
+pub mod segment_writer;
 pub mod storage_cleanup;
+pub mod thumbnail;
+pub mod transcode;
 pub mod hls_preparer;
 
+pub use archiver::RecordingArchiver;
 pub use record::RecordingManager;
 pub use scheduler::RecordingScheduler;
 pub use storage_cleanup::StorageCleanupService;
@@ -0,0 +1,264 @@
+use crate::error::Error;
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// Longest path component `sanitize_path_component` returns, in bytes; filesystems cap
+/// names at 255
+const MAX_COMPONENT_LEN: usize = 128;
+
+/// Layout of recording directories under the storage path, as before templates existed
+pub const DEFAULT_PATH_TEMPLATE: &str = "{camera_id}/{stream_name}/{year}/{month}/{day}";
+
+/// Tokens a path template may use
+pub const PATH_TEMPLATE_TOKENS: &[&str] = &[
+    "camera_id",
+    "camera_name",
+    "stream_id",
+    "stream_name",
+    "year",
+    "month",
+    "day",
+    "date",
+];
+
+/// Values substituted into a path template
+#[derive(Debug, Clone)]
+pub struct PathTokens<'a> {
+    pub camera_id: Uuid,
+    /// Falls back to the camera id when unknown
+    pub camera_name: Option<&'a str>,
+    pub stream_id: Uuid,
+    pub stream_name: &'a str,
+    pub time: DateTime<Utc>,
+}
+
+impl PathTokens<'_> {
+    fn value(&self, token: &str) -> Option<String> {
+        Some(match token {
+            "camera_id" => self.camera_id.to_string(),
+            "camera_name" => self
+                .camera_name
+                .map(str::to_string)
+                .unwrap_or_else(|| self.camera_id.to_string()),
+            "stream_id" => self.stream_id.to_string(),
+            "stream_name" => self.stream_name.to_string(),
+            "year" => self.time.format("%Y").to_string(),
+            "month" => self.time.format("%m").to_string(),
+            "day" => self.time.format("%d").to_string(),
+            "date" => self.time.format("%Y-%m-%d").to_string(),
+            _ => return None,
+        })
+    }
+}
+
+/// Make a name safe to use as a single path component. Names come from cameras and users,
+/// so: separators, NUL and other control characters and characters Windows/SMB shares
+/// reject become `_`, surrounding whitespace and trailing dots go, names that are empty or
+/// only dots (`.`, `..`) become `_`, and long names are cut to `MAX_COMPONENT_LEN` bytes.
+pub fn sanitize_path_component(name: &str) -> String {
+    let sanitized: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let mut sanitized = sanitized.trim();
+    if !sanitized.chars().all(|c| c == '.') {
+        sanitized = sanitized.trim_end_matches(['.', ' ']);
+    }
+
+    if sanitized.chars().all(|c| c == '.') {
+        return "_".to_string();
+    }
+    let mut end = sanitized.len().min(MAX_COMPONENT_LEN);
+    while !sanitized.is_char_boundary(end) {
+        end -= 1;
+    }
+    sanitized[..end].to_string()
+}
+
+/// Check that `path`, with symlinks resolved, lies inside `base`. Both must exist. Returns
+/// the canonical path.
+pub fn ensure_under(base: &Path, path: &Path) -> Result<PathBuf> {
+    let base = base.canonicalize()?;
+    let canonical = path.canonicalize()?;
+    if !canonical.starts_with(&base) {
+        return Err(Error::Recording(format!(
+            "{} resolves to {}, outside of {}",
+            path.display(),
+            canonical.display(),
+            base.display()
+        ))
+        .into());
+    }
+    Ok(canonical)
+}
+
+/// Tokens of a template in order, or an error naming what's wrong with it
+fn parse_tokens(template: &str) -> Result<Vec<&str>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        let close = rest[open..]
+            .find('}')
+            .ok_or_else(|| format!("unclosed '{{' in \"{}\"", template))?;
+        let token = &rest[open + 1..open + close];
+        if !PATH_TEMPLATE_TOKENS.contains(&token) {
+            return Err(format!(
+                "unknown token {{{}}}, expected one of {}",
+                token,
+                PATH_TEMPLATE_TOKENS.join(", ")
+            ));
+        }
+        tokens.push(token);
+        rest = &rest[open + close + 1..];
+    }
+    Ok(tokens)
+}
+
+/// Check a template only uses known tokens and stays relative to the storage path
+pub fn check_template(template: &str) -> Result<(), String> {
+    parse_tokens(template)?;
+    if template.starts_with('/') || template.starts_with('\\') {
+        return Err("must be relative to the storage path".to_string());
+    }
+    if template.split(['/', '\\']).any(|part| part.trim() == "..") {
+        return Err("must not contain '..'".to_string());
+    }
+    Ok(())
+}
+
+/// Expand a template into a directory relative to the storage path. Every `/`-separated
+/// part of the template stays one directory: substituted names are sanitized, so a stream
+/// named `a/../b` can't add or climb directories. Empty parts are dropped.
+pub fn expand(template: &str, tokens: &PathTokens) -> PathBuf {
+    let mut path = PathBuf::new();
+    for part in template.split('/') {
+        if part.is_empty() {
+            continue;
+        }
+        let mut component = String::new();
+        let mut rest = part;
+        while let Some(open) = rest.find('{') {
+            let Some(close) = rest[open..].find('}') else {
+                break;
+            };
+            component.push_str(&rest[..open]);
+            let token = &rest[open + 1..open + close];
+            match tokens.value(token) {
+                Some(value) => component.push_str(&sanitize_path_component(&value)),
+                None => component.push_str(&rest[open..=open + close]),
+            }
+            rest = &rest[open + close + 1..];
+        }
+        component.push_str(rest);
+
+        path.push(sanitize_path_component(&component));
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::path::Component;
+
+    fn tokens(stream_name: &str) -> PathTokens<'_> {
+        PathTokens {
+            camera_id: Uuid::nil(),
+            camera_name: Some("Front door"),
+            stream_id: Uuid::nil(),
+            stream_name,
+            time: Utc.with_ymd_and_hms(2024, 3, 9, 23, 0, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn templates_expand_to_directories() {
+        let nil = Uuid::nil().to_string();
+        assert_eq!(
+            expand(DEFAULT_PATH_TEMPLATE, &tokens("main")),
+            Path::new(&nil).join("main/2024/03/09")
+        );
+        assert_eq!(
+            expand("{camera_name}/{date}", &tokens("main")),
+            Path::new("Front door/2024-03-09")
+        );
+        assert_eq!(expand("", &tokens("main")), PathBuf::new());
+
+        assert!(check_template("{camera_name}/{date}").is_ok());
+        assert!(check_template("{camera}/{date}").is_err());
+        assert!(check_template("/srv/{date}").is_err());
+        assert!(check_template("{date}/../x").is_err());
+    }
+
+    #[test]
+    fn stream_name_with_a_slash_stays_under_the_base() {
+        let base = Path::new("/recordings");
+        for name in [
+            "front/door",
+            "../../etc",
+            "..",
+            "/abs",
+            "a\\..\\b",
+            "nul\0byte",
+        ] {
+            let relative = expand(DEFAULT_PATH_TEMPLATE, &tokens(name));
+
+            assert_eq!(relative.components().count(), 5, "{:?}", relative);
+            assert!(relative
+                .components()
+                .all(|c| matches!(c, Component::Normal(_))));
+            assert!(base.join(&relative).starts_with(base));
+        }
+    }
+
+    #[test]
+    fn traversal_and_special_characters_are_sanitized() {
+        for (name, sanitized) in [
+            ("../../etc", ".._.._etc"),
+            ("..", "_"),
+            (".", "_"),
+            ("", "_"),
+            ("  ", "_"),
+            ("cam\0/../x", "cam__.._x"),
+            ("C:\\Windows", "C__Windows"),
+            ("a\nb\tc", "a_b_c"),
+            ("Front door ", "Front door"),
+            ("trailing...", "trailing"),
+            ("Café 1", "Café 1"),
+        ] {
+            assert_eq!(sanitize_path_component(name), sanitized, "{:?}", name);
+        }
+
+        let long = "é".repeat(100);
+        let cut = sanitize_path_component(&long);
+        assert!(cut.len() <= MAX_COMPONENT_LEN);
+        assert!(long.starts_with(&cut));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_out_of_the_base_are_refused() {
+        let root = std::env::temp_dir().join(format!("path-template-test-{}", Uuid::new_v4()));
+        let base = root.join("recordings");
+        let outside = root.join("outside");
+        std::fs::create_dir_all(base.join("camera")).unwrap();
+        std::fs::create_dir_all(&outside).unwrap();
+        std::os::unix::fs::symlink(&outside, base.join("escape")).unwrap();
+
+        assert!(ensure_under(&base, &base.join("camera")).is_ok());
+        let err = ensure_under(&base, &base.join("escape")).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Recording(_))
+        ));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,178 @@
+use anyhow::{anyhow, Result};
+use log::warn;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::process::Command;
+
+/// How long ffprobe may take on one file
+const PROBE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Set once ffprobe turned out to be missing, so the warning is only logged once
+static FFPROBE_MISSING: AtomicBool = AtomicBool::new(false);
+
+/// Media properties read from a finished file rather than assumed at recording start
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MediaInfo {
+    pub duration_secs: Option<f64>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub fps: Option<f64>,
+    pub video_codec: Option<String>,
+    pub audio_codec: Option<String>,
+    /// Overall bit rate in bits per second
+    pub bit_rate: Option<u64>,
+}
+
+impl MediaInfo {
+    /// "WIDTHxHEIGHT", the format of the recordings `resolution` column
+    pub fn resolution(&self) -> Option<String> {
+        Some(format!("{}x{}", self.width?, self.height?))
+    }
+
+    /// Merge the probed codecs and bit rate into a recording's metadata
+    pub fn merge_into(&self, metadata: &mut Value) {
+        let Value::Object(metadata) = metadata else {
+            return;
+        };
+
+        metadata.insert("probed".to_string(), json!(true));
+        if let Some(video_codec) = &self.video_codec {
+            metadata.insert("video_codec".to_string(), json!(video_codec));
+            // An empty audio codec marks a recording without audio
+            metadata.insert(
+                "audio_codec".to_string(),
+                json!(self.audio_codec.clone().unwrap_or_default()),
+            );
+        }
+        if let Some(bit_rate) = self.bit_rate {
+            metadata.insert("bit_rate".to_string(), json!(bit_rate));
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeOutput {
+    #[serde(default)]
+    streams: Vec<FfprobeStream>,
+    format: Option<FfprobeFormat>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeStream {
+    codec_type: Option<String>,
+    codec_name: Option<String>,
+    width: Option<u32>,
+    height: Option<u32>,
+    avg_frame_rate: Option<String>,
+    r_frame_rate: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FfprobeFormat {
+    duration: Option<String>,
+    bit_rate: Option<String>,
+}
+
+/// Frame rate from an ffprobe rational such as "30000/1001"; "0/0" means unknown
+fn parse_rate(rate: &str) -> Option<f64> {
+    let (num, den) = rate.split_once('/')?;
+    let (num, den): (f64, f64) = (num.parse().ok()?, den.parse().ok()?);
+    (num > 0.0 && den > 0.0).then_some(num / den)
+}
+
+/// Read the output of `ffprobe -show_format -show_streams -of json`
+pub fn parse_ffprobe(output: &str) -> Result<MediaInfo> {
+    let output: FfprobeOutput = serde_json::from_str(output)?;
+    let mut info = MediaInfo::default();
+
+    if let Some(video) = output
+        .streams
+        .iter()
+        .find(|s| s.codec_type.as_deref() == Some("video"))
+    {
+        info.video_codec = video.codec_name.clone();
+        info.width = video.width;
+        info.height = video.height;
+        info.fps = video
+            .avg_frame_rate
+            .as_deref()
+            .and_then(parse_rate)
+            .or_else(|| video.r_frame_rate.as_deref().and_then(parse_rate));
+    }
+    info.audio_codec = output
+        .streams
+        .iter()
+        .find(|s| s.codec_type.as_deref() == Some("audio"))
+        .and_then(|s| s.codec_name.clone());
+
+    if let Some(format) = output.format {
+        info.duration_secs = format.duration.and_then(|d| d.parse().ok());
+        info.bit_rate = format.bit_rate.and_then(|b| b.parse().ok());
+    }
+
+    Ok(info)
+}
+
+/// Run ffprobe on a file. Returns `None` when ffprobe isn't installed.
+pub async fn probe(path: &Path) -> Result<Option<MediaInfo>> {
+    if FFPROBE_MISSING.load(Ordering::Relaxed) {
+        return Ok(None);
+    }
+
+    let output = Command::new("ffprobe")
+        .args(["-v", "error", "-of", "json"])
+        .args(["-show_format", "-show_streams"])
+        .arg(path)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output();
+
+    let output = match tokio::time::timeout(PROBE_TIMEOUT, output).await {
+        Ok(Ok(output)) => output,
+        Ok(Err(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            if !FFPROBE_MISSING.swap(true, Ordering::Relaxed) {
+                warn!("ffprobe not found; recordings keep their estimated media info");
+            }
+            return Ok(None);
+        }
+        Ok(Err(e)) => return Err(anyhow!("Failed to run ffprobe: {}", e)),
+        Err(_) => return Err(anyhow!("ffprobe timed out on {}", path.display())),
+    };
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "ffprobe failed on {}: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    parse_ffprobe(&String::from_utf8_lossy(&output.stdout)).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ffprobe_output_fills_media_info() {
+        let info = parse_ffprobe(include_str!("testdata/ffprobe_segment.json")).unwrap();
+
+        assert_eq!(info.resolution().as_deref(), Some("1920x1080"));
+        assert_eq!(info.fps, Some(25.0));
+        assert_eq!(info.duration_secs, Some(30.016));
+        assert_eq!(info.video_codec.as_deref(), Some("h264"));
+        assert_eq!(info.audio_codec.as_deref(), Some("aac"));
+        assert_eq!(info.bit_rate, Some(4020975));
+
+        let mut metadata = json!({ "audio_codec": "", "finalized": true });
+        info.merge_into(&mut metadata);
+        assert_eq!(metadata["audio_codec"], "aac");
+        assert_eq!(metadata["finalized"], true);
+        assert_eq!(metadata["bit_rate"], 4020975);
+    }
+}
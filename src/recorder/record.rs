@@ -1,42 +1,698 @@
+use crate::db::models::analytics_event_models::AnalyticsEvent;
+use crate::db::models::event_settings_models::EventSettings;
 use crate::db::models::recording_models::{
     Recording, RecordingDb, RecordingEventType, RecordingUpdate,
 };
 use crate::db::models::recording_schedule_models::RecordingSchedule;
 use crate::db::models::stream_models::Stream;
+use crate::db::repositories::analytics_events::AnalyticsEventsRepository;
+use crate::db::repositories::camera_event_settings::EventSettingsRepository;
+use crate::db::repositories::cameras::CamerasRepository;
+use crate::db::repositories::recording_leases::RecordingLeasesRepository;
 use crate::db::repositories::recordings::RecordingsRepository;
+use crate::error::Error;
 use crate::messaging::broker::MessageBrokerTrait;
-use crate::stream_manager::StreamManager;
-use crate::utils::metadataparser::parse_onvif_event;
+use crate::recorder::dry_run::{self, RecordingTestReport, TestChains, TestSource};
+use crate::recorder::path_template::{self, PathTokens};
+use crate::recorder::probe::{self, MediaInfo};
+use crate::recorder::segment_writer::{SegmentWriter, SEGMENT_QUEUE_CAPACITY};
+use crate::recorder::thumbnail;
+use crate::recorder::transcode::{
+    build_h264_transcode_chain, transcode_depayloader, TranscodeSettings,
+};
+use crate::stream_manager::{authenticated_uri, IsolatedPipeline, StreamManager};
+use crate::utils::metadataparser::{parse_onvif_event, EventType, OnvifEvent};
+use crate::utils::redact::redact_url;
 use anyhow::{anyhow, Result};
-use chrono::{DateTime, Utc, Datelike};
+use chrono::{DateTime, Utc};
 // use cocoa::appkit::NSEventType::NSCursorUpdate;
 use gstreamer::{self as gst, ClockTime, PadProbeData, PadProbeReturn, PadProbeType};
 use gstreamer::glib;
 use gstreamer::prelude::*;
 use gstreamer_app::{AppSink, AppSinkCallbacks};
 use log::{debug, error, info, warn};
+use serde::Serialize;
 use serde_json::json;
 use sqlx::PgPool;
-use std::collections::HashMap;
-use std::fs::{File, OpenOptions};
-use std::io::{BufWriter, Write};
+use std::collections::{HashMap, HashSet};
+use std::fs::OpenOptions;
+use std::io::Write;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use tokio::time::{sleep, Duration};
 use uuid::Uuid;
 
+/// How long to wait for a tee to report negotiated caps before falling back to the DB codec
+const CAPS_PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long a recording request waits for its stream to be set up and PLAYING
+const STREAM_READY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Metadata key on a continuous recording describing the gap before it, when it resumed
+/// a recording of the same schedule that was interrupted
+pub const GAP_METADATA_KEY: &str = "gap_before";
+
+/// Map an `application/x-rtp` `encoding-name` to the codec names used by the recording chains
+pub fn codec_from_rtp_encoding(encoding_name: &str) -> String {
+    match encoding_name.to_uppercase().as_str() {
+        "H264" => "h264".to_string(),
+        "H265" => "h265".to_string(),
+        "JPEG" => "jpeg".to_string(),
+        "MP4V-ES" => "mpeg4".to_string(),
+        "VP8" => "vp8".to_string(),
+        "VP9" => "vp9".to_string(),
+        "AV1" => "av1".to_string(),
+        "MPEG4-GENERIC" => "aac".to_string(),
+        "PCMU" => "pcmu".to_string(),
+        "PCMA" => "pcma".to_string(),
+        other => other.to_lowercase(),
+    }
+}
+
+/// How well the recording chains handle a codec
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CodecSupport {
+    /// Recorded as is
+    Supported,
+    /// Recorded as is, but few players can play it back from MP4
+    Limited,
+    /// Transcoded before recording
+    Transcoded,
+    /// Not recorded: video makes the recording fail, audio is left out
+    Unsupported,
+}
+
+/// Support for a video codec, as handled by the video match in `start_recording_with_type`.
+/// `transcode` is whether `recording.transcode_fallback` is on.
+pub fn video_codec_support(codec: &str, transcode: bool) -> CodecSupport {
+    match codec {
+        "h264" | "h265" | "hevc" => CodecSupport::Supported,
+        "mpeg4" | "mp4v" => CodecSupport::Limited,
+        _ if transcode && transcode_depayloader(codec).is_some() => CodecSupport::Transcoded,
+        _ => CodecSupport::Unsupported,
+    }
+}
+
+/// Support for an audio codec, as handled by the audio match in `start_recording_with_type`
+pub fn audio_codec_support(codec: &str) -> CodecSupport {
+    match codec {
+        "aac" => CodecSupport::Supported,
+        "pcmu" | "g711u" | "pcma" | "g711a" => CodecSupport::Transcoded,
+        _ => CodecSupport::Unsupported,
+    }
+}
+
+/// Extract the codec from RTP caps, if the caps carry an `encoding-name`
+fn codec_from_caps(caps: &gst::CapsRef) -> Option<String> {
+    let s = caps.structure(0)?;
+    if s.name() != "application/x-rtp" {
+        return None;
+    }
+    s.get::<&str>("encoding-name")
+        .ok()
+        .map(codec_from_rtp_encoding)
+}
+
+/// Read the codec flowing through a tee by probing a temporary src pad for its caps event.
+/// Returns `None` if no caps were negotiated within `timeout`.
+async fn probe_tee_codec(tee: &gst::Element, timeout: Duration) -> Option<String> {
+    // Caps already negotiated upstream of the tee: no need to wait
+    if let Some(codec) = tee
+        .static_pad("sink")
+        .and_then(|pad| pad.current_caps())
+        .and_then(|caps| codec_from_caps(&caps))
+    {
+        return Some(codec);
+    }
+
+    let src_pad = tee.request_pad_simple("src_%u")?;
+    let (tx, rx) = tokio::sync::oneshot::channel::<String>();
+    let tx = std::sync::Mutex::new(Some(tx));
+
+    let probe_id = src_pad.add_probe(PadProbeType::EVENT_DOWNSTREAM, move |_pad, info| {
+        if let Some(PadProbeData::Event(event)) = &info.data {
+            if let gst::EventView::Caps(caps_event) = event.view() {
+                if let Some(codec) = codec_from_caps(caps_event.caps()) {
+                    if let Some(tx) = tx.lock().ok().and_then(|mut guard| guard.take()) {
+                        let _ = tx.send(codec);
+                    }
+                    return PadProbeReturn::Remove;
+                }
+            }
+        }
+        PadProbeReturn::Pass
+    });
+
+    let codec = tokio::time::timeout(timeout, rx).await.ok().and_then(|r| r.ok());
+
+    if codec.is_none() {
+        if let Some(probe_id) = probe_id {
+            src_pad.remove_probe(probe_id);
+        }
+    }
+    tee.release_request_pad(&src_pad);
+
+    codec
+}
+
+/// Detect a stream's video and audio codecs for recording. The negotiated caps on the tees
+/// win over the codecs stored at discovery time; an empty audio codec means no audio.
+async fn detect_codecs(
+    stream: &Stream,
+    video_tee: &gst::Element,
+    audio_tee: &gst::Element,
+) -> (String, String) {
+    // Probe the negotiated caps on the tees rather than trusting the codec stored
+    // at discovery time; cameras are often reconfigured without re-running discovery.
+    // The DB values are only used when nothing has been negotiated within the timeout.
+    let video_codec = match probe_tee_codec(video_tee, CAPS_PROBE_TIMEOUT).await {
+        Some(codec) => codec,
+        None => {
+            warn!(
+                "Timed out probing video caps for stream {}, falling back to stored codec {:?}",
+                stream.id, stream.codec
+            );
+            stream.codec.clone().unwrap_or_default().to_lowercase()
+        }
+    };
+    // A stream known to be video-only gets no audio chain: one hanging off a silent
+    // tee would hold back the muxer
+    let audio_codec = if stream.has_audio == Some(false) {
+        debug!("Stream {} has no audio track", stream.id);
+        String::new()
+    } else {
+        match probe_tee_codec(audio_tee, CAPS_PROBE_TIMEOUT).await {
+            Some(codec) => codec,
+            None => {
+                debug!(
+                    "No audio caps negotiated for stream {}, falling back to stored codec {:?}",
+                    stream.id, stream.audio_codec
+                );
+                stream
+                    .audio_codec
+                    .clone()
+                    .unwrap_or_default()
+                    .to_lowercase()
+            }
+        }
+    };
+
+    (video_codec, audio_codec)
+}
+
+/// How long to wait for EOS to travel through a recording branch when stopping it
+pub(crate) const EOS_FINALIZE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Unlink a recording branch from its tee once the pad is idle, then send EOS into the branch
+pub(crate) fn detach_branch_with_eos(tee_pad: &gst::Pad, chain: Option<&[gst::Element]>) {
+    let first_sink_pad = chain
+        .and_then(|chain| chain.first())
+        .and_then(|el| el.static_pad("sink"));
+
+    tee_pad.add_probe(PadProbeType::IDLE, move |pad, _info| {
+        if let Some(peer) = pad.peer() {
+            let _ = pad.unlink(&peer);
+        }
+        if let Some(tee) = pad.parent_element() {
+            tee.release_request_pad(pad);
+        }
+        if let Some(sink_pad) = &first_sink_pad {
+            sink_pad.send_event(gst::event::Eos::new());
+        }
+        PadProbeReturn::Remove
+    });
+}
+
+/// Build an element through the registry; used as the factory for the recording chains
+pub fn make_element(factory: &str, name: String) -> Result<gst::Element> {
+    gst::ElementFactory::make(factory)
+        .name(name)
+        .build()
+        .map_err(|e| anyhow!("Failed to create {}: {}", factory, e))
+}
+
+/// Build the splitmuxsink writing a recording's segments to `location`. Segments roll over
+/// after `segment_duration` seconds or once they reach `max_bytes` (0 for no limit),
+/// whichever comes first.
+fn build_splitmuxsink(
+    name: String,
+    muxer: &gst::Element,
+    location: String,
+    segment_duration: i64,
+    max_bytes: u64,
+) -> Result<gst::Element> {
+    Ok(gst::ElementFactory::make("splitmuxsink")
+        .name(name)
+        .property("muxer", muxer)
+        .property("location", location)
+        .property(
+            "max-size-time",
+            gst::ClockTime::from_seconds(segment_duration as u64),
+        )
+        .property("max-size-bytes", max_bytes)
+        .property("async-finalize", true) // Finalize segments in a separate thread
+        .property("max-files", 0u32) // No limit on number of files
+        .build()?)
+}
+
+/// Build `depay ! parse [! timestamper]` for H264/H265.
+///
+/// The timestamper plugins are missing from some GStreamer builds, so it is optional: when it
+/// can't be created the chain ends at the parser. Returns the elements in link order, the parser
+/// and the element that should be linked to the muxer.
+fn build_h26x_chain<F>(
+    make: &F,
+    depay_factory: &str,
+    parse_factory: &str,
+    timestamper_factory: &str,
+    codec: &str,
+    element_suffix: &str,
+) -> Result<(Vec<gst::Element>, gst::Element, gst::Element)>
+where
+    F: Fn(&str, String) -> Result<gst::Element>,
+{
+    let depay = make(
+        depay_factory,
+        format!("record_video_depay_{}_{}", codec, element_suffix),
+    )?;
+    let parse = make(
+        parse_factory,
+        format!("record_video_parse_{}_{}", codec, element_suffix),
+    )?;
+    add_missing_pts_probe(&parse);
+
+    let timestamper = match make(
+        timestamper_factory,
+        format!("record_video_timestamper_{}_{}", codec, element_suffix),
+    ) {
+        Ok(timestamper) => Some(timestamper),
+        Err(e) => {
+            warn!(
+                "{} not available ({}), recording {} without a timestamper",
+                timestamper_factory, e, codec
+            );
+            None
+        }
+    };
+
+    let output = timestamper.clone().unwrap_or_else(|| parse.clone());
+    let mut elements = vec![depay, parse.clone()];
+    elements.extend(timestamper);
+
+    Ok((elements, parse, output))
+}
+
+/// Build the chain between the recording video queue and the muxer. Returns the elements in
+/// link order; the last one is linked to the muxer.
+///
+/// H264/H265 and MPEG-4 are muxed as they arrive. Other codecs are transcoded to H264 when
+/// `transcode` is set, and fail with `Error::Recording` otherwise.
+pub(crate) fn build_video_chain<F>(
+    make: &F,
+    codec: &str,
+    transcode: Option<&TranscodeSettings>,
+    element_suffix: &str,
+) -> Result<Vec<gst::Element>>
+where
+    F: Fn(&str, String) -> Result<gst::Element>,
+{
+    // Keep video_codec_support in line with the codecs handled here
+    match codec {
+        "h264" => {
+            let (elements, _parse, _output) = build_h26x_chain(
+                make,
+                "rtph264depay",
+                "h264parse",
+                "h264timestamper",
+                "h264",
+                element_suffix,
+            )?;
+            Ok(elements)
+        }
+        "h265" | "hevc" => {
+            let (elements, parse, _output) = build_h26x_chain(
+                make,
+                "rtph265depay",
+                "h265parse",
+                "h265timestamper",
+                "h265",
+                element_suffix,
+            )?;
+            parse.set_property("config-interval", -1i32);
+            Ok(elements)
+        }
+        "mpeg4" | "mp4v" => {
+            // MPEG-4 Visual
+            let depay = make(
+                "rtpmp4vdepay",
+                format!("record_video_depay_mpeg4_{}", element_suffix),
+            )?;
+            let parse = make(
+                "mpeg4videoparse",
+                format!("record_video_parse_mpeg4_{}", element_suffix),
+            )?;
+            parse.set_property("config-interval", -1i32);
+            Ok(vec![depay, parse])
+        }
+        _ if transcode_depayloader(codec).is_none() => {
+            Err(Error::Recording(format!("Unsupported video codec: {}", codec)).into())
+        }
+        _ => match transcode {
+            Some(settings) => {
+                warn!(
+                    "Transcoding {} to H264 for recording; this costs a lot of CPU",
+                    codec
+                );
+                build_h264_transcode_chain(make, codec, settings, |role| {
+                    format!("record_video_{}_{}_{}", role, codec, element_suffix)
+                })
+            }
+            None => Err(Error::Recording(format!(
+                "{} video can't be recorded to MP4 without transcoding; \
+                 set recording.transcode_fallback to record it as H264",
+                codec
+            ))
+            .into()),
+        },
+    }
+}
+
+/// Encoders tried in order for decoded G.711 audio, each with the parser that follows it.
+/// Some GStreamer builds ship no AAC encoder for licensing reasons; MP3 and Opus go into MP4
+/// as well.
+const AUDIO_ENCODERS: [(&str, &str); 5] = [
+    ("avenc_aac", "aacparse"),
+    ("faac", "aacparse"),
+    ("voaacenc", "aacparse"),
+    ("lamemp3enc", "mpegaudioparse"),
+    ("opusenc", "opusparse"),
+];
+
+/// Output of the audio encoder when recorded audio is transcoded
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioEncodeSettings {
+    /// Bit rate in bit/s
+    pub bitrate: u32,
+    /// Sample rate in Hz the decoded audio is resampled to
+    pub sample_rate: u32,
+}
+
+impl Default for AudioEncodeSettings {
+    fn default() -> Self {
+        Self {
+            bitrate: 64000,
+            sample_rate: 16000,
+        }
+    }
+}
+
+/// Set an encoder's bit rate, whatever type and unit its property has
+fn set_encoder_bitrate(encoder: &gst::Element, factory: &str, bitrate: u32) {
+    // Mock stand-ins have no bitrate
+    if encoder.find_property("bitrate").is_none() {
+        return;
+    }
+    // lamemp3enc counts in kbit/s, the AAC and Opus encoders in bit/s
+    let value = if factory == "lamemp3enc" {
+        bitrate / 1000
+    } else {
+        bitrate
+    };
+    encoder.set_property_from_str("bitrate", &value.to_string());
+}
+
+/// Build the chain between the recording audio queue and the muxer. Returns the elements in
+/// link order, the last one linked to the muxer, or `None` when the recording goes without
+/// audio.
+pub(crate) fn build_audio_chain<F>(
+    make: &F,
+    codec: &str,
+    audio: &AudioEncodeSettings,
+    element_suffix: &str,
+) -> Result<Option<Vec<gst::Element>>>
+where
+    F: Fn(&str, String) -> Result<gst::Element>,
+{
+    // Keep audio_codec_support in line with the codecs handled here
+    match codec {
+        "aac" => {
+            // General RTP MPEG-4 generic depayloader
+            let depay = make(
+                "rtpmp4gdepay",
+                format!("record_audio_depay_aac_{}", element_suffix),
+            )?;
+            let parse = make(
+                "aacparse",
+                format!("record_audio_parse_aac_{}", element_suffix),
+            )?;
+            Ok(Some(vec![depay, parse]))
+        }
+        "pcmu" | "g711u" | "pcma" | "g711a" => {
+            let (depay_factory, decode_factory) = if codec == "pcmu" || codec == "g711u" {
+                ("rtppcmudepay", "mulawdec")
+            } else {
+                ("rtppcmadepay", "alawdec")
+            };
+            let depay = make(
+                depay_factory,
+                format!("record_audio_depay_{}_{}", codec, element_suffix),
+            )?;
+            let decode = make(
+                decode_factory,
+                format!("record_audio_decode_{}_{}", codec, element_suffix),
+            )?;
+            let audioconvert = make(
+                "audioconvert",
+                format!("record_audio_convert_{}", element_suffix),
+            )?;
+            let audioresample = make(
+                "audioresample",
+                format!("record_audio_resample_{}", element_suffix),
+            )?;
+            let capsfilter = make(
+                "capsfilter",
+                format!("record_audio_rate_{}", element_suffix),
+            )?;
+            if capsfilter.find_property("caps").is_some() {
+                let caps = gst::Caps::builder("audio/x-raw")
+                    .field("rate", audio.sample_rate as i32)
+                    .build();
+                capsfilter.set_property("caps", &caps);
+            }
+
+            let encoder = AUDIO_ENCODERS.iter().find_map(|(encoder, parser)| {
+                let encoded = make(encoder, format!("record_audio_enc_{}", element_suffix))
+                    .and_then(|enc| {
+                        let parse =
+                            make(parser, format!("record_audio_enc_parse_{}", element_suffix))?;
+                        Ok((enc, parse))
+                    });
+                match encoded {
+                    Ok((enc, parse)) => Some((*encoder, enc, parse)),
+                    Err(e) => {
+                        debug!("{} can't encode recorded audio: {}", encoder, e);
+                        None
+                    }
+                }
+            });
+            let Some((factory, encode, parse)) = encoder else {
+                warn!(
+                    "No audio encoder available (tried {}), recording {} streams without audio",
+                    AUDIO_ENCODERS.map(|(encoder, _)| encoder).join(", "),
+                    codec
+                );
+                return Ok(None);
+            };
+            if !factory.contains("aac") {
+                warn!(
+                    "No AAC encoder available, recording {} audio with {}",
+                    codec, factory
+                );
+            }
+
+            set_encoder_bitrate(&encode, factory, audio.bitrate);
+
+            Ok(Some(vec![
+                depay,
+                decode,
+                audioconvert,
+                audioresample,
+                capsfilter,
+                encode,
+                parse,
+            ]))
+        }
+        _ => {
+            warn!(
+                "Unsupported audio codec for recording: {}. No audio will be recorded.",
+                codec
+            );
+            Ok(None)
+        }
+    }
+}
+
+/// Factory names of a chain joined like a launch line, for logging
+fn describe_chain(elements: &[gst::Element]) -> String {
+    elements
+        .iter()
+        .map(|el| {
+            el.factory()
+                .map(|f| f.name().to_string())
+                .unwrap_or_default()
+        })
+        .collect::<Vec<_>>()
+        .join(" ! ")
+}
+
+/// Stamp buffers leaving the parser with the element running time when the camera sent none
+fn add_missing_pts_probe(parse: &gst::Element) {
+    let Some(src_pad) = parse.static_pad("src") else {
+        warn!("Parser {} has no src pad, skipping PTS probe", parse.name());
+        return;
+    };
+    let parse_weak = parse.downgrade();
+    src_pad.add_probe(PadProbeType::BUFFER, move |_pad, info| {
+        if let Some(PadProbeData::Buffer(buffer)) = &mut info.data {
+            let buffer_mut = buffer.make_mut();
+
+            if buffer_mut.pts().is_none() {
+                match parse_weak.upgrade().and_then(|p| p.current_running_time()) {
+                    Some(running_time) => {
+                        buffer_mut.set_pts(Some(running_time));
+                        buffer_mut.set_dts(Some(running_time));
+                        debug!("Set missing timestamp to element running time: {:?}", running_time);
+                    }
+                    None => warn!("Could not get element running time"),
+                }
+            } else {
+                debug!("Buffer already has timestamp: {:?}", buffer_mut.pts());
+            }
+        }
+        PadProbeReturn::Pass
+    });
+}
+
+/// Why a metadata buffer produced no analytics event
+#[derive(Debug)]
+enum MetadataError {
+    /// Not UTF-8, e.g. KLV; carries the buffer size
+    Binary(usize),
+    /// UTF-8 but not an ONVIF event we can parse
+    Parse(String),
+}
+
+/// Parse one metadata buffer from `rtponvifmetadatadepay` into the event and the row to store
+fn analytics_event_from_metadata(
+    camera_id: Uuid,
+    stream_id: Uuid,
+    data: &[u8],
+) -> std::result::Result<(OnvifEvent, AnalyticsEvent), MetadataError> {
+    let xml = std::str::from_utf8(data).map_err(|_| MetadataError::Binary(data.len()))?;
+    let mut event = parse_onvif_event(xml).map_err(MetadataError::Parse)?;
+    event.camera_id = Some(camera_id.to_string());
+    event.stream_id = Some(stream_id.to_string());
+
+    let analytics_event = AnalyticsEvent::from_onvif(camera_id, stream_id, &event, Some(xml));
+    Ok((event, analytics_event))
+}
+
+/// Recording type triggered by an ONVIF event, if any
+fn recording_event_type_for(event_type: &EventType) -> Option<RecordingEventType> {
+    match event_type {
+        EventType::MotionDetected => Some(RecordingEventType::Motion),
+        EventType::AudioDetected => Some(RecordingEventType::Audio),
+        EventType::LineDetected
+        | EventType::FieldDetected
+        | EventType::FaceDetected
+        | EventType::ObjectDetected => Some(RecordingEventType::Analytics),
+        EventType::TamperDetected | EventType::Other(_) => None,
+    }
+}
+
+/// Running time and wall-clock time a recording's segment timeline is anchored at
+#[derive(Debug, Clone, Copy)]
+struct SegmentClockAnchor {
+    running_time: ClockTime,
+    wall_time: DateTime<Utc>,
+}
+
+/// Wall-clock start of segment `fragment_id` from the running time of its first buffer.
+/// The first segment with a running time anchors the timeline; later ones start as far
+/// after it as their running time says, however long the segments in between were. Without
+/// a running time the start is estimated from the configured segment duration, which drifts
+/// as keyframe alignment makes segments longer or shorter.
+fn segment_start_time(
+    anchor: &mut Option<SegmentClockAnchor>,
+    recording_start: DateTime<Utc>,
+    running_time: Option<ClockTime>,
+    fragment_id: u32,
+    segment_duration: i64,
+) -> DateTime<Utc> {
+    let estimate =
+        recording_start + chrono::Duration::seconds(fragment_id as i64 * segment_duration);
+    let Some(running_time) = running_time else {
+        return estimate;
+    };
+
+    let anchor = anchor.get_or_insert(SegmentClockAnchor {
+        running_time,
+        wall_time: estimate,
+    });
+    let offset_ns = running_time.nseconds() as i64 - anchor.running_time.nseconds() as i64;
+    anchor.wall_time + chrono::Duration::nanoseconds(offset_ns)
+}
+
+/// Append a raw metadata buffer to the debug dump file
+fn append_metadata_dump(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().append(true).create(true).open(path)?;
+    file.write_all(data)
+}
+
 #[derive(Clone)]
 pub struct RecordingManager {
     stream_manager: Arc<StreamManager>,
     recordings_repo: RecordingsRepository,
+    analytics_repo: AnalyticsEventsRepository,
+    cameras_repo: CamerasRepository,
+    event_settings_repo: EventSettingsRepository,
     active_recordings: Arc<Mutex<std::collections::HashMap<String, ActiveRecordingElements>>>,
     recording_base_path: PathBuf,
+    // Directory of a recording under the base path, see `path_template`
+    path_template: String,
     segment_duration: i64,
+    // Size at which a segment rolls over before its duration is up, 0 for no limit
+    segment_max_bytes: u64,
     format: String,
     message_broker: Arc<Mutex<Option<Arc<crate::messaging::MessageBroker>>>>,
     // Track active events requiring recording to continue
     active_events: Arc<Mutex<HashMap<String, chrono::DateTime<Utc>>>>,
+    // Recordings started by events, by stream, stopped once the stream's events are over
+    event_recordings: Arc<Mutex<HashMap<Uuid, String>>>,
+    // How long an event recording outlives its event, see `event_completed`
+    event_debounce: Duration,
+    // Append raw ONVIF metadata to a per-stream file as well as the DB
+    metadata_dump: bool,
+    // Transcode video MP4 can't hold to H264 instead of refusing to record it
+    transcode_fallback: Option<TranscodeSettings>,
+    // Output of the G.711 transcode
+    audio_encode: AudioEncodeSettings,
+    // Leases on the streams this instance records, so instances sharing the database
+    // don't record the same stream twice
+    leases: RecordingLeasesRepository,
+    instance_id: Uuid,
+    lease_ttl: Duration,
+    // Keys of recordings being set up, so two concurrent starts of one can't both proceed,
+    // with the camera and stream they record
+    starting: Arc<Mutex<HashMap<String, (Uuid, Uuid)>>>,
+    // How many recordings may run at once
+    limits: RecordingLimits,
 }
 
 pub struct ActiveRecordingElements {
@@ -57,6 +713,56 @@ pub struct ActiveRecordingElements {
     pub event_type: RecordingEventType,
     pub file_path: PathBuf,
     pub pipeline_watch_id: Option<glib::SourceId>,
+    /// The pipeline of the recording's own when its camera is recorded in isolation,
+    /// stopped once the recording is dropped
+    pub isolated_pipeline: Option<IsolatedPipeline>,
+}
+
+/// How many recordings may run at once. Every recording is a branch of its own off the
+/// stream's tees, with its own muxer and files, so overlapping recordings of one camera
+/// multiply its CPU and disk use. 0 means no limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordingLimits {
+    pub per_camera: usize,
+    pub total: usize,
+}
+
+impl Default for RecordingLimits {
+    fn default() -> Self {
+        Self {
+            per_camera: 3,
+            total: 0,
+        }
+    }
+}
+
+impl RecordingLimits {
+    /// Fail with `Error::Recording` when another recording of `camera_id` doesn't fit next
+    /// to recordings of `cameras`, the cameras of those running or starting
+    pub fn check(&self, cameras: impl Iterator<Item = Uuid>, camera_id: &Uuid) -> Result<()> {
+        let (mut of_camera, mut total) = (0, 0);
+        for camera in cameras {
+            total += 1;
+            if camera == *camera_id {
+                of_camera += 1;
+            }
+        }
+        if self.per_camera > 0 && of_camera >= self.per_camera {
+            return Err(Error::Recording(format!(
+                "Camera {} already has {} recordings running, the limit per camera",
+                camera_id, of_camera
+            ))
+            .into());
+        }
+        if self.total > 0 && total >= self.total {
+            return Err(Error::Recording(format!(
+                "{} recordings are already running, the limit in total",
+                total
+            ))
+            .into());
+        }
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -95,16 +801,155 @@ impl RecordingManager {
     ) -> Self {
         Self {
             stream_manager,
-            recordings_repo: RecordingsRepository::new(db_pool),
+            recordings_repo: RecordingsRepository::new(db_pool.clone()),
+            analytics_repo: AnalyticsEventsRepository::new(db_pool.clone()),
+            leases: RecordingLeasesRepository::new(db_pool.clone()),
+            event_settings_repo: EventSettingsRepository::new(db_pool.clone()),
+            cameras_repo: CamerasRepository::new(db_pool),
             active_recordings: Arc::new(Mutex::new(HashMap::new())),
             recording_base_path: recording_base_path.to_owned(),
+            path_template: path_template::DEFAULT_PATH_TEMPLATE.to_string(),
             segment_duration,
+            segment_max_bytes: 0,
             format: format.to_owned(),
             message_broker: Arc::new(Mutex::new(None)),
             active_events: Arc::new(Mutex::new(HashMap::new())),
+            event_recordings: Arc::new(Mutex::new(HashMap::new())),
+            event_debounce: Duration::from_secs(5),
+            metadata_dump: false,
+            transcode_fallback: None,
+            audio_encode: AudioEncodeSettings::default(),
+            instance_id: Uuid::new_v4(),
+            lease_ttl: Duration::from_secs(30),
+            starting: Arc::new(Mutex::new(HashMap::new())),
+            limits: RecordingLimits::default(),
+        }
+    }
+
+    /// Also dump raw ONVIF metadata to `{stream_id}-metadata.xml` for debugging
+    pub fn with_metadata_dump(mut self, enabled: bool) -> Self {
+        self.metadata_dump = enabled;
+        self
+    }
+
+    /// Transcode video MP4 can't hold (MJPEG, VP8/VP9/AV1) to H264 with `settings` rather
+    /// than failing to record it
+    pub fn with_transcode_fallback(mut self, settings: Option<TranscodeSettings>) -> Self {
+        self.transcode_fallback = settings;
+        self
+    }
+
+    /// Lay recordings out under the base path by `template` instead of
+    /// `path_template::DEFAULT_PATH_TEMPLATE`
+    pub fn with_path_template(mut self, template: &str) -> Self {
+        self.path_template = template.to_owned();
+        self
+    }
+
+    /// Bit rate and sample rate G.711 audio is transcoded to
+    pub fn with_audio_encoding(mut self, audio: AudioEncodeSettings) -> Self {
+        self.audio_encode = audio;
+        self
+    }
+
+    /// How long a stream's recording lease lasts without renewal
+    pub fn with_lease_ttl(mut self, ttl: Duration) -> Self {
+        self.lease_ttl = ttl;
+        self
+    }
+
+    /// Also roll segments over once they reach `max_bytes`, 0 for no limit
+    pub fn with_segment_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.segment_max_bytes = max_bytes;
+        self
+    }
+
+    /// How long an event-triggered recording keeps going after its event ends
+    pub fn with_event_debounce(mut self, debounce: Duration) -> Self {
+        self.event_debounce = debounce;
+        self
+    }
+
+    /// How many recordings may run at once, per camera and in total
+    pub fn with_limits(mut self, limits: RecordingLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Renew this instance's recording leases every third of the lease TTL. Recordings of
+    /// a stream whose lease was lost, because renewals failed for longer than the TTL and
+    /// another instance took it over, are stopped.
+    pub fn start_lease_renewal(self: Arc<Self>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.lease_ttl / 3);
+            loop {
+                interval.tick().await;
+                let held = match self.leases.renew(&self.instance_id, self.lease_ttl).await {
+                    Ok(held) => held,
+                    Err(e) => {
+                        warn!("Failed to renew recording leases: {}", e);
+                        continue;
+                    }
+                };
+
+                let lost: Vec<String> = {
+                    let active_recordings = self.active_recordings.lock().await;
+                    active_recordings
+                        .iter()
+                        .filter(|(_, recording)| !held.contains(&recording.stream_id))
+                        .map(|(key, _)| key.clone())
+                        .collect()
+                };
+                for key in lost {
+                    warn!(
+                        "Lost the recording lease for {}, another instance records it now",
+                        key
+                    );
+                    if let Err(e) = self.stop_recording_by_key(&key).await {
+                        error!("Failed to stop recording {}: {}", key, e);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Give up the lease on a stream once this instance neither records nor is starting a
+    /// recording of it
+    async fn release_lease_if_idle(&self, stream_id: &Uuid) {
+        let recording = self
+            .active_recordings
+            .lock()
+            .await
+            .values()
+            .any(|recording| recording.stream_id == *stream_id);
+        let starting = self
+            .starting
+            .lock()
+            .await
+            .values()
+            .any(|(_, stream)| stream == stream_id);
+        if recording || starting {
+            return;
+        }
+
+        if let Err(e) = self.leases.release(stream_id, &self.instance_id).await {
+            warn!(
+                "Failed to release recording lease on stream {}: {}",
+                stream_id, e
+            );
         }
     }
 
+    /// Directory recordings are written under
+    pub fn recording_base_path(&self) -> &Path {
+        &self.recording_base_path
+    }
+
+    /// Whether video MP4 can't hold is transcoded to H264 rather than failing to record
+    pub fn transcodes_video(&self) -> bool {
+        self.transcode_fallback.is_some()
+    }
+
     /// Set message broker for event publishing
     pub async fn set_message_broker(
         &self,
@@ -140,17 +985,99 @@ impl RecordingManager {
         {
             let active_recordings = self.active_recordings.lock().await;
             if active_recordings.contains_key(&recording_key) {
-                return Err(anyhow!(
+                return Err(Error::AlreadyExists(format!(
                     "Already recording stream {} for schedule {}",
-                    stream.id,
-                    schedule.id
-                ));
+                    stream.id, schedule.id
+                ))
+                .into());
             }
         }
 
+        let previous = match self.last_continuous_recording(&schedule.id, &stream.id).await {
+            Ok(previous) => previous,
+            Err(e) => {
+                warn!(
+                    "Failed to look up the previous recording for schedule {}: {}",
+                    schedule.id, e
+                );
+                None
+            }
+        };
+        let resumed_at = Utc::now();
+
         // Generate unique recording ID and create initial recording entry
-        self.start_recording_with_type(stream, Some(schedule.id), RecordingEventType::Continuous)
-            .await
+        let recording_id = self
+            .start_recording_with_type(stream, Some(schedule.id), RecordingEventType::Continuous)
+            .await?;
+
+        // Mark the discontinuity when this picks up from a recording that was cut short
+        // (crash, restart, pipeline failure) so the timeline can show it
+        if let Some((previous_id, stopped_at)) = previous {
+            if schedule.continuous_recording && schedule.interrupted_between(stopped_at, resumed_at)
+            {
+                warn!(
+                    "Continuous recording for schedule {} resumed after a {}s gap",
+                    schedule.id,
+                    (resumed_at - stopped_at).num_seconds()
+                );
+                let gap = RecordingUpdate {
+                    file_path: None,
+                    duration: None,
+                    file_size: None,
+                    end_time: None,
+                    metadata: Some(json!({
+                        GAP_METADATA_KEY: {
+                            "start": stopped_at.to_rfc3339(),
+                            "end": resumed_at.to_rfc3339(),
+                            "previous_recording_id": previous_id,
+                        }
+                    })),
+                    segment_id: None,
+                    parent_recording_id: None,
+                    resolution: None,
+                    fps: None,
+                };
+                if let Err(e) = self.recordings_repo.update_with_data(&recording_id, gap).await {
+                    error!("Failed to record gap before recording {}: {}", recording_id, e);
+                }
+            }
+        }
+
+        Ok(recording_id)
+    }
+
+    /// The most recent continuous recording of a schedule and stream with the time it
+    /// stopped: its end time, or for one that was never finalized the end of its last
+    /// segment
+    async fn last_continuous_recording(
+        &self,
+        schedule_id: &Uuid,
+        stream_id: &Uuid,
+    ) -> Result<Option<(Uuid, DateTime<Utc>)>> {
+        let row = sqlx::query_as::<_, (Uuid, DateTime<Utc>)>(
+            r#"
+            SELECT p.id,
+                   COALESCE(
+                       p.end_time,
+                       (SELECT MAX(s.start_time + s.duration * INTERVAL '1 second')
+                        FROM recordings s
+                        WHERE s.parent_recording_id = p.id),
+                       p.start_time
+                   )
+            FROM recordings p
+            WHERE p.schedule_id = $1
+            AND p.stream_id = $2
+            AND p.parent_recording_id IS NULL
+            ORDER BY p.start_time DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(schedule_id)
+        .bind(stream_id)
+        .fetch_optional(&*self.recordings_repo.pool)
+        .await?;
+
+        Ok(row)
     }
 
     /// Start manual recording for a stream
@@ -163,6 +1090,43 @@ impl RecordingManager {
         .await
     }
 
+    /// Record `stream` for `duration` into a throwaway file, without touching its
+    /// recordings, and report whether the recording chain works for it
+    pub async fn test_recording(
+        &self,
+        stream: &Stream,
+        duration: Duration,
+    ) -> Result<RecordingTestReport> {
+        self.stream_manager
+            .wait_for_stream_ready(&stream.id.to_string(), STREAM_READY_TIMEOUT)
+            .await?;
+        let (pipeline, video_tee, audio_tee, _audio_source_element) = self
+            .stream_manager
+            .get_stream_access(&stream.id.to_string())?;
+        let (video_codec, audio_codec) = detect_codecs(stream, &video_tee, &audio_tee).await;
+        info!(
+            "Test recording stream {}. Detected video: [{}], Detected audio: [{}]",
+            stream.id, video_codec, audio_codec
+        );
+
+        let source = TestSource {
+            pipeline,
+            video_tee,
+            audio_tee,
+        };
+        let chains = TestChains {
+            video_codec,
+            audio_codec,
+            transcode: self.transcode_fallback.clone(),
+            audio_encode: self.audio_encode.clone(),
+        };
+        let report = tokio::task::spawn_blocking(move || {
+            dry_run::run_test_recording(&source, &chains, duration, &std::env::temp_dir())
+        })
+        .await?;
+        Ok(report)
+    }
+
     /// Start event-triggered recording for a stream
     pub async fn start_event_recording(
         &self,
@@ -174,10 +1138,34 @@ impl RecordingManager {
             return Err(anyhow!("Invalid event type for event recording"));
         }
 
+        // Overlapping events share one recording rather than each adding a branch
+        if let Some(recording_id) = self.running_event_recording(&stream.id).await {
+            info!(
+                "{} event on stream {} joins running event recording {}",
+                event_type, stream.id, recording_id
+            );
+            return Ok(recording_id);
+        }
+
         self.start_recording_with_type(stream, None, event_type)
             .await
     }
 
+    /// An event-triggered recording already running on `stream_id`
+    async fn running_event_recording(&self, stream_id: &Uuid) -> Option<Uuid> {
+        let active_recordings = self.active_recordings.lock().await;
+        active_recordings
+            .values()
+            .find(|r| {
+                &r.stream_id == stream_id
+                    && !matches!(
+                        r.event_type,
+                        RecordingEventType::Continuous | RecordingEventType::Manual
+                    )
+            })
+            .map(|r| r.recording_id)
+    }
+
  async fn start_recording_with_type(
         &self,
         stream: &Stream,
@@ -190,52 +1178,96 @@ impl RecordingManager {
             None => format!("{}-{}", event_type.to_string(), stream.id),
         };
 
-        // Check if already recording this combination
+        // Check if already recording, or starting, this combination, and that another
+        // recording branch fits within the limits
         {
             let active_recordings = self.active_recordings.lock().await;
-            if active_recordings.contains_key(&recording_key) {
-                return Err(anyhow!(
+            let mut starting = self.starting.lock().await;
+            if active_recordings.contains_key(&recording_key)
+                || starting.contains_key(&recording_key)
+            {
+                return Err(Error::AlreadyExists(format!(
                     "Already recording stream {} with key {}",
-                    stream.id,
-                    recording_key
-                ));
+                    stream.id, recording_key
+                ))
+                .into());
             }
+            let cameras = active_recordings
+                .values()
+                .map(|r| r.camera_id)
+                .chain(starting.values().map(|(camera_id, _)| *camera_id));
+            self.limits.check(cameras, &stream.camera_id)?;
+            starting.insert(recording_key.clone(), (stream.camera_id, stream.id));
         }
 
-        // Use the codec info from the stream struct.
-        // The original code's commented-out caps detection is more robust for live streams
-        // but for this refactor, we'll stick to the uncommented approach.
-        let detected_video_codec = stream.codec.clone().unwrap_or_default().to_lowercase();
-        let detected_audio_codec = stream
-            .audio_codec
-            .clone()
-            .unwrap_or_default()
-            .to_lowercase();
+        let result = self
+            .start_leased_recording(stream, schedule_id, event_type, &recording_key)
+            .await;
+        self.starting.lock().await.remove(&recording_key);
+        if result.is_err() {
+            self.release_lease_if_idle(&stream.id).await;
+        }
+        result
+    }
 
-        info!(
-            "Initiating recording for stream {}. Detected video: [{}], Detected audio: [{}]",
-            stream.id, detected_video_codec, detected_audio_codec
-        );
+    /// Take the stream's recording lease and build the recording branch. The lease comes
+    /// first, so a stream another instance records is refused before any pipeline work.
+    async fn start_leased_recording(
+        &self,
+        stream: &Stream,
+        schedule_id: Option<Uuid>,
+        event_type: RecordingEventType,
+        recording_key: &str,
+    ) -> Result<Uuid> {
+        if !self
+            .leases
+            .acquire(&stream.id, &self.instance_id, self.lease_ttl)
+            .await?
+        {
+            return Err(Error::AlreadyExists(format!(
+                "Stream {} is being recorded by another instance",
+                stream.id
+            ))
+            .into());
+        }
 
         let recording_id = Uuid::new_v4(); // This is the parent recording ID for all segments
         let now = Utc::now();
 
-        // self.log_metadata_stream(&stream.id.to_string()) ... (Keep if needed)
+        // The camera decides whether the recording taps its shared stream or gets a
+        // pipeline of its own, and may name the recording's directory
+        let camera = match self.cameras_repo.get_by_id(&stream.camera_id).await {
+            Ok(camera) => camera,
+            Err(e) => {
+                warn!(
+                    "Failed to look up camera {} for recording: {}",
+                    stream.camera_id, e
+                );
+                None
+            }
+        };
+        let isolated = camera.as_ref().is_some_and(|c| c.isolated_recording);
+
+        if !isolated {
+            // The stream may still be set up in the background, e.g. right after a camera
+            // connected; wait for it rather than failing straight away
+            self.stream_manager
+                .wait_for_stream_ready(&stream.id.to_string(), STREAM_READY_TIMEOUT)
+                .await?;
+        }
 
         // Create directory structure
-        let year = now.format("%Y").to_string();
-        let month = now.format("%m").to_string();
-        let day = now.format("%d").to_string();
-        let camera_id_str = stream.camera_id.to_string();
-        let stream_name_str = stream.name.clone();
-
+        let camera_name = camera.as_ref().map(|camera| camera.name.clone());
+        let tokens = PathTokens {
+            camera_id: stream.camera_id,
+            camera_name: camera_name.as_deref(),
+            stream_id: stream.id,
+            stream_name: &stream.name,
+            time: now,
+        };
         let mut dir_path = self
             .recording_base_path
-            .join(&camera_id_str)
-            .join(&stream_name_str)
-            .join(&year)
-            .join(&month)
-            .join(&day);
+            .join(path_template::expand(&self.path_template, &tokens));
 
         match std::fs::create_dir_all(&dir_path) {
             Ok(_) => {
@@ -257,35 +1289,70 @@ impl RecordingManager {
                 ));
             }
         };
+        // Names are sanitized, but a symlink below the base could still lead elsewhere
+        path_template::ensure_under(&self.recording_base_path, &dir_path)?;
         info!("Recording segments will be stored in: {:?}", dir_path);
 
-        // Get access to the MAIN PIPELINE and TEEs
-        let (pipeline, video_tee, audio_tee, _audio_source_element) = self
-            .stream_manager
-            .get_stream_access(&stream.id.to_string())
-            .map_err(|e| {
-                error!("Failed to get stream access for {}: {}", stream.id, e);
-                anyhow!("Failed to get stream access for {}: {}", stream.id, e)
-            })?;
-
-        // Ensure pipeline is playing (original logic kept)
-        if pipeline.current_state() != gst::State::Playing {
-            info!("Pipeline not in PLAYING state. Setting to PLAYING for dynamic element addition.");
-            pipeline
-                .set_state(gst::State::Playing)
-                .map_err(|e| anyhow!("Failed to set pipeline to PLAYING: {:?}", e))?;
-            let (state_res, _current, _pending) =
-                pipeline.state(gst::ClockTime::from_seconds(2));
-            state_res.map_err(|e| {
-                anyhow!(
-                    "Pipeline did not reach PLAYING state in time for element addition: {:?}",
-                    e
+        // Get access to the MAIN PIPELINE and TEEs, or a pipeline of the recording's own
+        let isolated_pipeline = match camera.as_ref().filter(|_| isolated) {
+            Some(camera) => {
+                let uri = match (&camera.username, &camera.password) {
+                    (Some(username), Some(password)) => {
+                        authenticated_uri(&stream.url, username, password)
+                    }
+                    _ => stream.url.clone(),
+                };
+                info!(
+                    "Recording stream {} from an isolated pipeline on {}",
+                    stream.id,
+                    redact_url(&uri)
+                );
+                Some(
+                    self.stream_manager
+                        .build_isolated_pipeline(&uri, &recording_id.simple().to_string())?,
                 )
-            })?;
-            info!("Pipeline set to PLAYING.");
-        } else {
-            info!("Pipeline already in PLAYING state.");
+            }
+            None => None,
+        };
+        // The shared stream's metadata branch doesn't see an isolated pipeline's metadata
+        if let Some(isolated) = &isolated_pipeline {
+            if let Err(e) = self.attach_metadata_logger(
+                &isolated.pipeline,
+                &isolated.metadata_tee,
+                &stream.camera_id,
+                &stream.id.to_string(),
+            ) {
+                warn!(
+                    "Metadata logging unavailable for isolated recording {}: {}",
+                    recording_id, e
+                );
+            }
         }
+        let (pipeline, video_tee, audio_tee) = match &isolated_pipeline {
+            Some(isolated) => (
+                isolated.pipeline.clone(),
+                isolated.video_tee.clone(),
+                isolated.audio_tee.clone(),
+            ),
+            None => {
+                let (pipeline, video_tee, audio_tee, _audio_source_element) = self
+                    .stream_manager
+                    .get_stream_access(&stream.id.to_string())
+                    .map_err(|e| {
+                        error!("Failed to get stream access for {}: {}", stream.id, e);
+                        e
+                    })?;
+                (pipeline, video_tee, audio_tee)
+            }
+        };
+
+        let (detected_video_codec, detected_audio_codec) =
+            detect_codecs(stream, &video_tee, &audio_tee).await;
+
+        info!(
+            "Initiating recording for stream {}. Detected video: [{}], Detected audio: [{}]",
+            stream.id, detected_video_codec, detected_audio_codec
+        );
 
         let element_suffix = recording_id.to_string().replace("-", "");
 
@@ -296,27 +1363,19 @@ impl RecordingManager {
             .name(format!("mp4mux_{}", element_suffix))
             .build()?;
 
-        let splitmuxsink = gst::ElementFactory::make("splitmuxsink")
-            .name(format!("splitmuxsink_{}", element_suffix))
-            .property("muxer", &muxer)
-            .property(
-                "location",
-                format!(
-                    "{}/segment_%Y%m%d_%H%M%S_%%05d.{}",
-                    dir_path
-                        .to_str()
-                        .ok_or_else(|| anyhow!("Dir path is not valid UTF-8"))?,
-                    self.format
-                ),
-            )
-            .property(
-                "max-size-time",
-                gst::ClockTime::from_seconds(self.segment_duration as u64),
-            )
-            .property("max-size-bytes", 0u64) // No size limit in bytes, only time
-            .property("async-finalize", true) // Finalize segments in a separate thread
-            .property("max-files", 0u32) // No limit on number of files
-            .build()?;
+        let splitmuxsink = build_splitmuxsink(
+            format!("splitmuxsink_{}", element_suffix),
+            &muxer,
+            format!(
+                "{}/segment_%Y%m%d_%H%M%S_%%05d.{}",
+                dir_path
+                    .to_str()
+                    .ok_or_else(|| anyhow!("Dir path is not valid UTF-8"))?,
+                self.format
+            ),
+            self.segment_duration,
+            self.segment_max_bytes,
+        )?;
 
         // Setup segment location signal handler (original logic kept)
         let recording_id_clone = recording_id;
@@ -328,25 +1387,9 @@ impl RecordingManager {
         let start_time_clone = now;
         let segment_duration_clone = self.segment_duration;
         let dir_path_clone_for_signal = dir_path.clone();
+        let segment_clock_anchor = std::sync::Mutex::new(None::<SegmentClockAnchor>);
 
-        let (tx_db, mut rx_db) = tokio::sync::mpsc::channel(100);
-        let tx_db_clone_for_signal = tx_db.clone();
-
-        tokio::spawn(async move {
-            while let Some((segment_rec, frag_id)) = rx_db.recv().await {
-                if let Err(e) = recordings_repo_clone.create(&segment_rec).await {
-                    error!(
-                        "Failed to create DB entry for segment {} (frag_id {}): {}",
-                        segment_rec.id, frag_id, e
-                    );
-                } else {
-                    debug!(
-                        "Successfully created DB entry for segment {} (frag_id {})",
-                        segment_rec.id, frag_id
-                    );
-                }
-            }
-        });
+        let segment_writer = SegmentWriter::spawn(recordings_repo_clone, SEGMENT_QUEUE_CAPACITY);
         
         splitmuxsink.connect("format-location-full", false, move |args| {
             if args.len() < 3 {
@@ -379,6 +1422,7 @@ impl RecordingManager {
             let mut mime = "unknown/unknown";
             let mut caps_string = "N/A".to_string();
             let mut pts_val: Option<u64> = None;
+            let mut running_time: Option<ClockTime> = None;
             // let mut dts_val: Option<u64> = None; // dts not used in segment_recording_entry
             // let mut duration_val: Option<u64> = None; // duration not used
 
@@ -397,23 +1441,31 @@ impl RecordingManager {
                 }
                 if let Some(buffer) = first_sample.buffer() {
                     pts_val = buffer.pts().map(|pts| pts.nseconds());
+                    // Running time follows the pipeline clock, unlike PTS which restarts
+                    // with every new segment event
+                    running_time = buffer.pts().and_then(|pts| {
+                        match first_sample
+                            .segment()
+                            .and_then(|seg| seg.downcast_ref::<ClockTime>())
+                        {
+                            Some(segment) => segment.to_running_time(pts),
+                            None => Some(pts),
+                        }
+                    });
                     // dts_val = buffer.dts().map(|dts| dts.nseconds());
                     // duration_val = buffer.duration().map(|dur| dur.nseconds());
                 }
             }
 
-            let segment_start_time = if let Some(pts_ns) = pts_val {
-                 // Assuming PTS from sample is relative to pipeline start for this segment.
-                 // Or, if it's an absolute timestamp from an RTCP sender report, conversion is complex.
-                 // For simplicity with splitmuxsink, calculate based on fragment_id if PTS is tricky.
-                 start_time_clone + chrono::Duration::seconds(fragment_id as i64 * segment_duration_clone as i64)
-                 // A more robust way if PTS is available and reliable from an NTP source:
-                 // chrono::DateTime::<Utc>::from_timestamp( (pts_ns / 1_000_000_000) as i64, (pts_ns % 1_000_000_000) as u32).unwrap_or(current_segment_timestamp_obj)
-            } else {
-                // Fallback: estimate start time based on fragment ID and configured segment duration
-                start_time_clone + chrono::Duration::seconds(fragment_id as i64 * segment_duration_clone as i64)
-            };
-
+            let segment_start_time = segment_start_time(
+                &mut segment_clock_anchor
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner()),
+                start_time_clone,
+                running_time,
+                fragment_id,
+                segment_duration_clone,
+            );
 
             let actual_fps = if fps_num > 0 && fps_den > 0 {
                 (fps_num as f64 / fps_den as f64).round() as u32
@@ -451,9 +1503,7 @@ impl RecordingManager {
                 parent_recording_id: Some(recording_id_clone),
             };
         
-            if let Err(e) = tx_db_clone_for_signal.try_send((segment_recording_entry.clone(), fragment_id)) {
-                error!("Failed to send segment info to DB task for frag {}: {}", fragment_id, e);
-            }
+            segment_writer.submit(segment_recording_entry, fragment_id);
         
             debug!("format-location-full: providing filename: {}", full_segment_path.display());
             Some(full_segment_path.to_str().unwrap_or("").to_value())
@@ -464,7 +1514,6 @@ impl RecordingManager {
         // VIDEO PROCESSING CHAIN SETUP
         //-----------------------------------------------------------------------------
         let mut video_elements_to_add: Vec<gst::Element> = Vec::new();
-        let mut final_video_processor_for_muxer: Option<gst::Element> = None;
 
         // Common first element for the recording video branch
         let video_queue_rec = gst::ElementFactory::make("queue")
@@ -472,145 +1521,23 @@ impl RecordingManager {
             .build()?;
         video_elements_to_add.push(video_queue_rec);
 
-        match detected_video_codec.as_str() {
-            "h264" => {
-                let depay = gst::ElementFactory::make("rtph264depay")
-                    .name(format!("record_video_depay_h264_{}", element_suffix))
-                    .build()?;
-                let parse = gst::ElementFactory::make("h264parse")
-                    .name(format!("record_video_parse_h264_{}", element_suffix))
-                    .build()?;
-
-                let timestamper = gst::ElementFactory::make("h264timestamper")
-                    .name(format!("record_video_timestamper_h264_{}", element_suffix))
-                    .build()?;
-
-let parse_clone = parse.clone();
-parse
-    .static_pad("src")
-    .unwrap()
-    .add_probe(PadProbeType::BUFFER, move |_pad, info| {
-        if let Some(PadProbeData::Buffer(buffer)) = &mut info.data {
-            // Get a mutable buffer to modify
-            let buffer_mut = buffer.make_mut();
-            
-            // Check if PTS is missing
-            if buffer_mut.pts().is_none() {
-                // Get the parse element's current running time
-                if let Some(running_time) = parse.current_running_time() {
-                    // Set both PTS and DTS
-                    buffer_mut.set_pts(Some(running_time));
-                    buffer_mut.set_dts(Some(running_time));
-                    
-                    debug!("Set missing timestamp to element running time: {:?}", running_time);
-                } else {
-                    warn!("Could not get element running time");
-                }
-            } else {
-                // Optional: log existing timestamp for debugging
-                debug!("Buffer already has timestamp: {:?}", buffer_mut.pts());
-            }
-        }
-        PadProbeReturn::Pass
-    });
-
-                video_elements_to_add.push(depay);
-                video_elements_to_add.push(parse_clone);
-                video_elements_to_add.push(timestamper.clone());
-                final_video_processor_for_muxer = Some(timestamper);
-                info!("Video chain (H264): ... ! queue ! rtph264depay ! h264parse ! h264timestamper ! muxer");
-            }
-            "h265" | "hevc" => {
-                let depay = gst::ElementFactory::make("rtph265depay")
-                    .name(format!("record_video_depay_h265_{}", element_suffix))
-                    .build()?;
-                let parse = gst::ElementFactory::make("h265parse")
-                    .name(format!("record_video_parse_h265_{}", element_suffix))
-                    .property("config-interval", -1i32)
-                    .build()?;
-                let timestamper = gst::ElementFactory::make("h265timestamper")
-                    .name(format!("record_video_timestamper_h265_{}", element_suffix))
-                    .build()?;
-let parse_clone = parse.clone();
-parse
-    .static_pad("src")
-    .unwrap()
-    .add_probe(PadProbeType::BUFFER, move |_pad, info| {
-        if let Some(PadProbeData::Buffer(buffer)) = &mut info.data {
-            // Get a mutable buffer to modify
-            let buffer_mut = buffer.make_mut();
-            
-            // Check if PTS is missing
-            if buffer_mut.pts().is_none() {
-                // Get the parse element's current running time
-                if let Some(running_time) = parse.current_running_time() {
-                    // Set both PTS and DTS
-                    buffer_mut.set_pts(Some(running_time));
-                    buffer_mut.set_dts(Some(running_time));
-                    
-                    debug!("Set missing timestamp to element running time: {:?}", running_time);
-                } else {
-                    warn!("Could not get element running time");
-                }
-            } else {
-                // Optional: log existing timestamp for debugging
-                debug!("Buffer already has timestamp: {:?}", buffer_mut.pts());
-            }
-        }
-        PadProbeReturn::Pass
-    });
-
-                video_elements_to_add.push(depay);
-                video_elements_to_add.push(parse_clone.clone());
-                video_elements_to_add.push(timestamper);
-                final_video_processor_for_muxer = Some(parse_clone);
-                info!("Video chain (H265/HEVC): ... ! queue ! rtph265depay ! h265parse ! muxer");
-            }
-            "jpeg" | "mjpeg" => {
-                // Note: Muxing JPEG into standard MP4 is uncommon.
-                // This setup assumes the muxer can handle it or it's intended for a different container/purpose.
-                // For ONVIF MP4, you'd typically have H.264/H.265.
-                let depay = gst::ElementFactory::make("rtpjpegdepay")
-                    .name(format!("record_video_depay_jpeg_{}", element_suffix))
-                    .build()?;
-                // Jpegparse might not be strictly necessary if depayloader outputs raw JPEG images
-                // and the muxer expects that. However, it's often good for stream validation.
-                let parse = gst::ElementFactory::make("jpegparse")
-                    .name(format!("record_video_parse_jpeg_{}", element_suffix))
-                    .build()?;
-
-// Keep track of the last timestamp and frame counter
-                video_elements_to_add.push(depay);
-                video_elements_to_add.push(parse.clone());
-                final_video_processor_for_muxer = Some(parse);
-                info!("Video chain (JPEG/MJPEG): ... ! queue ! rtpjpegdepay ! jpegparse ! muxer");
-            }
-            "mpeg4" | "mp4v" => { // MPEG-4 Visual
-                let depay = gst::ElementFactory::make("rtpmp4vdepay")
-                    .name(format!("record_video_depay_mpeg4_{}", element_suffix))
-                    .build()?;
-                let parse = gst::ElementFactory::make("mpeg4videoparse")
-                    .name(format!("record_video_parse_mpeg4_{}", element_suffix))
-                    .property("config-interval", -1i32) // May be relevant
-                    .build()?;
-                video_elements_to_add.push(depay);
-                video_elements_to_add.push(parse.clone());
-                final_video_processor_for_muxer = Some(parse);
-                info!("Video chain (MPEG-4 Visual): ... ! queue ! rtpmp4vdepay ! mpeg4videoparse ! muxer");
-
-            }
-            _ => {
-                error!(
-                    "Unsupported video codec for recording: {}. Aborting.",
-                    detected_video_codec
-                );
-                // Consider cleaning up any elements added to pipeline before this error if any.
-                return Err(anyhow!(
-                    "Unsupported video codec: {}",
-                    detected_video_codec
-                ));
-            }
-        }
+        let video_chain = build_video_chain(
+            &make_element,
+            &detected_video_codec,
+            self.transcode_fallback.as_ref(),
+            &element_suffix,
+        )
+        .map_err(|e| {
+            error!("Can't record video of stream {}: {}", stream.id, e);
+            e
+        })?;
+        info!(
+            "Video chain ({}): ... ! queue ! {} ! muxer",
+            detected_video_codec,
+            describe_chain(&video_chain)
+        );
+        let final_video_processor_for_muxer = video_chain.last().cloned();
+        video_elements_to_add.extend(video_chain);
 
         //-----------------------------------------------------------------------------
         // AUDIO PROCESSING CHAIN SETUP (original logic kept, with G.711 to AAC transcoding)
@@ -623,67 +1550,25 @@ parse
                 "Setting up audio chain for determined codec: {}",
                 detected_audio_codec
             );
-            let current_audio_queue = gst::ElementFactory::make("queue")
-                .name(format!("record_audio_queue_{}", element_suffix))
-                .build()?;
-            audio_elements_to_add.push(current_audio_queue.clone());
-
-            match detected_audio_codec.as_str() {
-                "aac" => {
-                    let depay = gst::ElementFactory::make("rtpmp4gdepay") // General RTP MPEG-4 generic depayloader
-                        .name(format!("record_audio_depay_aac_{}", element_suffix))
-                        .build()?;
-                    let parse = gst::ElementFactory::make("aacparse")
-                        .name(format!("record_audio_parse_aac_{}", element_suffix))
-                        .build()?;
-                    audio_elements_to_add.push(depay);
-                    audio_elements_to_add.push(parse.clone());
-                    final_audio_processor_for_muxer = Some(parse);
-                    info!("Audio chain (AAC passthrough): ... ! queue ! rtpmp4gdepay ! aacparse ! muxer");
-                }
-                "pcmu" | "g711u" | "pcma" | "g711a" => {
-                    let (depay_name, decode_name) = if detected_audio_codec == "pcmu" || detected_audio_codec == "g711u" {
-                        ("rtppcmudepay", "mulawdec")
-                    } else {
-                        ("rtppcmadepay", "alawdec")
-                    };
-
-                    let depay = gst::ElementFactory::make(depay_name)
-                        .name(format!("record_audio_depay_{}_{}", detected_audio_codec, element_suffix))
-                        .build()?;
-                    let decode = gst::ElementFactory::make(decode_name)
-                        .name(format!("record_audio_decode_{}_{}", detected_audio_codec, element_suffix))
-                        .build()?;
-                    let audioconvert = gst::ElementFactory::make("audioconvert")
-                        .name(format!("record_audio_convert_{}", element_suffix))
-                        .build()?;
-                    let audio_encoder_aac = gst::ElementFactory::make("avenc_aac") // faac or voaacenc also possible
-                        .name(format!("record_audio_enc_aac_{}", element_suffix))
-                        // .property("bitrate", 64000_i32) // Example bitrate, adjust as needed
-                        .build()?;
-                    let aacparse_transcoded = gst::ElementFactory::make("aacparse") // Parse the newly encoded AAC
-                        .name(format!("record_audio_transcoded_parse_aac_{}",element_suffix))
-                        .build()?;
-
-                    audio_elements_to_add.push(depay);
-                    audio_elements_to_add.push(decode);
-                    audio_elements_to_add.push(audioconvert);
-                    audio_elements_to_add.push(audio_encoder_aac);
-                    audio_elements_to_add.push(aacparse_transcoded.clone());
-                    final_audio_processor_for_muxer = Some(aacparse_transcoded);
-                    info!(
-                        "Audio chain ({} to AAC): ... ! queue ! {} ! {} ! audioconvert ! avenc_aac ! aacparse ! muxer",
-                        detected_audio_codec, depay_name, decode_name
-                    );
-                }
-                _ => {
-                    warn!(
-                        "Unsupported audio codec for recording: {}. No audio will be recorded.",
-                        detected_audio_codec
-                    );
-                    audio_elements_to_add.clear(); // Remove queue if codec is unsupported
-                    final_audio_processor_for_muxer = None;
-                }
+            if let Some(audio_chain) =
+                build_audio_chain(
+                    &make_element,
+                    &detected_audio_codec,
+                    &self.audio_encode,
+                    &element_suffix,
+                )?
+            {
+                let current_audio_queue = gst::ElementFactory::make("queue")
+                    .name(format!("record_audio_queue_{}", element_suffix))
+                    .build()?;
+                info!(
+                    "Audio chain ({}): ... ! queue ! {} ! muxer",
+                    detected_audio_codec,
+                    describe_chain(&audio_chain)
+                );
+                audio_elements_to_add.push(current_audio_queue);
+                final_audio_processor_for_muxer = audio_chain.last().cloned();
+                audio_elements_to_add.extend(audio_chain);
             }
         } else {
             info!("No audio codec detected or specified. Recording video only.");
@@ -973,11 +1858,44 @@ parse
             event_type,
             file_path: dir_path.clone(),
             pipeline_watch_id: None, // Placeholder for bus watch ID
+            isolated_pipeline,
+        };
+
+        // Parent row that the segments hang off; finalized with end time and size on stop
+        let parent_recording = Recording {
+            id: recording_id,
+            camera_id: stream.camera_id,
+            stream_id: stream.id,
+            start_time: now,
+            end_time: None,
+            file_path: dir_path.clone(),
+            file_size: 0,
+            duration: 0,
+            format: self.format.clone(),
+            resolution: stream.resolution.clone().unwrap_or_else(|| "unknown".to_string()),
+            fps: stream.framerate.unwrap_or(0) as u32,
+            event_type,
+            metadata: Some(json!({
+                "status": "recording",
+                "finalized": false,
+                "recording_type": "segmented",
+                "video_codec": detected_video_codec,
+                "audio_codec": detected_audio_codec,
+            })),
+            schedule_id,
+            segment_id: None,
+            parent_recording_id: None,
         };
+        if let Err(e) = self.recordings_repo.create(&parent_recording).await {
+            error!(
+                "Failed to create parent recording entry {}: {}",
+                recording_id, e
+            );
+        }
 
         {
             let mut active_recordings_map = self.active_recordings.lock().await;
-            active_recordings_map.insert(recording_key.clone(), active_elements_struct);
+            active_recordings_map.insert(recording_key.to_string(), active_elements_struct);
         }
 
         info!(
@@ -992,6 +1910,27 @@ parse
             }
         );
 
+        // Publish recording started event, paired with the stopped one on stop
+        if let Some(broker) = self.message_broker.lock().await.as_ref() {
+            if let Err(e) = broker
+                .publish(
+                    crate::messaging::EventType::RecordingStarted,
+                    Some(stream.camera_id),
+                    serde_json::json!({
+                        "camera_id": stream.camera_id.to_string(),
+                        "recording_id": recording_id.to_string(),
+                        "stream_id": stream.id.to_string(),
+                        "event_type": event_type.to_string(),
+                        "start_time": now.to_rfc3339(),
+                        "schedule_id": schedule_id.map(|id| id.to_string())
+                    }),
+                )
+                .await
+            {
+                warn!("Failed to publish recording started event: {}", e);
+            }
+        }
+
         Ok(recording_id)
     }
     /// Stop recording a specific schedule
@@ -1037,10 +1976,11 @@ parse
             let mut active_recordings = self.active_recordings.lock().await;
 
             if !active_recordings.contains_key(recording_key) {
-                return Err(anyhow!(
+                return Err(Error::NotFound(format!(
                     "No active recording found for key {}",
                     recording_key
-                ));
+                ))
+                .into());
             }
 
             active_recordings
@@ -1053,138 +1993,74 @@ parse
             drop(watch_id);
         }
 
-        // Find the splitmuxsink element we added for this recording
         let pipeline = &active_recording.pipeline;
-        let element_suffix = active_recording.recording_id.to_string().replace("-", "");
-        let splitmuxsink_name = format!("record_splitmuxsink_{}", element_suffix);
-
-        if let Some(splitmuxsink) = pipeline.by_name(&splitmuxsink_name) {
-            // Send EOS just to the splitmuxsink element to finalize it properly
-            let _ = splitmuxsink.send_event(gst::event::Eos::new());
 
-            // Set only the splitmuxsink to NULL state
-            let _ = splitmuxsink.set_state(gst::State::Null);
+        // Watch for EOS reaching splitmuxsink so we know the last segment is being finalized
+        let (eos_tx, eos_rx) = tokio::sync::oneshot::channel::<()>();
+        let eos_tx = std::sync::Mutex::new(Some(eos_tx));
+        active_recording.splitmuxsink_video_pad.add_probe(
+            PadProbeType::EVENT_DOWNSTREAM,
+            move |_pad, info| {
+                if let Some(PadProbeData::Event(event)) = &info.data {
+                    if event.type_() == gst::EventType::Eos {
+                        if let Some(tx) = eos_tx.lock().ok().and_then(|mut guard| guard.take()) {
+                            let _ = tx.send(());
+                        }
+                        return PadProbeReturn::Remove;
+                    }
+                }
+                PadProbeReturn::Pass
+            },
+        );
 
-            // info!(
-            //     "Set splitmuxsink to Null state for recording {}",
-            //     active_recording.recording_id
-            // );
-        } else {
-            warn!(
-                "Could not find splitmuxsink element for recording {}",
-                active_recording.recording_id
+        // Detach the recording branches from the tees and push EOS through them so
+        // splitmuxsink writes the moov atom of the last segment
+        detach_branch_with_eos(
+            &active_recording.video_tee_pad,
+            active_recording.video_elements_chain.as_deref(),
+        );
+        if let Some(audio_tee_pad) = &active_recording.audio_tee_pad {
+            detach_branch_with_eos(
+                audio_tee_pad,
+                active_recording.audio_elements_chain.as_deref(),
             );
         }
 
-        // Find and set video elements to NULL
-        let queue_name = format!("record_queue_{}", element_suffix);
-        if let Some(queue) = pipeline.by_name(&queue_name) {
-            let _ = queue.set_state(gst::State::Null);
-        }
-
-        let depay_name = format!("record_depay_{}", element_suffix);
-        if let Some(depay) = pipeline.by_name(&depay_name) {
-            let _ = depay.set_state(gst::State::Null);
-        }
-
-        let parse_name = format!("record_parse_{}", element_suffix);
-        if let Some(parse) = pipeline.by_name(&parse_name) {
-            let _ = parse.set_state(gst::State::Null);
-        }
-
-        // Find and set audio elements to NULL
-        let audio_queue_name = format!("record_audio_queue_{}", element_suffix);
-        if let Some(audio_queue) = pipeline.by_name(&audio_queue_name) {
-            let _ = audio_queue.set_state(gst::State::Null);
-        }
-
-        let audio_depay_name = format!("record_audio_depay_{}", element_suffix);
-        if let Some(audio_depay) = pipeline.by_name(&audio_depay_name) {
-            let _ = audio_depay.set_state(gst::State::Null);
-        }
-
-        // Audio parse no longer used
-
-        let audio_decoder_name = format!("record_audio_decoder_{}", element_suffix);
-        if let Some(audio_decoder) = pipeline.by_name(&audio_decoder_name) {
-            let _ = audio_decoder.set_state(gst::State::Null);
-        }
-
-        let audio_convert_name = format!("record_audio_convert_{}", element_suffix);
-        if let Some(audio_convert) = pipeline.by_name(&audio_convert_name) {
-            let _ = audio_convert.set_state(gst::State::Null);
-        }
-
-        let audio_resample_name = format!("record_audio_resample_{}", element_suffix);
-        if let Some(audio_resample) = pipeline.by_name(&audio_resample_name) {
-            let _ = audio_resample.set_state(gst::State::Null);
-        }
-
-        let audio_encoder_name = format!("record_audio_encoder_{}", element_suffix);
-        if let Some(audio_encoder) = pipeline.by_name(&audio_encoder_name) {
-            let _ = audio_encoder.set_state(gst::State::Null);
-        }
-
-        let audio_format_parse_name = format!("record_audio_format_parse_{}", element_suffix);
-        if let Some(audio_format_parse) = pipeline.by_name(&audio_format_parse_name) {
-            let _ = audio_format_parse.set_state(gst::State::Null);
+        match tokio::time::timeout(EOS_FINALIZE_TIMEOUT, eos_rx).await {
+            Ok(_) => debug!(
+                "EOS reached splitmuxsink for recording {}",
+                active_recording.recording_id
+            ),
+            Err(_) => warn!(
+                "Timed out waiting for EOS on recording {}, last segment may be incomplete",
+                active_recording.recording_id
+            ),
         }
 
         // Wait for file to be fully written
         sleep(Duration::from_secs(1)).await;
 
-        // Now remove all video elements from the pipeline
-        if let Some(queue) = pipeline.by_name(&queue_name) {
-            pipeline.remove(&queue).ok();
-        }
-
-        if let Some(depay) = pipeline.by_name(&depay_name) {
-            pipeline.remove(&depay).ok();
-        }
-
-        if let Some(parse) = pipeline.by_name(&parse_name) {
-            pipeline.remove(&parse).ok();
-        }
-
-        // Remove all audio elements from the pipeline
-        if let Some(audio_queue) = pipeline.by_name(&audio_queue_name) {
-            pipeline.remove(&audio_queue).ok();
-        }
-
-        if let Some(audio_depay) = pipeline.by_name(&audio_depay_name) {
-            pipeline.remove(&audio_depay).ok();
-        }
-
-        // Audio parse no longer used
-
-        if let Some(audio_decoder) = pipeline.by_name(&audio_decoder_name) {
-            pipeline.remove(&audio_decoder).ok();
-        }
-
-        if let Some(audio_convert) = pipeline.by_name(&audio_convert_name) {
-            pipeline.remove(&audio_convert).ok();
-        }
-
-        if let Some(audio_resample) = pipeline.by_name(&audio_resample_name) {
-            pipeline.remove(&audio_resample).ok();
-        }
+        // Now tear down the recording elements
+        let mut elements_to_remove: Vec<gst::Element> = Vec::new();
+        elements_to_remove.extend(active_recording.video_elements_chain.iter().flatten().cloned());
+        elements_to_remove.extend(active_recording.audio_elements_chain.iter().flatten().cloned());
+        elements_to_remove.push(active_recording.splitmuxsink.clone());
 
-        if let Some(audio_encoder) = pipeline.by_name(&audio_encoder_name) {
-            pipeline.remove(&audio_encoder).ok();
+        for el in &elements_to_remove {
+            let _ = el.set_state(gst::State::Null);
         }
-
-        if let Some(audio_format_parse) = pipeline.by_name(&audio_format_parse_name) {
-            pipeline.remove(&audio_format_parse).ok();
+        for el in &elements_to_remove {
+            if el.parent().as_ref() == Some(pipeline.upcast_ref::<gst::Object>()) {
+                pipeline.remove(el).ok();
+            }
         }
-
-        if let Some(splitmuxsink) = pipeline.by_name(&splitmuxsink_name) {
-            pipeline.remove(&splitmuxsink).ok();
+        // The muxer belongs to splitmuxsink, but if it ended up in the pipeline remove it too
+        if active_recording.muxer.parent().as_ref() == Some(pipeline.upcast_ref::<gst::Object>()) {
+            let _ = active_recording.muxer.set_state(gst::State::Null);
+            pipeline.remove(&active_recording.muxer).ok();
         }
-
-        // info!(
-        //     "Removed recording elements from pipeline for {}",
-        //     active_recording.recording_id
-        // );
+        // Nothing writes the stream any more; let another instance have it
+        self.release_lease_if_idle(&active_recording.stream_id).await;
 
         // Get file info
         let metadata = match std::fs::metadata(&active_recording.file_path) {
@@ -1203,10 +2079,7 @@ parse
 
         // Determine segments directory
         let end_time = Utc::now();
-        let segments_dir = active_recording
-            .file_path
-            .parent()
-            .unwrap_or_else(|| Path::new("."));
+        let segments_dir = active_recording.file_path.as_path();
 
         // Find all segment files
         let segment_pattern = format!("segment_*.{}", self.format);
@@ -1257,9 +2130,23 @@ parse
 
         // Track total file size for parent recording
         let mut total_file_size: u64 = 0;
+        // Duration and media info read back from the segment files, when ffprobe is available
+        let mut probed_duration: Option<f64> = None;
+        let mut probed_info: Option<MediaInfo> = None;
+
+        // Segments roll over on time or size, whichever comes first, so each one lasts until
+        // the next one started
+        let mut segment_recordings = segment_recordings;
+        segment_recordings.sort_by_key(|segment| segment.segment_id);
+        let segment_ends: Vec<DateTime<Utc>> = segment_recordings
+            .iter()
+            .skip(1)
+            .map(|segment| segment.start_time)
+            .chain(std::iter::once(end_time))
+            .collect();
 
         // First update all segment recordings to finalized state
-        for segment_recording in segment_recordings {
+        for (segment_recording, segment_end) in segment_recordings.into_iter().zip(segment_ends) {
             // Get segment index directly from the segment_id field
             let segment_idx = segment_recording.segment_id.unwrap_or(0) as usize;
 
@@ -1278,22 +2165,57 @@ parse
             total_file_size += segment_file_size;
 
             // Create segment metadata update
-            let segment_metadata = serde_json::json!({
+            let mut segment_metadata = serde_json::json!({
                 "finalized": true,
                 "status": "completed",
                 "completion_time": end_time.to_rfc3339(),
                 "file_size_bytes": segment_file_size
             });
 
+            // Read the actual media info back from the file
+            let info = if segment_file_size > 0 {
+                match probe::probe(&segment_path).await {
+                    Ok(info) => info,
+                    Err(e) => {
+                        warn!("Failed to probe segment {:?}: {}", segment_path, e);
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+            if let Some(info) = &info {
+                info.merge_into(&mut segment_metadata);
+                if let Some(secs) = info.duration_secs {
+                    *probed_duration.get_or_insert(0.0) += secs;
+                }
+                if probed_info.is_none() {
+                    probed_info = Some(info.clone());
+                }
+            }
+
             // Create update object for segment
             let segment_update = RecordingUpdate {
                 file_path: None, // Don't update path
-                duration: None,  // Use whatever duration was already recorded
+                // What the file says, or else how long until the next segment started
+                duration: info
+                    .as_ref()
+                    .and_then(|i| i.duration_secs)
+                    .map(|secs| secs.round() as u64)
+                    .or_else(|| {
+                        let secs = (segment_end - segment_recording.start_time).num_seconds();
+                        Some(secs.max(0) as u64)
+                    }),
                 file_size: Some(segment_file_size),
                 end_time: Some(end_time),
                 metadata: Some(segment_metadata),
                 segment_id: Some(segment_idx as u32), // Keep the segment ID
                 parent_recording_id: Some(parent_recording_id), // Keep the parent recording ID
+                resolution: info.as_ref().and_then(|i| i.resolution()),
+                fps: info
+                    .as_ref()
+                    .and_then(|i| i.fps)
+                    .map(|fps| fps.round() as u32),
             };
 
             // Save finalized segment to database using the new update_with_data method
@@ -1317,11 +2239,13 @@ parse
             }
         }
 
+        crate::metrics::record_bytes_written(&active_recording.camera_id, total_file_size);
+
         // Now update the parent recording as well
         let parent_recording_id = active_recording.recording_id;
 
         // Create final metadata for parent recording
-        let final_metadata = serde_json::json!({
+        let mut final_metadata = serde_json::json!({
             "finalized": true,
             "status": "completed",
             "completion_time": end_time.to_rfc3339(),
@@ -1330,6 +2254,16 @@ parse
             "recording_type": "segmented"
         });
 
+        // Prefer the summed segment durations over wall-clock time, which also counts
+        // pipeline startup and any time the stream was stalled
+        let duration = probed_duration.map_or(duration, |secs| secs.round() as u64);
+        if let Some(info) = &probed_info {
+            // Codecs come from the first segment; the bit rate covers the whole recording
+            let mut overall = info.clone();
+            overall.bit_rate = (duration > 0).then(|| total_file_size * 8 / duration);
+            overall.merge_into(&mut final_metadata);
+        }
+
         // Create update object for parent recording
         let parent_update = RecordingUpdate {
             file_path: None, // Don't update path
@@ -1339,6 +2273,11 @@ parse
             metadata: Some(final_metadata),
             segment_id: None,          // Parent recording is not a segment
             parent_recording_id: None, // Parent recording has no parent
+            resolution: probed_info.as_ref().and_then(|i| i.resolution()),
+            fps: probed_info
+                .as_ref()
+                .and_then(|i| i.fps)
+                .map(|fps| fps.round() as u32),
         };
 
         // Save finalized parent recording to database using the new update_with_data method
@@ -1347,10 +2286,20 @@ parse
             .update_with_data(&parent_recording_id, parent_update)
             .await
         {
-            Ok(_) => {
+            Ok(parent_recording) => {
                 // info!("Successfully finalized parent recording {} with {} segments, duration {}s and total size {}B",
                 //     parent_recording_id, segment_files.len(), duration, total_file_size);
 
+                // Poster image for the recordings grid; served lazily if this fails
+                if let Err(e) =
+                    thumbnail::ensure_thumbnail(&self.recordings_repo, &parent_recording).await
+                {
+                    warn!(
+                        "Failed to generate thumbnail for recording {}: {}",
+                        parent_recording_id, e
+                    );
+                }
+
                 // Additional validation that files exist
                 for (i, path) in segment_files.iter().enumerate().take(5) {
                     if path.exists() {
@@ -1436,6 +2385,15 @@ parse
         active_recordings.contains_key(&recording_key)
     }
 
+    /// IDs of all recordings currently in progress
+    pub async fn active_recording_ids(&self) -> HashSet<Uuid> {
+        let active_recordings = self.active_recordings.lock().await;
+        active_recordings
+            .values()
+            .map(|r| r.recording_id)
+            .collect()
+    }
+
     /// Check if any recording is active for a stream
     pub async fn is_stream_recording(&self, stream_id: &Uuid) -> bool {
         let active_recordings = self.active_recordings.lock().await;
@@ -1447,20 +2405,6 @@ parse
     /// Register an event that requires recording
     pub async fn register_event(&self, stream_id: &Uuid, event_type: RecordingEventType) -> Result<()> {
         let stream_key = stream_id.to_string();
-        let now = Utc::now();
-        
-        // Update the event time in the active events map
-        {
-            let mut active_events = self.active_events.lock().await;
-            active_events.insert(format!("{}-{}", stream_key, event_type.to_string()), now);
-        }
-        
-        // Check if we're already recording this stream
-        if self.is_stream_recording(stream_id).await {
-            // Already recording, no need to start a new recording
-            info!("Event received but already recording stream {}", stream_id);
-            return Ok(());
-        }
         
         // Get the stream info from the database
         let stream = match sqlx::query_as::<_, crate::db::models::stream_models::Stream>(
@@ -1475,33 +2419,123 @@ parse
             }
         };
         
-        // Check for any active schedules that allow recording this event type
-        let schedules = self.get_event_schedules(stream_id, &event_type).await?;
+        // Cameras with recording disabled keep their schedules but ignore events
+        let camera = sqlx::query_as::<_, crate::db::models::camera_models::Camera>(
+            "SELECT * FROM cameras WHERE id = $1",
+        )
+        .bind(stream.camera_id)
+        .fetch_optional(&*self.recordings_repo.pool)
+        .await?;
+        if camera.map_or(false, |c| c.recording_disabled()) {
+            info!("Ignoring {} event for stream {}: recording is disabled for its camera", event_type, stream_id);
+            return Ok(());
+        }
+
+        // Cameras can opt out of event types their schedules would otherwise record
+        let settings = self
+            .event_settings_repo
+            .get_by_camera_id(&stream.camera_id)
+            .await?
+            .unwrap_or_else(|| EventSettings::defaults_for(stream.camera_id));
+        if !settings.triggers_recording(event_type) {
+            info!(
+                "Ignoring {} event for stream {}: its camera's event settings don't record it",
+                event_type, stream_id
+            );
+            return Ok(());
+        }
+
+        // The event holds the recording until it completes, for at most the camera's
+        // recording duration in case its stop notification never arrives. This also cancels
+        // a pending stop, so an event restarting within the debounce window extends the
+        // recording.
+        let hold_secs = settings.recording_duration.max(1);
+        {
+            let mut active_events = self.active_events.lock().await;
+            let key = format!("{}-{}", stream_key, event_type.to_string());
+            active_events.insert(
+                key,
+                Utc::now() + chrono::Duration::seconds(hold_secs.into()),
+            );
+        }
+        let manager = self.clone();
+        let held_stream_id = *stream_id;
+        tokio::spawn(async move {
+            sleep(Duration::from_secs(hold_secs as u64)).await;
+            manager.stop_idle_event_recording(&held_stream_id).await;
+        });
+        
+        // Check if we're already recording this stream
+        if self.is_stream_recording(stream_id).await {
+            // Already recording, no need to start a new recording
+            info!("Event received but already recording stream {}", stream_id);
+            return Ok(());
+        }
+        
+        // Check for any active schedules that allow recording this event type
+        let schedules = self.get_event_schedules(stream_id, &event_type).await?;
         
         if !schedules.is_empty() {
             // Use the first matching schedule to start recording
             let schedule = &schedules[0];
             info!("Starting event recording for stream {} using schedule {}", stream_id, schedule.id);
             
-            let recording_id = self.start_recording(schedule, &stream).await?;
-            info!("Started scheduled event recording {} for event type {}", recording_id, event_type.to_string());
+            let result = self.start_recording(schedule, &stream).await;
+            let recording_key = format!("{}-{}", schedule.id, stream.id);
+            if let Some(recording_id) = self
+                .track_event_recording(result, stream.id, recording_key)
+                .await?
+            {
+                info!("Started scheduled event recording {} for event type {}", recording_id, event_type.to_string());
+            }
         } else {
             // No matching schedule, start a standalone event recording
-            let recording_id = self.start_event_recording(&stream, event_type).await?;
-            info!("Started standalone event recording {} for event type {}", recording_id, event_type.to_string());
+            let result = self.start_event_recording(&stream, event_type).await;
+            let recording_key = format!("{}-{}", event_type, stream.id);
+            if let Some(recording_id) = self
+                .track_event_recording(result, stream.id, recording_key)
+                .await?
+            {
+                info!("Started standalone event recording {} for event type {}", recording_id, event_type.to_string());
+            }
         }
         
         Ok(())
     }
-    
+
+    /// Remember a recording an event started under `recording_key`, so it is stopped once
+    /// the stream's events are over. An event arriving while an earlier one's recording is
+    /// still starting is carried by that recording: its `AlreadyExists` is not an error.
+    async fn track_event_recording(
+        &self,
+        result: Result<Uuid>,
+        stream_id: Uuid,
+        recording_key: String,
+    ) -> Result<Option<Uuid>> {
+        match result {
+            Ok(recording_id) => {
+                self.event_recordings
+                    .lock()
+                    .await
+                    .insert(stream_id, recording_key);
+                Ok(Some(recording_id))
+            }
+            Err(e) if matches!(e.downcast_ref::<Error>(), Some(Error::AlreadyExists(_))) => {
+                debug!(
+                    "Event recording {} is already starting: {}",
+                    recording_key, e
+                );
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
     /// Get schedules that match this event type and are currently active
     async fn get_event_schedules(&self, stream_id: &Uuid, event_type: &RecordingEventType) -> Result<Vec<RecordingSchedule>> {
-        // Get the current time
+        // Query for schedules that support this event type; whether they are active now is
+        // checked below so overnight schedules are handled
         let now = Utc::now();
-        let day_of_week = now.weekday().num_days_from_sunday() as i32;
-        let current_time = now.format("%H:%M").to_string();
-        
-        // Query for schedules that are active now and support this event type
         let event_field = match event_type {
             RecordingEventType::Motion => "record_on_motion",
             RecordingEventType::Audio => "record_on_audio",
@@ -1514,26 +2548,22 @@ parse
             r#"
             SELECT id, camera_id, stream_id, name, enabled, days_of_week, start_time, end_time,
                    created_at, updated_at, retention_days, record_on_motion, record_on_audio,
-                   record_on_analytics, record_on_external, continuous_recording
+                   record_on_analytics, record_on_external, continuous_recording, timezone
             FROM recording_schedules
             WHERE enabled = true
             AND stream_id = $1
             AND {} = true
-            AND $2 = ANY(days_of_week)
-            AND start_time <= $3
-            AND end_time >= $3
             "#,
             event_field
         );
         
         let schedules = sqlx::query_as::<_, crate::db::models::recording_schedule_models::RecordingScheduleDb>(&query)
             .bind(stream_id)
-            .bind(day_of_week)
-            .bind(current_time)
             .fetch_all(&*self.recordings_repo.pool)
             .await?
             .into_iter()
             .map(crate::db::models::recording_schedule_models::RecordingSchedule::from)
+            .filter(|schedule| schedule.is_active_at(now))
             .collect();
         
         Ok(schedules)
@@ -1544,19 +2574,54 @@ parse
         let stream_key = stream_id.to_string();
         let now = Utc::now();
         
-        // Update the event time in the active events map with expiration time (now + 5 seconds)
-        let expiration_time = now + chrono::Duration::seconds(5);
+        // Keep the event alive for the debounce window, then stop its recording unless an
+        // event started again in the meantime
+        let expiration_time = now
+            + chrono::Duration::from_std(self.event_debounce)
+                .unwrap_or_else(|_| chrono::Duration::seconds(5));
         {
             let mut active_events = self.active_events.lock().await;
             active_events.insert(format!("{}-{}", stream_key, event_type.to_string()), expiration_time);
         }
         
-        info!("Event {} completed for stream {}, recording will continue for 5 more seconds", 
-              event_type.to_string(), stream_id);
+        info!("Event {} completed for stream {}, recording will continue for {:?}", 
+              event_type.to_string(), stream_id, self.event_debounce);
+
+        let manager = self.clone();
+        let stream_id = *stream_id;
+        tokio::spawn(async move {
+            sleep(manager.event_debounce).await;
+            manager.stop_idle_event_recording(&stream_id).await;
+        });
         
         Ok(())
     }
-    
+
+    /// Stop the recording events started on a stream, unless one of its events is still
+    /// going or restarted within the debounce window
+    async fn stop_idle_event_recording(&self, stream_id: &Uuid) {
+        if self.has_active_events(stream_id).await {
+            debug!(
+                "Events on stream {} are still active, recording continues",
+                stream_id
+            );
+            return;
+        }
+
+        let Some(recording_key) = self.event_recordings.lock().await.remove(stream_id) else {
+            return;
+        };
+        match self.stop_recording_by_key(&recording_key).await {
+            Ok(()) => info!(
+                "Stopped event recording {} after its events ended",
+                recording_key
+            ),
+            // Already stopped, e.g. by the scheduler or the API
+            Err(e) if matches!(e.downcast_ref::<Error>(), Some(Error::NotFound(_))) => {}
+            Err(e) => error!("Failed to stop event recording {}: {}", recording_key, e),
+        }
+    }
+
     /// Check if there are active events requiring recording for this stream
     pub async fn has_active_events(&self, stream_id: &Uuid) -> bool {
         let stream_key = stream_id.to_string();
@@ -1649,16 +2714,75 @@ parse
             })
     }
 
-    pub fn log_metadata_stream(&self, stream_id: &str) -> Result<()> {
+    /// Log the ONVIF metadata of every stream the stream manager has or gets from now on,
+    /// so events can start recordings of streams that aren't being recorded
+    pub fn log_metadata_of_added_streams(&self) {
+        let manager = self.clone();
+        self.stream_manager.on_stream_added(move |stream_id| {
+            let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+                warn!(
+                    "No runtime to start metadata logging of stream {} on",
+                    stream_id
+                );
+                return;
+            };
+            let manager = manager.clone();
+            let stream_id = stream_id.to_string();
+            runtime.spawn(async move {
+                if let Err(e) = manager.log_stream_metadata(&stream_id).await {
+                    warn!(
+                        "Metadata logging unavailable for stream {}: {}",
+                        stream_id, e
+                    );
+                }
+            });
+        });
+    }
+
+    /// Log the metadata of a shared stream, looking up the camera its events belong to
+    async fn log_stream_metadata(&self, stream_id: &str) -> Result<()> {
+        let stream = self
+            .cameras_repo
+            .get_stream_by_id(&Uuid::parse_str(stream_id)?)
+            .await?
+            .ok_or_else(|| anyhow!("Stream {} not found", stream_id))?;
+        self.log_metadata_stream(&stream.camera_id, stream_id)
+    }
+
+    pub fn log_metadata_stream(&self, camera_id: &Uuid, stream_id: &str) -> Result<()> {
         // Get access to the pipeline and tees
         let (pipeline, _video_tee, _audio_tee, metadata_tee) = self
             .stream_manager
             .get_stream_access(stream_id)
             .map_err(|e| {
                 error!("Failed to get video stream access: {}", e);
-                anyhow!("Failed to get video stream access: {}", e)
+                e
             })?;
 
+        self.attach_metadata_logger(&pipeline, &metadata_tee, camera_id, stream_id)
+    }
+
+    /// Parse and persist the metadata coming out of `metadata_tee`, a shared stream's or
+    /// an isolated recording's, and drive event recordings from it
+    fn attach_metadata_logger(
+        &self,
+        pipeline: &gst::Pipeline,
+        metadata_tee: &gst::Element,
+        camera_id: &Uuid,
+        stream_id: &str,
+    ) -> Result<()> {
+        // One metadata branch per pipeline, shared by every recording of it
+        if pipeline
+            .by_name(&format!("metadata_sink_{}", stream_id))
+            .is_some()
+        {
+            debug!("Metadata logging already running for stream {}", stream_id);
+            return Ok(());
+        }
+
+        let stream_uuid = Uuid::parse_str(stream_id)
+            .map_err(|e| anyhow!("Invalid stream id {}: {}", stream_id, e))?;
+
         // Create elements for the metadata branch
         let queue = gst::ElementFactory::make("queue")
             .name(&format!("metadata_logger_queue_{}", stream_id))
@@ -1695,10 +2819,20 @@ parse
         // Get the appsink element and connect to new-sample signal
         let appsink = sink.dynamic_cast::<AppSink>().unwrap();
 
-        // Import the necessary types for metadata processing
         // Create clones of necessary data that will be moved into the callback
         let recording_manager = self.clone();
         let stream_id_clone = stream_id.to_string();
+        let camera_id = *camera_id;
+        // The appsink callback runs on a GStreamer streaming thread, outside the runtime
+        let runtime = tokio::runtime::Handle::current();
+        let binary_warned = AtomicBool::new(false);
+        let dump_path = self.metadata_dump.then(|| {
+            crate::utils::metadataparser::get_metadata_path()
+                .join(format!(
+                    "{}-metadata.xml",
+                    path_template::sanitize_path_component(stream_id)
+                ))
+        });
 
         appsink.set_callbacks(
             AppSinkCallbacks::builder()
@@ -1730,124 +2864,62 @@ parse
                         }
                     };
 
-                    // Convert the buffer data to a string if it's XML
-                    match std::str::from_utf8(&map) {
-                        Ok(metadata_str) => {
-                            info!("Received metadata: {}", metadata_str);
-                            
-                            // Write metadata to file in a proper location
-                            let metadata_dir = crate::utils::metadataparser::get_metadata_path();
-                            std::fs::create_dir_all(&metadata_dir).map_err(|_e| {
-                                println!("Error creating metadata directory");
-                                gstreamer::FlowError::Error
-                            })?;
-                            
-                            let metadata_file = metadata_dir.join(format!("{}-metadata.xml", stream_id_clone));
-                            let file = OpenOptions::new()
-                                .write(true)
-                                .append(true)
-                                .create(true)
-                                .open(metadata_file)
-                                .map_err(|e| {
-                                    println!("Error creating file for onvif-metadata: {}", e);
-                                    gstreamer::FlowError::Error
-                                })?;
-                            let mut buf_writer = BufWriter::new(file);
-
-                            buf_writer.write_all(&map).map_err(|_e| {
-                                println!("Error creating file for onvif-metadata");
-                                gstreamer::FlowError::Error
-                            })?;
-
-                            // Parse the ONVIF event metadata
-                            match parse_onvif_event(metadata_str) {
-                                Ok(metadata) => {
-                                    println!(
-                                        "Parsed Event: {:#?}, active: {:#?}",
-                                        metadata.event_type,
-                                        metadata.is_active.unwrap_or(false)
+                    let (onvif_event, analytics_event) =
+                        match analytics_event_from_metadata(camera_id, stream_uuid, &map) {
+                            Ok(parsed) => parsed,
+                            Err(MetadataError::Binary(len)) => {
+                                // KLV and other binary metadata isn't supported yet; say so once
+                                if !binary_warned.swap(true, Ordering::Relaxed) {
+                                    warn!(
+                                        "Stream {} sends binary metadata ({} bytes), ignoring it",
+                                        stream_id_clone, len
                                     );
-                                    
-                                    // Handle specific event types from camera
-                                    if let Some(is_active) = metadata.is_active {
-                                        if let Some(_camera_id) = metadata.camera_id.clone() {
-                                            if let Some(stream_id) = metadata.stream_id.clone() {
-                                                let stream_uuid = uuid::Uuid::parse_str(&stream_id).unwrap_or_default();
-                                                let recording_manager_clone = recording_manager.clone();
-                                                
-                                                // Handle motion events
-                                                if matches!(metadata.event_type, crate::utils::metadataparser::EventType::MotionDetected) {
-                                                    if is_active {
-                                                        // Motion started
-                                                        tokio::spawn(async move {
-                                                            if let Err(e) = recording_manager_clone.register_event(&stream_uuid, RecordingEventType::Motion).await {
-                                                                eprintln!("Failed to register motion event: {}", e);
-                                                            }
-                                                        });
-                                                    } else {
-                                                        // Motion ended
-                                                        tokio::spawn(async move {
-                                                            if let Err(e) = recording_manager_clone.event_completed(&stream_uuid, RecordingEventType::Motion).await {
-                                                                eprintln!("Failed to complete motion event: {}", e);
-                                                            }
-                                                        });
-                                                    }
-                                                } 
-                                                // Handle audio events
-                                                else if matches!(metadata.event_type, crate::utils::metadataparser::EventType::AudioDetected) {
-                                                    let recording_manager_clone = recording_manager.clone();
-                                                    if is_active {
-                                                        // Audio started
-                                                        tokio::spawn(async move {
-                                                            if let Err(e) = recording_manager_clone.register_event(&stream_uuid, RecordingEventType::Audio).await {
-                                                                eprintln!("Failed to register audio event: {}", e);
-                                                            }
-                                                        });
-                                                    } else {
-                                                        // Audio ended
-                                                        tokio::spawn(async move {
-                                                            if let Err(e) = recording_manager_clone.event_completed(&stream_uuid, RecordingEventType::Audio).await {
-                                                                eprintln!("Failed to complete audio event: {}", e);
-                                                            }
-                                                        });
-                                                    }
-                                                } 
-                                                // Handle analytics events
-                                                else if matches!(metadata.event_type, 
-                                                     crate::utils::metadataparser::EventType::LineDetected |
-                                                     crate::utils::metadataparser::EventType::FieldDetected |
-                                                     crate::utils::metadataparser::EventType::FaceDetected |
-                                                     crate::utils::metadataparser::EventType::ObjectDetected) {
-                                                    let recording_manager_clone = recording_manager.clone();
-                                                    if is_active {
-                                                        // Analytics event started
-                                                        tokio::spawn(async move {
-                                                            if let Err(e) = recording_manager_clone.register_event(&stream_uuid, RecordingEventType::Analytics).await {
-                                                                eprintln!("Failed to register analytics event: {}", e);
-                                                            }
-                                                        });
-                                                    } else {
-                                                        // Analytics event ended
-                                                        tokio::spawn(async move {
-                                                            if let Err(e) = recording_manager_clone.event_completed(&stream_uuid, RecordingEventType::Analytics).await {
-                                                                eprintln!("Failed to complete analytics event: {}", e);
-                                                            }
-                                                        });
-                                                    }
-                                                }
-                                            }
-                                        }
-                                    }
-                                },
-                                Err(e) => {
-                                    println!("Failed to parse ONVIF event: {}", e);
                                 }
+                                return Ok(gst::FlowSuccess::Ok);
+                            }
+                            Err(MetadataError::Parse(e)) => {
+                                debug!(
+                                    "Skipping unparseable metadata on {}: {}",
+                                    stream_id_clone, e
+                                );
+                                return Ok(gst::FlowSuccess::Ok);
                             }
+                        };
+
+                    if let Some(dump_path) = &dump_path {
+                        if let Err(e) = append_metadata_dump(dump_path, &map) {
+                            warn!("Failed to write metadata dump {}: {}", dump_path.display(), e);
                         }
-                        Err(_) => {
-                            // If it's not UTF-8 (could be binary format like KLV)
-                            debug!("Received binary metadata of size: {} bytes", map.len());
+                    }
+
+                    debug!(
+                        "Parsed event {:?} on stream {}, active: {:?}",
+                        onvif_event.event_type, stream_id_clone, onvif_event.is_active
+                    );
+
+                    let analytics_repo = recording_manager.analytics_repo.clone();
+                    runtime.spawn(async move {
+                        if let Err(e) = analytics_repo.create(&analytics_event).await {
+                            error!("Failed to store analytics event: {}", e);
                         }
+                    });
+
+                    // Stateful events drive event-triggered recordings
+                    if let (Some(is_active), Some(event_type)) = (
+                        onvif_event.is_active,
+                        recording_event_type_for(&onvif_event.event_type),
+                    ) {
+                        let recording_manager = recording_manager.clone();
+                        runtime.spawn(async move {
+                            let result = if is_active {
+                                recording_manager.register_event(&stream_uuid, event_type).await
+                            } else {
+                                recording_manager.event_completed(&stream_uuid, event_type).await
+                            };
+                            if let Err(e) = result {
+                                error!("Failed to handle {} event: {}", event_type, e);
+                            }
+                        });
                     }
 
                     Ok(gst::FlowSuccess::Ok)
@@ -1866,3 +2938,1045 @@ parse
         Ok(())
     }
 }
+
+#[cfg(test)]
+impl RecordingManager {
+    /// Register a manual recording of `stream_id` as in progress, with identity elements
+    /// standing in for the recording branch. Returns the pipeline, already playing.
+    pub(crate) async fn insert_test_recording(
+        &self,
+        recording_id: Uuid,
+        camera_id: Uuid,
+        stream_id: Uuid,
+    ) -> Result<gst::Pipeline> {
+        self.insert_test_event_recording(
+            recording_id,
+            camera_id,
+            stream_id,
+            RecordingEventType::Manual,
+        )
+        .await
+    }
+
+    /// Like `insert_test_recording`, for a recording of `event_type`. Recordings of event
+    /// types are tracked as started by their event, like `register_event` does.
+    pub(crate) async fn insert_test_event_recording(
+        &self,
+        recording_id: Uuid,
+        camera_id: Uuid,
+        stream_id: Uuid,
+        event_type: RecordingEventType,
+    ) -> Result<gst::Pipeline> {
+        gst::init()?;
+        let pipeline = gst::Pipeline::new();
+        let tee = make_element("tee", format!("test_tee_{}", recording_id))?;
+        let queue = make_element("identity", format!("test_queue_{}", recording_id))?;
+        let sink = make_element("identity", format!("test_sink_{}", recording_id))?;
+        pipeline.add_many([&tee, &queue, &sink])?;
+        queue.link(&sink)?;
+
+        let video_tee_pad = tee
+            .request_pad_simple("src_%u")
+            .ok_or_else(|| anyhow!("tee gave no src pad"))?;
+        let queue_sink = queue.static_pad("sink").unwrap();
+        video_tee_pad.link(&queue_sink)?;
+        pipeline.set_state(gst::State::Playing)?;
+
+        let recording = ActiveRecordingElements {
+            pipeline: pipeline.clone(),
+            video_tee_pad,
+            video_elements_chain: Some(vec![queue]),
+            muxer: sink.clone(),
+            splitmuxsink: sink.clone(),
+            splitmuxsink_video_pad: sink.static_pad("sink").unwrap(),
+            audio_tee_pad: None,
+            audio_elements_chain: None,
+            splitmuxsink_audio_pad: None,
+            recording_id,
+            schedule_id: None,
+            camera_id,
+            stream_id,
+            start_time: Utc::now(),
+            event_type,
+            file_path: self.recording_base_path.join(recording_id.to_string()),
+            pipeline_watch_id: None,
+            isolated_pipeline: None,
+        };
+        let key = format!("{}-{}", event_type, stream_id);
+        if !matches!(
+            event_type,
+            RecordingEventType::Manual | RecordingEventType::Continuous
+        ) {
+            self.event_recordings
+                .lock()
+                .await
+                .insert(stream_id, key.clone());
+        }
+        self.active_recordings.lock().await.insert(key, recording);
+        Ok(pipeline)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_fixtures::insert_camera_and_stream;
+
+    const MOTION_METADATA: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<tt:MetadataStream xmlns:tt="http://www.onvif.org/ver10/schema">
+  <tt:Event>
+    <wsnt:NotificationMessage xmlns:tns1="http://www.onvif.org/ver10/topics" xmlns:wsnt="http://docs.oasis-open.org/wsn/b-2" xmlns:wsa5="http://www.w3.org/2005/08/addressing">
+      <wsnt:Topic Dialect="http://www.onvif.org/ver10/tev/topicExpression/ConcreteSet">tns1:RuleEngine/CellMotionDetector/Motion</wsnt:Topic>
+      <wsnt:ProducerReference>
+        <wsa5:Address>192.168.1.105/onvif/event/alarm</wsa5:Address>
+      </wsnt:ProducerReference>
+      <wsnt:Message>
+        <tt:Message PropertyOperation="Changed" UtcTime="2025-04-28T23:28:42Z">
+          <tt:Source>
+            <tt:SimpleItem Value="video_source_config" Name="VideoSourceConfigurationToken"></tt:SimpleItem>
+            <tt:SimpleItem Value="MyMotionDetectorRule" Name="Rule"></tt:SimpleItem>
+          </tt:Source>
+          <tt:Data>
+            <tt:SimpleItem Value="true" Name="IsMotion"></tt:SimpleItem>
+          </tt:Data>
+        </tt:Message>
+      </wsnt:Message>
+    </wsnt:NotificationMessage>
+  </tt:Event>
+</tt:MetadataStream>"#;
+
+    #[test]
+    fn motion_metadata_produces_analytics_row() {
+        let camera_id = Uuid::new_v4();
+        let stream_id = Uuid::new_v4();
+
+        let (event, row) =
+            analytics_event_from_metadata(camera_id, stream_id, MOTION_METADATA.as_bytes())
+                .unwrap();
+
+        assert_eq!(row.camera_id, camera_id);
+        assert_eq!(row.stream_id, stream_id);
+        assert_eq!(row.event_type, "motion");
+        assert_eq!(row.is_active, Some(true));
+        assert_eq!(row.timestamp.to_rfc3339(), "2025-04-28T23:28:42+00:00");
+        assert_eq!(row.raw_metadata.as_deref(), Some(MOTION_METADATA));
+        assert_eq!(event.stream_id, Some(stream_id.to_string()));
+        assert_eq!(
+            recording_event_type_for(&event.event_type),
+            Some(RecordingEventType::Motion)
+        );
+    }
+
+    #[test]
+    fn binary_and_garbage_metadata_are_rejected_without_panicking() {
+        let klv = [0x06, 0x0e, 0x2b, 0x34, 0xff, 0xfe, 0x00, 0x81];
+        assert!(matches!(
+            analytics_event_from_metadata(Uuid::new_v4(), Uuid::new_v4(), &klv),
+            Err(MetadataError::Binary(8))
+        ));
+        assert!(matches!(
+            analytics_event_from_metadata(Uuid::new_v4(), Uuid::new_v4(), b"<tt:Frame"),
+            Err(MetadataError::Parse(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_motion_metadata_row_is_stored() -> Result<()> {
+        let (Ok(database_url), Ok(stream_id)) = (
+            std::env::var("TEST_DATABASE_URL"),
+            std::env::var("TEST_STREAM_ID"),
+        ) else {
+            println!("Skipping analytics event test. Set TEST_DATABASE_URL and TEST_STREAM_ID to run.");
+            return Ok(());
+        };
+
+        let pool = Arc::new(PgPool::connect(&database_url).await?);
+        let stream = crate::db::repositories::cameras::CamerasRepository::new(pool.clone())
+            .get_stream_by_id(&Uuid::parse_str(&stream_id)?)
+            .await?
+            .ok_or_else(|| anyhow!("Stream {} not found", stream_id))?;
+
+        let (_, row) =
+            analytics_event_from_metadata(stream.camera_id, stream.id, MOTION_METADATA.as_bytes())
+                .map_err(|e| anyhow!("{:?}", e))?;
+        let repo = AnalyticsEventsRepository::new(pool.clone());
+        repo.create(&row).await?;
+
+        let found = repo
+            .search(&crate::db::models::analytics_event_models::AnalyticsEventSearchQuery {
+                stream_ids: Some(vec![stream.id]),
+                start_time: Some(row.timestamp),
+                end_time: Some(row.timestamp),
+                ..Default::default()
+            })
+            .await?;
+        assert!(found.iter().any(|e| e.id == row.id && e.event_type == "motion"));
+
+        sqlx::query("DELETE FROM analytics_events WHERE id = $1")
+            .bind(row.id)
+            .execute(&*pool)
+            .await?;
+        Ok(())
+    }
+
+    #[test]
+    fn splitmuxsink_rolls_on_size_and_time() {
+        gst::init().unwrap();
+        let muxer = make_element("identity", "test_mux".to_string()).unwrap();
+
+        let sink = build_splitmuxsink(
+            "test_splitmuxsink".to_string(),
+            &muxer,
+            "/tmp/segment_%05d.mp4".to_string(),
+            30,
+            64 * 1024 * 1024,
+        )
+        .unwrap();
+        assert_eq!(sink.property::<u64>("max-size-bytes"), 64 * 1024 * 1024);
+        assert_eq!(
+            sink.property::<u64>("max-size-time"),
+            ClockTime::from_seconds(30).nseconds()
+        );
+
+        let muxer = make_element("identity", "test_mux_unlimited".to_string()).unwrap();
+        let unlimited = build_splitmuxsink(
+            "test_splitmuxsink_unlimited".to_string(),
+            &muxer,
+            "/tmp/segment_%05d.mp4".to_string(),
+            30,
+            0,
+        )
+        .unwrap();
+        assert_eq!(unlimited.property::<u64>("max-size-bytes"), 0);
+    }
+
+    #[test]
+    fn segment_start_times_follow_running_time() {
+        let start = Utc::now();
+        let mut anchor = None;
+
+        // Keyframe alignment makes the 2 s segments 2.4, 1.7 and 2.9 s long
+        let running_times = [0, 2_400, 4_100, 7_000].map(ClockTime::from_mseconds);
+        let starts: Vec<_> = running_times
+            .iter()
+            .enumerate()
+            .map(|(id, rt)| segment_start_time(&mut anchor, start, Some(*rt), id as u32, 2))
+            .collect();
+        let offsets: Vec<_> = starts
+            .iter()
+            .map(|t| (*t - start).num_milliseconds())
+            .collect();
+        assert_eq!(offsets, [0, 2_400, 4_100, 7_000]);
+
+        // A recording whose running time doesn't start at zero
+        let mut anchor = None;
+        let first = segment_start_time(&mut anchor, start, Some(ClockTime::from_seconds(60)), 0, 2);
+        let second = segment_start_time(
+            &mut anchor,
+            start,
+            Some(ClockTime::from_mseconds(62_500)),
+            1,
+            2,
+        );
+        assert_eq!(first, start);
+        assert_eq!((second - start).num_milliseconds(), 2_500);
+
+        // Without a running time the configured duration is all there is
+        let mut anchor = None;
+        let estimate = segment_start_time(&mut anchor, start, None, 3, 2);
+        assert_eq!((estimate - start).num_seconds(), 6);
+        assert!(anchor.is_none());
+    }
+
+    #[test]
+    fn codec_support_follows_the_recording_chains() {
+        assert_eq!(video_codec_support("h264", false), CodecSupport::Supported);
+        assert_eq!(video_codec_support("hevc", false), CodecSupport::Supported);
+        assert_eq!(video_codec_support("mpeg4", false), CodecSupport::Limited);
+        assert_eq!(video_codec_support("mjpeg", true), CodecSupport::Transcoded);
+        assert_eq!(
+            video_codec_support("theora", true),
+            CodecSupport::Unsupported
+        );
+        let vp9 = codec_from_rtp_encoding("VP9");
+        assert_eq!(video_codec_support(&vp9, true), CodecSupport::Transcoded);
+        // Without the transcode fallback they fail to record
+        assert_eq!(
+            video_codec_support("mjpeg", false),
+            CodecSupport::Unsupported
+        );
+        assert_eq!(video_codec_support(&vp9, false), CodecSupport::Unsupported);
+
+        let aac = codec_from_rtp_encoding("MPEG4-GENERIC");
+        assert_eq!(audio_codec_support(&aac), CodecSupport::Supported);
+        assert_eq!(audio_codec_support("pcma"), CodecSupport::Transcoded);
+        assert_eq!(audio_codec_support("opus"), CodecSupport::Unsupported);
+    }
+
+    #[test]
+    fn h26x_chain_links_without_timestamper() {
+        gst::init().unwrap();
+
+        // Mock factory: everything is an identity element, timestampers don't exist
+        let make = |factory: &str, name: String| -> Result<gst::Element> {
+            if factory.ends_with("timestamper") {
+                return Err(anyhow!("no such element: {}", factory));
+            }
+            Ok(gst::ElementFactory::make("identity").name(name).build()?)
+        };
+
+        for (depay, parse, timestamper, codec) in [
+            ("rtph264depay", "h264parse", "h264timestamper", "h264"),
+            ("rtph265depay", "h265parse", "h265timestamper", "h265"),
+        ] {
+            let (elements, parse_el, output) =
+                build_h26x_chain(&make, depay, parse, timestamper, codec, "test").unwrap();
+
+            assert_eq!(elements.len(), 2);
+            assert_eq!(output, parse_el);
+
+            let pipeline = gst::Pipeline::new();
+            pipeline.add_many(&elements).unwrap();
+            gst::Element::link_many(&elements).unwrap();
+        }
+    }
+
+    /// Mock factory: `absent` don't exist, everything else is an identity element named after
+    /// its factory
+    fn factory_without(
+        absent: &'static [&'static str],
+    ) -> impl Fn(&str, String) -> Result<gst::Element> {
+        move |factory: &str, _name: String| {
+            if absent.contains(&factory) {
+                return Err(anyhow!("no such element: {}", factory));
+            }
+            Ok(gst::ElementFactory::make("identity")
+                .name(factory)
+                .build()?)
+        }
+    }
+
+    #[test]
+    fn audio_chain_survives_missing_aac_encoders() {
+        gst::init().unwrap();
+
+        let make = factory_without(&["avenc_aac", "faac", "voaacenc"]);
+        let audio = AudioEncodeSettings::default();
+        let chain = build_audio_chain(&make, "pcmu", &audio, "test")
+            .unwrap()
+            .unwrap();
+        let names: Vec<_> = chain.iter().map(|el| el.name().to_string()).collect();
+        assert_eq!(
+            names,
+            [
+                "rtppcmudepay",
+                "mulawdec",
+                "audioconvert",
+                "audioresample",
+                "capsfilter",
+                "lamemp3enc",
+                "mpegaudioparse"
+            ]
+        );
+
+        // Nothing can encode it: record video only instead of failing
+        let make = factory_without(&["avenc_aac", "faac", "voaacenc", "lamemp3enc", "opusenc"]);
+        assert!(build_audio_chain(&make, "pcma", &audio, "test")
+            .unwrap()
+            .is_none());
+    }
+
+    // Needs the G.711 and audio base plugins and one of the AUDIO_ENCODERS
+    #[test]
+    fn audio_encoder_follows_the_config() {
+        gst::init().unwrap();
+
+        let audio = AudioEncodeSettings {
+            bitrate: 48000,
+            sample_rate: 22050,
+        };
+        let Ok(Some(chain)) = build_audio_chain(&make_element, "pcmu", &audio, "test") else {
+            println!("Skipping audio_encoder_follows_the_config: audio plugins missing.");
+            return;
+        };
+
+        let caps = chain[4].property::<gst::Caps>("caps");
+        assert_eq!(caps.structure(0).unwrap().get::<i32>("rate"), Ok(22050));
+
+        let encoder = &chain[5];
+        let factory = encoder.factory().unwrap().name();
+        let bitrate = encoder
+            .property_value("bitrate")
+            .transform::<i64>()
+            .unwrap()
+            .get::<i64>()
+            .unwrap();
+        let expected = if factory == "lamemp3enc" { 48 } else { 48000 };
+        assert_eq!(bitrate, expected, "bitrate of {}", factory);
+    }
+    // Record RTP MJPEG from a test source into an MP4 through the transcode chain. The
+    // pipeline part needs the jpeg, rtp, x264 and isomp4 plugins.
+    #[test]
+    fn mjpeg_records_only_with_transcode_fallback() {
+        gst::init().unwrap();
+
+        let err = build_video_chain(&make_element, "mjpeg", None, "test").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Recording(_))
+        ));
+
+        let settings = TranscodeSettings::default();
+        let (Ok(src), Ok(enc), Ok(pay), Ok(mux), Ok(chain)) = (
+            make_element("videotestsrc", "src".to_string()),
+            make_element("jpegenc", "enc".to_string()),
+            make_element("rtpjpegpay", "pay".to_string()),
+            make_element("mp4mux", "mux".to_string()),
+            build_video_chain(&make_element, "mjpeg", Some(&settings), "test"),
+        ) else {
+            println!("Skipping mjpeg_records_only_with_transcode_fallback: plugins missing.");
+            return;
+        };
+        src.set_property("num-buffers", 10i32);
+        let path = std::env::temp_dir().join(format!("mjpeg-transcode-{}.mp4", Uuid::new_v4()));
+        let sink = gst::ElementFactory::make("filesink")
+            .property("location", path.to_str().unwrap())
+            .build()
+            .unwrap();
+
+        let mut elements = vec![src, enc, pay];
+        elements.extend(chain);
+        elements.extend([mux, sink]);
+        let pipeline = gst::Pipeline::new();
+        pipeline.add_many(&elements).unwrap();
+        gst::Element::link_many(&elements).unwrap();
+        pipeline.set_state(gst::State::Playing).unwrap();
+
+        let msg = pipeline
+            .bus()
+            .unwrap()
+            .timed_pop_filtered(
+                gst::ClockTime::from_seconds(30),
+                &[gst::MessageType::Eos, gst::MessageType::Error],
+            )
+            .expect("transcoded recording didn't finish");
+        pipeline.set_state(gst::State::Null).unwrap();
+        assert_eq!(msg.type_(), gst::MessageType::Eos, "{:?}", msg);
+        assert!(std::fs::metadata(&path).unwrap().len() > 0);
+        let _ = std::fs::remove_file(&path);
+    }
+
+    // Start a real recording, shut it down the way run_app does and check the parent row
+    // was finalized. Needs a database with a camera stream and that stream's RTSP URL.
+    #[tokio::test]
+    async fn test_shutdown_finalizes_recordings() -> Result<()> {
+        let (Ok(database_url), Ok(stream_id), Ok(rtsp_url)) = (
+            std::env::var("TEST_DATABASE_URL"),
+            std::env::var("TEST_STREAM_ID"),
+            std::env::var("TEST_RTSP_URL"),
+        ) else {
+            println!(
+                "Skipping recording shutdown test. Set TEST_DATABASE_URL, TEST_STREAM_ID and TEST_RTSP_URL to run."
+            );
+            return Ok(());
+        };
+
+        let pool = Arc::new(PgPool::connect(&database_url).await?);
+        let stream = crate::db::repositories::cameras::CamerasRepository::new(pool.clone())
+            .get_stream_by_id(&Uuid::parse_str(&stream_id)?)
+            .await?
+            .ok_or_else(|| anyhow!("Stream {} not found", stream_id))?;
+
+        let stream_manager = Arc::new(StreamManager::new(pool.clone()));
+        stream_manager.add_stream(
+            crate::stream_manager::StreamSource {
+                stream_type: stream.stream_type,
+                uri: rtsp_url,
+                name: stream.name.clone(),
+                description: None,
+            },
+            stream.id.to_string(),
+        )?;
+
+        let recordings_dir = std::env::temp_dir().join(format!("g-streamer-test-{}", Uuid::new_v4()));
+        let manager = RecordingManager::new(pool.clone(), stream_manager.clone(), &recordings_dir, 2, "mp4");
+
+        let recording_id = manager.start_manual_recording(&stream).await?;
+        sleep(Duration::from_secs(5)).await;
+
+        manager.stop_all_recordings().await?;
+        stream_manager.stop_all_streams();
+
+        let recording = manager
+            .recordings_repo
+            .get_by_id(&recording_id)
+            .await?
+            .ok_or_else(|| anyhow!("Recording {} not found", recording_id))?;
+        assert!(recording.end_time.is_some());
+        assert!(recording.file_size > 0);
+
+        // A poster image was extracted from the first segment and recorded in the metadata
+        let thumbnail_path = recording
+            .metadata
+            .as_ref()
+            .and_then(|m| m.get(thumbnail::THUMBNAIL_METADATA_KEY))
+            .and_then(|p| p.as_str())
+            .map(PathBuf::from)
+            .ok_or_else(|| anyhow!("Recording {} has no thumbnail path", recording_id))?;
+        assert!(thumbnail_path.is_file());
+
+        let _ = std::fs::remove_dir_all(&recordings_dir);
+        Ok(())
+    }
+
+    // DELETE /api/streams/:id is rejected while the stream is being recorded and allowed
+    // once the recording stops
+    #[tokio::test]
+    async fn test_recorded_stream_cannot_be_removed() -> Result<()> {
+        let (Ok(database_url), Ok(stream_id), Ok(rtsp_url)) = (
+            std::env::var("TEST_DATABASE_URL"),
+            std::env::var("TEST_STREAM_ID"),
+            std::env::var("TEST_RTSP_URL"),
+        ) else {
+            println!(
+                "Skipping stream removal test. Set TEST_DATABASE_URL, TEST_STREAM_ID and TEST_RTSP_URL to run."
+            );
+            return Ok(());
+        };
+
+        let pool = Arc::new(PgPool::connect(&database_url).await?);
+        let stream = crate::db::repositories::cameras::CamerasRepository::new(pool.clone())
+            .get_stream_by_id(&Uuid::parse_str(&stream_id)?)
+            .await?
+            .ok_or_else(|| anyhow!("Stream {} not found", stream_id))?;
+
+        let stream_manager = Arc::new(StreamManager::new(pool.clone()));
+        stream_manager.add_stream(
+            crate::stream_manager::StreamSource {
+                stream_type: stream.stream_type,
+                uri: rtsp_url,
+                name: stream.name.clone(),
+                description: None,
+            },
+            stream.id.to_string(),
+        )?;
+
+        let recordings_dir = std::env::temp_dir().join(format!("g-streamer-test-{}", Uuid::new_v4()));
+        let manager = RecordingManager::new(pool.clone(), stream_manager.clone(), &recordings_dir, 2, "mp4");
+
+        manager.start_manual_recording(&stream).await?;
+        let busy = manager.is_stream_recording(&stream.id).await;
+        manager.stop_all_recordings().await?;
+        let idle = !manager.is_stream_recording(&stream.id).await;
+        stream_manager.remove_stream(&stream.id.to_string()).await?;
+
+        let _ = std::fs::remove_dir_all(&recordings_dir);
+        assert!(busy);
+        assert!(idle);
+        assert!(stream_manager.get_stream_access(&stream.id.to_string()).is_err());
+        Ok(())
+    }
+
+    // A camera recorded in isolation gets a pipeline of its own, so its recording works
+    // without the stream being registered with the stream manager
+    #[tokio::test]
+    async fn test_isolated_recording_needs_no_shared_stream() -> Result<()> {
+        let (Ok(database_url), Ok(stream_id), Ok(rtsp_url)) = (
+            std::env::var("TEST_DATABASE_URL"),
+            std::env::var("TEST_STREAM_ID"),
+            std::env::var("TEST_RTSP_URL"),
+        ) else {
+            println!(
+                "Skipping isolated recording test. Set TEST_DATABASE_URL, TEST_STREAM_ID and TEST_RTSP_URL to run."
+            );
+            return Ok(());
+        };
+
+        let pool = Arc::new(PgPool::connect(&database_url).await?);
+        let cameras_repo = crate::db::repositories::cameras::CamerasRepository::new(pool.clone());
+        let mut stream = cameras_repo
+            .get_stream_by_id(&Uuid::parse_str(&stream_id)?)
+            .await?
+            .ok_or_else(|| anyhow!("Stream {} not found", stream_id))?;
+        stream.url = rtsp_url;
+        cameras_repo
+            .set_isolated_recording(&stream.camera_id, true)
+            .await?;
+
+        let stream_manager = Arc::new(StreamManager::new(pool.clone()));
+        let recordings_dir =
+            std::env::temp_dir().join(format!("g-streamer-test-{}", Uuid::new_v4()));
+        let manager = RecordingManager::new(
+            pool.clone(),
+            stream_manager.clone(),
+            &recordings_dir,
+            2,
+            "mp4",
+        );
+
+        let started = manager.start_manual_recording(&stream).await;
+        let mut logs_metadata = false;
+        if started.is_ok() {
+            // The isolated pipeline carries its own metadata branch
+            logs_metadata = manager.active_recordings.lock().await.values().any(|r| {
+                r.isolated_pipeline.as_ref().is_some_and(|isolated| {
+                    isolated
+                        .pipeline
+                        .by_name(&format!("metadata_sink_{}", stream.id))
+                        .is_some()
+                })
+            });
+            sleep(Duration::from_secs(5)).await;
+            manager.stop_all_recordings().await?;
+        }
+        cameras_repo
+            .set_isolated_recording(&stream.camera_id, false)
+            .await?;
+        let recording_id = started?;
+        assert!(logs_metadata);
+
+        let recording = manager
+            .recordings_repo
+            .get_by_id(&recording_id)
+            .await?
+            .ok_or_else(|| anyhow!("Recording {} not found", recording_id))?;
+        let _ = std::fs::remove_dir_all(&recordings_dir);
+        assert!(stream_manager.list_streams().is_empty());
+        assert!(recording.end_time.is_some());
+        assert!(recording.file_size > 0);
+        Ok(())
+    }
+
+    // Starting a recording publishes RecordingStarted, so consumers can pair it with the
+    // stopped event
+    #[tokio::test]
+    async fn test_start_publishes_recording_started() -> Result<()> {
+        let (Ok(database_url), Ok(stream_id), Ok(rtsp_url)) = (
+            std::env::var("TEST_DATABASE_URL"),
+            std::env::var("TEST_STREAM_ID"),
+            std::env::var("TEST_RTSP_URL"),
+        ) else {
+            println!(
+                "Skipping recording started event test. Set TEST_DATABASE_URL, TEST_STREAM_ID and TEST_RTSP_URL to run."
+            );
+            return Ok(());
+        };
+
+        let pool = Arc::new(PgPool::connect(&database_url).await?);
+        let stream = crate::db::repositories::cameras::CamerasRepository::new(pool.clone())
+            .get_stream_by_id(&Uuid::parse_str(&stream_id)?)
+            .await?
+            .ok_or_else(|| anyhow!("Stream {} not found", stream_id))?;
+
+        let stream_manager = Arc::new(StreamManager::new(pool.clone()));
+        stream_manager.add_stream(
+            crate::stream_manager::StreamSource {
+                stream_type: stream.stream_type,
+                uri: rtsp_url,
+                name: stream.name.clone(),
+                description: None,
+            },
+            stream.id.to_string(),
+        )?;
+
+        let broker = Arc::new(
+            crate::messaging::MessageBroker::new(crate::config::MessageBrokerConfig {
+                backend: crate::config::BrokerBackend::Memory,
+                ..Default::default()
+            })
+            .await?,
+        );
+        let received = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let received_clone = received.clone();
+        broker
+            .subscribe_pattern(
+                "recording.started.#",
+                Arc::new(move |event| {
+                    received_clone.lock().unwrap().push(event);
+                    Ok(())
+                }),
+            )
+            .await?;
+
+        let recordings_dir = std::env::temp_dir().join(format!("g-streamer-test-{}", Uuid::new_v4()));
+        let manager = RecordingManager::new(pool.clone(), stream_manager.clone(), &recordings_dir, 2, "mp4");
+        manager.set_message_broker(broker).await?;
+
+        let recording_id = manager.start_manual_recording(&stream).await?;
+        manager.stop_all_recordings().await?;
+        stream_manager.stop_all_streams();
+        let _ = std::fs::remove_dir_all(&recordings_dir);
+
+        let events = received.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].source_id, Some(stream.camera_id));
+        let payload = &events[0].payload;
+        assert_eq!(payload["recording_id"], recording_id.to_string());
+        assert_eq!(payload["camera_id"], stream.camera_id.to_string());
+        assert_eq!(payload["stream_id"], stream.id.to_string());
+        assert_eq!(payload["event_type"], RecordingEventType::Manual.to_string());
+        assert!(payload["start_time"].as_str().is_some());
+        Ok(())
+    }
+
+    // A camera already recording continuously, on motion and manually has no room for a
+    // fourth recording; another camera still does
+    #[tokio::test]
+    async fn test_fourth_recording_of_a_camera_is_rejected() -> Result<()> {
+        let pool = Arc::new(PgPool::connect_lazy("postgres://localhost/unused")?);
+        let stream_manager = Arc::new(StreamManager::new(pool.clone()));
+        let recordings_dir =
+            std::env::temp_dir().join(format!("g-streamer-test-{}", Uuid::new_v4()));
+        let manager = RecordingManager::new(pool, stream_manager, &recordings_dir, 2, "mp4")
+            .with_limits(RecordingLimits {
+                per_camera: 3,
+                total: 0,
+            });
+
+        let camera_id = Uuid::new_v4();
+        let mut pipelines = Vec::new();
+        for event_type in [
+            RecordingEventType::Continuous,
+            RecordingEventType::Motion,
+            RecordingEventType::Manual,
+        ] {
+            pipelines.push(
+                manager
+                    .insert_test_event_recording(
+                        Uuid::new_v4(),
+                        camera_id,
+                        Uuid::new_v4(),
+                        event_type,
+                    )
+                    .await?,
+            );
+        }
+
+        let stream = Stream {
+            camera_id,
+            ..Default::default()
+        };
+        let err = manager
+            .start_event_recording(&stream, RecordingEventType::Audio)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(err.downcast_ref::<Error>(), Some(Error::Recording(_))),
+            "{}",
+            err
+        );
+        assert_eq!(manager.active_recording_ids().await.len(), 3);
+
+        let other_camera = [Uuid::new_v4()];
+        assert!(manager
+            .limits
+            .check(other_camera.into_iter(), &other_camera[0])
+            .is_ok());
+        let total = RecordingLimits {
+            per_camera: 0,
+            total: 3,
+        };
+        assert!(total
+            .check(std::iter::repeat(camera_id).take(3), &other_camera[0])
+            .is_err());
+
+        for pipeline in pipelines {
+            pipeline.set_state(gst::State::Null)?;
+        }
+        Ok(())
+    }
+
+    // An audio event on a stream already recording motion reuses that recording
+    #[tokio::test]
+    async fn test_overlapping_events_share_a_recording() -> Result<()> {
+        let pool = Arc::new(PgPool::connect_lazy("postgres://localhost/unused")?);
+        let stream_manager = Arc::new(StreamManager::new(pool.clone()));
+        let recordings_dir =
+            std::env::temp_dir().join(format!("g-streamer-test-{}", Uuid::new_v4()));
+        let manager = RecordingManager::new(pool, stream_manager, &recordings_dir, 2, "mp4");
+
+        let stream = Stream {
+            id: Uuid::new_v4(),
+            camera_id: Uuid::new_v4(),
+            ..Default::default()
+        };
+        let motion = Uuid::new_v4();
+        let pipeline = manager
+            .insert_test_event_recording(
+                motion,
+                stream.camera_id,
+                stream.id,
+                RecordingEventType::Motion,
+            )
+            .await?;
+
+        let recording_id = manager
+            .start_event_recording(&stream, RecordingEventType::Audio)
+            .await?;
+        assert_eq!(recording_id, motion);
+        assert_eq!(
+            manager.active_recording_ids().await,
+            HashSet::from([motion])
+        );
+
+        pipeline.set_state(gst::State::Null)?;
+        Ok(())
+    }
+
+    // Motion starts a recording, flapping off and on within the debounce window keeps that
+    // one recording going, and it stops once the window passes without the event coming back
+    #[tokio::test]
+    async fn test_flapping_motion_keeps_one_recording() -> Result<()> {
+        let (Ok(database_url), Ok(stream_id), Ok(rtsp_url)) = (
+            std::env::var("TEST_DATABASE_URL"),
+            std::env::var("TEST_STREAM_ID"),
+            std::env::var("TEST_RTSP_URL"),
+        ) else {
+            println!(
+                "Skipping event debounce test. Set TEST_DATABASE_URL, TEST_STREAM_ID and TEST_RTSP_URL to run."
+            );
+            return Ok(());
+        };
+
+        let pool = Arc::new(PgPool::connect(&database_url).await?);
+        let stream = crate::db::repositories::cameras::CamerasRepository::new(pool.clone())
+            .get_stream_by_id(&Uuid::parse_str(&stream_id)?)
+            .await?
+            .ok_or_else(|| anyhow!("Stream {} not found", stream_id))?;
+
+        let stream_manager = Arc::new(StreamManager::new(pool.clone()));
+        stream_manager.add_stream(
+            crate::stream_manager::StreamSource {
+                stream_type: stream.stream_type,
+                uri: rtsp_url,
+                name: stream.name.clone(),
+                description: None,
+            },
+            stream.id.to_string(),
+        )?;
+
+        let recordings_dir =
+            std::env::temp_dir().join(format!("g-streamer-test-{}", Uuid::new_v4()));
+        let manager =
+            RecordingManager::new(pool, stream_manager.clone(), &recordings_dir, 2, "mp4")
+                .with_event_debounce(Duration::from_millis(300));
+
+        // The event starts the recording
+        manager
+            .register_event(&stream.id, RecordingEventType::Motion)
+            .await?;
+        let started = manager.active_recording_ids().await;
+        assert_eq!(started.len(), 1);
+
+        // Stop and start again within milliseconds
+        manager
+            .event_completed(&stream.id, RecordingEventType::Motion)
+            .await?;
+        sleep(Duration::from_millis(10)).await;
+        manager
+            .register_event(&stream.id, RecordingEventType::Motion)
+            .await?;
+
+        sleep(Duration::from_millis(600)).await;
+        assert_eq!(manager.active_recording_ids().await, started);
+
+        // The event ends for good
+        manager
+            .event_completed(&stream.id, RecordingEventType::Motion)
+            .await?;
+        for _ in 0..50 {
+            if manager.active_recording_ids().await.is_empty() {
+                break;
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+        let stopped = manager.active_recording_ids().await.is_empty();
+
+        manager.stop_all_recordings().await?;
+        stream_manager.stop_all_streams();
+        let _ = std::fs::remove_dir_all(&recordings_dir);
+        assert!(stopped);
+        Ok(())
+    }
+
+    // A continuous recording that was never finalized (the process died) should be
+    // picked up from the end of its last segment, and resuming it is a gap
+    #[tokio::test]
+    async fn test_restart_resumes_from_the_last_segment() -> Result<()> {
+        let (Ok(database_url), Ok(stream_id)) = (
+            std::env::var("TEST_DATABASE_URL"),
+            std::env::var("TEST_STREAM_ID"),
+        ) else {
+            println!(
+                "Skipping recording resume test. Set TEST_DATABASE_URL and TEST_STREAM_ID to run."
+            );
+            return Ok(());
+        };
+
+        let pool = Arc::new(PgPool::connect(&database_url).await?);
+        let stream = crate::db::repositories::cameras::CamerasRepository::new(pool.clone())
+            .get_stream_by_id(&Uuid::parse_str(&stream_id)?)
+            .await?
+            .ok_or_else(|| anyhow!("Stream {} not found", stream_id))?;
+
+        let schedules_repo =
+            crate::db::repositories::schedules::SchedulesRepository::new(pool.clone());
+        let schedule = schedules_repo
+            .create(&RecordingSchedule {
+                id: Uuid::new_v4(),
+                camera_id: stream.camera_id,
+                stream_id: stream.id,
+                name: "resume-test".to_string(),
+                enabled: false,
+                days_of_week: (0..7).collect(),
+                start_time: "00:00".to_string(),
+                end_time: "23:59".to_string(),
+                created_at: Utc::now(),
+                updated_at: Utc::now(),
+                retention_days: 1,
+                record_on_motion: false,
+                record_on_audio: false,
+                record_on_analytics: false,
+                record_on_external: false,
+                continuous_recording: true,
+                timezone: "UTC".to_string(),
+            })
+            .await?;
+
+        let recording = |start_time: DateTime<Utc>, parent_recording_id: Option<Uuid>| Recording {
+            id: Uuid::new_v4(),
+            camera_id: stream.camera_id,
+            stream_id: stream.id,
+            start_time,
+            end_time: None,
+            file_path: PathBuf::from("/tmp/resume-test"),
+            file_size: 0,
+            duration: 60,
+            format: "mp4".to_string(),
+            resolution: "1280x720".to_string(),
+            fps: 25,
+            event_type: RecordingEventType::Continuous,
+            metadata: None,
+            schedule_id: Some(schedule.id),
+            segment_id: parent_recording_id.map(|_| 0),
+            parent_recording_id,
+        };
+        let now = Utc::now();
+        let manager = RecordingManager::new(
+            pool.clone(),
+            Arc::new(StreamManager::new(pool.clone())),
+            &std::env::temp_dir(),
+            60,
+            "mp4",
+        );
+        let parent = manager
+            .recordings_repo
+            .create(&recording(now - chrono::Duration::minutes(10), None))
+            .await?;
+        let segment = manager
+            .recordings_repo
+            .create(&recording(now - chrono::Duration::minutes(5), Some(parent.id)))
+            .await?;
+
+        let previous = manager
+            .last_continuous_recording(&schedule.id, &stream.id)
+            .await?;
+
+        manager.recordings_repo.delete_with_segments(&parent.id).await?;
+        schedules_repo.delete(&schedule.id).await?;
+
+        let (previous_id, stopped_at) =
+            previous.ok_or_else(|| anyhow!("No previous recording found"))?;
+        assert_eq!(previous_id, parent.id);
+        assert_eq!(
+            stopped_at.timestamp(),
+            (segment.start_time + chrono::Duration::seconds(60)).timestamp()
+        );
+        assert!(schedule.interrupted_between(stopped_at, Utc::now()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_two_managers_contend_for_a_stream_lease() -> Result<()> {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else {
+            println!("Skipping recording lease test. Set TEST_DATABASE_URL to run.");
+            return Ok(());
+        };
+
+        let pool = Arc::new(PgPool::connect(&database_url).await?);
+        let (camera_id, stream_id) = insert_camera_and_stream(&*pool).await?;
+        let stream = Stream {
+            id: stream_id,
+            camera_id,
+            name: "main".to_string(),
+            ..Default::default()
+        };
+
+        // Two instances sharing the database
+        let stream_manager = Arc::new(StreamManager::new(pool.clone()));
+        let recordings_dir =
+            std::env::temp_dir().join(format!("g-streamer-test-{}", Uuid::new_v4()));
+        let first = RecordingManager::new(
+            pool.clone(),
+            stream_manager.clone(),
+            &recordings_dir,
+            2,
+            "mp4",
+        );
+        let second = RecordingManager::new(pool.clone(), stream_manager, &recordings_dir, 2, "mp4");
+        assert!(
+            first
+                .leases
+                .acquire(&stream.id, &first.instance_id, first.lease_ttl)
+                .await?
+        );
+
+        // The second instance is refused before it touches the pipeline, and doesn't
+        // disturb the first one's lease
+        let refused = second.start_manual_recording(&stream).await.unwrap_err();
+        assert!(
+            matches!(
+                refused.downcast_ref::<Error>(),
+                Some(Error::AlreadyExists(_))
+            ),
+            "{}",
+            refused
+        );
+        assert_eq!(
+            first
+                .leases
+                .renew(&first.instance_id, first.lease_ttl)
+                .await?,
+            [stream.id]
+        );
+
+        // The first instance dies: its lease runs out and the second takes over
+        sqlx::query("UPDATE recording_leases SET expires_at = now() - interval '1 second' WHERE stream_id = $1")
+            .bind(stream.id)
+            .execute(&*pool)
+            .await?;
+        assert!(
+            second
+                .leases
+                .acquire(&stream.id, &second.instance_id, second.lease_ttl)
+                .await?
+        );
+        assert!(
+            !first
+                .leases
+                .acquire(&stream.id, &first.instance_id, first.lease_ttl)
+                .await?
+        );
+        assert!(first
+            .leases
+            .renew(&first.instance_id, first.lease_ttl)
+            .await?
+            .is_empty());
+
+        sqlx::query("DELETE FROM cameras WHERE id = $1")
+            .bind(stream.camera_id)
+            .execute(&*pool)
+            .await?;
+        Ok(())
+    }
+}
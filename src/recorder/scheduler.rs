@@ -2,18 +2,57 @@ use crate::db::repositories::cameras::CamerasRepository;
 use crate::db::repositories::schedules::SchedulesRepository;
 use crate::recorder::record::RecordingManager;
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use log::{error, info, warn};
+use rand::Rng;
+use serde::Serialize;
 use sqlx::PgPool;
 use std::collections::HashMap;
-use std::sync::Arc;
-use tokio::time::{interval, Duration};
+use std::sync::{Arc, Mutex};
+use tokio::time::{interval_at, Duration, Instant};
+use uuid::Uuid;
+
+/// Where one enabled schedule stands right now
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduleStatus {
+    pub schedule_id: Uuid,
+    pub camera_id: Uuid,
+    pub stream_id: Uuid,
+    pub name: String,
+    /// The current time falls inside the schedule's window
+    pub in_window: bool,
+    /// A recording is running for this schedule
+    pub recording: bool,
+    /// When the schedule next starts (if outside its window) or stops (if inside)
+    pub next_transition: Option<DateTime<Utc>>,
+}
+
+/// Snapshot of the scheduler for diagnostics
+#[derive(Debug, Clone, Serialize)]
+pub struct SchedulerStatus {
+    /// When the scheduler last checked schedules; a stale value means it has stalled
+    pub last_tick: Option<DateTime<Utc>>,
+    pub check_interval_secs: u64,
+    pub schedules: Vec<ScheduleStatus>,
+}
+
+/// Random delay of up to a tenth of the check interval, capped at 5 seconds
+fn tick_jitter(check_interval: Duration) -> Duration {
+    let max_ms = (check_interval / 10).min(Duration::from_secs(5)).as_millis() as u64;
+    if max_ms == 0 {
+        return Duration::ZERO;
+    }
+    Duration::from_millis(rand::thread_rng().gen_range(0..max_ms))
+}
 
 /// Manages recording schedules and starts/stops recordings based on schedule times
 pub struct RecordingScheduler {
     schedules_repo: SchedulesRepository,
     cameras_repo: CamerasRepository,
     recording_manager: Arc<RecordingManager>,
-    check_interval: Duration,
+    /// Changed on config reload; the running loop picks it up after its next tick
+    check_interval: Mutex<Duration>,
+    last_tick: Mutex<Option<DateTime<Utc>>>,
 }
 
 impl RecordingScheduler {
@@ -28,7 +67,8 @@ impl RecordingScheduler {
             schedules_repo: SchedulesRepository::new(db_pool.clone()),
             cameras_repo: CamerasRepository::new(db_pool.clone()),
             recording_manager,
-            check_interval: Duration::from_secs(check_interval_secs),
+            check_interval: Mutex::new(Duration::from_secs(check_interval_secs)),
+            last_tick: Mutex::new(None),
         }
     }
 
@@ -38,10 +78,29 @@ impl RecordingScheduler {
 
         // Create task to periodically check schedules
         tokio::spawn(async move {
-            let mut interval = interval(self.check_interval);
+            // Resume continuous recordings straight away after a restart instead of
+            // leaving a gap until the first jittered tick
+            *self.last_tick.lock().unwrap() = Some(Utc::now());
+            if let Err(e) = self.process_schedules().await {
+                error!("Error resuming recording schedules: {}", e);
+            }
+
+            let mut period = self.check_interval();
+            let mut interval = interval_at(Instant::now() + period, period);
 
             loop {
                 interval.tick().await;
+                *self.last_tick.lock().unwrap() = Some(Utc::now());
+
+                let configured = self.check_interval();
+                if configured != period {
+                    period = configured;
+                    interval = interval_at(Instant::now() + period, period);
+                }
+
+                // Spread the queries out so many schedulers (or many schedules on the same
+                // minute boundary) don't all hit the database at the same instant
+                tokio::time::sleep(tick_jitter(period)).await;
 
                 if let Err(e) = self.process_schedules().await {
                     error!("Error processing recording schedules: {}", e);
@@ -52,6 +111,19 @@ impl RecordingScheduler {
         Ok(())
     }
 
+    fn check_interval(&self) -> Duration {
+        *self.check_interval.lock().unwrap()
+    }
+
+    /// Change how often schedules are checked
+    pub fn set_check_interval(&self, check_interval_secs: u64) {
+        info!(
+            "Recording scheduler check interval set to {} seconds",
+            check_interval_secs
+        );
+        *self.check_interval.lock().unwrap() = Duration::from_secs(check_interval_secs);
+    }
+
     /// Process recording schedules
     async fn process_schedules(&self) -> Result<()> {
         // info!("Processing recording schedules");
@@ -196,6 +268,35 @@ impl RecordingScheduler {
         Ok(())
     }
 
+    /// Report each enabled schedule's window, recording state and next transition
+    pub async fn status(&self) -> Result<SchedulerStatus> {
+        let now = Utc::now();
+        let mut schedules = Vec::new();
+
+        for schedule in self.schedules_repo.get_all_enabled().await? {
+            let recording = self
+                .recording_manager
+                .is_recording_active(&schedule.id, &schedule.stream_id)
+                .await;
+
+            schedules.push(ScheduleStatus {
+                schedule_id: schedule.id,
+                camera_id: schedule.camera_id,
+                stream_id: schedule.stream_id,
+                in_window: schedule.is_active_at(now),
+                recording,
+                next_transition: schedule.next_transition(now),
+                name: schedule.name,
+            });
+        }
+
+        Ok(SchedulerStatus {
+            last_tick: *self.last_tick.lock().unwrap(),
+            check_interval_secs: self.check_interval().as_secs(),
+            schedules,
+        })
+    }
+
     /// Properly shut down the scheduler and stop all recordings
     pub async fn shutdown(&self) -> Result<()> {
         info!("Shutting down recording scheduler");
@@ -0,0 +1,229 @@
+use crate::db::models::recording_models::Recording;
+use crate::db::repositories::recordings::RecordingsRepository;
+use crate::metrics;
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{debug, error, warn};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::runtime::Handle;
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+/// Segment rows queued for the writer task before `SegmentWriter::submit` has to wait
+pub const SEGMENT_QUEUE_CAPACITY: usize = 100;
+
+/// How long `submit` waits for room in a full queue before writing the row itself
+const SEGMENT_QUEUE_WAIT: Duration = Duration::from_secs(1);
+
+/// Where segment rows end up; the recordings table outside of tests
+#[async_trait]
+pub trait SegmentStore: Send + Sync + 'static {
+    async fn create_segment(&self, segment: &Recording) -> Result<()>;
+}
+
+#[async_trait]
+impl SegmentStore for RecordingsRepository {
+    async fn create_segment(&self, segment: &Recording) -> Result<()> {
+        self.create(segment).await.map(|_| ())
+    }
+}
+
+/// Writes the rows of a recording's segments from a task of its own, so splitmuxsink's
+/// `format-location-full` handler never waits on the database.
+///
+/// A missing segment row makes playback skip that footage, so rows aren't dropped when the
+/// database falls behind: a full queue makes `submit` wait a moment for room, then write
+/// the row synchronously.
+pub struct SegmentWriter<S: SegmentStore> {
+    tx: mpsc::Sender<(Recording, u32)>,
+    store: Arc<S>,
+    runtime: Handle,
+}
+
+impl<S: SegmentStore> SegmentWriter<S> {
+    /// Start the writer task on the current runtime
+    pub fn spawn(store: S, capacity: usize) -> Self {
+        let store = Arc::new(store);
+        let (tx, mut rx) = mpsc::channel::<(Recording, u32)>(capacity);
+
+        let worker_store = store.clone();
+        tokio::spawn(async move {
+            while let Some((segment, fragment_id)) = rx.recv().await {
+                write(&*worker_store, &segment, fragment_id).await;
+            }
+        });
+
+        Self {
+            tx,
+            store,
+            runtime: Handle::current(),
+        }
+    }
+
+    /// Queue the row of segment `fragment_id`. While the queue is full this blocks the
+    /// calling thread, normally a GStreamer streaming thread; on a runtime thread the row
+    /// is written from a task of its own instead.
+    pub fn submit(&self, segment: Recording, fragment_id: u32) {
+        let mut item = match self.tx.try_send((segment, fragment_id)) {
+            Ok(()) => return,
+            Err(TrySendError::Full(item)) => item,
+            Err(TrySendError::Closed((segment, fragment_id))) => {
+                // The writer task is gone with its runtime; nothing left to write with
+                error!(
+                    "Segment writer stopped, dropping DB entry for segment {} (frag_id {})",
+                    segment.id, fragment_id
+                );
+                metrics::SEGMENT_ROWS_DROPPED.inc();
+                return;
+            }
+        };
+
+        metrics::SEGMENT_QUEUE_FULL.inc();
+        if Handle::try_current().is_ok() {
+            // Called from a runtime thread after all, which must not block: write the row
+            // from a task of its own instead
+            let (segment, fragment_id) = item;
+            let store = self.store.clone();
+            self.runtime
+                .spawn(async move { write(&*store, &segment, fragment_id).await });
+            return;
+        }
+
+        warn!(
+            "Segment DB queue is full, waiting to queue segment {} (frag_id {})",
+            item.0.id, item.1
+        );
+        let deadline = Instant::now() + SEGMENT_QUEUE_WAIT;
+        while Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+            item = match self.tx.try_send(item) {
+                Ok(()) => return,
+                Err(TrySendError::Full(item)) | Err(TrySendError::Closed(item)) => item,
+            };
+        }
+
+        let (segment, fragment_id) = item;
+        warn!(
+            "Segment DB queue still full, writing segment {} (frag_id {}) directly",
+            segment.id, fragment_id
+        );
+        self.runtime
+            .block_on(write(&*self.store, &segment, fragment_id));
+    }
+}
+
+async fn write<S: SegmentStore>(store: &S, segment: &Recording, fragment_id: u32) {
+    match store.create_segment(segment).await {
+        Ok(()) => debug!(
+            "Successfully created DB entry for segment {} (frag_id {})",
+            segment.id, fragment_id
+        ),
+        Err(e) => {
+            metrics::SEGMENT_ROWS_DROPPED.inc();
+            error!(
+                "Failed to create DB entry for segment {} (frag_id {}): {}",
+                segment.id, fragment_id, e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// A database taking `delay` for every insert
+    struct SlowStore {
+        delay: Duration,
+        rows: Mutex<Vec<u32>>,
+    }
+
+    #[async_trait]
+    impl SegmentStore for Arc<SlowStore> {
+        async fn create_segment(&self, segment: &Recording) -> Result<()> {
+            tokio::time::sleep(self.delay).await;
+            self.rows.lock().unwrap().push(segment.segment_id.unwrap());
+            Ok(())
+        }
+    }
+
+    fn segment(fragment_id: u32) -> Recording {
+        Recording {
+            id: uuid::Uuid::new_v4(),
+            camera_id: uuid::Uuid::nil(),
+            stream_id: uuid::Uuid::nil(),
+            start_time: chrono::Utc::now(),
+            end_time: None,
+            file_path: format!("segment_{:05}.mp4", fragment_id).into(),
+            file_size: 0,
+            duration: 2,
+            format: "mp4".to_string(),
+            resolution: "1280x720".to_string(),
+            fps: 25,
+            event_type: Default::default(),
+            metadata: None,
+            schedule_id: None,
+            segment_id: Some(fragment_id),
+            parent_recording_id: None,
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn slow_database_loses_no_segment_rows() {
+        let store = Arc::new(SlowStore {
+            delay: Duration::from_millis(20),
+            rows: Mutex::new(Vec::new()),
+        });
+        let writer = Arc::new(SegmentWriter::spawn(store.clone(), 2));
+        let full_before = metrics::SEGMENT_QUEUE_FULL.get();
+
+        // Segments close far faster than the database takes them, from a streaming thread
+        let submitter = writer.clone();
+        let streaming_thread = std::thread::spawn(move || {
+            for fragment_id in 0..20 {
+                submitter.submit(segment(fragment_id), fragment_id);
+            }
+        });
+        tokio::task::block_in_place(|| streaming_thread.join()).unwrap();
+
+        // Waiting for room kept the thread until all but the queued rows were written
+        assert!(store.rows.lock().unwrap().len() >= 20 - 2 - 1);
+
+        for _ in 0..100 {
+            if store.rows.lock().unwrap().len() == 20 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let mut rows = store.rows.lock().unwrap().clone();
+        rows.sort_unstable();
+        assert_eq!(rows, (0..20).collect::<Vec<_>>());
+        assert!(metrics::SEGMENT_QUEUE_FULL.get() > full_before);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn stalled_database_gets_the_row_written_synchronously() {
+        let store = Arc::new(SlowStore {
+            delay: SEGMENT_QUEUE_WAIT + Duration::from_millis(500),
+            rows: Mutex::new(Vec::new()),
+        });
+        let writer = Arc::new(SegmentWriter::spawn(store.clone(), 1));
+
+        // The writer task is stuck on segment 0 with segment 1 queued, so segment 2 outlasts
+        // the wait for room and is written by the streaming thread itself
+        let submitter = writer.clone();
+        let rows = store.clone();
+        let streaming_thread = std::thread::spawn(move || {
+            submitter.submit(segment(0), 0);
+            std::thread::sleep(Duration::from_millis(50));
+            submitter.submit(segment(1), 1);
+            submitter.submit(segment(2), 2);
+            rows.rows.lock().unwrap().clone()
+        });
+        let written = tokio::task::block_in_place(|| streaming_thread.join()).unwrap();
+
+        assert!(written.contains(&2));
+        assert!(!written.contains(&1));
+    }
+}
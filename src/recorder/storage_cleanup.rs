@@ -1,20 +1,43 @@
 use crate::config::StorageCleanupConfig;
+use crate::db::models::recording_models::{Recording, RecordingUpdate};
+use crate::db::repositories::cameras::CamerasRepository;
 use crate::db::repositories::recordings::RecordingsRepository;
+use crate::error::Error;
 use crate::messaging::broker::MessageBrokerTrait;
+use crate::recorder::archiver::{archived_key, RecordingArchiver};
+use crate::recorder::record::RecordingManager;
+use crate::storage::{LocalObjectStore, ObjectStore};
 use anyhow::{anyhow, Result};
-use chrono::Utc;
-use log::{error, info, warn};
+use log::{debug, error, info, warn};
+use std::collections::HashSet;
 use std::path::Path;
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use tokio::time::{interval, Duration};
+use tokio::time::{interval, interval_at, Duration, Instant};
+use uuid::Uuid;
+
+/// Recordings moved to the tier target per cleanup pass at most
+const TIER_BATCH_SIZE: i64 = 1000;
+
+/// Where the tiering pass moves old recordings
+enum Tier<'a> {
+    /// The `tier_target` directory
+    Directory(&'a Path, LocalObjectStore),
+    /// The archive store, where they become archived recordings
+    Archive(&'a RecordingArchiver),
+}
 
 /// Storage cleanup service for managing recording retention
 pub struct StorageCleanupService {
-    config: StorageCleanupConfig,
+    /// Swapped on config reload; each cleanup pass reads the current value
+    config: std::sync::RwLock<StorageCleanupConfig>,
     recordings_repo: RecordingsRepository,
     recordings_path: Arc<Path>,
     message_broker: Arc<Mutex<Option<Arc<crate::messaging::MessageBroker>>>>,
+    recording_manager: Option<Arc<RecordingManager>>,
+    archiver: Option<Arc<RecordingArchiver>>,
+    /// Reports the usage of the filesystem holding the recordings
+    disk_usage: fn(&Path) -> Result<DiskUsage>,
 }
 
 impl StorageCleanupService {
@@ -25,13 +48,51 @@ impl StorageCleanupService {
         recordings_path: &Path,
     ) -> Self {
         Self {
-            config,
+            config: std::sync::RwLock::new(config),
             recordings_repo,
             recordings_path: Arc::from(recordings_path),
             message_broker: Arc::new(Mutex::new(None)),
+            recording_manager: None,
+            archiver: None,
+            disk_usage: get_disk_usage,
         }
     }
 
+    /// Measure disk usage with `probe` instead of asking the filesystem
+    pub(crate) fn with_disk_usage(mut self, probe: fn(&Path) -> Result<DiskUsage>) -> Self {
+        self.disk_usage = probe;
+        self
+    }
+
+    /// Use the recording manager to avoid deleting recordings that are still in progress
+    pub fn with_recording_manager(mut self, recording_manager: Arc<RecordingManager>) -> Self {
+        self.recording_manager = Some(recording_manager);
+        self
+    }
+
+    /// Delete archived recordings' objects from the archive store along with their rows
+    pub fn with_archiver(mut self, archiver: Arc<RecordingArchiver>) -> Self {
+        self.archiver = Some(archiver);
+        self
+    }
+
+    /// Current cleanup settings
+    pub fn config(&self) -> StorageCleanupConfig {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Replace the cleanup settings; they take effect from the next check
+    pub fn update_config(&self, config: StorageCleanupConfig) {
+        info!(
+            "Storage cleanup settings updated (enabled: {}, retention: {} days, disk threshold: {}%, interval: {}s)",
+            config.enabled,
+            config.max_retention_days,
+            config.max_disk_usage_percent,
+            config.check_interval_secs
+        );
+        *self.config.write().unwrap() = config;
+    }
+
     /// Set message broker for event publishing
     pub async fn set_message_broker(
         &self,
@@ -55,26 +116,39 @@ impl StorageCleanupService {
         Ok(())
     }
 
-    /// Start the cleanup service in the background
+    /// Start the cleanup service in the background.
+    ///
+    /// The task keeps running while cleanup is disabled so a config reload can enable it.
     pub async fn start(self: Arc<Self>) -> Result<()> {
-        if !self.config.enabled {
+        let config = self.config();
+        if config.enabled {
+            info!(
+                "Starting storage cleanup service with interval of {} seconds",
+                config.check_interval_secs
+            );
+        } else {
             info!("Storage cleanup service is disabled");
-            return Ok(());
         }
 
-        info!(
-            "Starting storage cleanup service with interval of {} seconds",
-            self.config.check_interval_secs
-        );
-
         // Create task to periodically check storage
         tokio::spawn(async move {
-            let mut interval = interval(Duration::from_secs(self.config.check_interval_secs));
+            let mut period = Duration::from_secs(config.check_interval_secs);
+            let mut interval = interval(period);
 
             loop {
                 interval.tick().await;
 
-                if let Err(e) = self.run_cleanup().await {
+                let config = self.config();
+                let configured = Duration::from_secs(config.check_interval_secs);
+                if configured != period {
+                    period = configured;
+                    interval = interval_at(Instant::now() + period, period);
+                }
+                if !config.enabled {
+                    continue;
+                }
+
+                if let Err(e) = self.run_cleanup(&config).await {
                     error!("Error running storage cleanup: {}", e);
                 }
             }
@@ -84,7 +158,7 @@ impl StorageCleanupService {
     }
 
     /// Run the cleanup process
-    async fn run_cleanup(&self) -> Result<()> {
+    async fn run_cleanup(&self, config: &StorageCleanupConfig) -> Result<()> {
         info!("Running storage cleanup process");
 
         // Publish cleanup started event
@@ -101,16 +175,20 @@ impl StorageCleanupService {
             }
         }
 
-        // First check age-based retention
-        let age_cleanup_count = self.cleanup_by_age().await?;
+        // Old recordings move to cheaper storage before they are due for deletion
+        let tier_count = self.tier_old_recordings(config, None).await?;
 
-        // Then check storage usage
-        let storage_cleanup_count = if age_cleanup_count == 0 {
-            // Only check storage if we didn't already delete files by age
-            self.cleanup_by_storage_usage().await?
-        } else {
-            0
-        };
+        // Then check age-based retention
+        let age_cleanup_count = self.cleanup_by_age(config, None).await?;
+
+        // Then check storage usage; age cleanup alone may not bring the disk under the threshold
+        let storage_cleanup_count = self.cleanup_by_storage_usage(config, None).await?;
+
+        // Segment rows can outlive their parent (e.g. a parent deleted by hand)
+        let orphan_cleanup_count = self.cleanup_orphaned_segments(None).await?;
+
+        // Deleted cameras and recordings stay restorable for the grace period
+        let purge_count = self.purge_soft_deleted(config).await?;
 
         // Publish cleanup completed event
         if let Some(broker) = self.message_broker.lock().await.as_ref() {
@@ -119,9 +197,12 @@ impl StorageCleanupService {
                     crate::messaging::EventType::StorageCleanupCompleted,
                     None,
                     serde_json::json!({
+                        "tiered_recordings": tier_count,
                         "age_based_deletions": age_cleanup_count,
                         "storage_based_deletions": storage_cleanup_count,
-                        "total_deletions": age_cleanup_count + storage_cleanup_count
+                        "orphaned_segment_deletions": orphan_cleanup_count,
+                        "soft_deleted_purges": purge_count,
+                        "total_deletions": age_cleanup_count + storage_cleanup_count + orphan_cleanup_count + purge_count
                     }),
                 )
                 .await
@@ -133,21 +214,139 @@ impl StorageCleanupService {
         Ok(())
     }
 
-    /// Clean up recordings based on age
-    async fn cleanup_by_age(&self) -> Result<u64> {
+    /// Move recordings older than `tier_after_days` off the recordings disk: into
+    /// `tier_target`, keeping their directory layout, or else into the archive store.
+    ///
+    /// A recording's row is pointed at the moved file before the original is removed, so
+    /// it stays playable throughout; a failed move leaves it where it was. `camera_id`
+    /// limits the pass to one camera's recordings.
+    async fn tier_old_recordings(
+        &self,
+        config: &StorageCleanupConfig,
+        camera_id: Option<Uuid>,
+    ) -> Result<u64> {
+        let tier = match (config.tier_target(), &self.archiver) {
+            (Some(target), _) => Tier::Directory(target, LocalObjectStore::new(target)),
+            (None, Some(archiver)) if config.tiers_to_archive() => Tier::Archive(archiver),
+            _ => return Ok(0),
+        };
+
+        // With the trailing separator a sibling such as `/mnt/tier2` isn't taken for `/mnt/tier`
+        let tier_prefix = match &tier {
+            Tier::Directory(target, _) => Some(target.join("").to_string_lossy().into_owned()),
+            Tier::Archive(_) => None,
+        };
+        let older_than = chrono::Utc::now() - chrono::Duration::days(config.tier_after_days as i64);
+        let recordings = self
+            .recordings_repo
+            .get_recordings_to_tier(
+                older_than,
+                tier_prefix.as_deref(),
+                camera_id,
+                TIER_BATCH_SIZE,
+            )
+            .await?;
+        if recordings.is_empty() {
+            return Ok(0);
+        }
+
+        let active = match &self.recording_manager {
+            Some(manager) => manager.active_recording_ids().await,
+            None => HashSet::new(),
+        };
+
+        let destination = match &tier {
+            Tier::Directory(target, _) => target.display().to_string(),
+            Tier::Archive(_) => "the archive store".to_string(),
+        };
+        let mut tier_count = 0;
+        for recording in &recordings {
+            let in_progress = active.contains(&recording.id)
+                || recording
+                    .parent_recording_id
+                    .is_some_and(|parent| active.contains(&parent));
+            if in_progress || !recording.file_path.is_file() {
+                continue;
+            }
+
+            let moved = match &tier {
+                Tier::Directory(_, store) => self.tier_recording(store, recording).await,
+                Tier::Archive(archiver) => archiver.archive(recording).await,
+            };
+            match moved {
+                Ok(()) => tier_count += 1,
+                Err(e) => warn!(
+                    "Failed to move recording {} to {}: {}",
+                    recording.id, destination, e
+                ),
+            }
+        }
+
+        if tier_count > 0 {
+            info!("Moved {} recordings to {}", tier_count, destination);
+        }
+        Ok(tier_count)
+    }
+
+    /// Copy a recording's file into `tier`, point its row at the copy and remove the original
+    async fn tier_recording(&self, tier: &dyn ObjectStore, recording: &Recording) -> Result<()> {
+        let key = recording
+            .file_path
+            .strip_prefix(&*self.recordings_path)
+            .map_err(|_| {
+                Error::InvalidInput(format!(
+                    "{} is outside the recordings directory",
+                    recording.file_path.display()
+                ))
+            })?
+            .to_string_lossy()
+            .into_owned();
+        tier.put(&key, &recording.file_path).await?;
+
+        let update = RecordingUpdate {
+            file_path: Some(tier.location(&key)),
+            duration: None,
+            file_size: None,
+            end_time: None,
+            metadata: None,
+            segment_id: None,
+            parent_recording_id: None,
+            resolution: None,
+            fps: None,
+        };
+        if let Err(e) = self
+            .recordings_repo
+            .update_with_data(&recording.id, update)
+            .await
+        {
+            let _ = tier.delete(&key).await;
+            return Err(e);
+        }
+
+        remove_recording_file(&recording.file_path);
+        Ok(())
+    }
+
+    /// Clean up recordings based on age.
+    ///
+    /// Each recording is kept for its schedule's retention, else its camera's, else the
+    /// configured `max_retention_days` (`delete_after_days` while tiering is enabled).
+    /// `camera_id` limits the pass to one camera's recordings.
+    async fn cleanup_by_age(
+        &self,
+        config: &StorageCleanupConfig,
+        camera_id: Option<Uuid>,
+    ) -> Result<u64> {
+        let retention_days = config.default_retention_days();
         info!(
-            "Cleaning up recordings older than {} days",
-            self.config.max_retention_days
+            "Cleaning up recordings past their retention (default {} days)",
+            retention_days
         );
 
-        // Calculate cutoff date
-        let cutoff_date =
-            Utc::now() - chrono::Duration::days(self.config.max_retention_days as i64);
-
         // Get recordings to delete
         let recordings = self
             .recordings_repo
-            .get_recordings_to_prune(None, Some(cutoff_date))
+            .get_recordings_past_retention(retention_days, camera_id)
             .await?;
 
         if recordings.is_empty() {
@@ -159,20 +358,9 @@ impl StorageCleanupService {
 
         let mut delete_count = 0;
         for recording in recordings {
-            // Delete the file
-            if let Err(e) = std::fs::remove_file(&recording.file_path) {
-                warn!(
-                    "Failed to delete recording file {}: {}",
-                    recording.file_path.display(),
-                    e
-                );
-            }
-
-            // Delete from database
-            if let Ok(deleted) = self.recordings_repo.delete(&recording.id).await {
-                if deleted {
-                    delete_count += 1;
-                }
+            match self.delete_recording(&recording).await {
+                Ok((rows, _)) => delete_count += rows,
+                Err(e) => warn!("Failed to delete recording {}: {}", recording.id, e),
             }
         }
 
@@ -180,92 +368,107 @@ impl StorageCleanupService {
         Ok(delete_count)
     }
 
-    /// Clean up recordings based on storage usage
-    async fn cleanup_by_storage_usage(&self) -> Result<u64> {
+    /// Clean up recordings based on storage usage.
+    ///
+    /// When the filesystem holding the recordings is above `max_disk_usage_percent`, delete
+    /// recordings oldest-first until usage is 5% under the threshold, skipping recordings in
+    /// progress or from the last `min_retain_recent_hours`, and never going below
+    /// `min_recordings_kept`. `camera_id` limits the pass to one camera's recordings.
+    async fn cleanup_by_storage_usage(
+        &self,
+        config: &StorageCleanupConfig,
+        camera_id: Option<Uuid>,
+    ) -> Result<u64> {
         // Get current disk usage
-        let disk_usage = self.get_disk_usage()?;
+        let disk_usage = (self.disk_usage)(&self.recordings_path)?;
 
         // Check if we need to clean up
-        if disk_usage.percentage < self.config.max_disk_usage_percent as f64 {
+        if disk_usage.percentage < config.max_disk_usage_percent as f64 {
             info!(
-                "Current disk usage is {}%, below threshold of {}%. No cleanup needed.",
-                disk_usage.percentage, self.config.max_disk_usage_percent
+                "Current disk usage is {:.1}%, below threshold of {}%. No cleanup needed.",
+                disk_usage.percentage, config.max_disk_usage_percent
             );
             return Ok(0);
         }
 
         info!(
-            "Current disk usage is {}%, above threshold of {}%. Cleaning up oldest recordings.",
-            disk_usage.percentage, self.config.max_disk_usage_percent
+            "Current disk usage is {:.1}%, above threshold of {}%. Cleaning up oldest recordings.",
+            disk_usage.percentage, config.max_disk_usage_percent
         );
 
-        // Get total recording stats
-        let stats = self.recordings_repo.get_stats(None).await?;
+        // Top-level recordings only: deleting a parent takes its segments with it. Archived
+        // and tiered recordings take up no space here.
+        let tier_target = config.tier_target();
+        let candidates: Vec<Recording> = self
+            .recordings_repo
+            .get_recordings_to_prune(camera_id, None)
+            .await?
+            .into_iter()
+            .filter(|r| r.parent_recording_id.is_none() && archived_key(r).is_none())
+            .filter(|r| !tier_target.is_some_and(|target| r.file_path.starts_with(target)))
+            .collect();
 
-        if stats.total_count == 0 {
-            info!("No recordings found to clean up");
-            return Ok(0);
-        }
+        self.free_space(config, &disk_usage, candidates).await
+    }
 
-        // Calculate how many bytes to free
-        let target_usage = (disk_usage.total_bytes as f64
-            * (self.config.max_disk_usage_percent as f64 - 5.0)
-            / 100.0) as u64;
-        let bytes_to_free = disk_usage.used_bytes.saturating_sub(target_usage);
+    /// Delete `candidates`, oldest first, to bring `disk_usage` 5% under the threshold.
+    ///
+    /// Recordings that ended within `min_retain_recent_hours` are kept whatever the disk
+    /// usage; when the older ones can't free enough, a `StorageLimitReached` event flagged
+    /// `recent_footage_retained` alerts that the disk is full of recent footage.
+    async fn free_space(
+        &self,
+        config: &StorageCleanupConfig,
+        disk_usage: &DiskUsage,
+        candidates: Vec<Recording>,
+    ) -> Result<u64> {
+        let active = match &self.recording_manager {
+            Some(manager) => manager.active_recording_ids().await,
+            None => HashSet::new(),
+        };
 
-        if bytes_to_free == 0 {
-            info!("No need to free disk space");
-            return Ok(0);
-        }
+        let retain_after =
+            chrono::Utc::now() - chrono::Duration::hours(config.min_retain_recent_hours as i64);
+        let (candidates, recent): (Vec<Recording>, Vec<Recording>) = candidates
+            .into_iter()
+            .partition(|r| r.end_time.unwrap_or(r.start_time) < retain_after);
 
-        info!(
-            "Need to free approximately {} MB",
-            bytes_to_free / 1024 / 1024
+        let target_percent = (config.max_disk_usage_percent as f64 - 5.0).max(0.0);
+        let to_delete = plan_space_cleanup(
+            &candidates,
+            disk_usage,
+            target_percent,
+            &active,
+            config.min_recordings_kept.saturating_sub(recent.len()),
         );
 
-        // Get oldest recordings first, limited to a reasonable batch size
-        let mut deleted_bytes = 0;
-        let mut delete_count = 0;
-        let batch_size = 100;
-        let mut processed = 0;
-
-        while deleted_bytes < bytes_to_free && processed < 1000 {
-            // Safety limit
-            // Get a batch of oldest recordings
-            let recordings = self
-                .recordings_repo
-                .get_recordings_to_prune(None, None)
-                .await?;
-
-            if recordings.is_empty() {
-                break;
-            }
-
-            for recording in recordings.iter().take(batch_size) {
-                // Delete the file
-                if let Err(e) = std::fs::remove_file(&recording.file_path) {
-                    warn!(
-                        "Failed to delete recording file {}: {}",
-                        recording.file_path.display(),
-                        e
-                    );
-                    continue;
-                }
+        let planned_bytes: u64 = to_delete.iter().map(|r| r.file_size).sum();
+        let recent_footage_retained =
+            !recent.is_empty() && planned_bytes < bytes_to_free(disk_usage, target_percent);
+        if recent_footage_retained {
+            error!(
+                "Disk usage is {:.1}% but {} recordings from the last {} hours are kept; recent footage can't be freed",
+                disk_usage.percentage,
+                recent.len(),
+                config.min_retain_recent_hours
+            );
+        }
 
-                // Delete from database
-                if let Ok(deleted) = self.recordings_repo.delete(&recording.id).await {
-                    if deleted {
-                        deleted_bytes += recording.file_size;
-                        delete_count += 1;
+        if to_delete.is_empty() && !recent_footage_retained {
+            warn!("Disk usage is above threshold but no recordings can be deleted");
+            return Ok(0);
+        }
 
-                        if deleted_bytes >= bytes_to_free {
-                            break;
-                        }
-                    }
+        let mut deleted_bytes = 0;
+        let mut delete_count = 0;
+        for recording in to_delete {
+            match self.delete_recording(recording).await {
+                Ok((rows, bytes)) => {
+                    deleted_bytes += bytes;
+                    delete_count += rows;
                 }
+                Err(e) => warn!("Failed to delete recording {}: {}", recording.id, e),
             }
-
-            processed += batch_size;
         }
 
         info!(
@@ -274,111 +477,714 @@ impl StorageCleanupService {
             deleted_bytes / 1024 / 1024
         );
 
+        if let Some(broker) = self.message_broker.lock().await.as_ref() {
+            if let Err(e) = broker
+                .publish(
+                    crate::messaging::EventType::StorageLimitReached,
+                    None,
+                    serde_json::json!({
+                        "disk_usage_percent": disk_usage.percentage,
+                        "threshold_percent": config.max_disk_usage_percent,
+                        "recordings_deleted": delete_count,
+                        "bytes_freed": deleted_bytes,
+                        "recent_footage_retained": recent_footage_retained,
+                        "recent_recordings": recent.len(),
+                        "min_retain_recent_hours": config.min_retain_recent_hours,
+                    }),
+                )
+                .await
+            {
+                warn!("Failed to publish storage limit event: {}", e);
+            }
+        }
+
         Ok(delete_count)
     }
 
-    /// Get disk usage information
-    fn get_disk_usage(&self) -> Result<DiskUsage> {
-        #[cfg(target_os = "linux")]
+    /// Delete a recording's files and rows.
+    ///
+    /// A parent recording takes its segment files and rows with it, and its directory is
+    /// removed once empty. Returns the number of rows deleted and the bytes freed on disk.
+    async fn delete_recording(&self, recording: &Recording) -> Result<(u64, u64)> {
+        let is_parent = recording.parent_recording_id.is_none() && recording.segment_id.is_none();
+
+        if !is_parent {
+            let freed = self.remove_file(recording).await;
+            let deleted = self.recordings_repo.purge(&recording.id).await?;
+            return Ok((deleted as u64, freed));
+        }
+
+        let mut freed = 0;
+        // Purged parents come with soft-deleted segments
+        for segment in self
+            .recordings_repo
+            .get_segments_with_deleted(&recording.id)
+            .await?
         {
-            let path = self.recordings_path.to_string_lossy().to_string();
-            let out = std::process::Command::new("df")
-                .args(&["--output=size,used,avail", "-k", &path])
-                .output()?;
+            freed += self.remove_file(&segment).await;
+        }
 
-            if !out.status.success() {
-                return Err(anyhow!("Failed to get disk usage"));
+        if recording.file_path.is_dir() {
+            // Other recordings of the same stream and day share the directory
+            if let Err(e) = std::fs::remove_dir(&recording.file_path) {
+                debug!(
+                    "Keeping recording directory {}: {}",
+                    recording.file_path.display(),
+                    e
+                );
             }
+        } else {
+            freed += self.remove_file(recording).await;
+        }
 
-            let output = String::from_utf8_lossy(&out.stdout);
-            let lines: Vec<&str> = output.lines().collect();
+        let deleted = self.recordings_repo.delete_with_segments(&recording.id).await?;
+        Ok((deleted, freed))
+    }
 
-            if lines.len() < 2 {
-                return Err(anyhow!("Invalid df output"));
+    /// Remove a recording's file, or its object when it's archived. Returns the bytes freed
+    /// on the recordings disk.
+    async fn remove_file(&self, recording: &Recording) -> u64 {
+        if archived_key(recording).is_none() {
+            return remove_recording_file(&recording.file_path);
+        }
+
+        match &self.archiver {
+            Some(archiver) => {
+                if let Err(e) = archiver.delete(recording).await {
+                    warn!(
+                        "Failed to delete archived recording {}: {}",
+                        recording.id, e
+                    );
+                }
             }
+            None => warn!(
+                "Recording {} is archived but no archive store is configured, leaving {}",
+                recording.id,
+                recording.file_path.display()
+            ),
+        }
+        0
+    }
 
-            let values: Vec<&str> = lines[1].split_whitespace().collect();
-            if values.len() < 3 {
-                return Err(anyhow!("Invalid df output format"));
+    /// Permanently remove recordings and cameras that were soft-deleted more than
+    /// `soft_delete_grace_days` ago, along with their files. A purged camera takes all of
+    /// its recordings with it.
+    async fn purge_soft_deleted(&self, config: &StorageCleanupConfig) -> Result<u64> {
+        let cutoff =
+            chrono::Utc::now() - chrono::Duration::days(config.soft_delete_grace_days as i64);
+        let mut purge_count = 0;
+
+        for recording in self.recordings_repo.get_deleted_before(cutoff).await? {
+            match self.delete_recording(&recording).await {
+                Ok((rows, _)) => purge_count += rows,
+                Err(e) => warn!("Failed to purge recording {}: {}", recording.id, e),
             }
+        }
 
-            let total_kb: u64 = values[0].parse()?;
-            let used_kb: u64 = values[1].parse()?;
+        let cameras_repo = CamerasRepository::new(self.recordings_repo.pool.clone());
+        for camera in cameras_repo.get_deleted_before(cutoff).await? {
+            let recordings = self
+                .recordings_repo
+                .get_recordings_to_prune(Some(camera.id), None)
+                .await?;
+            for recording in &recordings {
+                // Segments go with their parent
+                if recording.parent_recording_id.is_some() {
+                    continue;
+                }
+                match self.delete_recording(recording).await {
+                    Ok((rows, _)) => purge_count += rows,
+                    Err(e) => warn!("Failed to purge recording {}: {}", recording.id, e),
+                }
+            }
 
-            let total_bytes = total_kb * 1024;
-            let used_bytes = used_kb * 1024;
-            let percentage = (used_bytes as f64 / total_bytes as f64) * 100.0;
+            match cameras_repo.purge(&camera.id).await {
+                Ok(_) => info!("Purged deleted camera {} ({})", camera.id, camera.name),
+                Err(e) => warn!("Failed to purge camera {}: {}", camera.id, e),
+            }
+        }
 
-            Ok(DiskUsage {
-                total_bytes,
-                used_bytes,
-                percentage,
-            })
+        if purge_count > 0 {
+            info!("Purged {} soft-deleted recordings", purge_count);
         }
+        Ok(purge_count)
+    }
 
-        #[cfg(target_os = "macos")]
-        {
-            let path = self.recordings_path.to_string_lossy().to_string();
-            let out = std::process::Command::new("df")
-                .args(&["-k", &path])
-                .output()?;
+    /// Remove segment rows left behind by deleted parents, along with their files.
+    /// `camera_id` limits the pass to one camera's segments.
+    async fn cleanup_orphaned_segments(&self, camera_id: Option<Uuid>) -> Result<u64> {
+        let orphans = self
+            .recordings_repo
+            .delete_orphaned_segments(camera_id)
+            .await?;
 
-            if !out.status.success() {
-                return Err(anyhow!("Failed to get disk usage"));
-            }
+        for segment in &orphans {
+            self.remove_file(segment).await;
+        }
 
-            let output = String::from_utf8_lossy(&out.stdout);
-            let lines: Vec<&str> = output.lines().collect();
+        if !orphans.is_empty() {
+            info!("Cleaned up {} orphaned segment recordings", orphans.len());
+        }
+        Ok(orphans.len() as u64)
+    }
+}
 
-            if lines.len() < 2 {
-                return Err(anyhow!("Invalid df output"));
-            }
+/// Get disk usage information
+pub(crate) fn get_disk_usage(recordings_path: &Path) -> Result<DiskUsage> {
+    #[cfg(target_os = "linux")]
+    {
+        let path = recordings_path.to_string_lossy().to_string();
+        let out = std::process::Command::new("df")
+            .args(&["--output=size,used,avail", "-k", &path])
+            .output()?;
 
-            let values: Vec<&str> = lines[1].split_whitespace().collect();
-            if values.len() < 5 {
-                return Err(anyhow!("Invalid df output format"));
-            }
+        if !out.status.success() {
+            return Err(anyhow!("Failed to get disk usage"));
+        }
 
-            let total_kb: u64 = values[1].parse()?;
-            let used_kb: u64 = values[2].parse()?;
-            let percentage: f64 = values[4].trim_end_matches('%').parse()?;
+        let output = String::from_utf8_lossy(&out.stdout);
+        let lines: Vec<&str> = output.lines().collect();
 
-            let total_bytes = total_kb * 1024;
-            let used_bytes = used_kb * 1024;
+        if lines.len() < 2 {
+            return Err(anyhow!("Invalid df output"));
+        }
 
-            Ok(DiskUsage {
-                total_bytes,
-                used_bytes,
-                percentage,
-            })
+        let values: Vec<&str> = lines[1].split_whitespace().collect();
+        if values.len() < 3 {
+            return Err(anyhow!("Invalid df output format"));
         }
 
-        #[cfg(target_os = "windows")]
-        {
-            // On Windows, use GetDiskFreeSpaceEx
-            // For simplicity, we'll use a temporary implementation here
-            let total_bytes = 1_000_000_000_000; // 1 TB
-            let used_bytes = 500_000_000_000; // 500 GB
-            let percentage = 50.0;
-
-            Ok(DiskUsage {
-                total_bytes,
-                used_bytes,
-                percentage,
-            })
+        let total_kb: u64 = values[0].parse()?;
+        let used_kb: u64 = values[1].parse()?;
+
+        let total_bytes = total_kb * 1024;
+        let used_bytes = used_kb * 1024;
+        let percentage = (used_bytes as f64 / total_bytes as f64) * 100.0;
+
+        Ok(DiskUsage {
+            total_bytes,
+            used_bytes,
+            percentage,
+        })
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        let path = recordings_path.to_string_lossy().to_string();
+        let out = std::process::Command::new("df")
+            .args(&["-k", &path])
+            .output()?;
+
+        if !out.status.success() {
+            return Err(anyhow!("Failed to get disk usage"));
         }
 
-        #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
-        {
-            Err(anyhow!("Unsupported operating system"))
+        let output = String::from_utf8_lossy(&out.stdout);
+        let lines: Vec<&str> = output.lines().collect();
+
+        if lines.len() < 2 {
+            return Err(anyhow!("Invalid df output"));
+        }
+
+        let values: Vec<&str> = lines[1].split_whitespace().collect();
+        if values.len() < 5 {
+            return Err(anyhow!("Invalid df output format"));
+        }
+
+        let total_kb: u64 = values[1].parse()?;
+        let used_kb: u64 = values[2].parse()?;
+        let percentage: f64 = values[4].trim_end_matches('%').parse()?;
+
+        let total_bytes = total_kb * 1024;
+        let used_bytes = used_kb * 1024;
+
+        Ok(DiskUsage {
+            total_bytes,
+            used_bytes,
+            percentage,
+        })
+    }
+
+    #[cfg(target_os = "windows")]
+    {
+        // On Windows, use GetDiskFreeSpaceEx
+        // For simplicity, we'll use a temporary implementation here
+        let total_bytes = 1_000_000_000_000; // 1 TB
+        let used_bytes = 500_000_000_000; // 500 GB
+        let percentage = 50.0;
+
+        Ok(DiskUsage {
+            total_bytes,
+            used_bytes,
+            percentage,
+        })
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+    {
+        Err(anyhow!("Unsupported operating system"))
+    }
+}
+
+/// Remove a recording file, returning its size if it was removed. A file that is
+/// already gone is not an error.
+fn remove_recording_file(path: &Path) -> u64 {
+    let size = std::fs::metadata(path).map(|m| m.len()).unwrap_or(0);
+
+    match std::fs::remove_file(path) {
+        Ok(()) => size,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+        Err(e) => {
+            warn!("Failed to delete recording file {}: {}", path.display(), e);
+            0
         }
     }
 }
 
+/// Pick the recordings to delete, oldest first, to bring disk usage down to `target_percent`.
+///
+/// `candidates` must be ordered oldest-first. Active recordings are skipped and at least
+/// `min_kept` candidates are always left in place.
+fn plan_space_cleanup<'a>(
+    candidates: &'a [Recording],
+    usage: &DiskUsage,
+    target_percent: f64,
+    active: &HashSet<Uuid>,
+    min_kept: usize,
+) -> Vec<&'a Recording> {
+    let bytes_to_free = bytes_to_free(usage, target_percent);
+
+    let mut planned = Vec::new();
+    let mut planned_bytes = 0;
+    let deletable = candidates.len().saturating_sub(min_kept);
+
+    for recording in candidates {
+        if planned_bytes >= bytes_to_free || planned.len() >= deletable {
+            break;
+        }
+        if active.contains(&recording.id) {
+            continue;
+        }
+        planned_bytes += recording.file_size;
+        planned.push(recording);
+    }
+
+    planned
+}
+
+/// Bytes to delete to bring disk usage down to `target_percent`
+fn bytes_to_free(usage: &DiskUsage, target_percent: f64) -> u64 {
+    let target_bytes = (usage.total_bytes as f64 * target_percent / 100.0) as u64;
+    usage.used_bytes.saturating_sub(target_bytes)
+}
+
 /// Disk usage information
-struct DiskUsage {
-    total_bytes: u64,
-    used_bytes: u64,
-    percentage: f64,
+#[derive(Debug, Clone)]
+pub(crate) struct DiskUsage {
+    pub(crate) total_bytes: u64,
+    pub(crate) used_bytes: u64,
+    pub(crate) percentage: f64,
 }
 
+impl DiskUsage {
+    /// Bytes still available on the volume
+    pub(crate) fn free_bytes(&self) -> u64 {
+        self.total_bytes.saturating_sub(self.used_bytes)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::{plan_space_cleanup, DiskUsage, StorageCleanupService};
+    use crate::db::models::recording_models::{Recording, RecordingEventType};
+    use std::collections::HashSet;
+    use std::path::Path;
+    use crate::config::{BrokerBackend, MessageBrokerConfig, StorageCleanupConfig};
+    use crate::db::models::recording_models::RecordingSearchQuery;
+    use crate::db::repositories::recordings::RecordingsRepository;
+    use crate::db::test_fixtures::insert_camera_and_stream;
+    use crate::messaging::broker::{create_message_broker, MessageBrokerTrait};
+    use crate::messaging::EventType;
+    use anyhow::Result;
+    use chrono::{Duration, Utc};
+    use sqlx::PgPool;
+    use std::sync::Arc;
+    use uuid::Uuid;
+
+    // Insert a camera with its own retention, a stream and a schedule with a longer one.
+    // Returns (camera_id, stream_id, schedule_id).
+    async fn insert_camera_with_schedule(
+        pool: &PgPool,
+        camera_retention_days: i32,
+        schedule_retention_days: i32,
+    ) -> Result<(Uuid, Uuid, Uuid)> {
+        let (camera_id, stream_id) = insert_camera_and_stream(pool).await?;
+        let schedule_id = Uuid::new_v4();
+        let now = Utc::now();
+
+        sqlx::query("UPDATE cameras SET retention_days = $2 WHERE id = $1")
+            .bind(camera_id)
+            .bind(camera_retention_days)
+            .execute(pool)
+            .await?;
+        sqlx::query(
+            "INSERT INTO recording_schedules (id, camera_id, stream_id, name, days_of_week, start_time, end_time, created_at, updated_at, retention_days) VALUES ($1, $2, $3, 'retention-test', '{0,1,2,3,4,5,6}', '00:00', '23:59', $4, $4, $5)",
+        )
+        .bind(schedule_id)
+        .bind(camera_id)
+        .bind(stream_id)
+        .bind(now)
+        .bind(schedule_retention_days)
+        .execute(pool)
+        .await?;
+
+        Ok((camera_id, stream_id, schedule_id))
+    }
+
+    async fn insert_recording(
+        pool: &PgPool,
+        camera_id: Uuid,
+        stream_id: Uuid,
+        schedule_id: Option<Uuid>,
+        age_days: i64,
+    ) -> Result<Uuid> {
+        insert_segment(pool, camera_id, stream_id, schedule_id, age_days, None, None).await
+    }
+
+    async fn insert_segment(
+        pool: &PgPool,
+        camera_id: Uuid,
+        stream_id: Uuid,
+        schedule_id: Option<Uuid>,
+        age_days: i64,
+        parent_recording_id: Option<Uuid>,
+        segment_id: Option<i32>,
+    ) -> Result<Uuid> {
+        let id = Uuid::new_v4();
+        let start_time = Utc::now() - Duration::days(age_days);
+
+        sqlx::query(
+            "INSERT INTO recordings (id, camera_id, stream_id, schedule_id, start_time, end_time, file_path, format, resolution, fps, created_at, parent_recording_id, segment_id) VALUES ($1, $2, $3, $4, $5, $5, '/tmp/retention-test.mp4', 'mp4', '1280x720', 25, $5, $6, $7)",
+        )
+        .bind(id)
+        .bind(camera_id)
+        .bind(stream_id)
+        .bind(schedule_id)
+        .bind(start_time)
+        .bind(parent_recording_id)
+        .bind(segment_id)
+        .execute(pool)
+        .await?;
+
+        Ok(id)
+    }
+
+    // Schedule retention (90d) beats the global default (30d), camera retention (7d)
+    // beats the default for unscheduled recordings.
+    #[tokio::test]
+    async fn test_retention_uses_most_specific_setting() -> Result<()> {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else {
+            println!("Skipping retention test. Set TEST_DATABASE_URL to run.");
+            return Ok(());
+        };
+
+        let pool = PgPool::connect(&database_url).await?;
+        let (camera_id, stream_id, schedule_id) = insert_camera_with_schedule(&pool, 7, 90).await?;
+
+        // Past the 30 day default, within the schedule's 90 days: kept
+        let scheduled = insert_recording(&pool, camera_id, stream_id, Some(schedule_id), 45).await?;
+        // Within the 30 day default, past the camera's 7 days: deleted
+        let unscheduled = insert_recording(&pool, camera_id, stream_id, None, 10).await?;
+
+        let repo = RecordingsRepository::new(Arc::new(pool.clone()));
+        let expired: Vec<Uuid> = repo
+            .get_recordings_past_retention(30, Some(camera_id))
+            .await?
+            .into_iter()
+            .map(|r| r.id)
+            .collect();
+
+        sqlx::query("DELETE FROM cameras WHERE id = $1")
+            .bind(camera_id)
+            .execute(&pool)
+            .await?;
+
+        // Only this camera's recordings are looked at
+        assert!(!expired.contains(&scheduled));
+        assert_eq!(expired, vec![unscheduled]);
+        Ok(())
+    }
+
+    // Cleaning up an expired parent takes its segments with it, and segments whose parent
+    // is already gone are removed as well
+    #[tokio::test]
+    async fn test_cleanup_removes_parent_and_segments() -> Result<()> {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else {
+            println!("Skipping cleanup test. Set TEST_DATABASE_URL to run.");
+            return Ok(());
+        };
+
+        let pool = PgPool::connect(&database_url).await?;
+        let (camera_id, stream_id, _) = insert_camera_with_schedule(&pool, 7, 90).await?;
+
+        let parent = insert_recording(&pool, camera_id, stream_id, None, 10).await?;
+        for segment_id in 0..2 {
+            insert_segment(&pool, camera_id, stream_id, None, 10, Some(parent), Some(segment_id))
+                .await?;
+        }
+        insert_segment(&pool, camera_id, stream_id, None, 1, Some(Uuid::new_v4()), Some(0)).await?;
+
+        let repo = RecordingsRepository::new(Arc::new(pool.clone()));
+        let service = StorageCleanupService::new(
+            StorageCleanupConfig::default(),
+            repo.clone(),
+            &std::env::temp_dir(),
+        );
+        service
+            .cleanup_by_age(&service.config(), Some(camera_id))
+            .await?;
+        service.cleanup_orphaned_segments(Some(camera_id)).await?;
+
+        let remaining = repo
+            .search(&RecordingSearchQuery {
+                camera_ids: Some(vec![camera_id]),
+                ..RecordingSearchQuery::default()
+            })
+            .await?;
+
+        sqlx::query("DELETE FROM cameras WHERE id = $1")
+            .bind(camera_id)
+            .execute(&pool)
+            .await?;
+
+        assert!(remaining.is_empty());
+        Ok(())
+    }
+
+    // With tiering enabled a recording moves to the tier target once it is older than
+    // tier_after_days, stays findable there, and is deleted after delete_after_days
+    #[tokio::test]
+    async fn test_recording_is_tiered_then_deleted() -> Result<()> {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else {
+            println!("Skipping tiering test. Set TEST_DATABASE_URL to run.");
+            return Ok(());
+        };
+
+        let pool = PgPool::connect(&database_url).await?;
+        let (camera_id, stream_id) = insert_camera_and_stream(&pool).await?;
+
+        let root = std::env::temp_dir().join(format!("tier-test-{}", camera_id));
+        let (recordings_dir, tier_dir) = (root.join("recordings"), root.join("tier"));
+        let original = recordings_dir.join("camera/recording.mp4");
+        std::fs::create_dir_all(original.parent().unwrap())?;
+        std::fs::write(&original, b"footage")?;
+
+        let id = insert_recording(&pool, camera_id, stream_id, None, 10).await?;
+        sqlx::query("UPDATE recordings SET file_path = $1 WHERE id = $2")
+            .bind(original.to_string_lossy().to_string())
+            .bind(id)
+            .execute(&pool)
+            .await?;
+
+        let repo = RecordingsRepository::new(Arc::new(pool.clone()));
+        let service = StorageCleanupService::new(
+            StorageCleanupConfig {
+                tier_after_days: 5,
+                tier_target: Some(tier_dir.clone()),
+                delete_after_days: 20,
+                ..StorageCleanupConfig::default()
+            },
+            repo.clone(),
+            &recordings_dir,
+        );
+        service
+            .tier_old_recordings(&service.config(), Some(camera_id))
+            .await?;
+        let tiered = repo.get_by_id(&id).await?.map(|r| r.file_path);
+        let original_exists = original.exists();
+
+        // Past delete_after_days, though still within the 30 day max_retention_days
+        sqlx::query(
+            "UPDATE recordings SET start_time = NOW() - INTERVAL '25 days', end_time = NOW() - INTERVAL '25 days' WHERE id = $1",
+        )
+        .bind(id)
+        .execute(&pool)
+        .await?;
+        service
+            .cleanup_by_age(&service.config(), Some(camera_id))
+            .await?;
+        let remaining = repo
+            .search(&RecordingSearchQuery {
+                camera_ids: Some(vec![camera_id]),
+                ..RecordingSearchQuery::default()
+            })
+            .await?;
+
+        sqlx::query("DELETE FROM cameras WHERE id = $1")
+            .bind(camera_id)
+            .execute(&pool)
+            .await?;
+        let tier_file = tier_dir.join("camera/recording.mp4");
+        let tier_file_exists = tier_file.exists();
+        let _ = std::fs::remove_dir_all(&root);
+
+        assert_eq!(tiered, Some(tier_file));
+        assert!(!original_exists);
+        assert!(remaining.is_empty());
+        assert!(!tier_file_exists);
+        Ok(())
+    }
+
+    // Over the threshold, the camera's oldest recordings go until usage would be 5% under it
+    #[tokio::test]
+    async fn test_storage_pressure_deletes_oldest_recordings() -> Result<()> {
+        let Ok(database_url) = std::env::var("TEST_DATABASE_URL") else {
+            println!("Skipping storage pressure test. Set TEST_DATABASE_URL to run.");
+            return Ok(());
+        };
+
+        let pool = PgPool::connect(&database_url).await?;
+        let (camera_id, stream_id, _) = insert_camera_with_schedule(&pool, 365, 365).await?;
+        let mut oldest_first = Vec::new();
+        for age_days in [5, 4, 3, 2] {
+            oldest_first.push(insert_recording(&pool, camera_id, stream_id, None, age_days).await?);
+        }
+        sqlx::query("UPDATE recordings SET file_size = 100 WHERE camera_id = $1")
+            .bind(camera_id)
+            .execute(&pool)
+            .await?;
+
+        let repo = RecordingsRepository::new(Arc::new(pool.clone()));
+        let service = StorageCleanupService::new(
+            StorageCleanupConfig {
+                min_recordings_kept: 0,
+                ..StorageCleanupConfig::default()
+            },
+            repo.clone(),
+            &std::env::temp_dir(),
+        )
+        .with_disk_usage(fake_full_disk);
+        let deleted = service
+            .cleanup_by_storage_usage(&service.config(), Some(camera_id))
+            .await?;
+        let remaining: HashSet<Uuid> = repo
+            .search(&RecordingSearchQuery {
+                camera_ids: Some(vec![camera_id]),
+                ..RecordingSearchQuery::default()
+            })
+            .await?
+            .into_iter()
+            .map(|r| r.id)
+            .collect();
+
+        sqlx::query("DELETE FROM cameras WHERE id = $1")
+            .bind(camera_id)
+            .execute(&pool)
+            .await?;
+
+        // 950 of 1000 bytes used against a 75% target: the two oldest free enough
+        assert_eq!(deleted, 2);
+        assert_eq!(remaining, oldest_first[2..].iter().copied().collect());
+        Ok(())
+    }
+
+    fn fake_full_disk(_path: &Path) -> Result<DiskUsage> {
+        Ok(DiskUsage {
+            total_bytes: 1000,
+            used_bytes: 950,
+            percentage: 95.0,
+        })
+    }
+
+    fn recording_of_size(file_size: u64) -> Recording {
+        Recording {
+            id: Uuid::new_v4(),
+            camera_id: Uuid::new_v4(),
+            stream_id: Uuid::new_v4(),
+            start_time: Utc::now(),
+            end_time: Some(Utc::now()),
+            file_path: std::env::temp_dir().join("space-test.mp4"),
+            file_size,
+            duration: 0,
+            format: "mp4".to_string(),
+            resolution: "1280x720".to_string(),
+            fps: 25,
+            event_type: RecordingEventType::Continuous,
+            metadata: None,
+            schedule_id: None,
+            segment_id: None,
+            parent_recording_id: None,
+        }
+    }
+
+    #[test]
+    fn test_plan_space_cleanup_oldest_first() {
+        let usage = fake_full_disk(Path::new("/")).unwrap();
+        let candidates: Vec<Recording> = (0..10).map(|_| recording_of_size(100)).collect();
+
+        // 950 used, target 75% of 1000 = 750: two 100 byte recordings bring it down
+        let planned = plan_space_cleanup(&candidates, &usage, 75.0, &HashSet::new(), 0);
+        let ids: Vec<Uuid> = planned.iter().map(|r| r.id).collect();
+        assert_eq!(ids, vec![candidates[0].id, candidates[1].id]);
+    }
+
+    #[test]
+    fn test_plan_space_cleanup_skips_active_and_keeps_floor() {
+        let usage = fake_full_disk(Path::new("/")).unwrap();
+        let candidates: Vec<Recording> = (0..4).map(|_| recording_of_size(100)).collect();
+        let active: HashSet<Uuid> = [candidates[0].id].into_iter().collect();
+
+        // Would need all of them, but the oldest is active and two must be kept
+        let planned = plan_space_cleanup(&candidates, &usage, 0.0, &active, 2);
+        let ids: Vec<Uuid> = planned.iter().map(|r| r.id).collect();
+        assert_eq!(ids, vec![candidates[1].id]);
+    }
+
+    // A full disk holding only recordings from the last min_retain_recent_hours frees
+    // nothing and raises the alert instead
+    #[tokio::test]
+    async fn test_full_disk_of_recent_recordings_alerts_instead_of_deleting() -> Result<()> {
+        let pool = PgPool::connect_lazy("postgres://localhost/unused")?;
+        let service = StorageCleanupService::new(
+            StorageCleanupConfig {
+                min_recordings_kept: 0,
+                min_retain_recent_hours: 24,
+                ..StorageCleanupConfig::default()
+            },
+            RecordingsRepository::new(Arc::new(pool)),
+            &std::env::temp_dir(),
+        );
+
+        let broker = create_message_broker(MessageBrokerConfig {
+            backend: BrokerBackend::Memory,
+            ..MessageBrokerConfig::default()
+        })
+        .await?;
+        let alerts = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let alerts_clone = alerts.clone();
+        broker
+            .subscribe(
+                EventType::StorageLimitReached,
+                Arc::new(move |event| {
+                    alerts_clone.lock().unwrap().push(event.payload);
+                    Ok(())
+                }),
+            )
+            .await?;
+        service.set_message_broker(broker).await?;
+
+        let usage = fake_full_disk(Path::new("/"))?;
+        let candidates: Vec<Recording> = (0..10).map(|_| recording_of_size(100)).collect();
+        let deleted = service
+            .free_space(&service.config(), &usage, candidates)
+            .await?;
+
+        let alerts = alerts.lock().unwrap();
+        assert_eq!(deleted, 0);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0]["recent_footage_retained"], true);
+        assert_eq!(alerts[0]["recordings_deleted"], 0);
+        Ok(())
+    }
+}
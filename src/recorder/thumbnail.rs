@@ -0,0 +1,219 @@
+use crate::db::models::recording_models::{Recording, RecordingUpdate};
+use crate::db::repositories::recordings::RecordingsRepository;
+use anyhow::{anyhow, Result};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use log::{debug, info, warn};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// How long decoding the first frame of a segment may take
+const THUMBNAIL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Metadata key holding the path of a recording's thumbnail
+pub const THUMBNAIL_METADATA_KEY: &str = "thumbnail_path";
+
+/// Where the thumbnail of a recording is stored: inside the segments directory of a
+/// parent recording, next to the file for a single-file recording or segment
+pub fn thumbnail_path(recording: &Recording) -> PathBuf {
+    if recording.file_path.is_dir() {
+        recording.file_path.join("thumbnail.jpg")
+    } else {
+        recording.file_path.with_extension("jpg")
+    }
+}
+
+/// The first media file of a recording: the file itself, or the earliest segment in
+/// its segments directory
+pub fn first_segment(recording: &Recording) -> Option<PathBuf> {
+    if recording.file_path.is_file() {
+        return Some(recording.file_path.clone());
+    }
+
+    let suffix = format!(".{}", recording.format);
+    std::fs::read_dir(&recording.file_path)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| {
+            path.file_name()
+                .and_then(|name| name.to_str())
+                .map_or(false, |name| {
+                    name.starts_with("segment_") && name.ends_with(&suffix)
+                })
+        })
+        .min()
+}
+
+/// Decode the first frame of `source` and write it to `output` as a JPEG.
+///
+/// Blocks until the frame is written or decoding fails, so call it from a blocking task.
+pub fn extract_first_frame(source: &Path, output: &Path, timeout: Duration) -> Result<()> {
+    gst::init()?;
+
+    let make = |factory: &str| {
+        gst::ElementFactory::make(factory)
+            .build()
+            .map_err(|e| anyhow!("Failed to create {}: {}", factory, e))
+    };
+
+    let pipeline = gst::Pipeline::new();
+    let filesrc = make("filesrc")?;
+    filesrc.set_property("location", source.to_string_lossy().to_string());
+    let decodebin = make("decodebin")?;
+    let convert = make("videoconvert")?;
+    // `snapshot` makes jpegenc send EOS after the first frame
+    let jpegenc = make("jpegenc")?;
+    jpegenc.set_property("snapshot", true);
+    let filesink = make("filesink")?;
+    filesink.set_property("location", output.to_string_lossy().to_string());
+
+    pipeline.add_many([&filesrc, &decodebin, &convert, &jpegenc, &filesink])?;
+    filesrc.link(&decodebin)?;
+    gst::Element::link_many([&convert, &jpegenc, &filesink])?;
+
+    let convert_sink = convert
+        .static_pad("sink")
+        .ok_or_else(|| anyhow!("videoconvert has no sink pad"))?;
+    decodebin.connect_pad_added(move |_, pad| {
+        let is_video = pad
+            .current_caps()
+            .and_then(|caps| caps.structure(0).map(|s| s.name().starts_with("video/")))
+            .unwrap_or(false);
+        if is_video && !convert_sink.is_linked() {
+            if let Err(e) = pad.link(&convert_sink) {
+                warn!("Failed to link thumbnail decoder: {:?}", e);
+            }
+        }
+    });
+
+    pipeline.set_state(gst::State::Playing)?;
+    let bus = pipeline
+        .bus()
+        .ok_or_else(|| anyhow!("Thumbnail pipeline has no bus"))?;
+    let message = bus.timed_pop_filtered(
+        gst::ClockTime::from_nseconds(timeout.as_nanos() as u64),
+        &[gst::MessageType::Eos, gst::MessageType::Error],
+    );
+    let _ = pipeline.set_state(gst::State::Null);
+
+    match message.as_ref().map(|m| m.view()) {
+        Some(gst::MessageView::Eos(_)) if output.exists() => Ok(()),
+        Some(gst::MessageView::Error(err)) => {
+            let _ = std::fs::remove_file(output);
+            Err(anyhow!(
+                "Failed to decode {}: {}",
+                source.display(),
+                err.error()
+            ))
+        }
+        _ => {
+            let _ = std::fs::remove_file(output);
+            Err(anyhow!("Timed out decoding {}", source.display()))
+        }
+    }
+}
+
+/// Return the thumbnail of a recording, generating it from the first segment if it is
+/// missing and recording its path in the recording metadata
+pub async fn ensure_thumbnail(
+    repo: &RecordingsRepository,
+    recording: &Recording,
+) -> Result<PathBuf> {
+    let recorded = recording
+        .metadata
+        .as_ref()
+        .and_then(|m| m.get(THUMBNAIL_METADATA_KEY))
+        .and_then(|p| p.as_str())
+        .map(PathBuf::from);
+    if let Some(path) = recorded.filter(|p| p.is_file()) {
+        return Ok(path);
+    }
+
+    let source = first_segment(recording)
+        .ok_or_else(|| anyhow!("Recording {} has no media files", recording.id))?;
+    let output = thumbnail_path(recording);
+    debug!(
+        "Generating thumbnail for recording {} from {}",
+        recording.id,
+        source.display()
+    );
+
+    let output_clone = output.clone();
+    tokio::task::spawn_blocking(move || {
+        extract_first_frame(&source, &output_clone, THUMBNAIL_TIMEOUT)
+    })
+    .await??;
+
+    repo.update_with_data(
+        &recording.id,
+        RecordingUpdate {
+            file_path: None,
+            duration: None,
+            file_size: None,
+            end_time: None,
+            metadata: Some(serde_json::json!({
+                THUMBNAIL_METADATA_KEY: output.to_string_lossy(),
+            })),
+            segment_id: None,
+            parent_recording_id: None,
+            resolution: None,
+            fps: None,
+        },
+    )
+    .await?;
+
+    info!(
+        "Stored thumbnail for recording {} at {}",
+        recording.id,
+        output.display()
+    );
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::models::recording_models::RecordingEventType;
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    #[test]
+    fn thumbnail_comes_from_the_earliest_segment() {
+        let dir = std::env::temp_dir().join(format!("thumbnail-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in [
+            "segment_20250101_120500_00001.mp4",
+            "segment_20250101_120000_00000.mp4",
+            "notes.txt",
+        ] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+
+        let recording = Recording {
+            id: Uuid::new_v4(),
+            camera_id: Uuid::new_v4(),
+            stream_id: Uuid::new_v4(),
+            start_time: Utc::now(),
+            end_time: None,
+            file_path: dir.clone(),
+            file_size: 0,
+            duration: 0,
+            format: "mp4".to_string(),
+            resolution: "1920x1080".to_string(),
+            fps: 25,
+            event_type: RecordingEventType::Continuous,
+            metadata: None,
+            schedule_id: None,
+            segment_id: None,
+            parent_recording_id: None,
+        };
+
+        assert_eq!(
+            first_segment(&recording),
+            Some(dir.join("segment_20250101_120000_00000.mp4"))
+        );
+        assert_eq!(thumbnail_path(&recording), dir.join("thumbnail.jpg"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,143 @@
+use anyhow::{anyhow, Result};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use log::{debug, info};
+
+/// Target of the H264 encoder in the transcode chains
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranscodeSettings {
+    /// Bit rate in kbit/s
+    pub bitrate_kbps: u32,
+    /// x264enc `speed-preset`, one of `config::X264_PRESETS`
+    pub preset: String,
+}
+
+impl Default for TranscodeSettings {
+    fn default() -> Self {
+        Self {
+            bitrate_kbps: 2048,
+            preset: "veryfast".to_string(),
+        }
+    }
+}
+
+/// RTP depayloader for the codecs that have to be transcoded before they can be recorded
+pub fn transcode_depayloader(codec: &str) -> Option<&'static str> {
+    match codec {
+        "jpeg" | "mjpeg" => Some("rtpjpegdepay"),
+        "vp8" => Some("rtpvp8depay"),
+        "vp9" => Some("rtpvp9depay"),
+        "av1" => Some("rtpav1depay"),
+        _ => None,
+    }
+}
+
+/// Decoders to try for a codec, in order of preference
+fn decoders(codec: &str) -> &'static [&'static str] {
+    match codec {
+        "jpeg" | "mjpeg" => &["jpegdec"],
+        "vp8" => &["vp8dec"],
+        "vp9" => &["vp9dec"],
+        "av1" => &["dav1ddec", "av1dec"],
+        _ => &[],
+    }
+}
+
+/// Build `depay ! decoder ! videoconvert ! x264enc ! h264parse`, turning an RTP stream of
+/// `codec` into H264 that MP4 and browsers accept. `name` gives each element its name from
+/// its role. Returns the elements in link order; the last one is the output.
+///
+/// Decoding and encoding every frame costs far more CPU than the passthrough chains.
+pub fn build_h264_transcode_chain<F, N>(
+    make: &F,
+    codec: &str,
+    settings: &TranscodeSettings,
+    name: N,
+) -> Result<Vec<gst::Element>>
+where
+    F: Fn(&str, String) -> Result<gst::Element>,
+    N: Fn(&str) -> String,
+{
+    let depay_factory = transcode_depayloader(codec)
+        .ok_or_else(|| anyhow!("No transcode chain for codec {}", codec))?;
+    let depay = make(depay_factory, name("depay"))?;
+
+    let mut decoder = None;
+    for factory in decoders(codec) {
+        match make(factory, name("decode")) {
+            Ok(element) => {
+                decoder = Some(element);
+                break;
+            }
+            Err(e) => debug!("{} not available for {}: {}", factory, codec, e),
+        }
+    }
+    let decoder = decoder.ok_or_else(|| {
+        anyhow!(
+            "No decoder available for {} (tried {})",
+            codec,
+            decoders(codec).join(", ")
+        )
+    })?;
+
+    let convert = make("videoconvert", name("convert"))?;
+    let encode = make("x264enc", name("encode"))?;
+    // Skipped for stand-ins without the x264enc properties
+    if encode.find_property("tune").is_some() {
+        encode.set_property_from_str("tune", "zerolatency");
+        encode.set_property_from_str("speed-preset", &settings.preset);
+        encode.set_property("bitrate", settings.bitrate_kbps);
+    }
+    let parse = make("h264parse", name("parse"))?;
+
+    info!(
+        "Transcoding {} to H264 at {} kbit/s ({}) with {}",
+        codec,
+        settings.bitrate_kbps,
+        settings.preset,
+        decoder.name()
+    );
+    Ok(vec![depay, decoder, convert, encode, parse])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn transcode_chain_falls_back_to_the_next_decoder() {
+        gst::init().unwrap();
+
+        // Mock factory: dav1d isn't installed, everything else is an identity element
+        let make = |factory: &str, name: String| -> Result<gst::Element> {
+            if factory == "dav1ddec" {
+                return Err(anyhow!("no such element: {}", factory));
+            }
+            Ok(gst::ElementFactory::make("identity").name(name).build()?)
+        };
+
+        let settings = TranscodeSettings::default();
+        let elements =
+            build_h264_transcode_chain(&make, "av1", &settings, |role| format!("test_{}", role))
+                .unwrap();
+        let names: Vec<_> = elements.iter().map(|el| el.name().to_string()).collect();
+        assert_eq!(
+            names,
+            [
+                "test_depay",
+                "test_decode",
+                "test_convert",
+                "test_encode",
+                "test_parse"
+            ]
+        );
+
+        let pipeline = gst::Pipeline::new();
+        pipeline.add_many(&elements).unwrap();
+        gst::Element::link_many(&elements).unwrap();
+
+        assert!(
+            build_h264_transcode_chain(&make, "h264", &settings, |role| role.to_string()).is_err()
+        );
+    }
+}
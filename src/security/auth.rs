@@ -2,12 +2,15 @@ use crate::config::SecurityConfig;
 use crate::db::models::user_models::{AuthToken, LoginCredentials, User, UserRole};
 use crate::db::repositories::users::UsersRepository;
 use crate::error::Error;
+use crate::security::lockout::{throttle_keys, LoginThrottle};
 use crate::security::{password, Claims, SecurityService};
 use anyhow::Result;
 use chrono::Utc;
 use sqlx::{postgres::PgPoolOptions, PgPool};
+use std::net::IpAddr;
 use std::sync::Arc;
-use tracing::info;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
 use uuid::Uuid;
 
 /// Authentication service for handling user login/logout
@@ -15,6 +18,15 @@ pub struct AuthService {
     users_repo: UsersRepository,
     security: SecurityService,
     config: SecurityConfig,
+    throttle: LoginThrottle,
+}
+
+fn login_throttle(config: &SecurityConfig) -> LoginThrottle {
+    LoginThrottle::new(
+        config.login_max_failures,
+        Duration::from_secs(config.login_failure_window_secs),
+        Duration::from_secs(config.login_lockout_secs),
+    )
 }
 
 impl AuthService {
@@ -24,6 +36,7 @@ impl AuthService {
             users_repo: UsersRepository::new(pool),
             security: SecurityService::new(config.clone()),
             config: config.clone(),
+            throttle: login_throttle(config),
         }
     }
 
@@ -39,11 +52,73 @@ impl AuthService {
             users_repo: UsersRepository::new(db_pool),
             security: SecurityService::new(config.clone()),
             config: config.clone(),
+            throttle: login_throttle(config),
+        }
+    }
+
+    /// Validate a bearer token and check that its user holds at least `role`
+    pub fn authorize(&self, token: &str, role: UserRole) -> Result<Claims> {
+        let token_data = self.security.validate_token(token)?;
+        if !self.security.has_role(&token_data, role.clone()) {
+            return Err(Error::Authorization(format!(
+                "User {} lacks the {:?} role",
+                token_data.claims.name, role
+            ))
+            .into());
         }
+        Ok(token_data.claims)
     }
 
-    /// Login a user with username/password
-    pub async fn login(&self, credentials: &LoginCredentials) -> Result<(User, AuthToken)> {
+    /// Login a user with username/password.
+    ///
+    /// Failed attempts are counted per username and per client address. Once either
+    /// reaches `login_max_failures` within the window, every login for it is refused until
+    /// the lockout ends, including ones with the right password.
+    pub async fn login(
+        &self,
+        credentials: &LoginCredentials,
+        client: Option<IpAddr>,
+    ) -> Result<(User, AuthToken)> {
+        let keys = throttle_keys(&credentials.username, client);
+        if let Some(remaining) = self.throttle.locked_for(&keys, Instant::now()) {
+            warn!(
+                "Refused login for {} from {:?}: locked out after repeated failures",
+                credentials.username, client
+            );
+            return Err(Error::TooManyRequests(format!(
+                "Too many failed logins, try again in {}s",
+                remaining.as_secs().max(1)
+            ))
+            .into());
+        }
+
+        let user = match self.check_credentials(credentials).await {
+            Ok(user) => user,
+            Err(e) if matches!(e.downcast_ref::<Error>(), Some(Error::Authentication(_))) => {
+                // Slow down guessing even before the lockout kicks in
+                let delay = self.throttle.record_failure(&keys, Instant::now());
+                tokio::time::sleep(delay).await;
+                return Err(e);
+            }
+            Err(e) => return Err(e),
+        };
+        // Only the username's count; the address may be shared with someone still guessing
+        self.throttle
+            .reset(&throttle_keys(&credentials.username, None));
+
+        // Update last login time
+        self.users_repo.update_last_login(&user.id).await?;
+
+        // Generate auth token
+        let token = self.security.generate_token(&user)?;
+
+        info!("User logged in: {}", user.username);
+
+        Ok((user, token))
+    }
+
+    /// Look up a user and verify their password
+    async fn check_credentials(&self, credentials: &LoginCredentials) -> Result<User> {
         // Find user by username
         let user = self
             .users_repo
@@ -63,15 +138,7 @@ impl AuthService {
             return Err(Error::Authentication("Invalid username or password".to_string()).into());
         }
 
-        // Update last login time
-        self.users_repo.update_last_login(&user.id).await?;
-
-        // Generate auth token
-        let token = self.security.generate_token(&user)?;
-
-        info!("User logged in: {}", user.username);
-
-        Ok((user, token))
+        Ok(user)
     }
 
     /// Register a new user
@@ -82,6 +149,8 @@ impl AuthService {
         password: &str,
         role: UserRole,
     ) -> Result<User> {
+        password::check_strength(password, &self.config)?;
+
         // Check if username already exists
         if let Some(_) = self.users_repo.get_by_username(username).await? {
             return Err(Error::AlreadyExists("Username already exists".to_string()).into());
@@ -137,6 +206,8 @@ impl AuthService {
             return Err(Error::Authentication("Current password is incorrect".to_string()).into());
         }
 
+        password::check_strength(new_password, &self.config)?;
+
         // Hash new password
         let password_hash = password::hash_password(new_password, &self.config)?;
 
@@ -162,7 +233,7 @@ impl AuthService {
             .ok_or_else(|| Error::NotFound("User not found".to_string()))?;
 
         // Generate random password
-        let new_password = password::generate_random_password(12);
+        let new_password = password::generate_policy_password(&self.config);
 
         // Hash new password
         let password_hash = password::hash_password(&new_password, &self.config)?;
@@ -243,3 +314,47 @@ pub async fn get_temporary_claims() -> Claims {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use std::net::Ipv4Addr;
+
+    #[tokio::test]
+    async fn correct_password_fails_during_lockout() {
+        let mut config = Config::default().security;
+        config.login_max_failures = 3;
+        let auth = AuthService::new_without_db(&config);
+        let client = Some(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 10)));
+
+        let keys = throttle_keys("admin", client);
+        for _ in 0..3 {
+            auth.throttle.record_failure(&keys, Instant::now());
+        }
+
+        // Refused before the password is even looked at (there is no database here)
+        let credentials = LoginCredentials {
+            username: "admin".to_string(),
+            password: "the-right-password".to_string(),
+        };
+        let err = auth.login(&credentials, client).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::TooManyRequests(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn weak_password_is_rejected_on_register() {
+        let auth = AuthService::new_without_db(&Config::default().security);
+
+        let err = auth
+            .register("alice", "alice@example.com", "alice", UserRole::Viewer)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Config(_))
+        ));
+    }
+}
@@ -0,0 +1,160 @@
+use crate::error::Error;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use anyhow::Result;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use once_cell::sync::OnceCell;
+use sha2::{Digest, Sha256};
+
+/// Prefix marking a column value as encrypted by this module
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+
+/// AES-GCM nonce length in bytes
+const NONCE_LEN: usize = 12;
+
+// Global key for camera credentials, set once at startup
+static CREDENTIAL_KEY: OnceCell<[u8; 32]> = OnceCell::new();
+
+// Key credentials were encrypted under before the current one, set once at startup
+static PREVIOUS_CREDENTIAL_KEY: OnceCell<[u8; 32]> = OnceCell::new();
+
+/// Derive the camera credential key from the configured secret.
+///
+/// Only the first call has any effect; later calls are ignored so the key cannot change
+/// while rows encrypted under it are in use.
+pub fn init_credential_key(secret: &str) {
+    let _ = CREDENTIAL_KEY.set(derive_key(secret));
+}
+
+/// Keep accepting credentials encrypted under the key derived from `secret`, the one in use
+/// before the current key. [`needs_reencryption`] reports such values so they can be
+/// rewritten under the current key.
+pub fn init_previous_credential_key(secret: &str) {
+    let _ = PREVIOUS_CREDENTIAL_KEY.set(derive_key(secret));
+}
+
+fn derive_key(secret: &str) -> [u8; 32] {
+    Sha256::digest(secret.as_bytes()).into()
+}
+
+fn current_key() -> Result<&'static [u8; 32]> {
+    CREDENTIAL_KEY.get().ok_or_else(|| {
+        Error::Config("Credential encryption key has not been initialized".to_string()).into()
+    })
+}
+
+/// Whether a stored value was produced by [`encrypt_credential`]
+pub fn is_encrypted(value: &str) -> bool {
+    value.starts_with(ENCRYPTED_PREFIX)
+}
+
+/// Encrypt a credential for storage. Values that are already encrypted are returned as-is.
+pub fn encrypt_credential(plaintext: &str) -> Result<String> {
+    encrypt_with(current_key()?, plaintext)
+}
+
+/// Decrypt a stored credential. Legacy plaintext values are returned unchanged.
+pub fn decrypt_credential(stored: &str) -> Result<String> {
+    let (plaintext, _) = decrypt_with(stored, current_key()?, PREVIOUS_CREDENTIAL_KEY.get())?;
+    Ok(plaintext)
+}
+
+/// Whether a stored credential only decrypts under the previous key
+pub fn needs_reencryption(stored: &str) -> Result<bool> {
+    let (_, previous) = decrypt_with(stored, current_key()?, PREVIOUS_CREDENTIAL_KEY.get())?;
+    Ok(previous)
+}
+
+fn encrypt_with(key: &[u8; 32], plaintext: &str) -> Result<String> {
+    if is_encrypted(plaintext) {
+        return Ok(plaintext.to_string());
+    }
+
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))
+        .encrypt(&nonce, plaintext.as_bytes())
+        .map_err(|e| Error::Internal(format!("Failed to encrypt credential: {}", e)))?;
+
+    let mut payload = nonce.to_vec();
+    payload.extend_from_slice(&ciphertext);
+    Ok(format!("{}{}", ENCRYPTED_PREFIX, STANDARD.encode(payload)))
+}
+
+// Decrypt under `key`, falling back to `previous`. Also reports whether the fallback was used.
+fn decrypt_with(
+    stored: &str,
+    key: &[u8; 32],
+    previous: Option<&[u8; 32]>,
+) -> Result<(String, bool)> {
+    let Some(encoded) = stored.strip_prefix(ENCRYPTED_PREFIX) else {
+        return Ok((stored.to_string(), false));
+    };
+
+    let payload = STANDARD
+        .decode(encoded)
+        .map_err(|e| Error::Config(format!("Malformed encrypted credential: {}", e)))?;
+    if payload.len() < NONCE_LEN {
+        return Err(Error::Config("Malformed encrypted credential: too short".to_string()).into());
+    }
+    let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+
+    let open = |key: &[u8; 32]| {
+        Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .ok()
+    };
+    let (plaintext, used_previous) = if let Some(plaintext) = open(key) {
+        (plaintext, false)
+    } else if let Some(plaintext) = previous.and_then(open) {
+        (plaintext, true)
+    } else {
+        return Err(Error::Config(
+            "Failed to decrypt credential, check security.cred_encryption_key".to_string(),
+        )
+        .into());
+    };
+
+    let plaintext = String::from_utf8(plaintext)
+        .map_err(|e| Error::Config(format!("Decrypted credential is not UTF-8: {}", e)))?;
+    Ok((plaintext, used_previous))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credential_round_trips_and_is_not_stored_in_clear() {
+        init_credential_key("test-credential-key");
+
+        let stored = encrypt_credential("s3cret!").unwrap();
+        assert_ne!(stored, "s3cret!");
+        assert!(!stored.contains("s3cret!"));
+        assert!(is_encrypted(&stored));
+        assert_eq!(decrypt_credential(&stored).unwrap(), "s3cret!");
+
+        // Encrypting twice must not double-wrap, and legacy plaintext reads back as-is
+        assert_eq!(encrypt_credential(&stored).unwrap(), stored);
+        assert_eq!(decrypt_credential("legacy").unwrap(), "legacy");
+    }
+
+    #[test]
+    fn credential_encrypted_under_the_previous_key_still_decrypts() {
+        let (previous, current) = (derive_key("built-in-key"), derive_key("real-key"));
+        let stored = encrypt_with(&previous, "s3cret!").unwrap();
+
+        // Without the previous key the value is unreadable once the key changes
+        assert!(decrypt_with(&stored, &current, None).is_err());
+        assert_eq!(
+            decrypt_with(&stored, &current, Some(&previous)).unwrap(),
+            ("s3cret!".to_string(), true)
+        );
+
+        // Once rewritten under the current key it no longer needs the fallback
+        let rewritten = encrypt_with(&current, "s3cret!").unwrap();
+        assert_eq!(
+            decrypt_with(&rewritten, &current, Some(&previous)).unwrap(),
+            ("s3cret!".to_string(), false)
+        );
+    }
+}
@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Delay before answering the first failed login; doubles with every further failure
+const BASE_DELAY: Duration = Duration::from_millis(250);
+
+/// Longest delay added to a failed login
+const MAX_DELAY: Duration = Duration::from_secs(4);
+
+/// Failed logins of one username or client address
+#[derive(Debug, Default)]
+struct Failures {
+    /// When each failure inside the window happened
+    times: Vec<Instant>,
+    locked_until: Option<Instant>,
+}
+
+/// Tracks failed logins per username and per client address, locking either out for a
+/// while once too many fail within the window
+pub struct LoginThrottle {
+    max_failures: u32,
+    window: Duration,
+    lockout: Duration,
+    failures: Mutex<HashMap<String, Failures>>,
+}
+
+/// Throttle keys of a login attempt
+pub fn throttle_keys(username: &str, client: Option<IpAddr>) -> Vec<String> {
+    let mut keys = vec![format!("user:{}", username)];
+    if let Some(client) = client {
+        keys.push(format!("ip:{}", client));
+    }
+    keys
+}
+
+impl LoginThrottle {
+    pub fn new(max_failures: u32, window: Duration, lockout: Duration) -> Self {
+        Self {
+            max_failures,
+            window,
+            lockout,
+            failures: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// How much longer the longest lockout among `keys` lasts, `None` when none is locked
+    pub fn locked_for(&self, keys: &[String], now: Instant) -> Option<Duration> {
+        let failures = self.failures.lock().unwrap();
+        keys.iter()
+            .filter_map(|key| failures.get(key)?.locked_until)
+            .filter(|until| *until > now)
+            .max()
+            .map(|until| until - now)
+    }
+
+    /// Count a failed login against every key. Returns how long to hold back the answer.
+    pub fn record_failure(&self, keys: &[String], now: Instant) -> Duration {
+        let mut failures = self.failures.lock().unwrap();
+        // Drop entries that expired so guessed usernames don't pile up
+        failures.retain(|_, f| {
+            f.times.retain(|t| now.duration_since(*t) < self.window);
+            !f.times.is_empty() || f.locked_until.map_or(false, |until| until > now)
+        });
+
+        let mut most = 1;
+        for key in keys {
+            let entry = failures.entry(key.clone()).or_default();
+            entry.times.push(now);
+            let count = entry.times.len();
+            if count >= self.max_failures as usize {
+                entry.locked_until = Some(now + self.lockout);
+                entry.times.clear();
+            }
+            most = most.max(count);
+        }
+
+        BASE_DELAY
+            .saturating_mul(1 << (most - 1).min(16))
+            .min(MAX_DELAY)
+    }
+
+    /// Forget the failures of `keys` after a successful login
+    pub fn reset(&self, keys: &[String]) {
+        let mut failures = self.failures.lock().unwrap();
+        for key in keys {
+            failures.remove(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn nth_failure_within_the_window_locks_out() {
+        let throttle = LoginThrottle::new(3, Duration::from_secs(60), Duration::from_secs(300));
+        let keys = throttle_keys("admin", Some(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+        let start = Instant::now();
+
+        let first = throttle.record_failure(&keys, start);
+        let second = throttle.record_failure(&keys, start + Duration::from_secs(1));
+        assert!(second > first);
+        assert_eq!(throttle.locked_for(&keys, start), None);
+
+        throttle.record_failure(&keys, start + Duration::from_secs(2));
+        let locked = throttle.locked_for(&keys, start + Duration::from_secs(2));
+        assert_eq!(locked, Some(Duration::from_secs(300)));
+        // Another username from the same address is locked out as well
+        let other = throttle_keys("operator", Some(IpAddr::V4(Ipv4Addr::LOCALHOST)));
+        assert!(throttle.locked_for(&other, start).is_some());
+
+        // The lockout expires on its own
+        assert_eq!(
+            throttle.locked_for(&keys, start + Duration::from_secs(303)),
+            None
+        );
+    }
+
+    #[test]
+    fn failures_outside_the_window_and_successes_reset_the_count() {
+        let throttle = LoginThrottle::new(2, Duration::from_secs(60), Duration::from_secs(300));
+        let keys = throttle_keys("admin", None);
+        let start = Instant::now();
+
+        throttle.record_failure(&keys, start);
+        throttle.record_failure(&keys, start + Duration::from_secs(61));
+        assert_eq!(
+            throttle.locked_for(&keys, start + Duration::from_secs(61)),
+            None
+        );
+
+        throttle.reset(&keys);
+        throttle.record_failure(&keys, start + Duration::from_secs(62));
+        assert_eq!(
+            throttle.locked_for(&keys, start + Duration::from_secs(62)),
+            None
+        );
+    }
+}
@@ -8,6 +8,8 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 pub mod auth;
+pub mod credentials;
+pub mod lockout;
 pub mod password;
 
 /// JWT claims structure
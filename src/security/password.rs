@@ -23,6 +23,64 @@ pub fn verify_password(password: &str, hash: &str) -> Result<bool> {
     Ok(result)
 }
 
+/// Reject a password that doesn't meet the configured complexity policy, listing every
+/// rule it breaks
+pub fn check_strength(password: &str, config: &SecurityConfig) -> Result<()> {
+    let mut problems = Vec::new();
+
+    if password.chars().count() < config.password_min_length {
+        problems.push(format!(
+            "be at least {} characters long",
+            config.password_min_length
+        ));
+    }
+    if config.password_require_uppercase && !password.chars().any(|c| c.is_uppercase()) {
+        problems.push("contain an uppercase letter".to_string());
+    }
+    if config.password_require_lowercase && !password.chars().any(|c| c.is_lowercase()) {
+        problems.push("contain a lowercase letter".to_string());
+    }
+    if config.password_require_digit && !password.chars().any(|c| c.is_ascii_digit()) {
+        problems.push("contain a digit".to_string());
+    }
+    if config.password_require_symbol && !password.chars().any(|c| !c.is_alphanumeric()) {
+        problems.push("contain a symbol".to_string());
+    }
+
+    if problems.is_empty() {
+        return Ok(());
+    }
+    Err(Error::Config(format!("Password must {}", problems.join(", "))).into())
+}
+
+/// Generate a random password that satisfies the complexity policy
+pub fn generate_policy_password(config: &SecurityConfig) -> String {
+    use rand::seq::SliceRandom;
+    use rand::{thread_rng, Rng};
+    const UPPERCASE: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+    const LOWERCASE: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+    const DIGITS: &[u8] = b"0123456789";
+    const SYMBOLS: &[u8] = b"!@#$%^&*()";
+
+    let mut rng = thread_rng();
+    let length = config.password_min_length.max(12);
+    // One character of every required class, the rest from the full charset
+    let mut password: Vec<char> = [
+        (config.password_require_uppercase, UPPERCASE),
+        (config.password_require_lowercase, LOWERCASE),
+        (config.password_require_digit, DIGITS),
+        (config.password_require_symbol, SYMBOLS),
+    ]
+    .iter()
+    .filter(|(required, _)| *required)
+    .map(|(_, class)| class[rng.gen_range(0..class.len())] as char)
+    .collect();
+    password.extend(generate_random_password(length.saturating_sub(password.len())).chars());
+    password.shuffle(&mut rng);
+
+    password.into_iter().collect()
+}
+
 /// Generate a random password
 pub fn generate_random_password(length: usize) -> String {
     use rand::{Rng, thread_rng};
@@ -37,4 +95,45 @@ pub fn generate_random_password(length: usize) -> String {
         .collect();
     
     password
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn weak_passwords_are_rejected_with_every_broken_rule() {
+        let mut config = Config::default().security;
+        config.password_min_length = 10;
+        config.password_require_symbol = true;
+
+        assert!(check_strength("Corr3ct-Horse", &config).is_ok());
+
+        let err = check_strength("password", &config).unwrap_err().to_string();
+        assert!(err.contains("at least 10 characters"), "{}", err);
+        assert!(err.contains("uppercase"), "{}", err);
+        assert!(err.contains("digit"), "{}", err);
+        assert!(err.contains("symbol"), "{}", err);
+        assert!(!err.contains("lowercase"), "{}", err);
+
+        // Relaxed policies only enforce what is configured
+        config.password_require_uppercase = false;
+        config.password_require_digit = false;
+        config.password_require_symbol = false;
+        assert!(check_strength("longenough", &config).is_ok());
+    }
+
+    #[test]
+    fn generated_passwords_meet_the_policy() {
+        let mut config = Config::default().security;
+        config.password_min_length = 16;
+        config.password_require_symbol = true;
+
+        for _ in 0..50 {
+            let password = generate_policy_password(&config);
+            assert_eq!(password.chars().count(), 16);
+            check_strength(&password, &config).unwrap();
+        }
+    }
+}
@@ -0,0 +1,8 @@
+pub mod object_store;
+pub mod signed_url;
+
+pub use object_store::{object_store, LocalObjectStore, ObjectStore, S3ObjectStore};
+pub use signed_url::{
+    url_provider, HmacUrlSigner, LocalUrlProvider, SignedUrl, SignedUrlProvider,
+    StorageUrlProvider, UrlSigner,
+};
@@ -0,0 +1,375 @@
+use crate::config::{ArchiveBackend, ArchiveConfig};
+use crate::error::Error;
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+/// Storage recordings are archived to, addressed by `/`-separated keys
+#[async_trait]
+pub trait ObjectStore: Send + Sync {
+    /// Short name recorded with archived recordings, e.g. "s3"
+    fn name(&self) -> &'static str;
+
+    /// Where `key` lives, stored as an archived recording's `file_path`
+    fn location(&self, key: &str) -> PathBuf;
+
+    /// Upload the file at `path` as `key`, replacing any previous object
+    async fn put(&self, key: &str, path: &Path) -> Result<()>;
+
+    /// Download `key` to `dest`. The file only appears once it is complete.
+    async fn get(&self, key: &str, dest: &Path) -> Result<()>;
+
+    /// Size of `key` in bytes, `None` when there is no such object
+    async fn size(&self, key: &str) -> Result<Option<u64>>;
+
+    /// Remove `key`; removing a missing object is not an error
+    async fn delete(&self, key: &str) -> Result<()>;
+}
+
+/// Partial file `dest` is written to before being renamed into place, unique so
+/// concurrent downloads of the same object don't clobber each other
+fn partial_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}.part", Uuid::new_v4()));
+    dest.with_file_name(name)
+}
+
+async fn create_parent(path: &Path) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| Error::Io(format!("Failed to create {}: {}", parent.display(), e)))?;
+    }
+    Ok(())
+}
+
+/// Copy `src` to `dest` through a partial file
+async fn copy_into_place(src: &Path, dest: &Path) -> Result<()> {
+    create_parent(dest).await?;
+    let partial = partial_path(dest);
+    if let Err(e) = tokio::fs::copy(src, &partial).await {
+        let _ = tokio::fs::remove_file(&partial).await;
+        return Err(Error::Io(format!(
+            "Failed to copy {} to {}: {}",
+            src.display(),
+            dest.display(),
+            e
+        ))
+        .into());
+    }
+    tokio::fs::rename(&partial, dest).await.map_err(|e| {
+        Error::Io(format!(
+            "Failed to move {} into place: {}",
+            dest.display(),
+            e
+        ))
+    })?;
+    Ok(())
+}
+
+/// Objects as files under a directory, e.g. a NAS mount
+pub struct LocalObjectStore {
+    root: PathBuf,
+}
+
+impl LocalObjectStore {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+#[async_trait]
+impl ObjectStore for LocalObjectStore {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    fn location(&self, key: &str) -> PathBuf {
+        self.root.join(key)
+    }
+
+    async fn put(&self, key: &str, path: &Path) -> Result<()> {
+        copy_into_place(path, &self.location(key)).await
+    }
+
+    async fn get(&self, key: &str, dest: &Path) -> Result<()> {
+        copy_into_place(&self.location(key), dest).await
+    }
+
+    async fn size(&self, key: &str) -> Result<Option<u64>> {
+        match tokio::fs::metadata(self.location(key)).await {
+            Ok(metadata) => Ok(Some(metadata.len())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(Error::Io(format!("Failed to stat archived {}: {}", key, e)).into()),
+        }
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        match tokio::fs::remove_file(self.location(key)).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(Error::Io(format!("Failed to remove archived {}: {}", key, e)).into()),
+        }
+    }
+}
+
+/// SHA-256 of an empty body, sent with requests that have none
+const EMPTY_PAYLOAD_SHA256: &str =
+    "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+/// Sent in place of a body's SHA-256 when the body isn't hashed
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent-encode everything but RFC 3986 unreserved characters, as SigV4 expects
+fn uri_encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// An S3-compatible bucket (AWS, MinIO, Ceph, ...), addressed path-style and signed with
+/// AWS Signature Version 4
+pub struct S3ObjectStore {
+    client: reqwest::Client,
+    endpoint: url::Url,
+    bucket: String,
+    region: String,
+    access_key: String,
+    secret_key: String,
+}
+
+impl S3ObjectStore {
+    pub fn new(
+        endpoint: &str,
+        bucket: &str,
+        region: &str,
+        access_key: &str,
+        secret_key: &str,
+    ) -> Result<Self> {
+        let endpoint = url::Url::parse(endpoint)
+            .map_err(|e| Error::InvalidInput(format!("Invalid S3 endpoint {}: {}", endpoint, e)))?;
+        Ok(Self {
+            client: reqwest::Client::new(),
+            endpoint,
+            bucket: bucket.to_string(),
+            region: region.to_string(),
+            access_key: access_key.to_string(),
+            secret_key: secret_key.to_string(),
+        })
+    }
+
+    /// Path of `key` in the bucket, already encoded for both the URL and the signature
+    fn object_path(&self, key: &str) -> String {
+        let base = self.endpoint.path().trim_end_matches('/');
+        let key: Vec<String> = key.split('/').map(uri_encode).collect();
+        format!("{}/{}/{}", base, uri_encode(&self.bucket), key.join("/"))
+    }
+
+    fn host(&self) -> String {
+        let host = self.endpoint.host_str().unwrap_or_default();
+        match self.endpoint.port() {
+            Some(port) => format!("{}:{}", host, port),
+            None => host.to_string(),
+        }
+    }
+
+    /// Send a signed request for `key`, with a body of the given length streamed from a
+    /// file. Bodies are sent unsigned so they never have to be read up front to be hashed.
+    async fn send(
+        &self,
+        method: reqwest::Method,
+        key: &str,
+        body: Option<(reqwest::Body, u64)>,
+    ) -> Result<reqwest::Response> {
+        let path = self.object_path(key);
+        let host = self.host();
+        let payload_hash = match &body {
+            Some(_) => UNSIGNED_PAYLOAD,
+            None => EMPTY_PAYLOAD_SHA256,
+        };
+
+        let now = Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{}\n{}\n\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method, path, host, payload_hash, amz_date, signed_headers, payload_hash
+        );
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let signing_key = ["s3", "aws4_request"].iter().fold(
+            hmac_sha256(
+                &hmac_sha256(format!("AWS4{}", self.secret_key).as_bytes(), &date),
+                &self.region,
+            ),
+            |key, part| hmac_sha256(&key, part),
+        );
+        let signature = hex(&hmac_sha256(&signing_key, &string_to_sign));
+
+        let mut url = self.endpoint.clone();
+        url.set_path(&path);
+        let mut request = self
+            .client
+            .request(method.clone(), url)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("x-amz-date", &amz_date)
+            .header(
+                reqwest::header::AUTHORIZATION,
+                format!(
+                    "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                    self.access_key, scope, signed_headers, signature
+                ),
+            );
+        if let Some((body, length)) = body {
+            // S3 rejects chunked uploads, so the length is sent up front
+            request = request
+                .header(reqwest::header::CONTENT_LENGTH, length)
+                .body(body);
+        }
+
+        request
+            .send()
+            .await
+            .map_err(|e| Error::Io(format!("S3 {} of {} failed: {}", method, key, e)).into())
+    }
+
+    async fn check(response: reqwest::Response, what: &str) -> Result<reqwest::Response> {
+        if response.status().is_success() {
+            return Ok(response);
+        }
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        Err(Error::Io(format!(
+            "S3 {} failed with {}: {}",
+            what,
+            status,
+            body.trim()
+        ))
+        .into())
+    }
+}
+
+#[async_trait]
+impl ObjectStore for S3ObjectStore {
+    fn name(&self) -> &'static str {
+        "s3"
+    }
+
+    fn location(&self, key: &str) -> PathBuf {
+        PathBuf::from(format!("s3://{}/{}", self.bucket, key))
+    }
+
+    async fn put(&self, key: &str, path: &Path) -> Result<()> {
+        let read_error =
+            |e: std::io::Error| Error::Io(format!("Failed to read {}: {}", path.display(), e));
+        let file = tokio::fs::File::open(path).await.map_err(read_error)?;
+        let length = file.metadata().await.map_err(read_error)?.len();
+        let body = reqwest::Body::wrap_stream(ReaderStream::new(file));
+        let response = self
+            .send(reqwest::Method::PUT, key, Some((body, length)))
+            .await?;
+        Self::check(response, &format!("upload of {}", key)).await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str, dest: &Path) -> Result<()> {
+        let response = self.send(reqwest::Method::GET, key, None).await?;
+        let mut response = Self::check(response, &format!("download of {}", key)).await?;
+
+        create_parent(dest).await?;
+        let partial = partial_path(dest);
+        let result = async {
+            let mut file = tokio::fs::File::create(&partial).await?;
+            while let Some(chunk) = response.chunk().await? {
+                file.write_all(&chunk).await?;
+            }
+            file.flush().await?;
+            tokio::fs::rename(&partial, dest).await?;
+            anyhow::Ok(())
+        }
+        .await;
+        if result.is_err() {
+            let _ = tokio::fs::remove_file(&partial).await;
+        }
+        result.map_err(|e| Error::Io(format!("Failed to download {}: {}", key, e)).into())
+    }
+
+    async fn size(&self, key: &str) -> Result<Option<u64>> {
+        let response = self.send(reqwest::Method::HEAD, key, None).await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = Self::check(response, &format!("lookup of {}", key)).await?;
+        // The header itself: a HEAD response has no body for content_length() to measure
+        Ok(response
+            .headers()
+            .get(reqwest::header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok()))
+    }
+
+    async fn delete(&self, key: &str) -> Result<()> {
+        let response = self.send(reqwest::Method::DELETE, key, None).await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(());
+        }
+        Self::check(response, &format!("delete of {}", key)).await?;
+        Ok(())
+    }
+}
+
+/// Build the object store `config` selects, `None` when archival is off
+pub fn object_store(config: &ArchiveConfig) -> Result<Option<Arc<dyn ObjectStore>>> {
+    let required = |value: &Option<String>, name: &str| {
+        value
+            .clone()
+            .ok_or_else(|| Error::Config(format!("storage.archive.{} is required for S3", name)))
+    };
+
+    Ok(match config.backend {
+        ArchiveBackend::None => None,
+        ArchiveBackend::Local => {
+            let root = config.local_path.clone().ok_or_else(|| {
+                Error::Config("storage.archive.local_path is required".to_string())
+            })?;
+            Some(Arc::new(LocalObjectStore::new(root)))
+        }
+        ArchiveBackend::S3 => Some(Arc::new(S3ObjectStore::new(
+            &required(&config.s3_endpoint, "s3_endpoint")?,
+            &required(&config.s3_bucket, "s3_bucket")?,
+            &config.s3_region,
+            &required(&config.s3_access_key, "s3_access_key")?,
+            &required(&config.s3_secret_key, "s3_secret_key")?,
+        )?)),
+    })
+}
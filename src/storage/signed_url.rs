@@ -0,0 +1,227 @@
+use crate::config::{StorageConfig, StorageUrlBackend};
+use crate::error::Error;
+use anyhow::Result;
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Time-limited URL a client fetches a recording file from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedUrl {
+    pub url: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Decides how clients reach recording files
+pub trait StorageUrlProvider: Send + Sync {
+    /// URL `path` can be fetched from, or `None` when the API serves the file itself
+    fn url_for(&self, path: &Path) -> Result<Option<SignedUrl>>;
+}
+
+/// Files on the local filesystem, served by the API
+pub struct LocalUrlProvider;
+
+impl StorageUrlProvider for LocalUrlProvider {
+    fn url_for(&self, _path: &Path) -> Result<Option<SignedUrl>> {
+        Ok(None)
+    }
+}
+
+/// Signs the object key and expiry of a URL
+pub trait UrlSigner: Send + Sync {
+    fn sign(&self, key: &str, expires: i64) -> String;
+}
+
+/// base64url(HMAC-SHA256(secret, "<key>:<expiry unix time>")), the scheme of nginx
+/// `secure_link_hmac` and most CDN token checks
+pub struct HmacUrlSigner {
+    secret: Vec<u8>,
+}
+
+impl HmacUrlSigner {
+    pub fn new(secret: &str) -> Self {
+        Self {
+            secret: secret.as_bytes().to_vec(),
+        }
+    }
+}
+
+impl UrlSigner for HmacUrlSigner {
+    fn sign(&self, key: &str, expires: i64) -> String {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.secret).expect("HMAC accepts keys of any length");
+        mac.update(format!("{}:{}", key, expires).as_bytes());
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+    }
+}
+
+/// Files mirrored under `base_url` by an HTTP server or object store that checks the
+/// signature: `<base_url>/<path relative to root>?expires=<unix time>&signature=<sig>`
+pub struct SignedUrlProvider {
+    base_url: url::Url,
+    root: PathBuf,
+    /// Directories outside `root` whose files the API serves itself
+    local_roots: Vec<PathBuf>,
+    ttl: Duration,
+    signer: Box<dyn UrlSigner>,
+}
+
+impl SignedUrlProvider {
+    pub fn new(
+        base_url: &str,
+        root: impl Into<PathBuf>,
+        ttl: Duration,
+        signer: Box<dyn UrlSigner>,
+    ) -> Result<Self> {
+        let base_url = url::Url::parse(base_url).map_err(|e| {
+            Error::InvalidInput(format!("Invalid storage URL base {}: {}", base_url, e))
+        })?;
+        if base_url.cannot_be_a_base() {
+            return Err(Error::InvalidInput(format!(
+                "Storage URL base {} can't be a base",
+                base_url
+            ))
+            .into());
+        }
+        Ok(Self {
+            base_url,
+            root: root.into(),
+            local_roots: Vec::new(),
+            ttl,
+            signer,
+        })
+    }
+
+    /// Serve files under `root` locally, e.g. the storage tier directory, which isn't
+    /// mirrored under the URL base
+    pub fn with_local_root(mut self, root: impl Into<PathBuf>) -> Self {
+        self.local_roots.push(root.into());
+        self
+    }
+}
+
+impl StorageUrlProvider for SignedUrlProvider {
+    fn url_for(&self, path: &Path) -> Result<Option<SignedUrl>> {
+        if self.local_roots.iter().any(|root| path.starts_with(root)) {
+            return Ok(None);
+        }
+        let relative = path.strip_prefix(&self.root).map_err(|_| {
+            Error::InvalidInput(format!(
+                "{} is outside the storage root {}",
+                path.display(),
+                self.root.display()
+            ))
+        })?;
+        let segments: Vec<String> = relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        let key = segments.join("/");
+
+        let expires_at = Utc::now() + chrono::Duration::seconds(self.ttl.as_secs() as i64);
+        let expires = expires_at.timestamp();
+        let signature = self.signer.sign(&key, expires);
+
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .expect("checked to be a base in new")
+            .pop_if_empty()
+            .extend(&segments);
+        url.query_pairs_mut()
+            .append_pair("expires", &expires.to_string())
+            .append_pair("signature", &signature);
+
+        Ok(Some(SignedUrl {
+            url: url.into(),
+            expires_at,
+        }))
+    }
+}
+
+/// Build the URL provider `config` selects for recordings stored under `root`. Recordings
+/// moved to `tier_dir` are always served by the API.
+pub fn url_provider(
+    config: &StorageConfig,
+    root: &Path,
+    tier_dir: Option<&Path>,
+) -> Result<Arc<dyn StorageUrlProvider>> {
+    Ok(match config.url_provider {
+        StorageUrlBackend::Local => Arc::new(LocalUrlProvider),
+        StorageUrlBackend::Signed => {
+            let base_url = config.url_base.as_deref().ok_or_else(|| {
+                Error::Config("storage.url_base is required for signed URLs".to_string())
+            })?;
+            let secret = config.signing_key.as_deref().ok_or_else(|| {
+                Error::Config("storage.signing_key is required for signed URLs".to_string())
+            })?;
+            let mut provider = SignedUrlProvider::new(
+                base_url,
+                root,
+                Duration::from_secs(config.url_ttl_secs),
+                Box::new(HmacUrlSigner::new(secret)),
+            )?;
+            if let Some(tier_dir) = tier_dir {
+                provider = provider.with_local_root(tier_dir);
+            }
+            Arc::new(provider)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Signs with a fixed token so the URL is predictable
+    struct MockSigner;
+
+    impl UrlSigner for MockSigner {
+        fn sign(&self, key: &str, expires: i64) -> String {
+            format!("mock-{}-{}", key.replace('/', "."), expires)
+        }
+    }
+
+    #[test]
+    fn signed_url_carries_expiry_and_signature() {
+        let provider = SignedUrlProvider::new(
+            "https://cdn.example.com/recordings/",
+            "/var/recordings",
+            Duration::from_secs(600),
+            Box::new(MockSigner),
+        )
+        .unwrap();
+
+        let before = Utc::now();
+        let signed = provider
+            .url_for(Path::new("/var/recordings/cam 1/2024/clip.mp4"))
+            .unwrap()
+            .expect("signed provider returns a URL");
+
+        let expires = signed.expires_at.timestamp();
+        assert!(signed.expires_at >= before + chrono::Duration::seconds(600));
+        assert_eq!(
+            signed.url,
+            format!(
+                "https://cdn.example.com/recordings/cam%201/2024/clip.mp4?expires={}&signature=mock-cam+1.2024.clip.mp4-{}",
+                expires, expires
+            )
+        );
+
+        // Files outside the storage root have no URL, unless they are served locally
+        assert!(provider.url_for(Path::new("/tmp/clip.mp4")).is_err());
+        let provider = provider.with_local_root("/mnt/tier");
+        assert!(provider
+            .url_for(Path::new("/mnt/tier/cam 1/clip.mp4"))
+            .unwrap()
+            .is_none());
+        assert!(provider.url_for(Path::new("/mnt/tier2/clip.mp4")).is_err());
+        assert!(LocalUrlProvider
+            .url_for(Path::new("/var/recordings/clip.mp4"))
+            .unwrap()
+            .is_none());
+    }
+}
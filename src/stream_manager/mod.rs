@@ -1,3 +1,8 @@
+pub mod multicast;
+pub mod snapshot;
 pub mod stream_manager;
 
-pub use stream_manager::{StreamId, StreamManager, StreamSource};
+pub use stream_manager::{
+    authenticated_uri, BufferSettings, IsolatedPipeline, ReconnectPolicy, StreamAccessError,
+    StreamId, StreamManager, StreamSource, StreamStatus,
+};
@@ -0,0 +1,131 @@
+use anyhow::{anyhow, Result};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+
+/// Multicast group a stream's RTP video is re-sent to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct MulticastGroup {
+    pub address: Ipv4Addr,
+    pub port: u16,
+}
+
+/// Hands out one multicast group per stream from a fixed range.
+///
+/// Slot `n` is `base + n` on port `port_start + 2n`, leaving the odd port free for RTCP.
+/// Allocation continues after the last slot handed out and wraps around, so a group that
+/// was just released isn't reused straight away by a different stream.
+#[derive(Debug)]
+pub struct MulticastAllocator {
+    base: Ipv4Addr,
+    port_start: u16,
+    size: u32,
+    next: u32,
+    allocated: HashMap<String, u32>,
+}
+
+impl MulticastAllocator {
+    /// Create an allocator for `size` groups starting at `base`/`port_start`
+    pub fn new(base: Ipv4Addr, port_start: u16, size: u32) -> Self {
+        Self {
+            base,
+            port_start,
+            size,
+            next: 0,
+            allocated: HashMap::new(),
+        }
+    }
+
+    /// Allocate a group for a stream, or return the one it already has. `None` when the
+    /// range is exhausted.
+    pub fn allocate(&mut self, stream_id: &str) -> Option<MulticastGroup> {
+        if let Some(&slot) = self.allocated.get(stream_id) {
+            return Some(self.group(slot));
+        }
+
+        let slot = (0..self.size)
+            .map(|offset| (self.next + offset) % self.size)
+            .find(|slot| !self.allocated.values().any(|used| used == slot))?;
+        self.allocated.insert(stream_id.to_string(), slot);
+        self.next = (slot + 1) % self.size;
+        Some(self.group(slot))
+    }
+
+    /// Give a stream's group back to the pool
+    pub fn release(&mut self, stream_id: &str) -> Option<MulticastGroup> {
+        self.allocated
+            .remove(stream_id)
+            .map(|slot| self.group(slot))
+    }
+
+    fn group(&self, slot: u32) -> MulticastGroup {
+        MulticastGroup {
+            address: Ipv4Addr::from(u32::from(self.base) + slot),
+            port: self.port_start + 2 * slot as u16,
+        }
+    }
+}
+
+/// Attach `queue ! udpsink` to a stream's video tee, sending its RTP packets to `group`.
+/// The branch lives as long as the stream's pipeline.
+pub fn attach_udp_branch(
+    pipeline: &gst::Pipeline,
+    tee: &gst::Element,
+    stream_id: &str,
+    group: MulticastGroup,
+    ttl: u32,
+) -> Result<()> {
+    let queue = gst::ElementFactory::make("queue")
+        .name(format!("{}_multicast_q", stream_id))
+        .build()?;
+    queue.set_property_from_str("leaky", "downstream");
+    let udpsink = gst::ElementFactory::make("udpsink")
+        .name(format!("{}_multicast_sink", stream_id))
+        .property("host", group.address.to_string())
+        .property("port", group.port as i32)
+        .property("auto-multicast", true)
+        .property("ttl-mc", ttl as i32)
+        .property("sync", false)
+        .property("async", false)
+        .build()?;
+
+    pipeline.add_many([&queue, &udpsink])?;
+    if let Err(e) = gst::Element::link_many([tee, &queue, &udpsink]) {
+        let _ = pipeline.remove_many([&queue, &udpsink]);
+        return Err(anyhow!("Failed to link multicast branch: {}", e));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocation_wraps_around_and_reuses_released_groups() {
+        let mut allocator = MulticastAllocator::new(Ipv4Addr::new(239, 0, 0, 0), 5000, 3);
+
+        let a = allocator.allocate("a").unwrap();
+        let b = allocator.allocate("b").unwrap();
+        let c = allocator.allocate("c").unwrap();
+        assert_eq!((a.address, a.port), (Ipv4Addr::new(239, 0, 0, 0), 5000));
+        assert_eq!((c.address, c.port), (Ipv4Addr::new(239, 0, 0, 2), 5004));
+        assert_eq!(allocator.allocate("a"), Some(a));
+        assert_eq!(allocator.allocate("d"), None);
+
+        // The freed middle slot is found by wrapping past the end of the range
+        assert_eq!(allocator.release("b"), Some(b));
+        assert_eq!(allocator.allocate("d"), Some(b));
+        assert_eq!(allocator.allocate("e"), None);
+
+        allocator.release("a");
+        allocator.release("c");
+        // Continues after the last slot handed out rather than restarting at the base
+        assert_eq!(allocator.allocate("f"), Some(c));
+        assert_eq!(allocator.allocate("g"), Some(a));
+        assert_eq!(allocator.release("missing"), None);
+    }
+}
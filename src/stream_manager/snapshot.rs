@@ -0,0 +1,180 @@
+use anyhow::{anyhow, Result};
+use gstreamer as gst;
+use gstreamer::prelude::*;
+use gstreamer_app as gst_app;
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// How long to wait for a temporary branch to be unlinked from its tee
+const DETACH_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Recently captured JPEG snapshots, keyed by stream ID
+pub struct SnapshotCache {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (Instant, Vec<u8>)>>,
+}
+
+impl SnapshotCache {
+    /// Create a cache that serves a snapshot for `ttl` after it was captured
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Get the cached snapshot of a stream if it is still fresh
+    pub fn get(&self, stream_id: &str) -> Option<Vec<u8>> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(stream_id)
+            .filter(|(captured_at, _)| captured_at.elapsed() < self.ttl)
+            .map(|(_, jpeg)| jpeg.clone())
+    }
+
+    pub fn insert(&self, stream_id: &str, jpeg: Vec<u8>) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (captured_at, _)| captured_at.elapsed() < self.ttl);
+        entries.insert(stream_id.to_string(), (Instant::now(), jpeg));
+    }
+}
+
+/// Depayloader for the RTP video flowing through a stream's video tee
+fn depayloader_for(encoding_name: &str) -> Result<&'static str> {
+    match encoding_name.to_uppercase().as_str() {
+        "H264" => Ok("rtph264depay"),
+        "H265" => Ok("rtph265depay"),
+        "JPEG" => Ok("rtpjpegdepay"),
+        "MP4V-ES" => Ok("rtpmp4vdepay"),
+        other => Err(anyhow!("Unsupported video codec for snapshots: {}", other)),
+    }
+}
+
+/// Grab one frame from a stream's video tee as a JPEG.
+///
+/// A temporary `queue ! depay ! decodebin ! videoconvert ! jpegenc ! appsink` branch is
+/// attached to the tee, one sample is pulled within `timeout`, and the branch is removed
+/// again. This blocks, so call it from a blocking task.
+pub fn capture_jpeg(
+    pipeline: &gst::Pipeline,
+    tee: &gst::Element,
+    timeout: Duration,
+) -> Result<Vec<u8>> {
+    let encoding_name = tee
+        .static_pad("sink")
+        .and_then(|pad| pad.current_caps())
+        .and_then(|caps| {
+            caps.structure(0)
+                .and_then(|s| s.get::<String>("encoding-name").ok())
+        })
+        .ok_or_else(|| anyhow!("No video caps negotiated on the stream yet"))?;
+
+    let suffix = Uuid::new_v4().simple().to_string();
+    let make = |factory: &str| {
+        gst::ElementFactory::make(factory)
+            .name(format!("snapshot_{}_{}", factory, suffix))
+            .build()
+            .map_err(|e| anyhow!("Failed to create {}: {}", factory, e))
+    };
+
+    let queue = make("queue")?;
+    queue.set_property_from_str("leaky", "downstream");
+    let depay = make(depayloader_for(&encoding_name)?)?;
+    let decodebin = make("decodebin")?;
+    let convert = make("videoconvert")?;
+    let jpegenc = make("jpegenc")?;
+    let appsink = gst_app::AppSink::builder()
+        .name(format!("snapshot_appsink_{}", suffix))
+        .caps(&gst::Caps::builder("image/jpeg").build())
+        .max_buffers(1)
+        .drop(true)
+        .sync(false)
+        .build();
+
+    let elements = [
+        &queue,
+        &depay,
+        &decodebin,
+        &convert,
+        &jpegenc,
+        appsink.upcast_ref(),
+    ];
+    pipeline.add_many(elements)?;
+
+    let result: Result<Vec<u8>> = (|| {
+        gst::Element::link_many([&queue, &depay, &decodebin])?;
+        gst::Element::link_many([&convert, &jpegenc, appsink.upcast_ref()])?;
+
+        let convert_sink = convert
+            .static_pad("sink")
+            .ok_or_else(|| anyhow!("videoconvert has no sink pad"))?;
+        decodebin.connect_pad_added(move |_, pad| {
+            let is_video = pad
+                .current_caps()
+                .and_then(|caps| caps.structure(0).map(|s| s.name().starts_with("video/")))
+                .unwrap_or(false);
+            if is_video && !convert_sink.is_linked() {
+                if let Err(e) = pad.link(&convert_sink) {
+                    warn!("Failed to link snapshot decoder: {:?}", e);
+                }
+            }
+        });
+
+        for element in elements {
+            element.sync_state_with_parent()?;
+        }
+
+        let tee_pad = tee
+            .request_pad_simple("src_%u")
+            .ok_or_else(|| anyhow!("Failed to request a tee pad"))?;
+        let queue_sink = queue
+            .static_pad("sink")
+            .ok_or_else(|| anyhow!("queue has no sink pad"))?;
+        if let Err(e) = tee_pad.link(&queue_sink) {
+            tee.release_request_pad(&tee_pad);
+            return Err(e.into());
+        }
+
+        let sample =
+            appsink.try_pull_sample(gst::ClockTime::from_nseconds(timeout.as_nanos() as u64));
+        detach(&tee_pad);
+
+        let sample = sample.ok_or_else(|| anyhow!("Timed out waiting for a video frame"))?;
+        let buffer = sample
+            .buffer()
+            .ok_or_else(|| anyhow!("Snapshot sample has no buffer"))?;
+        let map = buffer.map_readable()?;
+        Ok(map.as_slice().to_vec())
+    })();
+
+    for element in elements {
+        let _ = element.set_state(gst::State::Null);
+    }
+    let _ = pipeline.remove_many(elements);
+    debug!("Removed snapshot branch {}", suffix);
+
+    result
+}
+
+/// Unlink a branch from its tee once the pad is idle and release the request pad. This
+/// blocks for up to a second.
+pub(crate) fn detach(tee_pad: &gst::Pad) {
+    let (tx, rx) = std::sync::mpsc::channel();
+    tee_pad.add_probe(gst::PadProbeType::IDLE, move |pad, _info| {
+        if let Some(peer) = pad.peer() {
+            let _ = pad.unlink(&peer);
+        }
+        if let Some(tee) = pad.parent_element() {
+            tee.release_request_pad(pad);
+        }
+        let _ = tx.send(());
+        gst::PadProbeReturn::Remove
+    });
+
+    if rx.recv_timeout(DETACH_TIMEOUT).is_err() {
+        warn!("Timed out detaching {} from its tee", tee_pad.name());
+    }
+}
@@ -1,12 +1,20 @@
 use crate::db::models::stream_models::StreamType;
 use crate::db::repositories::cameras::CamerasRepository;
+use crate::error::Error;
+use crate::stream_manager::multicast::{attach_udp_branch, MulticastAllocator, MulticastGroup};
+use crate::utils::redact::redact_url;
 use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
 use gstreamer as gst;
 use gstreamer::prelude::*;
-use log::{info, warn};
+use log::{debug, error, info, warn};
+use serde::Serialize;
 use sqlx::PgPool;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use uuid::Uuid;
 
 pub type StreamId = String;
 
@@ -19,6 +27,108 @@ pub struct StreamSource {
     pub description: Option<String>,
 }
 
+/// How the watchdog reconnects a stream after a bus error or EOS
+#[derive(Debug, Clone, Copy)]
+pub struct ReconnectPolicy {
+    /// Maximum consecutive reconnect attempts (0 = retry forever)
+    pub max_retries: u32,
+    /// Delay before the first reconnect attempt, doubled on each failure
+    pub initial_backoff: Duration,
+    /// Upper bound for the backoff delay
+    pub max_backoff: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 10,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+/// How much media a stream buffers between the camera and its branches. More buffering
+/// rides out jitter and stalls on slow links at the cost of live latency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct BufferSettings {
+    /// Jitterbuffer latency of the RTSP source, in milliseconds
+    pub latency_ms: u32,
+    /// Most bytes each ingest queue feeding a tee holds before it blocks the source
+    pub queue_max_bytes: u32,
+    /// Most media, in milliseconds, each ingest queue holds before it blocks the source
+    pub queue_max_time_ms: u64,
+}
+
+impl Default for BufferSettings {
+    fn default() -> Self {
+        Self {
+            latency_ms: 2000,
+            queue_max_bytes: 10 * 1024 * 1024,
+            queue_max_time_ms: 1000,
+        }
+    }
+}
+
+/// Live health of a stream, updated by its watchdog from bus messages
+#[derive(Debug, Default)]
+struct StreamHealth {
+    /// When the RTSP source last reached PLAYING; cleared on error/EOS
+    playing_since: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+    last_error_at: Option<DateTime<Utc>>,
+    /// Reconnect attempts since the stream was added
+    reconnect_attempts: u32,
+}
+
+/// Pipeline-level status of a stream, as reported by GStreamer rather than the camera row
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamStatus {
+    pub stream_id: StreamId,
+    /// Current pipeline state (VoidPending, Null, Ready, Paused, Playing)
+    pub state: String,
+    /// Seconds since the RTSP source last reached PLAYING, 0 when not playing
+    pub uptime_secs: i64,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<DateTime<Utc>>,
+    /// Times the watchdog tried to reconnect the camera since the stream was added
+    pub reconnect_attempts: u32,
+    /// Video codec from the negotiated RTP caps
+    pub codec: Option<String>,
+    /// Resolution as "WIDTHxHEIGHT" if the caps carry it
+    pub resolution: Option<String>,
+    pub framerate: Option<String>,
+    /// Audio codec from the negotiated RTP caps; `None` for a video-only stream
+    pub audio_codec: Option<String>,
+    /// Group the stream's RTP video is re-sent to, when multicast output is enabled
+    pub multicast: Option<MulticastGroup>,
+    /// Buffering applied between the camera and the stream's branches
+    pub buffering: BufferSettings,
+}
+
+/// Why a stream's pipeline can't be handed out
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum StreamAccessError {
+    /// No stream with this id was ever added, or it was removed
+    #[error("Stream not registered: {0}")]
+    NotRegistered(StreamId),
+    /// The stream exists, but its source is down or never started delivering media
+    #[error("Stream {0} is not playing: {1}")]
+    NotPlaying(StreamId, String),
+}
+
+/// Multicast output of every stream's video
+struct MulticastOutput {
+    allocator: std::sync::Mutex<MulticastAllocator>,
+    ttl: u32,
+}
+
+/// A stream that stays up this long after a reconnect gets its retry budget back
+const RECONNECT_STABLE_PERIOD: Duration = Duration::from_secs(60);
+
+/// How often `wait_for_stream_ready` looks at the stream again
+const STREAM_READY_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
 // Internal stream representation
 struct Stream {
     source: StreamSource,
@@ -26,12 +136,45 @@ struct Stream {
     tee: gst::Element,
     audio_tee: gst::Element,
     metadata_tee: gst::Element,
+    watchdog_stop: Arc<AtomicBool>,
+    health: Arc<std::sync::Mutex<StreamHealth>>,
+    multicast: Option<MulticastGroup>,
 }
 
+/// A pipeline built by `StreamManager::build_isolated_pipeline`, stopped with its watchdog
+/// when dropped
+pub struct IsolatedPipeline {
+    pub pipeline: gst::Pipeline,
+    pub video_tee: gst::Element,
+    pub audio_tee: gst::Element,
+    pub metadata_tee: gst::Element,
+    watchdog_stop: Arc<AtomicBool>,
+}
+
+impl Drop for IsolatedPipeline {
+    fn drop(&mut self) {
+        self.watchdog_stop.store(true, Ordering::SeqCst);
+        if let Err(e) = self.pipeline.set_state(gst::State::Null) {
+            warn!(
+                "Failed to stop isolated pipeline {}: {:?}",
+                self.pipeline.name(),
+                e
+            );
+        }
+    }
+}
+
+/// Called with the id of each stream added to a `StreamManager`
+pub type StreamAddedHook = Box<dyn Fn(&str) + Send + Sync>;
+
 /// StreamManager: Core class that manages video streams and their branches
 pub struct StreamManager {
     streams: RwLock<HashMap<StreamId, Stream>>,
     db_pool: Arc<PgPool>,
+    reconnect_policy: ReconnectPolicy,
+    buffering: BufferSettings,
+    multicast: Option<MulticastOutput>,
+    stream_added: RwLock<Option<StreamAddedHook>>,
 }
 
 impl StreamManager {
@@ -40,9 +183,81 @@ impl StreamManager {
         Self {
             streams: RwLock::new(HashMap::new()),
             db_pool,
+            reconnect_policy: ReconnectPolicy::default(),
+            buffering: BufferSettings::default(),
+            multicast: None,
+            stream_added: RwLock::new(None),
+        }
+    }
+
+    /// Set the policy used to reconnect streams that fail or reach EOS
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect_policy = policy;
+        self
+    }
+
+    /// Set how much streams added from now on buffer between the camera and their branches
+    pub fn with_buffering(mut self, buffering: BufferSettings) -> Self {
+        self.buffering = buffering;
+        self
+    }
+
+    /// Re-send each stream's RTP video to its own multicast group from `allocator`
+    pub fn with_multicast(mut self, allocator: MulticastAllocator, ttl: u32) -> Self {
+        self.multicast = Some(MulticastOutput {
+            allocator: std::sync::Mutex::new(allocator),
+            ttl,
+        });
+        self
+    }
+
+    /// Call `hook` with the id of each stream added from now on, and of those already
+    /// added. Replaces any earlier hook.
+    pub fn on_stream_added(&self, hook: impl Fn(&str) + Send + Sync + 'static) {
+        *self.stream_added.write().unwrap() = Some(Box::new(hook));
+        let existing: Vec<StreamId> = self.streams.read().unwrap().keys().cloned().collect();
+        if let Some(hook) = self.stream_added.read().unwrap().as_ref() {
+            for stream_id in &existing {
+                hook(stream_id);
+            }
         }
     }
 
+    /// Build a pipeline of its own for `uri`, outside the shared streams, for a consumer
+    /// that shouldn't be affected by other streams or depend on the camera being streamed.
+    /// It has the same tees as a shared stream and is reconnected the same way; it plays
+    /// from the start and stops once dropped.
+    pub fn build_isolated_pipeline(&self, uri: &str, name: &str) -> Result<IsolatedPipeline> {
+        gst::init()?;
+        let id = format!("isolated_{}", name);
+        let pipeline = gst::Pipeline::with_name(&format!("pipeline_{}", id));
+        let (video_tee, audio_tee, metadata_tee) =
+            build_source(&pipeline, uri, &id, self.buffering)?;
+
+        let watchdog_stop = Arc::new(AtomicBool::new(false));
+        spawn_watchdog(
+            pipeline.clone(),
+            id,
+            uri.to_string(),
+            self.reconnect_policy,
+            self.buffering,
+            watchdog_stop.clone(),
+            Arc::new(std::sync::Mutex::new(StreamHealth::default())),
+        );
+        let isolated = IsolatedPipeline {
+            pipeline,
+            video_tee,
+            audio_tee,
+            metadata_tee,
+            watchdog_stop,
+        };
+        isolated
+            .pipeline
+            .set_state(gst::State::Playing)
+            .map_err(|e| anyhow!("Failed to start isolated pipeline {}: {:?}", name, e))?;
+        Ok(isolated)
+    }
+
     pub async fn connect(&self) -> Result<i32> {
         let cameras_with_streams = CamerasRepository::new(self.db_pool.clone())
             .get_all_with_streams()
@@ -61,25 +276,9 @@ impl StreamManager {
                 .ok_or_else(|| anyhow::anyhow!("Camera password is missing"))?;
 
             for stream in camera_with_streams.streams.iter() {
-                // Parse the original URL to insert username and password
-                let stream_uri = stream.url.to_string();
-
-                // Check if URL already contains credentials
-                let auth_uri = if stream_uri.contains('@') {
-                    // URL already has credentials, use as is
-                    stream_uri
-                } else {
-                    // URL doesn't have credentials, add them
-                    if stream_uri.starts_with("rtsp://") {
-                        format!("rtsp://{}:{}@{}", username, password, &stream_uri[7..])
-                    } else {
-                        // Handle non-RTSP URLs or malformed URLs
-                        warn!("Invalid RTSP URL format: {}", stream_uri);
-                        stream_uri
-                    }
-                };
+                let auth_uri = authenticated_uri(&stream.url, username, password);
 
-                info!("Connecting to camera URL: {}", auth_uri.clone());
+                info!("Connecting to camera URL: {}", redact_url(&auth_uri));
 
                 let source = StreamSource {
                     stream_type: stream.stream_type,
@@ -99,135 +298,65 @@ impl StreamManager {
     }
 
     pub fn add_stream(&self, source: StreamSource, stream_id: String) -> Result<StreamId> {
+        // A second pipeline for the same stream would orphan the first one
+        if self.streams.read().unwrap().contains_key(&stream_id) {
+            return Err(
+                Error::AlreadyExists(format!("Stream already running: {}", stream_id)).into(),
+            );
+        }
         // 1) Init GStreamer
         gst::init()?;
         // 2) Create a new empty pipeline
         let pipeline = gst::Pipeline::with_name(&format!("pipeline_{}", stream_id));
-        // 3) Create and add the RTSP source
-        let rtspsrc = gst::ElementFactory::make("rtspsrc")
-            .property("location", &source.uri)
-            .property("latency", &2000u32)
-            .property("onvif-mode", &true)
-            .build()?;
-        pipeline.add(&rtspsrc)?;
-        // 4) Create three tees and add them
-        let video_tee = gst::ElementFactory::make("tee")
-            .name(&format!("video_tee_{}", stream_id))
-            .build()?;
-        let audio_tee = gst::ElementFactory::make("tee")
-            .name(&format!("audio_tee_{}", stream_id))
-            .build()?;
-        let metadata_tee = gst::ElementFactory::make("tee")
-            .name(&format!("metadata_tee_{}", stream_id))
-            .build()?;
-        pipeline.add_many(&[&video_tee, &audio_tee, &metadata_tee])?;
-        // 5) Route incoming pads into the right tee
-        //
-        // We clone what we need into the closure:
-        // // this is a memory leak -> need to use downgrade and upgrade flow
-        let pipeline_clone = pipeline.clone();
-        let sid_clone = stream_id.clone();
-        rtspsrc.connect_pad_added(move |_, src_pad| {
-            // inspect caps to decide audio vs video vs metadata
-            if let Some(caps) = src_pad.current_caps() {
-                if let Some(s) = caps.structure(0) {
-                    if let Ok(media_type) = s.get::<String>("media") {
-                        let tee_name = match media_type.as_str() {
-                            "video" => format!("video_tee_{}", sid_clone),
-                            "audio" => format!("audio_tee_{}", sid_clone),
-                            "application" | "meta" => format!("metadata_tee_{}", sid_clone),
-                            _ => {
-                                warn!("Unsupported media type: {}", media_type);
-                                return;
-                            }
-                        };
-
-                        // Look up the tee by name
-                        let tee = match pipeline_clone.by_name(&tee_name) {
-                            Some(t) => t,
-                            None => {
-                                eprintln!("Failed to find tee: {}", tee_name);
-                                // Debug what tees are available
-                                let elements = pipeline_clone.children();
-                                eprintln!(
-                                    "Available elements: {:?}",
-                                    elements.iter().map(|e| e.name()).collect::<Vec<_>>()
-                                );
-                                return;
-                            }
-                        };
-
-                        // Create a queue for this branch
-                        let queue = match gst::ElementFactory::make("queue").build() {
-                            Ok(q) => q,
-                            Err(e) => {
-                                eprintln!("Failed to create queue: {:?}", e);
-                                return;
-                            }
-                        };
-
-                        // Add the queue to the pipeline
-                        if let Err(e) = pipeline_clone.add(&queue) {
-                            eprintln!("Failed to add queue to pipeline: {:?}", e);
-                            return;
-                        }
-
-                        if let Err(e) = queue.sync_state_with_parent() {
-                            eprintln!("Failed to sync queue state: {:?}", e);
-                            return;
-                        }
-
-                        // Link: src_pad → queue → tee
-                        let sink_pad = match queue.static_pad("sink") {
-                            Some(p) => p,
-                            None => {
-                                eprintln!("Failed to get sink pad from queue");
-                                return;
-                            }
-                        };
-
-                        if let Err(e) = src_pad.link(&sink_pad) {
-                            eprintln!("Failed to link src_pad to queue: {:?}", e);
-                            return;
-                        }
-
-                        if let Err(e) = queue.link(&tee) {
-                            eprintln!("Failed to link queue to tee: {:?}", e);
-                            return;
-                        }
-
-                        println!("Successfully linked {} pad to {}", media_type, tee_name);
-                    }
+        // 3) Create the tees and the RTSP source feeding them
+        let (video_tee, audio_tee, metadata_tee) =
+            build_source(&pipeline, &source.uri, &stream_id, self.buffering)?;
+        // 4) Optionally re-send the video to a multicast group; the stream works without it
+        let multicast = self.multicast.as_ref().and_then(|output| {
+            let mut allocator = output.allocator.lock().unwrap();
+            let Some(group) = allocator.allocate(&stream_id) else {
+                warn!("No free multicast group for stream {}", stream_id);
+                return None;
+            };
+            match attach_udp_branch(&pipeline, &video_tee, &stream_id, group, output.ttl) {
+                Ok(()) => {
+                    info!(
+                        "Stream {} multicast on {}:{}",
+                        stream_id, group.address, group.port
+                    );
+                    Some(group)
+                }
+                Err(e) => {
+                    warn!("Failed to set up multicast for stream {}: {}", stream_id, e);
+                    allocator.release(&stream_id);
+                    None
                 }
             }
         });
-        // 6) Prevent tees from blocking when no real branches exist
-        for (tee, tag) in [
-            (&video_tee, "video"),
-            (&audio_tee, "audio"),
-            (&metadata_tee, "metadata"),
-        ] {
-            let dummy_q = gst::ElementFactory::make("queue")
-                .name(&format!("{}_dummy_q_{}", stream_id, tag))
-                .build()?;
-            let dummy_sink = gst::ElementFactory::make("fakesink")
-                .name(&format!("{}_dummy_sink_{}", stream_id, tag))
-                .property("sync", &false)
-                .property("async", &false)
-                .build()?;
-            pipeline.add_many(&[&dummy_q, &dummy_sink])?;
-            tee.link(&dummy_q)?;
-            dummy_q.link(&dummy_sink)?;
-        }
-        // 7) Wrap into your Stream struct (you'll need to add metadata_tee to it)
+        // 5) Watch the bus and rebuild the source when the camera drops
+        let watchdog_stop = Arc::new(AtomicBool::new(false));
+        let health = Arc::new(std::sync::Mutex::new(StreamHealth::default()));
+        spawn_watchdog(
+            pipeline.clone(),
+            stream_id.clone(),
+            source.uri.clone(),
+            self.reconnect_policy,
+            self.buffering,
+            watchdog_stop.clone(),
+            health.clone(),
+        );
+        // 6) Wrap into the Stream struct
         let stream = Stream {
             source,
             pipeline: pipeline.clone(),
             tee: video_tee.clone(),
             audio_tee: audio_tee.clone(),
             metadata_tee: metadata_tee.clone(),
+            watchdog_stop,
+            health,
+            multicast,
         };
-        // 8) Store and set READY
+        // 7) Store and set READY
         {
             let mut streams = self.streams.write().unwrap();
             streams.insert(stream_id.clone(), stream);
@@ -237,17 +366,36 @@ impl StreamManager {
                 .pipeline
                 .set_state(gst::State::Ready)?;
         }
+        // 8) Let the hook attach to the new stream, e.g. to log its metadata
+        if let Some(hook) = self.stream_added.read().unwrap().as_ref() {
+            hook(&stream_id);
+        }
         Ok(stream_id)
     }
 
     pub fn get_stream_access(
         &self,
         stream_id: &str,
-    ) -> Result<(gst::Pipeline, gst::Element, gst::Element, gst::Element)> {
+    ) -> std::result::Result<
+        (gst::Pipeline, gst::Element, gst::Element, gst::Element),
+        StreamAccessError,
+    > {
         let streams = self.streams.read().unwrap();
         let stream = streams
             .get(stream_id)
-            .ok_or_else(|| anyhow!("Stream not found: {}", stream_id))?;
+            .ok_or_else(|| StreamAccessError::NotRegistered(stream_id.to_string()))?;
+
+        // Idle streams are started on demand, but one whose source failed can't be used
+        // until the watchdog brings it back
+        if stream.pipeline.current_state() != gst::State::Playing {
+            let health = stream.health.lock().unwrap();
+            if let (None, Some(last_error)) = (health.playing_since, &health.last_error) {
+                return Err(StreamAccessError::NotPlaying(
+                    stream_id.to_string(),
+                    last_error.clone(),
+                ));
+            }
+        }
 
         // Return clones of the pipeline and tee
         // This provides access without giving ownership or mutable access
@@ -259,18 +407,107 @@ impl StreamManager {
         ))
     }
 
-    /// Remove a stream and all its branches
-    pub fn remove_stream(&self, stream_id: &str) -> Result<()> {
-        let mut streams = self.streams.write().unwrap();
+    /// Wait until a stream exists and its pipeline is PLAYING, starting the pipeline if it
+    /// is still idle. Streams set up in the background right after a camera connects may
+    /// not be registered yet when a recording is requested.
+    ///
+    /// Fails with `NotFound` when the stream doesn't appear within `timeout`, and with
+    /// [`StreamAccessError::NotPlaying`] when it appears but doesn't start playing.
+    pub async fn wait_for_stream_ready(
+        &self,
+        stream_id: &StreamId,
+        timeout: Duration,
+    ) -> Result<()> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut started = false;
 
-        if let Some(stream) = streams.get_mut(stream_id) {
-            // Stop the pipeline
-            stream.pipeline.set_state(gst::State::Null)?;
-            streams.remove(stream_id);
-            Ok(())
-        } else {
-            Err(anyhow!("Stream not found: {}", stream_id))
+        loop {
+            let pipeline_and_health = self
+                .streams
+                .read()
+                .unwrap()
+                .get(stream_id)
+                .map(|stream| (stream.pipeline.clone(), stream.health.clone()));
+
+            match pipeline_and_health {
+                Some((pipeline, _)) if pipeline.current_state() == gst::State::Playing => {
+                    return Ok(());
+                }
+                Some((pipeline, _)) if !started => {
+                    info!("Starting pipeline of stream {}", stream_id);
+                    pipeline.set_state(gst::State::Playing).map_err(|e| {
+                        anyhow!("Failed to set stream {} to PLAYING: {:?}", stream_id, e)
+                    })?;
+                    started = true;
+                    continue;
+                }
+                Some((pipeline, health)) if tokio::time::Instant::now() >= deadline => {
+                    let last_error = health.lock().unwrap().last_error.clone();
+                    return Err(StreamAccessError::NotPlaying(
+                        stream_id.clone(),
+                        format!(
+                            "did not reach PLAYING within {:?} (state {:?}, last error: {})",
+                            timeout,
+                            pipeline.current_state(),
+                            last_error.as_deref().unwrap_or("none")
+                        ),
+                    )
+                    .into());
+                }
+                None if tokio::time::Instant::now() >= deadline => {
+                    return Err(Error::NotFound(format!("Stream not found: {}", stream_id)).into());
+                }
+                _ => {}
+            }
+
+            tokio::time::sleep(STREAM_READY_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Remove a stream and all its branches, and mark it inactive in the database
+    pub async fn remove_stream(&self, stream_id: &StreamId) -> Result<()> {
+        let stream = self
+            .streams
+            .write()
+            .unwrap()
+            .remove(stream_id)
+            .ok_or_else(|| Error::NotFound(format!("Stream not found: {}", stream_id)))?;
+
+        // Stop the watchdog first so it doesn't try to reconnect a stream being torn down
+        stream.watchdog_stop.store(true, Ordering::SeqCst);
+        // Stop the pipeline; dropping the last reference to it frees its elements
+        stream.pipeline.set_state(gst::State::Null)?;
+        drop(stream);
+        if let Some(output) = &self.multicast {
+            output.allocator.lock().unwrap().release(stream_id);
+        }
+        info!("Removed stream {}", stream_id);
+
+        // The pipeline is already gone, so a failed status update is only logged
+        if let Ok(id) = Uuid::parse_str(stream_id) {
+            if let Err(e) = CamerasRepository::new(self.db_pool.clone())
+                .update_stream_status(&id, false)
+                .await
+            {
+                warn!("Failed to mark stream {} inactive: {}", stream_id, e);
+            }
         }
+
+        Ok(())
+    }
+
+    /// Stop every stream pipeline and its watchdog; used on shutdown
+    pub fn stop_all_streams(&self) {
+        let streams = self.streams.read().unwrap();
+
+        for (stream_id, stream) in streams.iter() {
+            stream.watchdog_stop.store(true, Ordering::SeqCst);
+            if let Err(e) = stream.pipeline.set_state(gst::State::Null) {
+                warn!("Failed to stop pipeline for stream {}: {:?}", stream_id, e);
+            }
+        }
+
+        info!("Stopped {} stream pipelines", streams.len());
     }
 
     /// Get information about a stream
@@ -284,6 +521,73 @@ impl StreamManager {
         }
     }
 
+    /// Get the pipeline state, uptime, last bus error and negotiated video caps of a stream
+    pub fn get_stream_status(&self, stream_id: &StreamId) -> Result<StreamStatus> {
+        let streams = self.streams.read().unwrap();
+        let stream = streams
+            .get(stream_id)
+            .ok_or_else(|| Error::NotFound(format!("Stream not found: {}", stream_id)))?;
+
+        let (_, state, _) = stream.pipeline.state(gst::ClockTime::ZERO);
+        let health = stream.health.lock().unwrap();
+
+        let uptime_secs = match (state, health.playing_since) {
+            (gst::State::Playing, Some(since)) => (Utc::now() - since).num_seconds(),
+            _ => 0,
+        };
+
+        let mut status = StreamStatus {
+            stream_id: stream_id.clone(),
+            state: format!("{:?}", state),
+            uptime_secs,
+            last_error: health.last_error.clone(),
+            last_error_at: health.last_error_at,
+            reconnect_attempts: health.reconnect_attempts,
+            codec: None,
+            resolution: None,
+            framerate: None,
+            audio_codec: None,
+            multicast: stream.multicast,
+            buffering: self.buffering,
+        };
+
+        let caps = stream
+            .tee
+            .static_pad("sink")
+            .and_then(|pad| pad.current_caps());
+        if let Some(s) = caps.as_ref().and_then(|caps| caps.structure(0)) {
+            status.codec = s
+                .get::<&str>("encoding-name")
+                .ok()
+                .map(|name| name.to_lowercase());
+            status.resolution = match (s.get::<i32>("width"), s.get::<i32>("height")) {
+                (Ok(w), Ok(h)) => Some(format!("{}x{}", w, h)),
+                // SDP a=framesize:<pt> <width>-<height>
+                _ => s
+                    .get::<&str>("a-framesize")
+                    .ok()
+                    .map(|size| size.replace('-', "x")),
+            };
+            status.framerate = match s.get::<gst::Fraction>("framerate") {
+                Ok(frac) => Some(format!("{}/{}", frac.numer(), frac.denom())),
+                Err(_) => s.get::<&str>("a-framerate").ok().map(str::to_string),
+            };
+        }
+
+        // The audio tee only gets caps once the camera announced an audio track
+        let audio_caps = stream
+            .audio_tee
+            .static_pad("sink")
+            .and_then(|pad| pad.current_caps());
+        status.audio_codec = audio_caps
+            .as_ref()
+            .and_then(|caps| caps.structure(0))
+            .and_then(|s| s.get::<&str>("encoding-name").ok())
+            .map(|name| name.to_lowercase());
+
+        Ok(status)
+    }
+
     /// List all streams
     pub fn list_streams(&self) -> Vec<(StreamId, StreamSource)> {
         let streams = self.streams.read().unwrap();
@@ -294,3 +598,546 @@ impl StreamManager {
             .collect()
     }
 }
+
+/// Insert a camera's credentials into an RTSP URL that doesn't carry any
+pub fn authenticated_uri(url: &str, username: &str, password: &str) -> String {
+    if url.contains('@') {
+        // URL already has credentials, use as is
+        url.to_string()
+    } else if let Some(rest) = url.strip_prefix("rtsp://") {
+        format!("rtsp://{}:{}@{}", username, password, rest)
+    } else {
+        // Handle non-RTSP URLs or malformed URLs
+        warn!("Invalid RTSP URL format: {}", redact_url(url));
+        url.to_string()
+    }
+}
+
+/// Add a stream's video, audio and metadata tees to `pipeline`, with the RTSP source
+/// feeding them. The tees outlive the RTSP source so recording branches stay attached
+/// across reconnects.
+fn build_source(
+    pipeline: &gst::Pipeline,
+    uri: &str,
+    stream_id: &str,
+    buffering: BufferSettings,
+) -> Result<(gst::Element, gst::Element, gst::Element)> {
+    let video_tee = gst::ElementFactory::make("tee")
+        .name(&format!("video_tee_{}", stream_id))
+        .build()?;
+    let audio_tee = gst::ElementFactory::make("tee")
+        .name(&format!("audio_tee_{}", stream_id))
+        .build()?;
+    let metadata_tee = gst::ElementFactory::make("tee")
+        .name(&format!("metadata_tee_{}", stream_id))
+        .build()?;
+    pipeline.add_many(&[&video_tee, &audio_tee, &metadata_tee])?;
+    // Its pads are routed into the tees as they appear
+    let rtspsrc = build_rtsp_source(uri, stream_id, pipeline, buffering)?;
+    pipeline.add(&rtspsrc)?;
+    // Prevent tees from blocking when no real branches exist
+    for (tee, tag) in [
+        (&video_tee, "video"),
+        (&audio_tee, "audio"),
+        (&metadata_tee, "metadata"),
+    ] {
+        let dummy_q = gst::ElementFactory::make("queue")
+            .name(&format!("{}_dummy_q_{}", stream_id, tag))
+            .build()?;
+        let dummy_sink = gst::ElementFactory::make("fakesink")
+            .name(&format!("{}_dummy_sink_{}", stream_id, tag))
+            .property("sync", &false)
+            .property("async", &false)
+            .build()?;
+        pipeline.add_many(&[&dummy_q, &dummy_sink])?;
+        tee.link(&dummy_q)?;
+        dummy_q.link(&dummy_sink)?;
+    }
+    Ok((video_tee, audio_tee, metadata_tee))
+}
+
+/// Create an `rtspsrc` whose pads are routed into the stream's tees by media type.
+///
+/// Each media type goes through a named ingest queue. A queue left over from a previous
+/// source is removed before the new pad is linked, since a tee only has one sink pad.
+fn build_rtsp_source(
+    uri: &str,
+    stream_id: &str,
+    pipeline: &gst::Pipeline,
+    buffering: BufferSettings,
+) -> Result<gst::Element> {
+    let rtspsrc = gst::ElementFactory::make("rtspsrc")
+        .name(&format!("rtspsrc_{}", stream_id))
+        .property("location", uri)
+        .property("latency", &buffering.latency_ms)
+        .property("onvif-mode", &true)
+        .build()?;
+
+    let pipeline_weak = pipeline.downgrade();
+    let sid_clone = stream_id.to_string();
+    rtspsrc.connect_pad_added(move |_, src_pad| {
+        let Some(pipeline_clone) = pipeline_weak.upgrade() else {
+            return;
+        };
+        // inspect caps to decide audio vs video vs metadata
+        if let Some(caps) = src_pad.current_caps() {
+            if let Some(s) = caps.structure(0) {
+                if let Ok(media_type) = s.get::<String>("media") {
+                    let (tee_name, queue_name) = match media_type.as_str() {
+                        "video" => (
+                            format!("video_tee_{}", sid_clone),
+                            format!("ingest_queue_video_{}", sid_clone),
+                        ),
+                        "audio" => (
+                            format!("audio_tee_{}", sid_clone),
+                            format!("ingest_queue_audio_{}", sid_clone),
+                        ),
+                        "application" | "meta" => (
+                            format!("metadata_tee_{}", sid_clone),
+                            format!("ingest_queue_metadata_{}", sid_clone),
+                        ),
+                        _ => {
+                            warn!("Unsupported media type: {}", media_type);
+                            return;
+                        }
+                    };
+
+                    // Look up the tee by name
+                    let tee = match pipeline_clone.by_name(&tee_name) {
+                        Some(t) => t,
+                        None => {
+                            error!("Failed to find tee: {}", tee_name);
+                            // Debug what tees are available
+                            let elements = pipeline_clone.children();
+                            debug!(
+                                "Available elements: {:?}",
+                                elements.iter().map(|e| e.name()).collect::<Vec<_>>()
+                            );
+                            return;
+                        }
+                    };
+
+                    // Drop the ingest queue of a previous connection
+                    if let Some(stale_queue) = pipeline_clone.by_name(&queue_name) {
+                        let _ = stale_queue.set_state(gst::State::Null);
+                        stale_queue.unlink(&tee);
+                        let _ = pipeline_clone.remove(&stale_queue);
+                    }
+
+                    // Create a queue for this branch, bounded by size and time only so a
+                    // burst after a stall isn't dropped at the default 200 buffers
+                    let queue = match gst::ElementFactory::make("queue")
+                        .name(&queue_name)
+                        .property("max-size-buffers", &0u32)
+                        .property("max-size-bytes", &buffering.queue_max_bytes)
+                        .property(
+                            "max-size-time",
+                            &gst::ClockTime::from_mseconds(buffering.queue_max_time_ms),
+                        )
+                        .build()
+                    {
+                        Ok(q) => q,
+                        Err(e) => {
+                            error!("Failed to create queue: {:?}", e);
+                            return;
+                        }
+                    };
+
+                    // Add the queue to the pipeline
+                    if let Err(e) = pipeline_clone.add(&queue) {
+                        error!("Failed to add queue to pipeline: {:?}", e);
+                        return;
+                    }
+
+                    if let Err(e) = queue.sync_state_with_parent() {
+                        error!("Failed to sync queue state: {:?}", e);
+                        return;
+                    }
+
+                    // Link: src_pad → queue → tee
+                    let sink_pad = match queue.static_pad("sink") {
+                        Some(p) => p,
+                        None => {
+                            error!("Failed to get sink pad from queue");
+                            return;
+                        }
+                    };
+
+                    if let Err(e) = src_pad.link(&sink_pad) {
+                        error!("Failed to link src_pad to queue: {:?}", e);
+                        return;
+                    }
+
+                    if let Err(e) = queue.link(&tee) {
+                        error!("Failed to link queue to tee: {:?}", e);
+                        return;
+                    }
+
+                    debug!("Successfully linked {} pad to {}", media_type, tee_name);
+                }
+            }
+        }
+    });
+
+    Ok(rtspsrc)
+}
+
+/// Replace the stream's `rtspsrc` with a fresh one, leaving the tees and their branches in place
+fn rebuild_rtsp_source(
+    pipeline: &gst::Pipeline,
+    stream_id: &str,
+    uri: &str,
+    buffering: BufferSettings,
+) -> Result<()> {
+    if let Some(old_src) = pipeline.by_name(&format!("rtspsrc_{}", stream_id)) {
+        old_src.set_state(gst::State::Null)?;
+        pipeline.remove(&old_src)?;
+    }
+
+    let rtspsrc = build_rtsp_source(uri, stream_id, pipeline, buffering)?;
+    pipeline.add(&rtspsrc)?;
+    rtspsrc.sync_state_with_parent()?;
+    Ok(())
+}
+
+/// Whether `msg` was posted by the stream's current `rtspsrc` or an element inside it.
+/// Messages from the branches hanging off the tees, or from a source that has since been
+/// replaced, are not.
+fn from_rtsp_source(msg: &gst::Message, pipeline: &gst::Pipeline, stream_id: &str) -> bool {
+    let Some(source) = pipeline.by_name(&format!("rtspsrc_{}", stream_id)) else {
+        return false;
+    };
+    let mut object = msg.src().cloned();
+    while let Some(current) = object {
+        if current == *source.upcast_ref::<gst::Object>() {
+            return true;
+        }
+        object = current.parent();
+    }
+    false
+}
+
+/// Watch a stream's RTSP source for errors and EOS and rebuild it with exponential backoff.
+///
+/// Bus messages are observed through a signal watch dispatched by the glib main loop, so
+/// other consumers of the bus still get them. Reconnecting runs on its own thread, which
+/// exits when `stop` is set or the retry budget is exhausted.
+fn spawn_watchdog(
+    pipeline: gst::Pipeline,
+    stream_id: StreamId,
+    uri: String,
+    policy: ReconnectPolicy,
+    buffering: BufferSettings,
+    stop: Arc<AtomicBool>,
+    health: Arc<std::sync::Mutex<StreamHealth>>,
+) {
+    let Some(bus) = pipeline.bus() else {
+        warn!("Stream {} has no bus, reconnect watchdog disabled", stream_id);
+        return;
+    };
+
+    let (failures_tx, failures) = std::sync::mpsc::channel::<String>();
+    bus.add_signal_watch();
+    let handler = {
+        let pipeline = pipeline.downgrade();
+        let stream_id = stream_id.clone();
+        let health = health.clone();
+        bus.connect_message(None, move |_, msg| {
+            let Some(pipeline) = pipeline.upgrade() else {
+                return;
+            };
+            let from_pipeline = msg.src() == Some(pipeline.upcast_ref::<gst::Object>());
+            let failure = match msg.view() {
+                gst::MessageView::StateChanged(changed) => {
+                    // Uptime follows the RTSP source itself, since the watchdog replaces it
+                    // while the pipeline stays in PLAYING
+                    let source = pipeline.by_name(&format!("rtspsrc_{}", stream_id));
+                    if source.is_some_and(|source| msg.src() == Some(source.upcast_ref())) {
+                        let mut health = health.lock().unwrap();
+                        if changed.current() == gst::State::Playing {
+                            health.playing_since = Some(Utc::now());
+                        } else {
+                            health.playing_since = None;
+                        }
+                    }
+                    return;
+                }
+                gst::MessageView::Error(err) if from_rtsp_source(msg, &pipeline, &stream_id) => {
+                    format!(
+                        "Error from {}: {} ({:?})",
+                        err.src()
+                            .map(|s| s.name())
+                            .unwrap_or_else(|| "unknown".into()),
+                        err.error(),
+                        err.debug()
+                    )
+                }
+                // The pipeline posts EOS once all of its sinks saw it, which the dummy sinks
+                // on the tees only do when the source ended
+                gst::MessageView::Eos(_) if from_pipeline => "End of stream".to_string(),
+                _ => return,
+            };
+            let _ = failures_tx.send(failure);
+        })
+    };
+
+    std::thread::spawn(move || {
+        let mut attempts: u32 = 0;
+        let mut last_failure: Option<Instant> = None;
+
+        'watch: while !stop.load(Ordering::SeqCst) {
+            let failure = match failures.recv_timeout(Duration::from_millis(500)) {
+                Ok(failure) => failure,
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => continue,
+                Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => break,
+            };
+            warn!("Stream {}: {}", stream_id, failure);
+
+            {
+                let mut health = health.lock().unwrap();
+                health.playing_since = None;
+                health.last_error = Some(failure);
+                health.last_error_at = Some(Utc::now());
+            }
+
+            // Only a stream that has been healthy for a while gets its retry budget back
+            if last_failure.map_or(false, |t| t.elapsed() > RECONNECT_STABLE_PERIOD) {
+                attempts = 0;
+            }
+            last_failure = Some(Instant::now());
+
+            // Keep trying until a source could be rebuilt; the next failure comes back via the bus
+            loop {
+                if policy.max_retries != 0 && attempts >= policy.max_retries {
+                    error!(
+                        "Stream {} failed {} reconnect attempts, giving up",
+                        stream_id, attempts
+                    );
+                    break 'watch;
+                }
+
+                let backoff = policy
+                    .initial_backoff
+                    .saturating_mul(2u32.saturating_pow(attempts))
+                    .min(policy.max_backoff);
+                attempts += 1;
+                health.lock().unwrap().reconnect_attempts += 1;
+                info!(
+                    "Reconnecting stream {} in {:?} (attempt {})",
+                    stream_id, backoff, attempts
+                );
+                std::thread::sleep(backoff);
+
+                if stop.load(Ordering::SeqCst) {
+                    break 'watch;
+                }
+
+                // Failures reported by the old source are stale once it has been replaced
+                while failures.try_recv().is_ok() {}
+
+                match rebuild_rtsp_source(&pipeline, &stream_id, &uri, buffering) {
+                    Ok(()) => {
+                        info!("Rebuilt RTSP source for stream {}", stream_id);
+                        break;
+                    }
+                    Err(e) => {
+                        error!("Failed to rebuild RTSP source for stream {}: {}", stream_id, e)
+                    }
+                }
+            }
+        }
+
+        bus.disconnect(handler);
+        bus.remove_signal_watch();
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn streams_can_be_added_and_removed_at_runtime() {
+        // Never connected; marking the stream inactive fails and is only logged
+        let pool = PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+        let manager = StreamManager::new(Arc::new(pool));
+        let stream_id = Uuid::new_v4().to_string();
+
+        manager
+            .add_stream(
+                StreamSource {
+                    stream_type: StreamType::Rtsp,
+                    uri: "rtsp://127.0.0.1:1/unused".to_string(),
+                    name: "runtime".to_string(),
+                    description: None,
+                },
+                stream_id.clone(),
+            )
+            .unwrap();
+        assert!(manager.get_stream_access(&stream_id).is_ok());
+        let source = manager.get_stream_info(&stream_id).unwrap();
+        let duplicate = manager.add_stream(source, stream_id.clone()).unwrap_err();
+        assert!(matches!(
+            duplicate.downcast_ref::<Error>(),
+            Some(Error::AlreadyExists(_))
+        ));
+
+        manager.remove_stream(&stream_id).await.unwrap();
+        assert_eq!(
+            manager.get_stream_access(&stream_id).unwrap_err(),
+            StreamAccessError::NotRegistered(stream_id.clone())
+        );
+        assert!(manager.list_streams().is_empty());
+
+        let err = manager.remove_stream(&stream_id).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn stream_added_hook_sees_existing_and_new_streams() {
+        let pool = PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+        let manager = StreamManager::new(Arc::new(pool));
+        let source = || StreamSource {
+            stream_type: StreamType::Rtsp,
+            uri: "rtsp://127.0.0.1:1/unused".to_string(),
+            name: "hooked".to_string(),
+            description: None,
+        };
+        let (existing, added) = (Uuid::new_v4().to_string(), Uuid::new_v4().to_string());
+        manager.add_stream(source(), existing.clone()).unwrap();
+
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let hook_seen = seen.clone();
+        manager.on_stream_added(move |stream_id| {
+            hook_seen.lock().unwrap().push(stream_id.to_string());
+        });
+        manager.add_stream(source(), added.clone()).unwrap();
+
+        assert_eq!(*seen.lock().unwrap(), vec![existing, added]);
+        manager.stop_all_streams();
+    }
+
+    #[tokio::test]
+    async fn configured_buffering_is_applied_to_the_source() {
+        let pool = PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+        let buffering = BufferSettings {
+            latency_ms: 750,
+            queue_max_bytes: 4 * 1024 * 1024,
+            queue_max_time_ms: 5000,
+        };
+        let manager = StreamManager::new(Arc::new(pool)).with_buffering(buffering);
+        let stream_id = Uuid::new_v4().to_string();
+
+        manager
+            .add_stream(
+                StreamSource {
+                    stream_type: StreamType::Rtsp,
+                    uri: "rtsp://127.0.0.1:1/unused".to_string(),
+                    name: "buffered".to_string(),
+                    description: None,
+                },
+                stream_id.clone(),
+            )
+            .unwrap();
+        let (pipeline, _, _, _) = manager.get_stream_access(&stream_id).unwrap();
+        let rtspsrc = pipeline.by_name(&format!("rtspsrc_{}", stream_id)).unwrap();
+        assert_eq!(rtspsrc.property::<u32>("latency"), 750);
+        assert_eq!(
+            manager.get_stream_status(&stream_id).unwrap().buffering,
+            buffering
+        );
+
+        manager.remove_stream(&stream_id).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn waiting_for_a_stream_covers_its_background_setup() {
+        let pool = PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+        let manager = Arc::new(StreamManager::new(Arc::new(pool)));
+        let stream_id = Uuid::new_v4().to_string();
+
+        // Nothing shows up: NotFound, but only after the whole timeout
+        let started = Instant::now();
+        let err = manager
+            .wait_for_stream_ready(&stream_id, Duration::from_millis(200))
+            .await
+            .unwrap_err();
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::NotFound(_))
+        ));
+
+        // The stream is set up while the caller is already waiting
+        let setup = {
+            let manager = manager.clone();
+            let stream_id = stream_id.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                manager
+                    .add_stream(
+                        StreamSource {
+                            stream_type: StreamType::Rtsp,
+                            uri: "rtsp://127.0.0.1:1/unused".to_string(),
+                            name: "late".to_string(),
+                            description: None,
+                        },
+                        stream_id,
+                    )
+                    .unwrap();
+            })
+        };
+        let result = manager
+            .wait_for_stream_ready(&stream_id, Duration::from_secs(2))
+            .await;
+        setup.await.unwrap();
+        // The camera doesn't exist, so the pipeline may never play, but the stream was found
+        if let Err(e) = result {
+            assert!(!matches!(
+                e.downcast_ref::<Error>(),
+                Some(Error::NotFound(_))
+            ));
+        }
+
+        manager.remove_stream(&stream_id).await.unwrap();
+    }
+
+    #[test]
+    fn credentials_are_only_added_when_missing() {
+        assert_eq!(
+            authenticated_uri("rtsp://10.0.0.2/live", "admin", "pw"),
+            "rtsp://admin:pw@10.0.0.2/live"
+        );
+        assert_eq!(
+            authenticated_uri("rtsp://user:x@10.0.0.2/live", "admin", "pw"),
+            "rtsp://user:x@10.0.0.2/live"
+        );
+    }
+
+    // Errors from the tees' branches, or from a source already replaced, don't trigger a
+    // reconnect
+    #[test]
+    fn only_the_current_rtsp_source_is_watched() {
+        gst::init().unwrap();
+        let pipeline = gst::Pipeline::new();
+        let uri = "rtsp://127.0.0.1:1/unused";
+        let source =
+            build_rtsp_source(uri, "watched", &pipeline, BufferSettings::default()).unwrap();
+        let video_tee = gst::ElementFactory::make("tee").build().unwrap();
+        pipeline.add_many([&source, &video_tee]).unwrap();
+        let error = |src: &gst::Element| {
+            gst::message::Error::builder(gst::CoreError::Failed, "test")
+                .src(src)
+                .build()
+        };
+
+        assert!(from_rtsp_source(&error(&source), &pipeline, "watched"));
+        assert!(!from_rtsp_source(&error(&video_tee), &pipeline, "watched"));
+
+        rebuild_rtsp_source(&pipeline, "watched", uri, BufferSettings::default()).unwrap();
+        assert!(!from_rtsp_source(&error(&source), &pipeline, "watched"));
+    }
+}
@@ -1 +1,2 @@
 pub mod metadataparser;
+pub mod redact;